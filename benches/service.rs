@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use chrono::{TimeDelta, Utc};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use ed25519_dalek::SigningKey;
+use lightbulb_mcp::logger::{FileLogger, InMemoryLogger, Logger, SigningLogger, format_log_line};
+use lightbulb_mcp::stats::{Metric, compute, parse_power_events};
+use lightbulb_mcp::testing::TestClient;
+use lightbulb_mcp::{LightService, SessionFactory};
+use serde_json::json;
+use tokio::runtime::Runtime;
+
+const LOG_SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const CONCURRENT_CLIENTS: [usize; 3] = [1, 8, 32];
+
+fn runtime() -> Runtime {
+    tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap()
+}
+
+// A log of `entries` alternating power changes, one a minute, ending now
+async fn filled_logger(entries: usize) -> InMemoryLogger {
+    let mut logger = InMemoryLogger::new();
+    let start = Utc::now() - TimeDelta::minutes(entries as i64);
+    for i in 0..entries {
+        let action = if i % 2 == 0 { "ON [movie] by bench" } else { "OFF by bench" };
+        logger.append_line(&format_log_line(start + TimeDelta::minutes(i as i64), action)).await.unwrap();
+    }
+    logger
+}
+
+fn log_append(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("log_append");
+    group.throughput(Throughput::Elements(1));
+    let path = std::env::temp_dir().join(format!("lightbulb-bench-{}.log", std::process::id()));
+    let loggers: [(&str, Box<dyn Logger + Send + Sync>); 3] = [
+        ("memory", Box::new(InMemoryLogger::new())),
+        ("file", Box::new(FileLogger::new(path.display().to_string()))),
+        ("signed_file", Box::new(SigningLogger::new(Box::new(FileLogger::new(path.display().to_string())), SigningKey::from_bytes(&[7; 32])))),
+    ];
+    for (name, mut logger) in loggers {
+        group.bench_function(name, |b| {
+            b.iter(|| runtime.block_on(logger.log_event(Utc::now(), "ON by bench")).unwrap());
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file(path);
+}
+
+fn summary(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("summary");
+    for entries in LOG_SIZES {
+        group.throughput(Throughput::Elements(entries as u64));
+        let log = runtime.block_on(async { filled_logger(entries).await.read_log().await.unwrap() });
+        group.bench_with_input(BenchmarkId::new("statistics", entries), &log, |b, log| {
+            let metrics = [Metric::Counts, Metric::OnTime, Metric::Energy, Metric::Histogram];
+            b.iter(|| compute(&parse_power_events(log), &[], &metrics, None, Utc::now(), 9.0, Some("movie")));
+        });
+
+        let client = runtime.block_on(async {
+            let logger = filled_logger(entries).await;
+            TestClient::connect(LightService::builder().logger(Box::new(logger)).build()).await.unwrap()
+        });
+        group.bench_function(BenchmarkId::new("summary_resource", entries), |b| {
+            b.to_async(&runtime).iter(|| async { client.read("lightbulb://summary").await.unwrap() });
+        });
+        group.bench_function(BenchmarkId::new("get_statistics", entries), |b| {
+            let arguments = json!({ "metrics": ["counts", "on_time", "energy", "histogram"] });
+            b.to_async(&runtime).iter(|| async { client.call("get_statistics", arguments.clone()).await.unwrap() });
+        });
+    }
+    group.finish();
+}
+
+// Every client sends one call at the same moment; each iteration waits for all of them
+fn concurrent_calls(c: &mut Criterion) {
+    let runtime = runtime();
+    let mut group = c.benchmark_group("concurrent_calls");
+    group.measurement_time(Duration::from_secs(10));
+    for clients in CONCURRENT_CLIENTS {
+        group.throughput(Throughput::Elements(clients as u64));
+        let sessions = runtime.block_on(async {
+            let factory = SessionFactory::Shared(LightService::builder().logger(Box::new(InMemoryLogger::new())).build());
+            let mut sessions = Vec::new();
+            for i in 0..clients {
+                sessions.push(TestClient::connect_as(factory.create(), &format!("bench-{}", i)).await.unwrap());
+            }
+            sessions
+        });
+        group.bench_function(BenchmarkId::new("get_lightbulb_status", clients), |b| {
+            b.to_async(&runtime).iter(|| {
+                futures_util::future::join_all(sessions.iter().map(|client| client.call("get_lightbulb_status", json!({}))))
+            });
+        });
+        group.bench_function(BenchmarkId::new("toggle", clients), |b| {
+            b.to_async(&runtime).iter(|| {
+                futures_util::future::join_all(sessions.iter().enumerate().map(|(i, client)| {
+                    let tool = if i % 2 == 0 { "turn_on_lightbulb" } else { "turn_off_lightbulb" };
+                    client.call(tool, json!({}))
+                }))
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, log_append, summary, concurrent_calls);
+criterion_main!(benches);
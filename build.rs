@@ -0,0 +1,11 @@
+use std::process::Command;
+
+// Embeds the short git hash of the checkout being built, when there is one
+fn main() {
+    let output = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output().ok();
+    if let Some(output) = output.filter(|output| output.status.success()) {
+        println!("cargo:rustc-env=LIGHTBULB_GIT_HASH={}", String::from_utf8_lossy(&output.stdout).trim());
+    }
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
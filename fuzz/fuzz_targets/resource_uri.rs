@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lightbulb_mcp::LightService;
+use lightbulb_mcp::logger::InMemoryLogger;
+use lightbulb_mcp::testing::TestClient;
+
+// Reads an arbitrary URI over the protocol; it must either resolve or fail as an unknown resource
+fuzz_target!(|uri: &str| {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().start_paused(true).build().unwrap();
+    runtime.block_on(async {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+        let client = TestClient::connect(service).await.unwrap();
+        if let Err(error) = client.read(uri).await {
+            let code = error.data.as_ref().and_then(|data| data["code"].as_str());
+            assert_eq!(code, Some("UNKNOWN_RESOURCE"), "{:?} failed with {:?}", uri, error);
+        }
+        client.close().await.unwrap();
+    });
+});
@@ -0,0 +1,38 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lightbulb_mcp::LightService;
+use lightbulb_mcp::logger::InMemoryLogger;
+use lightbulb_mcp::testing::TestClient;
+use rmcp::model::ErrorCode;
+
+// The first byte picks a tool and the rest is its JSON arguments. Every call must succeed,
+// be rejected while deserializing, or fail with a LightError code; panics and hangs are bugs.
+// Time is paused, so effects and macro waits finish as soon as nothing else can run.
+fuzz_target!(|input: &[u8]| {
+    let Some((&pick, arguments)) = input.split_first() else {
+        return;
+    };
+    let Ok(arguments) = serde_json::from_slice::<serde_json::Value>(arguments) else {
+        return;
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().start_paused(true).build().unwrap();
+    runtime.block_on(async {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+        let client = TestClient::connect(service).await.unwrap();
+        let mut tools = client.tools().await.unwrap();
+        tools.sort();
+        let tool = &tools[pick as usize % tools.len()];
+        if let Err(error) = client.call(tool, arguments.clone()).await {
+            let light_error = error.data.as_ref().is_some_and(|data| data["code"].is_string());
+            assert!(
+                light_error || error.code == ErrorCode::INVALID_PARAMS,
+                "{} {} failed with {:?}",
+                tool,
+                arguments,
+                error
+            );
+        }
+        client.close().await.unwrap();
+    });
+});
@@ -0,0 +1,58 @@
+# German messages for lightbulb-mcp. Each key's English text is in the code; `{ $name }` is filled in from the
+# message's arguments, and a message missing here is said in English.
+
+# Status and confirmations
+status-on = Die Lampe ist an
+status-off = Die Lampe ist aus
+status-transitioning = Die Lampe wechselt gerade den Zustand
+status-unreachable = Die Lampe ist nicht erreichbar
+status-locked = { $status } (gesperrt)
+turned-on = Lampe erfolgreich eingeschaltet
+turned-off = Lampe erfolgreich ausgeschaltet
+already-on = Die Lampe ist bereits an
+already-off = Die Lampe ist bereits aus
+locked = Lampe erfolgreich gesperrt
+unlocked = Lampe erfolgreich entsperrt
+undone-on = Letzte Änderung rückgängig gemacht: die Lampe ist an
+undone-off = Letzte Änderung rückgängig gemacht: die Lampe ist aus
+redone-on = Rückgängig gemachte Änderung wiederhergestellt: die Lampe ist an
+redone-off = Rückgängig gemachte Änderung wiederhergestellt: die Lampe ist aus
+
+# Summaries of JSON results
+state-summary = Die Lampe ist { $state } (Version { $version })
+diagnostics-passed = Alle { $total } Prüfungen bestanden oder übersprungen
+diagnostics-failed = { $failed } von { $total } Prüfungen fehlgeschlagen: { $checks }
+jobs-summary = { $total } Aufträge gespeichert, { $running } davon laufen
+
+# Errors, by their code
+error-bulb-locked = „{ $transition }“ ist nicht möglich, solange die Lampe gesperrt ist
+error-invalid-transition = „{ $transition }“ ist im Zustand { $state } nicht möglich
+error-backend-unreachable = Die Lampe ist nicht erreichbar: { $detail }
+error-log-write-failed = Das Ereignis konnte nicht protokolliert werden: { $detail }
+error-log-unavailable = Das Protokoll konnte nicht gelesen werden: { $detail }
+error-signing-disabled = Protokollsignaturen sind auf diesem Server nicht aktiviert
+error-nothing-to-undo = Es gibt keine Änderung, die rückgängig gemacht werden kann
+error-nothing-to-redo = Es gibt keine rückgängig gemachte Änderung, die wiederhergestellt werden kann
+error-invalid-parameter = Ungültiger Parameter: { $detail }
+error-fault-injection-unsupported = Das konfigurierte Backend unterstützt keine Fehlerinjektion
+error-color-unsupported = Das konfigurierte Backend unterstützt keine Farben
+error-brightness-unsupported = Das konfigurierte Backend unterstützt keine Helligkeit
+error-firmware-update-unsupported = Das konfigurierte Backend unterstützt keine Firmware-Updates
+error-firmware-update-in-progress = Ein Firmware-Update läuft bereits ({ $stage })
+error-effect-already-running = Der Effekt „{ $effect }“ läuft bereits
+error-no-active-effect = Es läuft kein Effekt
+error-unknown-job = Unbekannter Auftrag „{ $job_id }“; list_jobs listet die gespeicherten Aufträge
+error-unknown-bulb = Unbekannte Lampe „{ $bulb_id }“; list_lightbulbs listet die Lampen
+error-job-finished = Auftrag „{ $job_id }“ ist bereits beendet
+error-too-many-jobs = Es laufen bereits { $limit } Aufträge; warten Sie, bis einer endet, oder brechen Sie einen ab
+error-unknown-resource = Unbekannte Ressourcen-URI: { $uri }
+error-unknown-tool = Unbekanntes Werkzeug „{ $tool }“; tools/list listet die Werkzeuge dieses Servers
+error-tool-failed = { $detail }
+error-confirmation-invalid = { $detail }
+error-version-conflict = Der Zustand der Lampe hat sich geändert: erwartet war Version { $expected_version }, vorgefunden { $actual_version }
+error-toggle-too-soon = Die Lampe wurde vor weniger als { $min_interval_ms } ms geschaltet; erneut versuchen in { $retry_after_ms } ms
+error-outside-allowed-hours = '{ $tool }' ist während der Sperrzeit '{ $curfew }' nicht erlaubt; ab { $allowed_from } wieder möglich
+error-insufficient-scope = '{ $tool }' erfordert den Scope { $scope }, den das Bearer-Token nicht gewährt
+error-tool-timed-out = '{ $tool }' wurde nicht innerhalb von { $timeout_secs } s fertig; was es bereits bei der Lampe angefordert hat, kann trotzdem noch geschehen
+error-service-unavailable = Der Lampen-Aktor läuft nicht mehr
+error-internal-error = Interner Fehler: { $detail }
@@ -0,0 +1,58 @@
+# French messages for lightbulb-mcp. Each key's English text is in the code; `{ $name }` is filled in from the
+# message's arguments, and a message missing here is said in English.
+
+# Status and confirmations
+status-on = L'ampoule est allumée
+status-off = L'ampoule est éteinte
+status-transitioning = L'ampoule est en train de changer d'état
+status-unreachable = L'ampoule est injoignable
+status-locked = { $status } (verrouillée)
+turned-on = Ampoule allumée avec succès
+turned-off = Ampoule éteinte avec succès
+already-on = L'ampoule est déjà allumée
+already-off = L'ampoule est déjà éteinte
+locked = Ampoule verrouillée avec succès
+unlocked = Ampoule déverrouillée avec succès
+undone-on = Dernière modification annulée : l'ampoule est allumée
+undone-off = Dernière modification annulée : l'ampoule est éteinte
+redone-on = Modification annulée rétablie : l'ampoule est allumée
+redone-off = Modification annulée rétablie : l'ampoule est éteinte
+
+# Summaries of JSON results
+state-summary = L'ampoule est { $state } (version { $version })
+diagnostics-passed = Les { $total } vérifications ont réussi ou ont été ignorées
+diagnostics-failed = { $failed } vérifications sur { $total } ont échoué : { $checks }
+jobs-summary = { $total } tâches conservées, dont { $running } en cours
+
+# Errors, by their code
+error-bulb-locked = Impossible de « { $transition } » tant que l'ampoule est verrouillée
+error-invalid-transition = Impossible de « { $transition } » dans l'état { $state }
+error-backend-unreachable = Impossible de joindre l'ampoule : { $detail }
+error-log-write-failed = Impossible de journaliser l'événement : { $detail }
+error-log-unavailable = Impossible de lire le journal : { $detail }
+error-signing-disabled = La signature du journal n'est pas activée sur ce serveur
+error-nothing-to-undo = Il n'y a aucune modification à annuler
+error-nothing-to-redo = Il n'y a aucune modification annulée à rétablir
+error-invalid-parameter = Paramètre invalide : { $detail }
+error-fault-injection-unsupported = Le backend configuré ne prend pas en charge l'injection de pannes
+error-color-unsupported = Le backend configuré ne prend pas en charge la couleur
+error-brightness-unsupported = Le backend configuré ne prend pas en charge la luminosité
+error-firmware-update-unsupported = Le backend configuré ne prend pas en charge les mises à jour du micrologiciel
+error-firmware-update-in-progress = Une mise à jour du micrologiciel est déjà en cours ({ $stage })
+error-effect-already-running = L'effet « { $effect } » est déjà en cours
+error-no-active-effect = Aucun effet n'est en cours
+error-unknown-job = Tâche inconnue « { $job_id } » ; list_jobs liste les tâches conservées
+error-unknown-bulb = Ampoule inconnue « { $bulb_id } » ; list_lightbulbs liste les ampoules
+error-job-finished = La tâche « { $job_id } » est déjà terminée
+error-too-many-jobs = { $limit } tâches sont déjà en cours ; attendez qu'une se termine ou annulez-en une
+error-unknown-resource = URI de ressource inconnue : { $uri }
+error-unknown-tool = Outil inconnu « { $tool } » ; tools/list liste les outils de ce serveur
+error-tool-failed = { $detail }
+error-confirmation-invalid = { $detail }
+error-version-conflict = L'état de l'ampoule a changé : version { $expected_version } attendue, { $actual_version } trouvée
+error-toggle-too-soon = L'ampoule a été commutée il y a moins de { $min_interval_ms } ms ; réessayez dans { $retry_after_ms } ms
+error-outside-allowed-hours = '{ $tool }' est hors des heures autorisées pendant le couvre-feu '{ $curfew }' ; de nouveau possible à partir de { $allowed_from }
+error-insufficient-scope = '{ $tool }' nécessite le scope { $scope }, que le jeton bearer n'accorde pas
+error-tool-timed-out = '{ $tool }' ne s'est pas terminé en { $timeout_secs } s ; ce qu'il avait déjà demandé à l'ampoule peut encore se produire
+error-service-unavailable = L'acteur de l'ampoule ne fonctionne plus
+error-internal-error = Erreur interne : { $detail }
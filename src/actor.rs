@@ -0,0 +1,2452 @@
+use std::collections::VecDeque;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use futures_util::FutureExt;
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+use tokio::time::{Duration, Instant, sleep_until};
+
+use crate::backend::{BulbMetadata, DEVICE_CALLER, DeviceReport, LightBackend};
+use crate::brightness::{BrightnessCurve, BrightnessSchedule};
+use crate::clock::SharedClock;
+use crate::color::clamp_to_gamut;
+use crate::compaction::{Compaction, compact};
+use crate::diagnostics::{HealthProbe, ReadinessProbe, StateFileProbe, Tasks};
+use crate::effects::EffectStep;
+use crate::error::{LightError, panic_message};
+use crate::events::{EventBus, EventKind, LightEvent};
+use crate::import::{Merge, ParsedLog, merge};
+use crate::journal::{Journal, repair};
+use crate::logger::{
+    DetachedRead, LOG_BRIGHTNESS, LOG_COLOR, LOG_FLUSH_ENTRIES, LogChunk, Logger, OutageEntry, format_attribute_line, format_denied_line, format_named_line, format_outage_line,
+    format_shutdown_line, number_log_entry,
+};
+use crate::model::{Color, PowerState};
+use crate::privacy::{LogPrivacy, Purge, PurgeMode, purge};
+use crate::offline::{HELD_FOR_UNREACHABLE, OfflineQueue, PendingChange, QueuedChange};
+use crate::state::{DEFAULT_BRIGHTNESS, LightState, StateMachine, StateSnapshot, Transition, TransitionError};
+use crate::stats::{HistoryEntry, UsageCounters};
+use crate::usage_cache::{CATCH_UP_ENTRIES, UsageCache, catch_up};
+use crate::tags::{TagRule, format_tags, tags_for};
+
+const COMMAND_BUFFER: usize = 32;
+const HISTORY_LIMIT: usize = 20;
+// How long a power reading from the backend is trusted unless the handle is given another TTL
+pub const DEFAULT_STATE_TTL: Duration = Duration::from_secs(5);
+// Buffered log entries reach the file at most this long after they are written
+const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const LOG_TAG_UNDO: &str = "UNDO";
+const LOG_TAG_REDO: &str = "REDO";
+const LOG_TAG_WATCHDOG_OFF: &str = "WATCHDOG_OFF";
+// The actions behind the safety watchdog's events, as their `action`
+pub const WATCHDOG_OFF_ACTION: &str = "watchdog_off";
+pub const WATCHDOG_WARNING_ACTION: &str = "watchdog_warning";
+
+// Result of a power change request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerChange {
+    Changed,
+    AlreadyInState,
+    // The bulb could not be reached, so the change waits in the offline queue for it to come back
+    Queued,
+}
+
+// Result of a color change: the color the bulb was given, which differs from the one asked for when that was
+// outside the bulb's gamut, and the state it left the bulb in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorChange {
+    pub requested: Color,
+    pub applied: Color,
+    pub state: LightState,
+    // Held in the offline queue rather than applied, leaving `state` as it was
+    pub queued: bool,
+}
+
+// Result of a brightness change: the percentage asked for and the gamma-corrected level the backend was sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrightnessChange {
+    pub percent: u8,
+    pub level: u8,
+    pub state: LightState,
+    // Held in the offline queue rather than applied, leaving `state` as it was
+    pub queued: bool,
+}
+
+// What a factory reset cleared: how many log entries there were, and where they were archived if they were kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FactoryReset {
+    pub entries: usize,
+    pub archived_to: Option<String>,
+}
+
+// Outcome of a mutating command, tagged with the position it was applied in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sequenced<T> {
+    pub sequence: u64,
+    pub outcome: T,
+}
+
+// The state together with its version and what last changed it, read atomically
+#[derive(Debug, Clone, PartialEq)]
+pub struct LightStatus {
+    pub state: LightState,
+    pub version: u64,
+    pub changed_by: Option<String>,
+    pub cause: Option<String>,
+    pub last_changed: Option<DateTime<Utc>>,
+    pub in_state_secs: Option<i64>,
+}
+
+// What the backend last said the bulb's power is, or why it could not say, and when
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendReading {
+    pub power: Result<PowerState, String>,
+    pub at: DateTime<Utc>,
+    // When the backend last answered, which is `at` unless this reading failed; None if it never has
+    pub contacted_at: Option<DateTime<Utc>>,
+}
+
+// Who sent a mutating command, and the state version they expect it to find
+#[derive(Debug, Clone, Default)]
+struct Caller {
+    name: Option<String>,
+    expected_version: Option<u64>,
+    reason: Option<String>,
+    tags: Vec<String>,
+}
+
+type Reply<T> = oneshot::Sender<Result<Sequenced<T>, LightError>>;
+
+// A change a dry run asks about
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Power(PowerState),
+    Apply(Transition),
+    Color(Color),
+    Brightness(u8),
+    Undo,
+    Redo,
+}
+
+// What undo and redo put back: the power, brightness and color the bulb had before a change
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Undoable {
+    power: PowerState,
+    brightness: u8,
+    color: Color,
+}
+
+// How a brightness goes out to the backend right now, for the changes whose brightness the actor picks itself
+#[derive(Debug, Clone, Copy)]
+struct Output {
+    curve: BrightnessCurve,
+    cap: Option<u8>,
+}
+
+impl Output {
+    fn level(&self, percent: u8) -> u8 {
+        self.curve.output(self.cap.map_or(percent, |cap| percent.min(cap)))
+    }
+}
+
+// What a change would do, worked out without touching the bulb, the backend or the log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preview {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub changed: bool,
+    // The entry the change would log, for the changes that are logged
+    pub log_entry: Option<String>,
+}
+
+enum Command {
+    SetPower(PowerState, Caller, Reply<PowerChange>),
+    EffectStep(EffectStep, Option<u8>, Caller, Reply<PowerChange>),
+    Apply(Transition, Caller, Reply<LightState>),
+    SetColor(Color, Caller, Reply<ColorChange>),
+    SetBrightness(u8, u8, Caller, Reply<BrightnessChange>),
+    ReadLog(oneshot::Sender<Result<String, LightError>>),
+    ReadLogChunk(u64, usize, oneshot::Sender<Result<LogChunk, LightError>>),
+    ReadLogTail(usize, oneshot::Sender<Result<Vec<String>, LightError>>),
+    ReadLogRange(DateTime<Utc>, DateTime<Utc>, oneshot::Sender<Result<Vec<String>, LightError>>),
+    ReadLogRecords(DateTime<Utc>, DateTime<Utc>, oneshot::Sender<Result<Option<Vec<HistoryEntry>>, LightError>>),
+    Usage(oneshot::Sender<Result<UsageCounters, LightError>>),
+    Probe(oneshot::Sender<HealthProbe>),
+    // Carries the backend reading when the handle still trusts it, so frequent checks do not poll the device
+    Readiness(Option<BackendReading>, oneshot::Sender<ReadinessProbe>),
+    ReadBackend(oneshot::Sender<BackendReading>),
+    FlushLog(oneshot::Sender<Result<(), LightError>>),
+    Ping(oneshot::Sender<()>),
+    Shutdown(String, oneshot::Sender<Result<(), LightError>>),
+    LogDenied(String, String, Caller, oneshot::Sender<Result<(), LightError>>),
+    LogOutage(OutageEntry, oneshot::Sender<Result<(), LightError>>),
+    LogNamed(Option<String>, Vec<String>, Caller, oneshot::Sender<Result<(), LightError>>),
+    Preview(Vec<Change>, Caller, oneshot::Sender<Vec<Result<Preview, LightError>>>),
+    CompactLog(DateTime<Utc>, oneshot::Sender<Result<Option<Compaction>, LightError>>),
+    PurgeLog(DateTime<Utc>, PurgeMode, LogPrivacy, oneshot::Sender<Result<Option<Purge>, LightError>>),
+    ImportLog(ParsedLog, oneshot::Sender<Result<Merge, LightError>>),
+    Undo(Caller, Output, Reply<PowerState>),
+    Redo(Caller, Output, Reply<PowerState>),
+    FactoryReset(bool, u8, Caller, Reply<FactoryReset>),
+    WatchdogOff(Caller, Reply<PowerChange>),
+    WarnOnTooLong(oneshot::Sender<u64>),
+}
+
+// Task that exclusively owns the state machine, backend and logger
+struct LightActor {
+    machine: StateMachine,
+    backend: Box<dyn LightBackend + Send + Sync>,
+    logger: Box<dyn Logger + Send + Sync>,
+    undo_stack: VecDeque<Undoable>,
+    redo_stack: Vec<Undoable>,
+    last_sequence: u64,
+    // Why the current command was sent and how it was tagged, recorded with its log entry
+    reason: Option<String>,
+    tags: Vec<String>,
+    tag_rules: Vec<TagRule>,
+    privacy: LogPrivacy,
+    events: EventBus,
+    // Counted from the log the first time they are asked for, then kept up to date as entries are written;
+    // None until then, so a server only turning the bulb on and off never parses its history
+    usage: Option<UsageCounters>,
+    // When the entries written since the last flush are due to be flushed
+    flush_due: Option<Instant>,
+    // Logged changes not yet flushed to the log, for repairing it after a crash
+    journal: Option<Journal>,
+    usage_cache: Option<UsageCache>,
+    // The state as of the last change, which handles read without queueing behind commands
+    published: watch::Sender<(LightState, StateSnapshot)>,
+    // The backend's last power reading, including the power it was last told to switch to
+    reading: watch::Sender<Option<BackendReading>>,
+    // When an entry was last written to the log, or it was last rewritten
+    log_modified: watch::Sender<Option<DateTime<Utc>>>,
+    // How often an unreachable bulb is asked whether it is back, when changes it misses are queued for it
+    offline_retry: Option<Duration>,
+    offline: OfflineQueue,
+    // When the bulb is next asked, while changes are queued
+    retry_due: Option<Instant>,
+    queued: watch::Sender<Vec<QueuedChange>>,
+    // The number of the last entry written to the log, carried on from the log itself at startup
+    log_sequence: u64,
+    // The number of the entry the current command wrote, if it wrote one, for its event
+    logged: Option<u64>,
+}
+
+impl LightActor {
+    async fn run(mut self, mut commands: mpsc::Receiver<Command>, mut reports: Option<mpsc::Receiver<DeviceReport>>) {
+        self.log_modified.send_replace(self.logger.modified().await);
+        self.recover_journal().await;
+        self.log_sequence = self.logger.last_sequence().await.unwrap_or(0);
+        self.load_usage_cache().await;
+        loop {
+            let due = self.flush_due;
+            let retry = self.retry_due;
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => {
+                        // A panic fails the one command, whose caller gets an Internal error, not every one after it
+                        if let Err(panic) = AssertUnwindSafe(self.handle(command)).catch_unwind().await {
+                            self.recover_from_panic(&panic_message(panic.as_ref()));
+                        }
+                    },
+                    None => break,
+                },
+                _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                    let _ = self.flush_log().await;
+                },
+                _ = sleep_until(retry.unwrap_or_else(Instant::now)), if retry.is_some() => {
+                    self.retry_offline_queue().await;
+                },
+                report = next_report(&mut reports), if reports.is_some() => match report {
+                    Some(report) => self.follow_device(report).await,
+                    // The backend stopped pushing, so the bulb is only read when asked from now on
+                    None => reports = None,
+                },
+            }
+        }
+        let _ = self.flush_log().await;
+        self.save_usage_cache().await;
+    }
+
+    async fn handle(&mut self, command: Command) {
+        match command {
+            Command::SetPower(target, caller, reply) => {
+                let (sequence, before) = self.begin_command(&caller, Transition::Begin(target));
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.set_power(target).await,
+                    Err(e) => Err(e),
+                };
+                self.announce(sequence, Transition::Begin(target), &before, &result);
+                let result = self.queue_if_unreachable(PendingChange::Power(target), sequence, &caller, result, || PowerChange::Queued);
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            Command::EffectStep(step, level, caller, reply) => {
+                let sequence = self.next_sequence();
+                self.machine.attribute_to(caller.name, action_name(Transition::Begin(step.power)));
+                self.reason = caller.reason;
+                self.tags = caller.tags;
+                let result = self.effect_step(step, level).await;
+                self.publish();
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            Command::Apply(transition, caller, reply) => {
+                let (sequence, before) = self.begin_command(&caller, transition);
+                let result = self
+                    .check_version(caller.expected_version)
+                    .and_then(|()| self.machine.apply(transition).cloned().map_err(LightError::from));
+                self.announce(sequence, transition, &before, &result);
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            Command::SetColor(color, caller, reply) => {
+                let (sequence, before) = self.begin_command(&caller, Transition::SetColor(color));
+                let (previous, undoable) = (self.machine.snapshot().color, self.undoable());
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.set_color(color).await,
+                    Err(e) => Err(e),
+                };
+                let result = match result {
+                    Ok(change) if change.applied != previous => {
+                        self.remember_change(undoable);
+                        self.log_attribute(LOG_COLOR, &change.applied.to_string()).await.map(|()| change)
+                    },
+                    result => result,
+                };
+                self.announce(sequence, Transition::SetColor(color), &before, &result);
+                let queued = ColorChange { requested: color, applied: clamp_to_gamut(color, self.backend.gamut()), state: before, queued: true };
+                let result = self.queue_if_unreachable(PendingChange::Color(color), sequence, &caller, result, || queued);
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            Command::SetBrightness(percent, level, caller, reply) => {
+                let (sequence, before) = self.begin_command(&caller, Transition::SetBrightness(percent));
+                let (previous, undoable) = (self.machine.snapshot().brightness, self.undoable());
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.set_brightness(percent, level).await,
+                    Err(e) => Err(e),
+                };
+                let result = match result {
+                    Ok(change) if percent != previous => {
+                        self.remember_change(undoable);
+                        self.log_attribute(LOG_BRIGHTNESS, &format!("{}%", percent)).await.map(|()| change)
+                    },
+                    result => result,
+                };
+                self.announce(sequence, Transition::SetBrightness(percent), &before, &result);
+                let queued = BrightnessChange { percent, level, state: before, queued: true };
+                let result = self.queue_if_unreachable(PendingChange::Brightness { percent, level }, sequence, &caller, result, || queued);
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            // Log reads can be slow, so they happen beside the actor rather than ahead of the queued commands
+            Command::ReadLog(reply) => {
+                let _ = self.flush_log().await;
+                match self.logger.detached_read() {
+                    Some(read) => detach(read, reply),
+                    None => {
+                        let _ = reply.send(self.read_log().await);
+                    },
+                }
+            },
+            Command::ReadLogChunk(offset, limit, reply) => {
+                let _ = self.flush_log().await;
+                match self.logger.detached_read_chunk(offset, limit) {
+                    Some(read) => detach(read, reply),
+                    None => {
+                        let _ = reply.send(self.logger.read_chunk(offset, limit).await.map_err(log_unavailable));
+                    },
+                }
+            },
+            Command::ReadLogTail(entries, reply) => {
+                let _ = self.flush_log().await;
+                match self.logger.detached_read_tail(entries) {
+                    Some(read) => detach(read, reply),
+                    None => {
+                        let _ = reply.send(self.logger.read_tail(entries).await.map_err(log_unavailable));
+                    },
+                }
+            },
+            Command::ReadLogRange(from, to, reply) => {
+                let _ = self.flush_log().await;
+                match self.logger.detached_read_range(from, to) {
+                    Some(read) => detach(read, reply),
+                    None => {
+                        let _ = reply.send(self.logger.read_range(from, to).await.map_err(log_unavailable));
+                    },
+                }
+            },
+            Command::ReadLogRecords(from, to, reply) => {
+                let _ = self.flush_log().await;
+                let _ = reply.send(self.logger.read_records(from, to).await.map_err(log_unavailable));
+            },
+            Command::Usage(reply) => {
+                let _ = reply.send(self.usage().await);
+            },
+            Command::Probe(reply) => {
+                let _ = reply.send(self.probe().await);
+            },
+            Command::Readiness(reading, reply) => {
+                let _ = reply.send(self.readiness(reading).await);
+            },
+            Command::ReadBackend(reply) => {
+                let power = self.read_power().await;
+                let _ = reply.send(self.record_reading(power));
+            },
+            Command::FlushLog(reply) => {
+                let _ = reply.send(self.flush_log().await);
+            },
+            Command::Ping(reply) => {
+                let _ = reply.send(());
+            },
+            Command::Shutdown(cause, reply) => {
+                let _ = reply.send(self.log_shutdown(&cause).await);
+            },
+            Command::LogDenied(tool, curfew, caller, reply) => {
+                let _ = reply.send(self.log_denied(&tool, &curfew, &caller).await);
+            },
+            Command::LogOutage(entry, reply) => {
+                let line = format_outage_line(self.machine.clock().now(), &entry);
+                let _ = reply.send(self.log_note(&line).await);
+            },
+            Command::LogNamed(name, aliases, caller, reply) => {
+                let who = caller.name.as_deref().and_then(|who| self.privacy.client(who));
+                let line = format_named_line(self.machine.clock().now(), name.as_deref(), &aliases, who.as_deref());
+                let _ = reply.send(self.log_note(&line).await);
+            },
+            Command::Preview(changes, caller, reply) => {
+                let _ = reply.send(self.preview(&changes, &caller));
+            },
+            Command::CompactLog(before, reply) => {
+                let _ = reply.send(self.compact_log(before).await);
+            },
+            Command::PurgeLog(before, mode, privacy, reply) => {
+                let _ = reply.send(self.purge_log(before, mode, &privacy).await);
+            },
+            Command::ImportLog(parsed, reply) => {
+                let _ = reply.send(self.import_log(parsed).await);
+            },
+            Command::WatchdogOff(caller, reply) => {
+                let (sequence, before) = self.begin_command(&caller, WATCHDOG_OFF_ACTION);
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.set_power_logged(PowerState::Off, &format!("{} ({})", PowerState::Off.log_action(), LOG_TAG_WATCHDOG_OFF)).await,
+                    Err(e) => Err(e),
+                };
+                self.announce(sequence, WATCHDOG_OFF_ACTION, &before, &result);
+                if result.is_ok() {
+                    self.supersede_queued(&["power"]);
+                }
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            // Changes nothing, so it is announced without going through `announce`
+            Command::WarnOnTooLong(reply) => {
+                let sequence = self.next_sequence();
+                let state = self.machine.state();
+                self.events.publish(LightEvent {
+                    sequence,
+                    at: self.machine.clock().now(),
+                    kind: EventKind::OnTooLong,
+                    action: WATCHDOG_WARNING_ACTION.to_string(),
+                    from: state.label(),
+                    to: state.label(),
+                    state: self.machine.snapshot(),
+                    log_sequence: None,
+                });
+                let _ = reply.send(sequence);
+            },
+            Command::Undo(caller, output, reply) => {
+                let (sequence, before) = self.begin_command(&caller, "undo");
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.undo(output).await,
+                    Err(e) => Err(e),
+                };
+                self.announce(sequence, "undo", &before, &result);
+                if result.is_ok() {
+                    self.supersede_queued(&["power", "color", "brightness"]);
+                }
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            Command::Redo(caller, output, reply) => {
+                let (sequence, before) = self.begin_command(&caller, "redo");
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.redo(output).await,
+                    Err(e) => Err(e),
+                };
+                self.announce(sequence, "redo", &before, &result);
+                if result.is_ok() {
+                    self.supersede_queued(&["power", "color", "brightness"]);
+                }
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+            Command::FactoryReset(archive, level, caller, reply) => {
+                let (sequence, before) = self.begin_command(&caller, "factory reset");
+                let result = match self.check_version(caller.expected_version) {
+                    Ok(()) => self.factory_reset(archive, level).await,
+                    Err(e) => Err(e),
+                };
+                self.announce(sequence, "factory reset", &before, &result);
+                if result.is_ok() {
+                    self.supersede_queued(&["power", "color", "brightness"]);
+                }
+                let _ = reply.send(result.map(|outcome| Sequenced { sequence, outcome }));
+            },
+        }
+    }
+
+    // Mutating commands are numbered in the order the actor dequeues them
+    fn next_sequence(&mut self) -> u64 {
+        self.last_sequence += 1;
+        self.last_sequence
+    }
+
+    fn begin_command(&mut self, caller: &Caller, action: impl ToString) -> (u64, LightState) {
+        self.machine.attribute_to(caller.name.clone(), action_name(action));
+        self.reason = caller.reason.clone();
+        self.tags = caller.tags.clone();
+        self.logged = None;
+        (self.next_sequence(), self.machine.state().clone())
+    }
+
+    // Checked inside the actor so no other command can slip in between the check and the change
+    fn check_version(&self, expected: Option<u64>) -> Result<(), LightError> {
+        let actual = self.machine.version();
+        match expected {
+            Some(expected) if expected != actual => Err(LightError::VersionConflict { expected, actual }),
+            _ => Ok(()),
+        }
+    }
+
+    fn publish(&self) {
+        self.published.send_replace((self.machine.state().clone(), self.machine.snapshot()));
+    }
+
+    fn record_reading(&self, power: Result<PowerState, String>) -> BackendReading {
+        let at = self.machine.clock().now();
+        let contacted_at = match power {
+            Ok(_) => Some(at),
+            Err(_) => self.reading.borrow().as_ref().and_then(|reading| reading.contacted_at),
+        };
+        let reading = BackendReading { power, at, contacted_at };
+        self.reading.send_replace(Some(reading.clone()));
+        reading
+    }
+
+    // With the offline queue on, a change that could not reach the bulb waits for it instead of failing, and is
+    // answered with `queued`; one that went through supersedes whatever was queued for the same property
+    fn queue_if_unreachable<T>(
+        &mut self,
+        change: PendingChange,
+        sequence: u64,
+        caller: &Caller,
+        result: Result<T, LightError>,
+        queued: impl FnOnce() -> T,
+    ) -> Result<T, LightError> {
+        match result {
+            Err(LightError::BackendUnreachable(_)) if self.offline_retry.is_some() => {
+                let queued_at = self.machine.clock().now();
+                self.offline.push(QueuedChange {
+                    change,
+                    sequence,
+                    queued_at,
+                    by: caller.name.clone(),
+                    reason: caller.reason.clone(),
+                    tags: caller.tags.clone(),
+                });
+                self.queue_changed();
+                Ok(queued())
+            },
+            Ok(outcome) => {
+                self.supersede_queued(&[change.property()]);
+                Ok(outcome)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    // Forgets what was queued for `properties`, now that a change to them has gone through
+    fn supersede_queued(&mut self, properties: &[&str]) {
+        let mut cleared = false;
+        for property in properties {
+            cleared |= self.offline.clear(property);
+        }
+        if cleared {
+            self.queue_changed();
+        }
+    }
+
+    fn queue_changed(&mut self) {
+        self.queued.send_replace(self.offline.changes().to_vec());
+        self.retry_due = match (self.offline.is_empty(), self.offline_retry) {
+            (false, Some(retry)) => Some(self.retry_due.unwrap_or_else(|| Instant::now() + retry)),
+            _ => None,
+        };
+    }
+
+    // Asks the bulb whether it is back and, once it is, applies the queued changes as the callers who made them.
+    // A change the bulb still cannot take stays queued for the next try; one it refuses outright is dropped.
+    async fn retry_offline_queue(&mut self) {
+        self.retry_due = None;
+        let power = self.read_power().await;
+        let Ok(reported) = self.record_reading(power).power else {
+            self.queue_changed();
+            return;
+        };
+        let unknown = self.machine.state() == &LightState::Unreachable;
+        let changes = self.offline.take(unknown);
+        // With no power change to apply first, the bulb's power is taken from what it reports, without logging it
+        if unknown && !changes.iter().any(|queued| matches!(queued.change, PendingChange::Power(_))) {
+            let (sequence, before) = self.begin_command(&Caller::default(), "reconnect");
+            let result = self.drive_power(reported, None).await;
+            self.announce(sequence, "reconnect", &before, &result);
+        }
+        for (index, queued) in changes.iter().enumerate() {
+            match self.apply_queued(queued).await {
+                Ok(()) => {},
+                Err(LightError::BackendUnreachable(_) | LightError::ToggleTooSoon { .. }) => {
+                    changes[index..].iter().for_each(|queued| self.offline.push(queued.clone()));
+                    break;
+                },
+                Err(e) => crate::diagnostic!(Warn, "dropped the queued change to {}: {}", queued.change.describe(), e),
+            }
+        }
+        self.queue_changed();
+    }
+
+    async fn apply_queued(&mut self, queued: &QueuedChange) -> Result<(), LightError> {
+        let caller = Caller { name: queued.by.clone(), expected_version: None, reason: queued.reason.clone(), tags: queued.tags.clone() };
+        let transition = match queued.change {
+            PendingChange::Power(target) => Transition::Begin(target),
+            PendingChange::Color(color) => Transition::SetColor(color),
+            PendingChange::Brightness { percent, .. } => Transition::SetBrightness(percent),
+        };
+        let (sequence, before) = self.begin_command(&caller, transition);
+        let result = match queued.change {
+            PendingChange::Power(target) => self.set_power(target).await.map(|_| ()),
+            PendingChange::Color(color) => self.set_color(color).await.map(|_| ()),
+            PendingChange::Brightness { percent, level } => self.set_brightness(percent, level).await.map(|_| ()),
+        };
+        self.announce(sequence, transition, &before, &result);
+        result
+    }
+
+    // Publishes the state, and an event if the command changed the bulb or could not reach it
+    fn announce<T>(&self, sequence: u64, action: impl ToString, before: &LightState, result: &Result<T, LightError>) {
+        self.publish();
+        let after = self.machine.state();
+        let kind = match result {
+            Err(LightError::BackendUnreachable(_)) => EventKind::BackendUnreachable,
+            _ if after != before => EventKind::StateChanged,
+            _ => return,
+        };
+        self.events.publish(LightEvent {
+            sequence,
+            at: self.machine.clock().now(),
+            kind,
+            action: action_name(action),
+            from: before.label(),
+            to: after.label(),
+            state: self.machine.snapshot(),
+            log_sequence: self.logged,
+        });
+    }
+
+    // Reads include every entry written so far
+    async fn read_log(&mut self) -> Result<String, LightError> {
+        let _ = self.flush_log().await;
+        self.logger.read_log().await.map_err(log_unavailable)
+    }
+
+    // A failed flush is reported once; the entries it held are lost
+    async fn flush_log(&mut self) -> Result<(), LightError> {
+        if self.flush_due.take().is_none() {
+            return Ok(());
+        }
+        self.logger.flush().await.map_err(|e| {
+            crate::diagnostic!(Warn, "lost buffered log entries: {:#}", e);
+            LightError::LogWriteFailed(e.to_string())
+        })?;
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.clear().await
+        {
+            crate::diagnostic!(Warn, "{:#}", e);
+        }
+        Ok(())
+    }
+
+    async fn log_shutdown(&mut self, cause: &str) -> Result<(), LightError> {
+        let line = self.number(&format_shutdown_line(self.machine.clock().now(), cause));
+        self.logger.append_line(&line).await.map_err(|e| LightError::LogWriteFailed(e.to_string()))?;
+        self.log_written();
+        if let Some(usage) = &mut self.usage {
+            usage.record(&line);
+        }
+        self.flush_due = Some(Instant::now());
+        self.flush_log().await?;
+        self.save_usage_cache().await;
+        Ok(())
+    }
+
+    // Refused calls change nothing, so they are neither journaled nor announced
+    async fn log_denied(&mut self, tool: &str, curfew: &str, caller: &Caller) -> Result<(), LightError> {
+        let who = caller.name.as_deref().and_then(|who| self.privacy.client(who));
+        self.log_note(&format_denied_line(self.machine.clock().now(), tool, who.as_deref(), curfew)).await
+    }
+
+    // Numbers and writes an entry that records something other than a change to the bulb's state
+    async fn log_note(&mut self, entry: &str) -> Result<(), LightError> {
+        let line = self.number(entry);
+        self.logger.append_line(&line).await.map_err(|e| LightError::LogWriteFailed(e.to_string()))?;
+        self.log_written();
+        if let Some(usage) = &mut self.usage {
+            usage.record(&line);
+        }
+        self.flush_due.get_or_insert_with(|| Instant::now() + LOG_FLUSH_INTERVAL);
+        Ok(())
+    }
+
+    // Color and brightness changes are logged once made, without the journal, as they are not replayed
+    async fn log_attribute(&mut self, property: &str, value: &str) -> Result<(), LightError> {
+        let entry = self.attribute_entry(property, value, self.machine.acting(), &self.tags, self.reason.as_deref());
+        self.log_note(&format_attribute_line(self.machine.clock().now(), &entry)).await
+    }
+
+    // A change cut short by the panic may have left the bulb anywhere, so it is reported unreachable until the
+    // next change or status read finds out
+    fn recover_from_panic(&mut self, message: &str) {
+        crate::diagnostic!(Warn, "the light actor recovered from a panic: {}", message);
+        if matches!(self.machine.state(), LightState::Transitioning { .. }) {
+            let _ = self.machine.apply(Transition::Fail);
+            self.publish();
+        }
+    }
+
+    // Appends the entries of changes a crash kept out of the log, before the first command is handled
+    async fn recover_journal(&mut self) {
+        let Some(journal) = &self.journal else {
+            return;
+        };
+        let intents = match journal.pending().await {
+            Ok(intents) => intents,
+            Err(e) => {
+                crate::diagnostic!(Warn, "{:#}", e);
+                return;
+            },
+        };
+        if !intents.is_empty() {
+            let tail = match self.logger.read_tail(intents.len() + LOG_FLUSH_ENTRIES).await {
+                Ok(tail) => tail,
+                Err(e) => {
+                    crate::diagnostic!(Warn, "could not check the log against the journal: {:#}", e);
+                    return;
+                },
+            };
+            let power = self.backend.power().await;
+            self.record_reading(power.as_ref().copied().map_err(|e| e.to_string()));
+            let missing = repair(&intents, &tail, power.ok());
+            for (at, entry) in &missing {
+                if let Err(e) = self.logger.log_event(*at, entry).await {
+                    crate::diagnostic!(Warn, "could not recover log entries from the journal: {:#}", e);
+                    return;
+                }
+            }
+            if !missing.is_empty() {
+                self.log_written();
+                crate::diagnostic!(Warn, "recovered {} log entries from the journal of an unclean shutdown", missing.len());
+            }
+        }
+        // A journal left behind is only safe to drop once what it held is in the log
+        self.flush_due = Some(Instant::now());
+        let _ = self.flush_log().await;
+    }
+
+    // Takes up the counters saved at the last shutdown, if they still match the end of the log, counting whatever
+    // was logged after them; otherwise the log is scanned on the first request as usual
+    async fn load_usage_cache(&mut self) {
+        let Some(cache) = &self.usage_cache else {
+            return;
+        };
+        let saved = match cache.load().await {
+            Ok(Some(saved)) => saved,
+            Ok(None) => return,
+            Err(e) => {
+                crate::diagnostic!(Warn, "{:#}", e);
+                return;
+            },
+        };
+        let Ok(tail) = self.logger.read_tail(CATCH_UP_ENTRIES).await else {
+            return;
+        };
+        match catch_up(saved, &tail, tail.len() < CATCH_UP_ENTRIES) {
+            Some(usage) => self.usage = Some(usage),
+            None => crate::diagnostic!(Info, "the statistics saved in {} no longer match the log, which will be counted again", cache.path()),
+        }
+    }
+
+    async fn save_usage_cache(&self) {
+        if let (Some(cache), Some(usage)) = (&self.usage_cache, &self.usage)
+            && let Err(e) = cache.save(usage).await
+        {
+            crate::diagnostic!(Warn, "{:#}", e);
+        }
+    }
+
+    // Counted again from the compacted log, which holds the same totals, unless nothing has counted them yet
+    async fn compact_log(&mut self, before: DateTime<Utc>) -> Result<Option<Compaction>, LightError> {
+        let log = self.read_log().await?;
+        let Some(compaction) = compact(&log, before, |line| self.logger.as_written(line)) else {
+            return Ok(None);
+        };
+        self.logger.replace_log(&compaction.log).await.map_err(|e| LightError::LogWriteFailed(format!("{:#}", e)))?;
+        self.log_written();
+        if self.usage.is_some() {
+            self.usage = Some(UsageCounters::from_log(&compaction.log));
+        }
+        Ok(Some(compaction))
+    }
+
+    async fn purge_log(&mut self, before: DateTime<Utc>, mode: PurgeMode, privacy: &LogPrivacy) -> Result<Option<Purge>, LightError> {
+        let log = self.read_log().await?;
+        let Some(purged) = purge(&log, before, mode, privacy, |line| self.logger.as_written(line)) else {
+            return Ok(None);
+        };
+        self.logger.replace_log(&purged.log).await.map_err(|e| LightError::LogWriteFailed(format!("{:#}", e)))?;
+        self.log_written();
+        if self.usage.is_some() {
+            self.usage = Some(UsageCounters::from_log(&purged.log));
+        }
+        Ok(Some(purged))
+    }
+
+    // The bulb's state is left alone; only the history it is counted from changes
+    async fn import_log(&mut self, parsed: ParsedLog) -> Result<Merge, LightError> {
+        let log = self.read_log().await?;
+        let merged = merge(&log, parsed.entries, parsed.skipped, self.machine.clock().now(), |line| self.logger.as_written(line));
+        if merged.imported == 0 {
+            return Ok(merged);
+        }
+        self.logger.replace_log(&merged.log).await.map_err(|e| LightError::LogWriteFailed(format!("{:#}", e)))?;
+        self.log_written();
+        if self.usage.is_some() {
+            self.usage = Some(UsageCounters::from_log(&merged.log));
+        }
+        Ok(merged)
+    }
+
+    async fn read_power(&mut self) -> Result<PowerState, String> {
+        let started = Instant::now();
+        let result = self.backend.power().await;
+        match &result {
+            Ok(power) => crate::diagnostic!(Debug, "backend {} power: {:?} in {:?}", self.backend.name(), power, started.elapsed()),
+            Err(_) => crate::diagnostic!(Debug, "backend {} power: {}", self.backend.name(), outcome(&result, started)),
+        }
+        result.map_err(|e| e.to_string())
+    }
+
+    async fn readiness(&mut self, reading: Option<BackendReading>) -> ReadinessProbe {
+        let backend_power = match reading {
+            Some(reading) => reading.power,
+            None => {
+                let power = self.read_power().await;
+                self.record_reading(power.clone());
+                power
+            },
+        };
+        ReadinessProbe {
+            backend_name: self.backend.name().to_string(),
+            log_writable: self.logger.check_writable().await.map_err(|e| format!("{:#}", e)),
+            backend_power,
+            expected_power: self.machine.state().power(),
+            tasks: Vec::new(),
+        }
+    }
+
+    async fn probe(&mut self) -> HealthProbe {
+        let _ = self.flush_log().await;
+        let backend_power = self.read_power().await;
+        self.record_reading(backend_power.clone());
+        let state_files = match &self.usage_cache {
+            Some(cache) => {
+                let read = cache.load().await.map(|saved| saved.is_some()).map_err(|e| format!("{:#}", e));
+                vec![StateFileProbe { name: "usage_cache", path: cache.path().to_string(), read }]
+            },
+            None => Vec::new(),
+        };
+        HealthProbe {
+            backend_name: self.backend.name().to_string(),
+            log_writable: self.logger.check_writable().await.map_err(|e| format!("{:#}", e)),
+            log_content: self.logger.read_log().await.map_err(|e| format!("{:#}", e)),
+            backend_power,
+            expected_power: self.machine.state().power(),
+            tasks: Vec::new(),
+            state_files,
+        }
+    }
+
+    async fn set_power(&mut self, target: PowerState) -> Result<PowerChange, LightError> {
+        self.set_power_logged(target, target.log_action()).await
+    }
+
+    async fn set_power_logged(&mut self, target: PowerState, log_action: &str) -> Result<PowerChange, LightError> {
+        let state = self.machine.state();
+        if matches!(state, LightState::Off | LightState::On { .. }) && state.power() == Some(target) {
+            return Ok(PowerChange::AlreadyInState);
+        }
+        let previous = self.undoable();
+        self.drive_power(target, Some(log_action)).await?;
+        self.remember_change(previous);
+        Ok(PowerChange::Changed)
+    }
+
+    async fn set_color(&mut self, requested: Color) -> Result<ColorChange, LightError> {
+        self.hold_for_unreachable(self.backend.supports_color(), LightError::ColorUnsupported)?;
+        self.machine.check(Transition::SetColor(requested))?;
+        if !self.backend.supports_color() {
+            return Err(LightError::ColorUnsupported);
+        }
+        let color = clamp_to_gamut(requested, self.backend.gamut());
+        let started = Instant::now();
+        let result = self.backend.set_color(color).await;
+        crate::diagnostic!(Debug, "backend {} set_color {}: {}", self.backend.name(), color, outcome(&result, started));
+        result.map_err(|e| LightError::BackendUnreachable(e.to_string()))?;
+        let state = self.machine.apply(Transition::SetColor(color))?.clone();
+        Ok(ColorChange { requested, applied: color, state, queued: false })
+    }
+
+    async fn set_brightness(&mut self, percent: u8, level: u8) -> Result<BrightnessChange, LightError> {
+        self.hold_for_unreachable(self.backend.supports_brightness(), LightError::BrightnessUnsupported)?;
+        self.machine.check(Transition::SetBrightness(percent))?;
+        if !self.backend.supports_brightness() {
+            return Err(LightError::BrightnessUnsupported);
+        }
+        let started = Instant::now();
+        let result = self.backend.set_brightness(level).await;
+        crate::diagnostic!(Debug, "backend {} set_brightness {}: {}", self.backend.name(), level, outcome(&result, started));
+        result.map_err(|e| LightError::BackendUnreachable(e.to_string()))?;
+        let state = self.machine.apply(Transition::SetBrightness(percent))?.clone();
+        Ok(BrightnessChange { percent, level, state, queued: false })
+    }
+
+    // An unreachable bulb takes no color or brightness, so with the offline queue on they are held for it to come back
+    fn hold_for_unreachable(&self, supported: bool, unsupported: LightError) -> Result<(), LightError> {
+        match self.offline_retry.is_some() && self.machine.state() == &LightState::Unreachable {
+            true if !supported => Err(unsupported),
+            true => Err(LightError::BackendUnreachable(HELD_FOR_UNREACHABLE.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    // Effects flash the bulb many times a second, so their steps skip the log and undo history, and are
+    // published without being announced
+    async fn effect_step(&mut self, step: EffectStep, level: Option<u8>) -> Result<PowerChange, LightError> {
+        if let Some(color) = step.color {
+            self.set_color(color).await?;
+        }
+        if let (Some(percent), Some(level)) = (step.brightness, level) {
+            self.set_brightness(percent, level).await?;
+        }
+        if self.machine.state().power() == Some(step.power) {
+            return Ok(PowerChange::AlreadyInState);
+        }
+        self.drive_power(step.power, None).await?;
+        Ok(PowerChange::Changed)
+    }
+
+    async fn undo(&mut self, output: Output) -> Result<PowerState, LightError> {
+        let target = *self.undo_stack.back().ok_or(LightError::NothingToUndo)?;
+        let current = self.undoable();
+        self.restore(target, LOG_TAG_UNDO, output).await?;
+        self.undo_stack.pop_back();
+        if let Some(current) = current {
+            self.redo_stack.push(current);
+        }
+        Ok(target.power)
+    }
+
+    async fn redo(&mut self, output: Output) -> Result<PowerState, LightError> {
+        let target = *self.redo_stack.last().ok_or(LightError::NothingToRedo)?;
+        let current = self.undoable();
+        self.restore(target, LOG_TAG_REDO, output).await?;
+        self.redo_stack.pop();
+        if let Some(current) = current {
+            self.remember(current);
+        }
+        Ok(target.power)
+    }
+
+    // Puts the color, brightness and power of `target` back, logging each that changes with `tag`. A toggle held
+    // back by the minimum interval is refused before anything changes, and power goes last, so a bulb that cannot
+    // be reached is left as it was unless it was its power that failed.
+    async fn restore(&mut self, target: Undoable, tag: &str, output: Output) -> Result<(), LightError> {
+        // A locked bulb keeps what it has, even where putting it back would leave its power as it is
+        if matches!(self.machine.state(), LightState::Locked { .. }) {
+            return Err(TransitionError { state: "LOCKED", transition: Transition::Begin(target.power) }.into());
+        }
+        let toggles = self.machine.state().power() != Some(target.power);
+        if toggles {
+            self.check_toggle_interval()?;
+        }
+        let snapshot = self.machine.snapshot();
+        if target.color != snapshot.color {
+            self.set_color(target.color).await?;
+            self.log_attribute(LOG_COLOR, &format!("{} ({})", target.color, tag)).await?;
+        }
+        if target.brightness != snapshot.brightness {
+            self.set_brightness(target.brightness, output.level(target.brightness)).await?;
+            self.log_attribute(LOG_BRIGHTNESS, &format!("{}% ({})", target.brightness, tag)).await?;
+        }
+        if toggles {
+            self.drive_power(target.power, Some(&format!("{} ({})", target.power.log_action(), tag))).await?;
+        }
+        Ok(())
+    }
+
+    // Returns the bulb to how it came out of the box: off, white at full brightness, with no history. The bulb is
+    // reset first, so one that cannot be reached keeps its history; one whose log cannot be cleared is still reset.
+    async fn factory_reset(&mut self, archive: bool, level: u8) -> Result<FactoryReset, LightError> {
+        if matches!(self.machine.state(), LightState::Locked { .. }) {
+            return Err(TransitionError { state: "LOCKED", transition: Transition::Begin(PowerState::Off) }.into());
+        }
+        let entries = self.read_log().await?.lines().filter(|line| !line.trim().is_empty()).count();
+        if self.machine.state() != &LightState::Off {
+            let result = self.backend.set_power(PowerState::Off).await;
+            self.record_reading(result.as_ref().map(|()| PowerState::Off).map_err(|e| e.to_string()));
+            result.map_err(|e| LightError::BackendUnreachable(e.to_string()))?;
+        }
+        if self.backend.supports_color() {
+            self.backend.set_color(Color::WHITE).await.map_err(|e| LightError::BackendUnreachable(e.to_string()))?;
+        }
+        if self.backend.supports_brightness() {
+            self.backend.set_brightness(level).await.map_err(|e| LightError::BackendUnreachable(e.to_string()))?;
+        }
+        self.machine.reset();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        if self.usage.is_some() {
+            self.usage = Some(UsageCounters::default());
+        }
+        let cleared = match archive && entries > 0 {
+            true => self.logger.archive_log(&self.machine.clock().now().format("%Y%m%dT%H%M%SZ").to_string()).await.map(Some),
+            false => self.logger.replace_log("").await.map(|()| None),
+        };
+        self.log_written();
+        let archived_to = cleared.map_err(|e| LightError::LogWriteFailed(format!("the bulb was reset, but its log was kept: {:#}", e)))?;
+        Ok(FactoryReset { entries, archived_to })
+    }
+
+    fn log_written(&self) {
+        self.log_modified.send_replace(Some(self.machine.clock().now()));
+    }
+
+    // The bulb as undo would put it back, or None while its power is not known
+    fn undoable(&self) -> Option<Undoable> {
+        let snapshot = self.machine.snapshot();
+        snapshot.power.map(|power| Undoable { power, brightness: snapshot.brightness, color: snapshot.color })
+    }
+
+    // Records the bulb as it was before a change just made, which leaves nothing to redo
+    fn remember_change(&mut self, previous: Option<Undoable>) {
+        if let Some(previous) = previous {
+            self.remember(previous);
+        }
+        self.redo_stack.clear();
+    }
+
+    fn remember(&mut self, state: Undoable) {
+        if self.undo_stack.len() == HISTORY_LIMIT {
+            self.undo_stack.pop_front();
+        }
+        self.undo_stack.push_back(state);
+    }
+
+    fn check_toggle_interval(&self) -> Result<(), LightError> {
+        match self.machine.toggle_wait() {
+            Some(wait) => {
+                let min_interval_ms = self.machine.min_toggle_interval().as_millis() as u64;
+                Err(LightError::ToggleTooSoon { retry_after_ms: wait.as_millis().max(1) as u64, min_interval_ms })
+            },
+            None => Ok(()),
+        }
+    }
+
+    // Moves the machine through a transition to `target`, driving the backend and logging on success
+    async fn drive_power(&mut self, target: PowerState, log_action: Option<&str>) -> Result<(), LightError> {
+        // Effects pace their own flashes, so only logged changes are held to the minimum interval
+        if log_action.is_some() {
+            self.check_toggle_interval()?;
+        }
+        // Numbered ahead of the change, so the journal has the entry as it will be written, but only used up once it is
+        let entry = log_action.map(|action| {
+            let entry = self.log_entry(action, target, self.machine.acting(), &self.tags, self.reason.as_deref());
+            number_log_entry(&entry, self.log_sequence + 1)
+        });
+        // Written ahead of the change, so a crash before its entry reaches the log can be repaired
+        if let (Some(journal), Some(entry)) = (&mut self.journal, &entry) {
+            journal
+                .begin(self.machine.clock().now(), target, entry)
+                .await
+                .map_err(|e| LightError::LogWriteFailed(format!("{:#}", e)))?;
+        }
+        if let Err(e) = self.machine.apply(Transition::Begin(target)) {
+            self.abort_journaled(entry.is_some()).await;
+            return Err(e.into());
+        }
+        // Readers see the bulb transitioning while the backend takes its time
+        self.publish();
+        let started = Instant::now();
+        let result = self.backend.set_power(target).await;
+        crate::diagnostic!(Debug, "backend {} set_power {:?}: {}", self.backend.name(), target, outcome(&result, started));
+        if let Err(e) = result {
+            self.record_reading(Err(e.to_string()));
+            let _ = self.machine.apply(Transition::Fail);
+            self.abort_journaled(entry.is_some()).await;
+            return Err(LightError::BackendUnreachable(e.to_string()));
+        }
+        self.record_reading(Ok(target));
+        let _ = self.machine.apply(Transition::Complete);
+        let Some(entry) = entry else {
+            return Ok(());
+        };
+        self.log_sequence += 1;
+        self.logged = Some(self.log_sequence);
+        self.write_entry(&entry).await
+    }
+
+    // Takes the next number for an entry written straight away
+    fn number(&mut self, entry: &str) -> String {
+        self.log_sequence += 1;
+        self.logged = Some(self.log_sequence);
+        number_log_entry(entry, self.log_sequence)
+    }
+
+    async fn write_entry(&mut self, entry: &str) -> Result<(), LightError> {
+        let line = self.logger.log_event(self.machine.clock().now(), entry).await.map_err(|e| LightError::LogWriteFailed(e.to_string()))?;
+        self.log_written();
+        if let Some(usage) = &mut self.usage {
+            usage.record(&line);
+        }
+        self.flush_due.get_or_insert_with(|| Instant::now() + LOG_FLUSH_INTERVAL);
+        Ok(())
+    }
+
+    // A change someone made at the bulb itself, which its backend pushed: the state follows the bulb, and a power
+    // change is logged as the device's and can be undone, without the backend being told what the bulb already did.
+    // Reports of the state the server already has, such as echoes of its own changes, are ignored.
+    async fn follow_device(&mut self, report: DeviceReport) {
+        let (snapshot, state) = (self.machine.snapshot(), self.machine.state());
+        let (transition, property, current) = match report {
+            DeviceReport::Power(power) => {
+                self.record_reading(Ok(power));
+                let settled = matches!(state, LightState::Off | LightState::On { .. });
+                (Transition::Begin(power), "power", settled && state.power() == Some(power))
+            },
+            DeviceReport::Color(color) => (Transition::SetColor(color), "color", snapshot.color == color),
+            DeviceReport::Brightness(percent) => (Transition::SetBrightness(percent), "brightness", snapshot.brightness == percent),
+        };
+        if current {
+            return;
+        }
+        let caller = Caller { name: Some(DEVICE_CALLER.to_string()), ..Caller::default() };
+        let (sequence, before) = self.begin_command(&caller, transition);
+        let result = match report {
+            DeviceReport::Power(power) => self.follow_device_power(power).await,
+            _ => self.machine.apply(transition).map(|_| ()).map_err(LightError::from),
+        };
+        self.announce(sequence, transition, &before, &result);
+        match result {
+            Ok(()) => self.supersede_queued(&[property]),
+            Err(e) => crate::diagnostic!(Warn, "the bulb's {} was changed at the device, but the server could not follow: {}", property, e),
+        }
+    }
+
+    async fn follow_device_power(&mut self, target: PowerState) -> Result<(), LightError> {
+        let previous = self.undoable();
+        self.machine.apply(Transition::Begin(target))?;
+        let _ = self.machine.apply(Transition::Complete);
+        self.remember_change(previous);
+        let entry = self.log_entry(target.log_action(), target, self.machine.acting(), &self.tags, None);
+        let entry = self.number(&entry);
+        self.write_entry(&entry).await
+    }
+
+    fn log_entry(&self, action: &str, target: PowerState, who: Option<&str>, tags: &[String], reason: Option<&str>) -> String {
+        let mut entry = action.to_string();
+        let tags = tags_for(tags, &self.tag_rules, target, who, self.machine.clock().local_time());
+        if !tags.is_empty() {
+            entry.push_str(&format!(" {}", format_tags(&tags)));
+        }
+        // Tag rules see the client before the log forgets it
+        entry.push_str(&self.attribution(who, reason));
+        entry
+    }
+
+    // "COLOR #ff8800 [reading] by agent": tag rules go by power, so only the caller's own tags are kept
+    fn attribute_entry(&self, property: &str, value: &str, who: Option<&str>, tags: &[String], reason: Option<&str>) -> String {
+        let mut entry = format!("{} {}", property, value);
+        if !tags.is_empty() {
+            entry.push_str(&format!(" {}", format_tags(tags)));
+        }
+        entry.push_str(&self.attribution(who, reason));
+        entry
+    }
+
+    fn attribution(&self, who: Option<&str>, reason: Option<&str>) -> String {
+        let mut attribution = String::new();
+        if let Some(who) = who.and_then(|who| self.privacy.client(who)) {
+            attribution.push_str(&format!(" by {}", who));
+        }
+        if let Some(reason) = reason.and_then(|reason| self.privacy.reason(reason)) {
+            attribution.push_str(&format!(" (reason: {})", reason));
+        }
+        attribution
+    }
+
+    // Runs `changes` in order on a scratch copy of the machine and history, stopping at the first that fails
+    fn preview(&self, changes: &[Change], caller: &Caller) -> Vec<Result<Preview, LightError>> {
+        if let Err(e) = self.check_version(caller.expected_version) {
+            return vec![Err(e)];
+        }
+        let mut machine = self.machine.scratch();
+        let (mut undo_stack, mut redo_stack) = (self.undo_stack.clone(), self.redo_stack.clone());
+        let mut previews = Vec::new();
+        for change in changes {
+            let from = machine.state().clone();
+            let preview = self.preview_change(&mut machine, &mut undo_stack, &mut redo_stack, *change, caller).map(|log_entry| Preview {
+                from: from.label(),
+                to: machine.state().label(),
+                changed: *machine.state() != from,
+                log_entry,
+            });
+            let failed = preview.is_err();
+            previews.push(preview);
+            if failed {
+                break;
+            }
+        }
+        previews
+    }
+
+    // Mirrors set_power, set_color, set_brightness, undo and redo, returning the entry the change would log; an undo
+    // or redo putting back more than one thing gives their entries one after another
+    fn preview_change(
+        &self,
+        machine: &mut StateMachine,
+        undo_stack: &mut VecDeque<Undoable>,
+        redo_stack: &mut Vec<Undoable>,
+        change: Change,
+        caller: &Caller,
+    ) -> Result<Option<String>, LightError> {
+        let snapshot = machine.snapshot();
+        let current = snapshot.power.map(|power| Undoable { power, brightness: snapshot.brightness, color: snapshot.color });
+        let target = match change {
+            Change::Apply(transition) => {
+                machine.apply(transition)?;
+                return Ok(None);
+            },
+            Change::Color(color) => {
+                machine.check(Transition::SetColor(color))?;
+                if !self.backend.supports_color() {
+                    return Err(LightError::ColorUnsupported);
+                }
+                let color = clamp_to_gamut(color, self.backend.gamut());
+                machine.apply(Transition::SetColor(color))?;
+                if color == snapshot.color {
+                    return Ok(None);
+                }
+                undo_stack.extend(current);
+                redo_stack.clear();
+                return Ok(Some(self.attribute_entry(LOG_COLOR, &color.to_string(), caller.name.as_deref(), &caller.tags, caller.reason.as_deref())));
+            },
+            Change::Brightness(percent) => {
+                machine.check(Transition::SetBrightness(percent))?;
+                if !self.backend.supports_brightness() {
+                    return Err(LightError::BrightnessUnsupported);
+                }
+                machine.apply(Transition::SetBrightness(percent))?;
+                if percent == snapshot.brightness {
+                    return Ok(None);
+                }
+                undo_stack.extend(current);
+                redo_stack.clear();
+                return Ok(Some(self.attribute_entry(LOG_BRIGHTNESS, &format!("{}%", percent), caller.name.as_deref(), &caller.tags, caller.reason.as_deref())));
+            },
+            Change::Power(target) => {
+                if matches!(machine.state(), LightState::Off | LightState::On { .. }) && snapshot.power == Some(target) {
+                    return Ok(None);
+                }
+                machine.apply(Transition::Begin(target))?;
+                machine.apply(Transition::Complete)?;
+                undo_stack.extend(current);
+                redo_stack.clear();
+                return Ok(Some(self.log_entry(target.log_action(), target, caller.name.as_deref(), &caller.tags, caller.reason.as_deref())));
+            },
+            Change::Undo => *undo_stack.back().ok_or(LightError::NothingToUndo)?,
+            Change::Redo => *redo_stack.last().ok_or(LightError::NothingToRedo)?,
+        };
+        let tag = if change == Change::Undo { LOG_TAG_UNDO } else { LOG_TAG_REDO };
+        // As restore does: nothing while locked, otherwise color, then brightness, then power, each logged when it
+        // changes
+        if matches!(machine.state(), LightState::Locked { .. }) {
+            return Err(TransitionError { state: "LOCKED", transition: Transition::Begin(target.power) }.into());
+        }
+        let mut entries = Vec::new();
+        if target.color != snapshot.color {
+            machine.apply(Transition::SetColor(target.color))?;
+            entries.push(self.attribute_entry(LOG_COLOR, &format!("{} ({})", target.color, tag), caller.name.as_deref(), &caller.tags, caller.reason.as_deref()));
+        }
+        if target.brightness != snapshot.brightness {
+            machine.apply(Transition::SetBrightness(target.brightness))?;
+            let value = format!("{}% ({})", target.brightness, tag);
+            entries.push(self.attribute_entry(LOG_BRIGHTNESS, &value, caller.name.as_deref(), &caller.tags, caller.reason.as_deref()));
+        }
+        if snapshot.power != Some(target.power) {
+            machine.apply(Transition::Begin(target.power))?;
+            machine.apply(Transition::Complete)?;
+            let action = format!("{} ({})", target.power.log_action(), tag);
+            entries.push(self.log_entry(&action, target.power, caller.name.as_deref(), &caller.tags, caller.reason.as_deref()));
+        }
+        if change == Change::Undo {
+            undo_stack.pop_back();
+            redo_stack.extend(current);
+        } else {
+            redo_stack.pop();
+            undo_stack.extend(current);
+        }
+        Ok((!entries.is_empty()).then(|| entries.join("; ")))
+    }
+
+    async fn abort_journaled(&mut self, journaled: bool) {
+        if let Some(journal) = &mut self.journal
+            && journaled
+            && let Err(e) = journal.abort().await
+        {
+            crate::diagnostic!(Warn, "{:#}", e);
+        }
+    }
+
+    // Scans the log on the first request, and again after a scan that could not read it
+    async fn usage(&mut self) -> Result<UsageCounters, LightError> {
+        if let Some(usage) = &self.usage {
+            return Ok(usage.clone());
+        }
+        let log = self.read_log().await?;
+        Ok(self.usage.insert(UsageCounters::from_log(&log)).clone())
+    }
+}
+
+// How a backend call went, for debug diagnostics
+fn outcome<T>(result: &anyhow::Result<T>, started: Instant) -> String {
+    match result {
+        Ok(_) => format!("ok in {:?}", started.elapsed()),
+        Err(e) => format!("failed after {:?}: {:#}", started.elapsed(), e),
+    }
+}
+
+fn log_unavailable(e: anyhow::Error) -> LightError {
+    LightError::LogUnavailable(format!("{:#}", e))
+}
+
+// Answers `reply` from a read running on its own task
+fn detach<T: Send + 'static>(read: DetachedRead<T>, reply: oneshot::Sender<Result<T, LightError>>) {
+    tokio::spawn(async move {
+        let _ = reply.send(read.await.map_err(log_unavailable));
+    });
+}
+
+// The next change pushed by the bulb; only polled while the backend is pushing
+async fn next_report(reports: &mut Option<mpsc::Receiver<DeviceReport>>) -> Option<DeviceReport> {
+    reports.as_mut()?.recv().await
+}
+
+// Names commands the way events and the state report them, e.g. "turn_on"
+fn action_name(action: impl ToString) -> String {
+    action.to_string().replace(' ', "_")
+}
+
+// Cloneable handle used to send commands to the light actor
+#[derive(Clone)]
+pub struct LightHandle {
+    commands: mpsc::Sender<Command>,
+    published: watch::Receiver<(LightState, StateSnapshot)>,
+    reading: watch::Receiver<Option<BackendReading>>,
+    log_modified: watch::Receiver<Option<DateTime<Utc>>>,
+    queued: watch::Receiver<Vec<QueuedChange>>,
+    offline_queue: bool,
+    state_ttl: Duration,
+    events: EventBus,
+    clock: SharedClock,
+    metadata: BulbMetadata,
+    brightness_schedule: Arc<BrightnessSchedule>,
+    tasks: Tasks,
+    caller: Caller,
+}
+
+impl LightHandle {
+    // Spawns the actor on the current tokio runtime
+    pub fn spawn(
+        machine: StateMachine,
+        backend: Box<dyn LightBackend + Send + Sync>,
+        logger: Box<dyn Logger + Send + Sync>,
+    ) -> Self {
+        Self::spawn_with_journal(machine, backend, logger, Vec::new(), None)
+    }
+
+    // As `spawn`, tagging every logged power change that matches one of `tag_rules`
+    pub fn spawn_with_tag_rules(
+        machine: StateMachine,
+        backend: Box<dyn LightBackend + Send + Sync>,
+        logger: Box<dyn Logger + Send + Sync>,
+        tag_rules: Vec<TagRule>,
+    ) -> Self {
+        Self::spawn_with_journal(machine, backend, logger, tag_rules, None)
+    }
+
+    // As `spawn_with_tag_rules`, journaling logged changes in `journal` and first repairing the log from
+    // whatever an unclean shutdown left there
+    pub fn spawn_with_journal(
+        machine: StateMachine,
+        backend: Box<dyn LightBackend + Send + Sync>,
+        logger: Box<dyn Logger + Send + Sync>,
+        tag_rules: Vec<TagRule>,
+        journal: Option<Journal>,
+    ) -> Self {
+        Self::spawn_with_offline_queue(machine, backend, logger, tag_rules, journal, None)
+    }
+
+    // As `spawn_with_journal`, queueing power, color and brightness changes that cannot reach the bulb when
+    // `offline_retry` is set, and asking the bulb that often whether it is back to apply them
+    pub fn spawn_with_offline_queue(
+        machine: StateMachine,
+        backend: Box<dyn LightBackend + Send + Sync>,
+        logger: Box<dyn Logger + Send + Sync>,
+        tag_rules: Vec<TagRule>,
+        journal: Option<Journal>,
+        offline_retry: Option<Duration>,
+    ) -> Self {
+        Self::spawn_with_usage_cache(machine, backend, logger, tag_rules, journal, offline_retry, None)
+    }
+
+    // As `spawn_with_offline_queue`, saving the usage counters in `usage_cache` at shutdown and starting from
+    // them instead of a scan of the log the next time
+    pub fn spawn_with_usage_cache(
+        machine: StateMachine,
+        backend: Box<dyn LightBackend + Send + Sync>,
+        logger: Box<dyn Logger + Send + Sync>,
+        tag_rules: Vec<TagRule>,
+        journal: Option<Journal>,
+        offline_retry: Option<Duration>,
+        usage_cache: Option<UsageCache>,
+    ) -> Self {
+        Self::spawn_with_log_privacy(machine, backend, logger, tag_rules, journal, offline_retry, usage_cache, LogPrivacy::default())
+    }
+
+    // As `spawn_with_usage_cache`, recording who made each change and why only as far as `privacy` allows
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn_with_log_privacy(
+        machine: StateMachine,
+        backend: Box<dyn LightBackend + Send + Sync>,
+        logger: Box<dyn Logger + Send + Sync>,
+        tag_rules: Vec<TagRule>,
+        journal: Option<Journal>,
+        offline_retry: Option<Duration>,
+        usage_cache: Option<UsageCache>,
+        privacy: LogPrivacy,
+    ) -> Self {
+        let (commands, receiver) = mpsc::channel(COMMAND_BUFFER);
+        let events = EventBus::new();
+        let clock = machine.clock().clone();
+        let (published, receiver_of_state) = watch::channel((machine.state().clone(), machine.snapshot()));
+        let (reading, receiver_of_reading) = watch::channel(None);
+        let (log_modified, receiver_of_log_modified) = watch::channel(None);
+        let (queued, receiver_of_queued) = watch::channel(Vec::new());
+        let metadata = BulbMetadata::of(backend.as_ref());
+        let mut backend = backend;
+        let reports = backend.device_reports();
+        let actor = LightActor {
+            machine,
+            backend,
+            logger,
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            last_sequence: 0,
+            reason: None,
+            tags: Vec::new(),
+            tag_rules,
+            privacy,
+            events: events.clone(),
+            usage: None,
+            flush_due: None,
+            journal,
+            usage_cache,
+            published,
+            reading,
+            log_modified,
+            offline_retry,
+            offline: OfflineQueue::default(),
+            retry_due: None,
+            queued,
+            log_sequence: 0,
+            logged: None,
+        };
+        tokio::spawn(actor.run(receiver, reports));
+        Self {
+            commands,
+            published: receiver_of_state,
+            reading: receiver_of_reading,
+            log_modified: receiver_of_log_modified,
+            queued: receiver_of_queued,
+            offline_queue: offline_retry.is_some(),
+            state_ttl: DEFAULT_STATE_TTL,
+            events,
+            clock,
+            metadata,
+            brightness_schedule: Arc::default(),
+            tasks: Tasks::default(),
+            caller: Caller::default(),
+        }
+    }
+
+    // The background tasks working on the bulb, which report to run_diagnostics through every clone of the handle
+    pub fn tasks(&self) -> &Tasks {
+        &self.tasks
+    }
+
+    // When the log last changed, as far as this server knows; None if nothing has written to it yet
+    pub fn log_modified(&self) -> Option<DateTime<Utc>> {
+        *self.log_modified.borrow()
+    }
+
+    // Changes waiting for the bulb to come back, oldest first
+    pub fn queued_changes(&self) -> Vec<QueuedChange> {
+        self.queued.borrow().clone()
+    }
+
+    // Whether changes that cannot reach the bulb are queued rather than failed
+    pub fn queues_offline(&self) -> bool {
+        self.offline_queue
+    }
+
+    // What the backend's bulb can do, read when the actor was spawned
+    pub fn metadata(&self) -> &BulbMetadata {
+        &self.metadata
+    }
+
+    // Gamma corrects every brightness this handle and its clones set
+    pub fn with_brightness_curve(mut self, curve: BrightnessCurve) -> Self {
+        self.metadata.brightness = curve;
+        self
+    }
+
+    // Caps the brightness this handle and its clones send by the time of day
+    pub fn with_brightness_schedule(self, schedule: BrightnessSchedule) -> Self {
+        Self { brightness_schedule: Arc::new(schedule), ..self }
+    }
+
+    // The most brightness a command gets right now, or None when there is no brightness schedule
+    pub fn brightness_cap(&self) -> Option<u8> {
+        self.brightness_schedule.cap(self.clock.local_time())
+    }
+
+    // The level sent to the backend for `percent`, after the cap and gamma
+    fn output(&self, percent: u8) -> u8 {
+        self.brightness_output().level(percent)
+    }
+
+    fn brightness_output(&self) -> Output {
+        Output { curve: self.metadata.brightness, cap: self.brightness_cap() }
+    }
+
+    // A handle that trusts the backend's last power reading for `ttl`; zero asks the backend on every read
+    pub fn with_state_ttl(self, ttl: Duration) -> Self {
+        Self { state_ttl: ttl, ..self }
+    }
+
+    // The clock the actor times changes and log entries with
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    // A handle whose mutations fail with VersionConflict unless the state is still at `version`
+    pub fn expecting(&self, version: Option<u64>) -> Self {
+        let caller = Caller { expected_version: version, ..self.caller.clone() };
+        Self { caller, ..self.clone() }
+    }
+
+    // A handle whose logged changes record why they were made
+    pub fn with_reason(&self, reason: Option<String>) -> Self {
+        let caller = Caller { reason, ..self.caller.clone() };
+        Self { caller, ..self.clone() }
+    }
+
+    // A handle whose logged changes carry these tags, in addition to any from tag rules
+    pub fn tagged(&self, tags: Vec<String>) -> Self {
+        let caller = Caller { tags, ..self.caller.clone() };
+        Self { caller, ..self.clone() }
+    }
+
+    // A handle whose changes are attributed to `who` in the state and the log
+    pub fn acting_as(&self, who: impl Into<String>) -> Self {
+        let caller = Caller { name: Some(who.into()), ..self.caller.clone() };
+        Self { caller, ..self.clone() }
+    }
+
+    // Who the handle's changes are attributed to, when anyone is
+    pub fn caller(&self) -> Option<&str> {
+        self.caller.name.as_deref()
+    }
+
+    // A handle whose changes are attributed to whoever `other`'s are, for a session switching another bulb
+    pub fn acting_like(&self, other: &LightHandle) -> Self {
+        let caller = Caller { name: other.caller.name.clone(), ..self.caller.clone() };
+        Self { caller, ..self.clone() }
+    }
+
+    // Events for every state change from here on
+    pub fn subscribe(&self) -> broadcast::Receiver<LightEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub async fn state(&self) -> Result<LightState, LightError> {
+        Ok(self.status().await?.state)
+    }
+
+    // Reads the state the actor last published, so status reads never wait for commands ahead of them
+    pub async fn status(&self) -> Result<LightStatus, LightError> {
+        let (state, snapshot) = self.published()?;
+        Ok(LightStatus {
+            state,
+            version: snapshot.version,
+            changed_by: snapshot.changed_by,
+            cause: snapshot.cause,
+            last_changed: snapshot.last_changed,
+            in_state_secs: snapshot.in_state_secs,
+        })
+    }
+
+    pub async fn snapshot(&self) -> Result<StateSnapshot, LightError> {
+        Ok(self.published()?.1)
+    }
+
+    fn published(&self) -> Result<(LightState, StateSnapshot), LightError> {
+        self.published.has_changed().map_err(|_| LightError::ActorStopped)?;
+        let (state, snapshot) = self.published.borrow().clone();
+        Ok((state, snapshot.as_of(self.clock.now())))
+    }
+
+    // The power the backend last reported, asking it again when that reading is older than the TTL or `refresh` is set
+    pub async fn backend_power(&self, refresh: bool) -> Result<BackendReading, LightError> {
+        match self.fresh_reading().filter(|_| !refresh) {
+            Some(reading) => Ok(reading),
+            None => self.request(Command::ReadBackend).await,
+        }
+    }
+
+    // When the backend last answered, without asking it; None if it has not answered since the actor started
+    pub fn last_contact(&self) -> Option<DateTime<Utc>> {
+        self.reading.borrow().as_ref().and_then(|reading| reading.contacted_at)
+    }
+
+    fn fresh_reading(&self) -> Option<BackendReading> {
+        self.reading.borrow().clone().filter(|reading| (self.clock.now() - reading.at).to_std().unwrap_or_default() < self.state_ttl)
+    }
+
+    // Whether the log takes entries and the backend answers, reusing a backend reading younger than the TTL;
+    // cheap enough for a container's readiness probe, unlike the full diagnostics
+    pub async fn readiness(&self) -> Result<ReadinessProbe, LightError> {
+        let reading = self.fresh_reading();
+        let probe = self.request(|reply| Command::Readiness(reading, reply)).await?;
+        Ok(ReadinessProbe { tasks: self.tasks.probe(), ..probe })
+    }
+
+    pub async fn set_power(&self, target: PowerState) -> Result<Sequenced<PowerChange>, LightError> {
+        // A bulb lit while the time of day caps it is first sent the capped level, keeping the brightness asked for
+        let (_, snapshot) = self.published()?;
+        if target == PowerState::On
+            && snapshot.power != Some(PowerState::On)
+            && self.metadata.supports_brightness
+            && self.brightness_cap().is_some_and(|cap| cap < snapshot.brightness)
+        {
+            let level = self.output(snapshot.brightness);
+            self.request(|reply| Command::SetBrightness(snapshot.brightness, level, self.caller.clone(), reply)).await??;
+            let next = self.expecting(None);
+            return next.request(|reply| Command::SetPower(target, next.caller.clone(), reply)).await?;
+        }
+        self.request(|reply| Command::SetPower(target, self.caller.clone(), reply)).await?
+    }
+
+    // Changes power for an effect without logging it or recording it for undo
+    pub async fn effect_step(&self, step: &EffectStep) -> Result<Sequenced<PowerChange>, LightError> {
+        let level = step.brightness.map(|percent| self.metadata.brightness.output(percent));
+        self.request(|reply| Command::EffectStep(*step, level, self.caller.clone(), reply)).await?
+    }
+
+    pub async fn apply(&self, transition: Transition) -> Result<Sequenced<LightState>, LightError> {
+        self.request(|reply| Command::Apply(transition, self.caller.clone(), reply)).await?
+    }
+
+    pub async fn set_color(&self, color: Color) -> Result<Sequenced<ColorChange>, LightError> {
+        self.request(|reply| Command::SetColor(color, self.caller.clone(), reply)).await?
+    }
+
+    // Sets how bright the bulb looks, as a percentage, sending the backend its gamma-corrected level
+    pub async fn set_brightness(&self, percent: u8) -> Result<Sequenced<BrightnessChange>, LightError> {
+        let level = self.output(percent);
+        self.request(|reply| Command::SetBrightness(percent, level, self.caller.clone(), reply)).await?
+    }
+
+    pub async fn read_log(&self) -> Result<String, LightError> {
+        self.request(Command::ReadLog).await?
+    }
+
+    // Up to `limit` bytes of the log from `offset` on, ending on a whole line
+    pub async fn read_log_chunk(&self, offset: u64, limit: usize) -> Result<LogChunk, LightError> {
+        self.request(|reply| Command::ReadLogChunk(offset, limit, reply)).await?
+    }
+
+    // The last `entries` entries of the log, oldest first, without reading the rest
+    pub async fn read_log_tail(&self, entries: usize) -> Result<Vec<String>, LightError> {
+        self.request(|reply| Command::ReadLogTail(entries, reply)).await?
+    }
+
+    // The entries logged at or after `from` and before `to`, without reading the rest of the log
+    pub async fn read_log_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<String>, LightError> {
+        self.request(|reply| Command::ReadLogRange(from, to, reply)).await?
+    }
+
+    // The entries logged at or after `from` and before `to` as the logger's structured records, or None where it
+    // keeps none
+    pub async fn read_log_records(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Option<Vec<HistoryEntry>>, LightError> {
+        self.request(|reply| Command::ReadLogRecords(from, to, reply)).await?
+    }
+
+    // Totals over the log, without re-reading it
+    pub async fn usage(&self) -> Result<UsageCounters, LightError> {
+        self.request(Command::Usage).await?
+    }
+
+    // Gathers the raw results behind run_diagnostics
+    pub async fn probe(&self) -> Result<HealthProbe, LightError> {
+        let probe = self.request(Command::Probe).await?;
+        Ok(HealthProbe { tasks: self.tasks.probe(), ..probe })
+    }
+
+    // Writes out buffered log entries now rather than on the next timed flush, e.g. before the process exits
+    pub async fn flush_log(&self) -> Result<(), LightError> {
+        self.request(Command::FlushLog).await?
+    }
+
+    // Answers once the actor has worked through the commands queued ahead, e.g. for a liveness check
+    pub async fn ping(&self) -> Result<(), LightError> {
+        self.request(Command::Ping).await
+    }
+
+    // What `changes`, made in order by this handle's caller, would do; one result per change up to the first
+    // that would fail
+    pub async fn preview(&self, changes: Vec<Change>) -> Result<Vec<Result<Preview, LightError>>, LightError> {
+        self.request(|reply| Command::Preview(changes, self.caller.clone(), reply)).await
+    }
+
+    // Logs that a curfew kept this handle's caller from calling `tool`
+    pub async fn log_denied(&self, tool: &str, curfew: &str) -> Result<(), LightError> {
+        self.request(|reply| Command::LogDenied(tool.to_string(), curfew.to_string(), self.caller.clone(), reply)).await?
+    }
+
+    // Logs a simulated power outage beginning or ending
+    pub async fn log_outage(&self, entry: OutageEntry) -> Result<(), LightError> {
+        self.request(|reply| Command::LogOutage(entry, reply)).await?
+    }
+
+    // Logs that this handle's caller gave the bulb a new name and aliases
+    pub async fn log_named(&self, name: Option<&str>, aliases: &[String]) -> Result<(), LightError> {
+        self.request(|reply| Command::LogNamed(name.map(str::to_string), aliases.to_vec(), self.caller.clone(), reply)).await?
+    }
+
+    // Writes the final SHUTDOWN entry, naming what stopped the server, and flushes the log
+    pub async fn shutdown(&self, cause: &str) -> Result<(), LightError> {
+        self.request(|reply| Command::Shutdown(cause.to_string(), reply)).await?
+    }
+
+    // Collapses the log's entries before `before` into daily totals; None when none were old enough
+    pub async fn compact_log(&self, before: DateTime<Utc>) -> Result<Option<Compaction>, LightError> {
+        self.request(|reply| Command::CompactLog(before, reply)).await?
+    }
+
+    // Anonymizes, with `privacy`'s key, or removes the client and reason of every entry dated before `before`
+    pub async fn purge_log(&self, before: DateTime<Utc>, mode: PurgeMode, privacy: LogPrivacy) -> Result<Option<Purge>, LightError> {
+        self.request(|reply| Command::PurgeLog(before, mode, privacy, reply)).await?
+    }
+
+    // Merges parsed entries into the log in time order, skipping those it already has
+    pub async fn import_log(&self, parsed: ParsedLog) -> Result<Merge, LightError> {
+        self.request(|reply| Command::ImportLog(parsed, reply)).await?
+    }
+
+    // Reverts the most recent power, color or brightness change, returning the restored power state
+    pub async fn undo(&self) -> Result<Sequenced<PowerState>, LightError> {
+        self.request(|reply| Command::Undo(self.caller.clone(), self.brightness_output(), reply)).await?
+    }
+
+    // Re-applies the most recently undone change
+    pub async fn redo(&self) -> Result<Sequenced<PowerState>, LightError> {
+        self.request(|reply| Command::Redo(self.caller.clone(), self.brightness_output(), reply)).await?
+    }
+
+    // Turns the bulb off for the safety watchdog, logged as OFF (WATCHDOG_OFF); it can be undone like any other
+    // power change
+    pub async fn watchdog_off(&self) -> Result<Sequenced<PowerChange>, LightError> {
+        self.request(|reply| Command::WatchdogOff(self.caller.clone(), reply)).await?
+    }
+
+    // Announces that the bulb has been on for longer than the safety watchdog allows, returning the event's
+    // sequence
+    pub async fn warn_on_too_long(&self) -> Result<u64, LightError> {
+        self.request(Command::WarnOnTooLong).await
+    }
+
+    // Turns the bulb off and forgets its state and history, archiving the log first when `archive` is set
+    pub async fn factory_reset(&self, archive: bool) -> Result<Sequenced<FactoryReset>, LightError> {
+        let level = self.metadata.brightness.output(DEFAULT_BRIGHTNESS);
+        self.request(|reply| Command::FactoryReset(archive, level, self.caller.clone(), reply)).await?
+    }
+
+    async fn request<T>(&self, command: impl FnOnce(oneshot::Sender<T>) -> Command) -> Result<T, LightError> {
+        let (reply, response) = oneshot::channel();
+        self.commands.send(command(reply)).await.map_err(|_| LightError::ActorStopped)?;
+        // The actor drops the reply of a command that panicked and carries on
+        response.await.map_err(|_| {
+            if self.commands.is_closed() {
+                LightError::ActorStopped
+            } else {
+                LightError::Internal("the light actor panicked handling this request".to_string())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::clock::ManualClock;
+    use crate::logger::InMemoryLogger;
+
+    #[tokio::test]
+    async fn test_concurrent_commands_are_serialized() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+
+        let mut tasks = Vec::new();
+        for _ in 0..10 {
+            let handle = handle.clone();
+            tasks.push(tokio::spawn(async move { handle.set_power(PowerState::On).await }));
+        }
+        let mut changed = 0;
+        let mut sequences = Vec::new();
+        for task in tasks {
+            let applied = task.await.unwrap().unwrap();
+            if applied.outcome == PowerChange::Changed {
+                changed += 1;
+                assert_eq!(applied.sequence, 1);
+            }
+            sequences.push(applied.sequence);
+        }
+        sequences.sort();
+
+        assert_eq!(changed, 1);
+        assert_eq!(sequences, (1..=10).collect::<Vec<_>>());
+        assert_eq!(handle.read_log().await.unwrap().lines().count(), 1);
+        assert_eq!(handle.state().await.unwrap().power(), Some(PowerState::On));
+    }
+
+    #[tokio::test]
+    async fn test_undo_and_redo_power_changes() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        assert!(matches!(handle.undo().await, Err(LightError::NothingToUndo)));
+
+        handle.set_power(PowerState::On).await.unwrap();
+        assert_eq!(handle.undo().await.unwrap().outcome, PowerState::Off);
+        assert_eq!(handle.state().await.unwrap().power(), Some(PowerState::Off));
+        assert!(matches!(handle.undo().await, Err(LightError::NothingToUndo)));
+
+        assert_eq!(handle.redo().await.unwrap().outcome, PowerState::On);
+        assert!(matches!(handle.redo().await, Err(LightError::NothingToRedo)));
+
+        let log = handle.read_log().await.unwrap();
+        assert!(log.contains("turned OFF (UNDO)"));
+        assert!(log.contains("turned ON (REDO)"));
+    }
+
+    #[tokio::test]
+    async fn test_undo_and_redo_color_and_brightness_changes() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.set_brightness(40).await.unwrap();
+        let amber = Color { r: 255, g: 136, b: 0 };
+        handle.set_color(amber).await.unwrap();
+
+        let preview = handle.preview(vec![Change::Undo]).await.unwrap().remove(0).unwrap();
+        assert_eq!(preview.log_entry.as_deref(), Some("COLOR #ffffff (UNDO)"));
+        assert_eq!(handle.undo().await.unwrap().outcome, PowerState::On);
+        let snapshot = handle.snapshot().await.unwrap();
+        assert_eq!((snapshot.color, snapshot.brightness), (Color::WHITE, 40));
+        handle.undo().await.unwrap();
+        assert_eq!(handle.snapshot().await.unwrap().brightness, DEFAULT_BRIGHTNESS);
+        assert_eq!(handle.undo().await.unwrap().outcome, PowerState::Off);
+        assert!(matches!(handle.undo().await, Err(LightError::NothingToUndo)));
+
+        handle.redo().await.unwrap();
+        handle.redo().await.unwrap();
+        handle.redo().await.unwrap();
+        let snapshot = handle.snapshot().await.unwrap();
+        assert_eq!((snapshot.power, snapshot.color, snapshot.brightness), (Some(PowerState::On), amber, 40));
+        let log = handle.read_log().await.unwrap();
+        assert!(log.contains("Lightbulb COLOR #ffffff (UNDO)") && log.contains("Lightbulb BRIGHTNESS 40% (REDO)"), "{}", log);
+
+        // Locked, the bulb is not put back even where its power would stay as it is
+        handle.set_color(Color::WHITE).await.unwrap();
+        handle.apply(Transition::Lock).await.unwrap();
+        assert!(matches!(handle.preview(vec![Change::Undo]).await.unwrap().remove(0), Err(LightError::InvalidTransition(_))));
+        assert!(matches!(handle.undo().await, Err(LightError::InvalidTransition(_))));
+        assert_eq!(handle.snapshot().await.unwrap().color, Color::WHITE);
+    }
+
+    #[tokio::test]
+    async fn test_factory_reset_forgets_the_state_and_history() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.set_color(Color { r: 255, g: 0, b: 0 }).await.unwrap();
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.set_power(PowerState::Off).await.unwrap();
+        handle.set_power(PowerState::On).await.unwrap();
+        let version = handle.status().await.unwrap().version;
+
+        let reset = handle.factory_reset(false).await.unwrap().outcome;
+        assert_eq!(reset, FactoryReset { entries: 4, archived_to: None });
+        let status = handle.status().await.unwrap();
+        assert_eq!((status.state, status.changed_by, status.last_changed), (LightState::Off, None, None));
+        assert!(status.version > version);
+        assert_eq!(handle.snapshot().await.unwrap().color, Color::WHITE);
+        assert_eq!(handle.read_log().await.unwrap(), "");
+        assert!(matches!(handle.undo().await, Err(LightError::NothingToUndo)));
+        assert_eq!(handle.usage().await.unwrap().total, 0);
+
+        // The in-memory log has nowhere to archive to, but the bulb is reset all the same
+        handle.set_power(PowerState::On).await.unwrap();
+        assert!(matches!(handle.factory_reset(true).await, Err(LightError::LogWriteFailed(_))));
+        assert_eq!(handle.state().await.unwrap(), LightState::Off);
+
+        handle.apply(Transition::Lock).await.unwrap();
+        assert_eq!(handle.factory_reset(false).await.unwrap_err().code(), "BULB_LOCKED");
+    }
+
+    #[tokio::test]
+    async fn test_color_and_brightness_changes_are_logged() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let agent = handle.acting_as("agent").with_reason(Some("reading".to_string()));
+        agent.set_color(Color { r: 255, g: 136, b: 0 }).await.unwrap();
+        agent.tagged(vec!["evening".to_string()]).set_brightness(40).await.unwrap();
+        // Setting what the bulb already shows changes nothing to log
+        agent.set_brightness(40).await.unwrap();
+        let log = handle.read_log().await.unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", log);
+        assert!(lines[0].ends_with("Lightbulb COLOR #ff8800 by agent (reason: reading) seq=1"), "{}", log);
+        assert!(lines[1].ends_with("Lightbulb BRIGHTNESS 40% [evening] by agent (reason: reading) seq=2"), "{}", log);
+        let entry = crate::stats::HistoryEntry::parse(lines[1]).unwrap();
+        assert_eq!((entry.action, entry.by.as_deref(), entry.reason.as_deref()), (crate::stats::LogAction::Brightness, Some("agent"), Some("reading")));
+        // Both can be undone, the latest first
+        assert_eq!(handle.undo().await.unwrap().outcome, PowerState::Off);
+        assert_eq!(handle.snapshot().await.unwrap().brightness, DEFAULT_BRIGHTNESS);
+    }
+
+    #[tokio::test]
+    async fn test_log_modified_follows_log_writes() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.status().await.unwrap();
+        assert_eq!(handle.log_modified(), None);
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.set_color(Color { r: 255, g: 0, b: 0 }).await.unwrap();
+        let written = handle.log_modified().unwrap();
+        // Changes that write no entry leave it alone
+        handle.set_color(Color { r: 255, g: 0, b: 0 }).await.unwrap();
+        assert_eq!(handle.log_modified(), Some(written));
+        handle.set_power(PowerState::Off).await.unwrap();
+        assert!(handle.log_modified().unwrap() >= written);
+    }
+
+    #[tokio::test]
+    async fn test_state_changes_are_published() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let mut events = handle.subscribe();
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.apply(Transition::Lock).await.unwrap();
+
+        let event = events.recv().await.unwrap();
+        assert_eq!((event.sequence, event.kind, event.action.as_str()), (1, EventKind::StateChanged, "turn_on"));
+        assert_eq!((event.from, event.to), ("OFF", "ON"));
+        let event = events.recv().await.unwrap();
+        assert_eq!((event.sequence, event.action.as_str(), event.to), (3, "lock", "LOCKED"));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_new_change_clears_redo() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.undo().await.unwrap();
+        handle.set_power(PowerState::On).await.unwrap();
+        assert!(matches!(handle.redo().await, Err(LightError::NothingToRedo)));
+    }
+
+    #[tokio::test]
+    async fn test_stale_expected_version_is_rejected() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let version = handle.status().await.unwrap().version;
+        handle.set_power(PowerState::On).await.unwrap();
+
+        let stale = handle.expecting(Some(version));
+        assert!(matches!(stale.set_power(PowerState::Off).await, Err(LightError::VersionConflict { expected: 0, actual: 2 })));
+        assert_eq!(handle.state().await.unwrap().power(), Some(PowerState::On));
+
+        let version = handle.status().await.unwrap().version;
+        assert!(handle.expecting(Some(version)).apply(Transition::Lock).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_toggles_closer_than_the_minimum_interval_are_refused() {
+        let clock = ManualClock::new(DateTime::UNIX_EPOCH);
+        let mut machine = StateMachine::with_clock(Arc::new(clock.clone()));
+        machine.set_min_toggle_interval(Duration::from_secs(2));
+        let handle = LightHandle::spawn(machine, Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.set_power(PowerState::On).await.unwrap();
+        assert_eq!(handle.set_power(PowerState::On).await.unwrap().outcome, PowerChange::AlreadyInState);
+
+        clock.advance(TimeDelta::milliseconds(800));
+        let error = handle.set_power(PowerState::Off).await.unwrap_err();
+        assert!(matches!(error, LightError::ToggleTooSoon { retry_after_ms: 1200, min_interval_ms: 2000 }), "{:?}", error);
+        assert!(matches!(handle.undo().await, Err(LightError::ToggleTooSoon { .. })));
+        assert_eq!(handle.state().await.unwrap().power(), Some(PowerState::On));
+
+        clock.advance(TimeDelta::milliseconds(1200));
+        handle.set_power(PowerState::Off).await.unwrap();
+        assert_eq!(handle.read_log().await.unwrap().lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_attributed_to_the_caller() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.acting_as("kitchen-agent").set_power(PowerState::On).await.unwrap();
+        let status = handle.status().await.unwrap();
+        assert_eq!(status.changed_by.as_deref(), Some("kitchen-agent"));
+        assert_eq!(status.cause.as_deref(), Some("turn_on"));
+        assert!(status.last_changed.is_some());
+        assert!(handle.read_log().await.unwrap().contains("turned ON by kitchen-agent"));
+
+        handle.apply(Transition::Lock).await.unwrap();
+        assert_eq!(handle.snapshot().await.unwrap().changed_by, None);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_is_the_last_entry() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.shutdown("SIGTERM").await.unwrap();
+        let tail = handle.read_log_tail(1).await.unwrap();
+        assert!(tail[0].ends_with("] Lightbulb server SHUTDOWN (SIGTERM) seq=2"), "{:?}", tail);
+        assert_eq!(handle.usage().await.unwrap().on, 1);
+    }
+
+    #[tokio::test]
+    async fn test_reason_is_recorded_with_the_log_entry() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let agent = handle.acting_as("kitchen-agent");
+        agent.with_reason(Some("movie night".to_string())).set_power(PowerState::On).await.unwrap();
+        agent.set_power(PowerState::Off).await.unwrap();
+
+        let log = handle.read_log().await.unwrap();
+        assert!(log.contains("turned ON by kitchen-agent (reason: movie night)"));
+        assert!(log.trim_end().ends_with("turned OFF by kitchen-agent seq=2"));
+    }
+
+    #[tokio::test]
+    async fn test_privacy_keeps_who_and_why_out_of_the_log() {
+        let rule = TagRule::new(crate::config::TagRuleConfig { tag: "kitchen".to_string(), after: None, before: None, to: None, by: Some("kitchen-agent".to_string()) }).unwrap();
+        let config = crate::config::LogPrivacyConfig { clients: crate::config::Redaction::Omit, reasons: crate::config::Redaction::Hash, salt: Some("pepper".to_string()) };
+        let handle = LightHandle::spawn_with_log_privacy(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()), vec![rule], None, None, None, LogPrivacy::new(&config));
+        handle.acting_as("kitchen-agent").with_reason(Some("movie night".to_string())).set_power(PowerState::On).await.unwrap();
+
+        let log = handle.read_log().await.unwrap();
+        assert!(log.contains("] Lightbulb turned ON [kitchen] (reason: anon-") && log.trim_end().ends_with(" seq=1"), "{}", log);
+        assert!(!log.contains("kitchen-agent") && !log.contains("movie night"), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn test_log_entries_carry_explicit_and_rule_tags() {
+        let rule = TagRule::new(crate::config::TagRuleConfig {
+            tag: "kitchen".to_string(),
+            after: None,
+            before: None,
+            to: None,
+            by: Some("kitchen-agent".to_string()),
+        })
+        .unwrap();
+        let logger = Box::new(InMemoryLogger::new());
+        let handle = LightHandle::spawn_with_tag_rules(StateMachine::new(), Box::new(SimulatedBackend::new()), logger, vec![rule]);
+        let agent = handle.acting_as("kitchen-agent");
+        agent.tagged(vec!["movie".to_string()]).set_power(PowerState::On).await.unwrap();
+        handle.undo().await.unwrap();
+
+        let log = handle.read_log().await.unwrap();
+        assert!(log.contains("turned ON [movie,kitchen] by kitchen-agent"));
+        assert!(log.trim_end().ends_with("turned OFF (UNDO) seq=2"));
+    }
+
+    #[tokio::test]
+    async fn test_compacting_the_log_keeps_its_totals() {
+        let mut logger = InMemoryLogger::new();
+        logger.append_line("[2025-08-01T10:00:00+00:00] Lightbulb turned ON").await.unwrap();
+        logger.append_line("[2025-08-01T11:00:00+00:00] Lightbulb turned OFF").await.unwrap();
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(logger));
+        handle.set_power(PowerState::On).await.unwrap();
+        let before = handle.usage().await.unwrap();
+
+        let cutoff = DateTime::parse_from_rfc3339("2025-08-02T00:00:00Z").unwrap().with_timezone(&Utc);
+        let compaction = handle.compact_log(cutoff).await.unwrap().unwrap();
+        assert_eq!((compaction.days, compaction.entries), (1, 2));
+        let log = handle.read_log().await.unwrap();
+        assert!(log.starts_with("[2025-08-01T10:00:00+00:00] Lightbulb daily total 2025-08-01: entries=2 on=1 off=1 on_secs=3600"), "{}", log);
+        let after = handle.usage().await.unwrap();
+        assert_eq!((after.total, after.on, after.off, after.days.len()), (before.total, before.on, before.off, 1));
+        assert!(handle.compact_log(cutoff).await.unwrap().is_none());
+    }
+
+    // Fails the first read, then serves `inner`, counting every read
+    struct FlakyReadLogger {
+        inner: InMemoryLogger,
+        reads: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Logger for FlakyReadLogger {
+        async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+            self.inner.append_line(line).await
+        }
+
+        async fn read_log(&self) -> anyhow::Result<String> {
+            if self.reads.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                anyhow::bail!("disk not mounted yet");
+            }
+            self.inner.read_log().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_counted_from_the_log_once() {
+        let mut inner = InMemoryLogger::new();
+        inner.append_line("[2025-08-02T10:00:00+00:00] Lightbulb turned ON").await.unwrap();
+        let reads = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let logger = Box::new(FlakyReadLogger { inner, reads: reads.clone() });
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), logger);
+        handle.set_power(PowerState::On).await.unwrap();
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        // The first scan failed, so the next request scans again, finding the entry written before either
+        assert!(matches!(handle.usage().await, Err(LightError::LogUnavailable(_))));
+        let usage = handle.usage().await.unwrap();
+        assert_eq!((usage.total, usage.on, usage.events.len()), (2, 2, 2));
+        handle.set_power(PowerState::Off).await.unwrap();
+        let usage = handle.usage().await.unwrap();
+        assert_eq!((usage.total, usage.off), (3, 1));
+        assert!(usage.recent.back().unwrap().ends_with("Lightbulb turned OFF seq=2"));
+        assert_eq!(reads.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    // Counts flushes of `inner`
+    struct FlushCountingLogger {
+        inner: InMemoryLogger,
+        flushes: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl Logger for FlushCountingLogger {
+        async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+            self.inner.append_line(line).await
+        }
+
+        async fn read_log(&self) -> anyhow::Result<String> {
+            self.inner.read_log().await
+        }
+
+        async fn flush(&mut self) -> anyhow::Result<()> {
+            self.flushes.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_log_is_flushed_after_writes_and_before_reads() {
+        let flushes = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let logger = Box::new(FlushCountingLogger { inner: InMemoryLogger::new(), flushes: flushes.clone() });
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), logger);
+        let flushed = || flushes.load(std::sync::atomic::Ordering::SeqCst);
+
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.set_power(PowerState::Off).await.unwrap();
+        assert_eq!(flushed(), 0);
+        tokio::time::sleep(LOG_FLUSH_INTERVAL).await;
+        // Any command queued behind the timer will do to wait for it
+        handle.usage().await.unwrap();
+        assert_eq!(flushed(), 1);
+
+        // Nothing new to flush for the first read; the write after it is flushed by the next one
+        handle.read_log().await.unwrap();
+        handle.set_power(PowerState::On).await.unwrap();
+        handle.read_log().await.unwrap();
+        assert_eq!(flushed(), 2);
+        handle.flush_log().await.unwrap();
+        assert_eq!(flushed(), 2);
+    }
+
+    // Reads through a detached read that waits for `disk` before answering
+    struct SlowDiskLogger {
+        inner: InMemoryLogger,
+        disk: std::sync::Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl Logger for SlowDiskLogger {
+        async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+            self.inner.append_line(line).await
+        }
+
+        async fn read_log(&self) -> anyhow::Result<String> {
+            anyhow::bail!("reads should be detached")
+        }
+
+        fn detached_read(&self) -> Option<crate::logger::DetachedRead> {
+            let (inner, disk) = (self.inner.clone(), self.disk.clone());
+            Some(Box::pin(async move {
+                disk.notified().await;
+                inner.read_log().await
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_log_read_does_not_hold_up_other_commands() {
+        let disk = std::sync::Arc::new(tokio::sync::Notify::new());
+        let logger = Box::new(SlowDiskLogger { inner: InMemoryLogger::new(), disk: disk.clone() });
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), logger);
+        handle.set_power(PowerState::On).await.unwrap();
+
+        let reader = handle.clone();
+        let read = tokio::spawn(async move { reader.read_log().await });
+        tokio::task::yield_now().await;
+        handle.set_power(PowerState::Off).await.unwrap();
+        assert_eq!(handle.state().await.unwrap(), LightState::Off);
+        assert!(!read.is_finished());
+        disk.notify_one();
+        assert!(read.await.unwrap().unwrap().contains("turned ON"));
+    }
+
+    // Holds every power change until `gate` opens
+    struct GatedBackend {
+        gate: std::sync::Arc<tokio::sync::Notify>,
+    }
+
+    #[async_trait::async_trait]
+    impl LightBackend for GatedBackend {
+        fn name(&self) -> &str {
+            "gated"
+        }
+
+        async fn set_power(&mut self, _state: PowerState) -> anyhow::Result<()> {
+            self.gate.notified().await;
+            Ok(())
+        }
+
+        async fn power(&self) -> anyhow::Result<PowerState> {
+            Ok(PowerState::Off)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_do_not_wait_for_a_slow_change() {
+        let gate = std::sync::Arc::new(tokio::sync::Notify::new());
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(GatedBackend { gate: gate.clone() }), Box::new(InMemoryLogger::new()));
+        let writer = handle.clone();
+        let change = tokio::spawn(async move { writer.set_power(PowerState::On).await });
+        while handle.snapshot().await.unwrap().state != "TRANSITIONING" {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(handle.status().await.unwrap().version, 1);
+
+        gate.notify_one();
+        change.await.unwrap().unwrap();
+        // A change is published before its reply, so the caller reads its own write
+        let status = handle.status().await.unwrap();
+        assert_eq!((status.state.power(), status.version), (Some(PowerState::On), 2));
+    }
+
+    #[tokio::test]
+    async fn test_startup_repairs_the_log_from_the_journal() {
+        let path = std::env::temp_dir().join(format!("lightbulb-actor-{}.journal", std::process::id())).display().to_string();
+        // The OFF was made but never flushed; the last ON never reached the bulb, which is still off
+        std::fs::write(
+            &path,
+            "begin 2025-08-01T09:00:00+00:00 ON ON by kitchen-agent\n\
+             begin 2025-08-01T09:05:00+00:00 OFF OFF\n\
+             begin 2025-08-01T09:10:00+00:00 ON ON\n",
+        )
+        .unwrap();
+        let mut logger = InMemoryLogger::new();
+        logger.append_line("[2025-08-01T09:00:01+00:00] Lightbulb turned ON by kitchen-agent").await.unwrap();
+        let journal = Some(Journal::new(path.clone()));
+        let handle =
+            LightHandle::spawn_with_journal(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(logger), Vec::new(), journal);
+        let log = handle.read_log().await.unwrap();
+        assert_eq!(log.matches("Lightbulb turned").count(), 2);
+        assert!(log.contains("[2025-08-01T09:05:00+00:00] Lightbulb turned OFF (recovered from the journal)"));
+        assert!(!std::path::Path::new(&path).exists());
+
+        handle.set_power(PowerState::On).await.unwrap();
+        assert!(std::path::Path::new(&path).exists());
+        handle.read_log().await.unwrap();
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[tokio::test]
+    async fn test_usage_is_taken_up_from_the_last_shutdown() {
+        let dir = std::env::temp_dir().join(format!("lightbulb-actor-usage-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("lightbulb.log").display().to_string();
+        let spawn = || {
+            let cache = Some(UsageCache::new(crate::usage_cache::usage_cache_path(&log)));
+            let logger = Box::new(crate::logger::FileLogger::new(log.clone()));
+            LightHandle::spawn_with_usage_cache(StateMachine::new(), Box::new(SimulatedBackend::new()), logger, Vec::new(), None, None, cache)
+        };
+        let handle = spawn();
+        for power in [PowerState::On, PowerState::Off, PowerState::On, PowerState::Off, PowerState::On, PowerState::Off] {
+            handle.set_power(power).await.unwrap();
+        }
+        assert_eq!(handle.usage().await.unwrap().on, 3);
+        handle.shutdown("SIGTERM").await.unwrap();
+        drop(handle);
+
+        // An entry the server crashed before counting, and an early one changed where only a scan would see it
+        let edited = std::fs::read_to_string(&log).unwrap().replacen("turned ON", "turned on", 1);
+        std::fs::write(&log, format!("{}[2030-01-01T00:00:00+00:00] Lightbulb turned ON seq=8\n", edited)).unwrap();
+        let usage = spawn().usage().await.unwrap();
+        assert_eq!((usage.total, usage.on, usage.off), (8, 4, 3));
+
+        // A rotated log no longer holds what the counters saw last, so it is counted afresh
+        std::fs::write(&log, "[2030-01-02T00:00:00+00:00] Lightbulb turned ON seq=1\n").unwrap();
+        assert_eq!(spawn().usage().await.unwrap().total, 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    // Panics on every power change, like a driver with a bug in it
+    struct PanickingBackend;
+
+    #[async_trait::async_trait]
+    impl LightBackend for PanickingBackend {
+        fn name(&self) -> &str {
+            "panicking"
+        }
+
+        async fn set_power(&mut self, _state: PowerState) -> anyhow::Result<()> {
+            panic!("backend bug")
+        }
+
+        async fn power(&self) -> anyhow::Result<PowerState> {
+            Ok(PowerState::Off)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_a_panicking_command_does_not_stop_the_actor() {
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(PanickingBackend), Box::new(InMemoryLogger::new()));
+        let error = handle.set_power(PowerState::On).await.unwrap_err();
+        assert_eq!(error.code(), "INTERNAL_ERROR");
+        handle.ping().await.unwrap();
+        // The change it cut short may have reached the bulb, or not
+        assert_eq!(handle.snapshot().await.unwrap().state, "UNREACHABLE");
+        assert_eq!(handle.backend_power(true).await.unwrap().power, Ok(PowerState::Off));
+    }
+
+    #[tokio::test]
+    async fn test_a_failed_reading_keeps_the_last_contact() {
+        let backend = SimulatedBackend::new();
+        let faults = backend.fault_injector().unwrap();
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(backend), Box::new(InMemoryLogger::new()));
+        assert_eq!(handle.last_contact(), None);
+        let answered = handle.backend_power(true).await.unwrap();
+        assert_eq!(answered.contacted_at, Some(answered.at));
+
+        faults.set_unreachable_for(Duration::from_secs(60));
+        let failed = handle.backend_power(true).await.unwrap();
+        assert!(failed.power.is_err());
+        assert_eq!((failed.contacted_at, handle.last_contact()), (Some(answered.at), Some(answered.at)));
+    }
+
+    #[tokio::test]
+    async fn test_changes_for_an_unreachable_bulb_are_queued_until_it_returns() {
+        let backend = SimulatedBackend::new();
+        let faults = backend.fault_injector().unwrap();
+        let retry = Some(Duration::from_millis(10));
+        let handle = LightHandle::spawn_with_offline_queue(StateMachine::new(), Box::new(backend), Box::new(InMemoryLogger::new()), Vec::new(), None, retry);
+        faults.set_unreachable_for(Duration::from_secs(60));
+
+        assert_eq!(handle.set_power(PowerState::On).await.unwrap().outcome, PowerChange::Queued);
+        assert!(handle.set_brightness(40).await.unwrap().outcome.queued);
+        // The later power change replaces the earlier one
+        let off = handle.set_power(PowerState::Off).await.unwrap();
+        assert_eq!(off.outcome, PowerChange::Queued);
+        let queued = handle.queued_changes();
+        assert_eq!(queued.iter().map(|queued| queued.change.property()).collect::<Vec<_>>(), ["brightness", "power"]);
+        assert_eq!(queued[1].sequence, off.sequence);
+        assert_eq!(handle.state().await.unwrap(), LightState::Unreachable);
+
+        faults.set_unreachable_for(Duration::ZERO);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !handle.queued_changes().is_empty() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let snapshot = handle.snapshot().await.unwrap();
+        assert_eq!((snapshot.power, snapshot.brightness), (Some(PowerState::Off), 40));
+        assert_eq!(handle.read_log().await.unwrap().lines().filter(|line| line.contains("OFF")).count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_state_follows_changes_pushed_by_the_bulb() {
+        let backend = SimulatedBackend::new();
+        let faults = backend.fault_injector().unwrap();
+        let handle = LightHandle::spawn(StateMachine::new(), Box::new(backend), Box::new(InMemoryLogger::new()));
+        let mut events = handle.subscribe();
+
+        assert!(faults.change_at_device(DeviceReport::Power(PowerState::On)));
+        let event = events.recv().await.unwrap();
+        assert_eq!((event.kind, event.action.as_str(), event.state.changed_by.as_deref()), (EventKind::StateChanged, "turn_on", Some(DEVICE_CALLER)));
+        assert!(handle.read_log().await.unwrap().ends_with("ON by device seq=1\n"));
+        // A report of the state the server already has, such as the echo of its own change, changes nothing
+        faults.change_at_device(DeviceReport::Power(PowerState::On));
+        faults.change_at_device(DeviceReport::Brightness(30));
+        let event = events.recv().await.unwrap();
+        assert_eq!((event.action.as_str(), event.state.brightness), ("change_brightness", 30));
+        handle.set_power(PowerState::Off).await.unwrap();
+        assert_eq!(handle.undo().await.unwrap().outcome, PowerState::On);
+
+        // A lock only holds the server back, so the state keeps to the last it could follow
+        handle.apply(Transition::Lock).await.unwrap();
+        faults.change_at_device(DeviceReport::Power(PowerState::Off));
+        assert_eq!(handle.backend_power(true).await.unwrap().power, Ok(PowerState::Off));
+        assert_eq!(handle.state().await.unwrap().power(), Some(PowerState::On));
+        assert_eq!(handle.read_log().await.unwrap().lines().count(), 3);
+    }
+}
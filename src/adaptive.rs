@@ -0,0 +1,256 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+
+use crate::actor::LightHandle;
+use crate::config::{AdaptiveBrightnessConfig, MAX_LUX};
+use crate::error::LightError;
+use crate::state::LightState;
+
+// Who adjustments are made as, in the state's changed_by
+const ADAPTIVE_CALLER: &str = "adaptive brightness";
+
+// What adaptive brightness did with one ambient light reading
+#[derive(Debug, Clone, PartialEq)]
+pub enum Adjustment {
+    // Adaptive brightness is off, so the reading was only recorded
+    Disabled,
+    // The bulb is not lit, so there is no brightness to adjust; holds the state's label
+    NotLit(&'static str),
+    // Close enough to the target
+    Holding(u8),
+    // Correction wanted, but the bulb is already as bright or as dim as it goes
+    AtLimit(u8),
+    Changed { from: u8, to: u8 },
+}
+
+// Steps brightness toward the target, a step per reading. Correction starts once a reading strays more than
+// `hysteresis_lux` from the target and carries on until one is within half that, so readings hovering on the
+// edge of the band do not flap the bulb up and down.
+#[derive(Debug, Clone)]
+pub struct Controller {
+    settings: AdaptiveBrightnessConfig,
+    correcting: bool,
+}
+
+impl Controller {
+    pub fn new(settings: AdaptiveBrightnessConfig) -> Self {
+        Self { settings, correcting: false }
+    }
+
+    pub fn settings(&self) -> &AdaptiveBrightnessConfig {
+        &self.settings
+    }
+
+    // The brightness to move to from `brightness` after reading `lux`, or None to leave it
+    pub fn step(&mut self, lux: f64, brightness: u8) -> Option<u8> {
+        let error = self.settings.target_lux - lux;
+        let band = if self.correcting { self.settings.hysteresis_lux / 2.0 } else { self.settings.hysteresis_lux };
+        if error.abs() <= band {
+            self.correcting = false;
+            return None;
+        }
+        self.correcting = true;
+        // Proportional to how far off the room is, so a dark room brightens faster than a dim one
+        let max_step = f64::from(self.settings.max_step_percent);
+        let step = (error / self.settings.target_lux * 100.0).clamp(-max_step, max_step).round() as i16;
+        let step = if step == 0 { error.signum() as i16 } else { step };
+        Some((i16::from(brightness) + step).clamp(1, 100) as u8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+    pub lux: f64,
+    pub at: DateTime<Utc>,
+}
+
+struct AdaptiveState {
+    controller: Controller,
+    enabled: bool,
+    last: Option<Reading>,
+}
+
+// Adaptive brightness shared by every session, and the MQTT subscription when there is one
+#[derive(Clone)]
+pub struct AdaptiveBrightness {
+    state: Arc<Mutex<AdaptiveState>>,
+    // As configured at startup, which a reset returns to
+    configured: Option<AdaptiveBrightnessConfig>,
+}
+
+impl AdaptiveBrightness {
+    // On from the start when configured, and otherwise off until a client enables it
+    pub fn new(config: Option<AdaptiveBrightnessConfig>) -> Self {
+        let enabled = config.is_some();
+        let controller = Controller::new(config.clone().unwrap_or_default());
+        Self { state: Arc::new(Mutex::new(AdaptiveState { controller, enabled, last: None })), configured: config }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AdaptiveState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Starts correcting afresh with `settings`, or stops adjusting when None
+    pub fn configure(&self, settings: Option<AdaptiveBrightnessConfig>) {
+        let mut state = self.lock();
+        state.enabled = settings.is_some();
+        if let Some(settings) = settings {
+            state.controller = Controller::new(settings);
+        }
+    }
+
+    // Back to the startup settings, forgetting the last reading
+    pub fn reset(&self) {
+        let mut state = self.lock();
+        state.enabled = self.configured.is_some();
+        state.controller = Controller::new(self.configured.clone().unwrap_or_default());
+        state.last = None;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.lock().enabled
+    }
+
+    pub fn settings(&self) -> AdaptiveBrightnessConfig {
+        self.lock().controller.settings().clone()
+    }
+
+    pub fn last_reading(&self) -> Option<Reading> {
+        self.lock().last
+    }
+
+    // Records `lux` and moves the bulb's brightness toward the target. With `dry_run` nothing is changed or
+    // recorded, and the adjustment is only worked out.
+    pub async fn report(&self, light: &LightHandle, lux: f64, dry_run: bool) -> Result<Adjustment, LightError> {
+        if !(0.0..=MAX_LUX).contains(&lux) {
+            return Err(LightError::InvalidParameter(format!("lux must be between 0 and {}, got {}", MAX_LUX, lux)));
+        }
+        let current = light.state().await?;
+        let brightness = match current {
+            LightState::On { brightness, .. } => Some(brightness),
+            _ => None,
+        };
+        let target = {
+            let mut state = self.lock();
+            if !dry_run {
+                state.last = Some(Reading { lux, at: light.clock().now() });
+            }
+            if !state.enabled {
+                return Ok(Adjustment::Disabled);
+            }
+            let Some(brightness) = brightness else {
+                return Ok(Adjustment::NotLit(current.label()));
+            };
+            let target = match dry_run {
+                true => state.controller.clone().step(lux, brightness),
+                false => state.controller.step(lux, brightness),
+            };
+            match target {
+                None => return Ok(Adjustment::Holding(brightness)),
+                Some(target) if target == brightness => return Ok(Adjustment::AtLimit(brightness)),
+                Some(target) => (brightness, target),
+            }
+        };
+        let (from, to) = target;
+        if !dry_run {
+            light.acting_as(ADAPTIVE_CALLER).set_brightness(to).await?;
+        }
+        Ok(Adjustment::Changed { from, to })
+    }
+}
+
+// Reads a sensor's message: a bare number, or JSON with the reading as `illuminance_lux` or `illuminance`
+// (as Zigbee2MQTT publishes it) or `lux`
+pub fn parse_lux(payload: &[u8]) -> Option<f64> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    if let Ok(lux) = text.parse::<f64>() {
+        return Some(lux);
+    }
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    ["illuminance_lux", "illuminance", "lux"].iter().find_map(|field| value.get(*field)?.as_f64())
+}
+
+// Feeds every reading published on `topic` into adaptive brightness
+#[cfg(feature = "mqtt")]
+pub fn spawn_lux_subscriber(
+    adaptive: AdaptiveBrightness,
+    publisher: Arc<dyn crate::mqtt::StatePublisher + Send + Sync>,
+    topic: String,
+    light: LightHandle,
+) {
+    tokio::spawn(async move {
+        let mut readings = match publisher.subscribe(&topic).await {
+            Ok(readings) => readings,
+            Err(e) => return crate::diagnostic!(Warn, "could not subscribe to ambient light topic {}: {}", topic, e),
+        };
+        while let Some(payload) = readings.recv().await {
+            let Some(lux) = parse_lux(&payload) else {
+                crate::diagnostic!(Debug, "ignored an ambient light message on {} with no lux reading", topic);
+                continue;
+            };
+            match adaptive.report(&light, lux, false).await {
+                Err(LightError::ActorStopped) => return,
+                Err(e) => crate::diagnostic!(Warn, "adaptive brightness could not use {} lux: {}", lux, e),
+                Ok(adjustment) => crate::diagnostic!(Debug, "adaptive brightness read {} lux: {:?}", lux, adjustment),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+    use crate::state::StateMachine;
+
+    fn settings() -> AdaptiveBrightnessConfig {
+        AdaptiveBrightnessConfig { target_lux: 300.0, hysteresis_lux: 30.0, max_step_percent: 20, topic: None }
+    }
+
+    #[test]
+    fn test_hysteresis_keeps_readings_near_the_edge_from_flapping() {
+        let mut controller = Controller::new(settings());
+        // Inside the band: left alone
+        assert_eq!(controller.step(320.0, 50), None);
+        assert_eq!(controller.step(280.0, 50), None);
+        // Outside it: corrected until within half the band, even by readings that did not start a correction
+        assert_eq!(controller.step(240.0, 50), Some(70));
+        assert_eq!(controller.step(280.0, 70), Some(77));
+        assert_eq!(controller.step(290.0, 77), None);
+        assert_eq!(controller.step(280.0, 77), None);
+        // Steps are capped and brightness stays between 1% and 100%
+        assert_eq!(controller.step(0.0, 90), Some(100));
+        assert_eq!(controller.step(5000.0, 10), Some(1));
+    }
+
+    #[test]
+    fn test_sensor_payloads() {
+        assert_eq!(parse_lux(b" 120.5\n"), Some(120.5));
+        assert_eq!(parse_lux(br#"{"illuminance_lux": 42, "battery": 90}"#), Some(42.0));
+        assert_eq!(parse_lux(br#"{"lux": 7}"#), Some(7.0));
+        assert_eq!(parse_lux(br#"{"battery": 90}"#), None);
+        assert_eq!(parse_lux(b"dark"), None);
+    }
+
+    #[tokio::test]
+    async fn test_readings_adjust_a_lit_bulb_only() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let adaptive = AdaptiveBrightness::new(None);
+        assert_eq!(adaptive.report(&light, 100.0, false).await.unwrap(), Adjustment::Disabled);
+        assert_eq!(adaptive.last_reading().unwrap().lux, 100.0);
+
+        adaptive.configure(Some(settings()));
+        assert_eq!(adaptive.report(&light, 100.0, false).await.unwrap(), Adjustment::NotLit("OFF"));
+        light.set_power(PowerState::On).await.unwrap();
+        assert_eq!(adaptive.report(&light, 100.0, false).await.unwrap(), Adjustment::AtLimit(100));
+        assert_eq!(adaptive.report(&light, 600.0, true).await.unwrap(), Adjustment::Changed { from: 100, to: 80 });
+        assert_eq!(adaptive.last_reading().unwrap().lux, 100.0);
+        assert_eq!(adaptive.report(&light, 600.0, false).await.unwrap(), Adjustment::Changed { from: 100, to: 80 });
+        assert_eq!(light.state().await.unwrap(), LightState::On { brightness: 80, color: crate::model::Color::WHITE });
+        assert_eq!(light.status().await.unwrap().changed_by.as_deref(), Some(ADAPTIVE_CALLER));
+        assert!(adaptive.report(&light, -1.0, false).await.is_err());
+    }
+}
@@ -0,0 +1,754 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rmcp::handler::server::tool::{Parameters, ToolCallContext, ToolRouter};
+use rmcp::model::*;
+use rmcp::service::{RequestContext, RunningService, ServiceError};
+use rmcp::transport::TokioChildProcess;
+use rmcp::{RoleClient, RoleServer, ServerHandler, ServiceExt, tool, tool_router};
+use serde::Deserialize;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::clock::{SharedClock, system_clock};
+use crate::config::{AggregatorConfig, CONFIG_PATH_ENV, Config, ContactConfig, DownstreamConfig, RulePower};
+use crate::contact::CONTACT_TAG;
+use crate::curfew::Curfews;
+use crate::error::{LightError, with_context};
+use crate::info::VERSION;
+use crate::model::{Easing, MAX_CROSSFADE_MS};
+use crate::reply;
+use crate::service::LOG_NEXT_PAGE;
+use crate::timeline::{MergedHistory, merge_histories};
+
+const LOG_URI: &str = "lightbulb://log";
+// Each downstream server's log as written, which the merged log is made from
+const DOWNSTREAM_LOG_URI: &str = "lightbulb://log.txt";
+const CLIENT_NAME: &str = "lightbulb-aggregator";
+// Set for local downstream servers, so one that finds [[downstream]] entries of its own cannot recurse
+const DOWNSTREAM_ENV: &str = "LIGHTBULB_DOWNSTREAM";
+const DEFAULT_HISTORY_ENTRIES: usize = 100;
+const MAX_HISTORY_ENTRIES: usize = 1000;
+
+// The parts of a downstream lightbulb server the aggregator uses
+#[async_trait::async_trait]
+pub trait BulbClient {
+    // Calls a tool, returning its result as the bulb sent it, or the error behind an isError result
+    async fn call_tool(&self, name: &str, arguments: Option<JsonObject>) -> Result<CallToolResult, ErrorData>;
+    async fn read_log(&self) -> Result<String, ErrorData>;
+}
+
+// A downstream server running as a child process, e.g. `ssh pi@kitchen lightbulb-mcp`
+pub struct McpBulb {
+    service: RunningService<RoleClient, ClientInfo>,
+}
+
+impl McpBulb {
+    pub async fn connect(config: &DownstreamConfig) -> anyhow::Result<Self> {
+        let mut command = Command::new(&config.command);
+        // Our own config path would point a local server back at this aggregator's config
+        command.args(&config.args).env(DOWNSTREAM_ENV, &config.id).env_remove(CONFIG_PATH_ENV);
+        if let Some(dir) = &config.dir {
+            command.current_dir(dir);
+        }
+        let transport = TokioChildProcess::new(command)
+            .map_err(|e| anyhow::anyhow!("Failed to start downstream '{}' ({}): {}", config.id, config.command, e))?;
+        // Downstream servers attribute the changes we make to this name
+        let info = ClientInfo {
+            client_info: Implementation { name: CLIENT_NAME.to_string(), version: VERSION.to_string() },
+            ..Default::default()
+        };
+        let service = info
+            .serve(transport)
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to initialize downstream '{}': {}", config.id, e))?;
+        crate::diagnostic!(Info, "connected to downstream '{}' ({})", config.id, config.command);
+        Ok(Self { service })
+    }
+}
+
+fn downstream_error(error: ServiceError) -> ErrorData {
+    match error {
+        ServiceError::McpError(error) => error,
+        e => LightError::BackendUnreachable(e.to_string()).into(),
+    }
+}
+
+#[async_trait::async_trait]
+impl BulbClient for McpBulb {
+    async fn call_tool(&self, name: &str, arguments: Option<JsonObject>) -> Result<CallToolResult, ErrorData> {
+        let result = self
+            .service
+            .call_tool(CallToolRequestParam { name: name.to_string().into(), arguments })
+            .await
+            .map_err(downstream_error)?;
+        match reply::error_of(&result) {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    }
+
+    // Follows the pages of a long log to its end
+    async fn read_log(&self) -> Result<String, ErrorData> {
+        let mut log = String::new();
+        let mut uri = DOWNSTREAM_LOG_URI.to_string();
+        loop {
+            let result = self.service.read_resource(ReadResourceRequestParam { uri }).await.map_err(downstream_error)?;
+            let page: String = result
+                .contents
+                .into_iter()
+                .filter_map(|contents| match contents {
+                    ResourceContents::TextResourceContents { text, .. } => Some(text),
+                    ResourceContents::BlobResourceContents { .. } => None,
+                })
+                .collect();
+            // Only the first page's heading is kept
+            let page = if log.is_empty() { page.as_str() } else { page.split_once("\n\n").map_or(page.as_str(), |(_, entries)| entries) };
+            let next = page.trim_end().rsplit_once('\n').and_then(|(entries, last)| Some((entries, last.strip_prefix(LOG_NEXT_PAGE)?)));
+            match next {
+                Some((entries, next)) => {
+                    log.push_str(entries);
+                    log.push('\n');
+                    uri = next.to_string();
+                },
+                None => return Ok(log + page),
+            }
+        }
+    }
+}
+
+type Bulb = (String, Arc<dyn BulbClient + Send + Sync>);
+
+// A downstream server that does not answer in time counts as unreachable
+async fn within<T>(timeout: Duration, call: impl Future<Output = Result<T, ErrorData>>) -> Result<T, ErrorData> {
+    match tokio::time::timeout(timeout, call).await {
+        Ok(result) => result,
+        Err(_) => Err(LightError::BackendUnreachable(format!("no answer within {}s", timeout.as_secs())).into()),
+    }
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct BulbsRequest {
+    /// Bulb IDs from list_bulbs; omit to act on every bulb
+    pub bulbs: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct BulbSceneRequest {
+    /// Bulb IDs from list_bulbs; omit to act on every bulb
+    pub bulbs: Option<Vec<String>>,
+    /// Scene to apply, which every bulb's server must know
+    pub name: String,
+    /// Crossfade the bulbs together over this many milliseconds, at most 20000; omit to set the scene at once
+    #[schemars(range(max = MAX_CROSSFADE_MS))]
+    pub transition_ms: Option<u64>,
+    /// How the fade moves through its time: "linear" (the default), "ease-in", "ease-out" or "ease-in-out"
+    pub easing: Option<Easing>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct BulbToolRequest {
+    /// Bulb ID from list_bulbs
+    pub bulb: String,
+    /// Name of a tool on that bulb's server, e.g. "lock_lightbulb"
+    pub tool: String,
+    /// Arguments for the tool
+    pub arguments: Option<JsonObject>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct HistoryRequest {
+    /// Bulb IDs from list_bulbs; omit for every bulb
+    pub bulbs: Option<Vec<String>>,
+    /// Only entries logged at or after this time (RFC 3339)
+    #[schemars(extend("format" = "date-time"))]
+    pub from: Option<String>,
+    /// How many of the most recent entries to return, at most 1000 (default 100)
+    #[schemars(range(min = 1, max = MAX_HISTORY_ENTRIES))]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ContactRequest {
+    /// Door or window sensor, as configured
+    pub sensor: String,
+    /// True when it opened, false when it closed
+    pub open: bool,
+}
+
+// One MCP endpoint in front of several lightbulb-mcp servers, each bulb namespaced by its downstream id
+#[derive(Clone)]
+pub struct AggregatorService {
+    tool_router: ToolRouter<Self>,
+    bulbs: Arc<Vec<Bulb>>,
+    limits: AggregatorConfig,
+    curfews: Arc<Curfews>,
+    contacts: Arc<Vec<ContactConfig>>,
+    clock: SharedClock,
+    // The client making the current call, by the name it gave at initialization
+    client: Option<String>,
+    // Started with one of the config's admin keys, so no curfew applies
+    admin: bool,
+}
+
+#[tool_router]
+impl AggregatorService {
+    pub fn new(bulbs: Vec<Bulb>) -> Self {
+        Self {
+            tool_router: Self::tool_router(),
+            bulbs: Arc::new(bulbs),
+            limits: AggregatorConfig::default(),
+            curfews: Arc::default(),
+            contacts: Arc::default(),
+            clock: system_clock(),
+            client: None,
+            admin: false,
+        }
+    }
+
+    // Caps how many downstream calls run at once and how long each may take
+    pub fn with_limits(self, limits: AggregatorConfig) -> Self {
+        Self { limits, ..self }
+    }
+
+    // Keeps the downstream tools these curfews cover out of reach during their hours, timed with `clock`
+    pub fn with_curfews(self, curfews: Curfews, clock: SharedClock) -> Self {
+        Self { curfews: Arc::new(curfews), clock, ..self }
+    }
+
+    // The same aggregator, held to no curfew
+    pub fn admin(self) -> Self {
+        Self { admin: true, ..self }
+    }
+
+    // Switches the bulbs each of these door and window sensors names as it opens and closes
+    pub fn with_contact_sensors(self, sensors: Vec<ContactConfig>) -> Self {
+        Self { contacts: Arc::new(sensors), ..self }
+    }
+
+    // Starts every downstream server; the aggregator has no bulb of its own, so local integrations are rejected
+    pub async fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let local = [
+            ("webhooks", !config.webhooks.is_empty()),
+            ("mqtt", config.mqtt.is_some()),
+            ("triggers", !config.triggers.is_empty()),
+            ("notifications", !config.notifications.is_empty()),
+            ("weather", config.weather.is_some()),
+            ("presence", config.presence.is_some()),
+            ("rest", config.rest.is_some()),
+        ];
+        if let Some((name, _)) = local.iter().find(|(_, configured)| *configured) {
+            anyhow::bail!("An aggregator has no bulb of its own, so `{}` must be configured on the downstream servers", name);
+        }
+        if let Ok(id) = std::env::var(DOWNSTREAM_ENV) {
+            anyhow::bail!("Downstream '{}' was started by an aggregator but is configured as one itself; give it a `dir` with its own config", id);
+        }
+        config.aggregator.validate()?;
+        let mut ids = HashSet::new();
+        let mut bulbs: Vec<Bulb> = Vec::new();
+        for downstream in &config.downstream {
+            downstream.validate()?;
+            if !ids.insert(downstream.id.as_str()) {
+                anyhow::bail!("Downstream id '{}' is used more than once", downstream.id);
+            }
+            bulbs.push((downstream.id.clone(), Arc::new(McpBulb::connect(downstream).await?)));
+        }
+        let curfews = Curfews::new(&config.curfews)?;
+        for curfew in curfews.iter() {
+            if let Some(bulb) = curfew.bulbs().iter().find(|bulb| !ids.contains(bulb.as_str())) {
+                anyhow::bail!("Curfew '{}' covers bulb '{}', which is not a downstream id", curfew.name(), bulb);
+            }
+        }
+        for (i, sensor) in config.contact.iter().enumerate() {
+            sensor.validate()?;
+            if config.contact[..i].iter().any(|other| other.sensor == sensor.sensor) {
+                anyhow::bail!("Contact sensor '{}' is configured twice", sensor.sensor);
+            }
+            if sensor.topic.is_some() {
+                anyhow::bail!("Contact sensor '{}' has a topic, but an aggregator has no MQTT broker; report it with report_contact", sensor.sensor);
+            }
+            if let Some(bulb) = sensor.bulbs.iter().find(|bulb| !ids.contains(bulb.as_str())) {
+                anyhow::bail!("Contact sensor '{}' switches bulb '{}', which is not a downstream id", sensor.sensor, bulb);
+            }
+        }
+        Ok(Self::new(bulbs)
+            .with_limits(config.aggregator.clone())
+            .with_curfews(curfews, system_clock())
+            .with_contact_sensors(config.contact.clone()))
+    }
+
+    #[tool(description = "List every aggregated bulb with its current status")]
+    async fn list_bulbs(&self) -> Result<String, ErrorData> {
+        let statuses = self.call_each(self.bulbs.iter().cloned().collect(), "get_lightbulb_status").await;
+        Ok(Self::report(statuses, "unavailable"))
+    }
+
+    #[tool(description = "Turn on some or all of the aggregated bulbs")]
+    async fn turn_on_bulbs(&self, Parameters(request): Parameters<BulbsRequest>) -> Result<String, ErrorData> {
+        let bulbs = self.select(request.bulbs.as_deref())?;
+        Ok(Self::report(self.call_each(bulbs, "turn_on_lightbulb").await, "failed"))
+    }
+
+    #[tool(description = "Turn off some or all of the aggregated bulbs")]
+    async fn turn_off_bulbs(&self, Parameters(request): Parameters<BulbsRequest>) -> Result<String, ErrorData> {
+        let bulbs = self.select(request.bulbs.as_deref())?;
+        Ok(Self::report(self.call_each(bulbs, "turn_off_lightbulb").await, "failed"))
+    }
+
+    #[tool(description = "Apply a scene to some or all of the aggregated bulbs, crossfading them together over transition_ms if given")]
+    async fn apply_scene_to_bulbs(&self, Parameters(request): Parameters<BulbSceneRequest>) -> Result<String, ErrorData> {
+        let bulbs = self.select(request.bulbs.as_deref())?;
+        let mut arguments = JsonObject::new();
+        arguments.insert("name".to_string(), request.name.into());
+        if let Some(transition_ms) = request.transition_ms {
+            arguments.insert("transition_ms".to_string(), transition_ms.into());
+        }
+        if let Some(easing) = request.easing {
+            arguments.insert("easing".to_string(), easing.label().into());
+        }
+        // Every bulb starts its fade at once, however low the concurrency limit, so they finish together, and
+        // each has the fade's length on top of the usual time to answer
+        let limits = AggregatorConfig {
+            concurrency: bulbs.len().max(1),
+            timeout_secs: self.limits.timeout_secs + request.transition_ms.unwrap_or(0).div_ceil(1000),
+        };
+        let together = Self { limits, ..self.clone() };
+        let results = together
+            .fan_out(bulbs, |id, client| {
+                let allowed = self.check_curfews("apply_scene", id);
+                let arguments = arguments.clone();
+                async move {
+                    allowed?;
+                    Ok(reply::text_of(&client.call_tool("apply_scene", Some(arguments)).await?))
+                }
+            })
+            .await;
+        Ok(Self::report(results, "failed"))
+    }
+
+    #[tool(description = "Call any tool on one aggregated bulb, e.g. lock_lightbulb or set_color_by_name")]
+    async fn call_bulb_tool(&self, Parameters(request): Parameters<BulbToolRequest>) -> Result<CallToolResult, ErrorData> {
+        let (_, client) = self.select(Some(std::slice::from_ref(&request.bulb)))?.remove(0);
+        self.check_curfews(&request.tool, &request.bulb).map_err(|error| with_context(error, "bulb", request.bulb.clone()))?;
+        let result = within(self.limits.timeout(), client.call_tool(&request.tool, request.arguments)).await;
+        result.map_err(|error| with_context(error, "bulb", request.bulb))
+    }
+
+    #[tool(description = "Report a door or window sensor opening or closing, switching the bulbs configured for it")]
+    async fn report_contact(&self, Parameters(request): Parameters<ContactRequest>) -> Result<String, ErrorData> {
+        let sensor = crate::contact::sensor(&self.contacts, &request.sensor)?.clone();
+        let bulbs = self.select((!sensor.bulbs.is_empty()).then_some(sensor.bulbs.as_slice()))?;
+        let opened = if request.open { "opened" } else { "closed" };
+        let mut arguments = JsonObject::new();
+        arguments.insert("reason".to_string(), format!("{} {}", sensor.sensor, opened).into());
+        arguments.insert("tags".to_string(), serde_json::json!([CONTACT_TAG, sensor.sensor]));
+        let (tool, brightness) = match sensor.action(request.open) {
+            RulePower::On => ("turn_on_lightbulb", sensor.brightness),
+            RulePower::Off => ("turn_off_lightbulb", None),
+        };
+        let results = self
+            .fan_out(bulbs, |id, client| {
+                let allowed = self.check_curfews(tool, id).and_then(|()| match brightness {
+                    Some(_) => self.check_curfews("set_brightness", id),
+                    None => Ok(()),
+                });
+                let arguments = arguments.clone();
+                async move {
+                    allowed?;
+                    // Set first, so the bulb never shows its old brightness lit
+                    if let Some(percent) = brightness {
+                        let mut dim = arguments.clone();
+                        dim.insert("percent".to_string(), percent.into());
+                        client.call_tool("set_brightness", Some(dim)).await?;
+                    }
+                    Ok(reply::text_of(&client.call_tool(tool, Some(arguments)).await?))
+                }
+            })
+            .await;
+        Ok(Self::report(results, "failed"))
+    }
+
+    #[tool(description = "Get the bulbs' logs merged into one history in time order, each entry attributed to its bulb and entries a bulb's log holds twice dropped, returned as JSON")]
+    async fn get_merged_history(&self, Parameters(request): Parameters<HistoryRequest>) -> Result<CallToolResult, ErrorData> {
+        let from = request
+            .from
+            .as_deref()
+            .map(|from| {
+                DateTime::parse_from_rfc3339(from)
+                    .map(|from| from.with_timezone(&Utc))
+                    .map_err(|e| LightError::InvalidParameter(format!("from is not an RFC 3339 timestamp: {}", e)))
+            })
+            .transpose()?;
+        let limit = request.limit.unwrap_or(DEFAULT_HISTORY_ENTRIES);
+        if !(1..=MAX_HISTORY_ENTRIES).contains(&limit) {
+            return Err(LightError::InvalidParameter(format!("limit takes between 1 and {} entries", MAX_HISTORY_ENTRIES)).into());
+        }
+        let bulbs = self.select(request.bulbs.as_deref())?;
+        let ids: Vec<String> = bulbs.iter().map(|(id, _)| id.clone()).collect();
+        let (mut history, failures) = self.histories(bulbs).await;
+        // Undated lines, such as a log's heading, say nothing about when things happened
+        history.entries.retain(|entry| entry.record.at.is_some_and(|at| from.is_none_or(|from| at >= from)));
+        let total = history.entries.len();
+        history.entries.drain(..total.saturating_sub(limit));
+        let mut summary = format!("{} of {} entries from {}", history.entries.len(), total, ids.join(", "));
+        if history.duplicates > 0 {
+            summary.push_str(&format!(" ({} duplicates dropped)", history.duplicates));
+        }
+        for (id, error) in &failures {
+            summary.push_str(&format!("; {}: log unavailable ({})", id, error.message));
+        }
+        reply::json(summary, &history)
+    }
+
+    // Unknown IDs are rejected up front so a typo does not silently act on fewer bulbs
+    fn select(&self, ids: Option<&[String]>) -> Result<Vec<Bulb>, ErrorData> {
+        let Some(ids) = ids else {
+            return Ok(self.bulbs.iter().cloned().collect());
+        };
+        ids.iter()
+            .map(|id| {
+                self.bulbs.iter().find(|(bulb, _)| bulb == id).cloned().ok_or_else(|| {
+                    let known: Vec<&str> = self.bulbs.iter().map(|(bulb, _)| bulb.as_str()).collect();
+                    LightError::InvalidParameter(format!("Unknown bulb '{}'; known bulbs: {}", id, known.join(", "))).into()
+                })
+            })
+            .collect()
+    }
+
+    async fn call_each(&self, bulbs: Vec<Bulb>, tool: &'static str) -> Vec<(String, Result<String, ErrorData>)> {
+        self.fan_out(bulbs, move |id, client| {
+            let allowed = self.check_curfews(tool, id);
+            async move {
+                allowed?;
+                // One line per bulb, so a status's JSON is left out
+                Ok(reply::summary_of(&client.call_tool(tool, None).await?))
+            }
+        })
+        .await
+    }
+
+    // The aggregator has no log of its own, so a refused call is only reported in the diagnostics
+    fn check_curfews(&self, tool: &str, bulb: &str) -> Result<(), ErrorData> {
+        if self.admin {
+            return Ok(());
+        }
+        self.curfews.check(tool, Some(bulb), self.clock.local_time()).map_err(|error| {
+            let client = self.client.as_deref().unwrap_or("an unnamed client");
+            crate::diagnostic!(Info, "{} on {} called by {}: {}", tool, bulb, client, error);
+            error.into()
+        })
+    }
+
+    // Runs `call` against every bulb, at most `concurrency` at a time and each within the timeout, returning the
+    // results in the order the bulbs were given
+    async fn fan_out<T, F, Fut>(&self, bulbs: Vec<Bulb>, call: F) -> Vec<(String, Result<T, ErrorData>)>
+    where
+        T: Send + 'static,
+        F: Fn(&str, Arc<dyn BulbClient + Send + Sync>) -> Fut,
+        Fut: Future<Output = Result<T, ErrorData>> + Send + 'static,
+    {
+        let permits = Arc::new(Semaphore::new(self.limits.concurrency));
+        let timeout = self.limits.timeout();
+        let mut calls = JoinSet::new();
+        for (index, (id, client)) in bulbs.into_iter().enumerate() {
+            let (call, permits) = (call(&id, client), permits.clone());
+            calls.spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                let result = within(timeout, call).await.map_err(|error| with_context(error, "bulb", id.clone()));
+                (index, id, result)
+            });
+        }
+        let mut results = calls.join_all().await;
+        results.sort_by_key(|(index, _, _)| *index);
+        results.into_iter().map(|(_, id, result)| (id, result)).collect()
+    }
+
+    fn report(results: Vec<(String, Result<String, ErrorData>)>, failure: &str) -> String {
+        results
+            .into_iter()
+            .map(|(id, result)| match result {
+                Ok(text) => format!("{}: {}", id, text),
+                Err(error) => format!("{}: {} ({})", id, failure, error.message),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    // The logs of the reachable `bulbs` in one timeline, and why the others' could not be read
+    async fn histories(&self, bulbs: Vec<Bulb>) -> (MergedHistory, Vec<(String, ErrorData)>) {
+        let logs = self.fan_out(bulbs, |_, client| async move { client.read_log().await }).await;
+        let (read, failures): (Vec<_>, Vec<_>) = logs.into_iter().partition(|(_, log)| log.is_ok());
+        let read: Vec<(String, String)> = read.into_iter().filter_map(|(id, log)| Some((id, log.ok()?))).collect();
+        let history = merge_histories(read.iter().map(|(id, log)| (id.as_str(), log.as_str())));
+        (history, failures.into_iter().filter_map(|(id, log)| Some((id, log.err()?))).collect())
+    }
+
+    // Every reachable bulb's log in one timeline, each entry tagged with its bulb
+    async fn merged_log(&self) -> String {
+        self.histories(self.bulbs.iter().cloned().collect()).await.0.text()
+    }
+}
+
+impl ServerHandler for AggregatorService {
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            instructions: Some("Aggregates several lightbulbs; call list_bulbs for their IDs".into()),
+            capabilities: ServerCapabilities::builder().enable_tools().enable_resources().build(),
+            ..Default::default()
+        }
+    }
+
+    // Failures reach the client as isError results, as they do from a single bulb's server
+    async fn call_tool(&self, request: CallToolRequestParam, context: RequestContext<RoleServer>) -> Result<CallToolResult, ErrorData> {
+        if !self.tool_router.has_route(&request.name) {
+            return Err(LightError::UnknownTool(request.name.to_string()).into());
+        }
+        let client = context.peer.peer_info().map(|info| info.client_info.name.clone());
+        let service = Self { client, ..self.clone() };
+        reply::outcome(self.tool_router.call(ToolCallContext::new(&service, request, context)).await)
+    }
+
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, ErrorData> {
+        let log = RawResource {
+            uri: LOG_URI.to_string(),
+            name: "Merged Lightbulb Activity Log".to_string(),
+            description: Some("Activity logs of every aggregated bulb, merged in time order and tagged with bulb IDs".to_string()),
+            mime_type: Some("text/plain".to_string()),
+            size: None,
+        };
+        Ok(ListResourcesResult { resources: vec![Resource { raw: log, annotations: None }], next_cursor: None })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, ErrorData> {
+        if request.uri != LOG_URI {
+            return Err(LightError::UnknownResource(request.uri).into());
+        }
+        Ok(ReadResourceResult { contents: vec![ResourceContents::text(self.merged_log().await, request.uri)] })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::config::CurfewConfig;
+
+    // Answers tool calls with the tool name, recording them
+    struct FakeBulb {
+        log: &'static str,
+        reachable: bool,
+        calls: Mutex<Vec<String>>,
+        arguments: Mutex<Vec<Option<JsonObject>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl BulbClient for FakeBulb {
+        async fn call_tool(&self, name: &str, arguments: Option<JsonObject>) -> Result<CallToolResult, ErrorData> {
+            if !self.reachable {
+                return Err(LightError::BackendUnreachable("timed out".to_string()).into());
+            }
+            self.calls.lock().unwrap().push(name.to_string());
+            self.arguments.lock().unwrap().push(arguments);
+            Ok(reply::with_swatch(format!("did {}", name), crate::model::Color { r: 255, g: 0, b: 0 }))
+        }
+
+        async fn read_log(&self) -> Result<String, ErrorData> {
+            Ok(self.log.to_string())
+        }
+    }
+
+    fn bulb(id: &str, log: &'static str, reachable: bool) -> (Arc<FakeBulb>, Bulb) {
+        let fake = Arc::new(FakeBulb { log, reachable, calls: Mutex::default(), arguments: Mutex::default() });
+        (fake.clone(), (id.to_string(), fake))
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_reports_each_bulb() {
+        let (kitchen, kitchen_bulb) = bulb("kitchen", "", true);
+        let (_, porch_bulb) = bulb("porch", "", false);
+        let service = AggregatorService::new(vec![kitchen_bulb, porch_bulb]);
+
+        let report = service.turn_on_bulbs(Parameters(BulbsRequest { bulbs: None })).await.unwrap();
+        assert_eq!(report, "kitchen: did turn_on_lightbulb\nporch: failed (Failed to reach the lightbulb: timed out)");
+
+        let request = BulbsRequest { bulbs: Some(vec!["kitchen".to_string(), "attic".to_string()]) };
+        let error = service.turn_off_bulbs(Parameters(request)).await.unwrap_err();
+        assert_eq!(error.message, "Invalid parameter: Unknown bulb 'attic'; known bulbs: kitchen, porch");
+        assert_eq!(*kitchen.calls.lock().unwrap(), vec!["turn_on_lightbulb"]);
+
+        // A bulb's answer is passed on whole, images and all
+        let request = BulbToolRequest { bulb: "kitchen".to_string(), tool: "set_color_by_name".to_string(), arguments: None };
+        let result = service.call_bulb_tool(Parameters(request)).await.unwrap();
+        assert!(result.content.unwrap()[1].as_image().is_some());
+
+        let request = BulbToolRequest { bulb: "porch".to_string(), tool: "lock_lightbulb".to_string(), arguments: None };
+        let error = service.call_bulb_tool(Parameters(request)).await.unwrap_err();
+        assert_eq!(error.data.unwrap(), serde_json::json!({ "code": "BACKEND_UNREACHABLE", "detail": "timed out", "bulb": "porch" }));
+    }
+
+    #[tokio::test]
+    async fn test_scenes_crossfade_every_bulb_together() {
+        let (kitchen, kitchen_bulb) = bulb("kitchen", "", true);
+        let (hall, hall_bulb) = bulb("hall", "", true);
+        let limits = AggregatorConfig { concurrency: 1, ..Default::default() };
+        let service = AggregatorService::new(vec![kitchen_bulb, hall_bulb]).with_limits(limits);
+
+        let request = BulbSceneRequest { bulbs: None, name: "movie".to_string(), transition_ms: Some(2_000), easing: Some(Easing::EaseInOut) };
+        let report = service.apply_scene_to_bulbs(Parameters(request)).await.unwrap();
+        assert_eq!(report, "kitchen: did apply_scene\nhall: did apply_scene");
+        let expected = serde_json::json!({ "name": "movie", "transition_ms": 2000, "easing": "ease-in-out" });
+        for fake in [kitchen, hall] {
+            assert_eq!(serde_json::Value::Object(fake.arguments.lock().unwrap()[0].clone().unwrap()), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_curfews_cover_the_bulbs_they_name() {
+        let (kids, kids_bulb) = bulb("kids-room", "", true);
+        let (_, kitchen_bulb) = bulb("kitchen", "", true);
+        let config = CurfewConfig {
+            name: "bedtime".to_string(),
+            after: "20:30".to_string(),
+            before: "07:00".to_string(),
+            tools: Vec::new(),
+            bulbs: vec!["kids-room".to_string()],
+        };
+        let night = chrono::DateTime::parse_from_rfc3339("2025-08-01T22:00:00Z").unwrap().to_utc();
+        let clock = Arc::new(crate::clock::ManualClock::new(night));
+        let service = AggregatorService::new(vec![kids_bulb, kitchen_bulb]).with_curfews(Curfews::new(&[config]).unwrap(), clock);
+
+        let report = service.turn_on_bulbs(Parameters(BulbsRequest { bulbs: None })).await.unwrap();
+        assert!(report.starts_with("kids-room: failed ('turn_on_lightbulb' is outside allowed hours"), "{}", report);
+        assert!(report.ends_with("kitchen: did turn_on_lightbulb"), "{}", report);
+        let request = BulbToolRequest { bulb: "kids-room".to_string(), tool: "lock_lightbulb".to_string(), arguments: None };
+        let data = service.call_bulb_tool(Parameters(request)).await.unwrap_err().data.unwrap();
+        assert_eq!((data["code"].as_str(), data["bulb"].as_str()), (Some("OUTSIDE_ALLOWED_HOURS"), Some("kids-room")));
+        assert!(kids.calls.lock().unwrap().is_empty());
+        let report = service.admin().turn_on_bulbs(Parameters(BulbsRequest { bulbs: None })).await.unwrap();
+        assert!(report.starts_with("kids-room: did turn_on_lightbulb"), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn test_contact_sensors_switch_the_bulbs_they_name() {
+        let (porch, porch_bulb) = bulb("porch", "", true);
+        let (kitchen, kitchen_bulb) = bulb("kitchen", "", true);
+        let sensor = ContactConfig {
+            sensor: "back-door".to_string(),
+            topic: None,
+            bulbs: vec!["porch".to_string()],
+            on_open: RulePower::On,
+            on_close: RulePower::Off,
+            brightness: Some(40),
+        };
+        let service = AggregatorService::new(vec![porch_bulb, kitchen_bulb]).with_contact_sensors(vec![sensor]);
+        let report = |open: bool| service.report_contact(Parameters(ContactRequest { sensor: "back-door".to_string(), open }));
+
+        assert_eq!(report(true).await.unwrap(), "porch: did turn_on_lightbulb");
+        assert_eq!(report(false).await.unwrap(), "porch: did turn_off_lightbulb");
+        assert_eq!(*porch.calls.lock().unwrap(), vec!["set_brightness", "turn_on_lightbulb", "turn_off_lightbulb"]);
+        assert!(kitchen.calls.lock().unwrap().is_empty());
+        let error = service.report_contact(Parameters(ContactRequest { sensor: "garage".to_string(), open: true })).await.unwrap_err();
+        assert!(error.message.contains("configured sensors: back-door"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn test_logs_merge_in_time_order() {
+        let (_, kitchen) = bulb("kitchen", "[2025-08-02T10:00:00+00:00] Lightbulb turned ON\n[2025-08-02T12:00:00+00:00] Lightbulb turned OFF\n", true);
+        let (_, porch) = bulb("porch", "[2025-08-02T11:00:00+00:00] Lightbulb turned ON\n", true);
+        let service = AggregatorService::new(vec![kitchen, porch]);
+
+        let lines: Vec<String> = service.merged_log().await.lines().map(String::from).collect();
+        assert_eq!(lines, vec![
+            "[2025-08-02T10:00:00+00:00] kitchen: Lightbulb turned ON",
+            "[2025-08-02T11:00:00+00:00] porch: Lightbulb turned ON",
+            "[2025-08-02T12:00:00+00:00] kitchen: Lightbulb turned OFF",
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_merged_history_attributes_entries_to_bulbs() {
+        let kitchen_log = "Lightbulb Activity Log:\n\n[2025-08-02T10:00:00+00:00] Lightbulb turned ON\n[2025-08-02T10:00:00+00:00] Lightbulb turned ON sig=abc\n[2025-08-02T12:00:00+00:00] Lightbulb turned OFF\n";
+        let (_, kitchen) = bulb("kitchen", kitchen_log, true);
+        let (_, porch) = bulb("porch", "[2025-08-02T11:00:00+00:00] Lightbulb turned ON [night]\n", true);
+        let service = AggregatorService::new(vec![kitchen, porch]);
+        let history = |from: Option<&str>, limit: Option<usize>| {
+            service.get_merged_history(Parameters(HistoryRequest { bulbs: None, from: from.map(String::from), limit }))
+        };
+
+        let result = history(Some("2025-08-02T10:30:00Z"), None).await.unwrap();
+        assert!(reply::text_of(&result).starts_with("2 of 2 entries from kitchen, porch (1 duplicates dropped)\n"));
+        let json = reply::json_of(&result).unwrap();
+        let entries: Vec<(&str, &str)> = json["entries"].as_array().unwrap().iter().map(|entry| (entry["bulb"].as_str().unwrap(), entry["power"].as_str().unwrap())).collect();
+        assert_eq!(entries, [("porch", "on"), ("kitchen", "off")]);
+        assert_eq!(json["entries"][0]["tags"], serde_json::json!(["night"]));
+
+        let json = reply::json_of(&history(None, Some(1)).await.unwrap()).unwrap();
+        assert_eq!(json["entries"][0]["at"], "2025-08-02T12:00:00Z");
+        assert!(history(None, Some(0)).await.is_err());
+        assert!(history(Some("noon"), None).await.is_err());
+    }
+
+    // Takes `delay` to answer, tracking how many calls to bulbs like it are in flight
+    struct SlowBulb {
+        delay: Duration,
+        in_flight: Arc<std::sync::atomic::AtomicUsize>,
+        most_in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl BulbClient for SlowBulb {
+        async fn call_tool(&self, name: &str, _arguments: Option<JsonObject>) -> Result<CallToolResult, ErrorData> {
+            use std::sync::atomic::Ordering;
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_in_flight.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(self.delay).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(reply::text(format!("did {}", name)))
+        }
+
+        async fn read_log(&self) -> Result<String, ErrorData> {
+            Ok(String::new())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fan_out_is_bounded_and_timed_out() {
+        let (in_flight, most_in_flight): (Arc<std::sync::atomic::AtomicUsize>, Arc<std::sync::atomic::AtomicUsize>) = Default::default();
+        let bulbs: Vec<Bulb> = (0..6)
+            .map(|i| {
+                let delay = Duration::from_secs(if i == 5 { 60 } else { 1 });
+                let bulb = SlowBulb { delay, in_flight: in_flight.clone(), most_in_flight: most_in_flight.clone() };
+                (format!("bulb-{}", i), Arc::new(bulb) as Arc<dyn BulbClient + Send + Sync>)
+            })
+            .collect();
+        let service = AggregatorService::new(bulbs).with_limits(AggregatorConfig { concurrency: 2, timeout_secs: 5 });
+
+        let started = tokio::time::Instant::now();
+        let report = service.turn_off_bulbs(Parameters(BulbsRequest { bulbs: None })).await.unwrap();
+        assert_eq!(most_in_flight.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert!(report.starts_with("bulb-0: did turn_off_lightbulb\n"), "{}", report);
+        assert!(report.ends_with("bulb-5: failed (Failed to reach the lightbulb: no answer within 5s)"), "{}", report);
+        // Five one-second calls two at a time, then the slow one gives up after its timeout
+        assert_eq!(started.elapsed(), Duration::from_secs(7));
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::BTreeSet;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey, VerificationAlgorithm};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Sha256, Sha384, Sha512};
+
+use crate::config::JwtConfig;
+
+// What a bearer JWT's `light:` scopes allow; each scope includes the ones before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scope {
+    // The status, history and report tools
+    Read,
+    // Every tool that switches or changes the bulb
+    Write,
+    // Tools that reset, reconfigure or rewrite the server and its log
+    Admin,
+}
+
+impl Scope {
+    pub fn parse(scope: &str) -> Option<Self> {
+        match scope {
+            "light:read" => Some(Scope::Read),
+            "light:write" => Some(Scope::Write),
+            "light:admin" => Some(Scope::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Read => "light:read",
+            Scope::Write => "light:write",
+            Scope::Admin => "light:admin",
+        }
+    }
+}
+
+// Tools that only look at the bulb, its log or the server
+const READ_TOOLS: [&str; 14] = [
+    "get_lightbulb_status",
+    "get_lightbulb_state",
+    "get_offline_queue",
+    "list_lightbulbs",
+    "get_statistics",
+    "diff_state",
+    "query_history",
+    "list_schedules",
+    "get_job_status",
+    "list_jobs",
+    "run_diagnostics",
+    "server_info",
+    "verify_log_signatures",
+    "export_home_assistant",
+];
+// Tools that reset or reconfigure the server, or rewrite its log
+const ADMIN_TOOLS: [&str; 12] = [
+    "factory_reset",
+    "import_log",
+    "purge_data",
+    "set_fault_injection",
+    "clear_fault_injection",
+    "change_at_device",
+    "simulate_power_outage",
+    "update_firmware",
+    "add_lightbulb",
+    "rename_bulb",
+    "set_bulb_aliases",
+    "import_automation",
+];
+
+// The scope a tool needs; set_response_detail only changes what the session's own replies say
+pub fn tool_scope(tool: &str) -> Scope {
+    if READ_TOOLS.contains(&tool) || tool == "set_response_detail" {
+        Scope::Read
+    } else if ADMIN_TOOLS.contains(&tool) {
+        Scope::Admin
+    } else {
+        Scope::Write
+    }
+}
+
+// A configured key a request's bearer token matched, put in the request's extensions by the HTTP transport: a
+// guest key's requests are served as a guest session's, an admin key's as an admin session's
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAccess {
+    Guest,
+    Admin,
+}
+
+// Who a validated token was issued to and the scopes it carries, put in the request's extensions by the HTTP
+// transport for the service to check tool calls against
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grant {
+    pub subject: Option<String>,
+    pub scopes: BTreeSet<Scope>,
+}
+
+impl Grant {
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|granted| *granted >= scope)
+    }
+
+    pub fn allows_tool(&self, tool: &str) -> bool {
+        self.allows(tool_scope(tool))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TokenError {
+    #[error("the token is not a JWT")]
+    Malformed,
+    #[error("the token is signed with {0}, which this server does not accept")]
+    UnsupportedAlgorithm(String),
+    #[error("no configured key matches the token")]
+    UnknownKey,
+    #[error("the token's signature is invalid")]
+    BadSignature,
+    #[error("the token has no exp claim")]
+    MissingExpiry,
+    #[error("the token has expired")]
+    Expired,
+    #[error("the token is not valid yet")]
+    NotYetValid,
+    #[error("the token was not issued by {0}")]
+    WrongIssuer(String),
+    #[error("the token is not meant for {0}")]
+    WrongAudience(String),
+}
+
+// One key of a JWK set, as RFC 7517 writes it
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Header {
+    alg: String,
+    kid: Option<String>,
+}
+
+// A public key of the JWK set, decoded for ring
+#[derive(Debug, Clone)]
+enum PublicKey {
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    // The uncompressed point, 0x04 || x || y
+    Ec { curve: String, point: Vec<u8> },
+    Ed25519(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+struct Key {
+    kid: Option<String>,
+    alg: Option<String>,
+    key: PublicKey,
+}
+
+impl Key {
+    fn from_jwk(jwk: Jwk) -> anyhow::Result<Option<Self>> {
+        let field = |value: &Option<String>, name: &str| -> anyhow::Result<Vec<u8>> {
+            let value = value.as_deref().ok_or_else(|| anyhow::anyhow!("JWK {} key has no '{}'", jwk.kty, name))?;
+            URL_SAFE_NO_PAD.decode(value).map_err(|_| anyhow::anyhow!("JWK '{}' is not base64url", name))
+        };
+        let key = match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => PublicKey::Rsa { n: field(&jwk.n, "n")?, e: field(&jwk.e, "e")? },
+            ("EC", Some(curve @ ("P-256" | "P-384"))) => {
+                let point = [vec![0x04], field(&jwk.x, "x")?, field(&jwk.y, "y")?].concat();
+                PublicKey::Ec { curve: curve.to_string(), point }
+            },
+            ("OKP", Some("Ed25519")) => PublicKey::Ed25519(field(&jwk.x, "x")?),
+            // Keys of other types cannot check any token this server accepts
+            _ => return Ok(None),
+        };
+        Ok(Some(Self { kid: jwk.kid, alg: jwk.alg, key }))
+    }
+
+    fn verifies(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        if self.alg.as_deref().is_some_and(|key_alg| key_alg != alg) {
+            return false;
+        }
+        match (&self.key, alg) {
+            (PublicKey::Rsa { n, e }, "RS256" | "RS384" | "RS512") => {
+                let params = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    _ => &signature::RSA_PKCS1_2048_8192_SHA512,
+                };
+                RsaPublicKeyComponents { n, e }.verify(params, message, signature).is_ok()
+            },
+            (PublicKey::Ec { curve, point }, "ES256") if curve == "P-256" => verify(&signature::ECDSA_P256_SHA256_FIXED, point, message, signature),
+            (PublicKey::Ec { curve, point }, "ES384") if curve == "P-384" => verify(&signature::ECDSA_P384_SHA384_FIXED, point, message, signature),
+            (PublicKey::Ed25519(key), "EdDSA") => verify(&signature::ED25519, key, message, signature),
+            _ => false,
+        }
+    }
+}
+
+fn verify(algorithm: &'static dyn VerificationAlgorithm, key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+    UnparsedPublicKey::new(algorithm, key).verify(message, signature).is_ok()
+}
+
+// Checks bearer JWTs against the configured secret and JWK set, and turns their scopes into a Grant
+#[derive(Debug, Clone)]
+pub struct JwtValidator {
+    secret: Option<Vec<u8>>,
+    keys: Vec<Key>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    leeway_secs: i64,
+}
+
+impl JwtValidator {
+    // A validator for `config`, checking tokens against the JWK set `jwks` as well as its secret
+    pub fn new(config: &JwtConfig, jwks: Option<&str>) -> anyhow::Result<Self> {
+        config.validate()?;
+        let keys = match jwks {
+            Some(jwks) => {
+                let set: JwkSet = serde_json::from_str(jwks).map_err(|e| anyhow::anyhow!("Cannot parse the JWK set: {}", e))?;
+                set.keys.into_iter().filter_map(|jwk| Key::from_jwk(jwk).transpose()).collect::<anyhow::Result<_>>()?
+            },
+            None => Vec::new(),
+        };
+        if config.secret.is_none() && keys.is_empty() {
+            anyhow::bail!("The JWK set has no RSA, EC P-256/P-384 or Ed25519 key to check tokens with");
+        }
+        Ok(Self {
+            secret: config.secret.as_ref().map(|secret| secret.as_bytes().to_vec()),
+            keys,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            leeway_secs: config.leeway_secs as i64,
+        })
+    }
+
+    // Reads the JWK set from the configured file or URL, once at startup
+    pub async fn load(config: &JwtConfig) -> anyhow::Result<Self> {
+        let jwks = match (&config.jwks_file, &config.jwks_url) {
+            (Some(file), _) => Some(std::fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Cannot read the JWK set {}: {}", file, e))?),
+            (None, Some(url)) => {
+                let response = reqwest::get(url).await.and_then(|response| response.error_for_status());
+                Some(response.map_err(|e| anyhow::anyhow!("Cannot fetch the JWK set from {}: {}", url, e))?.text().await?)
+            },
+            (None, None) => None,
+        };
+        Self::new(config, jwks.as_deref())
+    }
+
+    pub fn validate(&self, token: &str) -> Result<Grant, TokenError> {
+        self.validate_at(token, chrono::Utc::now().timestamp())
+    }
+
+    pub fn validate_at(&self, token: &str, now: i64) -> Result<Grant, TokenError> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(claims), Some(signature), None) = (parts.next(), parts.next(), parts.next(), parts.next()) else {
+            return Err(TokenError::Malformed);
+        };
+        let decode = |part: &str| URL_SAFE_NO_PAD.decode(part).map_err(|_| TokenError::Malformed);
+        let header: Header = serde_json::from_slice(&decode(header)?).map_err(|_| TokenError::Malformed)?;
+        let claims: Value = serde_json::from_slice(&decode(claims)?).map_err(|_| TokenError::Malformed)?;
+        let signature = decode(signature)?;
+        let (message, _) = token.rsplit_once('.').ok_or(TokenError::Malformed)?;
+        self.check_signature(&header, message.as_bytes(), &signature)?;
+        self.check_claims(&claims, now)?;
+        let scopes = match (&claims["scope"], &claims["scp"]) {
+            (Value::String(scope), _) | (_, Value::String(scope)) => scope.split_whitespace().filter_map(Scope::parse).collect(),
+            (_, Value::Array(scopes)) => scopes.iter().filter_map(Value::as_str).filter_map(Scope::parse).collect(),
+            _ => BTreeSet::new(),
+        };
+        Ok(Grant { subject: claims["sub"].as_str().map(String::from), scopes })
+    }
+
+    fn check_signature(&self, header: &Header, message: &[u8], signature: &[u8]) -> Result<(), TokenError> {
+        let alg = header.alg.as_str();
+        if let Some(hash) = alg.strip_prefix("HS") {
+            let secret = self.secret.as_deref().ok_or(TokenError::UnknownKey)?;
+            let valid = match hash {
+                "256" => Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length").chain_update(message).verify_slice(signature),
+                "384" => Hmac::<Sha384>::new_from_slice(secret).expect("HMAC accepts keys of any length").chain_update(message).verify_slice(signature),
+                "512" => Hmac::<Sha512>::new_from_slice(secret).expect("HMAC accepts keys of any length").chain_update(message).verify_slice(signature),
+                _ => return Err(TokenError::UnsupportedAlgorithm(header.alg.clone())),
+            };
+            return valid.map_err(|_| TokenError::BadSignature);
+        }
+        if !matches!(alg, "RS256" | "RS384" | "RS512" | "ES256" | "ES384" | "EdDSA") {
+            return Err(TokenError::UnsupportedAlgorithm(header.alg.clone()));
+        }
+        let mut keys = self.keys.iter().filter(|key| header.kid.is_none() || key.kid == header.kid).peekable();
+        if keys.peek().is_none() {
+            return Err(TokenError::UnknownKey);
+        }
+        match keys.any(|key| key.verifies(alg, message, signature)) {
+            true => Ok(()),
+            false => Err(TokenError::BadSignature),
+        }
+    }
+
+    fn check_claims(&self, claims: &Value, now: i64) -> Result<(), TokenError> {
+        let exp = claims["exp"].as_i64().ok_or(TokenError::MissingExpiry)?;
+        if now > exp + self.leeway_secs {
+            return Err(TokenError::Expired);
+        }
+        if claims["nbf"].as_i64().is_some_and(|nbf| now + self.leeway_secs < nbf) {
+            return Err(TokenError::NotYetValid);
+        }
+        if let Some(issuer) = &self.issuer
+            && claims["iss"].as_str() != Some(issuer)
+        {
+            return Err(TokenError::WrongIssuer(issuer.clone()));
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match &claims["aud"] {
+                Value::String(aud) => aud == audience,
+                Value::Array(auds) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err(TokenError::WrongAudience(audience.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Signs `claims` with HS256, for tests of the HTTP transport
+#[cfg(test)]
+pub(crate) fn hs256_token(secret: &str, claims: &Value) -> String {
+    let signing_input = format!("{}.{}", URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#), URL_SAFE_NO_PAD.encode(claims.to_string()));
+    let signature = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap().chain_update(signing_input.as_bytes()).finalize().into_bytes();
+    format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(signature))
+}
+
+#[cfg(test)]
+mod tests {
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair};
+    use serde_json::json;
+
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+    const NOW: i64 = 1_800_000_000;
+
+    fn config(toml: &str) -> JwtConfig {
+        crate::config::Config::parse(toml).unwrap().jwt.unwrap()
+    }
+
+    fn signed(header: Value, claims: Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let signing_input = format!("{}.{}", URL_SAFE_NO_PAD.encode(header.to_string()), URL_SAFE_NO_PAD.encode(claims.to_string()));
+        format!("{}.{}", signing_input, URL_SAFE_NO_PAD.encode(sign(signing_input.as_bytes())))
+    }
+
+    #[test]
+    fn test_tool_scopes() {
+        assert_eq!(tool_scope("get_lightbulb_status"), Scope::Read);
+        assert_eq!(tool_scope("turn_on_lightbulb"), Scope::Write);
+        assert_eq!(tool_scope("factory_reset"), Scope::Admin);
+        let grant = |scopes: &[Scope]| Grant { subject: None, scopes: scopes.iter().copied().collect() };
+        assert!(grant(&[Scope::Write]).allows_tool("query_history"));
+        assert!(!grant(&[Scope::Write]).allows_tool("purge_data"));
+        assert!(grant(&[Scope::Admin]).allows_tool("set_color"));
+        assert!(!grant(&[Scope::Read]).allows_tool("set_color"));
+        assert!(!grant(&[]).allows_tool("get_lightbulb_status"));
+    }
+
+    #[test]
+    fn test_hs256_tokens_are_checked_for_signature_and_claims() {
+        let validator = JwtValidator::new(&config(&format!("[jwt]\nsecret = \"{}\"\nissuer = \"auth\"\naudience = \"lightbulb\"", SECRET)), None).unwrap();
+        let claims = json!({ "sub": "kitchen-tablet", "iss": "auth", "aud": ["lightbulb"], "exp": NOW + 300, "scope": "openid light:write" });
+        let grant = validator.validate_at(&hs256_token(SECRET, &claims), NOW).unwrap();
+        assert_eq!((grant.subject.as_deref(), grant.scopes), (Some("kitchen-tablet"), BTreeSet::from([Scope::Write])));
+
+        let with = |change: Value| {
+            let mut claims = claims.clone();
+            claims.as_object_mut().unwrap().extend(change.as_object().unwrap().clone());
+            validator.validate_at(&hs256_token(SECRET, &claims), NOW)
+        };
+        assert_eq!(with(json!({ "exp": NOW - 61 })), Err(TokenError::Expired));
+        assert!(with(json!({ "exp": NOW - 30 })).is_ok(), "within the leeway");
+        assert_eq!(with(json!({ "exp": null })), Err(TokenError::MissingExpiry));
+        assert_eq!(with(json!({ "nbf": NOW + 600 })), Err(TokenError::NotYetValid));
+        assert_eq!(with(json!({ "iss": "other" })), Err(TokenError::WrongIssuer("auth".to_string())));
+        assert_eq!(with(json!({ "aud": "other" })), Err(TokenError::WrongAudience("lightbulb".to_string())));
+        assert_eq!(with(json!({ "scope": null, "scp": ["light:read", "light:admin"] })).unwrap().scopes, BTreeSet::from([Scope::Read, Scope::Admin]));
+
+        let forged = hs256_token("fedcba9876543210fedcba9876543210", &claims);
+        assert_eq!(validator.validate_at(&forged, NOW), Err(TokenError::BadSignature));
+        let unsigned = signed(json!({ "alg": "none" }), claims.clone(), |_| Vec::new());
+        assert_eq!(validator.validate_at(&unsigned, NOW), Err(TokenError::UnsupportedAlgorithm("none".to_string())));
+        assert_eq!(validator.validate_at("not.a-jwt", NOW), Err(TokenError::Malformed));
+    }
+
+    #[test]
+    fn test_jwks_tokens_are_checked_with_the_key_they_name() {
+        let rng = SystemRandom::new();
+        let ed25519 = Ed25519KeyPair::from_pkcs8(Ed25519KeyPair::generate_pkcs8(&rng).unwrap().as_ref()).unwrap();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let p256 = EcdsaKeyPair::from_pkcs8(&signature::ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = p256.public_key().as_ref();
+        let jwks = json!({ "keys": [
+            { "kty": "OKP", "crv": "Ed25519", "kid": "ed", "x": URL_SAFE_NO_PAD.encode(ed25519.public_key().as_ref()) },
+            { "kty": "EC", "crv": "P-256", "kid": "ec", "x": URL_SAFE_NO_PAD.encode(&point[1..33]), "y": URL_SAFE_NO_PAD.encode(&point[33..]) },
+            { "kty": "oct", "kid": "ignored", "k": "c2VjcmV0" },
+        ]});
+        let validator = JwtValidator::new(&config("[jwt]\njwks_file = \"jwks.json\""), Some(&jwks.to_string())).unwrap();
+        let claims = json!({ "exp": NOW + 60, "scope": "light:read" });
+
+        let token = signed(json!({ "alg": "EdDSA", "kid": "ed" }), claims.clone(), |message| ed25519.sign(message).as_ref().to_vec());
+        assert_eq!(validator.validate_at(&token, NOW).unwrap().scopes, BTreeSet::from([Scope::Read]));
+        let token = signed(json!({ "alg": "ES256", "kid": "ec" }), claims.clone(), |message| p256.sign(&rng, message).unwrap().as_ref().to_vec());
+        assert!(validator.validate_at(&token, NOW).is_ok());
+        let token = signed(json!({ "alg": "ES256" }), claims.clone(), |message| p256.sign(&rng, message).unwrap().as_ref().to_vec());
+        assert!(validator.validate_at(&token, NOW).is_ok(), "a token naming no key is tried against every key");
+
+        let token = signed(json!({ "alg": "EdDSA", "kid": "ec" }), claims.clone(), |message| ed25519.sign(message).as_ref().to_vec());
+        assert_eq!(validator.validate_at(&token, NOW), Err(TokenError::BadSignature));
+        let token = signed(json!({ "alg": "EdDSA", "kid": "gone" }), claims.clone(), |message| ed25519.sign(message).as_ref().to_vec());
+        assert_eq!(validator.validate_at(&token, NOW), Err(TokenError::UnknownKey));
+        assert_eq!(validator.validate_at(&hs256_token(SECRET, &claims), NOW), Err(TokenError::UnknownKey), "no secret is configured");
+        assert!(JwtValidator::new(&config("[jwt]\njwks_file = \"jwks.json\""), Some(r#"{"keys":[]}"#)).is_err());
+    }
+}
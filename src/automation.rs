@@ -0,0 +1,591 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use anyhow::Context;
+use chrono::{DateTime, Datelike, NaiveDateTime, NaiveTime, TimeDelta, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::actor::LightHandle;
+use crate::config::{RulePower, SceneConfig, ScheduleConfig};
+use crate::diagnostics::{SCHEDULER_TASK, StateFileProbe};
+use crate::error::LightError;
+use crate::scenes::Scenes;
+use crate::schedule::ScheduleTime;
+
+// Who scheduled changes are logged as
+pub const SCHEDULE_CALLER: &str = "schedule";
+// How often the scheduler looks at the clock, and so how late after its time a schedule may run
+const SCHEDULE_TICK: Duration = Duration::from_secs(30);
+// A clock that jumps further than this, e.g. after a suspend, runs none of the schedules it skipped
+const MAX_CATCH_UP: TimeDelta = TimeDelta::minutes(5);
+pub const MAX_AUTOMATION_BYTES: usize = 1024 * 1024;
+pub const MAX_TIMERS: usize = 20;
+// Problems listed in a refused import's error; the rest are only counted
+const LISTED_PROBLEMS: usize = 10;
+
+pub fn automation_path(log_file: &str) -> String {
+    format!("{}.automation.json", log_file)
+}
+
+// Timers are kept beside the imported automation, out of the document import_automation replaces
+fn timers_path(automation_file: &str) -> String {
+    format!("{}.timers.json", automation_file.trim_end_matches(".json"))
+}
+
+// Scenes and schedules loaded in one go, as import_automation takes them, with the fields of [[scenes]] and
+// [[schedules]] in the config
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutomationDocument {
+    pub scenes: Vec<SceneConfig>,
+    pub schedules: Vec<ScheduleConfig>,
+}
+
+impl AutomationDocument {
+    pub fn parse(document: &str) -> Result<Self, LightError> {
+        if document.len() > MAX_AUTOMATION_BYTES {
+            return Err(LightError::InvalidParameter(format!("Automation documents are limited to {} KiB", MAX_AUTOMATION_BYTES / 1024)));
+        }
+        serde_json::from_str(document).map_err(|e| LightError::InvalidParameter(format!("The automation document is not valid: {}", e)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    pub config: ScheduleConfig,
+    pub at: ScheduleTime,
+    pub imported: bool,
+}
+
+impl Schedule {
+    // The configuration must already have been validated
+    pub fn new(config: &ScheduleConfig, imported: bool) -> Self {
+        Self { config: config.clone(), at: config.when().unwrap_or(ScheduleTime::Daily(NaiveTime::default())), imported }
+    }
+
+    // Whether the schedule was due at some time after `since`, up to and including `now`
+    fn due(&self, since: NaiveDateTime, now: NaiveDateTime) -> bool {
+        if now <= since || now - since > MAX_CATCH_UP {
+            return false;
+        }
+        match &self.at {
+            ScheduleTime::Daily(time) => [since.date(), now.date()].into_iter().any(|day| {
+                let at = day.and_time(*time);
+                at > since && at <= now && self.config.runs_on(at.weekday())
+            }),
+            // Each minute it matches starts after `since` and no later than `now`
+            ScheduleTime::Cron(cron) => {
+                let minute = since.with_second(0).and_then(|since| since.with_nanosecond(0)).unwrap_or(since);
+                std::iter::successors(Some(minute + TimeDelta::minutes(1)), |minute| Some(*minute + TimeDelta::minutes(1)))
+                    .take_while(|minute| *minute <= now)
+                    .any(|minute| cron.matches(minute))
+            },
+        }
+    }
+
+    // "at 06:45 on mon, tue: turn the bulb on at 30%", or "at cron '*/30 18-22 * * fri': turn the bulb off"
+    pub fn describe(&self) -> String {
+        let days = match self.config.days.is_empty() {
+            true => "every day".to_string(),
+            false => format!("on {}", self.config.days.iter().map(|day| day.to_string().to_lowercase()).collect::<Vec<_>>().join(", ")),
+        };
+        let when = match &self.at {
+            ScheduleTime::Daily(time) => format!("at {} {}", time.format("%H:%M"), days),
+            ScheduleTime::Cron(cron) => format!("at cron '{}'", cron.expression()),
+        };
+        let action = match (&self.config.scene, self.config.power, self.config.brightness) {
+            (Some(scene), ..) => format!("apply scene '{}'", scene),
+            (None, RulePower::Off, _) => "turn the bulb off".to_string(),
+            (None, RulePower::On, Some(brightness)) => format!("turn the bulb on at {}%", brightness),
+            (None, RulePower::On, None) => "turn the bulb on".to_string(),
+        };
+        format!("{}: {}", when, action)
+    }
+}
+
+// The schedules the scheduler runs: the configured ones, with imported schedules replacing any of the same name.
+// Clones share the schedules, as Scenes clones share scenes.
+#[derive(Debug, Clone)]
+pub struct Schedules {
+    configured: Arc<Vec<ScheduleConfig>>,
+    schedules: Arc<RwLock<Vec<Schedule>>>,
+}
+
+impl Schedules {
+    pub fn new(configured: &[ScheduleConfig]) -> Self {
+        let schedules = Self::assemble(configured, &[]);
+        Self { configured: Arc::new(configured.to_vec()), schedules: Arc::new(RwLock::new(schedules)) }
+    }
+
+    fn assemble(configured: &[ScheduleConfig], imported: &[ScheduleConfig]) -> Vec<Schedule> {
+        let mut schedules: Vec<Schedule> = configured
+            .iter()
+            .filter(|schedule| !imported.iter().any(|other| other.name == schedule.name))
+            .map(|schedule| Schedule::new(schedule, false))
+            .collect();
+        schedules.extend(imported.iter().map(|schedule| Schedule::new(schedule, true)));
+        schedules
+    }
+
+    pub fn import(&self, imported: &[ScheduleConfig]) {
+        *self.schedules.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Self::assemble(&self.configured, imported);
+    }
+
+    pub fn all(&self) -> Vec<Schedule> {
+        self.schedules.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+// A one-off turning off of the bulb, as turn_off_after sets it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Timer {
+    pub id: String,
+    pub at: DateTime<Utc>,
+    pub set_by: Option<String>,
+}
+
+impl Timer {
+    // "timer-2: turn the bulb off at 2026-10-14T21:30:00Z"
+    pub fn describe(&self) -> String {
+        format!("{}: turn the bulb off at {}", self.id, self.at.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+    }
+}
+
+// The timers yet to fire, with the number of the last one set, so that no id is handed out twice, even after a
+// timer has fired or the server restarted
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct SavedTimers {
+    last_id: u64,
+    timers: Vec<Timer>,
+}
+
+impl SavedTimers {
+    // Files saved before the last id was kept only list the timers
+    fn parse(saved: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(saved).or_else(|e| match serde_json::from_str::<Vec<Timer>>(saved) {
+            Ok(timers) => {
+                let last_id = timers.iter().filter_map(|timer| timer.id.strip_prefix("timer-")?.parse().ok()).max().unwrap_or_default();
+                Ok(Self { last_id, timers })
+            },
+            Err(_) => Err(e),
+        })
+    }
+}
+
+// The timers yet to fire, kept in `file` when there is one so they outlive a restart. Clones share the timers,
+// and changes are saved one at a time, so the file always holds the latest of them.
+#[derive(Debug, Clone, Default)]
+pub struct Timers {
+    file: Option<String>,
+    timers: Arc<Mutex<SavedTimers>>,
+    saving: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Timers {
+    pub fn new(file: Option<String>) -> Self {
+        Self { file, ..Default::default() }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SavedTimers> {
+        self.timers.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn all(&self) -> Vec<Timer> {
+        self.lock().timers.clone()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Timer> {
+        self.lock().timers.iter().find(|timer| timer.id == id).cloned()
+    }
+
+    // Saves the timers `change` leaves before keeping them, returning what it returned
+    async fn update<T>(&self, change: impl FnOnce(&mut SavedTimers) -> Result<T, LightError>) -> Result<T, LightError> {
+        let _saving = self.saving.lock().await;
+        let mut timers = self.lock().clone();
+        let changed = change(&mut timers)?;
+        if let Some(file) = &self.file {
+            save(file, &timers, "timers").await.map_err(|e| LightError::ToolFailed(format!("The timers were left as they were: {:#}", e)))?;
+        }
+        *self.lock() = timers;
+        Ok(changed)
+    }
+
+    pub async fn add(&self, at: DateTime<Utc>, set_by: Option<String>) -> Result<Timer, LightError> {
+        self.update(|saved| {
+            if saved.timers.len() >= MAX_TIMERS {
+                return Err(LightError::InvalidParameter(format!("At most {} timers can be set; cancel_schedule cancels one", MAX_TIMERS)));
+            }
+            saved.last_id += 1;
+            let timer = Timer { id: format!("timer-{}", saved.last_id), at, set_by };
+            saved.timers.push(timer.clone());
+            Ok(timer)
+        })
+        .await
+    }
+
+    // The timer removed, if it was still set
+    pub async fn remove(&self, timer: &Timer) -> Result<bool, LightError> {
+        self.update(|saved| {
+            let before = saved.timers.len();
+            saved.timers.retain(|other| other != timer);
+            Ok(saved.timers.len() < before)
+        })
+        .await
+    }
+
+    pub fn load(&self) -> anyhow::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let saved = match std::fs::read_to_string(file) {
+            Ok(saved) => saved,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read timers: {}", file)),
+        };
+        *self.lock() = SavedTimers::parse(&saved).with_context(|| format!("Unreadable timers: {}", file))?;
+        Ok(())
+    }
+}
+
+// Every problem with the schedules of `configured` and `imported` together, against the scenes there will be
+pub fn schedule_problems(schedules: &[ScheduleConfig], scenes: &Scenes, imported_scenes: &[SceneConfig]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (index, schedule) in schedules.iter().enumerate() {
+        if let Err(e) = schedule.validate() {
+            problems.push(e.to_string());
+        }
+        if schedules[..index].iter().any(|other| other.name == schedule.name) {
+            problems.push(format!("Schedule '{}' is given twice", schedule.name));
+        }
+        if let Some(scene) = &schedule.scene
+            && !scenes.is_own(scene)
+            && !imported_scenes.iter().any(|imported| &imported.name == scene)
+        {
+            problems.push(format!("Schedule '{}' applies scene '{}', which does not exist", schedule.name, scene));
+        }
+    }
+    problems
+}
+
+// The scenes and schedules of the config, and those last imported, which are kept in `file` when there is one,
+// with the timers set beside it
+#[derive(Debug, Clone)]
+pub struct Automation {
+    pub scenes: Scenes,
+    pub schedules: Schedules,
+    pub timers: Timers,
+    file: Option<String>,
+    imported: Arc<Mutex<(AutomationDocument, Option<DateTime<Utc>>)>>,
+}
+
+impl Automation {
+    pub fn new(scenes: Scenes, schedules: Schedules, file: Option<String>) -> Self {
+        let timers = Timers::new(file.as_deref().map(timers_path));
+        Self { scenes, schedules, timers, file, imported: Arc::default() }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (AutomationDocument, Option<DateTime<Utc>>)> {
+        self.imported.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // The document imported last, empty when nothing has been
+    pub fn imported(&self) -> AutomationDocument {
+        self.lock().0.clone()
+    }
+
+    pub fn imported_at(&self) -> Option<DateTime<Utc>> {
+        self.lock().1
+    }
+
+    // Every problem with `document`, refusing it whole when there is any
+    pub fn check(&self, document: &AutomationDocument) -> Result<(), LightError> {
+        let mut problems = Vec::new();
+        for (index, scene) in document.scenes.iter().enumerate() {
+            if let Err(e) = scene.validate() {
+                problems.push(e.to_string());
+            }
+            if document.scenes[..index].iter().any(|other| other.name == scene.name) {
+                problems.push(format!("Scene '{}' is given twice", scene.name));
+            }
+        }
+        problems.extend(schedule_problems(&document.schedules, &self.scenes, &document.scenes));
+        if problems.is_empty() {
+            return Ok(());
+        }
+        let count = problems.len();
+        let mut listed: Vec<String> = problems.into_iter().take(LISTED_PROBLEMS).collect();
+        if count > LISTED_PROBLEMS {
+            listed.push(format!("and {} more", count - LISTED_PROBLEMS));
+        }
+        Err(LightError::InvalidParameter(format!("Nothing was imported: {}", listed.join("; "))))
+    }
+
+    // Replaces whatever was imported before with `document`: all of it, once it is saved, or none of it
+    pub async fn apply(&self, document: AutomationDocument, at: DateTime<Utc>) -> Result<(), LightError> {
+        self.check(&document)?;
+        if let Some(file) = &self.file {
+            save(file, &document, "imported automation").await.map_err(|e| LightError::ToolFailed(format!("Nothing was imported: {:#}", e)))?;
+        }
+        self.scenes.import(&document.scenes);
+        self.schedules.import(&document.schedules);
+        *self.lock() = (document, Some(at));
+        Ok(())
+    }
+
+    // Adds `schedule` to what was imported, replacing an imported schedule of the same name; true when it did
+    pub async fn set_schedule(&self, schedule: ScheduleConfig, at: DateTime<Utc>) -> Result<bool, LightError> {
+        let mut document = self.imported();
+        let replaced = document.schedules.iter().position(|other| other.name == schedule.name);
+        match replaced {
+            Some(index) => document.schedules[index] = schedule,
+            None => document.schedules.push(schedule),
+        }
+        self.apply(document, at).await?;
+        Ok(replaced.is_some())
+    }
+
+    // Removes an imported schedule; those of the config stay until the config drops them
+    pub async fn cancel_schedule(&self, name: &str, at: DateTime<Utc>) -> Result<(), LightError> {
+        let mut document = self.imported();
+        let before = document.schedules.len();
+        document.schedules.retain(|schedule| schedule.name != name);
+        if document.schedules.len() == before {
+            return Err(match self.schedules.configured.iter().any(|schedule| schedule.name == name) {
+                true => LightError::InvalidParameter(format!("Schedule '{}' is in the config file and can only be removed there", name)),
+                false => LightError::InvalidParameter(format!("There is no schedule or timer '{}'; list_schedules lists them", name)),
+            });
+        }
+        self.apply(document, at).await
+    }
+
+    // Loads the document saved by the last import, if it still fits the config; a saved document naming scenes
+    // the config no longer has is left on disk and not loaded
+    pub fn load(&self) -> anyhow::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let saved = match std::fs::read_to_string(file) {
+            Ok(saved) => saved,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read imported automation: {}", file)),
+        };
+        let document = AutomationDocument::parse(&saved).with_context(|| format!("Unreadable imported automation: {}", file))?;
+        self.check(&document).with_context(|| format!("The automation imported in {} no longer fits the config", file))?;
+        self.scenes.import(&document.scenes);
+        self.schedules.import(&document.schedules);
+        *self.lock() = (document, None);
+        Ok(())
+    }
+
+    // The imported automation and the timers, read back from their files as the next start would read them
+    pub fn state_files(&self) -> Vec<StateFileProbe> {
+        let Some(file) = &self.file else {
+            return Vec::new();
+        };
+        let automation = StateFileProbe::read("automation", file, |saved| AutomationDocument::parse(saved).map(drop).map_err(anyhow::Error::from));
+        let timers = StateFileProbe::read("timers", &timers_path(file), |saved| SavedTimers::parse(saved).map(drop).map_err(anyhow::Error::from));
+        vec![automation, timers]
+    }
+}
+
+// Written beside the file and renamed over it, so a crash mid-save leaves what was saved before whole
+async fn save(file: &str, value: &impl Serialize, what: &str) -> anyhow::Result<()> {
+    let saved = serde_json::to_string_pretty(value)?;
+    let temporary = format!("{}.tmp", file);
+    tokio::fs::write(&temporary, saved).await.with_context(|| format!("Failed to save {}: {}", what, temporary))?;
+    tokio::fs::rename(&temporary, file).await.with_context(|| format!("Failed to save {}: {}", what, file))
+}
+
+// Runs each schedule when the bulb's clock passes its time, as the user `schedule`. A schedule that fails, say
+// because the bulb is locked, is reported and waits for its next time.
+pub fn spawn_scheduler(automation: Automation, light: LightHandle) {
+    light.tasks().start_every(SCHEDULER_TASK, SCHEDULE_TICK);
+    tokio::spawn(async move {
+        run_scheduler(&automation, &light).await;
+        light.tasks().finish(SCHEDULER_TASK);
+    });
+}
+
+async fn run_scheduler(automation: &Automation, light: &LightHandle) {
+    let mut since = light.clock().local_now();
+    loop {
+        tokio::time::sleep(SCHEDULE_TICK).await;
+        let now = light.clock().local_now();
+        for schedule in automation.schedules.all().into_iter().filter(|schedule| schedule.due(since, now)) {
+            let Ok(snapshot) = light.snapshot().await else {
+                return;
+            };
+            let scheduler = light
+                .acting_as(SCHEDULE_CALLER)
+                .expecting(Some(snapshot.version))
+                .with_reason(Some(format!("schedule '{}'", schedule.config.name)));
+            let applied = match &schedule.config.scene {
+                Some(name) => match automation.scenes.get(name) {
+                    Ok(scene) => crate::scenes::apply(&scheduler.tagged(vec![scene.name.clone()]), &scene).await,
+                    Err(e) => Err(e),
+                },
+                None => crate::rules::apply(&scheduler, schedule.config.power, schedule.config.brightness).await,
+            };
+            match applied {
+                Ok(_) => crate::diagnostic!(Info, "the schedule '{}' ran {}", schedule.config.name, schedule.describe()),
+                Err(e) => crate::diagnostic!(Warn, "the schedule '{}' could not run: {}", schedule.config.name, e),
+            }
+        }
+        // Noticed even with no schedule due, so a session that has ended stops its scheduler
+        if light.snapshot().await.is_err() {
+            return;
+        }
+        light.tasks().beat(SCHEDULER_TASK);
+        since = now;
+    }
+}
+
+// Turns the bulb off once `timer` is due, as the user `schedule`, unless it has been cancelled by then. A timer
+// whose time passed while the server was down fires as soon as it starts again.
+pub fn spawn_timer(timers: Timers, light: LightHandle, timer: Timer) {
+    let wait = (timer.at - light.clock().now()).to_std().unwrap_or_default();
+    light.tasks().start_once(&timer.id, wait);
+    tokio::spawn(async move {
+        tokio::time::sleep(wait).await;
+        let removed = timers.remove(&timer).await;
+        light.tasks().finish(&timer.id);
+        match removed {
+            Ok(true) => {},
+            Ok(false) => return,
+            Err(e) => crate::diagnostic!(Warn, "the timer '{}' fired but could not be removed: {}", timer.id, e),
+        }
+        let set_by = timer.set_by.as_deref().map(|who| format!(" set by {}", who)).unwrap_or_default();
+        let timed = light.acting_as(SCHEDULE_CALLER).with_reason(Some(format!("timer '{}'{}", timer.id, set_by)));
+        match crate::rules::apply(&timed, RulePower::Off, None).await {
+            Ok(_) => crate::diagnostic!(Info, "the timer '{}' turned the bulb off", timer.id),
+            Err(e) => crate::diagnostic!(Warn, "the timer '{}' could not turn the bulb off: {}", timer.id, e),
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::clock::ManualClock;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+    use crate::state::StateMachine;
+
+    fn schedule(name: &str, at: &str, scene: Option<&str>) -> ScheduleConfig {
+        ScheduleConfig { name: name.to_string(), at: at.to_string(), days: Vec::new(), power: RulePower::On, brightness: None, scene: scene.map(String::from) }
+    }
+
+    fn local(at: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_a_schedule_is_due_once_its_time_passes() {
+        let mut weekend = Schedule::new(&schedule("late", "23:59", None), false);
+        assert!(weekend.due(local("2026-10-16 23:58:50"), local("2026-10-17 00:00:20")));
+        assert!(!weekend.due(local("2026-10-16 23:59:00"), local("2026-10-16 23:59:30")));
+        assert!(!weekend.due(local("2026-10-16 12:00:00"), local("2026-10-17 00:00:00")));
+        weekend.config.days = vec![chrono::Weekday::Sat];
+        // The Friday it passed on is no Saturday
+        assert!(!weekend.due(local("2026-10-16 23:58:50"), local("2026-10-17 00:00:20")));
+        assert!(weekend.due(local("2026-10-17 23:58:50"), local("2026-10-17 23:59:20")));
+        assert_eq!(weekend.describe(), "at 23:59 on sat: turn the bulb on");
+
+        let evenings = Schedule::new(&schedule("evenings", "*/30 18-22 * * fri", None), false);
+        assert!(evenings.due(local("2026-10-16 18:29:50"), local("2026-10-16 18:30:20")));
+        assert!(!evenings.due(local("2026-10-16 18:30:00"), local("2026-10-16 18:30:30")));
+        assert!(!evenings.due(local("2026-10-17 18:29:50"), local("2026-10-17 18:30:20")));
+        assert_eq!(evenings.describe(), "at cron '*/30 18-22 * * fri': turn the bulb on");
+        let mut listed = schedule("evenings", "*/30 18-22 * * fri", None);
+        listed.days = vec![chrono::Weekday::Sat];
+        assert!(listed.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_an_import_is_checked_whole_and_saved() {
+        let file = std::env::temp_dir().join(format!("lightbulb-{}.automation.json", std::process::id())).display().to_string();
+        let _ = std::fs::remove_file(&file);
+        let automation = Automation::new(Scenes::new(&[]), Schedules::new(&[schedule("evening", "19:00", Some("relax"))]), Some(file.clone()));
+        let document = AutomationDocument::parse(r#"{
+            "scenes": [{ "name": "party", "brightness": 100, "color": "magenta" }],
+            "schedules": [{ "name": "evening", "at": "20:00", "scene": "party" }, { "name": "night", "at": "23:30", "power": "off" }]
+        }"#).unwrap();
+
+        let broken = AutomationDocument { scenes: vec![document.scenes[0].clone(), document.scenes[0].clone()], schedules: vec![schedule("wake", "7:61", Some("disco"))] };
+        let error = automation.check(&broken).unwrap_err().to_string();
+        assert!(error.contains("Scene 'party' is given twice") && error.contains("is not HH:MM") && error.contains("scene 'disco', which does not exist"), "{}", error);
+        assert!(automation.apply(broken, Utc::now()).await.is_err() && !std::path::Path::new(&file).exists());
+
+        automation.apply(document.clone(), Utc::now()).await.unwrap();
+        let schedules: Vec<String> = automation.schedules.all().iter().map(Schedule::describe).collect();
+        assert_eq!(schedules, ["at 20:00 every day: apply scene 'party'", "at 23:30 every day: turn the bulb off"]);
+        assert!(automation.scenes.get("party").is_ok() && automation.imported_at().is_some());
+
+        // The next start loads what was imported
+        let restarted = Automation::new(Scenes::new(&[]), Schedules::new(&[]), Some(file.clone()));
+        restarted.load().unwrap();
+        assert_eq!((restarted.imported(), restarted.schedules.all().len()), (document, 2));
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timers_fire_once_and_outlive_a_restart() {
+        let file = std::env::temp_dir().join(format!("lightbulb-timers-{}.automation.json", std::process::id())).display().to_string();
+        let clock = ManualClock::new(DateTime::parse_from_rfc3339("2026-10-14T21:00:00Z").unwrap().with_timezone(&Utc));
+        let light = LightHandle::spawn(StateMachine::with_clock(Arc::new(clock.clone())), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        light.set_power(PowerState::On).await.unwrap();
+        let automation = Automation::new(Scenes::new(&[]), Schedules::new(&[]), Some(file.clone()));
+        let soon = automation.timers.add(light.clock().now() + TimeDelta::minutes(5), Some("agent".to_string())).await.unwrap();
+        let later = automation.timers.add(light.clock().now() + TimeDelta::hours(1), None).await.unwrap();
+        assert_eq!((soon.id.as_str(), later.id.as_str()), ("timer-1", "timer-2"));
+
+        let restarted = Automation::new(Scenes::new(&[]), Schedules::new(&[]), Some(file.clone()));
+        restarted.timers.load().unwrap();
+        assert_eq!(restarted.timers.all(), [soon.clone(), later.clone()]);
+        assert!(automation.timers.remove(&later).await.unwrap());
+        // The id of a timer that is gone is never handed out again, not even after a restart
+        let again = automation.timers.add(light.clock().now() + TimeDelta::hours(2), None).await.unwrap();
+        assert!(automation.timers.remove(&again).await.unwrap());
+        restarted.timers.load().unwrap();
+        let after_restart = restarted.timers.add(light.clock().now() + TimeDelta::hours(2), None).await.unwrap();
+        assert_eq!((again.id.as_str(), after_restart.id.as_str()), ("timer-3", "timer-4"));
+        assert!(restarted.timers.remove(&after_restart).await.unwrap());
+        let listed_only = serde_json::to_string(&[&soon, &later]).unwrap();
+        assert_eq!(SavedTimers::parse(&listed_only).unwrap().last_id, 2);
+        spawn_timer(automation.timers.clone(), light.clone(), later);
+        spawn_timer(automation.timers.clone(), light.clone(), soon);
+
+        tokio::time::sleep(Duration::from_secs(6 * 60)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::Off));
+        let log = light.read_log().await.unwrap();
+        assert!(log.contains("Lightbulb turned OFF by schedule (reason: timer 'timer-1' set by agent)"), "{}", log);
+        assert!(automation.timers.all().is_empty());
+        // The cancelled timer does nothing when its time comes
+        light.set_power(PowerState::On).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+        let _ = std::fs::remove_file(timers_path(&file));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_the_scheduler_runs_schedules_at_their_time() {
+        let clock = ManualClock::new(DateTime::parse_from_rfc3339("2026-10-14T06:59:50Z").unwrap().with_timezone(&Utc));
+        let light = LightHandle::spawn(StateMachine::with_clock(Arc::new(clock.clone())), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let automation = Automation::new(Scenes::new(&[]), Schedules::new(&[schedule("wake-up", "07:00", Some("reading"))]), None);
+        spawn_scheduler(automation, light.clone());
+
+        tokio::time::sleep(SCHEDULE_TICK / 2).await;
+        clock.advance(TimeDelta::seconds(20));
+        tokio::time::sleep(SCHEDULE_TICK).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+        let log = light.read_log().await.unwrap();
+        assert!(log.contains("Lightbulb turned ON [reading] by schedule (reason: schedule 'wake-up')"), "{}", log);
+        // Not again the next tick
+        clock.advance(TimeDelta::seconds(30));
+        tokio::time::sleep(SCHEDULE_TICK).await;
+        assert_eq!(light.read_log().await.unwrap(), log);
+    }
+}
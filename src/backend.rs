@@ -0,0 +1,567 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+use crate::brightness::BrightnessCurve;
+use crate::color::{Gamut, MiredRange};
+use crate::model::{Color, PowerState};
+use crate::registry::BackendOptions;
+
+pub const MAX_LATENCY_MS: u64 = 60_000;
+pub const MAX_UNREACHABLE: Duration = Duration::from_secs(24 * 60 * 60);
+// What a simulated bulb runs until update_firmware installs something else
+pub const FIRMWARE_VERSION: &str = "1.0.0";
+// Who changes made at the bulb itself are logged as being by
+pub const DEVICE_CALLER: &str = "device";
+pub const DEVICE_REPORT_BUFFER: usize = 16;
+
+// A change made at the bulb itself, with its own switch, remote or app, as a backend able to push reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceReport {
+    Power(PowerState),
+    Color(Color),
+    // As a percentage of how bright the bulb looks, the way set_brightness takes it
+    Brightness(u8),
+}
+
+// What a simulated bulb does when its power comes back after an outage, as `power_restore` in `[backend.options]`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerRestore {
+    #[default]
+    On,
+    Off,
+    // Back the way it was before the power went
+    Last,
+}
+
+impl PowerRestore {
+    pub fn label(self) -> &'static str {
+        match self {
+            PowerRestore::On => "on",
+            PowerRestore::Off => "off",
+            PowerRestore::Last => "last",
+        }
+    }
+
+    pub fn power(self, before: PowerState) -> PowerState {
+        match self {
+            PowerRestore::On => PowerState::On,
+            PowerRestore::Off => PowerState::Off,
+            PowerRestore::Last => before,
+        }
+    }
+}
+
+// Trait for the device actually driven by the service
+#[async_trait::async_trait]
+pub trait LightBackend {
+    fn name(&self) -> &str;
+    async fn set_power(&mut self, state: PowerState) -> anyhow::Result<()>;
+    async fn power(&self) -> anyhow::Result<PowerState>;
+
+    // Backends for bulbs with color support override both of these
+    fn supports_color(&self) -> bool {
+        false
+    }
+
+    async fn set_color(&mut self, _color: Color) -> anyhow::Result<()> {
+        anyhow::bail!("the {} backend does not support color", self.name())
+    }
+
+    // The colors a color bulb can show, if it cannot show all of sRGB; colors outside it are clamped first
+    fn gamut(&self) -> Option<Gamut> {
+        None
+    }
+
+    // The color temperatures a tunable white bulb can show; temperatures outside it are clamped first
+    fn mired_range(&self) -> Option<MiredRange> {
+        None
+    }
+
+    // Backends for dimmable bulbs override both of these; `level` is already gamma corrected, 0 to MAX_OUTPUT
+    fn supports_brightness(&self) -> bool {
+        false
+    }
+
+    async fn set_brightness(&mut self, _level: u8) -> anyhow::Result<()> {
+        anyhow::bail!("the {} backend does not support brightness", self.name())
+    }
+
+    // Backends that can simulate faults expose a handle for adjusting them at runtime
+    fn fault_injector(&self) -> Option<FaultInjector> {
+        None
+    }
+
+    // Backends whose bulb pushes changes made at the device (Hue's event stream, MQTT state topics, Yeelight
+    // notifications) hand over the stream once, when the light is spawned; the others are only read when asked.
+    // Reports of a change the server made itself are ignored, so a backend may pass on everything it hears.
+    fn device_reports(&mut self) -> Option<mpsc::Receiver<DeviceReport>> {
+        None
+    }
+}
+
+// What the bulb behind a backend can do, read once when the light is spawned, with the brightness curve the
+// server applies to it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BulbMetadata {
+    pub backend: String,
+    pub supports_color: bool,
+    pub supports_brightness: bool,
+    pub gamut: Option<Gamut>,
+    pub mired_range: Option<MiredRange>,
+    pub brightness: BrightnessCurve,
+}
+
+impl BulbMetadata {
+    pub fn of(backend: &dyn LightBackend) -> Self {
+        Self {
+            backend: backend.name().to_string(),
+            supports_color: backend.supports_color(),
+            supports_brightness: backend.supports_brightness(),
+            gamut: backend.gamut(),
+            mired_range: backend.mired_range(),
+            brightness: BrightnessCurve::default(),
+        }
+    }
+}
+
+// Faults applied to every simulated backend call
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaultConfig {
+    // Delay added before each call, in milliseconds
+    pub latency_ms: u64,
+    // Probability (0.0 to 1.0) that a call fails
+    pub failure_rate: f64,
+}
+
+impl FaultConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=1.0).contains(&self.failure_rate) {
+            anyhow::bail!("failure_rate must be between 0.0 and 1.0, got {}", self.failure_rate);
+        }
+        if self.latency_ms > MAX_LATENCY_MS {
+            anyhow::bail!("latency_ms must be at most {}, got {}", MAX_LATENCY_MS, self.latency_ms);
+        }
+        Ok(())
+    }
+}
+
+// Where a simulated firmware update has got to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirmwareStage {
+    Downloading,
+    Flashing,
+    Rebooting,
+}
+
+impl FirmwareStage {
+    pub fn label(self) -> &'static str {
+        match self {
+            FirmwareStage::Downloading => "downloading",
+            FirmwareStage::Flashing => "flashing",
+            FirmwareStage::Rebooting => "rebooting",
+        }
+    }
+
+    // The bulb answers nothing while it writes the new firmware and restarts
+    pub fn offline(self) -> bool {
+        self != FirmwareStage::Downloading
+    }
+}
+
+#[derive(Debug)]
+struct FaultState {
+    config: FaultConfig,
+    unreachable_until: Option<Instant>,
+    firmware: String,
+    update: Option<FirmwareStage>,
+    power: PowerState,
+    color: Option<Color>,
+    level: Option<u8>,
+    restore: PowerRestore,
+    // The power before the outage under way, if there is one
+    outage: Option<PowerState>,
+    // Where changes made at the device are pushed, once the server listens for them
+    reports: Option<mpsc::Sender<DeviceReport>>,
+}
+
+impl Default for FaultState {
+    fn default() -> Self {
+        Self {
+            config: FaultConfig::default(),
+            unreachable_until: None,
+            firmware: FIRMWARE_VERSION.to_string(),
+            update: None,
+            power: PowerState::Off,
+            color: None,
+            level: None,
+            restore: PowerRestore::default(),
+            outage: None,
+            reports: None,
+        }
+    }
+}
+
+// Shared, runtime-adjustable fault settings for a simulated backend, and the simulated bulb itself
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjector {
+    state: Arc<Mutex<FaultState>>,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FaultState { config, ..FaultState::default() })),
+        }
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        self.lock().config.clone()
+    }
+
+    pub fn set_config(&self, config: FaultConfig) -> anyhow::Result<()> {
+        config.validate()?;
+        self.lock().config = config;
+        Ok(())
+    }
+
+    // Makes every call fail with "unreachable" for the given duration, at most MAX_UNREACHABLE
+    pub fn set_unreachable_for(&self, duration: Duration) {
+        self.lock().unreachable_until = Some(Instant::now() + duration.min(MAX_UNREACHABLE));
+    }
+
+    // Remaining time the bulb is simulated as unreachable, if any
+    pub fn unreachable_remaining(&self) -> Option<Duration> {
+        let until = self.lock().unreachable_until?;
+        until.checked_duration_since(Instant::now()).filter(|remaining| !remaining.is_zero())
+    }
+
+    pub fn firmware(&self) -> String {
+        self.lock().firmware.clone()
+    }
+
+    // The brightness level the simulated bulb was last sent, after gamma
+    pub fn level(&self) -> Option<u8> {
+        self.lock().level
+    }
+
+    pub fn power(&self) -> PowerState {
+        self.lock().power
+    }
+
+    pub fn power_restore(&self) -> PowerRestore {
+        self.lock().restore
+    }
+
+    // Cuts the bulb's power: it goes dark and answers nothing for `duration`, until end_outage. Returns how long
+    // the outage already under way has left, if there is one.
+    pub fn begin_outage(&self, duration: Duration) -> Result<(), Duration> {
+        let mut state = self.lock();
+        if state.outage.is_some() {
+            let left = state.unreachable_until.and_then(|until| until.checked_duration_since(Instant::now()));
+            return Err(left.unwrap_or_default());
+        }
+        state.outage = Some(state.power);
+        state.power = PowerState::Off;
+        state.unreachable_until = Some(Instant::now() + duration.min(MAX_UNREACHABLE));
+        Ok(())
+    }
+
+    // Brings the power back: the bulb comes back as its power_restore behavior has it and pushes its power to the
+    // server, which returns
+    pub fn end_outage(&self) -> PowerState {
+        let mut state = self.lock();
+        let before = state.outage.take().unwrap_or(state.power);
+        state.power = state.restore.power(before);
+        state.unreachable_until = None;
+        if let Some(reports) = &state.reports {
+            let _ = reports.try_send(DeviceReport::Power(state.power));
+        }
+        state.power
+    }
+
+    pub fn firmware_update(&self) -> Option<FirmwareStage> {
+        self.lock().update
+    }
+
+    // Starts an update at the download stage, or returns the stage of the one already running
+    pub fn begin_firmware_update(&self) -> Result<(), FirmwareStage> {
+        let mut state = self.lock();
+        match state.update {
+            Some(stage) => Err(stage),
+            None => {
+                state.update = Some(FirmwareStage::Downloading);
+                Ok(())
+            },
+        }
+    }
+
+    pub fn set_firmware_stage(&self, stage: FirmwareStage) {
+        self.lock().update = Some(stage);
+    }
+
+    // Ends the update, running `installed` from now on if it got that far
+    pub fn finish_firmware_update(&self, installed: Option<&str>) {
+        let mut state = self.lock();
+        state.update = None;
+        if let Some(installed) = installed {
+            state.firmware = installed.to_string();
+        }
+    }
+
+    // Simulates someone using the bulb's own switch or app: the bulb changes and pushes the change to the server,
+    // unless it is offline, when the server only finds out by reading it. True if the change was pushed.
+    pub fn change_at_device(&self, report: DeviceReport) -> bool {
+        let mut state = self.lock();
+        match report {
+            DeviceReport::Power(power) => state.power = power,
+            DeviceReport::Color(color) => state.color = Some(color),
+            DeviceReport::Brightness(percent) => state.level = Some(BrightnessCurve::default().output(percent)),
+        }
+        let offline = state.unreachable_until.is_some_and(|until| until > Instant::now()) || state.update.is_some_and(FirmwareStage::offline);
+        match &state.reports {
+            Some(reports) if !offline => reports.try_send(report).is_ok(),
+            _ => false,
+        }
+    }
+
+    // Clears injected faults; a firmware update carries on regardless
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.config = FaultConfig::default();
+        state.unreachable_until = None;
+    }
+
+    async fn inject(&self) -> anyhow::Result<()> {
+        let config = self.config();
+        if config.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(config.latency_ms)).await;
+        }
+        if self.unreachable_remaining().is_some() {
+            anyhow::bail!("simulated bulb is unreachable");
+        }
+        if let Some(stage) = self.firmware_update().filter(|stage| stage.offline()) {
+            anyhow::bail!("simulated bulb is {} a firmware update", stage.label());
+        }
+        if config.failure_rate > 0.0 && rand::random::<f64>() < config.failure_rate {
+            anyhow::bail!("simulated random failure");
+        }
+        Ok(())
+    }
+
+    // The state only holds plain data, so a panic elsewhere cannot leave it inconsistent
+    fn lock(&self) -> std::sync::MutexGuard<'_, FaultState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Simulated bulb that only keeps its state in memory, shared with its fault injector
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedBackend {
+    gamut: Option<Gamut>,
+    mired_range: Option<MiredRange>,
+    faults: FaultInjector,
+}
+
+impl SimulatedBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_faults(config: FaultConfig) -> Self {
+        Self { gamut: None, mired_range: None, faults: FaultInjector::new(config) }
+    }
+
+    pub fn with_gamut(mut self, gamut: Gamut) -> Self {
+        self.gamut = Some(gamut);
+        self
+    }
+
+    pub fn with_mired_range(mut self, range: MiredRange) -> Self {
+        self.mired_range = Some(range);
+        self
+    }
+
+    pub fn with_power_restore(self, restore: PowerRestore) -> Self {
+        self.faults.lock().restore = restore;
+        self
+    }
+
+    // Builds a simulated backend from `[backend.options]`, e.g.
+    // { latency_ms = 200, failure_rate = 0.1, gamut = "C", min_mireds = 153, max_mireds = 500, power_restore = "last" }
+    pub fn from_options(options: &BackendOptions) -> anyhow::Result<Self> {
+        let mut options = options.clone();
+        let gamut = options.remove("gamut").map(|gamut| Gamut::from_value(&gamut)).transpose()?;
+        let mired_range = match (options.remove("min_mireds"), options.remove("max_mireds")) {
+            (None, None) => None,
+            (Some(min), Some(max)) => {
+                let mireds = |value: serde_json::Value| -> anyhow::Result<u32> {
+                    serde_json::from_value(value).map_err(|e| anyhow::anyhow!("Invalid simulated backend options: mireds {}", e))
+                };
+                Some(MiredRange::new(mireds(min)?, mireds(max)?)?)
+            },
+            _ => anyhow::bail!("Invalid simulated backend options: min_mireds and max_mireds go together"),
+        };
+        let restore = options
+            .remove("power_restore")
+            .map(|restore| serde_json::from_value::<PowerRestore>(restore).map_err(|e| anyhow::anyhow!("Invalid simulated backend options: power_restore {}", e)))
+            .transpose()?;
+        let config: FaultConfig = serde_json::from_value(serde_json::Value::Object(options))
+            .map_err(|e| anyhow::anyhow!("Invalid simulated backend options: {}", e))?;
+        config.validate()?;
+        Ok(Self { gamut, mired_range, ..Self::with_faults(config) }.with_power_restore(restore.unwrap_or_default()))
+    }
+}
+
+#[async_trait::async_trait]
+impl LightBackend for SimulatedBackend {
+    fn name(&self) -> &str {
+        "simulated"
+    }
+
+    async fn set_power(&mut self, state: PowerState) -> anyhow::Result<()> {
+        self.faults.inject().await?;
+        self.faults.lock().power = state;
+        Ok(())
+    }
+
+    async fn power(&self) -> anyhow::Result<PowerState> {
+        self.faults.inject().await?;
+        Ok(self.faults.lock().power)
+    }
+
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    async fn set_color(&mut self, color: Color) -> anyhow::Result<()> {
+        self.faults.inject().await?;
+        self.faults.lock().color = Some(color);
+        Ok(())
+    }
+
+    fn gamut(&self) -> Option<Gamut> {
+        self.gamut
+    }
+
+    fn mired_range(&self) -> Option<MiredRange> {
+        self.mired_range
+    }
+
+    fn supports_brightness(&self) -> bool {
+        true
+    }
+
+    async fn set_brightness(&mut self, level: u8) -> anyhow::Result<()> {
+        self.faults.inject().await?;
+        self.faults.lock().level = Some(level);
+        Ok(())
+    }
+
+    fn fault_injector(&self) -> Option<FaultInjector> {
+        Some(self.faults.clone())
+    }
+
+    fn device_reports(&mut self) -> Option<mpsc::Receiver<DeviceReport>> {
+        let (reports, receiver) = mpsc::channel(DEVICE_REPORT_BUFFER);
+        self.faults.lock().reports = Some(reports);
+        Some(receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_failure_rate_one_always_fails() {
+        let mut backend = SimulatedBackend::with_faults(FaultConfig { failure_rate: 1.0, ..Default::default() });
+        assert!(backend.set_power(PowerState::On).await.is_err());
+
+        backend.fault_injector().unwrap().clear();
+        assert!(backend.set_power(PowerState::On).await.is_ok());
+        assert_eq!(backend.power().await.unwrap(), PowerState::On);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unreachable_period_expires() {
+        let mut backend = SimulatedBackend::new();
+        let faults = backend.fault_injector().unwrap();
+        faults.set_unreachable_for(Duration::from_secs(30));
+        assert!(backend.set_power(PowerState::On).await.is_err());
+
+        tokio::time::advance(Duration::from_secs(31)).await;
+        assert!(faults.unreachable_remaining().is_none());
+        assert!(backend.set_power(PowerState::On).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bulb_is_offline_while_firmware_is_written() {
+        let mut backend = SimulatedBackend::new();
+        let faults = backend.fault_injector().unwrap();
+        assert_eq!(faults.begin_firmware_update(), Ok(()));
+        assert_eq!(faults.begin_firmware_update(), Err(FirmwareStage::Downloading));
+        assert!(backend.set_power(PowerState::On).await.is_ok());
+
+        faults.set_firmware_stage(FirmwareStage::Flashing);
+        let error = backend.set_power(PowerState::Off).await.unwrap_err();
+        assert_eq!(error.to_string(), "simulated bulb is flashing a firmware update");
+        faults.finish_firmware_update(Some("1.1.0"));
+        assert_eq!(faults.firmware(), "1.1.0");
+        assert!(backend.set_power(PowerState::Off).await.is_ok());
+    }
+
+    #[test]
+    fn test_options_are_validated() {
+        let mut options = BackendOptions::new();
+        options.insert("failure_rate".to_string(), serde_json::json!(1.5));
+        assert!(SimulatedBackend::from_options(&options).is_err());
+
+        options.insert("failure_rate".to_string(), serde_json::json!(0.25));
+        options.insert("latency_ms".to_string(), serde_json::json!(MAX_LATENCY_MS + 1));
+        assert!(SimulatedBackend::from_options(&options).is_err());
+
+        options.insert("latency_ms".to_string(), serde_json::json!(50));
+        let backend = SimulatedBackend::from_options(&options).unwrap();
+        assert_eq!(backend.fault_injector().unwrap().config(), FaultConfig { latency_ms: 50, failure_rate: 0.25 });
+        assert_eq!(backend.gamut(), None);
+
+        options.insert("gamut".to_string(), serde_json::json!("B"));
+        assert_eq!(SimulatedBackend::from_options(&options).unwrap().gamut(), Some(crate::color::GAMUT_B));
+        options.insert("gamut".to_string(), serde_json::json!("Z"));
+        assert!(SimulatedBackend::from_options(&options).is_err());
+
+        options.insert("gamut".to_string(), serde_json::json!("C"));
+        options.insert("min_mireds".to_string(), serde_json::json!(153));
+        assert!(SimulatedBackend::from_options(&options).is_err());
+        options.insert("max_mireds".to_string(), serde_json::json!(500));
+        assert_eq!(SimulatedBackend::from_options(&options).unwrap().mired_range(), Some(MiredRange::new(153, 500).unwrap()));
+
+        options.insert("power_restore".to_string(), serde_json::json!("last"));
+        assert_eq!(SimulatedBackend::from_options(&options).unwrap().fault_injector().unwrap().power_restore(), PowerRestore::Last);
+        options.insert("power_restore".to_string(), serde_json::json!("sometimes"));
+        assert!(SimulatedBackend::from_options(&options).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_outage_restores_the_power_it_was_told_to() {
+        let mut backend = SimulatedBackend::new().with_power_restore(PowerRestore::Last);
+        let faults = backend.fault_injector().unwrap();
+        backend.set_power(PowerState::On).await.unwrap();
+        assert_eq!(faults.begin_outage(Duration::from_secs(30)), Ok(()));
+        assert!(backend.power().await.is_err());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(faults.begin_outage(Duration::from_secs(30)), Err(Duration::from_secs(20)));
+
+        assert_eq!(faults.end_outage(), PowerState::On);
+        assert_eq!(backend.power().await.unwrap(), PowerState::On);
+        let faults = SimulatedBackend::new().with_power_restore(PowerRestore::Off).fault_injector().unwrap();
+        faults.begin_outage(Duration::from_secs(30)).unwrap();
+        assert_eq!(faults.end_outage(), PowerState::Off);
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use lightbulb_mcp::reply;
+use rmcp::ServiceExt;
+use rmcp::model::{CallToolRequestParam, ClientInfo, Implementation, ReadResourceRequestParam, ResourceContents};
+use rmcp::service::ServiceError;
+use rmcp::transport::TokioChildProcess;
+use tokio::process::Command;
+
+const SERVER_BINARY: &str = "lightbulb-mcp";
+const USAGE: &str = "\
+Usage: lightbulb-cli [--server <path>] <command>...
+
+Starts the lightbulb MCP server and runs each command against it, in order, in one session.
+
+Commands:
+  status    Show the current state
+  on        Turn the lightbulb on
+  off       Turn the lightbulb off
+  log       Print the activity log
+  summary   Print the usage summary";
+
+enum Request {
+    Tool(&'static str),
+    Resource(&'static str),
+}
+
+fn parse_command(command: &str) -> Option<Request> {
+    let request = match command {
+        "status" => Request::Tool("get_lightbulb_status"),
+        "on" => Request::Tool("turn_on_lightbulb"),
+        "off" => Request::Tool("turn_off_lightbulb"),
+        "log" => Request::Resource("lightbulb://log.txt"),
+        "summary" => Request::Resource("lightbulb://summary"),
+        _ => return None,
+    };
+    Some(request)
+}
+
+// The server installed next to this binary, falling back to PATH
+fn default_server() -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join(SERVER_BINARY)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(SERVER_BINARY))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1).peekable();
+    let server = match args.peek().map(String::as_str) {
+        Some("--server") => {
+            args.next();
+            match args.next() {
+                Some(path) => PathBuf::from(path),
+                None => return usage_error("--server needs a path"),
+            }
+        },
+        _ => default_server(),
+    };
+    let commands: Vec<String> = args.collect();
+    if commands.is_empty() {
+        return usage_error("no command given");
+    }
+    let mut requests = Vec::new();
+    for command in &commands {
+        match parse_command(command) {
+            Some(request) => requests.push(request),
+            None => return usage_error(&format!("unknown command '{}'", command)),
+        }
+    }
+
+    match run(server, requests).await {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("lightbulb-cli: {:#}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("lightbulb-cli: {}\n\n{}", message, USAGE);
+    ExitCode::from(2)
+}
+
+// Stops at the first failing command, exiting non-zero
+async fn run(server: PathBuf, requests: Vec<Request>) -> anyhow::Result<ExitCode> {
+    let transport = TokioChildProcess::new(Command::new(&server))
+        .map_err(|e| anyhow::anyhow!("failed to start {}: {}", server.display(), e))?;
+    // The server attributes the changes we make to this name
+    let info = ClientInfo {
+        client_info: Implementation { name: "lightbulb-cli".to_string(), version: env!("CARGO_PKG_VERSION").to_string() },
+        ..Default::default()
+    };
+    let client = info.serve(transport).await?;
+
+    let mut status = ExitCode::SUCCESS;
+    for request in requests {
+        let result = match request {
+            // A failed tool answers with an isError result, reported like any other error
+            Request::Tool(name) => client.call_tool(CallToolRequestParam { name: name.into(), arguments: None }).await.and_then(|result| {
+                match reply::error_of(&result) {
+                    Some(error) => Err(ServiceError::McpError(error)),
+                    None => Ok(vec![reply::text_of(&result)]),
+                }
+            }),
+            Request::Resource(uri) => client.read_resource(ReadResourceRequestParam { uri: uri.to_string() }).await.map(|result| {
+                result
+                    .contents
+                    .into_iter()
+                    .filter_map(|contents| match contents {
+                        ResourceContents::TextResourceContents { text, .. } => Some(text),
+                        ResourceContents::BlobResourceContents { .. } => None,
+                    })
+                    .collect()
+            }),
+        };
+        match result {
+            Ok(texts) => texts.iter().for_each(|text| println!("{}", text.trim_end())),
+            Err(ServiceError::McpError(error)) => {
+                let code = error.data.as_ref().and_then(|data| data.get("code")).and_then(|code| code.as_str()).unwrap_or("ERROR");
+                eprintln!("{}: {}", code, error.message);
+                status = ExitCode::FAILURE;
+                break;
+            },
+            Err(e) => return Err(e.into()),
+        }
+    }
+    client.cancel().await?;
+    Ok(status)
+}
@@ -0,0 +1,105 @@
+use chrono::NaiveTime;
+use serde::Serialize;
+
+use crate::config::BrightnessPointConfig;
+
+// About what LEDs need for 50% to look half as bright as 100%
+pub const DEFAULT_GAMMA: f64 = 2.2;
+pub const MIN_GAMMA: f64 = 0.1;
+pub const MAX_GAMMA: f64 = 5.0;
+// Backends take brightness as a level from 0 to this, as Zigbee and Hue do
+pub const MAX_OUTPUT: u8 = 255;
+
+// Maps the brightness clients ask for, a percentage of how bright the bulb looks, to the level sent to the
+// backend. LED output is linear in the level while the eye is not, so the level is the percentage raised to
+// `gamma`; a gamma of 1.0 sends the percentage unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct BrightnessCurve {
+    pub gamma: f64,
+}
+
+impl BrightnessCurve {
+    pub fn new(gamma: f64) -> anyhow::Result<Self> {
+        if !(MIN_GAMMA..=MAX_GAMMA).contains(&gamma) {
+            anyhow::bail!("gamma must be between {} and {}, got {}", MIN_GAMMA, MAX_GAMMA, gamma);
+        }
+        Ok(Self { gamma })
+    }
+
+    // Never 0 for a bulb asked to be lit, however dim
+    pub fn output(&self, percent: u8) -> u8 {
+        if percent == 0 {
+            return 0;
+        }
+        let level = (f64::from(percent.min(100)) / 100.0).powf(self.gamma) * f64::from(MAX_OUTPUT);
+        (level.round() as u8).max(1)
+    }
+}
+
+impl Default for BrightnessCurve {
+    fn default() -> Self {
+        Self { gamma: DEFAULT_GAMMA }
+    }
+}
+
+// The most brightness a command gets at each time of day, moving in a straight line from one point to the next
+// and from the last point round past midnight to the first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BrightnessSchedule {
+    points: Vec<(NaiveTime, u8)>,
+}
+
+impl BrightnessSchedule {
+    pub fn new(configs: &[BrightnessPointConfig]) -> anyhow::Result<Self> {
+        let mut points = Vec::new();
+        for config in configs {
+            config.validate()?;
+            let at = config.time()?;
+            if points.iter().any(|(other, _)| *other == at) {
+                anyhow::bail!("Brightness schedule time {} is given twice", config.at);
+            }
+            points.push((at, config.max));
+        }
+        points.sort();
+        Ok(Self { points })
+    }
+
+    // None when there are no points, so nothing is capped
+    pub fn cap(&self, now: NaiveTime) -> Option<u8> {
+        let after = self.points.iter().rposition(|(at, _)| *at <= now).unwrap_or(self.points.len().checked_sub(1)?);
+        let (from, from_max) = self.points[after];
+        let (to, to_max) = self.points[(after + 1) % self.points.len()];
+        let seconds = |from: NaiveTime, to: NaiveTime| (to - from).num_seconds().rem_euclid(86_400) as f64;
+        let span = seconds(from, to);
+        if span == 0.0 {
+            return Some(from_max);
+        }
+        let progress = seconds(from, now) / span;
+        Some((f64::from(from_max) + (f64::from(to_max) - f64::from(from_max)) * progress).round() as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gamma_maps_percentages_to_levels() {
+        let curve = BrightnessCurve::default();
+        assert_eq!([0, 1, 50, 100].map(|percent| curve.output(percent)), [0, 1, 55, 255]);
+        let linear = BrightnessCurve::new(1.0).unwrap();
+        assert_eq!([1, 50, 100].map(|percent| linear.output(percent)), [3, 128, 255]);
+        assert!(BrightnessCurve::new(0.0).is_err() && BrightnessCurve::new(6.0).is_err());
+    }
+
+    #[test]
+    fn test_schedule_caps_brightness_by_the_time_of_day() {
+        let time = |at: &str| NaiveTime::parse_from_str(at, "%H:%M").unwrap();
+        let point = |at: &str, max| BrightnessPointConfig { at: at.to_string(), max };
+        let schedule = BrightnessSchedule::new(&[point("23:00", 20), point("7:00", 100), point("22:00", 100)]).unwrap();
+        assert_eq!(["12:00", "22:00", "22:30", "23:00", "03:00", "07:00"].map(|at| schedule.cap(time(at))), [100, 100, 60, 20, 60, 100].map(Some));
+        assert_eq!(BrightnessSchedule::new(&[point("22:00", 40)]).unwrap().cap(time("08:00")), Some(40));
+        assert!(BrightnessSchedule::new(&[point("22:00", 40), point("22:00", 20)]).is_err());
+        assert_eq!(BrightnessSchedule::default().cap(time("08:00")), None);
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::actor::LightHandle;
+use crate::backend::LightBackend;
+use crate::config::{BulbConfig, MAIN_BULB, TagRuleConfig, validate_bulb_names};
+use crate::error::LightError;
+use crate::events::LightEvent;
+use crate::tags::TagRule;
+
+pub const MAX_BULBS: usize = 32;
+// Changes to the added bulbs a subscriber may fall behind by before it misses some
+const BULB_EVENTS: usize = 64;
+
+// Where the log of a bulb added beside the server's own is kept
+pub fn bulb_log_path(log_file: &str, id: &str) -> String {
+    format!("{}.bulb-{}", log_file, id)
+}
+
+// Spawns the actor of an added bulb from its id, its backend and the rule tagging its power changes with the id
+pub type BulbActors = Arc<dyn Fn(&str, Box<dyn LightBackend + Send + Sync>, TagRule) -> LightHandle + Send + Sync>;
+
+#[derive(Clone)]
+pub struct Bulb {
+    pub id: String,
+    pub room: Option<String>,
+    pub name: Option<String>,
+    pub aliases: Vec<String>,
+    pub light: LightHandle,
+}
+
+impl Bulb {
+    // Whether `key` is the bulb's name or one of its aliases, ignoring case
+    fn answers_to(&self, key: &str) -> bool {
+        let key = key.to_lowercase();
+        self.name.iter().chain(&self.aliases).any(|name| name.to_lowercase() == key)
+    }
+
+    // The id, followed by the name when the bulb has one: "desk (\"Desk Lamp\")"
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} (\"{}\")", self.id, name),
+            None => self.id.clone(),
+        }
+    }
+}
+
+// The server's own bulb and those added beside it, by id. Clones share the bulbs, so one added in one session
+// is seen by every other.
+#[derive(Clone)]
+pub struct Bulbs {
+    bulbs: Arc<RwLock<BTreeMap<String, Bulb>>>,
+    actors: BulbActors,
+    events: broadcast::Sender<(String, LightEvent)>,
+}
+
+impl Bulbs {
+    pub fn new(main: LightHandle, room: Option<String>, actors: BulbActors) -> Self {
+        let main = Bulb { id: MAIN_BULB.to_string(), room, name: None, aliases: Vec::new(), light: main };
+        Self {
+            bulbs: Arc::new(RwLock::new(BTreeMap::from([(MAIN_BULB.to_string(), main)]))),
+            actors,
+            events: broadcast::channel(BULB_EVENTS).0,
+        }
+    }
+
+    // Every change to the bulbs added beside the server's own from here on, each with the id of the bulb it was to;
+    // the server's own bulb's changes are its handle's to subscribe to
+    pub fn subscribe(&self) -> broadcast::Receiver<(String, LightEvent)> {
+        self.events.subscribe()
+    }
+
+    // The bulb with `key` as its id, name or one of its aliases
+    pub fn get(&self, key: &str) -> Result<Bulb, LightError> {
+        let bulbs = self.read();
+        let bulb = bulbs.get(key).or_else(|| bulbs.values().find(|bulb| bulb.answers_to(key)));
+        bulb.cloned().ok_or_else(|| LightError::UnknownBulb(key.to_string()))
+    }
+
+    // Every bulb, by id
+    pub fn all(&self) -> Vec<Bulb> {
+        self.read().values().cloned().collect()
+    }
+
+    pub fn in_room(&self, room: &str) -> Vec<Bulb> {
+        self.read().values().filter(|bulb| bulb.room.as_deref() == Some(room)).cloned().collect()
+    }
+
+    // Checks that the bulb could be added, adding nothing
+    pub fn check(&self, config: &BulbConfig) -> Result<(), LightError> {
+        config.validate().map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        Self::check_fits(&self.read(), config)
+    }
+
+    // Spawns the bulb's own actor, logging to a log of its own in which every power change is tagged with the
+    // bulb's id
+    pub fn add(&self, config: &BulbConfig, backend: Box<dyn LightBackend + Send + Sync>) -> Result<Bulb, LightError> {
+        config.validate().map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        let mut bulbs = self.bulbs.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Self::check_fits(&bulbs, config)?;
+        let tag = TagRuleConfig { tag: config.id.clone(), after: None, before: None, to: None, by: None };
+        let tag = TagRule::new(tag).map_err(|e| LightError::Internal(e.to_string()))?;
+        let light = (self.actors)(&config.id, backend, tag);
+        let bulb = Bulb { id: config.id.clone(), room: config.room.clone(), name: config.name.clone(), aliases: config.aliases.clone(), light };
+        Self::forward_events(&bulb, self.events.clone());
+        bulbs.insert(bulb.id.clone(), bulb.clone());
+        Ok(bulb)
+    }
+
+    // Passes the bulb's changes on to `events`, tagged with its id, for as long as its actor runs
+    fn forward_events(bulb: &Bulb, events: broadcast::Sender<(String, LightEvent)>) {
+        let (id, mut changes) = (bulb.id.clone(), bulb.light.subscribe());
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    // Having no subscribers is not an error
+                    Ok(event) => drop(events.send((id.clone(), event))),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    // The bulb `key` names as it would be with `name` and `aliases` in place of its own, renaming nothing
+    pub fn check_names(&self, key: &str, name: Option<&str>, aliases: &[String]) -> Result<Bulb, LightError> {
+        let id = self.get(key)?.id;
+        Self::named(&self.read(), &id, name, aliases)
+    }
+
+    // Gives the bulb `key` names a new name and aliases, both replacing what it had
+    pub fn set_names(&self, key: &str, name: Option<&str>, aliases: &[String]) -> Result<Bulb, LightError> {
+        let id = self.get(key)?.id;
+        let mut bulbs = self.bulbs.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let bulb = Self::named(&bulbs, &id, name, aliases)?;
+        bulbs.insert(id, bulb.clone());
+        Ok(bulb)
+    }
+
+    fn named(bulbs: &BTreeMap<String, Bulb>, id: &str, name: Option<&str>, aliases: &[String]) -> Result<Bulb, LightError> {
+        validate_bulb_names(name, aliases).map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        let mut bulb = bulbs.get(id).cloned().ok_or_else(|| LightError::UnknownBulb(id.to_string()))?;
+        for taken in name.into_iter().chain(aliases.iter().map(String::as_str)) {
+            if let Some(other) = bulbs.values().find(|other| other.id != id && (other.id == taken.to_lowercase() || other.answers_to(taken))) {
+                return Err(LightError::InvalidParameter(format!("'{}' already names lightbulb '{}'", taken, other.id)));
+            }
+        }
+        bulb.name = name.map(str::to_string);
+        bulb.aliases = aliases.to_vec();
+        Ok(bulb)
+    }
+
+    fn check_fits(bulbs: &BTreeMap<String, Bulb>, config: &BulbConfig) -> Result<(), LightError> {
+        if bulbs.contains_key(&config.id) {
+            return Err(LightError::InvalidParameter(format!("a lightbulb called '{}' already exists", config.id)));
+        }
+        if bulbs.len() >= MAX_BULBS {
+            return Err(LightError::InvalidParameter(format!("at most {} lightbulbs can be kept", MAX_BULBS)));
+        }
+        let names = std::iter::once(config.id.as_str()).chain(config.name.as_deref()).chain(config.aliases.iter().map(String::as_str));
+        for taken in names {
+            if let Some(other) = bulbs.values().find(|other| other.id == taken.to_lowercase() || other.answers_to(taken)) {
+                return Err(LightError::InvalidParameter(format!("'{}' already names lightbulb '{}'", taken, other.id)));
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, Bulb>> {
+        self.bulbs.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::config::BackendConfig;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+    use crate::state::StateMachine;
+
+    fn plain_actors() -> BulbActors {
+        Arc::new(|_: &str, backend, tag| LightHandle::spawn_with_tag_rules(StateMachine::new(), backend, Box::new(InMemoryLogger::new()), vec![tag]))
+    }
+
+    fn config(id: &str, room: Option<&str>) -> BulbConfig {
+        BulbConfig { id: id.to_string(), room: room.map(str::to_string), name: None, aliases: Vec::new(), backend: BackendConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn test_added_bulbs_keep_logs_of_their_own() {
+        let main = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let bulbs = Bulbs::new(main, Some("living-room".to_string()), plain_actors());
+        let lamp = bulbs.add(&config("lamp", Some("living-room")), Box::new(SimulatedBackend::new())).unwrap();
+        bulbs.add(&config("kitchen", None), Box::new(SimulatedBackend::new())).unwrap();
+
+        let ids = |bulbs: Vec<Bulb>| bulbs.into_iter().map(|bulb| bulb.id).collect::<Vec<_>>();
+        assert_eq!(ids(bulbs.all()), ["kitchen", "lamp", "main"]);
+        assert_eq!(ids(bulbs.in_room("living-room")), ["lamp", "main"]);
+        assert!(bulbs.add(&config("lamp", None), Box::new(SimulatedBackend::new())).is_err());
+        assert!(bulbs.check(&config("log", None)).is_err());
+        assert_eq!(bulbs.get("attic").err().map(|e| e.to_string()), Some(LightError::UnknownBulb("attic".to_string()).to_string()));
+
+        let mut events = bulbs.subscribe();
+        lamp.light.acting_as("agent").set_power(PowerState::On).await.unwrap();
+        assert!(lamp.light.read_log().await.unwrap().contains("Lightbulb turned ON [lamp] by agent"));
+        let (bulb, event) = events.recv().await.unwrap();
+        assert_eq!((bulb.as_str(), event.to), ("lamp", "ON"));
+        assert_eq!(bulbs.get("main").unwrap().light.read_log().await.unwrap(), "");
+    }
+
+    #[tokio::test]
+    async fn test_bulbs_answer_to_their_names_and_aliases() {
+        let main = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let bulbs = Bulbs::new(main, None, plain_actors());
+        let named = BulbConfig { name: Some("Desk Lamp".to_string()), ..config("desk", None) };
+        bulbs.add(&named, Box::new(SimulatedBackend::new())).unwrap();
+        bulbs.add(&config("hall", None), Box::new(SimulatedBackend::new())).unwrap();
+
+        assert_eq!(bulbs.get("desk lamp").unwrap().id, "desk");
+        let aliases = vec!["Reading Light".to_string()];
+        assert!(bulbs.check_names("hall", Some("desk lamp"), &[]).is_err());
+        assert!(bulbs.check_names("hall", Some("Desk"), &[]).is_err());
+        assert_eq!(bulbs.check_names("desk", None, &aliases).unwrap().aliases, aliases);
+        assert!(bulbs.get("reading light").is_err());
+        let renamed = bulbs.set_names("Desk Lamp", Some("Study Lamp"), &aliases).unwrap();
+        assert_eq!(renamed.label(), "desk (\"Study Lamp\")");
+        assert_eq!(bulbs.get("READING LIGHT").unwrap().id, "desk");
+        assert!(bulbs.get("desk lamp").is_err());
+        let clash = BulbConfig { aliases: vec!["study lamp".to_string()], ..config("porch", None) };
+        assert!(bulbs.check(&clash).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Local, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+
+// Source of the current time for timestamps, durations and time-of-day windows
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    // Time of day for "HH:MM" windows such as trigger and tag rule times
+    fn local_time(&self) -> NaiveTime {
+        self.local_now().time()
+    }
+
+    // Local date and time, for schedules that run on days of the week
+    fn local_now(&self) -> NaiveDateTime {
+        self.now().with_timezone(&Local).naive_local()
+    }
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+// The real wall clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+// Coarse, human-scale durations such as "45s", "5m 12s" or "2d 3h"
+pub fn format_duration(secs: i64) -> String {
+    match secs {
+        ..60 => format!("{}s", secs),
+        60..3600 => format!("{}m {}s", secs / 60, secs % 60),
+        3600..86400 => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+        _ => format!("{}d {}h", secs / 86400, secs % 86400 / 3600),
+    }
+}
+
+// A clock that only moves when told to, for tests.
+// Its local time is its UTC time, so windows behave the same in every time zone.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl ManualClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { now: Arc::new(Mutex::new(start)) }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.lock() = now;
+    }
+
+    pub fn advance(&self, by: TimeDelta) {
+        *self.lock() += by;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DateTime<Utc>> {
+        self.now.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.lock()
+    }
+
+    fn local_now(&self) -> NaiveDateTime {
+        self.now().naive_utc()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(45), "45s");
+        assert_eq!(format_duration(312), "5m 12s");
+        assert_eq!(format_duration(3 * 3600 + 4 * 60 + 5), "3h 4m");
+        assert_eq!(format_duration(2 * 86400 + 3 * 3600), "2d 3h");
+    }
+
+    #[test]
+    fn test_manual_clock_moves_only_when_told() {
+        let start = DateTime::parse_from_rfc3339("2025-08-02T23:30:00Z").unwrap().with_timezone(&Utc);
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(TimeDelta::minutes(45));
+        assert_eq!(clock.now().to_rfc3339(), "2025-08-03T00:15:00+00:00");
+        assert_eq!(clock.local_time(), NaiveTime::from_hms_opt(0, 15, 0).unwrap());
+    }
+}
@@ -0,0 +1,355 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::Color;
+
+const BLACK: Color = Color { r: 0, g: 0, b: 0 };
+// The D65 white point, which sRGB white maps to
+pub const WHITE_POINT: Xy = Xy { x: 0.3127, y: 0.3290 };
+
+// A chromaticity in the CIE 1931 xy diagram, as Hue and Matter bulbs take colors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Xy {
+    /// x chromaticity coordinate, between 0 and 1
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub x: f64,
+    /// y chromaticity coordinate, above 0 and at most 1 - x
+    #[schemars(range(max = 1.0), extend("exclusiveMinimum" = 0.0))]
+    pub y: f64,
+}
+
+impl Xy {
+    // Only points the diagram can hold: y above zero and x + y at most one
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=1.0).contains(&self.x) || !(0.0..=1.0).contains(&self.y) || self.y == 0.0 || self.x + self.y > 1.0 {
+            anyhow::bail!("xy must have x and y between 0 and 1, y above 0 and x + y at most 1, got ({}, {})", self.x, self.y);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Xy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.4}, {:.4}", self.x, self.y)
+    }
+}
+
+// A color as hue, saturation and value
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct Hsv {
+    /// Hue in degrees, between 0 and 360
+    #[schemars(range(min = 0.0, max = 360.0))]
+    pub h: f64,
+    /// Saturation, between 0.0 and 1.0
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub s: f64,
+    /// Value (brightness), between 0.0 and 1.0
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub v: f64,
+}
+
+impl Hsv {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0.0..=360.0).contains(&self.h) || !(0.0..=1.0).contains(&self.s) || !(0.0..=1.0).contains(&self.v) {
+            anyhow::bail!("hsv must have h between 0 and 360, s and v between 0 and 1, got ({}, {}, {})", self.h, self.s, self.v);
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Hsv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0}°, {:.0}%, {:.0}%", self.h, self.s * 100.0, self.v * 100.0)
+    }
+}
+
+// The triangle of colors a bulb's red, green and blue emitters can mix
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Gamut {
+    pub red: Xy,
+    pub green: Xy,
+    pub blue: Xy,
+}
+
+// Philips Hue's gamuts: A for older LivingColors, B for the first color bulbs, C for everything since
+pub const GAMUT_A: Gamut = Gamut { red: Xy { x: 0.704, y: 0.296 }, green: Xy { x: 0.2151, y: 0.7106 }, blue: Xy { x: 0.138, y: 0.08 } };
+pub const GAMUT_B: Gamut = Gamut { red: Xy { x: 0.675, y: 0.322 }, green: Xy { x: 0.409, y: 0.518 }, blue: Xy { x: 0.167, y: 0.04 } };
+pub const GAMUT_C: Gamut = Gamut { red: Xy { x: 0.6915, y: 0.3038 }, green: Xy { x: 0.17, y: 0.7 }, blue: Xy { x: 0.1532, y: 0.0475 } };
+
+impl Gamut {
+    // "A", "B" or "C", or three [x, y] corners in red, green, blue order
+    pub fn from_value(value: &serde_json::Value) -> anyhow::Result<Self> {
+        let gamut = match value.as_str() {
+            Some("A" | "a") => GAMUT_A,
+            Some("B" | "b") => GAMUT_B,
+            Some("C" | "c") => GAMUT_C,
+            Some(other) => anyhow::bail!("Unknown gamut '{}'; use A, B, C or three [x, y] corners", other),
+            None => {
+                let corners: [[f64; 2]; 3] = serde_json::from_value(value.clone())
+                    .map_err(|_| anyhow::anyhow!("A gamut is A, B, C or three [x, y] corners in red, green, blue order"))?;
+                let [red, green, blue] = corners.map(|[x, y]| Xy { x, y });
+                Gamut { red, green, blue }
+            },
+        };
+        for corner in [gamut.red, gamut.green, gamut.blue] {
+            corner.validate()?;
+        }
+        if cross(gamut.red, gamut.green, gamut.blue) == 0.0 {
+            anyhow::bail!("A gamut's corners must not lie on one line");
+        }
+        Ok(gamut)
+    }
+
+    pub fn contains(&self, point: Xy) -> bool {
+        let sides = [cross(self.red, self.green, point), cross(self.green, self.blue, point), cross(self.blue, self.red, point)];
+        sides.iter().all(|side| *side >= 0.0) || sides.iter().all(|side| *side <= 0.0)
+    }
+
+    // The point itself when the bulb can show it, otherwise the nearest point on the gamut's edge
+    pub fn clamp(&self, point: Xy) -> Xy {
+        if self.contains(point) {
+            return point;
+        }
+        [(self.red, self.green), (self.green, self.blue), (self.blue, self.red)]
+            .into_iter()
+            .map(|(from, to)| closest_on_segment(from, to, point))
+            .min_by(|a, b| distance(*a, point).total_cmp(&distance(*b, point)))
+            .unwrap_or(point)
+    }
+}
+
+// Color temperatures are given in mireds (a million over the temperature in kelvin) or kelvin, within what
+// the blackbody approximation below covers
+pub const MIN_KELVIN: u32 = 1000;
+pub const MAX_KELVIN: u32 = 40_000;
+pub const MIN_MIREDS: u32 = 25;
+pub const MAX_MIREDS: u32 = 1000;
+
+pub fn kelvin_to_mireds(kelvin: u32) -> u32 {
+    (1_000_000.0 / f64::from(kelvin.max(1))).round() as u32
+}
+
+pub fn mireds_to_kelvin(mireds: u32) -> u32 {
+    (1_000_000.0 / f64::from(mireds.max(1))).round() as u32
+}
+
+// The white temperatures a tunable bulb can show, in mireds, as bulbs report them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MiredRange {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl MiredRange {
+    pub fn new(min: u32, max: u32) -> anyhow::Result<Self> {
+        if min > max || min < MIN_MIREDS || max > MAX_MIREDS {
+            anyhow::bail!("A mired range must have {} <= min <= max <= {}, got {}-{}", MIN_MIREDS, MAX_MIREDS, min, max);
+        }
+        Ok(Self { min, max })
+    }
+
+    pub fn clamp(&self, mireds: u32) -> u32 {
+        mireds.clamp(self.min, self.max)
+    }
+}
+
+impl fmt::Display for MiredRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{} mireds ({}K-{}K)", self.min, self.max, mireds_to_kelvin(self.max), mireds_to_kelvin(self.min))
+    }
+}
+
+// The sRGB color of a blackbody at `mireds`, after Tanner Helland's fit to the CIE 1964 color matching data
+pub fn mireds_to_rgb(mireds: u32) -> Color {
+    let t = 1_000_000.0 / f64::from(mireds.clamp(MIN_MIREDS, MAX_MIREDS)) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.698727446 * (t - 60.0).powf(-0.1332047592) };
+    let g = if t <= 66.0 { 99.4708025861 * t.ln() - 161.1195681661 } else { 288.1221695283 * (t - 60.0).powf(-0.0755148492) };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    let [r, g, b] = [r, g, b].map(|c| c.clamp(0.0, 255.0).round() as u8);
+    Color { r, g, b }
+}
+
+fn cross(a: Xy, b: Xy, point: Xy) -> f64 {
+    (b.x - a.x) * (point.y - a.y) - (b.y - a.y) * (point.x - a.x)
+}
+
+fn closest_on_segment(from: Xy, to: Xy, point: Xy) -> Xy {
+    let (dx, dy) = (to.x - from.x, to.y - from.y);
+    let t = (((point.x - from.x) * dx + (point.y - from.y) * dy) / (dx * dx + dy * dy)).clamp(0.0, 1.0);
+    Xy { x: from.x + t * dx, y: from.y + t * dy }
+}
+
+fn distance(a: Xy, b: Xy) -> f64 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+fn to_linear(channel: u8) -> f64 {
+    let c = f64::from(channel) / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn from_linear(c: f64) -> f64 {
+    if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+}
+
+fn to_byte(c: f64) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+// The chromaticity of an sRGB color; black has none and is given the white point
+pub fn rgb_to_xy(color: Color) -> Xy {
+    let (r, g, b) = (to_linear(color.r), to_linear(color.g), to_linear(color.b));
+    let x = 0.4124 * r + 0.3576 * g + 0.1805 * b;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = 0.0193 * r + 0.1192 * g + 0.9505 * b;
+    match x + y + z {
+        sum if sum > 0.0 => Xy { x: x / sum, y: y / sum },
+        _ => WHITE_POINT,
+    }
+}
+
+// The brightest sRGB color of that chromaticity; points outside sRGB get the nearest color it has
+pub fn xy_to_rgb(point: Xy) -> Color {
+    let [r, g, b] = xy_to_channels(point);
+    Color { r: to_byte(r), g: to_byte(g), b: to_byte(b) }
+}
+
+// Gamma-encoded channels from 0.0 to 1.0, the largest always 1.0
+fn xy_to_channels(point: Xy) -> [f64; 3] {
+    let y = point.y.max(f64::EPSILON);
+    let (big_x, big_z) = (point.x / y, (1.0 - point.x - point.y) / y);
+    let linear = [
+        3.2406 * big_x - 1.5372 - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 + 1.0570 * big_z,
+    ]
+    .map(|c| c.max(0.0));
+    let max = linear.iter().copied().fold(0.0, f64::max);
+    if max == 0.0 {
+        return [1.0; 3];
+    }
+    linear.map(|c| from_linear(c / max))
+}
+
+pub fn rgb_to_hsv(color: Color) -> Hsv {
+    let [r, g, b] = [color.r, color.g, color.b].map(|c| f64::from(c) / 255.0);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    Hsv { h, s: if max == 0.0 { 0.0 } else { delta / max }, v: max }
+}
+
+pub fn hsv_to_rgb(hsv: Hsv) -> Color {
+    let h = hsv.h.rem_euclid(360.0) / 60.0;
+    let chroma = hsv.v * hsv.s;
+    let second = chroma * (1.0 - (h % 2.0 - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, second, 0.0),
+        1 => (second, chroma, 0.0),
+        2 => (0.0, chroma, second),
+        3 => (0.0, second, chroma),
+        4 => (second, 0.0, chroma),
+        _ => (chroma, 0.0, second),
+    };
+    let m = hsv.v - chroma;
+    Color { r: to_byte(r + m), g: to_byte(g + m), b: to_byte(b + m) }
+}
+
+// The color a bulb with `gamut` would show for `color`: the color itself when it can, otherwise the nearest
+// chromaticity it can mix, as bright as the color asked for
+pub fn clamp_to_gamut(color: Color, gamut: Option<Gamut>) -> Color {
+    let Some(gamut) = gamut else {
+        return color;
+    };
+    let point = rgb_to_xy(color);
+    if gamut.contains(point) || color == BLACK {
+        return color;
+    }
+    let brightness = f64::from(color.r.max(color.g).max(color.b)) / 255.0;
+    let [r, g, b] = xy_to_channels(gamut.clamp(point)).map(|c| c * brightness);
+    Color { r: to_byte(r), g: to_byte(g), b: to_byte(b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Color = Color { r: 255, g: 0, b: 0 };
+    const GREEN: Color = Color { r: 0, g: 255, b: 0 };
+
+    fn close(a: Xy, b: Xy) -> bool {
+        distance(a, b) < 0.001
+    }
+
+    #[test]
+    fn test_rgb_and_xy_convert_both_ways() {
+        assert!(close(rgb_to_xy(Color::WHITE), WHITE_POINT));
+        assert!(close(rgb_to_xy(RED), Xy { x: 0.64, y: 0.33 }));
+        assert!(close(rgb_to_xy(GREEN), Xy { x: 0.30, y: 0.60 }));
+        for color in [Color::WHITE, RED, GREEN, Color { r: 255, g: 169, b: 87 }, Color { r: 30, g: 144, b: 255 }] {
+            assert_eq!(xy_to_rgb(rgb_to_xy(color)), color);
+        }
+        assert_eq!(rgb_to_xy(BLACK), WHITE_POINT);
+        assert!(Xy { x: 0.7, y: 0.4 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_rgb_and_hsv_convert_both_ways() {
+        assert_eq!(rgb_to_hsv(RED), Hsv { h: 0.0, s: 1.0, v: 1.0 });
+        assert_eq!(rgb_to_hsv(GREEN).h, 120.0);
+        assert_eq!(hsv_to_rgb(Hsv { h: 240.0, s: 1.0, v: 1.0 }), Color { r: 0, g: 0, b: 255 });
+        for color in [Color::WHITE, BLACK, Color { r: 255, g: 169, b: 87 }, Color { r: 30, g: 144, b: 255 }] {
+            assert_eq!(hsv_to_rgb(rgb_to_hsv(color)), color);
+        }
+    }
+
+    #[test]
+    fn test_colors_are_clamped_to_the_gamut() {
+        assert!(!GAMUT_B.contains(rgb_to_xy(GREEN)));
+        let clamped = clamp_to_gamut(GREEN, Some(GAMUT_B));
+        assert_ne!(clamped, GREEN);
+        assert!(GAMUT_B.contains(GAMUT_B.clamp(rgb_to_xy(GREEN))));
+        assert!(close(GAMUT_B.clamp(Xy { x: 0.8, y: 0.2 }), GAMUT_B.red));
+        // Colors the bulb can show, and black, are left alone
+        let warm = Color { r: 255, g: 169, b: 87 };
+        assert_eq!(clamp_to_gamut(warm, Some(GAMUT_B)), warm);
+        assert_eq!(clamp_to_gamut(BLACK, Some(GAMUT_B)), BLACK);
+        assert_eq!(clamp_to_gamut(GREEN, None), GREEN);
+    }
+
+    #[test]
+    fn test_color_temperatures() {
+        assert_eq!((kelvin_to_mireds(2700), mireds_to_kelvin(370)), (370, 2703));
+        assert_eq!(mireds_to_rgb(kelvin_to_mireds(6600)), Color { r: 255, g: 255, b: 252 });
+        let candle = mireds_to_rgb(kelvin_to_mireds(1900));
+        assert!(candle.r == 255 && candle.b < candle.g, "{}", candle);
+        let range = MiredRange::new(153, 500).unwrap();
+        assert_eq!((range.clamp(100), range.clamp(370), range.clamp(600)), (153, 370, 500));
+        assert_eq!(range.to_string(), "153-500 mireds (2000K-6536K)");
+        assert!(MiredRange::new(500, 153).is_err());
+    }
+
+    #[test]
+    fn test_gamut_option_forms() {
+        assert_eq!(Gamut::from_value(&serde_json::json!("C")).unwrap(), GAMUT_C);
+        let corners = serde_json::json!([[0.675, 0.322], [0.409, 0.518], [0.167, 0.04]]);
+        assert_eq!(Gamut::from_value(&corners).unwrap(), GAMUT_B);
+        assert!(Gamut::from_value(&serde_json::json!("D")).is_err());
+        assert!(Gamut::from_value(&serde_json::json!([[0.1, 0.1], [0.2, 0.2], [0.3, 0.3]])).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+
+use crate::actor::LightHandle;
+use crate::error::LightError;
+use crate::logger::parse_log_timestamp;
+use crate::model::PowerState;
+use crate::stats::{DailyTotal, day_start, parse_daily_total, parse_power_event};
+
+// How often the compaction job looks for days that have aged out
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// The log after collapsing its old entries, with how much was collapsed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compaction {
+    pub log: String,
+    // Days the log now holds as daily totals, and the entries this compaction collapsed into them
+    pub days: usize,
+    pub entries: usize,
+}
+
+// Entries before midnight UTC `keep_days` days before `now` are old enough to compact
+pub fn compaction_cutoff(now: DateTime<Utc>, keep_days: u32) -> DateTime<Utc> {
+    let today = now.date_naive();
+    day_start(today.checked_sub_days(Days::new(keep_days.into())).unwrap_or(today))
+}
+
+// Collapses the entries dated before `before` into one daily total per UTC day and keeps the rest verbatim,
+// along with any lines ahead of the first dated entry. Undated lines among the old entries are counted and
+// dropped. `as_written` turns each total into the line the log writes, e.g. signed. None when nothing is
+// old enough to collapse.
+pub fn compact(log: &str, before: DateTime<Utc>, as_written: impl Fn(&str) -> String) -> Option<Compaction> {
+    let mut head = Vec::new();
+    let mut tail = Vec::new();
+    let mut days: BTreeMap<NaiveDate, DailyTotal> = BTreeMap::new();
+    let mut current: Option<NaiveDate> = None;
+    let mut on_since: Option<DateTime<Utc>> = None;
+    let mut collapsed = 0;
+    for line in log.lines() {
+        if !tail.is_empty() {
+            tail.push(line);
+            continue;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(total) = parse_daily_total(line) {
+            if total.start() >= before {
+                tail.push(line);
+                continue;
+            }
+            on_since = total.left_on.then(|| total.end());
+            current = Some(total.day);
+            merge(&mut days, total);
+            continue;
+        }
+        match (parse_log_timestamp(line), current) {
+            (Some((at, _)), _) if at >= before => tail.push(line),
+            (Some((at, _)), _) => {
+                collapsed += 1;
+                let stamp = line[1..].split(']').next().unwrap_or_default().to_string();
+                let day = days.entry(at.date_naive()).or_insert_with(|| DailyTotal::new(at.date_naive()));
+                day.entries += 1;
+                day.first.get_or_insert_with(|| stamp.clone());
+                day.last = Some(stamp);
+                current = Some(at.date_naive());
+                let Some(event) = parse_power_event(line) else {
+                    continue;
+                };
+                match event.power {
+                    PowerState::On => day.on += 1,
+                    PowerState::Off => day.off += 1,
+                }
+                match (event.power, on_since) {
+                    (PowerState::On, None) => on_since = Some(at),
+                    (PowerState::Off, Some(since)) => {
+                        add_on_time(&mut days, since, at);
+                        on_since = None;
+                    },
+                    _ => {},
+                }
+            },
+            (None, None) => head.push(line),
+            (None, Some(day)) => {
+                collapsed += 1;
+                days.entry(day).or_insert_with(|| DailyTotal::new(day)).entries += 1;
+            },
+        }
+    }
+    if collapsed == 0 {
+        return None;
+    }
+    // Still on at the cutoff, so the days up to it were on to the end
+    if let Some(since) = on_since {
+        add_on_time(&mut days, since, before);
+    }
+    let totals = days.values().map(|day| as_written(&day.log_line()));
+    let lines: Vec<String> = head.into_iter().map(String::from).chain(totals).chain(tail.into_iter().map(String::from)).collect();
+    Some(Compaction { log: lines.iter().map(|line| format!("{}\n", line)).collect(), days: days.len(), entries: collapsed })
+}
+
+// Splits the time the bulb was on between the days it spans
+fn add_on_time(days: &mut BTreeMap<NaiveDate, DailyTotal>, mut start: DateTime<Utc>, end: DateTime<Utc>) {
+    while start < end {
+        let day = start.date_naive();
+        let total = days.entry(day).or_insert_with(|| DailyTotal::new(day));
+        let until = total.end().min(end);
+        total.on_secs += (until - start).num_seconds();
+        total.left_on = until == total.end();
+        start = until;
+    }
+}
+
+// Only a clock that went backwards can leave two totals for one day
+fn merge(days: &mut BTreeMap<NaiveDate, DailyTotal>, total: DailyTotal) {
+    match days.get_mut(&total.day) {
+        Some(day) => {
+            day.entries += total.entries;
+            day.on += total.on;
+            day.off += total.off;
+            day.on_secs += total.on_secs;
+            day.left_on = total.left_on;
+            if day.first.is_none() {
+                day.first = total.first;
+            }
+            if total.last.is_some() {
+                day.last = total.last;
+            }
+        },
+        None => {
+            days.insert(total.day, total);
+        },
+    }
+}
+
+// Compacts the log now and then hourly, keeping entries from the current UTC day and the `keep_days` before it
+pub fn spawn_log_compaction(light: LightHandle, keep_days: u32) {
+    tokio::spawn(async move {
+        loop {
+            match light.compact_log(compaction_cutoff(light.clock().now(), keep_days)).await {
+                Err(LightError::ActorStopped) => return,
+                Err(e) => crate::diagnostic!(Warn, "log compaction failed: {}", e),
+                Ok(Some(compaction)) => {
+                    crate::diagnostic!(Info, "compacted {} log entries; the log holds {} daily totals", compaction.entries, compaction.days)
+                },
+                Ok(None) => {},
+            }
+            tokio::time::sleep(COMPACTION_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeDelta;
+    use ed25519_dalek::SigningKey;
+
+    use super::*;
+    use crate::logger::{InMemoryLogger, Logger, SignatureCheck, SigningLogger, verify_log_line};
+    use crate::stats::{Metric, UsageCounters, compute};
+
+    const LOG: &str = "\
+this line predates the log format
+[2025-08-01T09:00:00+00:00] Lightbulb turned ON [morning] by kitchen-agent
+[2025-08-01T09:45:00+00:00] Lightbulb turned OFF by kitchen-agent
+[2025-08-01T23:00:00+00:00] Lightbulb turned ON by desk-agent
+garbage
+[2025-08-02T01:00:00+00:00] Lightbulb turned OFF by desk-agent
+[2025-08-02T22:00:00+00:00] Lightbulb turned ON
+[2025-08-03T08:30:00+00:00] Lightbulb turned OFF [movie]
+[2025-08-03T09:00:00+00:00] Lightbulb turned ON
+";
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn on_time(usage: &UsageCounters, from: Option<DateTime<Utc>>, to: DateTime<Utc>) -> (Option<i64>, usize) {
+        let statistics = compute(&usage.events, &usage.days, &[Metric::Counts, Metric::OnTime], from, to, 9.0, None);
+        (statistics.on_time_secs, statistics.counts.unwrap().total)
+    }
+
+    #[test]
+    fn test_compaction_keeps_the_summary_and_statistics() {
+        let compaction = compact(LOG, at("2025-08-03T00:00:00Z"), str::to_string).unwrap();
+        assert_eq!((compaction.days, compaction.entries), (2, 6));
+        let lines: Vec<&str> = compaction.log.lines().collect();
+        assert_eq!(lines[0], "this line predates the log format");
+        assert_eq!(
+            lines[1],
+            "[2025-08-01T09:00:00+00:00] Lightbulb daily total 2025-08-01: entries=4 on=2 off=1 on_secs=6300 left_on=true last=2025-08-01T23:00:00+00:00"
+        );
+        assert_eq!(&lines[3..], ["[2025-08-03T08:30:00+00:00] Lightbulb turned OFF [movie]", "[2025-08-03T09:00:00+00:00] Lightbulb turned ON"]);
+
+        let (before, after) = (UsageCounters::from_log(LOG), UsageCounters::from_log(&compaction.log));
+        assert_eq!((after.total, after.on, after.off), (before.total, before.on, before.off));
+        assert_eq!((&after.first, &after.last), (&before.first, &before.last));
+        let now = at("2025-08-03T12:00:00Z");
+        assert_eq!(on_time(&after, None, now), on_time(&before, None, now));
+        assert_eq!(on_time(&after, Some(at("2025-08-02T00:00:00Z")), now), on_time(&before, Some(at("2025-08-02T00:00:00Z")), now));
+
+        assert!(compact(&compaction.log, at("2025-08-03T00:00:00Z"), str::to_string).is_none());
+        let again = compact(&compaction.log, at("2025-08-04T00:00:00Z"), str::to_string).unwrap();
+        assert_eq!((again.days, again.entries), (3, 2));
+        assert_eq!(on_time(&UsageCounters::from_log(&again.log), None, now + TimeDelta::days(1)), on_time(&before, None, now + TimeDelta::days(1)));
+    }
+
+    #[test]
+    fn test_days_left_on_get_totals_of_their_own() {
+        let log = "[2025-08-01T12:00:00+00:00] Lightbulb turned ON\n[2025-08-04T06:00:00+00:00] Lightbulb turned OFF\n";
+        let compaction = compact(log, at("2025-08-05T00:00:00Z"), str::to_string).unwrap();
+        let days = UsageCounters::from_log(&compaction.log).days;
+        let on_secs: Vec<i64> = days.iter().map(|day| day.on_secs).collect();
+        assert_eq!(on_secs, [12 * 3600, 24 * 3600, 24 * 3600, 6 * 3600]);
+        assert_eq!(days[1].first, None);
+        assert_eq!(UsageCounters::from_log(&compaction.log).total, 2);
+    }
+
+    #[test]
+    fn test_totals_are_written_as_the_log_writes_them() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let logger = SigningLogger::new(Box::new(InMemoryLogger::new()), key.clone());
+        let compaction = compact(LOG, at("2025-08-03T00:00:00Z"), |line| logger.as_written(line)).unwrap();
+        let total = compaction.log.lines().nth(1).unwrap();
+        assert_eq!(verify_log_line(&key.verifying_key(), total), SignatureCheck::Valid);
+        assert_eq!(parse_daily_total(total).unwrap().on_secs, 6300);
+        assert_eq!(UsageCounters::from_log(&compaction.log).on, 4);
+    }
+
+    #[test]
+    fn test_cutoff_keeps_whole_days() {
+        assert_eq!(compaction_cutoff(at("2025-08-10T15:30:00Z"), 7), at("2025-08-03T00:00:00Z"));
+        assert_eq!(compaction_cutoff(at("2025-08-10T15:30:00Z"), 0), at("2025-08-10T00:00:00Z"));
+    }
+}
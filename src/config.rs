@@ -0,0 +1,2036 @@
+use std::collections::BTreeMap;
+
+use anyhow::Context;
+use chrono::{NaiveTime, TimeDelta, Weekday};
+use serde::{Deserialize, Serialize};
+
+use crate::brightness::DEFAULT_GAMMA;
+use crate::color::{MAX_KELVIN, MIN_KELVIN};
+use crate::history_file::{DEFAULT_HISTORY_KEEP_FILES, DEFAULT_HISTORY_MAX_BYTES, HistoryRotation};
+use crate::i18n;
+use crate::logger::LOG_FILE_NAME;
+use crate::offline::DEFAULT_OFFLINE_RETRY;
+use crate::palette::resolve_color;
+use crate::schedule::{Cron, ScheduleTime};
+use crate::stats::{DEFAULT_WATTS, MAX_WATTS};
+use crate::tags::normalize_tag;
+
+pub const CONFIG_FILE_NAME: &str = "lightbulb.toml";
+pub const DEFAULT_BULB_NAME: &str = "The lightbulb";
+// A year, so a larger limit is a typo
+const MAX_ON_HOURS: f64 = 8760.0;
+pub const CONFIG_PATH_ENV: &str = "LIGHTBULB_CONFIG";
+const SIGNING_KEY_ENV: &str = "LIGHTBULB_SIGNING_KEY";
+const BACKEND_ENV: &str = "LIGHTBULB_BACKEND";
+// API key the MCP client launched this server with, e.g. from its server config's `env`
+pub const API_KEY_ENV: &str = "LIGHTBULB_API_KEY";
+const DEFAULT_WEBHOOK_RETRIES: u32 = 3;
+const DEFAULT_MQTT_PORT: u16 = 1883;
+const DEFAULT_MQTT_TOPIC: &str = "lightbulb/state";
+const DEFAULT_MQTT_CLIENT_ID: &str = "lightbulb-mcp";
+const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+const DEFAULT_FAN_OUT_CONCURRENCY: usize = 8;
+const DEFAULT_DOWNSTREAM_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_TOOL_TIMEOUT_SECS: u64 = 30;
+// Tools that wait on purpose, for as long as they were asked to, so the default timeout does not cover them
+const UNTIMED_TOOLS: [&str; 2] = ["run_macro", "update_firmware"];
+const DEFAULT_STATE_TTL_SECS: u64 = 5;
+// Small enough for a test, large enough to hold a few records before each rotation
+const MIN_HISTORY_MAX_BYTES: u64 = 4096;
+const MAX_HISTORY_KEEP_FILES: usize = 100;
+// A comfortable level for reading, going by the usual office lighting guidance
+const DEFAULT_TARGET_LUX: f64 = 300.0;
+const DEFAULT_HYSTERESIS_LUX: f64 = 30.0;
+const DEFAULT_MAX_STEP_PERCENT: u8 = 20;
+const DEFAULT_CURRENCY: &str = "USD";
+const DEFAULT_MOTION_OFF_AFTER_SECS: u64 = 300;
+const MAX_MOTION_OFF_AFTER_SECS: u64 = 24 * 60 * 60;
+const DEFAULT_WEATHER_URL: &str = "https://api.open-meteo.com/v1/forecast";
+const DEFAULT_WEATHER_POLL_MINUTES: u64 = 15;
+const MAX_WEATHER_POLL_MINUTES: u64 = 24 * 60;
+// Well past direct sunlight, so anything higher is a faulty sensor
+pub const MAX_LUX: f64 = 200_000.0;
+// Stands in for secrets when the configuration is shown to clients
+pub const REDACTED: &str = "[redacted]";
+// Backend options whose names contain one of these are taken for credentials
+const SECRET_OPTION_WORDS: [&str; 4] = ["key", "token", "secret", "password"];
+
+// Runtime configuration, read from lightbulb.toml (or the file named by LIGHTBULB_CONFIG)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    pub log_file: String,
+    pub signing_key: Option<String>,
+    pub log_compaction: Option<CompactionConfig>,
+    // Encrypts each entry of the log at rest, under a key from a file or derived from a passphrase
+    pub log_encryption: Option<LogEncryptionConfig>,
+    // Hashes or leaves out who made each change and why, keeping what was done and when
+    pub log_privacy: LogPrivacyConfig,
+    // Keeps a structured record of each log entry beside the log, for query_history
+    pub log_history: LogHistoryConfig,
+    // Journals each logged change beside the log before making it, so one a crash kept out of the log is
+    // written on the next start
+    pub journal: bool,
+    // Saves the usage counters beside the log at shutdown, so the first summary after a restart need not
+    // count the whole log again
+    pub stats_cache: bool,
+    // Keeps the scenes and schedules loaded with import_automation beside the log, and loads them again at startup
+    pub automation_file: bool,
+    pub backend: BackendConfig,
+    // The most brightness commands get at each time of day, e.g. dimmer late at night
+    pub brightness_schedule: Vec<BrightnessPointConfig>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub mqtt: Option<MqttPublisherConfig>,
+    pub triggers: Vec<TriggerConfig>,
+    pub notifications: Vec<NotificationConfig>,
+    pub rest: Option<RestConfig>,
+    // Where the HTTP transport gets the keys it checks bearer JWTs with, and the claims it requires
+    pub jwt: Option<JwtConfig>,
+    pub adaptive_brightness: Option<AdaptiveBrightnessConfig>,
+    // Rooms whose motion sensors turn the bulb on, each with its own inactivity timeout
+    pub motion: Vec<MotionConfig>,
+    // Door and window sensors, each switching the bulb, or an aggregator's bulbs, as it opens and closes
+    pub contact: Vec<ContactConfig>,
+    // Scenes of the user's own, replacing any built-in scene of the same name
+    pub scenes: Vec<SceneConfig>,
+    // Changes made at a local time of day, every day or on the days listed
+    pub schedules: Vec<ScheduleConfig>,
+    // The room the server's own bulb is in, for the room tools
+    pub room: Option<String>,
+    // Bulbs of their own beside the server's, each switched by its id
+    pub bulbs: Vec<BulbConfig>,
+    // What electricity costs, for the energy-savings prompt
+    pub tariff: Option<TariffConfig>,
+    // How long the bulb may stay on without a break before the safety watchdog steps in
+    pub safety: Option<SafetyConfig>,
+    // Where to read the weather from, and the rules that switch the bulb as it changes
+    pub weather: Option<WeatherConfig>,
+    // People whose comings and goings are reported with report_presence, and the rules applied as they do
+    pub presence: Option<PresenceConfig>,
+    pub sessions: SessionMode,
+    // Other lightbulb-mcp servers to aggregate; when set, this server has no bulb of its own
+    pub downstream: Vec<DownstreamConfig>,
+    pub aggregator: AggregatorConfig,
+    pub tool_timeouts: ToolTimeoutsConfig,
+    // API keys whose sessions get guest access: status and on/off only
+    pub guest_keys: Vec<String>,
+    // API keys whose sessions no curfew applies to
+    pub admin_keys: Vec<String>,
+    // Hours during which tools may only be called by admin sessions
+    pub curfews: Vec<CurfewConfig>,
+    pub tag_rules: Vec<TagRuleConfig>,
+    // Language of status, summary and error messages, e.g. "de" or "fr-CA"; English when unset
+    pub locale: String,
+    // Wording of the deployment's own for status and confirmation messages, in every locale
+    pub messages: MessagesConfig,
+    // File the configuration was read from, if any
+    #[serde(skip)]
+    pub source: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            log_file: LOG_FILE_NAME.to_string(),
+            signing_key: None,
+            log_compaction: None,
+            log_encryption: None,
+            log_privacy: LogPrivacyConfig::default(),
+            log_history: LogHistoryConfig::default(),
+            journal: true,
+            stats_cache: true,
+            automation_file: true,
+            backend: BackendConfig::default(),
+            brightness_schedule: Vec::new(),
+            webhooks: Vec::new(),
+            mqtt: None,
+            triggers: Vec::new(),
+            notifications: Vec::new(),
+            rest: None,
+            jwt: None,
+            adaptive_brightness: None,
+            motion: Vec::new(),
+            contact: Vec::new(),
+            scenes: Vec::new(),
+            schedules: Vec::new(),
+            room: None,
+            bulbs: Vec::new(),
+            tariff: None,
+            safety: None,
+            weather: None,
+            presence: None,
+            sessions: SessionMode::default(),
+            downstream: Vec::new(),
+            aggregator: AggregatorConfig::default(),
+            tool_timeouts: ToolTimeoutsConfig::default(),
+            guest_keys: Vec::new(),
+            admin_keys: Vec::new(),
+            curfews: Vec::new(),
+            tag_rules: Vec::new(),
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            messages: MessagesConfig::default(),
+            source: None,
+        }
+    }
+}
+
+// How MCP sessions map onto bulbs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionMode {
+    // Every session drives the configured bulb
+    #[default]
+    Shared,
+    // Each session gets its own simulated bulb and in-memory log
+    Sandbox,
+}
+
+// Selects a registered backend by name and passes it driver-specific options
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackendConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub options: serde_json::Map<String, serde_json::Value>,
+    // How long the power the device last reported is trusted before the next status read asks it again
+    pub state_ttl_secs: u64,
+    // Brightness percentages are raised to this before the backend is sent them, so 50% looks half as bright
+    pub gamma: f64,
+    // The least time between two power changes, so a client flip-flopping the bulb cannot wear out its relay
+    pub min_toggle_interval_ms: u64,
+    // Queues power, color and brightness changes the device cannot be reached for, applying them once it answers
+    pub offline_queue: bool,
+    // How often a device with queued changes is asked whether it is back
+    pub offline_retry_secs: u64,
+}
+
+impl BackendConfig {
+    pub fn state_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.state_ttl_secs)
+    }
+
+    pub fn min_toggle_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.min_toggle_interval_ms)
+    }
+
+    // None unless offline_queue is on
+    pub fn offline_retry(&self) -> Option<std::time::Duration> {
+        self.offline_queue.then(|| std::time::Duration::from_secs(self.offline_retry_secs))
+    }
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        Self {
+            kind: "simulated".to_string(),
+            options: serde_json::Map::new(),
+            state_ttl_secs: DEFAULT_STATE_TTL_SECS,
+            gamma: DEFAULT_GAMMA,
+            min_toggle_interval_ms: 0,
+            offline_queue: false,
+            offline_retry_secs: DEFAULT_OFFLINE_RETRY.as_secs(),
+        }
+    }
+}
+
+// An endpoint that receives a JSON POST for every matching light event
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    // Shared secret for the X-Lightbulb-Signature HMAC-SHA256 header
+    pub secret: Option<String>,
+    // Event kinds to deliver, e.g. ["state_changed"]; empty means all
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default = "default_webhook_retries")]
+    pub max_retries: u32,
+}
+
+fn default_webhook_retries() -> u32 {
+    DEFAULT_WEBHOOK_RETRIES
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            anyhow::bail!("Webhook URL must start with http:// or https://: {}", self.url);
+        }
+        Ok(())
+    }
+}
+
+// A broker that receives a retained message on `topic` after every state change
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttPublisherConfig {
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_mqtt_topic")]
+    pub topic: String,
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Announce the bulb to Home Assistant and accept ON/OFF commands on "<topic>/set"
+    #[serde(default = "enabled")]
+    pub discovery: bool,
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+}
+
+fn default_discovery_prefix() -> String {
+    DEFAULT_DISCOVERY_PREFIX.to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    DEFAULT_MQTT_PORT
+}
+
+fn default_mqtt_topic() -> String {
+    DEFAULT_MQTT_TOPIC.to_string()
+}
+
+fn default_mqtt_client_id() -> String {
+    DEFAULT_MQTT_CLIENT_ID.to_string()
+}
+
+impl MqttPublisherConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.host.is_empty() {
+            anyhow::bail!("MQTT host must not be empty");
+        }
+        // Wildcards are only valid when subscribing
+        if self.topic.is_empty() || self.topic.contains(['+', '#']) {
+            anyhow::bail!("MQTT topic must be non-empty and contain no wildcards: {}", self.topic);
+        }
+        if self.password.is_some() && self.username.is_none() {
+            anyhow::bail!("MQTT password requires a username");
+        }
+        if self.discovery && !self.client_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            anyhow::bail!("MQTT client_id is used as the Home Assistant object ID and may only contain letters, digits, '_' and '-': {}", self.client_id);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TriggerMethod {
+    Get,
+    #[default]
+    Post,
+}
+
+// A templated HTTP request (an IFTTT Webhooks applet, for example) fired by events matching every condition given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TriggerConfig {
+    pub name: String,
+    // May contain {placeholders}, which are URL-encoded
+    pub url: String,
+    #[serde(default)]
+    pub method: TriggerMethod,
+    // POST body template, sent as JSON
+    pub body: Option<String>,
+    // Event kinds that fire the trigger; empty means all
+    #[serde(default)]
+    pub events: Vec<String>,
+    // State label the bulb must end in, e.g. "ON"
+    pub to: Option<String>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+impl TriggerConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            anyhow::bail!("Trigger '{}' URL must start with http:// or https://: {}", self.name, self.url);
+        }
+        if self.method == TriggerMethod::Get && self.body.is_some() {
+            anyhow::bail!("Trigger '{}' is a GET and cannot have a body", self.name);
+        }
+        self.window()?;
+        Ok(())
+    }
+
+    pub fn window(&self) -> anyhow::Result<(Option<NaiveTime>, Option<NaiveTime>)> {
+        parse_window(&self.after, &self.before, || format!("Trigger '{}'", self.name))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatService {
+    Slack,
+    Discord,
+}
+
+// A Slack or Discord incoming webhook that receives a chat message for each enabled event type
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotificationConfig {
+    pub service: ChatService,
+    pub webhook_url: String,
+    #[serde(default = "enabled")]
+    pub state_changed: bool,
+    #[serde(default = "enabled")]
+    pub backend_unreachable: bool,
+    #[serde(default = "enabled")]
+    pub on_too_long: bool,
+    // State label a state change must end in to be announced, e.g. "OFF"
+    pub to: Option<String>,
+    // Only announce state changes inside this local window
+    pub quiet_hours: Option<QuietHours>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct QuietHours {
+    pub start: String,
+    pub end: String,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl NotificationConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.webhook_url.starts_with("https://") {
+            anyhow::bail!("Notification webhook URL must start with https://: {}", self.webhook_url);
+        }
+        self.quiet_hours()?;
+        Ok(())
+    }
+
+    pub fn quiet_hours(&self) -> anyhow::Result<Option<(NaiveTime, NaiveTime)>> {
+        self.quiet_hours
+            .as_ref()
+            .map(|hours| anyhow::Ok((parse_time(&hours.start)?, parse_time(&hours.end)?)))
+            .transpose()
+            .context("Notification quiet hours must be HH:MM")
+    }
+}
+
+// Tags every logged power change matching all the conditions given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagRuleConfig {
+    pub tag: String,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    // "on" or "off"
+    pub to: Option<String>,
+    // Who must have made the change, as recorded in `changed_by`
+    pub by: Option<String>,
+}
+
+impl TagRuleConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        normalize_tag(&self.tag)?;
+        if let Some(to) = &self.to
+            && !(to.eq_ignore_ascii_case("on") || to.eq_ignore_ascii_case("off"))
+        {
+            anyhow::bail!("Tag rule '{}' `to` must be \"on\" or \"off\", got '{}'", self.tag, to);
+        }
+        self.window()?;
+        Ok(())
+    }
+
+    pub fn window(&self) -> anyhow::Result<(Option<NaiveTime>, Option<NaiveTime>)> {
+        parse_window(&self.after, &self.before, || format!("Tag rule '{}'", self.tag))
+    }
+}
+
+// Keeps tools out of reach of everyone but admin clients for part of each day, e.g. no turning the bulb on after
+// bedtime
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CurfewConfig {
+    pub name: String,
+    // Local "HH:MM" window; `after` later than `before` wraps past midnight
+    pub after: String,
+    pub before: String,
+    // Tools the curfew covers; every tool when empty
+    #[serde(default)]
+    pub tools: Vec<String>,
+    // On an aggregator, the bulbs it covers; every bulb when empty
+    #[serde(default)]
+    pub bulbs: Vec<String>,
+}
+
+impl CurfewConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("Every curfew needs a name");
+        }
+        let (after, before) = self.window()?;
+        if after == before {
+            anyhow::bail!("Curfew '{}' starts and ends at {}, so it never applies", self.name, self.after);
+        }
+        Ok(())
+    }
+
+    pub fn window(&self) -> anyhow::Result<(NaiveTime, NaiveTime)> {
+        let parse = |time: &str| parse_time(time).with_context(|| format!("Curfew '{}' times must be HH:MM", self.name));
+        Ok((parse(&self.after)?, parse(&self.before)?))
+    }
+}
+
+fn parse_time(time: &str) -> anyhow::Result<NaiveTime> {
+    Ok(NaiveTime::parse_from_str(time, "%H:%M")?)
+}
+
+// The local "HH:MM" window of a trigger, tag rule or presence rule (`owner`), either end of which may be open;
+// `after` later than `before` wraps past midnight
+fn parse_window(after: &Option<String>, before: &Option<String>, owner: impl Fn() -> String) -> anyhow::Result<(Option<NaiveTime>, Option<NaiveTime>)> {
+    let parse = |time: &Option<String>| time.as_deref().map(parse_time).transpose().with_context(|| format!("{} times must be HH:MM", owner()));
+    Ok((parse(after)?, parse(before)?))
+}
+
+// Collapses log entries older than `keep_days` whole UTC days into one total per day
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompactionConfig {
+    pub keep_days: u32,
+}
+
+// What a log entry keeps of something identifying: all of it, a keyed hash, or nothing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redaction {
+    #[default]
+    Keep,
+    Hash,
+    Omit,
+}
+
+// How log entries record the client behind each change and the reason it gave; `salt` keys the hashes
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogPrivacyConfig {
+    pub clients: Redaction,
+    pub reasons: Redaction,
+    pub salt: Option<String>,
+}
+
+impl LogPrivacyConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let hashes = self.clients == Redaction::Hash || self.reasons == Redaction::Hash;
+        if hashes && self.salt.as_deref().is_none_or(str::is_empty) {
+            anyhow::bail!("[log_privacy] hashes entries but sets no salt, so anyone could hash a client name and find it in the log");
+        }
+        Ok(())
+    }
+}
+
+// The records of log entries kept in lightbulb.log.jsonl, one JSON object per line, rotated once the file would
+// pass `max_bytes`, with `keep_files` rotated files kept beside it
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogHistoryConfig {
+    pub enabled: bool,
+    pub max_bytes: u64,
+    pub keep_files: usize,
+}
+
+impl Default for LogHistoryConfig {
+    fn default() -> Self {
+        Self { enabled: true, max_bytes: DEFAULT_HISTORY_MAX_BYTES, keep_files: DEFAULT_HISTORY_KEEP_FILES }
+    }
+}
+
+impl LogHistoryConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_bytes < MIN_HISTORY_MAX_BYTES {
+            anyhow::bail!("[log_history] max_bytes must be at least {}", MIN_HISTORY_MAX_BYTES);
+        }
+        if self.keep_files > MAX_HISTORY_KEEP_FILES {
+            anyhow::bail!("[log_history] keep_files must be at most {}", MAX_HISTORY_KEEP_FILES);
+        }
+        Ok(())
+    }
+
+    pub fn rotation(&self) -> HistoryRotation {
+        HistoryRotation { max_bytes: self.max_bytes, keep_files: self.keep_files }
+    }
+}
+
+// Where the log's encryption key comes from: a key file, generated if missing, or a passphrase read from the
+// environment variable named by `passphrase_env`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogEncryptionConfig {
+    pub key_file: Option<String>,
+    pub passphrase_env: Option<String>,
+}
+
+impl LogEncryptionConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (&self.key_file, &self.passphrase_env) {
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => anyhow::bail!("[log_encryption] needs exactly one of key_file and passphrase_env"),
+        }
+    }
+
+    // The passphrase, read from the environment so it is never in the configuration file
+    pub fn passphrase(&self) -> anyhow::Result<Option<String>> {
+        let Some(var) = &self.passphrase_env else {
+            return Ok(None);
+        };
+        std::env::var(var).map(Some).map_err(|_| anyhow::anyhow!("[log_encryption] reads its passphrase from {}, which is not set", var))
+    }
+}
+
+// Adjusts brightness to hold the room at `target_lux` as ambient light readings come in, by tool call or from
+// the MQTT `topic` when one is given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveBrightnessConfig {
+    pub target_lux: f64,
+    // How far a reading may stray from the target before brightness is corrected
+    pub hysteresis_lux: f64,
+    // The most brightness changes by for one reading
+    pub max_step_percent: u8,
+    pub topic: Option<String>,
+}
+
+impl Default for AdaptiveBrightnessConfig {
+    fn default() -> Self {
+        Self {
+            target_lux: DEFAULT_TARGET_LUX,
+            hysteresis_lux: DEFAULT_HYSTERESIS_LUX,
+            max_step_percent: DEFAULT_MAX_STEP_PERCENT,
+            topic: None,
+        }
+    }
+}
+
+impl AdaptiveBrightnessConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.target_lux > 0.0 && self.target_lux <= MAX_LUX) {
+            anyhow::bail!("Adaptive brightness target_lux must be above 0 and at most {}, got {}", MAX_LUX, self.target_lux);
+        }
+        if !(self.hysteresis_lux >= 0.0 && self.hysteresis_lux < self.target_lux) {
+            anyhow::bail!("Adaptive brightness hysteresis_lux must be at least 0 and below target_lux, got {}", self.hysteresis_lux);
+        }
+        if !(1..=100).contains(&self.max_step_percent) {
+            anyhow::bail!("Adaptive brightness max_step_percent must be between 1 and 100, got {}", self.max_step_percent);
+        }
+        // Readings are matched to the topic exactly
+        if let Some(topic) = &self.topic
+            && (topic.is_empty() || topic.contains(['+', '#']))
+        {
+            anyhow::bail!("Adaptive brightness topic must be non-empty and contain no wildcards: {}", topic);
+        }
+        Ok(())
+    }
+}
+
+// A room whose motion turns the bulb on, which is turned off again once there has been no motion for
+// `off_after_secs`; motion is reported by tool call or on the MQTT `topic` when one is given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MotionConfig {
+    // Also tags the changes motion makes, so statistics can be filtered by room
+    pub room: String,
+    #[serde(default = "default_motion_off_after_secs")]
+    pub off_after_secs: u64,
+    pub topic: Option<String>,
+}
+
+fn default_motion_off_after_secs() -> u64 {
+    DEFAULT_MOTION_OFF_AFTER_SECS
+}
+
+impl MotionConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if normalize_tag(&self.room).ok().as_deref() != Some(self.room.as_str()) {
+            anyhow::bail!("Motion room '{}' must be a lowercase tag: letters, digits, '-' and '_'", self.room);
+        }
+        if !(1..=MAX_MOTION_OFF_AFTER_SECS).contains(&self.off_after_secs) {
+            anyhow::bail!("Motion room '{}' off_after_secs must be between 1 and {}, got {}", self.room, MAX_MOTION_OFF_AFTER_SECS, self.off_after_secs);
+        }
+        if let Some(topic) = &self.topic
+            && (topic.is_empty() || topic.contains(['+', '#']))
+        {
+            anyhow::bail!("Motion room '{}' topic must be non-empty and contain no wildcards: {}", self.room, topic);
+        }
+        Ok(())
+    }
+
+    pub fn off_after(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.off_after_secs)
+    }
+}
+
+// A door or window sensor, reported by tool call or on the MQTT `topic` when one is given; opening it turns the
+// bulb on and closing it turns the bulb off unless it says otherwise
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContactConfig {
+    // Also tags the changes the sensor makes
+    pub sensor: String,
+    pub topic: Option<String>,
+    // The bulbs an aggregator switches, by downstream id; every bulb when empty
+    #[serde(default)]
+    pub bulbs: Vec<String>,
+    #[serde(default = "default_contact_on_open")]
+    pub on_open: RulePower,
+    #[serde(default = "default_contact_on_close")]
+    pub on_close: RulePower,
+    // Brightness the bulb is turned on at
+    pub brightness: Option<u8>,
+}
+
+fn default_contact_on_open() -> RulePower {
+    RulePower::On
+}
+
+fn default_contact_on_close() -> RulePower {
+    RulePower::Off
+}
+
+impl ContactConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if normalize_tag(&self.sensor).ok().as_deref() != Some(self.sensor.as_str()) {
+            anyhow::bail!("Contact sensor '{}' must be a lowercase tag: letters, digits, '-' and '_'", self.sensor);
+        }
+        if let Some(topic) = &self.topic
+            && (topic.is_empty() || topic.contains(['+', '#']))
+        {
+            anyhow::bail!("Contact sensor '{}' topic must be non-empty and contain no wildcards: {}", self.sensor, topic);
+        }
+        match self.brightness {
+            Some(_) if self.on_open == RulePower::Off && self.on_close == RulePower::Off => {
+                anyhow::bail!("Contact sensor '{}' never turns the bulb on, so it cannot set a brightness", self.sensor)
+            },
+            Some(brightness) if !(1..=100).contains(&brightness) => {
+                anyhow::bail!("Contact sensor '{}' brightness must be between 1 and 100, got {}", self.sensor, brightness)
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // What opening, or closing, the sensor does
+    pub fn action(&self, open: bool) -> RulePower {
+        if open { self.on_open } else { self.on_close }
+    }
+}
+
+// The id of the server's own bulb, the one tools switch when they are given no bulb_id
+pub const MAIN_BULB: &str = "main";
+// Ids a bulb cannot be given: the server's own bulb's, and the first segments of other resource URIs
+pub const RESERVED_BULB_IDS: [&str; 4] = [MAIN_BULB, "log", "colors", "effects"];
+
+// A bulb beside the server's own, on the simulated backend unless another is given
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BulbConfig {
+    pub id: String,
+    pub room: Option<String>,
+    // What people call it, e.g. "Desk Lamp"; tools take it, and any alias, wherever they take the id
+    pub name: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub backend: BackendConfig,
+}
+
+impl BulbConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if normalize_tag(&self.id).ok().as_deref() != Some(self.id.as_str()) {
+            anyhow::bail!("Bulb '{}' id must be a lowercase tag: letters, digits, '-' and '_'", self.id);
+        }
+        if RESERVED_BULB_IDS.contains(&self.id.as_str()) {
+            anyhow::bail!("Bulb id '{}' is reserved; choose another", self.id);
+        }
+        validate_bulb_names(self.name.as_deref(), &self.aliases)?;
+        match &self.room {
+            Some(room) => validate_room(room),
+            None => Ok(()),
+        }
+    }
+}
+
+pub const MAX_BULB_NAME_LEN: usize = 40;
+pub const MAX_BULB_ALIASES: usize = 8;
+
+// A bulb's name and aliases are matched ignoring case, so no two of them may differ only in case
+pub fn validate_bulb_names(name: Option<&str>, aliases: &[String]) -> anyhow::Result<()> {
+    if aliases.len() > MAX_BULB_ALIASES {
+        anyhow::bail!("A bulb can have at most {} aliases", MAX_BULB_ALIASES);
+    }
+    let mut seen: Vec<String> = Vec::new();
+    for name in name.into_iter().chain(aliases.iter().map(String::as_str)) {
+        // Log entries quote names, and lightbulb://log/{date} and the like would shadow a bulb named after them
+        if name.trim() != name || name.is_empty() || name.chars().count() > MAX_BULB_NAME_LEN {
+            anyhow::bail!("Bulb name '{}' must be 1 to {} characters, without leading or trailing spaces", name, MAX_BULB_NAME_LEN);
+        }
+        if name.chars().any(|c| c == '"' || c.is_control()) {
+            anyhow::bail!("Bulb name '{}' must not contain quotes or control characters", name);
+        }
+        if RESERVED_BULB_IDS.iter().any(|reserved| reserved.eq_ignore_ascii_case(name)) {
+            anyhow::bail!("Bulb name '{}' is reserved; choose another", name);
+        }
+        if seen.contains(&name.to_lowercase()) {
+            anyhow::bail!("Bulb name '{}' is given twice", name);
+        }
+        seen.push(name.to_lowercase());
+    }
+    Ok(())
+}
+
+pub fn validate_room(room: &str) -> anyhow::Result<()> {
+    if normalize_tag(room).ok().as_deref() != Some(room) {
+        anyhow::bail!("Room '{}' must be a lowercase tag: letters, digits, '-' and '_'", room);
+    }
+    Ok(())
+}
+
+// A scene applied by name with apply_scene: the bulb is turned on at `brightness`, with either a white
+// temperature in `kelvin` or a palette `color`, or with its color left alone if neither is given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneConfig {
+    pub name: String,
+    pub brightness: u8,
+    pub kelvin: Option<u32>,
+    pub color: Option<String>,
+}
+
+impl SceneConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if normalize_tag(&self.name).ok().as_deref() != Some(self.name.as_str()) {
+            anyhow::bail!("Scene '{}' name must be a lowercase tag: letters, digits, '-' and '_'", self.name);
+        }
+        if !(1..=100).contains(&self.brightness) {
+            anyhow::bail!("Scene '{}' brightness must be between 1 and 100, got {}", self.name, self.brightness);
+        }
+        if let Some(kelvin) = self.kelvin
+            && !(MIN_KELVIN..=MAX_KELVIN).contains(&kelvin)
+        {
+            anyhow::bail!("Scene '{}' kelvin must be between {} and {}, got {}", self.name, MIN_KELVIN, MAX_KELVIN, kelvin);
+        }
+        match &self.color {
+            Some(_) if self.kelvin.is_some() => anyhow::bail!("Scene '{}' gives both kelvin and color; give one of them", self.name),
+            Some(color) if resolve_color(color).is_none() => anyhow::bail!("Scene '{}' color '{}' is not a palette color or #rrggbb code", self.name, color),
+            _ => Ok(()),
+        }
+    }
+}
+
+// At local time `at` ("HH:MM"), applies `scene` or does what a rule does: turns the bulb on, at `brightness` if
+// given, or off. Runs every day unless `days` lists some, e.g. ["sat", "sun"]; an `at` that is a cron expression,
+// e.g. "*/30 18-22 * * fri", runs at every minute it matches instead, and takes its days from the expression.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    pub name: String,
+    pub at: String,
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    #[serde(default)]
+    pub power: RulePower,
+    pub brightness: Option<u8>,
+    pub scene: Option<String>,
+}
+
+impl ScheduleConfig {
+    // Whether the scene it applies, if any, exists is for the caller to check
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if normalize_tag(&self.name).ok().as_deref() != Some(self.name.as_str()) {
+            anyhow::bail!("Schedule '{}' name must be a lowercase tag: letters, digits, '-' and '_'", self.name);
+        }
+        self.when()?;
+        match &self.scene {
+            Some(scene) if self.power == RulePower::Off || self.brightness.is_some() => {
+                anyhow::bail!("Schedule '{}' applies scene '{}', so it cannot also set power or brightness", self.name, scene)
+            },
+            Some(_) => Ok(()),
+            None => validate_rule_action("Schedule", &self.name, self.power, self.brightness),
+        }
+    }
+
+    pub fn time(&self) -> anyhow::Result<NaiveTime> {
+        parse_time(&self.at).with_context(|| format!("Schedule '{}' time '{}' is not HH:MM", self.name, self.at))
+    }
+
+    pub fn when(&self) -> anyhow::Result<ScheduleTime> {
+        if !self.at.trim().contains(char::is_whitespace) {
+            return self.time().map(ScheduleTime::Daily);
+        }
+        if !self.days.is_empty() {
+            anyhow::bail!("Schedule '{}' runs on the days of its cron expression, so it cannot also list days", self.name);
+        }
+        let cron = Cron::parse(&self.at).with_context(|| format!("Schedule '{}' time '{}' is not HH:MM or a cron expression", self.name, self.at))?;
+        Ok(ScheduleTime::Cron(cron))
+    }
+
+    pub fn runs_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+}
+
+// The price of a kWh in `currency`, the bulb's draw to cost its on time at, and a cheaper rate between two
+// local times when the tariff has one
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TariffConfig {
+    pub price_per_kwh: f64,
+    pub currency: String,
+    pub watts: f64,
+    pub off_peak: Option<OffPeakConfig>,
+}
+
+// From local time `at` ("HH:MM"), brightness commands are capped at `max` percent, the cap moving in a straight
+// line towards the next point's
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BrightnessPointConfig {
+    pub at: String,
+    pub max: u8,
+}
+
+impl BrightnessPointConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.time()?;
+        if !(1..=100).contains(&self.max) {
+            anyhow::bail!("Brightness schedule max at {} must be between 1 and 100, got {}", self.at, self.max);
+        }
+        Ok(())
+    }
+
+    pub fn time(&self) -> anyhow::Result<NaiveTime> {
+        parse_time(&self.at).with_context(|| format!("Brightness schedule time '{}' is not HH:MM", self.at))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OffPeakConfig {
+    pub price_per_kwh: f64,
+    pub start: String,
+    pub end: String,
+}
+
+// The safety watchdog's limit on continuous on-time, and whether it turns the bulb off once it passes or only
+// warns
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SafetyConfig {
+    pub max_on_hours: f64,
+    #[serde(default)]
+    pub action: WatchdogAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchdogAction {
+    #[default]
+    Off,
+    Warn,
+}
+
+impl SafetyConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.max_on_hours.is_finite() && self.max_on_hours > 0.0 && self.max_on_hours <= MAX_ON_HOURS) {
+            anyhow::bail!("Safety max_on_hours must be above 0 and at most {}, got {}", MAX_ON_HOURS, self.max_on_hours);
+        }
+        Ok(())
+    }
+
+    pub fn max_on(&self) -> TimeDelta {
+        TimeDelta::milliseconds((self.max_on_hours * 3_600_000.0).round() as i64)
+    }
+}
+
+// What a rule does to the bulb: turns it on, at the rule's brightness if it gives one, or off
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RulePower {
+    #[default]
+    On,
+    Off,
+}
+
+fn validate_rule_action(kind: &str, name: &str, power: RulePower, brightness: Option<u8>) -> anyhow::Result<()> {
+    match brightness {
+        Some(_) if power == RulePower::Off => anyhow::bail!("{} rule '{}' turns the bulb off, so it cannot set a brightness", kind, name),
+        Some(brightness) if !(1..=100).contains(&brightness) => {
+            anyhow::bail!("{} rule '{}' brightness must be between 1 and 100, got {}", kind, name, brightness)
+        },
+        _ => Ok(()),
+    }
+}
+
+// Where the weather scheduler reads the current weather from (an Open-Meteo forecast endpoint), how often, and
+// the rules it applies to each reading
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeatherConfig {
+    pub latitude: f64,
+    pub longitude: f64,
+    #[serde(default = "default_weather_url")]
+    pub url: String,
+    #[serde(default = "default_weather_poll_minutes")]
+    pub poll_minutes: u64,
+    #[serde(default)]
+    pub rules: Vec<WeatherRuleConfig>,
+}
+
+fn default_weather_url() -> String {
+    DEFAULT_WEATHER_URL.to_string()
+}
+
+fn default_weather_poll_minutes() -> u64 {
+    DEFAULT_WEATHER_POLL_MINUTES
+}
+
+// Sky conditions, each covering a range of WMO weather codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WeatherCondition {
+    Clear,
+    Cloudy,
+    Overcast,
+    Fog,
+    Rain,
+    Snow,
+    Thunderstorm,
+}
+
+// A rule applied once when the weather starts matching every condition given
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeatherRuleConfig {
+    pub name: String,
+    pub condition: Option<WeatherCondition>,
+    // Percent of the sky covered by cloud, at least
+    pub min_cloud_cover: Option<u8>,
+    // Whether the sun must be up, or down
+    pub daytime: Option<bool>,
+    #[serde(default)]
+    pub power: RulePower,
+    pub brightness: Option<u8>,
+}
+
+impl WeatherConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+            anyhow::bail!("Weather latitude must be between -90 and 90 and longitude between -180 and 180, got {}, {}", self.latitude, self.longitude);
+        }
+        if !(self.url.starts_with("http://") || self.url.starts_with("https://")) {
+            anyhow::bail!("Weather URL must start with http:// or https://: {}", self.url);
+        }
+        if !(1..=MAX_WEATHER_POLL_MINUTES).contains(&self.poll_minutes) {
+            anyhow::bail!("Weather poll_minutes must be between 1 and {}, got {}", MAX_WEATHER_POLL_MINUTES, self.poll_minutes);
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            rule.validate()?;
+            if self.rules[..index].iter().any(|earlier| earlier.name == rule.name) {
+                anyhow::bail!("Weather rule '{}' is configured twice", rule.name);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn poll_every(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.poll_minutes * 60)
+    }
+}
+
+impl WeatherRuleConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("Weather rules must have a name");
+        }
+        if self.condition.is_none() && self.min_cloud_cover.is_none() && self.daytime.is_none() {
+            anyhow::bail!("Weather rule '{}' needs at least one of condition, min_cloud_cover and daytime", self.name);
+        }
+        if let Some(cover) = self.min_cloud_cover
+            && cover > 100
+        {
+            anyhow::bail!("Weather rule '{}' min_cloud_cover must be at most 100, got {}", self.name, cover);
+        }
+        validate_rule_action("Weather", &self.name, self.power, self.brightness)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresenceConfig {
+    pub people: Vec<String>,
+    #[serde(default)]
+    pub rules: Vec<PresenceRuleConfig>,
+}
+
+// What a presence rule is applied on: anyone arriving or leaving, or the home going from empty to occupied
+// or back again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceEvent {
+    Arrive,
+    Leave,
+    FirstArrive,
+    LastLeave,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct PresenceRuleConfig {
+    pub name: String,
+    pub when: PresenceEvent,
+    // Only this person's arrivals or departures; anyone's when unset
+    pub person: Option<String>,
+    #[serde(default)]
+    pub power: RulePower,
+    pub brightness: Option<u8>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+impl PresenceConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (index, person) in self.people.iter().enumerate() {
+            if normalize_tag(person).ok().as_deref() != Some(person.as_str()) {
+                anyhow::bail!("Presence person '{}' must be a lowercase tag: letters, digits, '-' and '_'", person);
+            }
+            if self.people[..index].contains(person) {
+                anyhow::bail!("Presence person '{}' is configured twice", person);
+            }
+        }
+        for (index, rule) in self.rules.iter().enumerate() {
+            rule.validate()?;
+            if self.rules[..index].iter().any(|earlier| earlier.name == rule.name) {
+                anyhow::bail!("Presence rule '{}' is configured twice", rule.name);
+            }
+            if let Some(person) = rule.person.as_ref().filter(|person| !self.people.contains(person)) {
+                anyhow::bail!("Presence rule '{}' names '{}', who is not one of the configured people", rule.name, person);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PresenceRuleConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.name.trim().is_empty() {
+            anyhow::bail!("Presence rules must have a name");
+        }
+        validate_rule_action("Presence", &self.name, self.power, self.brightness)?;
+        self.window()?;
+        Ok(())
+    }
+
+    pub fn window(&self) -> anyhow::Result<(Option<NaiveTime>, Option<NaiveTime>)> {
+        parse_window(&self.after, &self.before, || format!("Presence rule '{}'", self.name))
+    }
+}
+
+// Templates replacing status and confirmation messages, keyed as in the locale catalogs, with what `{ $bulb }`
+// in them says
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MessagesConfig {
+    pub bulb_name: String,
+    #[serde(flatten)]
+    pub templates: BTreeMap<String, String>,
+}
+
+impl Default for MessagesConfig {
+    fn default() -> Self {
+        Self { bulb_name: DEFAULT_BULB_NAME.to_string(), templates: BTreeMap::new() }
+    }
+}
+
+impl MessagesConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.bulb_name.trim().is_empty() {
+            anyhow::bail!("Messages bulb_name must not be empty");
+        }
+        for (key, template) in &self.templates {
+            if !i18n::TEMPLATE_KEYS.contains(&key.as_str()) {
+                anyhow::bail!("Unknown message '{}'; the messages that can be replaced are {}", key, i18n::TEMPLATE_KEYS.join(", "));
+            }
+            if let Some(name) = i18n::placeholders(template).find(|name| !i18n::TEMPLATE_ARGUMENTS.contains(name)) {
+                anyhow::bail!("Message '{}' uses {{ ${} }}; templates can use {}", key, name, i18n::TEMPLATE_ARGUMENTS.map(|name| format!("{{ ${} }}", name)).join(", "));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for TariffConfig {
+    fn default() -> Self {
+        Self { price_per_kwh: 0.0, currency: DEFAULT_CURRENCY.to_string(), watts: DEFAULT_WATTS, off_peak: None }
+    }
+}
+
+impl TariffConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(self.price_per_kwh.is_finite() && self.price_per_kwh > 0.0) {
+            anyhow::bail!("Tariff price_per_kwh must be above 0, got {}", self.price_per_kwh);
+        }
+        if self.currency.trim().is_empty() {
+            anyhow::bail!("Tariff currency must not be empty");
+        }
+        if !(self.watts > 0.0 && self.watts <= MAX_WATTS) {
+            anyhow::bail!("Tariff watts must be above 0 and at most {}, got {}", MAX_WATTS, self.watts);
+        }
+        if let Some(off_peak) = &self.off_peak {
+            if !(off_peak.price_per_kwh.is_finite() && off_peak.price_per_kwh >= 0.0 && off_peak.price_per_kwh < self.price_per_kwh) {
+                anyhow::bail!("Tariff off-peak price_per_kwh must be below the standard {}, got {}", self.price_per_kwh, off_peak.price_per_kwh);
+            }
+            self.off_peak_hours()?;
+        }
+        Ok(())
+    }
+
+    pub fn off_peak_hours(&self) -> anyhow::Result<Option<(NaiveTime, NaiveTime)>> {
+        self.off_peak
+            .as_ref()
+            .map(|off_peak| {
+                let time = |time: &str| parse_time(time).with_context(|| format!("Tariff off-peak times must be HH:MM, got '{}'", time));
+                let hours = (time(&off_peak.start)?, time(&off_peak.end)?);
+                if hours.0 == hours.1 {
+                    anyhow::bail!("Tariff off-peak start and end must differ, got {} for both", off_peak.start);
+                }
+                anyhow::Ok(hours)
+            })
+            .transpose()
+    }
+}
+
+// Address the companion REST API listens on
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RestConfig {
+    pub bind: String,
+}
+
+impl RestConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        self.bind
+            .parse::<std::net::SocketAddr>()
+            .with_context(|| format!("REST bind address must be host:port, e.g. 127.0.0.1:8080: {}", self.bind))?;
+        Ok(())
+    }
+}
+
+// Bearer JWTs the HTTP transport accepts: signed with `secret` (HS256/384/512) or a key of the JWK set
+// read from `jwks_file` or fetched from `jwks_url` at startup (RS256/384/512, ES256/384, EdDSA)
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtConfig {
+    pub secret: Option<String>,
+    pub jwks_file: Option<String>,
+    pub jwks_url: Option<String>,
+    // The `iss` and `aud` claims a token must carry, when set
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    // Clock skew allowed when checking `exp` and `nbf`
+    pub leeway_secs: u64,
+}
+
+pub const DEFAULT_JWT_LEEWAY_SECS: u64 = 60;
+pub const MIN_JWT_SECRET_LEN: usize = 32;
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            secret: None,
+            jwks_file: None,
+            jwks_url: None,
+            issuer: None,
+            audience: None,
+            leeway_secs: DEFAULT_JWT_LEEWAY_SECS,
+        }
+    }
+}
+
+impl JwtConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.secret.is_none() && self.jwks_file.is_none() && self.jwks_url.is_none() {
+            anyhow::bail!("[jwt] needs a secret, a jwks_file or a jwks_url to check tokens with");
+        }
+        if self.jwks_file.is_some() && self.jwks_url.is_some() {
+            anyhow::bail!("[jwt] takes a jwks_file or a jwks_url, not both");
+        }
+        if let Some(secret) = &self.secret
+            && secret.len() < MIN_JWT_SECRET_LEN
+        {
+            anyhow::bail!("[jwt] secret must be at least {} bytes long, got {}", MIN_JWT_SECRET_LEN, secret.len());
+        }
+        if let Some(url) = &self.jwks_url
+            && !url.starts_with("https://")
+            && !url.starts_with("http://")
+        {
+            anyhow::bail!("[jwt] jwks_url must be an http(s) URL, got '{}'", url);
+        }
+        if self.leeway_secs > 3600 {
+            anyhow::bail!("[jwt] leeway_secs must be at most 3600, got {}", self.leeway_secs);
+        }
+        Ok(())
+    }
+}
+
+// A lightbulb-mcp server reached by running `command`, e.g. over ssh, and speaking MCP on its stdio
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownstreamConfig {
+    // Namespace for the bulb in the aggregator's tools and log
+    pub id: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    // Working directory for a local server, so it reads its own lightbulb.toml and writes its own log
+    pub dir: Option<String>,
+}
+
+// How an aggregator calls its downstream servers when a tool acts on several bulbs
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AggregatorConfig {
+    // Downstream calls one tool call keeps in flight at once
+    pub concurrency: usize,
+    // A downstream call that has not answered after this long fails as unreachable
+    pub timeout_secs: u64,
+}
+
+impl Default for AggregatorConfig {
+    fn default() -> Self {
+        Self { concurrency: DEFAULT_FAN_OUT_CONCURRENCY, timeout_secs: DEFAULT_DOWNSTREAM_TIMEOUT_SECS }
+    }
+}
+
+impl AggregatorConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.concurrency == 0 || self.timeout_secs == 0 {
+            anyhow::bail!("[aggregator] concurrency and timeout_secs must be at least 1");
+        }
+        Ok(())
+    }
+
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.timeout_secs)
+    }
+}
+
+// How long a tool call may run before it fails with TOOL_TIMED_OUT, so a stalled device or network cannot hold a
+// request open forever: `default_secs` for every tool, or a number of seconds of its own by tool name, where 0
+// lets it run as long as it takes
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ToolTimeoutsConfig {
+    pub default_secs: u64,
+    #[serde(flatten)]
+    pub tools: BTreeMap<String, u64>,
+}
+
+impl Default for ToolTimeoutsConfig {
+    fn default() -> Self {
+        Self { default_secs: DEFAULT_TOOL_TIMEOUT_SECS, tools: BTreeMap::new() }
+    }
+}
+
+impl ToolTimeoutsConfig {
+    // None when the tool may run as long as it takes
+    pub fn timeout(&self, tool: &str) -> Option<std::time::Duration> {
+        let secs = match self.tools.get(tool) {
+            Some(secs) => *secs,
+            None if UNTIMED_TOOLS.contains(&tool) => 0,
+            None => self.default_secs,
+        };
+        (secs > 0).then(|| std::time::Duration::from_secs(secs))
+    }
+}
+
+impl DownstreamConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.id.is_empty() || !self.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            anyhow::bail!("Downstream id may only contain letters, digits, '_' and '-': '{}'", self.id);
+        }
+        if self.command.is_empty() {
+            anyhow::bail!("Downstream '{}' needs a command", self.id);
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        toml::from_str(content).context("Invalid configuration")
+    }
+
+    // Reads the configuration file if present, falling back to defaults; env vars override file values
+    pub fn load() -> anyhow::Result<Self> {
+        let explicit_path = std::env::var(CONFIG_PATH_ENV).ok();
+        let path = explicit_path.clone().unwrap_or_else(|| CONFIG_FILE_NAME.to_string());
+        let mut config = match std::fs::read_to_string(&path) {
+            Ok(content) => Self {
+                source: Some(path.clone()),
+                ..Self::parse(&content).with_context(|| format!("Failed to load config file: {}", path))?
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && explicit_path.is_none() => Self::default(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read config file: {}", path)),
+        };
+        if let Ok(key_path) = std::env::var(SIGNING_KEY_ENV) {
+            config.signing_key = Some(key_path);
+        }
+        if let Ok(kind) = std::env::var(BACKEND_ENV) {
+            config.backend.kind = kind;
+        }
+        Ok(config)
+    }
+
+    pub fn is_guest_key(&self, key: &str) -> bool {
+        self.guest_keys.iter().any(|guest_key| guest_key == key)
+    }
+
+    pub fn is_admin_key(&self, key: &str) -> bool {
+        self.admin_keys.iter().any(|admin_key| admin_key == key)
+    }
+
+    // The configuration with every default filled in and its secrets replaced: webhook secrets, the MQTT
+    // password, guest and admin keys, the JWT secret, the log privacy salt and credential-like options of every backend, and the paths of webhook, trigger and chat URLs,
+    // which often carry a key of their own
+    pub fn redacted(&self) -> serde_json::Value {
+        let mut config = self.clone();
+        let bulb_options = config.bulbs.iter_mut().map(|bulb| &mut bulb.backend.options);
+        for options in std::iter::once(&mut config.backend.options).chain(bulb_options) {
+            for option in options.iter_mut().filter(|(name, _)| is_secret_option(name)).map(|(_, value)| value) {
+                *option = REDACTED.into();
+            }
+        }
+        for webhook in &mut config.webhooks {
+            webhook.url = redact_url(&webhook.url);
+            if webhook.secret.is_some() {
+                webhook.secret = Some(REDACTED.to_string());
+            }
+        }
+        if let Some(mqtt) = &mut config.mqtt
+            && mqtt.password.is_some()
+        {
+            mqtt.password = Some(REDACTED.to_string());
+        }
+        for trigger in &mut config.triggers {
+            trigger.url = redact_url(&trigger.url);
+        }
+        for notification in &mut config.notifications {
+            notification.webhook_url = redact_url(&notification.webhook_url);
+        }
+        config.guest_keys = vec![REDACTED.to_string(); config.guest_keys.len()];
+        config.admin_keys = vec![REDACTED.to_string(); config.admin_keys.len()];
+        if config.log_privacy.salt.is_some() {
+            config.log_privacy.salt = Some(REDACTED.to_string());
+        }
+        if let Some(jwt) = &mut config.jwt
+            && jwt.secret.is_some()
+        {
+            jwt.secret = Some(REDACTED.to_string());
+        }
+        serde_json::to_value(&config).unwrap_or_default()
+    }
+}
+
+fn is_secret_option(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SECRET_OPTION_WORDS.iter().any(|word| name.contains(word))
+}
+
+// Keeps the scheme and host, so it is still clear where requests go
+fn redact_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return REDACTED.to_string();
+    };
+    match rest.split_once(['/', '?', '#']) {
+        Some((host, _)) => format!("{}://{}/{}", scheme, host.rsplit('@').next().unwrap_or(host), REDACTED),
+        None => format!("{}://{}", scheme, rest.rsplit('@').next().unwrap_or(rest)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted_config_hides_secrets() {
+        let config = Config::parse(r#"
+            guest_keys = ["guest-123"]
+            admin_keys = ["parent-456"]
+
+            [backend]
+            type = "custom"
+            options = { host = "10.0.0.2", api_token = "t0ken" }
+
+            [[webhooks]]
+            url = "https://user:pw@hooks.example.com/lightbulb?key=abc"
+            secret = "s3cret"
+
+            [mqtt]
+            host = "broker.local"
+            password = "hunter2"
+
+            [jwt]
+            secret = "jwt-secret-0123456789abcdefghijklmn"
+
+            [[triggers]]
+            name = "ifttt"
+            url = "https://maker.ifttt.com/trigger/light/with/key/abc123"
+
+            [[notifications]]
+            service = "slack"
+            webhook_url = "https://hooks.slack.com/services/T0/B0/XYZ"
+
+            [[bulbs]]
+            id = "lamp"
+            backend = { type = "hue", options = { bridge = "192.168.1.20", app_key = "hue-k3y" } }
+        "#).unwrap();
+        let redacted = config.redacted();
+        let text = redacted.to_string();
+        for secret in ["guest-123", "parent-456", "t0ken", "s3cret", "hunter2", "jwt-secret", "abc123", "XYZ", "user:pw", "hue-k3y"] {
+            assert!(!text.contains(secret), "{} leaked: {}", secret, text);
+        }
+        assert_eq!(redacted["backend"]["options"]["host"], "10.0.0.2");
+        assert_eq!(redacted["bulbs"][0]["backend"]["options"]["bridge"], "192.168.1.20");
+        assert_eq!(redacted["webhooks"][0]["url"], "https://hooks.example.com/[redacted]");
+        assert_eq!(redacted["mqtt"]["port"], 1883);
+        assert_eq!(redacted["log_file"], "lightbulb.log");
+        assert_eq!(redacted["guest_keys"], serde_json::json!(["[redacted]"]));
+        assert_eq!(redact_url("not a url"), REDACTED);
+    }
+
+    #[test]
+    fn test_parse_backend_config() {
+        let config = Config::parse(r#"
+            log_file = "/var/log/lightbulb.log"
+
+            [backend]
+            type = "custom"
+            options = { host = "10.0.0.2", port = 8080 }
+        "#).unwrap();
+        assert_eq!(config.log_file, "/var/log/lightbulb.log");
+        assert_eq!(config.backend.kind, "custom");
+        assert_eq!(config.backend.options["port"], 8080);
+        assert_eq!(config.backend.state_ttl(), std::time::Duration::from_secs(DEFAULT_STATE_TTL_SECS));
+        assert_eq!(Config::parse("[backend]\nstate_ttl_secs = 0").unwrap().backend.state_ttl(), std::time::Duration::ZERO);
+        assert_eq!((config.backend.gamma, Config::parse("[backend]\ngamma = 1.0").unwrap().backend.gamma), (DEFAULT_GAMMA, 1.0));
+        let protected = Config::parse("[backend]\nmin_toggle_interval_ms = 1500").unwrap();
+        assert_eq!((config.backend.min_toggle_interval(), protected.backend.min_toggle_interval()), (std::time::Duration::ZERO, std::time::Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn test_parse_log_privacy() {
+        let config = Config::parse("[log_privacy]\nclients = \"hash\"\nreasons = \"omit\"\nsalt = \"pepper\"").unwrap();
+        assert_eq!((config.log_privacy.clients, config.log_privacy.reasons), (Redaction::Hash, Redaction::Omit));
+        assert!(config.log_privacy.validate().is_ok());
+        assert_eq!(config.redacted()["log_privacy"]["salt"], REDACTED);
+        assert_eq!(Config::default().log_privacy, LogPrivacyConfig::default());
+        let unsalted = Config::parse("[log_privacy]\nclients = \"hash\"").unwrap();
+        assert!(unsalted.log_privacy.validate().unwrap_err().to_string().contains("no salt"));
+        assert!(Config::parse("[log_privacy]\nclients = \"blur\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_log_history() {
+        assert_eq!(Config::default().log_history.rotation(), HistoryRotation::default());
+        let config = Config::parse("[log_history]\nmax_bytes = 65536\nkeep_files = 2").unwrap();
+        assert_eq!((config.log_history.enabled, config.log_history.rotation()), (true, HistoryRotation { max_bytes: 65536, keep_files: 2 }));
+        let tiny = Config::parse("[log_history]\nmax_bytes = 100").unwrap();
+        assert!(tiny.log_history.validate().unwrap_err().to_string().contains("at least 4096"));
+        assert!(!Config::parse("[log_history]\nenabled = false").unwrap().log_history.enabled);
+    }
+
+    #[test]
+    fn test_parse_log_encryption() {
+        let config = Config::parse("[log_encryption]\nkey_file = \"lightbulb.logkey\"").unwrap();
+        assert_eq!(config.log_encryption.as_ref().and_then(|encryption| encryption.key_file.as_deref()), Some("lightbulb.logkey"));
+        assert!(config.log_encryption.unwrap().validate().is_ok());
+        let both = LogEncryptionConfig { key_file: Some("key".to_string()), passphrase_env: Some("PASSPHRASE".to_string()) };
+        assert!(both.validate().is_err() && LogEncryptionConfig::default().validate().is_err());
+        let unset = LogEncryptionConfig { key_file: None, passphrase_env: Some("LIGHTBULB_TEST_UNSET_PASSPHRASE".to_string()) };
+        assert!(unset.passphrase().unwrap_err().to_string().contains("not set"));
+    }
+
+    #[test]
+    fn test_parse_log_compaction() {
+        assert_eq!(Config::parse("").unwrap().log_compaction, None);
+        let config = Config::parse("[log_compaction]\nkeep_days = 30").unwrap();
+        assert_eq!(config.log_compaction, Some(CompactionConfig { keep_days: 30 }));
+        assert!(Config::parse("[log_compaction]\nkeep_days = -1").is_err());
+    }
+
+    #[test]
+    fn test_parse_adaptive_brightness() {
+        assert_eq!(Config::parse("").unwrap().adaptive_brightness, None);
+        let adaptive = Config::parse("[adaptive_brightness]\ntarget_lux = 150").unwrap().adaptive_brightness.unwrap();
+        assert_eq!((adaptive.target_lux, adaptive.hysteresis_lux, adaptive.max_step_percent), (150.0, DEFAULT_HYSTERESIS_LUX, DEFAULT_MAX_STEP_PERCENT));
+        assert!(adaptive.validate().is_ok());
+        for invalid in ["target_lux = 0", "hysteresis_lux = 300", "max_step_percent = 0", "topic = \"sensors/+/lux\""] {
+            let config = Config::parse(&format!("[adaptive_brightness]\n{}", invalid)).unwrap();
+            assert!(config.adaptive_brightness.unwrap().validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_motion_rooms() {
+        let config = Config::parse(r#"
+            [[motion]]
+            room = "hallway"
+            off_after_secs = 120
+            topic = "zigbee2mqtt/hallway-motion"
+
+            [[motion]]
+            room = "kitchen"
+        "#).unwrap();
+        assert_eq!(config.motion.len(), 2);
+        assert_eq!(config.motion[0].off_after(), std::time::Duration::from_secs(120));
+        assert_eq!(config.motion[1].off_after_secs, DEFAULT_MOTION_OFF_AFTER_SECS);
+        assert!(config.motion.iter().all(|room| room.validate().is_ok()));
+        for invalid in ["room = \"Living Room\"", "room = \"hall\"\noff_after_secs = 0", "room = \"hall\"\ntopic = \"sensors/#\""] {
+            let config = Config::parse(&format!("[[motion]]\n{}", invalid)).unwrap();
+            assert!(config.motion[0].validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_tariff() {
+        let config = Config::parse(r#"
+            [tariff]
+            price_per_kwh = 0.30
+            currency = "EUR"
+            off_peak = { price_per_kwh = 0.12, start = "23:00", end = "07:00" }
+        "#).unwrap();
+        let tariff = config.tariff.unwrap();
+        assert_eq!((tariff.currency.as_str(), tariff.watts), ("EUR", DEFAULT_WATTS));
+        assert!(tariff.validate().is_ok());
+        assert_eq!(tariff.off_peak_hours().unwrap().map(|(start, _)| start.to_string()), Some("23:00:00".to_string()));
+        for invalid in [
+            "price_per_kwh = 0",
+            "price_per_kwh = 0.3\nwatts = 0",
+            "price_per_kwh = 0.3\noff_peak = { price_per_kwh = 0.4, start = \"23:00\", end = \"07:00\" }",
+            "price_per_kwh = 0.3\noff_peak = { price_per_kwh = 0.1, start = \"11pm\", end = \"07:00\" }",
+        ] {
+            let config = Config::parse(&format!("[tariff]\n{}", invalid)).unwrap();
+            assert!(config.tariff.unwrap().validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_scenes() {
+        let config = Config::parse(r#"
+            [[scenes]]
+            name = "movie"
+            brightness = 10
+            color = "amber"
+
+            [[scenes]]
+            name = "cleaning"
+            brightness = 100
+            kelvin = 5000
+        "#).unwrap();
+        assert_eq!(config.scenes.len(), 2);
+        assert_eq!(config.scenes[0].color.as_deref(), Some("amber"));
+        assert!(config.scenes.iter().all(|scene| scene.validate().is_ok()));
+        let invalid = [
+            "name = \"Movie Night\"\nbrightness = 10",
+            "name = \"dim\"\nbrightness = 0",
+            "name = \"warm\"\nbrightness = 50\nkelvin = 500",
+            "name = \"both\"\nbrightness = 50\nkelvin = 2700\ncolor = \"amber\"",
+            "name = \"odd\"\nbrightness = 50\ncolor = \"octarine\"",
+        ];
+        for invalid in invalid {
+            let config = Config::parse(&format!("[[scenes]]\n{}", invalid)).unwrap();
+            assert!(config.scenes[0].validate().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_brightness_schedule() {
+        let config = Config::parse("[[brightness_schedule]]\nat = \"22:00\"\nmax = 100\n[[brightness_schedule]]\nat = \"23:30\"\nmax = 25").unwrap();
+        assert_eq!(config.brightness_schedule[1], BrightnessPointConfig { at: "23:30".to_string(), max: 25 });
+        assert!(config.brightness_schedule.iter().all(|point| point.validate().is_ok()));
+        assert!(BrightnessPointConfig { at: "7pm".to_string(), max: 50 }.validate().is_err());
+        assert!(BrightnessPointConfig { at: "19:00".to_string(), max: 0 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_bulbs() {
+        let config = Config::parse(r#"
+            room = "living-room"
+
+            [[bulbs]]
+            id = "desk-lamp"
+            room = "study"
+            name = "Desk Lamp"
+            aliases = ["reading light"]
+
+            [[bulbs]]
+            id = "kitchen"
+            backend = { type = "simulated", options = { latency_ms = 50 } }
+        "#).unwrap();
+        assert_eq!(config.room.as_deref(), Some("living-room"));
+        assert!(config.bulbs.iter().all(|bulb| bulb.validate().is_ok()));
+        assert_eq!((config.bulbs[0].backend.kind.as_str(), config.bulbs[1].room.as_deref()), ("simulated", None));
+        let bulb = |id: &str, room: Option<&str>| BulbConfig { id: id.to_string(), room: room.map(str::to_string), name: None, aliases: Vec::new(), backend: BackendConfig::default() };
+        assert!(bulb("Desk Lamp", None).validate().is_err());
+        assert!(bulb("main", None).validate().is_err());
+        assert!(bulb("lamp", Some("Living Room")).validate().is_err());
+        assert_eq!((config.bulbs[0].name.as_deref(), config.bulbs[0].aliases.as_slice()), (Some("Desk Lamp"), ["reading light".to_string()].as_slice()));
+        for (name, aliases) in [("Log", vec![]), (" lamp", vec![]), ("Desk \"Lamp\"", vec![]), ("Desk Lamp", vec!["desk lamp"])] {
+            let aliases = aliases.into_iter().map(str::to_string).collect::<Vec<_>>();
+            assert!(validate_bulb_names(Some(name), &aliases).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_parse_schedules() {
+        let config = Config::parse(r#"
+            [[schedules]]
+            name = "wake-up"
+            at = "06:45"
+            days = ["mon", "Tuesday"]
+            brightness = 30
+
+            [[schedules]]
+            name = "evening"
+            at = "19:00"
+            scene = "relax"
+        "#).unwrap();
+        assert!(config.schedules.iter().all(|schedule| schedule.validate().is_ok()));
+        let wake_up = &config.schedules[0];
+        assert!(wake_up.runs_on(Weekday::Tue) && !wake_up.runs_on(Weekday::Sun) && config.schedules[1].runs_on(Weekday::Sun));
+        assert_eq!(wake_up.time().unwrap(), NaiveTime::from_hms_opt(6, 45, 0).unwrap());
+        let invalid = [
+            "name = \"late\"\nat = \"25:00\"",
+            "name = \"dim\"\nat = \"20:00\"\npower = \"off\"\nbrightness = 10",
+            "name = \"both\"\nat = \"20:00\"\nscene = \"movie\"\nbrightness = 10",
+        ];
+        for invalid in invalid {
+            let config = Config::parse(&format!("[[schedules]]\n{}", invalid)).unwrap();
+            assert!(config.schedules[0].validate().is_err(), "{}", invalid);
+        }
+        assert!(Config::parse("[[schedules]]\nname = \"x\"\nat = \"20:00\"\ndays = [\"someday\"]").is_err());
+    }
+
+    #[test]
+    fn test_tool_timeouts_fall_back_to_the_default() {
+        let timeouts = Config::parse("[tool_timeouts]\ndefault_secs = 10\nset_brightness = 2\nrun_diagnostics = 0").unwrap().tool_timeouts;
+        assert_eq!(timeouts.timeout("set_brightness"), Some(std::time::Duration::from_secs(2)));
+        assert_eq!(timeouts.timeout("turn_on_lightbulb"), Some(std::time::Duration::from_secs(10)));
+        assert_eq!((timeouts.timeout("run_diagnostics"), timeouts.timeout("run_macro")), (None, None));
+        assert_eq!(Config::parse("").unwrap().tool_timeouts.timeout("get_lightbulb_state"), Some(std::time::Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn test_journal_is_on_unless_turned_off() {
+        assert!(Config::parse("").unwrap().journal);
+        assert!(!Config::parse("journal = false").unwrap().journal);
+    }
+
+    #[test]
+    fn test_parse_webhooks() {
+        let config = Config::parse(r#"
+            [[webhooks]]
+            url = "https://example.com/hook"
+            secret = "s3cret"
+            events = ["state_changed"]
+        "#).unwrap();
+        assert_eq!(config.webhooks.len(), 1);
+        assert_eq!(config.webhooks[0].max_retries, 3);
+        assert!(config.webhooks[0].validate().is_ok());
+
+        let config = Config::parse("[[webhooks]]\nurl = \"ftp://example.com\"").unwrap();
+        assert!(config.webhooks[0].validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_mqtt_publisher() {
+        let config = Config::parse(r#"
+            [mqtt]
+            host = "broker.local"
+        "#).unwrap();
+        let mqtt = config.mqtt.unwrap();
+        assert_eq!((mqtt.port, mqtt.topic.as_str()), (1883, "lightbulb/state"));
+        assert!(mqtt.discovery);
+        assert_eq!(mqtt.discovery_prefix, "homeassistant");
+        assert!(mqtt.validate().is_ok());
+
+        let config = Config::parse("[mqtt]\nhost = \"broker.local\"\ntopic = \"home/#\"").unwrap();
+        assert!(config.mqtt.unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_triggers() {
+        let config = Config::parse(r#"
+            [[triggers]]
+            name = "late night"
+            url = "https://maker.ifttt.com/trigger/light_on/with/key/abc"
+            body = '{"value1": "{state}"}'
+            to = "ON"
+            after = "23:00"
+            before = "06:00"
+        "#).unwrap();
+        let trigger = &config.triggers[0];
+        assert_eq!(trigger.method, TriggerMethod::Post);
+        assert!(trigger.validate().is_ok());
+
+        let config = Config::parse("[[triggers]]\nname = \"t\"\nurl = \"https://x\"\nafter = \"11pm\"").unwrap();
+        assert!(config.triggers[0].validate().is_err());
+        let config = Config::parse("[[triggers]]\nname = \"t\"\nurl = \"https://x\"\nmethod = \"GET\"\nbody = \"{}\"").unwrap();
+        assert!(config.triggers[0].validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_notifications() {
+        let config = Config::parse(r#"
+            [[notifications]]
+            service = "discord"
+            webhook_url = "https://discord.com/api/webhooks/1/abc"
+            backend_unreachable = false
+            quiet_hours = { start = "22:00", end = "07:00" }
+        "#).unwrap();
+        let notification = &config.notifications[0];
+        assert_eq!(notification.service, ChatService::Discord);
+        assert!(notification.state_changed && !notification.backend_unreachable);
+        assert!(notification.validate().is_ok());
+
+        let config = Config::parse("[[notifications]]\nservice = \"slack\"\nwebhook_url = \"http://hooks.slack.com\"").unwrap();
+        assert!(config.notifications[0].validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_rest() {
+        let config = Config::parse("[rest]\nbind = \"127.0.0.1:8080\"").unwrap();
+        assert!(config.rest.unwrap().validate().is_ok());
+        let config = Config::parse("[rest]\nbind = \"localhost\"").unwrap();
+        assert!(config.rest.unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_jwt() {
+        let config = Config::parse("[jwt]\njwks_url = \"https://auth.example.com/.well-known/jwks.json\"\naudience = \"lightbulb\"").unwrap();
+        let jwt = config.jwt.unwrap();
+        assert!(jwt.validate().is_ok());
+        assert_eq!((jwt.audience.as_deref(), jwt.leeway_secs), (Some("lightbulb"), DEFAULT_JWT_LEEWAY_SECS));
+        assert!(Config::parse("[jwt]\nissuer = \"me\"").unwrap().jwt.unwrap().validate().is_err());
+        assert!(Config::parse("[jwt]\nsecret = \"short\"").unwrap().jwt.unwrap().validate().is_err());
+        assert!(Config::parse("[jwt]\njwks_file = \"a.json\"\njwks_url = \"https://b\"").unwrap().jwt.unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_empty_config_uses_defaults() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.log_file, LOG_FILE_NAME);
+        assert_eq!(config.backend.kind, "simulated");
+        assert!(config.signing_key.is_none());
+    }
+
+    #[test]
+    fn test_parse_downstream() {
+        let config = Config::parse(r#"
+            [[downstream]]
+            id = "kitchen"
+            command = "ssh"
+            args = ["pi@kitchen.local", "lightbulb-mcp"]
+        "#).unwrap();
+        assert_eq!(config.downstream[0].args, vec!["pi@kitchen.local", "lightbulb-mcp"]);
+        assert!(config.downstream[0].validate().is_ok());
+
+        let config = Config::parse("[[downstream]]\nid = \"living room\"\ncommand = \"lightbulb-mcp\"").unwrap();
+        assert!(config.downstream[0].validate().is_err());
+        assert_eq!(config.aggregator, AggregatorConfig::default());
+
+        let config = Config::parse("[aggregator]\nconcurrency = 2").unwrap();
+        assert_eq!((config.aggregator.concurrency, config.aggregator.timeout_secs), (2, DEFAULT_DOWNSTREAM_TIMEOUT_SECS));
+        assert!(Config::parse("[aggregator]\nconcurrency = 0").unwrap().aggregator.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_tag_rules() {
+        let config = Config::parse(r#"
+            [[tag_rules]]
+            tag = "after-midnight"
+            after = "00:00"
+            before = "05:00"
+        "#).unwrap();
+        assert_eq!(config.tag_rules[0].before.as_deref(), Some("05:00"));
+        assert!(config.tag_rules[0].validate().is_ok());
+
+        let config = Config::parse("[[tag_rules]]\ntag = \"movie night\"").unwrap();
+        assert!(config.tag_rules[0].validate().is_err());
+        let config = Config::parse("[[tag_rules]]\ntag = \"movie\"\nto = \"dim\"").unwrap();
+        assert!(config.tag_rules[0].validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_locale() {
+        assert_eq!(Config::default().locale, "en");
+        assert_eq!(Config::parse(r#"locale = "de-AT""#).unwrap().locale, "de-AT");
+    }
+
+    #[test]
+    fn test_parse_messages() {
+        let config = Config::parse(r#"
+            [messages]
+            bulb_name = "The desk lamp"
+            turned-on = "{ $bulb } is on at { $brightness }%"
+        "#).unwrap();
+        assert_eq!(config.messages.templates["turned-on"], "{ $bulb } is on at { $brightness }%");
+        assert!(config.messages.validate().is_ok());
+        assert_eq!(Config::default().messages.bulb_name, "The lightbulb");
+
+        let error = Config::parse("[messages]\nturned-up = \"on\"").unwrap().messages.validate().unwrap_err();
+        assert!(error.to_string().starts_with("Unknown message 'turned-up'"), "{}", error);
+        let error = Config::parse("[messages]\nturned-on = \"{ $colour }\"").unwrap().messages.validate().unwrap_err();
+        assert_eq!(error.to_string(), "Message 'turned-on' uses { $colour }; templates can use { $bulb }, { $state }, { $brightness }, { $status }");
+    }
+
+    #[test]
+    fn test_parse_weather() {
+        let weather = Config::parse(
+            r#"
+            [weather]
+            latitude = 52.52
+            longitude = 13.41
+
+            [[weather.rules]]
+            name = "gloomy day"
+            condition = "overcast"
+            daytime = true
+            brightness = 50
+            "#,
+        )
+        .unwrap()
+        .weather
+        .unwrap();
+        assert!(weather.validate().is_ok());
+        assert_eq!((weather.url.as_str(), weather.poll_every()), (DEFAULT_WEATHER_URL, std::time::Duration::from_secs(900)));
+        let rule = &weather.rules[0];
+        assert_eq!((rule.condition, rule.daytime, rule.power, rule.brightness), (Some(WeatherCondition::Overcast), Some(true), RulePower::On, Some(50)));
+
+        let invalid = |rule: &str| Config::parse(&format!("[weather]\nlatitude = 0\nlongitude = 0\n[[weather.rules]]\nname = \"x\"\n{}", rule)).unwrap().weather.unwrap().validate().is_err();
+        assert!(invalid(""));
+        assert!(invalid("daytime = false\npower = \"off\"\nbrightness = 20"));
+        assert!(invalid("min_cloud_cover = 120"));
+        assert!(!invalid("min_cloud_cover = 80\npower = \"off\""));
+        assert!(Config::parse("[weather]\nlatitude = 0\nlongitude = 0\n[[weather.rules]]\nname = \"x\"\ncondition = \"hail\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_presence() {
+        let presence = Config::parse(
+            r#"
+            [presence]
+            people = ["alice", "bob"]
+
+            [[presence.rules]]
+            name = "everyone left"
+            when = "last_leave"
+            power = "off"
+
+            [[presence.rules]]
+            name = "hallway at night"
+            when = "arrive"
+            after = "18:30"
+            before = "06:00"
+            brightness = 60
+            "#,
+        )
+        .unwrap()
+        .presence
+        .unwrap();
+        assert!(presence.validate().is_ok());
+        assert_eq!((presence.rules[0].when, presence.rules[0].power), (PresenceEvent::LastLeave, RulePower::Off));
+        assert_eq!(presence.rules[1].window().unwrap().0, Some(NaiveTime::from_hms_opt(18, 30, 0).unwrap()));
+
+        let invalid = |rule: &str| {
+            Config::parse(&format!("[presence]\npeople = [\"alice\"]\n[[presence.rules]]\nname = \"x\"\nwhen = \"arrive\"\n{}", rule))
+                .unwrap()
+                .presence
+                .unwrap()
+                .validate()
+                .is_err()
+        };
+        assert!(!invalid("person = \"alice\""));
+        assert!(invalid("person = \"carol\""));
+        assert!(invalid("after = \"dusk\""));
+        assert!(invalid("power = \"off\"\nbrightness = 10"));
+        assert!(Config::parse("[presence]\npeople = [\"Alice Smith\"]").unwrap().presence.unwrap().validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_contact_sensors() {
+        let config = Config::parse(
+            r#"
+            [[contact]]
+            sensor = "closet-door"
+            topic = "zigbee2mqtt/closet-door"
+
+            [[contact]]
+            sensor = "back-door"
+            bulbs = ["porch", "hallway"]
+            on_close = "on"
+            brightness = 40
+            "#,
+        )
+        .unwrap();
+        let (closet, back) = (&config.contact[0], &config.contact[1]);
+        assert!(closet.validate().is_ok() && back.validate().is_ok());
+        assert_eq!((closet.action(true), closet.action(false)), (RulePower::On, RulePower::Off));
+        assert_eq!((back.action(false), back.bulbs.len()), (RulePower::On, 2));
+
+        let invalid = |extra: &str| Config::parse(&format!("[[contact]]\nsensor = \"door\"\n{}", extra)).unwrap().contact[0].validate().is_err();
+        assert!(invalid("on_open = \"off\"\nbrightness = 50"));
+        assert!(invalid("topic = \"doors/#\""));
+        assert!(Config::parse("[[contact]]\nsensor = \"Front Door\"").unwrap().contact[0].validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_safety() {
+        let safety = Config::parse("[safety]\nmax_on_hours = 8").unwrap().safety.unwrap();
+        assert_eq!((safety.action, safety.max_on()), (WatchdogAction::Off, TimeDelta::hours(8)));
+        assert!(safety.validate().is_ok());
+        let safety = Config::parse("[safety]\nmax_on_hours = 0.5\naction = \"warn\"").unwrap().safety.unwrap();
+        assert_eq!((safety.action, safety.max_on()), (WatchdogAction::Warn, TimeDelta::minutes(30)));
+        for invalid in ["0", "-1", "9000"] {
+            assert!(Config::parse(&format!("[safety]\nmax_on_hours = {}", invalid)).unwrap().safety.unwrap().validate().is_err(), "{}", invalid);
+        }
+        assert!(Config::parse("[safety]\nmax_on_hours = 8\naction = \"explode\"").is_err());
+    }
+
+    #[test]
+    fn test_parse_curfews() {
+        let config = Config::parse(
+            r#"
+            admin_keys = ["parent-laptop"]
+
+            [[curfews]]
+            name = "bedtime"
+            after = "20:30"
+            before = "07:00"
+            tools = ["turn_on_lightbulb"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!((config.admin_keys.as_slice(), config.curfews[0].tools.as_slice()), (&["parent-laptop".to_string()][..], &["turn_on_lightbulb".to_string()][..]));
+        assert!(config.curfews[0].validate().is_ok());
+        let never = Config::parse("[[curfews]]\nname = \"never\"\nafter = \"07:00\"\nbefore = \"07:00\"").unwrap();
+        assert!(never.curfews[0].validate().unwrap_err().to_string().contains("never applies"));
+        let late = Config::parse("[[curfews]]\nname = \"late\"\nafter = \"9pm\"\nbefore = \"07:00\"").unwrap();
+        assert!(late.curfews[0].validate().is_err());
+    }
+
+    #[test]
+    fn test_guest_keys() {
+        let config = Config::parse(r#"guest_keys = ["visitor-1"]"#).unwrap();
+        assert!(config.is_guest_key("visitor-1"));
+        assert!(!config.is_guest_key("visitor-2"));
+        let config = Config::parse(r#"admin_keys = ["parent-1"]"#).unwrap();
+        assert!(config.is_admin_key("parent-1") && !config.is_guest_key("parent-1"));
+        assert!(!config.is_admin_key("parent-laptop"));
+    }
+}
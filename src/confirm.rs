@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::time::{Duration, Instant};
+
+use crate::error::LightError;
+
+// Long enough to read what a destructive tool is about to do and call it again, short enough that a token
+// left in an old transcript is of no use
+pub const CONFIRMATION_WINDOW: Duration = Duration::from_secs(120);
+
+struct Pending {
+    action: String,
+    issued: Instant,
+}
+
+// Tokens that destructive tools hand out on a first call and require on a second, so no single call destroys
+// anything. A token confirms the one action it was issued for, once, within the window.
+#[derive(Clone)]
+pub struct Confirmations {
+    pending: Arc<Mutex<HashMap<String, Pending>>>,
+    window: Duration,
+}
+
+impl Confirmations {
+    pub fn new(window: Duration) -> Self {
+        Self { pending: Arc::default(), window }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    // A new token for `action`, which error messages name it by
+    pub fn issue(&self, action: &str) -> String {
+        let token = format!("{:016x}", rand::random::<u64>());
+        let mut pending = self.lock();
+        pending.retain(|_, pending| pending.issued.elapsed() < self.window);
+        pending.insert(token.clone(), Pending { action: action.to_string(), issued: Instant::now() });
+        token
+    }
+
+    // Uses up `token`, failing unless it was issued for `action` within the window
+    pub fn redeem(&self, token: &str, action: &str) -> Result<(), LightError> {
+        let mut pending = self.lock();
+        pending.retain(|_, pending| pending.issued.elapsed() < self.window);
+        let issued = pending.remove(token.trim()).ok_or_else(|| {
+            LightError::ConfirmationInvalid(format!("Unknown or expired confirmation token '{}'; ask again for a new one", token.trim()))
+        })?;
+        if issued.action != action {
+            return Err(LightError::ConfirmationInvalid(format!(
+                "Confirmation token '{}' was issued to {}, not to {}",
+                token.trim(),
+                issued.action,
+                action
+            )));
+        }
+        Ok(())
+    }
+
+    // Forgets every outstanding token
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Pending>> {
+        self.pending.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for Confirmations {
+    fn default() -> Self {
+        Self::new(CONFIRMATION_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_tokens_confirm_one_action_once() {
+        let confirmations = Confirmations::default();
+        let token = confirmations.issue("erase the log");
+        assert_eq!(token.len(), 16);
+        let error = confirmations.redeem(&token, "reset the bulb").unwrap_err();
+        assert_eq!(error.to_string(), format!("Confirmation token '{}' was issued to erase the log, not to reset the bulb", token));
+        // A token offered for the wrong action is used up all the same
+        assert!(confirmations.redeem(&token, "erase the log").is_err());
+
+        let token = confirmations.issue("erase the log");
+        confirmations.redeem(&format!(" {} ", token), "erase the log").unwrap();
+        assert_eq!(confirmations.redeem(&token, "erase the log").unwrap_err().code(), "CONFIRMATION_INVALID");
+
+        let token = confirmations.issue("erase the log");
+        tokio::time::advance(CONFIRMATION_WINDOW).await;
+        assert!(confirmations.redeem(&token, "erase the log").is_err());
+    }
+}
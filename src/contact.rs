@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use crate::actor::{LightHandle, PowerChange, Sequenced};
+use crate::config::ContactConfig;
+use crate::error::LightError;
+
+// Tags every change a contact sensor makes, beside the sensor's name
+pub const CONTACT_TAG: &str = "contact";
+
+// The door and window sensors shared by every session and the MQTT subscriptions
+#[derive(Clone, Default)]
+pub struct ContactSensors {
+    sensors: Arc<Vec<ContactConfig>>,
+}
+
+impl ContactSensors {
+    pub fn new(sensors: Vec<ContactConfig>) -> Self {
+        Self { sensors: Arc::new(sensors) }
+    }
+
+    pub fn sensors(&self) -> &[ContactConfig] {
+        &self.sensors
+    }
+
+    pub fn sensor(&self, name: &str) -> Result<&ContactConfig, LightError> {
+        sensor(&self.sensors, name)
+    }
+
+    // Switches the bulb as the sensor's opening, or closing, says
+    pub async fn report(&self, light: &LightHandle, sensor: &ContactConfig, open: bool) -> Result<Sequenced<PowerChange>, LightError> {
+        let light = light
+            .acting_as(format!("{} contact sensor", sensor.sensor))
+            .with_reason(Some(format!("{} {}", sensor.sensor, if open { "opened" } else { "closed" })))
+            .tagged(vec![CONTACT_TAG.to_string(), sensor.sensor.clone()]);
+        crate::rules::apply(&light, sensor.action(open), sensor.brightness).await
+    }
+}
+
+// The named sensor from `sensors`, with the configured ones listed when there is none by that name
+pub fn sensor<'a>(sensors: &'a [ContactConfig], name: &str) -> Result<&'a ContactConfig, LightError> {
+    if sensors.is_empty() {
+        return Err(LightError::InvalidParameter("No contact sensors are configured".to_string()));
+    }
+    sensors.iter().find(|sensor| sensor.sensor == name).ok_or_else(|| {
+        let names = sensors.iter().map(|sensor| sensor.sensor.as_str()).collect::<Vec<_>>().join(", ");
+        LightError::InvalidParameter(format!("Unknown contact sensor '{}'; configured sensors: {}", name, names))
+    })
+}
+
+// Whether a sensor's message says it opened (true) or closed (false): a bare OPEN, ON, true or 1, or CLOSED, OFF,
+// false or 0, or JSON with `contact` (true when closed, as Zigbee2MQTT publishes it) or `open`; anything else is
+// ignored
+pub fn parse_contact(payload: &[u8]) -> Option<bool> {
+    let text = std::str::from_utf8(payload).ok()?.trim();
+    if ["open", "opened", "on", "true", "1"].iter().any(|word| text.eq_ignore_ascii_case(word)) {
+        return Some(true);
+    }
+    if ["closed", "close", "off", "false", "0"].iter().any(|word| text.eq_ignore_ascii_case(word)) {
+        return Some(false);
+    }
+    let value = serde_json::from_str::<serde_json::Value>(text).ok()?;
+    match value.get("contact").and_then(serde_json::Value::as_bool) {
+        Some(contact) => Some(!contact),
+        None => value.get("open").and_then(serde_json::Value::as_bool),
+    }
+}
+
+// Reports each sensor with a topic whenever it publishes
+#[cfg(feature = "mqtt")]
+pub fn spawn_contact_subscribers(
+    contacts: ContactSensors,
+    publisher: Arc<dyn crate::mqtt::StatePublisher + Send + Sync>,
+    light: LightHandle,
+) {
+    for sensor in contacts.sensors().iter().filter(|sensor| sensor.topic.is_some()).cloned() {
+        let (contacts, publisher, light) = (contacts.clone(), publisher.clone(), light.clone());
+        tokio::spawn(async move {
+            let topic = sensor.topic.clone().unwrap_or_default();
+            let mut messages = match publisher.subscribe(&topic).await {
+                Ok(messages) => messages,
+                Err(e) => return crate::diagnostic!(Warn, "could not subscribe to contact topic {}: {}", topic, e),
+            };
+            while let Some(payload) = messages.recv().await {
+                let Some(open) = parse_contact(&payload) else {
+                    continue;
+                };
+                match contacts.report(&light, &sensor, open).await {
+                    Err(LightError::ActorStopped) => return,
+                    Err(e) => crate::diagnostic!(Warn, "contact sensor {} could not switch the lightbulb: {}", sensor.sensor, e),
+                    Ok(applied) => crate::diagnostic!(Debug, "contact sensor {} {}: {:?}", sensor.sensor, if open { "opened" } else { "closed" }, applied.outcome),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::config::RulePower;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+    use crate::state::StateMachine;
+
+    fn closet() -> ContactConfig {
+        ContactConfig {
+            sensor: "closet-door".to_string(),
+            topic: None,
+            bulbs: Vec::new(),
+            on_open: RulePower::On,
+            on_close: RulePower::Off,
+            brightness: Some(70),
+        }
+    }
+
+    #[test]
+    fn test_sensor_payloads() {
+        assert_eq!((parse_contact(b"OPEN\n"), parse_contact(b"closed"), parse_contact(b"1")), (Some(true), Some(false), Some(true)));
+        assert_eq!(parse_contact(br#"{"contact": true, "battery": 91}"#), Some(false));
+        assert_eq!(parse_contact(br#"{"open": true}"#), Some(true));
+        assert_eq!((parse_contact(b"{}"), parse_contact(b"tamper")), (None, None));
+    }
+
+    #[tokio::test]
+    async fn test_opening_turns_the_bulb_on_and_closing_off() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let contacts = ContactSensors::new(vec![closet()]);
+        let sensor = contacts.sensor("closet-door").unwrap();
+
+        contacts.report(&light, sensor, true).await.unwrap();
+        let snapshot = light.snapshot().await.unwrap();
+        assert_eq!((snapshot.power, snapshot.brightness), (Some(PowerState::On), 70));
+        contacts.report(&light, sensor, false).await.unwrap();
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::Off));
+        let log = light.read_log().await.unwrap();
+        assert!(log.contains("turned OFF [contact,closet-door] by closet-door contact sensor (reason: closet-door closed)"), "{}", log);
+
+        assert!(contacts.sensor("garage").unwrap_err().to_string().contains("configured sensors: closet-door"));
+        assert!(ContactSensors::default().sensor("closet-door").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use chrono::NaiveTime;
+
+use crate::config::CurfewConfig;
+use crate::error::LightError;
+use crate::schedule::in_window;
+
+// One configured curfew, its window parsed
+#[derive(Debug, Clone)]
+pub struct Curfew {
+    config: CurfewConfig,
+    after: NaiveTime,
+    before: NaiveTime,
+}
+
+impl Curfew {
+    pub fn new(config: CurfewConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let (after, before) = config.window()?;
+        Ok(Self { config, after, before })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    pub fn bulbs(&self) -> &[String] {
+        &self.config.bulbs
+    }
+
+    pub fn tools(&self) -> &[String] {
+        &self.config.tools
+    }
+
+    // A curfew naming bulbs only covers a call on one of them, so it never covers a server's own bulb
+    fn covers(&self, tool: &str, bulb: Option<&str>, now: NaiveTime) -> bool {
+        let tool_matches = self.config.tools.is_empty() || self.config.tools.iter().any(|covered| covered == tool);
+        let bulb_matches = self.config.bulbs.is_empty() || bulb.is_some_and(|bulb| self.config.bulbs.iter().any(|covered| covered == bulb));
+        tool_matches && bulb_matches && in_window(now, Some(self.after), Some(self.before))
+    }
+}
+
+// A deployment's curfews; admin sessions, started with one of the config's `admin_keys`, skip the check
+#[derive(Debug, Clone, Default)]
+pub struct Curfews {
+    curfews: Vec<Curfew>,
+}
+
+impl Curfews {
+    pub fn new(configs: &[CurfewConfig]) -> anyhow::Result<Self> {
+        let curfews = configs.iter().cloned().map(Curfew::new).collect::<anyhow::Result<_>>()?;
+        Ok(Self { curfews })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Curfew> {
+        self.curfews.iter()
+    }
+
+    // Fails with OutsideAllowedHours when a curfew covers `tool`, called on `bulb` at local time `now`
+    pub fn check(&self, tool: &str, bulb: Option<&str>, now: NaiveTime) -> Result<(), LightError> {
+        match self.curfews.iter().find(|curfew| curfew.covers(tool, bulb, now)) {
+            Some(curfew) => Err(LightError::OutsideAllowedHours {
+                tool: tool.to_string(),
+                curfew: curfew.config.name.clone(),
+                allowed_from: curfew.config.before.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    fn curfew(tools: &[&str], bulbs: &[&str]) -> CurfewConfig {
+        CurfewConfig {
+            name: "bedtime".to_string(),
+            after: "20:30".to_string(),
+            before: "07:00".to_string(),
+            tools: tools.iter().map(|tool| tool.to_string()).collect(),
+            bulbs: bulbs.iter().map(|bulb| bulb.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_curfews_cover_their_tools_at_night() {
+        let curfews = Curfews::new(&[curfew(&["turn_on_lightbulb"], &[])]).unwrap();
+        let error = curfews.check("turn_on_lightbulb", None, time("22:00")).unwrap_err();
+        assert_eq!(error.to_string(), "'turn_on_lightbulb' is outside allowed hours during the 'bedtime' curfew; it can be called again from 07:00");
+        assert_eq!(error.data()["curfew"], "bedtime");
+
+        assert!(curfews.check("turn_off_lightbulb", None, time("22:00")).is_ok());
+        assert!(curfews.check("turn_on_lightbulb", None, time("07:00")).is_ok());
+        assert!(curfews.check("turn_on_lightbulb", None, time("06:59")).is_err());
+    }
+
+    #[test]
+    fn test_curfews_naming_bulbs_cover_only_those() {
+        let curfews = Curfews::new(&[curfew(&[], &["kids-room"])]).unwrap();
+        assert!(curfews.check("get_lightbulb_status", Some("kids-room"), time("23:00")).is_err());
+        assert!(curfews.check("turn_on_lightbulb", Some("kitchen"), time("23:00")).is_ok());
+        assert!(curfews.check("turn_on_lightbulb", None, time("23:00")).is_ok());
+    }
+}
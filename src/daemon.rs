@@ -0,0 +1,153 @@
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+pub const DEFAULT_PID_FILE: &str = "lightbulb-mcp.pid";
+pub const DEFAULT_DIAGNOSTICS_FILE: &str = "lightbulb-mcp.err";
+// Set in the detached server to the PID file it owns
+const DAEMON_ENV: &str = "LIGHTBULB_DAEMON_PID_FILE";
+// The detached server writes this to its stdout, a pipe to the starting process, once it is serving
+const READY_LINE: &str = "READY";
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+// The PID recorded in `pid_file`, if there is one
+pub fn read_pid(pid_file: &str) -> anyhow::Result<Option<i32>> {
+    let contents = match std::fs::read_to_string(pid_file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read PID file: {}", pid_file)),
+    };
+    // 0 and negative PIDs name process groups to kill(), never one server
+    match contents.trim().parse::<i32>() {
+        Ok(pid) if pid > 0 => Ok(Some(pid)),
+        _ => anyhow::bail!("PID file {} does not hold a process ID: {:?}", pid_file, contents.trim()),
+    }
+}
+
+pub fn is_running(pid: i32) -> bool {
+    // Signal 0 only checks that the process exists; EPERM means it does, under another user
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+// The server recorded in `pid_file`, if it is still running; a stale file is ignored
+pub fn running(pid_file: &str) -> anyhow::Result<Option<i32>> {
+    Ok(read_pid(pid_file)?.filter(|pid| is_running(*pid)))
+}
+
+// Starts the server again in its own session with no terminal, serving `args` headless, and returns its PID
+// once it is serving. Its stderr is appended to `diagnostics_file`.
+pub fn start(args: Vec<OsString>, pid_file: &str, diagnostics_file: &str) -> anyhow::Result<u32> {
+    if let Some(pid) = running(pid_file)? {
+        anyhow::bail!("lightbulb-mcp is already running (pid {}, from {})", pid, pid_file);
+    }
+    let diagnostics = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(diagnostics_file)
+        .with_context(|| format!("Failed to open diagnostics file: {}", diagnostics_file))?;
+    let mut command = Command::new(std::env::current_exe()?);
+    command.args(args).env(DAEMON_ENV, pid_file).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(diagnostics);
+    // Leaves the terminal's session, so closing the terminal does not hang the server up
+    unsafe {
+        command.pre_exec(|| if libc::setsid() == -1 { Err(std::io::Error::last_os_error()) } else { Ok(()) });
+    }
+    let mut child = command.spawn().context("Failed to start the daemon")?;
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line)?;
+    if line.trim() == READY_LINE {
+        return Ok(child.id());
+    }
+    let status = child.wait()?;
+    anyhow::bail!("The daemon exited during startup ({}); see {} for why", status, diagnostics_file)
+}
+
+// Sends SIGTERM to the server recorded in `pid_file` and waits for it to exit, returning its PID
+pub fn stop(pid_file: &str) -> anyhow::Result<i32> {
+    let Some(pid) = running(pid_file)? else {
+        anyhow::bail!("lightbulb-mcp is not running (no live process in {})", pid_file);
+    };
+    if unsafe { libc::kill(pid, libc::SIGTERM) } == -1 {
+        return Err(std::io::Error::last_os_error()).with_context(|| format!("Failed to stop pid {}", pid));
+    }
+    let started = Instant::now();
+    while is_running(pid) {
+        if started.elapsed() >= STOP_TIMEOUT {
+            anyhow::bail!("lightbulb-mcp (pid {}) did not stop within {}s", pid, STOP_TIMEOUT.as_secs());
+        }
+        std::thread::sleep(STOP_POLL);
+    }
+    // Normally gone already, removed by the server on its way out
+    let _ = std::fs::remove_file(pid_file);
+    Ok(pid)
+}
+
+// The PID file of a detached server, removed again when the server exits cleanly
+#[derive(Debug)]
+pub struct PidFile {
+    path: String,
+}
+
+impl PidFile {
+    pub fn create(path: &str) -> anyhow::Result<Self> {
+        std::fs::write(path, format!("{}\n", std::process::id())).with_context(|| format!("Failed to write PID file: {}", path))?;
+        Ok(Self { path: path.to_string() })
+    }
+
+    // Some in a server started by `start`
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        std::env::var(DAEMON_ENV).ok().map(|path| Self::create(&path)).transpose()
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// Tells the process that started this one that it is serving; does nothing outside daemon mode
+pub fn report_ready() {
+    if std::env::var_os(DAEMON_ENV).is_some() {
+        println!("{}", READY_LINE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pid_file(name: &str) -> String {
+        std::env::temp_dir().join(format!("lightbulb-daemon-{}-{}.pid", name, std::process::id())).display().to_string()
+    }
+
+    #[test]
+    fn test_pid_file_names_this_process_until_dropped() {
+        let path = pid_file("own");
+        let pid = PidFile::create(&path).unwrap();
+        assert_eq!(read_pid(&path).unwrap(), Some(std::process::id() as i32));
+        assert_eq!(running(&path).unwrap(), Some(std::process::id() as i32));
+        drop(pid);
+        assert_eq!(read_pid(&path).unwrap(), None);
+    }
+
+    #[test]
+    fn test_stale_and_bad_pid_files() {
+        let path = pid_file("stale");
+        // Above any pid_max, so never a live process
+        std::fs::write(&path, "2000000000\n").unwrap();
+        assert_eq!(running(&path).unwrap(), None);
+        assert!(stop(&path).unwrap_err().to_string().contains("not running"));
+        for contents in ["0", "-1", "lightbulb"] {
+            std::fs::write(&path, contents).unwrap();
+            assert!(read_pid(&path).is_err(), "{}", contents);
+        }
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::model::PowerState;
+
+// Wall clock readings before this are treated as an unset clock
+const EARLIEST_PLAUSIBLE_TIME: &str = "2024-01-01T00:00:00Z";
+const MAX_CLOCK_SKEW_SECS: i64 = 60;
+// How far behind its time a background task may fall before it is reported stuck
+const TASK_GRACE: std::time::Duration = std::time::Duration::from_secs(30);
+pub const SCHEDULER_TASK: &str = "scheduler";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct DiagnosticCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct DiagnosticsReport {
+    // True when no check failed; skipped checks do not count against it
+    pub healthy: bool,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+// Raw results gathered by the light actor, which owns the logger and backend, with the background tasks and
+// state files of the service around it
+#[derive(Debug, Clone)]
+pub struct HealthProbe {
+    pub backend_name: String,
+    pub log_writable: Result<(), String>,
+    pub log_content: Result<String, String>,
+    pub backend_power: Result<PowerState, String>,
+    pub expected_power: Option<PowerState>,
+    pub tasks: Vec<TaskProbe>,
+    pub state_files: Vec<StateFileProbe>,
+}
+
+// The subset of a HealthProbe that says whether the server can take changes, without reading the log
+#[derive(Debug, Clone)]
+pub struct ReadinessProbe {
+    pub backend_name: String,
+    pub log_writable: Result<(), String>,
+    pub backend_power: Result<PowerState, String>,
+    pub expected_power: Option<PowerState>,
+    pub tasks: Vec<TaskProbe>,
+}
+
+// A background task as it was when probed: the scheduler, due every `every`, or a timer, due once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskProbe {
+    pub name: String,
+    pub every: Option<std::time::Duration>,
+    // How long since the task last did its work, or since it started
+    pub idle: std::time::Duration,
+    // How far past its time the task is, zero while it is not yet due
+    pub overdue: std::time::Duration,
+}
+
+// A file the server keeps state in between runs, read back as the next start would read it; Ok(false) when
+// nothing has been saved to it yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateFileProbe {
+    pub name: &'static str,
+    pub path: String,
+    pub read: Result<bool, String>,
+}
+
+impl StateFileProbe {
+    pub fn read(name: &'static str, path: &str, parse: impl FnOnce(&str) -> anyhow::Result<()>) -> Self {
+        let read = match std::fs::read_to_string(path) {
+            Ok(saved) => parse(&saved).map(|()| true).map_err(|e| format!("{:#}", e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.to_string()),
+        };
+        Self { name, path: path.to_string(), read }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Task {
+    every: Option<std::time::Duration>,
+    due: Instant,
+    beat: Instant,
+}
+
+// The background tasks working on a bulb beside its actor, each with when it last did its work, for
+// run_diagnostics to tell a stuck one. Clones share them.
+#[derive(Debug, Clone, Default)]
+pub struct Tasks(Arc<Mutex<BTreeMap<String, Task>>>);
+
+impl Tasks {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, Task>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // A task that does its work every `every`, such as the scheduler
+    pub fn start_every(&self, name: &str, every: std::time::Duration) {
+        let now = Instant::now();
+        self.lock().insert(name.to_string(), Task { every: Some(every), due: now + every, beat: now });
+    }
+
+    // A task that does its work once, `after` from now, such as a timer
+    pub fn start_once(&self, name: &str, after: std::time::Duration) {
+        let now = Instant::now();
+        self.lock().insert(name.to_string(), Task { every: None, due: now + after, beat: now });
+    }
+
+    // The task did its work, and is next due a period on
+    pub fn beat(&self, name: &str) {
+        if let Some(task) = self.lock().get_mut(name) {
+            task.beat = Instant::now();
+            task.due = task.beat + task.every.unwrap_or_default();
+        }
+    }
+
+    pub fn finish(&self, name: &str) {
+        self.lock().remove(name);
+    }
+
+    pub fn probe(&self) -> Vec<TaskProbe> {
+        let now = Instant::now();
+        self.lock()
+            .iter()
+            .map(|(name, task)| TaskProbe {
+                name: name.clone(),
+                every: task.every,
+                idle: now.saturating_duration_since(task.beat),
+                overdue: now.saturating_duration_since(task.due),
+            })
+            .collect()
+    }
+}
+
+impl DiagnosticsReport {
+    fn new(checks: Vec<DiagnosticCheck>) -> Self {
+        Self { healthy: checks.iter().all(|check| check.status != CheckStatus::Fail), checks }
+    }
+}
+
+pub fn build_report(probe: &HealthProbe, now: DateTime<Utc>) -> DiagnosticsReport {
+    DiagnosticsReport::new(vec![
+        check_log_writable(&probe.log_writable),
+        check_log_integrity(probe),
+        check_state_files(&probe.state_files),
+        check_backend(&probe.backend_name, &probe.backend_power, probe.expected_power),
+        check_scheduler(&probe.tasks),
+        check_clock(probe, now),
+    ])
+}
+
+pub fn build_readiness_report(probe: &ReadinessProbe) -> DiagnosticsReport {
+    DiagnosticsReport::new(vec![
+        check_log_writable(&probe.log_writable),
+        check_backend(&probe.backend_name, &probe.backend_power, probe.expected_power),
+        check_scheduler(&probe.tasks),
+    ])
+}
+
+fn pass(name: &'static str, detail: String) -> DiagnosticCheck {
+    DiagnosticCheck { name, status: CheckStatus::Pass, detail }
+}
+
+fn fail(name: &'static str, detail: String) -> DiagnosticCheck {
+    DiagnosticCheck { name, status: CheckStatus::Fail, detail }
+}
+
+fn check_log_writable(log_writable: &Result<(), String>) -> DiagnosticCheck {
+    match log_writable {
+        Ok(()) => pass("log_writable", "The log accepts new entries".to_string()),
+        Err(e) => fail("log_writable", e.clone()),
+    }
+}
+
+// Every entry must start with a bracketed RFC 3339 timestamp
+fn check_log_integrity(probe: &HealthProbe) -> DiagnosticCheck {
+    let content = match &probe.log_content {
+        Ok(content) => content,
+        Err(e) => return fail("log_integrity", e.clone()),
+    };
+    let mut entries = 0;
+    let mut malformed = Vec::new();
+    for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        entries += 1;
+        if log_timestamp(line).is_none() {
+            malformed.push((index + 1).to_string());
+        }
+    }
+    if malformed.is_empty() {
+        pass("log_integrity", format!("{} entries, all well-formed", entries))
+    } else {
+        fail("log_integrity", format!("Malformed entries on lines: {}", malformed.join(", ")))
+    }
+}
+
+fn check_backend(backend_name: &str, backend_power: &Result<PowerState, String>, expected_power: Option<PowerState>) -> DiagnosticCheck {
+    match (backend_power, expected_power) {
+        (Err(e), _) => fail("backend", format!("The {} backend is unreachable: {}", backend_name, e)),
+        (Ok(power), Some(expected)) if *power != expected => fail(
+            "backend",
+            format!(
+                "The {} backend reports the bulb {} but the server believes it is {}",
+                backend_name,
+                power.log_action().to_lowercase(),
+                expected.log_action().to_lowercase()
+            ),
+        ),
+        (Ok(power), _) => pass(
+            "backend",
+            format!("The {} backend reports the bulb {}", backend_name, power.log_action().to_lowercase()),
+        ),
+    }
+}
+
+// Every state file must read back as the server would on its next start
+fn check_state_files(files: &[StateFileProbe]) -> DiagnosticCheck {
+    if files.is_empty() {
+        return DiagnosticCheck { name: "state_file", status: CheckStatus::Skip, detail: "No state is kept on disk".to_string() };
+    }
+    let unreadable: Vec<String> = files.iter().filter_map(|file| Some(format!("{} ({})", file.path, file.read.as_ref().err()?))).collect();
+    if !unreadable.is_empty() {
+        return fail("state_file", format!("Unreadable state files: {}", unreadable.join("; ")));
+    }
+    let described: Vec<String> = files
+        .iter()
+        .map(|file| match file.read {
+            Ok(true) => format!("{} ({})", file.name, file.path),
+            _ => format!("{} (nothing saved yet)", file.name),
+        })
+        .collect();
+    pass("state_file", format!("Read back {}", described.join(", ")))
+}
+
+// The scheduler must have checked the schedules within a tick of when it was due, and no timer be left waiting
+// past its time
+fn check_scheduler(tasks: &[TaskProbe]) -> DiagnosticCheck {
+    let Some(scheduler) = tasks.iter().find(|task| task.name == SCHEDULER_TASK) else {
+        return DiagnosticCheck { name: "scheduler", status: CheckStatus::Skip, detail: "No scheduler is running".to_string() };
+    };
+    let every = scheduler.every.unwrap_or_default().as_secs();
+    if scheduler.overdue > TASK_GRACE {
+        return fail("scheduler", format!("The scheduler last checked the schedules {}s ago, though it checks every {}s", scheduler.idle.as_secs(), every));
+    }
+    let stuck: Vec<String> = tasks
+        .iter()
+        .filter(|task| task.every.is_none() && task.overdue > TASK_GRACE)
+        .map(|task| format!("{} ({}s late)", task.name, task.overdue.as_secs()))
+        .collect();
+    if !stuck.is_empty() {
+        return fail("scheduler", format!("Timers past their time without firing: {}", stuck.join(", ")));
+    }
+    let timers = tasks.iter().filter(|task| task.every.is_none()).count();
+    pass("scheduler", format!("The scheduler checks the schedules every {}s, last {}s ago; {} timers waiting", every, scheduler.idle.as_secs(), timers))
+}
+
+fn check_clock(probe: &HealthProbe, now: DateTime<Utc>) -> DiagnosticCheck {
+    let earliest = DateTime::parse_from_rfc3339(EARLIEST_PLAUSIBLE_TIME).map(|time| time.with_timezone(&Utc)).ok();
+    if earliest.is_some_and(|earliest| now < earliest) {
+        return fail("clock", format!("The system clock reads {}, which looks unset", now.to_rfc3339()));
+    }
+    let latest_entry = probe
+        .log_content
+        .as_ref()
+        .ok()
+        .and_then(|content| content.lines().filter_map(log_timestamp).max());
+    match latest_entry {
+        Some(latest) if latest > now + Duration::seconds(MAX_CLOCK_SKEW_SECS) => fail(
+            "clock",
+            format!("The newest log entry ({}) is later than the system clock ({})", latest.to_rfc3339(), now.to_rfc3339()),
+        ),
+        _ => pass("clock", format!("The system clock reads {}", now.to_rfc3339())),
+    }
+}
+
+fn log_timestamp(line: &str) -> Option<DateTime<Utc>> {
+    let (timestamp, _) = line.strip_prefix('[')?.split_once(']')?;
+    DateTime::parse_from_rfc3339(timestamp).ok().map(|time| time.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(log_content: &str) -> HealthProbe {
+        HealthProbe {
+            backend_name: "simulated".to_string(),
+            log_writable: Ok(()),
+            log_content: Ok(log_content.to_string()),
+            backend_power: Ok(PowerState::On),
+            expected_power: Some(PowerState::On),
+            tasks: Vec::new(),
+            state_files: Vec::new(),
+        }
+    }
+
+    fn status(report: &DiagnosticsReport, name: &str) -> CheckStatus {
+        report.checks.iter().find(|check| check.name == name).unwrap().status
+    }
+
+    #[test]
+    fn test_healthy_report() {
+        let now = Utc::now();
+        let report = build_report(&probe(&format!("[{}] Lightbulb turned ON\n", now.to_rfc3339())), now);
+        assert!(report.healthy);
+        assert_eq!(status(&report, "log_integrity"), CheckStatus::Pass);
+        assert_eq!(status(&report, "state_file"), CheckStatus::Skip);
+        assert_eq!(status(&report, "scheduler"), CheckStatus::Skip);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_reports_the_scheduler_and_timers() {
+        let tasks = Tasks::default();
+        tasks.start_every(SCHEDULER_TASK, std::time::Duration::from_secs(30));
+        tasks.start_once("timer-1", std::time::Duration::from_secs(45));
+        let check = |tasks: &Tasks| {
+            let report = build_report(&HealthProbe { tasks: tasks.probe(), ..probe("") }, Utc::now());
+            report.checks.into_iter().find(|check| check.name == "scheduler").unwrap()
+        };
+        tokio::time::advance(std::time::Duration::from_secs(20)).await;
+        let running = check(&tasks);
+        assert_eq!(running.status, CheckStatus::Pass);
+        assert_eq!(running.detail, "The scheduler checks the schedules every 30s, last 20s ago; 1 timers waiting");
+
+        tokio::time::advance(std::time::Duration::from_secs(60)).await;
+        tasks.beat(SCHEDULER_TASK);
+        let stuck = check(&tasks);
+        assert_eq!((stuck.status, stuck.detail.as_str()), (CheckStatus::Fail, "Timers past their time without firing: timer-1 (35s late)"));
+        tasks.finish("timer-1");
+        assert_eq!(check(&tasks).status, CheckStatus::Pass);
+
+        tokio::time::advance(std::time::Duration::from_secs(90)).await;
+        let stalled = check(&tasks);
+        assert_eq!((stalled.status, stalled.detail.as_str()), (CheckStatus::Fail, "The scheduler last checked the schedules 90s ago, though it checks every 30s"));
+    }
+
+    #[test]
+    fn test_reads_back_the_state_files() {
+        let path = |name: &str| std::env::temp_dir().join(format!("lightbulb-diagnostics-{}.{}", std::process::id(), name));
+        let (saved, broken, missing) = (path("timers.json"), path("automation.json"), path("usage.json"));
+        std::fs::write(&saved, "[]").unwrap();
+        std::fs::write(&broken, "{").unwrap();
+        let parse = |saved: &str| serde_json::from_str::<serde_json::Value>(saved).map(drop).map_err(anyhow::Error::from);
+        let read = |name, path: &std::path::Path| StateFileProbe::read(name, path.to_str().unwrap(), parse);
+        let check = |state_files: Vec<StateFileProbe>| {
+            let report = build_report(&HealthProbe { state_files, ..probe("") }, Utc::now());
+            report.checks.into_iter().find(|check| check.name == "state_file").unwrap()
+        };
+
+        let readable = check(vec![read("timers", &saved), read("usage", &missing)]);
+        assert_eq!(readable.status, CheckStatus::Pass);
+        assert_eq!(readable.detail, format!("Read back timers ({}), usage (nothing saved yet)", saved.display()));
+        let unreadable = check(vec![read("timers", &saved), read("automation", &broken)]);
+        assert_eq!(unreadable.status, CheckStatus::Fail);
+        assert!(unreadable.detail.starts_with(&format!("Unreadable state files: {} (", broken.display())), "{}", unreadable.detail);
+        let _ = (std::fs::remove_file(&saved), std::fs::remove_file(&broken));
+    }
+
+    #[test]
+    fn test_detects_malformed_log_and_clock_drift() {
+        let now = Utc::now();
+        let future = now + Duration::hours(2);
+        let report = build_report(&probe(&format!("garbage\n[{}] Lightbulb turned ON\n", future.to_rfc3339())), now);
+        assert!(!report.healthy);
+        assert_eq!(report.checks[1].detail, "Malformed entries on lines: 1");
+        assert_eq!(status(&report, "clock"), CheckStatus::Fail);
+    }
+
+    #[test]
+    fn test_detects_backend_mismatch() {
+        let mut probe = probe("");
+        probe.expected_power = Some(PowerState::Off);
+        let report = build_report(&probe, Utc::now());
+        assert_eq!(status(&report, "backend"), CheckStatus::Fail);
+
+        probe.backend_power = Err("timed out".to_string());
+        let report = build_report(&probe, Utc::now());
+        assert!(report.checks[3].detail.contains("unreachable: timed out"));
+    }
+}
@@ -0,0 +1,415 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::actor::LightHandle;
+use crate::color::{Hsv, hsv_to_rgb};
+use crate::model::{Color, PowerState};
+
+const HALLOWEEN_ORANGE: Color = Color { r: 255, g: 106, b: 0 };
+const CHRISTMAS_RED: Color = Color { r: 255, g: 0, b: 0 };
+const CHRISTMAS_GREEN: Color = Color { r: 0, g: 255, b: 0 };
+// A candle never quite goes out
+const FLICKER_MIN_BRIGHTNESS: u8 = 20;
+// Party mode hits full brightness on the beat and falls back to this until the next one
+const PARTY_LOW_BRIGHTNESS: u8 = 40;
+// Far enough round the color wheel that every beat visibly changes color
+const PARTY_MIN_HUE_STEP: f64 = 60.0;
+
+// One step of an effect: change the bulb's color and brightness if given, then hold it at `power` for `duration`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectStep {
+    pub power: PowerState,
+    pub duration: Duration,
+    pub color: Option<Color>,
+    pub brightness: Option<u8>,
+}
+
+impl EffectStep {
+    pub fn new(power: PowerState, duration: Duration) -> Self {
+        Self { power, duration, color: None, brightness: None }
+    }
+
+    pub fn with_color(self, color: Color) -> Self {
+        Self { color: Some(color), ..self }
+    }
+
+    pub fn with_brightness(self, brightness: u8) -> Self {
+        Self { brightness: Some(brightness), ..self }
+    }
+}
+
+// A themed effect started by name with start_effect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectPreset {
+    pub name: &'static str,
+    pub description: &'static str,
+    // How long each step lasts unless start_effect is given a period
+    pub period: Duration,
+}
+
+pub const PRESETS: &[EffectPreset] = &[
+    EffectPreset { name: "halloween", description: "Orange candle flicker at uneven brightness", period: Duration::from_millis(150) },
+    EffectPreset { name: "christmas", description: "Red and green alternation", period: Duration::from_secs(1) },
+    EffectPreset { name: "new_year", description: "Fast white strobe", period: Duration::from_millis(100) },
+];
+
+pub fn preset(name: &str) -> Option<&'static EffectPreset> {
+    PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name.trim()))
+}
+
+pub fn complete_preset(prefix: &str) -> Vec<&'static str> {
+    let prefix = prefix.trim().to_ascii_lowercase();
+    PRESETS.iter().map(|preset| preset.name).filter(|name| name.starts_with(&prefix)).collect()
+}
+
+impl EffectPreset {
+    // Steps filling `duration`, each lasting about `period`; `seed` picks the flicker, so a seed repeats an effect
+    pub fn steps(&self, duration: Duration, period: Duration, seed: u64) -> Vec<EffectStep> {
+        let count = (duration.as_millis() / period.as_millis().max(1)).max(1) as u32;
+        match self.name {
+            "halloween" => {
+                let mut rng = StdRng::seed_from_u64(seed);
+                (0..count)
+                    .map(|_| {
+                        let length = period.mul_f64(rng.random_range(0.5..1.5));
+                        EffectStep::new(PowerState::On, length)
+                            .with_color(HALLOWEEN_ORANGE)
+                            .with_brightness(rng.random_range(FLICKER_MIN_BRIGHTNESS..=100))
+                    })
+                    .collect()
+            },
+            "christmas" => (0..count)
+                .map(|i| EffectStep::new(PowerState::On, period).with_color(if i % 2 == 0 { CHRISTMAS_RED } else { CHRISTMAS_GREEN }))
+                .collect(),
+            _ => {
+                let mut steps = flash_steps(count.div_ceil(2), period);
+                steps[0] = steps[0].with_color(Color::WHITE).with_brightness(100);
+                steps
+            },
+        }
+    }
+}
+
+// A random hue every beat of `bpm` for `duration`, pulsing bright for the first quarter of each beat
+pub fn party_steps(duration: Duration, bpm: u32, seed: u64) -> Vec<EffectStep> {
+    let beat = Duration::from_secs(60) / bpm.max(1);
+    let beats = (duration.as_millis() / beat.as_millis().max(1)).max(1);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut hue: f64 = rng.random_range(0.0..360.0);
+    let mut steps = Vec::new();
+    for _ in 0..beats {
+        hue = (hue + rng.random_range(PARTY_MIN_HUE_STEP..360.0 - PARTY_MIN_HUE_STEP)) % 360.0;
+        let color = hsv_to_rgb(Hsv { h: hue, s: 1.0, v: 1.0 });
+        steps.push(EffectStep::new(PowerState::On, beat / 4).with_color(color).with_brightness(100));
+        steps.push(EffectStep::new(PowerState::On, beat - beat / 4).with_brightness(PARTY_LOW_BRIGHTNESS));
+    }
+    steps
+}
+
+// Standard Morse timing, in units: dot 1, dash 3, gaps of 1 within a letter, 3 between letters and 7 between words
+pub fn morse_steps(text: &str, unit: Duration) -> Result<Vec<EffectStep>, char> {
+    let mut units: Vec<(PowerState, u32)> = Vec::new();
+    for word in text.split_whitespace() {
+        if !units.is_empty() {
+            units.push((PowerState::Off, 7));
+        }
+        for (index, letter) in word.chars().enumerate() {
+            if index > 0 {
+                units.push((PowerState::Off, 3));
+            }
+            let code = morse_code(letter).ok_or(letter)?;
+            for (index, symbol) in code.chars().enumerate() {
+                if index > 0 {
+                    units.push((PowerState::Off, 1));
+                }
+                units.push((PowerState::On, if symbol == '-' { 3 } else { 1 }));
+            }
+        }
+    }
+    Ok(units.into_iter().map(|(power, count)| EffectStep::new(power, unit * count)).collect())
+}
+
+// Flashes the bulb on and off `count` times, each phase lasting `period`
+pub fn flash_steps(count: u32, period: Duration) -> Vec<EffectStep> {
+    (0..count)
+        .flat_map(|_| [EffectStep::new(PowerState::On, period), EffectStep::new(PowerState::Off, period)])
+        .collect()
+}
+
+fn morse_code(letter: char) -> Option<&'static str> {
+    let code = match letter.to_ascii_uppercase() {
+        'A' => ".-",
+        'B' => "-...",
+        'C' => "-.-.",
+        'D' => "-..",
+        'E' => ".",
+        'F' => "..-.",
+        'G' => "--.",
+        'H' => "....",
+        'I' => "..",
+        'J' => ".---",
+        'K' => "-.-",
+        'L' => ".-..",
+        'M' => "--",
+        'N' => "-.",
+        'O' => "---",
+        'P' => ".--.",
+        'Q' => "--.-",
+        'R' => ".-.",
+        'S' => "...",
+        'T' => "-",
+        'U' => "..-",
+        'V' => "...-",
+        'W' => ".--",
+        'X' => "-..-",
+        'Y' => "-.--",
+        'Z' => "--..",
+        '0' => "-----",
+        '1' => ".----",
+        '2' => "..---",
+        '3' => "...--",
+        '4' => "....-",
+        '5' => ".....",
+        '6' => "-....",
+        '7' => "--...",
+        '8' => "---..",
+        '9' => "----.",
+        _ => return None,
+    };
+    Some(code)
+}
+
+struct ActiveEffect {
+    id: u64,
+    name: String,
+    cancel: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct RunnerState {
+    next_id: u64,
+    active: Option<ActiveEffect>,
+}
+
+// Runs at most one effect at a time in the background, restoring the bulb when it ends
+#[derive(Clone, Default)]
+pub struct EffectRunner {
+    state: Arc<Mutex<RunnerState>>,
+}
+
+impl EffectRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Starts `steps` on a background task, or returns the name of the effect already running. The receiver hears
+    // whether the effect ran to its end once the bulb is restored.
+    pub fn start(&self, light: LightHandle, name: String, steps: Vec<EffectStep>) -> Result<oneshot::Receiver<bool>, String> {
+        let mut state = self.lock();
+        if let Some(active) = &state.active {
+            return Err(active.name.clone());
+        }
+        state.next_id += 1;
+        let id = state.next_id;
+        let (cancel, cancelled) = oneshot::channel();
+        let (ended, done) = oneshot::channel();
+        let light = light.acting_as(format!("effect {}", name));
+
+        let runner = self.clone();
+        // Cannot clear `active` before it is set below, since that waits for the lock held here
+        let task = tokio::spawn(async move {
+            let finished = run_steps(&light, steps, cancelled).await;
+            {
+                let mut state = runner.lock();
+                if state.active.as_ref().is_some_and(|active| active.id == id) {
+                    state.active = None;
+                }
+            }
+            let _ = ended.send(finished);
+        });
+        state.active = Some(ActiveEffect { id, name, cancel, task });
+        Ok(done)
+    }
+
+    // Signals the running effect to stop, returning its name
+    pub fn cancel(&self) -> Option<String> {
+        let active = self.lock().active.take()?;
+        let _ = active.cancel.send(());
+        Some(active.name)
+    }
+
+    // As `cancel`, returning once the effect has restored the bulb's power
+    pub async fn stop(&self) -> Option<String> {
+        let active = self.lock().active.take()?;
+        let _ = active.cancel.send(());
+        let _ = active.task.await;
+        Some(active.name)
+    }
+
+    pub fn active(&self) -> Option<String> {
+        self.lock().active.as_ref().map(|active| active.name.clone())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, RunnerState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Puts back the color and brightness too, when the effect changed them. True when every step ran.
+async fn run_steps(light: &LightHandle, steps: Vec<EffectStep>, mut cancelled: oneshot::Receiver<()>) -> bool {
+    let Ok(before) = light.snapshot().await else {
+        return false;
+    };
+    let Some(power) = before.power else {
+        return false;
+    };
+    let mut finished = true;
+    let mut restore = EffectStep::new(power, Duration::ZERO);
+    for step in steps {
+        if step.color.is_some() {
+            restore.color = Some(before.color);
+        }
+        if step.brightness.is_some() {
+            restore.brightness = Some(before.brightness);
+        }
+        if light.effect_step(&step).await.is_err() {
+            return false;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(step.duration) => {},
+            _ = &mut cancelled => {
+                finished = false;
+                break;
+            },
+        }
+    }
+    let _ = light.effect_step(&restore).await;
+    finished
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::state::StateMachine;
+
+    fn spawn_light() -> LightHandle {
+        LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()))
+    }
+
+    #[test]
+    fn test_morse_timing() {
+        let unit = Duration::from_millis(10);
+        let units: Vec<(PowerState, u128)> = morse_steps("et a", unit)
+            .unwrap()
+            .into_iter()
+            .map(|step| (step.power, step.duration.as_millis() / 10))
+            .collect();
+        assert_eq!(units, vec![
+            (PowerState::On, 1),
+            (PowerState::Off, 3),
+            (PowerState::On, 3),
+            (PowerState::Off, 7),
+            (PowerState::On, 1),
+            (PowerState::Off, 1),
+            (PowerState::On, 3),
+        ]);
+        assert_eq!(morse_steps("hi!", unit), Err('!'));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_effect_restores_power_without_logging() {
+        let light = spawn_light();
+        let runner = EffectRunner::new();
+        runner.start(light.clone(), "morse: SOS".to_string(), morse_steps("SOS", Duration::from_millis(100)).unwrap()).unwrap();
+        assert_eq!(runner.start(light.clone(), "other".to_string(), Vec::new()).unwrap_err(), "morse: SOS");
+
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(runner.active().is_none());
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::Off));
+        assert!(light.read_log().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_presets_fill_the_duration() {
+        let christmas = preset("Christmas").unwrap();
+        let steps = christmas.steps(Duration::from_secs(4), christmas.period, 0);
+        let colors: Vec<Option<Color>> = steps.iter().map(|step| step.color).collect();
+        assert_eq!(colors, [Some(CHRISTMAS_RED), Some(CHRISTMAS_GREEN), Some(CHRISTMAS_RED), Some(CHRISTMAS_GREEN)]);
+
+        let halloween = preset("halloween").unwrap();
+        let flicker = halloween.steps(Duration::from_secs(3), halloween.period, 7);
+        assert_eq!(flicker.len(), 20);
+        assert!(flicker.iter().all(|step| step.power == PowerState::On && step.brightness.is_some_and(|b| b >= FLICKER_MIN_BRIGHTNESS)));
+        assert_eq!(flicker, halloween.steps(Duration::from_secs(3), halloween.period, 7));
+
+        let strobe = preset("new_year").unwrap().steps(Duration::from_secs(1), Duration::from_millis(100), 0);
+        assert_eq!((strobe.len(), strobe[0].color, strobe[1].power), (10, Some(Color::WHITE), PowerState::Off));
+        assert_eq!(complete_preset("n"), ["new_year"]);
+        assert!(preset("easter").is_none());
+    }
+
+    #[test]
+    fn test_party_changes_color_every_beat() {
+        let steps = party_steps(Duration::from_secs(10), 120, 3);
+        assert_eq!(steps.len(), 40);
+        assert_eq!(steps.iter().map(|step| step.duration).sum::<Duration>(), Duration::from_secs(10));
+        let colors: Vec<Color> = steps.iter().filter_map(|step| step.color).collect();
+        assert_eq!(colors.len(), 20);
+        assert!(colors.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(steps.iter().skip(1).step_by(2).all(|step| step.brightness == Some(PARTY_LOW_BRIGHTNESS)));
+        assert_eq!(steps, party_steps(Duration::from_secs(10), 120, 3));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_effect_restores_color_and_brightness() {
+        let light = spawn_light();
+        light.set_power(PowerState::On).await.unwrap();
+        let runner = EffectRunner::new();
+        let halloween = preset("halloween").unwrap();
+        runner.start(light.clone(), "halloween".to_string(), halloween.steps(Duration::from_secs(2), halloween.period, 1)).unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let during = light.snapshot().await.unwrap();
+        assert_eq!(during.color, HALLOWEEN_ORANGE);
+
+        runner.stop().await.unwrap();
+        let after = light.snapshot().await.unwrap();
+        assert_eq!((after.power, after.color, after.brightness), (Some(PowerState::On), Color::WHITE, 100));
+        assert_eq!(light.read_log().await.unwrap().lines().count(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_stops_effect() {
+        let light = spawn_light();
+        let runner = EffectRunner::new();
+        let done = runner.start(light.clone(), "morse: O".to_string(), morse_steps("O", Duration::from_secs(1)).unwrap()).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::On));
+
+        assert_eq!(runner.cancel(), Some("morse: O".to_string()));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::Off));
+        assert!(runner.cancel().is_none());
+        assert!(!done.await.unwrap());
+        let done = runner.start(light.clone(), "morse: E".to_string(), morse_steps("E", Duration::from_millis(100)).unwrap()).unwrap();
+        assert!(done.await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stop_returns_once_power_is_restored() {
+        let light = spawn_light();
+        let runner = EffectRunner::new();
+        runner.start(light.clone(), "flash".to_string(), flash_steps(10, Duration::from_secs(1))).unwrap();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(runner.stop().await, Some("flash".to_string()));
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::Off));
+        assert_eq!(runner.stop().await, None);
+    }
+}
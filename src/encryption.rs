@@ -0,0 +1,272 @@
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Utc};
+use sha2::Sha256;
+
+use crate::logger::{DetachedRead, LOG_FLUSH_ENTRIES, LogChunk, Logger, lines_between, parse_log_sequence};
+
+// Marks a line of the log file as encrypted; lines without it, written before encryption was turned on, are read as
+// they are
+pub const ENCRYPTED_LINE_PREFIX: &str = "enc:";
+const NONCE_BYTES: usize = 12;
+const SALT_BYTES: usize = 16;
+// PBKDF2-HMAC-SHA256 rounds turning a passphrase into a key
+const PASSPHRASE_ROUNDS: u32 = 600_000;
+
+pub fn salt_path(log_file: &str) -> String {
+    format!("{}.salt", log_file)
+}
+
+// AES-256-GCM under the log's key, sealing each line on its own with a random nonce
+#[derive(Clone)]
+pub struct LogCipher {
+    cipher: Arc<Aes256Gcm>,
+}
+
+impl LogCipher {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self { cipher: Arc::new(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))) }
+    }
+
+    // Loads the base64-encoded key at `path`, generating a new key file if none exists
+    pub fn from_key_file(path: &str) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(encoded) => {
+                let bytes = BASE64.decode(encoded.trim()).with_context(|| format!("Log encryption key file is not valid base64: {}", path))?;
+                let key: [u8; 32] = bytes.try_into().map_err(|_| anyhow::anyhow!("Log encryption key file must contain a 32-byte key: {}", path))?;
+                Ok(Self::new(key))
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let key: [u8; 32] = random_bytes()?;
+                write_private(path, BASE64.encode(key)).with_context(|| format!("Failed to write log encryption key file: {}", path))?;
+                Ok(Self::new(key))
+            },
+            Err(e) => Err(e).with_context(|| format!("Failed to read log encryption key file: {}", path)),
+        }
+    }
+
+    // Derives the key from `passphrase` and the salt at `salt_path`, generating the salt if none exists. The salt is
+    // no secret, but losing it loses the log.
+    pub fn from_passphrase(passphrase: &str, salt_path: &str) -> anyhow::Result<Self> {
+        Self::derive(passphrase, salt_path, PASSPHRASE_ROUNDS)
+    }
+
+    fn derive(passphrase: &str, salt_path: &str, rounds: u32) -> anyhow::Result<Self> {
+        if passphrase.is_empty() {
+            anyhow::bail!("The log encryption passphrase is empty");
+        }
+        let salt = match std::fs::read_to_string(salt_path) {
+            Ok(encoded) => BASE64.decode(encoded.trim()).with_context(|| format!("Log encryption salt file is not valid base64: {}", salt_path))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let salt: [u8; SALT_BYTES] = random_bytes()?;
+                write_private(salt_path, BASE64.encode(salt)).with_context(|| format!("Failed to write log encryption salt file: {}", salt_path))?;
+                salt.to_vec()
+            },
+            Err(e) => return Err(e).with_context(|| format!("Failed to read log encryption salt file: {}", salt_path)),
+        };
+        Ok(Self::new(pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(passphrase.as_bytes(), &salt, rounds)))
+    }
+
+    pub fn encrypt_line(&self, line: &str) -> anyhow::Result<String> {
+        let nonce: [u8; NONCE_BYTES] = random_bytes()?;
+        let sealed = self.cipher.encrypt(Nonce::from_slice(&nonce), line.as_bytes()).map_err(|_| anyhow::anyhow!("Failed to encrypt a log entry"))?;
+        Ok(format!("{}{}", ENCRYPTED_LINE_PREFIX, BASE64.encode([nonce.as_slice(), &sealed].concat())))
+    }
+
+    pub fn decrypt_line(&self, line: &str) -> anyhow::Result<String> {
+        let Some(encoded) = line.strip_prefix(ENCRYPTED_LINE_PREFIX) else {
+            return Ok(line.to_string());
+        };
+        let undecryptable = || anyhow::anyhow!("A log entry could not be decrypted; the log was encrypted under another key, or has been damaged");
+        let sealed = BASE64.decode(encoded.trim()).map_err(|_| undecryptable())?;
+        if sealed.len() < NONCE_BYTES {
+            return Err(undecryptable());
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_BYTES);
+        let plain = self.cipher.decrypt(Nonce::from_slice(nonce), sealed).map_err(|_| undecryptable())?;
+        String::from_utf8(plain).map_err(|_| undecryptable())
+    }
+
+    // Every line of `log` decrypted, keeping blank lines and the trailing newline as they were
+    pub fn decrypt_log(&self, log: &str) -> anyhow::Result<String> {
+        let mut plain = String::with_capacity(log.len());
+        for line in log.split_inclusive('\n') {
+            let (line, newline) = line.strip_suffix('\n').map_or((line, ""), |line| (line, "\n"));
+            plain.push_str(&self.decrypt_line(line)?);
+            plain.push_str(newline);
+        }
+        Ok(plain)
+    }
+
+    fn decrypt_lines(&self, lines: Vec<String>) -> anyhow::Result<Vec<String>> {
+        lines.iter().map(|line| self.decrypt_line(line)).collect()
+    }
+
+    // A chunk's offsets stay those of the file, so paging through it works as before
+    fn decrypt_chunk(&self, chunk: LogChunk) -> anyhow::Result<LogChunk> {
+        Ok(LogChunk { text: self.decrypt_log(&chunk.text)?, ..chunk })
+    }
+}
+
+fn random_bytes<const N: usize>() -> anyhow::Result<[u8; N]> {
+    let mut bytes = [0u8; N];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow::anyhow!("Failed to generate random bytes: {}", e))?;
+    Ok(bytes)
+}
+
+fn write_private(path: &str, contents: String) -> std::io::Result<()> {
+    std::fs::write(path, contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+// Decorator that encrypts every line the inner logger stores and decrypts every line read back, so the rest of the
+// server only ever sees plaintext. Range reads cannot search ciphertext for a time and read the whole log instead.
+pub struct EncryptingLogger {
+    inner: Box<dyn Logger + Send + Sync>,
+    cipher: LogCipher,
+}
+
+impl EncryptingLogger {
+    pub fn new(inner: Box<dyn Logger + Send + Sync>, cipher: LogCipher) -> Self {
+        Self { inner, cipher }
+    }
+}
+
+#[async_trait::async_trait]
+impl Logger for EncryptingLogger {
+    async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let line = self.cipher.encrypt_line(line)?;
+        self.inner.append_line(&line).await
+    }
+
+    async fn read_log(&self) -> anyhow::Result<String> {
+        self.cipher.decrypt_log(&self.inner.read_log().await?)
+    }
+
+    async fn read_chunk(&self, offset: u64, limit: usize) -> anyhow::Result<LogChunk> {
+        self.cipher.decrypt_chunk(self.inner.read_chunk(offset, limit).await?)
+    }
+
+    fn detached_read(&self) -> Option<DetachedRead> {
+        let (read, cipher) = (self.inner.detached_read()?, self.cipher.clone());
+        Some(Box::pin(async move { cipher.decrypt_log(&read.await?) }))
+    }
+
+    fn detached_read_chunk(&self, offset: u64, limit: usize) -> Option<DetachedRead<LogChunk>> {
+        let (read, cipher) = (self.inner.detached_read_chunk(offset, limit)?, self.cipher.clone());
+        Some(Box::pin(async move { cipher.decrypt_chunk(read.await?) }))
+    }
+
+    async fn read_tail(&self, entries: usize) -> anyhow::Result<Vec<String>> {
+        self.cipher.decrypt_lines(self.inner.read_tail(entries).await?)
+    }
+
+    fn detached_read_tail(&self, entries: usize) -> Option<DetachedRead<Vec<String>>> {
+        let (read, cipher) = (self.inner.detached_read_tail(entries)?, self.cipher.clone());
+        Some(Box::pin(async move { cipher.decrypt_lines(read.await?) }))
+    }
+
+    fn detached_read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<DetachedRead<Vec<String>>> {
+        let (read, cipher) = (self.inner.detached_read()?, self.cipher.clone());
+        Some(Box::pin(async move { Ok(lines_between(&cipher.decrypt_log(&read.await?)?, from, to)) }))
+    }
+
+    async fn check_writable(&self) -> anyhow::Result<()> {
+        self.inner.check_writable().await
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush().await
+    }
+
+    async fn replace_log(&mut self, log: &str) -> anyhow::Result<()> {
+        let mut sealed = String::with_capacity(log.len() * 2);
+        for line in log.lines().filter(|line| !line.trim().is_empty()) {
+            sealed.push_str(&self.cipher.encrypt_line(line)?);
+            sealed.push('\n');
+        }
+        self.inner.replace_log(&sealed).await
+    }
+
+    async fn archive_log(&mut self, suffix: &str) -> anyhow::Result<String> {
+        self.inner.archive_log(suffix).await
+    }
+
+    async fn modified(&self) -> Option<DateTime<Utc>> {
+        self.inner.modified().await
+    }
+
+    // The inner logger cannot see the numbers for the ciphertext, so they are looked for here, near the end first
+    async fn last_sequence(&self) -> Option<u64> {
+        let tail = self.read_tail(LOG_FLUSH_ENTRIES).await.ok()?;
+        if let Some(sequence) = tail.iter().filter_map(|line| parse_log_sequence(line)).max() {
+            return Some(sequence);
+        }
+        self.read_log().await.ok()?.lines().filter_map(parse_log_sequence).max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::{FileLogger, InMemoryLogger, format_log_line, number_log_entry};
+
+    #[tokio::test]
+    async fn test_the_log_file_holds_no_plaintext() {
+        let path = std::env::temp_dir().join(format!("lightbulb-encrypted-{}.log", std::process::id()));
+        let key_path = format!("{}.key", path.display());
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&key_path);
+        // Written before encryption was turned on, and still readable after
+        std::fs::write(&path, "[2025-08-01T09:00:00+00:00] Lightbulb turned OFF\n").unwrap();
+        let cipher = LogCipher::from_key_file(&key_path).unwrap();
+        let mut logger = EncryptingLogger::new(Box::new(FileLogger::new(path.display().to_string())), cipher);
+        let at = DateTime::parse_from_rfc3339("2025-08-02T09:00:00Z").unwrap().with_timezone(&Utc);
+        logger.append_line(&number_log_entry(&format_log_line(at, "ON"), 1)).await.unwrap();
+        logger.flush().await.unwrap();
+
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(stored.lines().nth(1).unwrap().starts_with(ENCRYPTED_LINE_PREFIX) && !stored.contains("turned ON"), "{}", stored);
+        assert_eq!(logger.read_log().await.unwrap(), "[2025-08-01T09:00:00+00:00] Lightbulb turned OFF\n[2025-08-02T09:00:00+00:00] Lightbulb turned ON seq=1\n");
+        assert_eq!(logger.read_tail(1).await.unwrap(), ["[2025-08-02T09:00:00+00:00] Lightbulb turned ON seq=1"]);
+        assert_eq!(logger.read_range(at, at + chrono::TimeDelta::hours(1)).await.unwrap().len(), 1);
+        assert_eq!(logger.detached_read_range(at, at + chrono::TimeDelta::hours(1)).unwrap().await.unwrap().len(), 1);
+        let chunk = logger.read_chunk(0, 4096).await.unwrap();
+        assert!(chunk.text.ends_with("turned ON seq=1\n") && chunk.end == stored.len() as u64);
+        assert_eq!(logger.last_sequence().await, Some(1));
+
+        // The key is kept, so a restart reads what was written; any other key reads nothing
+        let reopened = EncryptingLogger::new(Box::new(FileLogger::new(path.display().to_string())), LogCipher::from_key_file(&key_path).unwrap());
+        assert_eq!(reopened.read_tail(1).await.unwrap(), logger.read_tail(1).await.unwrap());
+        let other = EncryptingLogger::new(Box::new(FileLogger::new(path.display().to_string())), LogCipher::new([1; 32]));
+        assert!(other.read_log().await.unwrap_err().to_string().contains("could not be decrypted"));
+        let _ = std::fs::remove_file(&key_path);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_a_passphrase_gives_the_same_key_with_the_same_salt() {
+        let salt = std::env::temp_dir().join(format!("lightbulb-encrypted-{}.salt", std::process::id())).display().to_string();
+        let _ = std::fs::remove_file(&salt);
+        let sealed = LogCipher::derive("correct horse", &salt, 1_000).unwrap().encrypt_line("entry").unwrap();
+        assert_eq!(LogCipher::derive("correct horse", &salt, 1_000).unwrap().decrypt_line(&sealed).unwrap(), "entry");
+        assert!(LogCipher::derive("wrong horse", &salt, 1_000).unwrap().decrypt_line(&sealed).is_err());
+        assert!(LogCipher::derive("", &salt, 1_000).is_err());
+
+        // Compaction rewrites the log encrypted too
+        let mut logger = EncryptingLogger::new(Box::new(InMemoryLogger::new()), LogCipher::new([2; 32]));
+        logger.replace_log("first\n\nsecond\n").await.unwrap();
+        assert_eq!(logger.read_log().await.unwrap(), "first\nsecond\n");
+        let _ = std::fs::remove_file(salt);
+    }
+}
@@ -0,0 +1,262 @@
+use std::any::Any;
+
+use rmcp::model::{ErrorCode, ErrorData};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::i18n;
+use crate::state::TransitionError;
+
+// Every failure a client can see, each with a stable machine-readable code
+#[derive(Debug, thiserror::Error)]
+pub enum LightError {
+    #[error(transparent)]
+    InvalidTransition(#[from] TransitionError),
+    #[error("Failed to reach the lightbulb: {0}")]
+    BackendUnreachable(String),
+    #[error("Failed to log event: {0}")]
+    LogWriteFailed(String),
+    #[error("Failed to read log: {0}")]
+    LogUnavailable(String),
+    #[error("Log signing is not enabled on this server")]
+    SigningDisabled,
+    #[error("There is no change to undo")]
+    NothingToUndo,
+    #[error("There is no undone change to redo")]
+    NothingToRedo,
+    #[error("Invalid parameter: {0}")]
+    InvalidParameter(String),
+    #[error("The configured backend does not support fault injection")]
+    FaultInjectionUnsupported,
+    #[error("The configured backend does not support color")]
+    ColorUnsupported,
+    #[error("The configured backend does not support brightness")]
+    BrightnessUnsupported,
+    #[error("The configured backend does not support firmware updates")]
+    FirmwareUpdateUnsupported,
+    #[error("A firmware update is already {0}")]
+    FirmwareUpdateInProgress(&'static str),
+    #[error("The effect '{0}' is already running")]
+    EffectAlreadyRunning(String),
+    #[error("No effect is running")]
+    NoActiveEffect,
+    #[error("Unknown job '{0}'; list_jobs lists the jobs kept")]
+    UnknownJob(String),
+    #[error("Unknown lightbulb '{0}'; list_lightbulbs lists the lightbulbs")]
+    UnknownBulb(String),
+    #[error("Job '{0}' has already finished")]
+    JobFinished(String),
+    #[error("{0} jobs are already running; wait for one to finish or cancel it")]
+    TooManyJobs(usize),
+    #[error("Unknown resource URI: {0}")]
+    UnknownResource(String),
+    #[error("Unknown tool '{0}'; tools/list lists the tools this server offers")]
+    UnknownTool(String),
+    #[error("{0}")]
+    ToolFailed(String),
+    #[error("{0}")]
+    ConfirmationInvalid(String),
+    #[error("The lightbulb state has changed: expected version {expected}, found {actual}")]
+    VersionConflict { expected: u64, actual: u64 },
+    #[error("The lightbulb was switched less than {min_interval_ms}ms ago; try again in {retry_after_ms}ms")]
+    ToggleTooSoon { retry_after_ms: u64, min_interval_ms: u64 },
+    #[error("'{tool}' is outside allowed hours during the '{curfew}' curfew; it can be called again from {allowed_from}")]
+    OutsideAllowedHours { tool: String, curfew: String, allowed_from: String },
+    #[error("'{tool}' needs the {scope} scope, which the bearer token does not grant")]
+    ScopeRequired { tool: String, scope: &'static str },
+    #[error("'{tool}' did not finish within {timeout_secs}s; whatever it had already asked of the lightbulb may still happen")]
+    ToolTimedOut { tool: String, timeout_secs: u64 },
+    #[error("The lightbulb actor is no longer running")]
+    ActorStopped,
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl LightError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LightError::InvalidTransition(e) if e.state == "LOCKED" => "BULB_LOCKED",
+            LightError::InvalidTransition(_) => "INVALID_TRANSITION",
+            LightError::BackendUnreachable(_) => "BACKEND_UNREACHABLE",
+            LightError::LogWriteFailed(_) => "LOG_WRITE_FAILED",
+            LightError::LogUnavailable(_) => "LOG_UNAVAILABLE",
+            LightError::SigningDisabled => "SIGNING_DISABLED",
+            LightError::NothingToUndo => "NOTHING_TO_UNDO",
+            LightError::NothingToRedo => "NOTHING_TO_REDO",
+            LightError::InvalidParameter(_) => "INVALID_PARAMETER",
+            LightError::FaultInjectionUnsupported => "FAULT_INJECTION_UNSUPPORTED",
+            LightError::ColorUnsupported => "COLOR_UNSUPPORTED",
+            LightError::BrightnessUnsupported => "BRIGHTNESS_UNSUPPORTED",
+            LightError::FirmwareUpdateUnsupported => "FIRMWARE_UPDATE_UNSUPPORTED",
+            LightError::FirmwareUpdateInProgress(_) => "FIRMWARE_UPDATE_IN_PROGRESS",
+            LightError::EffectAlreadyRunning(_) => "EFFECT_ALREADY_RUNNING",
+            LightError::NoActiveEffect => "NO_ACTIVE_EFFECT",
+            LightError::UnknownJob(_) => "UNKNOWN_JOB",
+            LightError::UnknownBulb(_) => "UNKNOWN_BULB",
+            LightError::JobFinished(_) => "JOB_FINISHED",
+            LightError::TooManyJobs(_) => "TOO_MANY_JOBS",
+            LightError::UnknownResource(_) => "UNKNOWN_RESOURCE",
+            LightError::UnknownTool(_) => "UNKNOWN_TOOL",
+            LightError::ToolFailed(_) => "TOOL_FAILED",
+            LightError::ConfirmationInvalid(_) => "CONFIRMATION_INVALID",
+            LightError::VersionConflict { .. } => "VERSION_CONFLICT",
+            LightError::ToggleTooSoon { .. } => "TOGGLE_TOO_SOON",
+            LightError::OutsideAllowedHours { .. } => "OUTSIDE_ALLOWED_HOURS",
+            LightError::ScopeRequired { .. } => "INSUFFICIENT_SCOPE",
+            LightError::ToolTimedOut { .. } => "TOOL_TIMED_OUT",
+            LightError::ActorStopped => "SERVICE_UNAVAILABLE",
+            LightError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    fn error_code(&self) -> ErrorCode {
+        match self {
+            LightError::InvalidTransition(_)
+            | LightError::SigningDisabled
+            | LightError::NothingToUndo
+            | LightError::NothingToRedo
+            | LightError::FaultInjectionUnsupported
+            | LightError::ColorUnsupported
+            | LightError::BrightnessUnsupported
+            | LightError::FirmwareUpdateUnsupported
+            | LightError::FirmwareUpdateInProgress(_)
+            | LightError::EffectAlreadyRunning(_)
+            | LightError::NoActiveEffect
+            | LightError::JobFinished(_)
+            | LightError::TooManyJobs(_)
+            | LightError::VersionConflict { .. }
+            | LightError::ToggleTooSoon { .. }
+            | LightError::OutsideAllowedHours { .. }
+            | LightError::ScopeRequired { .. } => ErrorCode::INVALID_REQUEST,
+            LightError::InvalidParameter(_) | LightError::ConfirmationInvalid(_) | LightError::UnknownJob(_) | LightError::UnknownBulb(_) => ErrorCode::INVALID_PARAMS,
+            LightError::UnknownTool(_) => ErrorCode::INVALID_PARAMS,
+            LightError::UnknownResource(_) => ErrorCode::RESOURCE_NOT_FOUND,
+            _ => ErrorCode::INTERNAL_ERROR,
+        }
+    }
+}
+
+impl LightError {
+    // The machine-readable `data` of the error: its code, with whatever identifies what failed
+    pub fn data(&self) -> Value {
+        let code = self.code();
+        match self {
+            LightError::InvalidTransition(e) => json!({ "code": code, "state": e.state, "transition": e.transition.to_string() }),
+            LightError::BackendUnreachable(detail) | LightError::LogWriteFailed(detail) | LightError::LogUnavailable(detail) => {
+                json!({ "code": code, "detail": detail })
+            },
+            LightError::FirmwareUpdateInProgress(stage) => json!({ "code": code, "stage": stage }),
+            LightError::EffectAlreadyRunning(effect) => json!({ "code": code, "effect": effect }),
+            LightError::UnknownResource(uri) => json!({ "code": code, "uri": uri }),
+            LightError::UnknownTool(tool) => json!({ "code": code, "tool": tool }),
+            LightError::UnknownJob(job_id) | LightError::JobFinished(job_id) => json!({ "code": code, "job_id": job_id }),
+            LightError::UnknownBulb(bulb_id) => json!({ "code": code, "bulb_id": bulb_id }),
+            LightError::TooManyJobs(limit) => json!({ "code": code, "limit": limit }),
+            LightError::VersionConflict { expected, actual } => json!({ "code": code, "expected_version": expected, "actual_version": actual }),
+            LightError::ToggleTooSoon { retry_after_ms, min_interval_ms } => {
+                json!({ "code": code, "retry_after_ms": retry_after_ms, "min_interval_ms": min_interval_ms })
+            },
+            LightError::OutsideAllowedHours { tool, curfew, allowed_from } => {
+                json!({ "code": code, "tool": tool, "curfew": curfew, "allowed_from": allowed_from })
+            },
+            LightError::ScopeRequired { tool, scope } => json!({ "code": code, "tool": tool, "scope": scope }),
+            LightError::ToolTimedOut { tool, timeout_secs } => json!({ "code": code, "tool": tool, "timeout_secs": timeout_secs }),
+            _ => json!({ "code": code }),
+        }
+    }
+
+    // The message in the configured language, filled in from `data()` and, for messages that are all detail, the
+    // detail itself
+    pub fn message(&self) -> String {
+        let mut arguments: Vec<(String, String)> = match self.data() {
+            Value::Object(data) => data
+                .into_iter()
+                .map(|(key, value)| (key, value.as_str().map(String::from).unwrap_or_else(|| value.to_string())))
+                .collect(),
+            _ => Vec::new(),
+        };
+        if let LightError::InvalidParameter(detail) | LightError::ConfirmationInvalid(detail) | LightError::ToolFailed(detail) | LightError::Internal(detail) =
+            self
+        {
+            arguments.push(("detail".to_string(), detail.clone()));
+        }
+        let arguments: Vec<(&str, &dyn std::fmt::Display)> = arguments.iter().map(|(key, value)| (key.as_str(), value as _)).collect();
+        i18n::localize(&Self::message_key(self.code()), self.to_string(), &arguments)
+    }
+
+    // The catalog key of the message for an error code, e.g. "error-bulb-locked" for BULB_LOCKED
+    pub fn message_key(code: &str) -> String {
+        format!("error-{}", code.to_lowercase().replace('_', "-"))
+    }
+}
+
+impl From<LightError> for ErrorData {
+    fn from(error: LightError) -> Self {
+        ErrorData::new(error.error_code(), error.message(), Some(error.data()))
+    }
+}
+
+// Adds `key` to an error's `data`, such as the backend or bulb it came from, keeping its code
+pub fn with_context(mut error: ErrorData, key: &str, value: impl Into<Value>) -> ErrorData {
+    let data = error.data.get_or_insert_with(|| json!({ "code": "INTERNAL_ERROR" }));
+    if let Value::Object(data) = data {
+        data.entry(key).or_insert_with(|| value.into());
+    }
+    error
+}
+
+// Tool replies and resources that are JSON; serializing one only fails on a bug
+pub fn to_json<T: Serialize + ?Sized>(value: &T) -> Result<String, ErrorData> {
+    serde_json::to_string_pretty(value).map_err(|e| LightError::Internal(format!("could not serialize JSON: {}", e)).into())
+}
+
+// What a caught panic was raised with, for the error that takes its place
+pub fn panic_message(panic: &(dyn Any + Send)) -> String {
+    let message = panic.downcast_ref::<&str>().map(|message| message.to_string()).or_else(|| panic.downcast_ref::<String>().cloned());
+    format!("a request handler panicked: {}", message.as_deref().unwrap_or("no message"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PowerState;
+    use crate::state::Transition;
+
+    #[test]
+    fn test_locked_error_maps_to_bulb_locked() {
+        let error = LightError::from(TransitionError { state: "LOCKED", transition: Transition::Begin(PowerState::On) });
+        let data = ErrorData::from(error);
+        assert_eq!(data.code, ErrorCode::INVALID_REQUEST);
+        assert_eq!(data.message, "Cannot turn on while the lightbulb is locked");
+        assert_eq!(data.data.unwrap()["code"], "BULB_LOCKED");
+    }
+
+    #[test]
+    fn test_unknown_resource_includes_uri() {
+        let data = ErrorData::from(LightError::UnknownResource("lightbulb://nope".to_string()));
+        assert_eq!(data.code, ErrorCode::RESOURCE_NOT_FOUND);
+        assert_eq!(data.data.unwrap()["uri"], "lightbulb://nope");
+    }
+
+    #[test]
+    fn test_errors_carry_what_failed() {
+        let data = ErrorData::from(LightError::EffectAlreadyRunning("party".to_string())).data.unwrap();
+        assert_eq!(data, json!({ "code": "EFFECT_ALREADY_RUNNING", "effect": "party" }));
+        let data = ErrorData::from(LightError::BackendUnreachable("timed out".to_string())).data.unwrap();
+        assert_eq!(data, json!({ "code": "BACKEND_UNREACHABLE", "detail": "timed out" }));
+
+        let error = with_context(LightError::BackendUnreachable("timed out".to_string()).into(), "bulb", "kitchen");
+        assert_eq!(error.data.unwrap()["bulb"], "kitchen");
+        let error = with_context(ErrorData::internal_error("downstream failed", None), "bulb", "kitchen");
+        assert_eq!(error.data.unwrap(), json!({ "code": "INTERNAL_ERROR", "bulb": "kitchen" }));
+    }
+
+    #[test]
+    fn test_panic_message_names_the_panic() {
+        let panic = std::panic::catch_unwind(|| panic!("bad {}", "state")).unwrap_err();
+        assert_eq!(panic_message(panic.as_ref()), "a request handler panicked: bad state");
+        let data = ErrorData::from(LightError::Internal(panic_message(&42)));
+        assert_eq!(data.code, ErrorCode::INTERNAL_ERROR);
+        assert_eq!(data.data.unwrap()["code"], "INTERNAL_ERROR");
+    }
+}
@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::state::StateSnapshot;
+
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    StateChanged,
+    BackendUnreachable,
+    // The safety watchdog's warning that the bulb has been on for longer than its limit
+    OnTooLong,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::StateChanged => "state_changed",
+            EventKind::BackendUnreachable => "backend_unreachable",
+            EventKind::OnTooLong => "on_too_long",
+        }
+    }
+}
+
+// Announced by the light actor after a command changes the bulb or fails to reach it
+#[derive(Debug, Clone, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct LightEvent {
+    pub sequence: u64,
+    pub at: DateTime<Utc>,
+    pub kind: EventKind,
+    // The command that caused the event, e.g. "turn_on", "undo" or "lock"
+    pub action: String,
+    pub from: &'static str,
+    pub to: &'static str,
+    pub state: StateSnapshot,
+    // The number of the log entry the change wrote, for catching up from lightbulb://log?since=
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_sequence: Option<u64>,
+}
+
+// Fan-out of light events to any number of subscribers; slow subscribers miss events rather than block the actor
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<LightEvent>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER);
+        Self { sender }
+    }
+
+    pub fn publish(&self, event: LightEvent) {
+        // Having no subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LightEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
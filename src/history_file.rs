@@ -0,0 +1,403 @@
+use std::io::Write;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+
+#[cfg(feature = "encryption")]
+use crate::encryption::LogCipher;
+use crate::logger::{DetachedRead, LOG_FLUSH_ENTRIES, LogChunk, Logger};
+use crate::model::PowerState;
+use crate::stats::{HistoryEntry, history_entries};
+
+// Rotated once the next record would take it past this size
+pub const DEFAULT_HISTORY_MAX_BYTES: u64 = 8 * 1024 * 1024;
+// Rotated files kept beside the current one, as history.jsonl.1 (the newest) to history.jsonl.N
+pub const DEFAULT_HISTORY_KEEP_FILES: usize = 4;
+
+pub fn history_path(log_file: &str) -> String {
+    format!("{}.jsonl", log_file)
+}
+
+// When the history file is rotated, and how many rotated files are kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryRotation {
+    pub max_bytes: u64,
+    pub keep_files: usize,
+}
+
+impl Default for HistoryRotation {
+    fn default() -> Self {
+        Self { max_bytes: DEFAULT_HISTORY_MAX_BYTES, keep_files: DEFAULT_HISTORY_KEEP_FILES }
+    }
+}
+
+// Writes each entry of the log it wraps again as a structured record, one JSON object per line, in a file of its
+// own that is rotated by size. Records say what the entry recorded, who made it and why, and for a power change
+// the power the change before it left the bulb in, decided in log order as the entries are written. The first
+// write to a log that has no history file yet records the log's existing entries, and rewrites and archives of
+// the log are followed, so the records always hold what the log does.
+pub struct HistoryLogger {
+    inner: Box<dyn Logger + Send + Sync>,
+    path: String,
+    rotation: HistoryRotation,
+    pending: Vec<HistoryEntry>,
+    // What the last recorded power change left the bulb in, once the records have been read for it
+    power: Option<Option<PowerState>>,
+    // Seals each record as the log's entries are sealed, when the log is encrypted
+    #[cfg(feature = "encryption")]
+    cipher: Option<LogCipher>,
+}
+
+impl HistoryLogger {
+    pub fn new(inner: Box<dyn Logger + Send + Sync>, path: String, rotation: HistoryRotation) -> Self {
+        Self {
+            inner,
+            path,
+            rotation,
+            pending: Vec::new(),
+            power: None,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn with_cipher(mut self, cipher: LogCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    // Parses a stored record, unsealing it first when the log is encrypted
+    fn parser(&self) -> impl Fn(&str) -> Option<HistoryEntry> + Send + 'static {
+        #[cfg(feature = "encryption")]
+        let cipher = self.cipher.clone();
+        move |line| {
+            #[cfg(feature = "encryption")]
+            if let Some(cipher) = &cipher {
+                return serde_json::from_str(&cipher.decrypt_line(line).ok()?).ok();
+            }
+            serde_json::from_str(line).ok()
+        }
+    }
+
+    // Called before each entry reaches the log, so an existing log is recorded without the entry on its way
+    async fn prime(&mut self) -> anyhow::Result<()> {
+        if self.power.is_some() {
+            return Ok(());
+        }
+        let mut power = None;
+        match read_history(self.path.clone(), self.rotation.keep_files, self.parser()).await? {
+            Some(records) => {
+                history_power(&records, &mut power);
+            },
+            None => {
+                let log = self.inner.read_log().await.unwrap_or_default();
+                self.pending = history_entries(log.lines(), &mut power);
+            },
+        }
+        self.power = Some(power);
+        Ok(())
+    }
+
+    async fn record(&mut self, line: &str) -> anyhow::Result<()> {
+        let mut power = self.power.flatten();
+        self.pending.extend(history_entries([line], &mut power));
+        self.power = Some(power);
+        if self.pending.len() >= LOG_FLUSH_ENTRIES {
+            self.write_pending().await?;
+        }
+        Ok(())
+    }
+
+    // Records that cannot be written are dropped rather than kept, so a history file that cannot be written
+    // never holds up the log
+    async fn write_pending(&mut self) -> anyhow::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        #[allow(unused_mut)]
+        let mut lines = std::mem::take(&mut self.pending).iter().map(serde_json::to_string).collect::<Result<Vec<_>, _>>()?;
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            lines = lines.iter().map(|line| cipher.encrypt_line(line)).collect::<anyhow::Result<_>>()?;
+        }
+        let (path, rotation) = (self.path.clone(), self.rotation);
+        tokio::task::spawn_blocking(move || append_history(&path, rotation, &lines)).await?
+    }
+
+    // Replaces every history file with records of `log`
+    async fn rewrite(&mut self, log: &str) -> anyhow::Result<()> {
+        let mut power = None;
+        self.pending = history_entries(log.lines(), &mut power);
+        self.power = Some(power);
+        let (path, keep_files) = (self.path.clone(), self.rotation.keep_files);
+        tokio::task::spawn_blocking(move || remove_history(&path, keep_files)).await??;
+        self.write_pending().await
+    }
+}
+
+#[async_trait::async_trait]
+impl Logger for HistoryLogger {
+    async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+        self.prime().await?;
+        self.inner.append_line(line).await?;
+        self.record(line).await
+    }
+
+    async fn log_event(&mut self, at: DateTime<Utc>, action: &str) -> anyhow::Result<String> {
+        self.prime().await?;
+        let line = self.inner.log_event(at, action).await?;
+        self.record(&line).await?;
+        Ok(line)
+    }
+
+    async fn read_log(&self) -> anyhow::Result<String> {
+        self.inner.read_log().await
+    }
+
+    async fn read_chunk(&self, offset: u64, limit: usize) -> anyhow::Result<LogChunk> {
+        self.inner.read_chunk(offset, limit).await
+    }
+
+    fn detached_read(&self) -> Option<DetachedRead> {
+        self.inner.detached_read()
+    }
+
+    fn detached_read_chunk(&self, offset: u64, limit: usize) -> Option<DetachedRead<LogChunk>> {
+        self.inner.detached_read_chunk(offset, limit)
+    }
+
+    async fn read_tail(&self, entries: usize) -> anyhow::Result<Vec<String>> {
+        self.inner.read_tail(entries).await
+    }
+
+    fn detached_read_tail(&self, entries: usize) -> Option<DetachedRead<Vec<String>>> {
+        self.inner.detached_read_tail(entries)
+    }
+
+    async fn read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> anyhow::Result<Vec<String>> {
+        self.inner.read_range(from, to).await
+    }
+
+    fn detached_read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<DetachedRead<Vec<String>>> {
+        self.inner.detached_read_range(from, to)
+    }
+
+    async fn check_writable(&self) -> anyhow::Result<()> {
+        self.inner.check_writable().await
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush().await?;
+        self.write_pending().await
+    }
+
+    fn as_written(&self, line: &str) -> String {
+        self.inner.as_written(line)
+    }
+
+    async fn replace_log(&mut self, log: &str) -> anyhow::Result<()> {
+        self.inner.replace_log(log).await?;
+        self.rewrite(log).await
+    }
+
+    // The records go with the log, gathered into one file named as its archive is
+    async fn archive_log(&mut self, suffix: &str) -> anyhow::Result<String> {
+        self.write_pending().await?;
+        let archive = self.inner.archive_log(suffix).await?;
+        let (path, keep_files) = (self.path.clone(), self.rotation.keep_files);
+        let records = format!("{}.{}", path, suffix);
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let mut archived = String::new();
+            for file in history_files(&path, keep_files) {
+                match std::fs::read_to_string(&file) {
+                    Ok(text) => archived.push_str(&text),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                    Err(e) => return Err(e).with_context(|| format!("Failed to read history file: {}", file)),
+                }
+            }
+            std::fs::write(&records, archived).with_context(|| format!("Failed to archive history file: {}", path))?;
+            remove_history(&path, keep_files)
+        })
+        .await??;
+        self.power = Some(None);
+        Ok(archive)
+    }
+
+    async fn modified(&self) -> Option<DateTime<Utc>> {
+        self.inner.modified().await
+    }
+
+    async fn last_sequence(&self) -> Option<u64> {
+        self.inner.last_sequence().await
+    }
+
+    // Before the first write to a log that had no history file there are no records, and the log is read instead
+    async fn read_records(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> anyhow::Result<Option<Vec<HistoryEntry>>> {
+        let records = read_history(self.path.clone(), self.rotation.keep_files, self.parser()).await?;
+        if records.is_none() && self.pending.is_empty() {
+            return Ok(None);
+        }
+        let records = records.into_iter().flatten().chain(self.pending.iter().cloned());
+        Ok(Some(records.filter(|record| record.at >= from && record.at < to).collect()))
+    }
+}
+
+// What the last power change among `records` left the bulb in, where there is one
+fn history_power(records: &[HistoryEntry], power: &mut Option<PowerState>) {
+    let last = records.iter().rev().find_map(|record| match record.action {
+        crate::stats::LogAction::On => Some(PowerState::On),
+        crate::stats::LogAction::Off => Some(PowerState::Off),
+        _ => None,
+    });
+    if last.is_some() {
+        *power = last;
+    }
+}
+
+fn rotated_path(path: &str, index: usize) -> String {
+    format!("{}.{}", path, index)
+}
+
+// Oldest first
+fn history_files(path: &str, keep_files: usize) -> Vec<String> {
+    let mut files: Vec<String> = (1..=keep_files).rev().map(|index| rotated_path(path, index)).collect();
+    files.push(path.to_string());
+    files
+}
+
+// Every record in the history files, oldest first, or None when there are none of the files at all. A line that
+// does not parse, such as one cut short by a crash, is skipped.
+async fn read_history(
+    path: String,
+    keep_files: usize,
+    parse: impl Fn(&str) -> Option<HistoryEntry> + Send + 'static,
+) -> anyhow::Result<Option<Vec<HistoryEntry>>> {
+    tokio::task::spawn_blocking(move || {
+        let mut records = None;
+        for file in history_files(&path, keep_files) {
+            let text = match std::fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("Failed to read history file: {}", file)),
+            };
+            let records: &mut Vec<HistoryEntry> = records.get_or_insert_with(Vec::new);
+            records.extend(text.lines().filter_map(&parse));
+        }
+        Ok(records)
+    })
+    .await?
+}
+
+// Appends `lines`, rotating before any that would take the file past its size
+fn append_history(path: &str, rotation: HistoryRotation, lines: &[String]) -> anyhow::Result<()> {
+    let open = || std::fs::OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("Failed to open history file: {}", path));
+    let mut file = open()?;
+    let mut size = file.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+    for line in lines {
+        let length = line.len() as u64 + 1;
+        if size > 0 && size + length > rotation.max_bytes {
+            drop(file);
+            rotate_history(path, rotation.keep_files)?;
+            file = open()?;
+            size = 0;
+        }
+        writeln!(file, "{}", line).with_context(|| format!("Failed to write history file: {}", path))?;
+        size += length;
+    }
+    Ok(())
+}
+
+// history.jsonl becomes history.jsonl.1, .1 becomes .2 and so on, and the oldest beyond `keep_files` is deleted
+fn rotate_history(path: &str, keep_files: usize) -> anyhow::Result<()> {
+    if keep_files == 0 {
+        return std::fs::remove_file(path).with_context(|| format!("Failed to rotate history file: {}", path));
+    }
+    remove_if_present(&rotated_path(path, keep_files))?;
+    for index in (1..keep_files).rev() {
+        let from = rotated_path(path, index);
+        match std::fs::rename(&from, rotated_path(path, index + 1)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e).with_context(|| format!("Failed to rotate history file: {}", from)),
+            _ => {},
+        }
+    }
+    std::fs::rename(path, rotated_path(path, 1)).with_context(|| format!("Failed to rotate history file: {}", path))
+}
+
+fn remove_history(path: &str, keep_files: usize) -> anyhow::Result<()> {
+    history_files(path, keep_files).iter().try_for_each(|file| remove_if_present(file))
+}
+
+fn remove_if_present(path: &str) -> anyhow::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).with_context(|| format!("Failed to remove history file: {}", path)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::{InMemoryLogger, number_log_entry};
+    use crate::stats::LogAction;
+
+    fn at(stamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(stamp).unwrap().with_timezone(&Utc)
+    }
+
+    #[tokio::test]
+    async fn test_history_records_follow_the_log_and_rotate_by_size() {
+        let path = std::env::temp_dir().join(format!("lightbulb-{}.log.jsonl", std::process::id())).display().to_string();
+        remove_history(&path, 3).unwrap();
+        let mut inner = InMemoryLogger::new();
+        inner.append_line("[2025-08-02T10:00:00+00:00] Lightbulb turned ON by agent seq=1").await.unwrap();
+        let mut logger = HistoryLogger::new(Box::new(inner), path.clone(), HistoryRotation { max_bytes: 400, keep_files: 3 });
+        let (from, to) = (DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC);
+        assert_eq!(logger.read_records(from, to).await.unwrap(), None);
+
+        // Two changes in the same instant still follow each other
+        for (sequence, power) in [(2, "OFF"), (3, "ON"), (4, "OFF")] {
+            let line = number_log_entry(&format!("[2025-08-02T11:00:00+00:00] Lightbulb turned {} by agent", power), sequence);
+            logger.append_line(&line).await.unwrap();
+        }
+        logger.flush().await.unwrap();
+        let records = logger.read_records(from, to).await.unwrap().unwrap();
+        let previous: Vec<_> = records.iter().map(|record| (record.sequence, record.previous)).collect();
+        assert_eq!(previous, [(Some(1), None), (Some(2), Some(PowerState::On)), (Some(3), Some(PowerState::Off)), (Some(4), Some(PowerState::On))]);
+        assert!(std::path::Path::new(&rotated_path(&path, 1)).exists(), "400 bytes hold fewer than four records");
+        let later = logger.read_records(at("2025-08-02T10:30:00Z"), to).await.unwrap().unwrap();
+        assert_eq!((later.len(), later[0].action), (3, LogAction::Off));
+
+        // A fresh logger carries on from the records
+        let mut logger = HistoryLogger::new(Box::new(InMemoryLogger::new()), path.clone(), HistoryRotation { max_bytes: 400, keep_files: 3 });
+        logger.append_line("[2025-08-02T12:00:00+00:00] Lightbulb turned ON seq=5").await.unwrap();
+        let records = logger.read_records(at("2025-08-02T12:00:00Z"), to).await.unwrap().unwrap();
+        assert_eq!(records[0].previous, Some(PowerState::Off));
+
+        logger.replace_log("[2025-08-02T13:00:00+00:00] Lightbulb turned OFF seq=6\n").await.unwrap();
+        let records = logger.read_records(from, to).await.unwrap().unwrap();
+        assert_eq!((records.len(), records[0].sequence, records[0].previous), (1, Some(6), None));
+        assert!(!std::path::Path::new(&rotated_path(&path, 1)).exists());
+        remove_history(&path, 3).unwrap();
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn test_history_records_are_sealed_with_the_log() {
+        let path = std::env::temp_dir().join(format!("lightbulb-sealed-{}.log.jsonl", std::process::id())).display().to_string();
+        remove_history(&path, 1).unwrap();
+        let sealed = |inner: InMemoryLogger| HistoryLogger::new(Box::new(inner), path.clone(), HistoryRotation::default()).with_cipher(LogCipher::new([3; 32]));
+        let mut logger = sealed(InMemoryLogger::new());
+        logger.append_line("[2025-08-02T10:00:00+00:00] Lightbulb turned ON by agent seq=1").await.unwrap();
+        logger.flush().await.unwrap();
+        let stored = std::fs::read_to_string(&path).unwrap();
+        assert!(!stored.contains("agent") && stored.starts_with(crate::encryption::ENCRYPTED_LINE_PREFIX), "{}", stored);
+
+        let (from, to) = (DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC);
+        let records = sealed(InMemoryLogger::new()).read_records(from, to).await.unwrap().unwrap();
+        assert_eq!((records.len(), records[0].action), (1, LogAction::On));
+        let plain = HistoryLogger::new(Box::new(InMemoryLogger::new()), path.clone(), HistoryRotation::default());
+        assert_eq!(plain.read_records(from, to).await.unwrap(), Some(Vec::new()));
+        remove_history(&path, 1).unwrap();
+    }
+}
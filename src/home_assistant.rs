@@ -0,0 +1,152 @@
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+
+use crate::config::MqttPublisherConfig;
+use crate::info::VERSION;
+
+pub const COMMAND_TOPIC_SUFFIX: &str = "/set";
+pub const DEFAULT_ENTITY_NAME: &str = "Lightbulb";
+
+// What export_home_assistant writes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HomeAssistantFormat {
+    // configuration.yaml entries: an MQTT light, or a template light over the REST API
+    #[default]
+    Yaml,
+    // The retained message that announces the bulb to Home Assistant's MQTT discovery
+    MqttDiscovery,
+}
+
+pub fn command_topic(config: &MqttPublisherConfig) -> String {
+    format!("{}{}", config.topic, COMMAND_TOPIC_SUFFIX)
+}
+
+pub fn discovery_topic(config: &MqttPublisherConfig) -> String {
+    format!("{}/light/{}/config", config.discovery_prefix, config.client_id)
+}
+
+// Home Assistant "MQTT light" config, reading the power field of the state snapshots this server publishes
+pub fn discovery_payload(config: &MqttPublisherConfig) -> serde_json::Value {
+    serde_json::json!({
+        "name": null,
+        "unique_id": config.client_id,
+        "state_topic": config.topic,
+        "state_value_template": STATE_TEMPLATE,
+        "command_topic": command_topic(config),
+        "payload_on": "ON",
+        "payload_off": "OFF",
+        "device": {
+            "identifiers": [config.client_id],
+            "name": DEFAULT_ENTITY_NAME,
+            "manufacturer": "lightbulb-mcp",
+            "sw_version": VERSION,
+        },
+    })
+}
+
+const STATE_TEMPLATE: &str = "{{ 'ON' if value_json.power == 'on' else 'OFF' }}";
+
+// A YAML double-quoted scalar, which JSON string syntax is a subset of
+fn quote(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
+// The part of a name Home Assistant keeps in entity IDs: "Desk Lamp" becomes "desk_lamp"
+fn object_id(name: &str) -> String {
+    let id: String = name.to_lowercase().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let id = id.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_");
+    if id.is_empty() { "lightbulb".to_string() } else { id }
+}
+
+// An MQTT light reading and switching the bulb through the broker, as discovery would set it up
+pub fn mqtt_yaml(config: &MqttPublisherConfig, name: &str) -> String {
+    [
+        "mqtt:".to_string(),
+        "  light:".to_string(),
+        format!("    - name: {}", quote(name)),
+        format!("      unique_id: {}", quote(&config.client_id)),
+        format!("      state_topic: {}", quote(&config.topic)),
+        format!("      state_value_template: {}", quote(STATE_TEMPLATE)),
+        format!("      command_topic: {}", quote(&command_topic(config))),
+        "      payload_on: \"ON\"".to_string(),
+        "      payload_off: \"OFF\"".to_string(),
+    ]
+    .iter()
+    .map(|line| format!("{}\n", line))
+    .collect()
+}
+
+// A template light switched with REST commands, its power polled from GET /state by a RESTful binary sensor
+pub fn rest_yaml(bind: &str, name: &str) -> String {
+    // A server listening on every interface is reached by the host's own name, which only the user knows
+    let base = match bind.parse::<SocketAddr>() {
+        Ok(addr) if addr.ip().is_unspecified() => format!("http://HOST:{}", addr.port()),
+        _ => format!("http://{}", bind),
+    };
+    let id = object_id(name);
+    let mut lines = Vec::new();
+    if base.contains("HOST") {
+        lines.push("# Replace HOST with the name or address of the machine running lightbulb-mcp".to_string());
+    }
+    lines.extend([
+        "rest_command:".to_string(),
+        format!("  {}_turn_on:", id),
+        format!("    url: {}", quote(&format!("{}/on", base))),
+        "    method: post".to_string(),
+        format!("  {}_turn_off:", id),
+        format!("    url: {}", quote(&format!("{}/off", base))),
+        "    method: post".to_string(),
+        "binary_sensor:".to_string(),
+        "  - platform: rest".to_string(),
+        format!("    name: {}", quote(&format!("{} power", name))),
+        format!("    resource: {}", quote(&format!("{}/state", base))),
+        "    value_template: \"{{ value_json.power == 'on' }}\"".to_string(),
+        "    scan_interval: 10".to_string(),
+        "light:".to_string(),
+        "  - platform: template".to_string(),
+        "    lights:".to_string(),
+        format!("      {}:", id),
+        format!("        friendly_name: {}", quote(name)),
+        format!("        value_template: \"{{{{ is_state('binary_sensor.{}_power', 'on') }}}}\"", id),
+        "        turn_on:".to_string(),
+        format!("          action: rest_command.{}_turn_on", id),
+        "        turn_off:".to_string(),
+        format!("          action: rest_command.{}_turn_off", id),
+    ]);
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn mqtt() -> MqttPublisherConfig {
+        Config::parse("[mqtt]\nhost = \"broker\"\ntopic = \"home/lightbulb\"").unwrap().mqtt.unwrap()
+    }
+
+    #[test]
+    fn test_mqtt_yaml_matches_discovery() {
+        let yaml = mqtt_yaml(&mqtt(), "Desk \"lamp\"");
+        assert!(yaml.starts_with("mqtt:\n  light:\n    - name: \"Desk \\\"lamp\\\"\"\n"), "{}", yaml);
+        assert!(yaml.contains("      command_topic: \"home/lightbulb/set\"\n"), "{}", yaml);
+        let payload = discovery_payload(&mqtt());
+        assert!(yaml.contains(&format!("      state_value_template: {}\n", payload["state_value_template"])), "{}", yaml);
+        assert_eq!(discovery_topic(&mqtt()), "homeassistant/light/lightbulb-mcp/config");
+    }
+
+    #[test]
+    fn test_rest_yaml_names_the_endpoints() {
+        let yaml = rest_yaml("127.0.0.1:8080", "Desk Lamp");
+        assert!(yaml.contains("  desk_lamp_turn_on:\n    url: \"http://127.0.0.1:8080/on\"\n    method: post\n"), "{}", yaml);
+        assert!(yaml.contains("    resource: \"http://127.0.0.1:8080/state\"\n"), "{}", yaml);
+        assert!(yaml.contains("        value_template: \"{{ is_state('binary_sensor.desk_lamp_power', 'on') }}\"\n"), "{}", yaml);
+        assert!(!yaml.contains("HOST"));
+
+        let yaml = rest_yaml("0.0.0.0:9000", "!!!");
+        assert!(yaml.starts_with("# Replace HOST"), "{}", yaml);
+        assert!(yaml.contains("\"http://HOST:9000/off\"") && yaml.contains("  lightbulb_turn_off:"), "{}", yaml);
+    }
+}
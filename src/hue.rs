@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::backend::LightBackend;
+use crate::brightness::MAX_OUTPUT;
+use crate::color::{Gamut, rgb_to_xy};
+use crate::model::{Color, PowerState};
+use crate::registry::BackendOptions;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// Hue's brightness runs from 1 to 254
+const MAX_BRI: u8 = 254;
+
+// Sends one request to the bridge's local API, below /api/<app key>, returning the JSON it answers
+#[async_trait::async_trait]
+pub trait HueApi {
+    async fn get(&self, path: &str) -> anyhow::Result<Value>;
+    async fn put(&self, path: &str, body: Value) -> anyhow::Result<Value>;
+}
+
+pub struct HueBridge {
+    client: reqwest::Client,
+    base: String,
+}
+
+impl HueBridge {
+    // `bridge` is the bridge's address, e.g. "192.168.1.20", or a URL for one behind a proxy
+    pub fn new(bridge: &str, app_key: &str) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        let bridge = bridge.trim_end_matches('/');
+        let base = match bridge.contains("://") {
+            true => format!("{}/api/{}", bridge, app_key),
+            false => format!("http://{}/api/{}", bridge, app_key),
+        };
+        Ok(Self { client, base })
+    }
+}
+
+#[async_trait::async_trait]
+impl HueApi for HueBridge {
+    async fn get(&self, path: &str) -> anyhow::Result<Value> {
+        let response = self.client.get(format!("{}/{}", self.base, path)).send().await?.error_for_status()?;
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+
+    async fn put(&self, path: &str, body: Value) -> anyhow::Result<Value> {
+        let request = self.client.put(format!("{}/{}", self.base, path)).header("Content-Type", "application/json");
+        let response = request.body(body.to_string()).send().await?.error_for_status()?;
+        Ok(serde_json::from_str(&response.text().await?)?)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HueOptions {
+    bridge: String,
+    app_key: String,
+    #[serde(default = "default_light")]
+    light: String,
+    gamut: Option<Value>,
+}
+
+fn default_light() -> String {
+    "1".to_string()
+}
+
+// A bulb paired with a Philips Hue bridge, driven through the bridge's local API
+pub struct HueBackend {
+    api: Box<dyn HueApi + Send + Sync>,
+    light: String,
+    gamut: Option<Gamut>,
+}
+
+impl HueBackend {
+    pub fn new(api: Box<dyn HueApi + Send + Sync>, light: &str) -> Self {
+        Self { api, light: light.to_string(), gamut: None }
+    }
+
+    pub fn with_gamut(mut self, gamut: Gamut) -> Self {
+        self.gamut = Some(gamut);
+        self
+    }
+
+    pub fn from_options(options: &BackendOptions) -> anyhow::Result<Self> {
+        let options: HueOptions = serde_json::from_value(Value::Object(options.clone()))
+            .map_err(|e| anyhow::anyhow!("Invalid hue backend options: {}", e))?;
+        let gamut = options.gamut.map(|gamut| Gamut::from_value(&gamut)).transpose()?;
+        let backend = Self::new(Box::new(HueBridge::new(&options.bridge, &options.app_key)?), &options.light);
+        Ok(Self { gamut, ..backend })
+    }
+
+    async fn set_state(&self, state: Value) -> anyhow::Result<()> {
+        check(self.api.put(&format!("lights/{}/state", self.light), state).await?).map(|_| ())
+    }
+}
+
+// The bridge answers 200 even when it refuses a request, with the reason in an `error` entry
+fn check(response: Value) -> anyhow::Result<Value> {
+    let error = response.as_array().into_iter().flatten().find_map(|entry| entry.get("error"));
+    match error {
+        Some(error) => anyhow::bail!("the Hue bridge refused the request: {}", error["description"].as_str().unwrap_or("no reason given")),
+        None => Ok(response),
+    }
+}
+
+// The server's levels run to MAX_OUTPUT; Hue's lowest brightness is 1, not off
+fn bri_of(level: u8) -> u8 {
+    ((f64::from(level) / f64::from(MAX_OUTPUT)) * f64::from(MAX_BRI)).round().max(1.0) as u8
+}
+
+#[async_trait::async_trait]
+impl LightBackend for HueBackend {
+    fn name(&self) -> &str {
+        "hue"
+    }
+
+    async fn set_power(&mut self, state: PowerState) -> anyhow::Result<()> {
+        self.set_state(json!({ "on": state == PowerState::On })).await
+    }
+
+    async fn power(&self) -> anyhow::Result<PowerState> {
+        let light = check(self.api.get(&format!("lights/{}", self.light)).await?)?;
+        if light["state"]["reachable"] == Value::Bool(false) {
+            anyhow::bail!("the Hue bridge cannot reach light {}", self.light);
+        }
+        match light["state"]["on"].as_bool() {
+            Some(on) => Ok(on.into()),
+            None => anyhow::bail!("the Hue bridge has no light {}", self.light),
+        }
+    }
+
+    fn supports_color(&self) -> bool {
+        true
+    }
+
+    async fn set_color(&mut self, color: Color) -> anyhow::Result<()> {
+        let xy = rgb_to_xy(color);
+        self.set_state(json!({ "xy": [xy.x, xy.y] })).await
+    }
+
+    fn gamut(&self) -> Option<Gamut> {
+        self.gamut
+    }
+
+    fn supports_brightness(&self) -> bool {
+        true
+    }
+
+    async fn set_brightness(&mut self, level: u8) -> anyhow::Result<()> {
+        self.set_state(json!({ "bri": bri_of(level) })).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct FakeBridge {
+        light: Arc<Mutex<Value>>,
+        puts: Arc<Mutex<Vec<(String, Value)>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl HueApi for FakeBridge {
+        async fn get(&self, path: &str) -> anyhow::Result<Value> {
+            assert_eq!(path, "lights/3");
+            Ok(self.light.lock().unwrap().clone())
+        }
+
+        async fn put(&self, path: &str, body: Value) -> anyhow::Result<Value> {
+            self.puts.lock().unwrap().push((path.to_string(), body.clone()));
+            match body.get("bri") {
+                Some(_) => Ok(json!([{ "error": { "type": 201, "description": "parameter, bri, is not modifiable. Device is set to off." } }])),
+                None => Ok(json!([{ "success": body }])),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hue_backend_drives_the_bridge() {
+        let bridge = FakeBridge::default();
+        let mut backend = HueBackend::new(Box::new(bridge.clone()), "3");
+        backend.set_power(PowerState::On).await.unwrap();
+        backend.set_color(Color { r: 255, g: 0, b: 0 }).await.unwrap();
+        let error = backend.set_brightness(MAX_OUTPUT).await.unwrap_err();
+        assert_eq!(error.to_string(), "the Hue bridge refused the request: parameter, bri, is not modifiable. Device is set to off.");
+        let puts = bridge.puts.lock().unwrap().clone();
+        assert_eq!(puts[0], ("lights/3/state".to_string(), json!({ "on": true })));
+        assert_eq!(puts[1].1, json!({ "xy": [0.6400744994567747, 0.32997051063169336] }));
+        assert_eq!(puts[2].1, json!({ "bri": 254 }));
+
+        *bridge.light.lock().unwrap() = json!({ "state": { "on": true, "reachable": true } });
+        assert_eq!(backend.power().await.unwrap(), PowerState::On);
+        *bridge.light.lock().unwrap() = json!({ "state": { "on": true, "reachable": false } });
+        assert_eq!(backend.power().await.unwrap_err().to_string(), "the Hue bridge cannot reach light 3");
+        assert_eq!((bri_of(0), bri_of(128)), (1, 127));
+    }
+
+    #[test]
+    fn test_hue_options_need_a_bridge_and_app_key() {
+        let options = |value: Value| value.as_object().unwrap().clone();
+        assert!(HueBackend::from_options(&options(json!({ "bridge": "192.168.1.20" }))).is_err());
+        assert!(HueBackend::from_options(&options(json!({ "bridge": "192.168.1.20", "app_key": "k", "lamp": "2" }))).is_err());
+        let backend = HueBackend::from_options(&options(json!({ "bridge": "192.168.1.20", "app_key": "k", "light": "2", "gamut": "C" }))).unwrap();
+        assert_eq!((backend.light.as_str(), backend.gamut), ("2", Some(crate::color::GAMUT_C)));
+    }
+}
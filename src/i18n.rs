@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::config::MessagesConfig;
+
+// Translations built into the server, in a subset of Fluent syntax: `key = text` lines, `{ $name }` for an
+// argument and `#` comments. English needs no catalog; it is what the code says, and any message a catalog lacks
+// stays English.
+const CATALOGS: [(&str, &str); 2] = [("de", include_str!("../locales/de.ftl")), ("fr", include_str!("../locales/fr.ftl"))];
+pub const DEFAULT_LOCALE: &str = "en";
+
+// The status and confirmation messages a deployment can reword in its config, and what their templates can say
+pub const TEMPLATE_KEYS: [&str; 15] = [
+    "status-on",
+    "status-off",
+    "status-transitioning",
+    "status-unreachable",
+    "status-locked",
+    "turned-on",
+    "turned-off",
+    "already-on",
+    "already-off",
+    "locked",
+    "unlocked",
+    "undone-on",
+    "undone-off",
+    "redone-on",
+    "redone-off",
+];
+pub const TEMPLATE_ARGUMENTS: [&str; 4] = ["bulb", "state", "brightness", "status"];
+
+static ACTIVE: OnceLock<Catalog> = OnceLock::new();
+static TEMPLATES: OnceLock<Templates> = OnceLock::new();
+
+pub type Arguments<'a> = [(&'a str, &'a dyn fmt::Display)];
+
+// A message the server says, by its catalog key and in English
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    pub key: &'static str,
+    pub english: &'static str,
+}
+
+impl Message {
+    pub const fn new(key: &'static str, english: &'static str) -> Self {
+        Self { key, english }
+    }
+
+    // The message as the config rewords it, or else in the configured language, with `arguments` filled in
+    pub fn text(&self, arguments: &Arguments) -> String {
+        if let Some(text) = TEMPLATES.get().and_then(|templates| templates.format(self.key, arguments)) {
+            return text;
+        }
+        localize(self.key, fill(self.english, arguments), arguments)
+    }
+}
+
+#[derive(Debug)]
+pub struct Templates {
+    bulb_name: String,
+    templates: HashMap<String, String>,
+}
+
+impl Templates {
+    pub fn new(config: &MessagesConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let templates = config.templates.iter().map(|(key, template)| (key.clone(), template.clone())).collect();
+        Ok(Self { bulb_name: config.bulb_name.clone(), templates })
+    }
+
+    // The template for `key` with `arguments` and the bulb's name filled in
+    pub fn format(&self, key: &str, arguments: &Arguments) -> Option<String> {
+        let template = self.templates.get(key)?;
+        let mut arguments = arguments.to_vec();
+        arguments.push(("bulb", &self.bulb_name));
+        Some(fill(template, &arguments))
+    }
+}
+
+#[derive(Debug)]
+pub struct Catalog {
+    pub locale: &'static str,
+    messages: HashMap<&'static str, &'static str>,
+}
+
+impl Catalog {
+    // The catalog for `locale`, e.g. "de" or "de-AT"; regional variants fall back to their language, and English
+    // has none
+    pub fn for_locale(locale: &str) -> anyhow::Result<Option<Self>> {
+        let language = locale.split(['-', '_']).next().unwrap_or_default().to_ascii_lowercase();
+        if language == DEFAULT_LOCALE {
+            return Ok(None);
+        }
+        let Some((name, source)) = CATALOGS.iter().find(|(name, _)| *name == language) else {
+            let known: Vec<&str> = std::iter::once(DEFAULT_LOCALE).chain(CATALOGS.iter().map(|(name, _)| *name)).collect();
+            anyhow::bail!("No messages in locale '{}'; available locales: {}", locale, known.join(", "));
+        };
+        Self::parse(name, source).map(Some)
+    }
+
+    fn parse(locale: &'static str, source: &'static str) -> anyhow::Result<Self> {
+        let mut messages = HashMap::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, text)) = line.split_once('=').map(|(key, text)| (key.trim(), text.trim())) else {
+                anyhow::bail!("{}.ftl line {}: expected `key = text`", locale, number + 1);
+            };
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                anyhow::bail!("{}.ftl line {}: '{}' is not a message key", locale, number + 1, key);
+            }
+            if messages.insert(key, text).is_some() {
+                anyhow::bail!("{}.ftl line {}: '{}' is defined twice", locale, number + 1, key);
+            }
+        }
+        Ok(Self { locale, messages })
+    }
+
+    pub fn format(&self, key: &str, arguments: &Arguments) -> Option<String> {
+        self.messages.get(key).map(|text| fill(text, arguments))
+    }
+}
+
+// Chooses the language of every message from now on; called once at startup, before any message is said
+pub fn set_locale(locale: &str) -> anyhow::Result<()> {
+    if let Some(catalog) = Catalog::for_locale(locale)? {
+        ACTIVE.set(catalog).map_err(|_| anyhow::anyhow!("The locale is already set"))?;
+    }
+    Ok(())
+}
+
+// Rewords the messages the config has templates for, in every locale; called once at startup
+pub fn set_templates(config: &MessagesConfig) -> anyhow::Result<()> {
+    let templates = Templates::new(config)?;
+    if !templates.templates.is_empty() {
+        TEMPLATES.set(templates).map_err(|_| anyhow::anyhow!("The message templates are already set"))?;
+    }
+    Ok(())
+}
+
+pub fn locale() -> &'static str {
+    ACTIVE.get().map_or(DEFAULT_LOCALE, |catalog| catalog.locale)
+}
+
+// `english`, or its translation into the configured language when the catalog has one
+pub fn localize(key: &str, english: String, arguments: &Arguments) -> String {
+    ACTIVE.get().and_then(|catalog| catalog.format(key, arguments)).unwrap_or(english)
+}
+
+// The argument names a template's `{ $name }` placeholders ask for
+pub fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|rest| rest.split_once('}')?.0.trim().strip_prefix('$'))
+}
+
+// Replaces each `{ $name }` with its argument, leaving placeholders without one as they are
+fn fill(template: &str, arguments: &Arguments) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        let placeholder = &rest[start..start + end + 1];
+        let name = placeholder[1..placeholder.len() - 1].trim().strip_prefix('$');
+        match name.and_then(|name| arguments.iter().find(|(argument, _)| *argument == name)) {
+            Some((_, value)) => text.push_str(&value.to_string()),
+            None => text.push_str(placeholder),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::LightError;
+
+    // Every message the server looks up, so a catalog cannot carry a key nothing asks for
+    fn keys() -> Vec<String> {
+        let messages = TEMPLATE_KEYS.iter().chain(&["state-summary", "diagnostics-passed", "diagnostics-failed", "jobs-summary"]);
+        let errors = [
+            "BULB_LOCKED", "INVALID_TRANSITION", "BACKEND_UNREACHABLE", "LOG_WRITE_FAILED", "LOG_UNAVAILABLE", "SIGNING_DISABLED",
+            "NOTHING_TO_UNDO", "NOTHING_TO_REDO", "INVALID_PARAMETER", "FAULT_INJECTION_UNSUPPORTED", "COLOR_UNSUPPORTED",
+            "BRIGHTNESS_UNSUPPORTED", "FIRMWARE_UPDATE_UNSUPPORTED", "FIRMWARE_UPDATE_IN_PROGRESS", "EFFECT_ALREADY_RUNNING",
+            "NO_ACTIVE_EFFECT", "UNKNOWN_JOB", "UNKNOWN_BULB", "JOB_FINISHED", "TOO_MANY_JOBS", "UNKNOWN_RESOURCE", "UNKNOWN_TOOL", "TOOL_FAILED",
+            "CONFIRMATION_INVALID", "VERSION_CONFLICT", "TOGGLE_TOO_SOON", "OUTSIDE_ALLOWED_HOURS", "INSUFFICIENT_SCOPE", "TOOL_TIMED_OUT", "SERVICE_UNAVAILABLE", "INTERNAL_ERROR",
+        ];
+        messages.map(|key| key.to_string()).chain(errors.iter().map(|code| LightError::message_key(code))).collect()
+    }
+
+    #[test]
+    fn test_catalogs_translate_every_message() {
+        let keys = keys();
+        for (locale, _) in CATALOGS {
+            let catalog = Catalog::for_locale(locale).unwrap().unwrap();
+            let mut defined: Vec<&str> = catalog.messages.keys().copied().collect();
+            defined.sort();
+            let missing: Vec<&String> = keys.iter().filter(|key| !catalog.messages.contains_key(key.as_str())).collect();
+            let unknown: Vec<&&str> = defined.iter().filter(|key| !keys.iter().any(|known| known == *key)).collect();
+            assert!(missing.is_empty() && unknown.is_empty(), "{}: missing {:?}, unknown {:?}", locale, missing, unknown);
+        }
+    }
+
+    #[test]
+    fn test_messages_fall_back_to_english() {
+        let german = Catalog::for_locale("de-AT").unwrap().unwrap();
+        assert_eq!(german.locale, "de");
+        let version = 7;
+        assert_eq!(german.format("state-summary", &[("state", &"ON"), ("version", &version)]).unwrap(), "Die Lampe ist ON (Version 7)");
+        assert_eq!(german.format("no-such-message", &[]), None);
+        assert!(Catalog::for_locale("en-GB").unwrap().is_none());
+        assert_eq!(Catalog::for_locale("tlh").unwrap_err().to_string(), "No messages in locale 'tlh'; available locales: en, de, fr");
+
+        let message = Message::new("state-summary", "The lightbulb is { $state } (version { $version })");
+        assert_eq!(message.text(&[("state", &"OFF"), ("version", &version)]), "The lightbulb is OFF (version 7)");
+        assert_eq!(fill("{ $missing } and {", &[]), "{ $missing } and {");
+    }
+
+    #[test]
+    fn test_templates_reword_messages_with_the_bulb_name() {
+        let config = MessagesConfig {
+            bulb_name: "The desk lamp".to_string(),
+            templates: [("turned-on".to_string(), "{ $bulb } is { $state } at { $brightness }%".to_string())].into(),
+        };
+        let templates = Templates::new(&config).unwrap();
+        let brightness = 40;
+        assert_eq!(templates.format("turned-on", &[("state", &"on"), ("brightness", &brightness)]).unwrap(), "The desk lamp is on at 40%");
+        assert_eq!(templates.format("turned-off", &[]), None);
+        assert_eq!(placeholders("{ $bulb } at { $brightness }% {oops}").collect::<Vec<_>>(), ["bulb", "brightness"]);
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rmcp::model::ErrorData;
+use tokio::sync::OnceCell;
+
+use crate::error::LightError;
+
+// Long enough to cover an agent's retries after a timeout, short enough that keys can be reused later
+pub const IDEMPOTENCY_WINDOW: Duration = Duration::from_secs(600);
+
+struct Entry {
+    tool: String,
+    created: Instant,
+    result: Arc<OnceCell<String>>,
+}
+
+// Results of recent state-changing tool calls, keyed by the client's idempotency key
+#[derive(Clone)]
+pub struct IdempotencyCache {
+    entries: Arc<Mutex<HashMap<String, Entry>>>,
+    window: Duration,
+}
+
+impl IdempotencyCache {
+    pub fn new(window: Duration) -> Self {
+        Self { entries: Arc::default(), window }
+    }
+
+    // Runs `change` once per key within the window; a retry gets the first call's result back.
+    // Failed calls are not remembered, so a retry after an error tries again.
+    pub async fn run(
+        &self,
+        key: Option<&str>,
+        tool: &str,
+        change: impl Future<Output = Result<String, ErrorData>>,
+    ) -> Result<String, ErrorData> {
+        let Some(key) = key else {
+            return change.await;
+        };
+        let result = {
+            let mut entries = self.lock();
+            entries.retain(|_, entry| entry.created.elapsed() < self.window);
+            let entry = entries.entry(key.to_string()).or_insert_with(|| Entry {
+                tool: tool.to_string(),
+                created: Instant::now(),
+                result: Arc::default(),
+            });
+            if entry.tool != tool {
+                return Err(LightError::InvalidParameter(format!(
+                    "Idempotency key '{}' was already used for {}",
+                    key, entry.tool
+                ))
+                .into());
+            }
+            entry.result.clone()
+        };
+        // Concurrent retries wait here for the first call instead of repeating it
+        result.get_or_try_init(|| change).await.cloned()
+    }
+
+    // Forgets every remembered result, so retried keys change the bulb again
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(IDEMPOTENCY_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    async fn count(calls: &AtomicUsize, result: Result<&str, &str>) -> Result<String, ErrorData> {
+        let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+        match result {
+            Ok(message) => Ok(format!("{} (call {})", message, call)),
+            Err(message) => Err(ErrorData::internal_error(message.to_string(), None)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_get_the_original_result() {
+        let cache = IdempotencyCache::default();
+        let calls = AtomicUsize::new(0);
+        let first = cache.run(Some("abc"), "turn_on_lightbulb", count(&calls, Ok("on"))).await.unwrap();
+        let retry = cache.run(Some("abc"), "turn_on_lightbulb", count(&calls, Ok("on"))).await.unwrap();
+        assert_eq!((first.as_str(), retry.as_str()), ("on (call 1)", "on (call 1)"));
+
+        assert!(cache.run(None, "turn_on_lightbulb", count(&calls, Ok("on"))).await.is_ok());
+        let error = cache.run(Some("abc"), "turn_off_lightbulb", count(&calls, Ok("off"))).await.unwrap_err();
+        assert!(error.message.contains("already used for turn_on_lightbulb"));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failures_and_expired_keys_run_again() {
+        let cache = IdempotencyCache::new(Duration::from_millis(20));
+        let calls = AtomicUsize::new(0);
+        assert!(cache.run(Some("abc"), "undo_last_change", count(&calls, Err("unreachable"))).await.is_err());
+        assert!(cache.run(Some("abc"), "undo_last_change", count(&calls, Ok("undone"))).await.is_ok());
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let result = cache.run(Some("abc"), "undo_last_change", count(&calls, Ok("undone"))).await.unwrap();
+        assert_eq!(result, "undone (call 3)");
+    }
+}
@@ -0,0 +1,312 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use crate::error::LightError;
+use crate::logger::{LOG_SIGNATURE_MARKER, format_log_line, parse_log_timestamp};
+use crate::model::PowerState;
+use crate::stats::{LOG_EVENT_PREFIX, parse_daily_total, parse_power_event};
+use crate::tags::{format_tags, normalize_tags};
+
+// Larger logs are imported in parts
+pub const MAX_IMPORT_BYTES: usize = 1024 * 1024;
+
+// What an imported log is written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportFormat {
+    // This server's own log, as lightbulb.log or the lightbulb://log resource holds it
+    Text,
+    // A lightbulb://log.json page, a JSON array of entries or one JSON entry per line
+    Json,
+    // Comma-separated values with a header row naming the columns
+    Csv,
+}
+
+// One entry to import, as it will read in the log (before signing) and when it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedEntry {
+    pub position: usize,
+    pub at: DateTime<Utc>,
+    pub line: String,
+}
+
+// An entry left out of an import, numbered by line for text and CSV and by position for JSON
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skipped {
+    pub position: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParsedLog {
+    pub entries: Vec<ImportedEntry>,
+    pub skipped: Vec<Skipped>,
+}
+
+impl ParsedLog {
+    fn push(&mut self, position: usize, entry: Result<(DateTime<Utc>, String), String>) {
+        match entry {
+            Ok((at, line)) => self.entries.push(ImportedEntry { position, at, line }),
+            Err(reason) => self.skipped.push(Skipped { position, reason }),
+        }
+    }
+}
+
+// The log with imported entries merged in, and the entries that were not
+#[derive(Debug, Clone, PartialEq)]
+pub struct Merge {
+    pub log: String,
+    pub imported: usize,
+    pub skipped: Vec<Skipped>,
+}
+
+// A JSON entry or CSV row; the names are those of lightbulb://log.json, with the usual alternatives
+#[derive(Debug, Default, Deserialize)]
+struct Record {
+    #[serde(alias = "timestamp", alias = "time")]
+    at: Option<String>,
+    message: Option<String>,
+    #[serde(alias = "state")]
+    power: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(alias = "changed_by", alias = "who")]
+    by: Option<String>,
+    reason: Option<String>,
+}
+
+// Validates every entry and turns it into a log line, refusing the whole import only when it cannot be read at all
+pub fn parse(format: ImportFormat, content: &str) -> Result<ParsedLog, LightError> {
+    if content.len() > MAX_IMPORT_BYTES {
+        return Err(LightError::InvalidParameter(format!("Logs over {} KiB must be imported in parts", MAX_IMPORT_BYTES / 1024)));
+    }
+    let mut parsed = ParsedLog::default();
+    match format {
+        ImportFormat::Text => {
+            for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                parsed.push(index + 1, text_entry(line.trim()));
+            }
+        },
+        ImportFormat::Json => {
+            for (index, record) in json_records(content)?.into_iter().enumerate() {
+                let entry = serde_json::from_value::<Record>(record).map_err(|e| e.to_string()).and_then(record_entry);
+                parsed.push(index + 1, entry);
+            }
+        },
+        ImportFormat::Csv => {
+            let mut rows = content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+            let header: Vec<String> = rows.next().map(|(_, line)| csv_fields(line)).unwrap_or_default();
+            let header: Vec<String> = header.iter().map(|name| name.trim().to_lowercase()).collect();
+            let has = |names: &[&str]| header.iter().any(|name| names.contains(&name.as_str()));
+            if !has(&["at", "timestamp", "time"]) || !has(&["power", "state", "message"]) {
+                return Err(LightError::InvalidParameter(
+                    "A CSV log needs a header row with a timestamp column and a power or message column".to_string(),
+                ));
+            }
+            for (index, line) in rows {
+                parsed.push(index + 1, csv_record(&header, line).and_then(record_entry));
+            }
+        },
+    }
+    Ok(parsed)
+}
+
+// A line of this server's log, without its signature, which only the key that made it can vouch for
+fn text_entry(line: &str) -> Result<(DateTime<Utc>, String), String> {
+    let entry = line.rsplit_once(LOG_SIGNATURE_MARKER).map_or(line, |(entry, _)| entry);
+    let Some((at, rest)) = parse_log_timestamp(entry) else {
+        return Err("not a log entry".to_string());
+    };
+    match (parse_power_event(entry), rest.trim().strip_prefix(LOG_EVENT_PREFIX)) {
+        (Some(_), Some(action)) => Ok((at, format_log_line(at, action))),
+        _ => Err("not a power change".to_string()),
+    }
+}
+
+fn json_records(content: &str) -> Result<Vec<serde_json::Value>, LightError> {
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(content) {
+        return match value {
+            serde_json::Value::Array(records) => Ok(records),
+            serde_json::Value::Object(mut page) => match page.remove("entries") {
+                Some(serde_json::Value::Array(records)) => Ok(records),
+                _ => Ok(vec![serde_json::Value::Object(page)]),
+            },
+            _ => Err(LightError::InvalidParameter("A JSON log must hold entries as objects".to_string())),
+        };
+    }
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(|e| LightError::InvalidParameter(format!("Unreadable JSON log: {}", e))))
+        .collect()
+}
+
+// Splits a CSV row, allowing double-quoted fields with "" for a quote
+fn csv_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let (mut quoted, mut chars) = (false, line.chars().peekable());
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                fields.last_mut().unwrap().push('"');
+            },
+            ('"', _) => quoted = !quoted,
+            (',', false) => fields.push(String::new()),
+            (c, _) => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+fn csv_record(header: &[String], line: &str) -> Result<Record, String> {
+    let mut record = serde_json::Map::new();
+    for (name, value) in header.iter().zip(csv_fields(line)) {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        let value = match name.as_str() {
+            // Tags are separated by semicolons or spaces, since commas separate the columns
+            "tags" => value.split([';', ' ']).filter(|tag| !tag.is_empty()).map(|tag| serde_json::json!(tag)).collect(),
+            _ => serde_json::json!(value),
+        };
+        record.insert(name.clone(), value);
+    }
+    serde_json::from_value(serde_json::Value::Object(record)).map_err(|e| e.to_string())
+}
+
+fn parse_timestamp(text: &str) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(text) {
+        return Some(at.with_timezone(&Utc));
+    }
+    // Without an offset the time is taken as UTC
+    ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .map(|at| at.and_utc())
+}
+
+fn record_entry(record: Record) -> Result<(DateTime<Utc>, String), String> {
+    let Some(stamp) = record.at.as_deref().map(str::trim).filter(|stamp| !stamp.is_empty()) else {
+        return Err("no timestamp".to_string());
+    };
+    let at = parse_timestamp(stamp).ok_or_else(|| format!("unreadable timestamp '{}'", stamp))?;
+    // The message of a lightbulb://log.json entry already says everything
+    if let Some(message) = record.message.as_deref().filter(|message| message.starts_with(LOG_EVENT_PREFIX)) {
+        return text_entry(&format_log_line(at, &message[LOG_EVENT_PREFIX.len()..]));
+    }
+    let power = match record.power.as_deref().map(str::trim) {
+        Some(power) if power.eq_ignore_ascii_case("on") => PowerState::On,
+        Some(power) if power.eq_ignore_ascii_case("off") => PowerState::Off,
+        Some(power) => return Err(format!("power '{}' is neither ON nor OFF", power)),
+        None => return Err("not a power change".to_string()),
+    };
+    let mut action = power.log_action().to_string();
+    let tags = normalize_tags(&record.tags).map_err(|e| e.to_string())?;
+    if !tags.is_empty() {
+        action.push_str(&format!(" {}", format_tags(&tags)));
+    }
+    for text in [&record.by, &record.reason].into_iter().flatten() {
+        if text.contains(['\n', '\r']) {
+            return Err("a line break in who or why".to_string());
+        }
+    }
+    if let Some(by) = record.by.as_deref().map(str::trim).filter(|by| !by.is_empty()) {
+        action.push_str(&format!(" by {}", by));
+    }
+    if let Some(reason) = record.reason.as_deref().map(str::trim).filter(|reason| !reason.is_empty()) {
+        action.push_str(&format!(" (reason: {})", reason));
+    }
+    Ok((at, format_log_line(at, &action)))
+}
+
+// Merges `entries` into `log` in time order, leaving out those dated after `now`, those on a day the log holds only
+// as a daily total, and those the log already has. `as_written` turns each entry into the line the log writes, e.g.
+// signed. Lines without a timestamp stay behind the entry they follow.
+pub fn merge(log: &str, mut entries: Vec<ImportedEntry>, mut skipped: Vec<Skipped>, now: DateTime<Utc>, as_written: impl Fn(&str) -> String) -> Merge {
+    let unsigned = |line: &str| line.rsplit_once(LOG_SIGNATURE_MARKER).map_or(line, |(entry, _)| entry).to_string();
+    let mut known: HashSet<String> = log.lines().map(unsigned).collect();
+    let compacted: HashSet<NaiveDate> = log.lines().filter_map(parse_daily_total).map(|total| total.day).collect();
+    entries.sort_by_key(|entry| entry.at);
+    let mut imports = Vec::new();
+    for entry in entries {
+        let reason = if entry.at > now {
+            "dated in the future"
+        } else if compacted.contains(&entry.at.date_naive()) {
+            "on a day the log has compacted"
+        } else if !known.insert(entry.line.clone()) {
+            "already in the log"
+        } else {
+            imports.push(entry);
+            continue;
+        };
+        skipped.push(Skipped { position: entry.position, reason: reason.to_string() });
+    }
+    skipped.sort_by_key(|skip| skip.position);
+    let imported = imports.len();
+    let mut imports = imports.into_iter().peekable();
+    let mut lines = Vec::new();
+    let mut last = DateTime::<Utc>::MIN_UTC;
+    for line in log.lines().filter(|line| !line.trim().is_empty()) {
+        let at = parse_log_timestamp(line).map(|(at, _)| at).unwrap_or(last);
+        while let Some(entry) = imports.next_if(|entry| entry.at < at) {
+            lines.push(as_written(&entry.line));
+        }
+        lines.push(line.to_string());
+        last = at;
+    }
+    lines.extend(imports.map(|entry| as_written(&entry.line)));
+    Merge { log: lines.iter().map(|line| format!("{}\n", line)).collect(), imported, skipped }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(stamp: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(stamp).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_every_format_is_normalized_to_log_lines() {
+        let text = "Lightbulb Activity Log:\n\n[2026-10-01T07:00:00+00:00] Lightbulb turned ON [morning] by kitchen sig=abc\n[2026-10-01T07:05:00Z] Lightbulb server SHUTDOWN (signal)\n";
+        let parsed = parse(ImportFormat::Text, text).unwrap();
+        assert_eq!(parsed.entries[0].line, "[2026-10-01T07:00:00+00:00] Lightbulb turned ON [morning] by kitchen");
+        assert_eq!(parsed.skipped, [Skipped { position: 1, reason: "not a log entry".to_string() }, Skipped { position: 4, reason: "not a power change".to_string() }]);
+
+        let json = r#"{"entries": [{"at": "2026-10-01T07:00:00Z", "message": "Lightbulb turned ON [morning] by kitchen", "power": "on", "tags": ["morning"]}, {"timestamp": "2026-10-01 20:00:00", "state": "OFF", "tags": ["Night"], "by": "hall"}, {"at": "yesterday", "power": "on"}]}"#;
+        let parsed = parse(ImportFormat::Json, json).unwrap();
+        let lines: Vec<&str> = parsed.entries.iter().map(|entry| entry.line.as_str()).collect();
+        assert_eq!(lines, ["[2026-10-01T07:00:00+00:00] Lightbulb turned ON [morning] by kitchen", "[2026-10-01T20:00:00+00:00] Lightbulb turned OFF [night] by hall"]);
+        assert_eq!(parsed.skipped[0].reason, "unreadable timestamp 'yesterday'");
+
+        let csv = "timestamp,state,who,reason,tags\n2026-10-01T07:00:00Z,on,kitchen,\"coffee, then news\",morning;weekday\n2026-10-01T08:00:00Z,dim,kitchen,,\n";
+        let parsed = parse(ImportFormat::Csv, csv).unwrap();
+        assert_eq!(parsed.entries[0].line, "[2026-10-01T07:00:00+00:00] Lightbulb turned ON [morning,weekday] by kitchen (reason: coffee, then news)");
+        assert_eq!(parsed.skipped, [Skipped { position: 3, reason: "power 'dim' is neither ON nor OFF".to_string() }]);
+        assert!(parse(ImportFormat::Csv, "when,what\n").is_err());
+    }
+
+    #[test]
+    fn test_merging_keeps_the_log_in_time_order() {
+        let log = "[2026-09-30T00:00:00+00:00] Lightbulb daily total 2026-09-30: entries=2 on=1 off=1 on_secs=60 left_on=false\n[2026-10-01T07:00:00+00:00] Lightbulb turned ON\n[2026-10-01T09:00:00+00:00] Lightbulb turned OFF\n";
+        let entry = |position, stamp: &str, action: &str| ImportedEntry { position, at: at(stamp), line: format_log_line(at(stamp), action) };
+        let entries = vec![
+            entry(1, "2026-10-01T08:00:00Z", "OFF"),
+            entry(2, "2026-10-01T07:00:00Z", "ON"),
+            entry(3, "2026-10-01T10:00:00Z", "ON"),
+            entry(4, "2026-09-30T12:00:00Z", "ON"),
+            entry(5, "2026-10-20T12:00:00Z", "ON"),
+        ];
+        let merge = merge(log, entries, Vec::new(), at("2026-10-14T12:00:00Z"), |line| format!("{} sig=x", line));
+        let times: Vec<&str> = merge.log.lines().map(|line| &line[6..14]).collect();
+        assert_eq!(times, ["09-30T00", "10-01T07", "10-01T08", "10-01T09", "10-01T10"]);
+        assert!(merge.log.contains("[2026-10-01T08:00:00+00:00] Lightbulb turned OFF sig=x\n"));
+        assert_eq!(merge.imported, 2);
+        let reasons: Vec<(usize, &str)> = merge.skipped.iter().map(|skip| (skip.position, skip.reason.as_str())).collect();
+        assert_eq!(reasons, [(2, "already in the log"), (4, "on a day the log has compacted"), (5, "dated in the future")]);
+    }
+}
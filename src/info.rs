@@ -0,0 +1,115 @@
+use std::time::Instant;
+
+use serde::Serialize;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const GIT_HASH: Option<&str> = option_env!("LIGHTBULB_GIT_HASH");
+
+// Cargo features this build was compiled with
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("core", cfg!(feature = "core")),
+        ("history", cfg!(feature = "history")),
+        ("audit", cfg!(feature = "audit")),
+        ("simulation", cfg!(feature = "simulation")),
+        ("effects", cfg!(feature = "effects")),
+        ("macros", cfg!(feature = "macros")),
+        ("diagnostics", cfg!(feature = "diagnostics")),
+        ("analytics", cfg!(feature = "analytics")),
+        ("adaptive", cfg!(feature = "adaptive")),
+        ("motion", cfg!(feature = "motion")),
+        ("contact", cfg!(feature = "contact")),
+        ("presence", cfg!(feature = "presence")),
+        ("scenes", cfg!(feature = "scenes")),
+        ("schedules", cfg!(feature = "schedules")),
+        ("bulbs", cfg!(feature = "bulbs")),
+        ("hue", cfg!(feature = "hue")),
+        ("http", cfg!(feature = "http")),
+        ("home-assistant", cfg!(feature = "home-assistant")),
+        ("safety", cfg!(feature = "safety")),
+        ("jobs", cfg!(feature = "jobs")),
+        ("webhooks", cfg!(feature = "webhooks")),
+        ("mqtt", cfg!(feature = "mqtt")),
+        ("triggers", cfg!(feature = "triggers")),
+        ("notifications", cfg!(feature = "notifications")),
+        ("weather", cfg!(feature = "weather")),
+        ("rest", cfg!(feature = "rest")),
+        ("tui", cfg!(feature = "tui")),
+        ("systemd", cfg!(feature = "systemd")),
+        ("daemon", cfg!(feature = "daemon")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("aggregator", cfg!(feature = "aggregator")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+// How the service was deployed, recorded when it is built so server_info can report it
+#[derive(Debug, Clone, Default)]
+pub struct Deployment {
+    pub transport: Option<String>,
+    pub backend: String,
+    pub config_file: Option<String>,
+    pub log_file: Option<String>,
+    pub signing_key_file: Option<String>,
+    pub available_backends: Vec<String>,
+    // The configuration it was built from, with secrets redacted, for lightbulb://config
+    pub config: Option<serde_json::Value>,
+}
+
+impl Deployment {
+    // The redacted configuration with what was resolved at startup: the transport, the active backend and
+    // absolute paths. None for a service built without a configuration.
+    pub fn effective_config(&self) -> Option<serde_json::Value> {
+        let config = self.config.clone()?;
+        let absolute = |path: &Option<String>| {
+            path.as_deref().map(|path| std::path::absolute(path).map_or_else(|_| path.to_string(), |path| path.display().to_string()))
+        };
+        Some(serde_json::json!({
+            "transport": self.transport,
+            "backend": self.backend,
+            "paths": {
+                "working_directory": std::env::current_dir().ok().map(|dir| dir.display().to_string()),
+                "config_file": absolute(&self.config_file),
+                "log_file": absolute(&self.log_file),
+                "signing_key_file": absolute(&self.signing_key_file),
+            },
+            "config": config,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerInfoReport {
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub features: Vec<&'static str>,
+    pub uptime_secs: u64,
+    pub transport: Option<String>,
+    pub backend: String,
+    pub available_backends: Vec<String>,
+    pub config_file: Option<String>,
+    pub log_file: Option<String>,
+    pub signing_key_file: Option<String>,
+    // State is only kept in memory, so there is never a state file yet
+    pub state_file: Option<String>,
+}
+
+impl ServerInfoReport {
+    pub fn new(deployment: &Deployment, started_at: Instant) -> Self {
+        Self {
+            version: VERSION,
+            git_hash: GIT_HASH,
+            features: enabled_features(),
+            uptime_secs: started_at.elapsed().as_secs(),
+            transport: deployment.transport.clone(),
+            backend: deployment.backend.clone(),
+            available_backends: deployment.available_backends.clone(),
+            config_file: deployment.config_file.clone(),
+            log_file: deployment.log_file.clone(),
+            signing_key_file: deployment.signing_key_file.clone(),
+            state_file: None,
+        }
+    }
+}
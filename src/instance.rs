@@ -0,0 +1,87 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+
+use anyhow::Context;
+
+// Held for as long as the server runs, so a second server pointed at the same log refuses to start instead of
+// interleaving its writes. The lock is on a file beside the log rather than the log itself, since compaction
+// renames a new log into place.
+#[derive(Debug)]
+pub struct InstanceLock {
+    path: String,
+    _file: File,
+}
+
+pub fn lock_path(log_file: &str) -> String {
+    format!("{}.lock", log_file)
+}
+
+impl InstanceLock {
+    // Takes the advisory lock for `log_file` and records this process in it, or fails naming the holder
+    pub fn acquire(log_file: &str) -> anyhow::Result<Self> {
+        let path = lock_path(log_file);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open lock file: {}", path))?;
+        match file.try_lock() {
+            Ok(()) => {},
+            Err(TryLockError::WouldBlock) => {
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+                let holder = match holder.trim() {
+                    "" => String::new(),
+                    pid => format!(" (process {})", pid),
+                };
+                anyhow::bail!(
+                    "Another lightbulb-mcp instance{} is already using {}; stop it, or give this one its own log_file",
+                    holder,
+                    log_file
+                );
+            },
+            Err(TryLockError::Error(e)) => return Err(e).with_context(|| format!("Failed to lock {}", path)),
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        writeln!(file, "{}", std::process::id()).with_context(|| format!("Failed to write lock file: {}", path))?;
+        Ok(Self { path, _file: file })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log_file(name: &str) -> String {
+        std::env::temp_dir().join(format!("lightbulb-instance-{}-{}.log", name, std::process::id())).display().to_string()
+    }
+
+    #[test]
+    fn test_second_instance_is_refused() {
+        let log = log_file("refused");
+        let lock = InstanceLock::acquire(&log).unwrap();
+        assert_eq!(std::fs::read_to_string(lock.path()).unwrap().trim(), std::process::id().to_string());
+        let error = InstanceLock::acquire(&log).unwrap_err().to_string();
+        assert!(error.contains(&format!("(process {}) is already using {}", std::process::id(), log)), "{}", error);
+        drop(lock);
+        let _ = std::fs::remove_file(lock_path(&log));
+    }
+
+    #[test]
+    fn test_lock_is_released_on_drop() {
+        let log = log_file("released");
+        drop(InstanceLock::acquire(&log).unwrap());
+        let lock = InstanceLock::acquire(&log).unwrap();
+        assert!(InstanceLock::acquire(&log_file("other")).is_ok());
+        drop(lock);
+        let _ = std::fs::remove_file(lock_path(&log));
+        let _ = std::fs::remove_file(lock_path(&log_file("other")));
+    }
+}
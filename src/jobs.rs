@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::clock::SharedClock;
+use crate::error::LightError;
+
+// Enough for an effect, a firmware update and a few macros at once
+pub const MAX_RUNNING_JOBS: usize = 8;
+// Finished jobs are kept this long for get_job_status, oldest dropped first
+pub const MAX_FINISHED_JOBS: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+// What get_job_status and list_jobs report about a job
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: &'static str,
+    pub description: String,
+    pub status: JobStatus,
+    pub started: DateTime<Utc>,
+    pub finished: Option<DateTime<Utc>>,
+    // Percent done and what the job is doing, for jobs that report it
+    pub progress: Option<u32>,
+    pub message: Option<String>,
+    // The job's final report, as the tool would have returned it had the client waited
+    pub result: Option<String>,
+    pub cancel_requested: bool,
+}
+
+struct Job {
+    info: JobInfo,
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+// Long operations started in the background, so a client gets a job ID back at once and polls it rather than
+// holding its request open for minutes
+#[derive(Clone)]
+pub struct JobRegistry {
+    table: Arc<Mutex<JobTable>>,
+    clock: SharedClock,
+}
+
+impl JobRegistry {
+    pub fn new(clock: SharedClock) -> Self {
+        Self { table: Arc::default(), clock }
+    }
+
+    // Runs `job` on a background task and returns its ID. The job gets a channel for (percent, message) progress
+    // reports and a token that cancel_job cancels, and ends with its status and report.
+    pub fn start<F, Fut>(&self, kind: &'static str, description: String, job: F) -> Result<String, LightError>
+    where
+        F: FnOnce(mpsc::UnboundedSender<(u32, String)>, CancellationToken) -> Fut,
+        Fut: Future<Output = (JobStatus, String)> + Send + 'static,
+    {
+        let cancel = CancellationToken::new();
+        let id = {
+            let mut table = self.lock();
+            if table.jobs.values().filter(|job| job.info.status == JobStatus::Running).count() >= MAX_RUNNING_JOBS {
+                return Err(LightError::TooManyJobs(MAX_RUNNING_JOBS));
+            }
+            table.next_id += 1;
+            let id = table.next_id;
+            let info = JobInfo {
+                id: job_id(id),
+                kind,
+                description,
+                status: JobStatus::Running,
+                started: self.clock.now(),
+                finished: None,
+                progress: None,
+                message: None,
+                result: None,
+                cancel_requested: false,
+            };
+            table.jobs.insert(id, Job { info, cancel: cancel.clone() });
+            id
+        };
+        let (progress, mut updates) = mpsc::unbounded_channel();
+        let job = job(progress, cancel);
+        let registry = self.clone();
+        tokio::spawn(async move {
+            tokio::pin!(job);
+            let (status, result) = loop {
+                tokio::select! {
+                    outcome = &mut job => break outcome,
+                    Some(update) = updates.recv() => registry.report(id, update),
+                }
+            };
+            while let Ok(update) = updates.try_recv() {
+                registry.report(id, update);
+            }
+            registry.finish(id, status, result);
+        });
+        Ok(job_id(id))
+    }
+
+    pub fn get(&self, id: &str) -> Result<JobInfo, LightError> {
+        let key = parse_job_id(id)?;
+        self.lock().jobs.get(&key).map(|job| job.info.clone()).ok_or_else(|| unknown_job(id))
+    }
+
+    // Every job kept, running or finished, the oldest first
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.lock().jobs.values().map(|job| job.info.clone()).collect()
+    }
+
+    // Asks a running job to stop; it reports cancelled once it has, unless it was past the point of stopping
+    pub fn cancel(&self, id: &str) -> Result<JobInfo, LightError> {
+        let key = parse_job_id(id)?;
+        let mut table = self.lock();
+        let job = table.jobs.get_mut(&key).ok_or_else(|| unknown_job(id))?;
+        if job.info.status != JobStatus::Running {
+            return Err(LightError::JobFinished(job.info.id.clone()));
+        }
+        job.info.cancel_requested = true;
+        job.cancel.cancel();
+        Ok(job.info.clone())
+    }
+
+    fn report(&self, id: u64, (progress, message): (u32, String)) {
+        if let Some(job) = self.lock().jobs.get_mut(&id) {
+            job.info.progress = Some(progress);
+            job.info.message = Some(message);
+        }
+    }
+
+    fn finish(&self, id: u64, status: JobStatus, result: String) {
+        let now = self.clock.now();
+        let mut table = self.lock();
+        if let Some(job) = table.jobs.get_mut(&id) {
+            job.info.status = status;
+            job.info.finished = Some(now);
+            job.info.result = Some(result);
+        }
+        let finished: Vec<u64> = table.jobs.iter().filter(|(_, job)| job.info.status != JobStatus::Running).map(|(id, _)| *id).collect();
+        for id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_JOBS)) {
+            table.jobs.remove(id);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JobTable> {
+        self.table.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn job_id(id: u64) -> String {
+    format!("job-{}", id)
+}
+
+fn parse_job_id(id: &str) -> Result<u64, LightError> {
+    id.trim().strip_prefix("job-").and_then(|number| number.parse().ok()).ok_or_else(|| unknown_job(id))
+}
+
+fn unknown_job(id: &str) -> LightError {
+    LightError::UnknownJob(id.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::system_clock;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_jobs_report_progress_and_their_result() {
+        let jobs = JobRegistry::new(system_clock());
+        let id = jobs
+            .start("count", "Counting to three".to_string(), |progress, _| async move {
+                for n in 1..=3 {
+                    let _ = progress.send((n * 33, format!("Counted {}", n)));
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                (JobStatus::Completed, "Counted to three".to_string())
+            })
+            .unwrap();
+        assert_eq!(id, "job-1");
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let running = jobs.get(" job-1 ").unwrap();
+        assert_eq!((running.status, running.progress, running.message.as_deref()), (JobStatus::Running, Some(66), Some("Counted 2")));
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        let done = jobs.get(&id).unwrap();
+        assert_eq!((done.status, done.result.as_deref()), (JobStatus::Completed, Some("Counted to three")));
+        assert!(done.finished.is_some());
+        assert_eq!(jobs.cancel(&id).unwrap_err().code(), "JOB_FINISHED");
+        assert_eq!(jobs.get("job-9").unwrap_err().code(), "UNKNOWN_JOB");
+        assert_eq!(jobs.get("nine").unwrap_err().code(), "UNKNOWN_JOB");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_jobs_stop_and_running_jobs_are_limited() {
+        let jobs = JobRegistry::new(system_clock());
+        let wait = |_, cancel: CancellationToken| async move {
+            cancel.cancelled().await;
+            (JobStatus::Cancelled, "Stopped waiting".to_string())
+        };
+        let ids: Vec<String> = (0..MAX_RUNNING_JOBS).map(|_| jobs.start("wait", "Waiting".to_string(), wait).unwrap()).collect();
+        assert_eq!(jobs.start("wait", "Waiting".to_string(), wait).unwrap_err().code(), "TOO_MANY_JOBS");
+
+        assert!(jobs.cancel(&ids[0]).unwrap().cancel_requested);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let cancelled = jobs.get(&ids[0]).unwrap();
+        assert_eq!((cancelled.status, cancelled.result.as_deref()), (JobStatus::Cancelled, Some("Stopped waiting")));
+        assert!(jobs.start("wait", "Waiting".to_string(), wait).is_ok());
+        assert_eq!(jobs.list().len(), MAX_RUNNING_JOBS + 1);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+#[cfg(feature = "encryption")]
+use crate::encryption::LogCipher;
+use crate::logger::{LOG_ACTION_OFF, LOG_ACTION_ON, format_log_line, parse_log_timestamp};
+use crate::model::PowerState;
+
+const BEGIN_RECORD: &str = "begin";
+const ABORT_RECORD: &str = "abort";
+pub const RECOVERED_SUFFIX: &str = "(recovered from the journal)";
+
+pub fn journal_path(log_file: &str) -> String {
+    format!("{}.journal", log_file)
+}
+
+// A power change the journal recorded before the backend was told to make it, with the entry it was to log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Intent {
+    pub at: DateTime<Utc>,
+    pub target: PowerState,
+    pub entry: String,
+}
+
+// Write-ahead record of logged power changes, kept beside the log. Each change is written and synced here
+// before the backend is driven, and the journal is emptied once the log has been flushed, so whatever it
+// still holds at startup was interrupted between changing the bulb and writing its entry.
+pub struct Journal {
+    path: String,
+    // Whether anything has been written since the journal was last emptied
+    dirty: bool,
+    // Seals each record as the log's entries are sealed, when the log is encrypted
+    #[cfg(feature = "encryption")]
+    cipher: Option<LogCipher>,
+}
+
+impl Journal {
+    pub fn new(path: String) -> Self {
+        Self {
+            path,
+            dirty: true,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    pub fn with_cipher(mut self, cipher: LogCipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    async fn write(&mut self, record: &str) -> anyhow::Result<()> {
+        #[cfg(feature = "encryption")]
+        let record = &match &self.cipher {
+            Some(cipher) => cipher.encrypt_line(record)?,
+            None => record.to_string(),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Failed to open journal: {}", self.path))?;
+        file.write_all(format!("{}\n", record).as_bytes()).await?;
+        file.sync_data().await.with_context(|| format!("Failed to sync journal: {}", self.path))?;
+        self.dirty = true;
+        Ok(())
+    }
+
+    pub async fn begin(&mut self, at: DateTime<Utc>, target: PowerState, entry: &str) -> anyhow::Result<()> {
+        self.write(&format!("{} {} {} {}", BEGIN_RECORD, at.to_rfc3339(), power_word(target), entry)).await
+    }
+
+    // The change last begun did not happen
+    pub async fn abort(&mut self) -> anyhow::Result<()> {
+        self.write(ABORT_RECORD).await
+    }
+
+    // Called once every entry journaled so far is in the log
+    pub async fn clear(&mut self) -> anyhow::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        match tokio::fs::remove_file(&self.path).await {
+            Ok(()) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e).with_context(|| format!("Failed to clear journal: {}", self.path)),
+        }
+        self.dirty = false;
+        Ok(())
+    }
+
+    // The changes still in the journal that were not aborted, oldest first
+    pub async fn pending(&self) -> anyhow::Result<Vec<Intent>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(journal) => {
+                // A record cut short would not decrypt, and is skipped anyway
+                #[cfg(feature = "encryption")]
+                let journal = match &self.cipher {
+                    Some(cipher) => {
+                        let complete = &journal[..journal.rfind('\n').map_or(0, |end| end + 1)];
+                        cipher.decrypt_log(complete).with_context(|| format!("Failed to decrypt journal: {}", self.path))?
+                    },
+                    None => journal,
+                };
+                Ok(parse_journal(&journal))
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e).with_context(|| format!("Failed to read journal: {}", self.path)),
+        }
+    }
+}
+
+fn power_word(power: PowerState) -> &'static str {
+    match power {
+        PowerState::On => LOG_ACTION_ON,
+        PowerState::Off => LOG_ACTION_OFF,
+    }
+}
+
+// A record cut short by the crash has no newline and is skipped
+fn parse_journal(journal: &str) -> Vec<Intent> {
+    let mut intents = Vec::new();
+    for record in journal.split_inclusive('\n').filter(|record| record.ends_with('\n')) {
+        let record = record.trim_end_matches('\n');
+        if record == ABORT_RECORD {
+            intents.pop();
+            continue;
+        }
+        let Some(intent) = parse_intent(record) else {
+            continue;
+        };
+        intents.push(intent);
+    }
+    intents
+}
+
+fn parse_intent(record: &str) -> Option<Intent> {
+    let mut fields = record.strip_prefix(BEGIN_RECORD)?.strip_prefix(' ')?.splitn(3, ' ');
+    let at = DateTime::parse_from_rfc3339(fields.next()?).ok()?.with_timezone(&Utc);
+    let target = match fields.next()? {
+        LOG_ACTION_ON => PowerState::On,
+        LOG_ACTION_OFF => PowerState::Off,
+        _ => return None,
+    };
+    Some(Intent { at, target, entry: fields.next()?.to_string() })
+}
+
+// The entries to append for the intents whose lines are missing from `tail`, the end of the log. Every intent
+// but the last was followed by another change, so the backend made it; the last may have been cut short, and
+// is only logged when the bulb's `power` now shows it happened.
+pub fn repair(intents: &[Intent], tail: &[String], power: Option<PowerState>) -> Vec<(DateTime<Utc>, String)> {
+    let mut lines = tail.iter();
+    let mut missing = Vec::new();
+    for (i, intent) in intents.iter().enumerate() {
+        // Logged once the backend answered, so a little after the journal's record, and perhaps signed
+        let logged = lines.by_ref().any(|line| {
+            parse_log_timestamp(line).is_some_and(|(at, _)| {
+                let expected = format_log_line(at, &intent.entry);
+                at >= intent.at && (*line == expected || line.starts_with(&format!("{} sig=", expected)))
+            })
+        });
+        if logged {
+            continue;
+        }
+        // Nothing after an unlogged change can have been flushed either
+        lines = [].iter();
+        if i + 1 < intents.len() || power == Some(intent.target) {
+            missing.push((intent.at, format!("{} {}", intent.entry, RECOVERED_SUFFIX)));
+        }
+    }
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn intent(time: &str, target: PowerState, entry: &str) -> Intent {
+        Intent { at: at(time), target, entry: entry.to_string() }
+    }
+
+    #[test]
+    fn test_aborted_and_torn_records_are_dropped() {
+        let journal = "\
+begin 2025-08-01T09:00:00+00:00 ON ON by kitchen-agent
+begin 2025-08-01T09:01:00+00:00 OFF OFF (reason: bedtime)
+abort
+begin 2025-08-01T09:02:00+00:00 OFF OFF (reason: bedtime)
+begin 2025-08-01T09:03:00+00:00 ON O";
+        assert_eq!(
+            parse_journal(journal),
+            [
+                intent("2025-08-01T09:00:00+00:00", PowerState::On, "ON by kitchen-agent"),
+                intent("2025-08-01T09:02:00+00:00", PowerState::Off, "OFF (reason: bedtime)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repair_appends_only_what_the_log_is_missing() {
+        let intents = [
+            intent("2025-08-01T09:00:00+00:00", PowerState::On, "ON by kitchen-agent"),
+            intent("2025-08-01T09:05:00+00:00", PowerState::Off, "OFF"),
+            intent("2025-08-01T09:10:00+00:00", PowerState::On, "ON"),
+        ];
+        let tail = vec!["[2025-08-01T09:00:01+00:00] Lightbulb turned ON by kitchen-agent sig=abc".to_string()];
+        assert_eq!(
+            repair(&intents, &tail, Some(PowerState::On)),
+            [
+                (at("2025-08-01T09:05:00+00:00"), "OFF (recovered from the journal)".to_string()),
+                (at("2025-08-01T09:10:00+00:00"), "ON (recovered from the journal)".to_string()),
+            ]
+        );
+        // The last change never reached the bulb
+        assert_eq!(repair(&intents, &tail, Some(PowerState::Off)).len(), 1);
+        assert!(repair(&intents[..1], &tail, None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_clear_removes_the_journal() {
+        let path = std::env::temp_dir().join(format!("lightbulb-journal-{}.journal", std::process::id())).display().to_string();
+        let mut journal = Journal::new(path.clone());
+        journal.clear().await.unwrap();
+        journal.begin(at("2025-08-01T09:00:00+00:00"), PowerState::On, "ON").await.unwrap();
+        journal.begin(at("2025-08-01T09:01:00+00:00"), PowerState::Off, "OFF").await.unwrap();
+        journal.abort().await.unwrap();
+        assert_eq!(journal.pending().await.unwrap(), [intent("2025-08-01T09:00:00+00:00", PowerState::On, "ON")]);
+        journal.clear().await.unwrap();
+        assert!(journal.pending().await.unwrap().is_empty());
+        assert!(!std::path::Path::new(&path).exists());
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn test_an_encrypted_journal_keeps_no_entry_in_plaintext() {
+        let path = std::env::temp_dir().join(format!("lightbulb-journal-sealed-{}.journal", std::process::id())).display().to_string();
+        let mut journal = Journal::new(path.clone()).with_cipher(LogCipher::new([7; 32]));
+        journal.clear().await.unwrap();
+        journal.begin(at("2025-08-01T09:00:00+00:00"), PowerState::On, "ON by kitchen-agent (reason: guests)").await.unwrap();
+        // Cut short by a crash
+        let mut file = OpenOptions::new().append(true).open(&path).await.unwrap();
+        file.write_all(b"enc:AAAA").await.unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("kitchen-agent") && !written.contains("2025-08-01"), "{}", written);
+        assert_eq!(journal.pending().await.unwrap(), [intent("2025-08-01T09:00:00+00:00", PowerState::On, "ON by kitchen-agent (reason: guests)")]);
+        assert!(Journal::new(path.clone()).with_cipher(LogCipher::new([8; 32])).pending().await.is_err());
+        journal.clear().await.unwrap();
+    }
+}
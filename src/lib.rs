@@ -0,0 +1,99 @@
+//! Model Context Protocol server for managing a virtual lightbulb.
+
+#[cfg(feature = "adaptive")]
+pub mod adaptive;
+#[cfg(feature = "aggregator")]
+pub mod aggregator;
+pub mod actor;
+#[cfg(feature = "http")]
+pub mod auth;
+#[cfg(feature = "schedules")]
+pub mod automation;
+pub mod backend;
+pub mod brightness;
+#[cfg(feature = "bulbs")]
+pub mod bulbs;
+pub mod clock;
+pub mod color;
+pub mod compaction;
+pub mod config;
+pub mod confirm;
+#[cfg(feature = "contact")]
+pub mod contact;
+pub mod curfew;
+#[cfg(all(feature = "daemon", unix))]
+pub mod daemon;
+pub mod diagnostics;
+pub mod effects;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod info;
+pub mod error;
+pub mod events;
+pub mod history_file;
+pub mod home_assistant;
+#[cfg(feature = "hue")]
+pub mod hue;
+pub mod i18n;
+pub mod idempotency;
+pub mod import;
+pub mod instance;
+#[cfg(feature = "jobs")]
+pub mod jobs;
+pub mod journal;
+pub mod logger;
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock;
+pub mod model;
+#[cfg(feature = "motion")]
+pub mod motion;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "notifications")]
+pub mod notifications;
+pub mod offline;
+#[cfg(feature = "rest")]
+pub mod openapi;
+pub mod palette;
+#[cfg(feature = "presence")]
+pub mod presence;
+pub mod privacy;
+#[cfg(feature = "recording")]
+pub mod recording;
+pub mod registry;
+pub mod reply;
+pub mod report;
+pub mod rules;
+#[cfg(feature = "safety")]
+pub mod safety;
+#[cfg(feature = "scenes")]
+pub mod scenes;
+#[cfg(feature = "rest")]
+pub mod rest;
+pub mod schedule;
+pub mod service;
+pub mod shutdown;
+#[cfg(feature = "soak")]
+pub mod soak;
+pub mod state;
+pub mod stdio;
+pub mod stats;
+#[cfg(all(feature = "systemd", unix))]
+pub mod systemd;
+pub mod tags;
+pub mod timeline;
+pub mod transport;
+pub mod usage_cache;
+pub mod verbosity;
+#[cfg(feature = "test-support")]
+pub mod testing;
+#[cfg(feature = "tui")]
+pub mod tui;
+#[cfg(feature = "triggers")]
+pub mod triggers;
+#[cfg(feature = "weather")]
+pub mod weather;
+#[cfg(feature = "webhooks")]
+pub mod webhooks;
+
+pub use service::{LightService, LightServiceBuilder, SessionFactory, ToolGroup, tool_router_for};
@@ -0,0 +1,878 @@
+use std::collections::VecDeque;
+use std::fs::TryLockError;
+use std::io::{SeekFrom, Write};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use tokio::fs::{File, OpenOptions, read_to_string};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+
+use crate::stats::HistoryEntry;
+
+pub const LOG_FILE_NAME: &str = "lightbulb.log";
+pub const LOG_ACTION_ON: &str = "ON";
+pub const LOG_ACTION_OFF: &str = "OFF";
+pub const LOG_SHUTDOWN: &str = "SHUTDOWN";
+pub const LOG_DENIED: &str = "DENIED";
+pub const LOG_OUTAGE: &str = "OUTAGE";
+pub const LOG_RESTORE: &str = "RESTORE";
+pub const LOG_COLOR: &str = "COLOR";
+pub const LOG_BRIGHTNESS: &str = "BRIGHTNESS";
+pub const LOG_NAMED: &str = "NAMED";
+pub const LOG_SIGNATURE_MARKER: &str = " sig=";
+// Who a refused call is logged as when the client gave no name, or it was left out
+pub const UNNAMED_CLIENT: &str = "an unnamed client";
+// Every entry the server writes is numbered, one up from the last, before it is signed
+pub const LOG_SEQUENCE_MARKER: &str = " seq=";
+// Buffered entries are written out once this many accumulate, or when the owner flushes
+pub const LOG_FLUSH_ENTRIES: usize = 64;
+// How long a write waits for another process holding the log's advisory lock, such as a rotation, to let go
+const LOG_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+const LOG_LOCK_RETRY: Duration = Duration::from_millis(10);
+// Tail reads step back from the end of the log file this many bytes at a time
+const TAIL_BLOCK_BYTES: u64 = 8 * 1024;
+// Range reads search the log file for their start until it is narrowed down to this many bytes, then read on
+const RANGE_SEARCH_BYTES: u64 = 64 * 1024;
+
+pub fn format_log_line(at: DateTime<Utc>, action: &str) -> String {
+    format!("[{}] Lightbulb turned {}", at.to_rfc3339(), action)
+}
+
+// The last entry a server writes when it is told to stop, with what told it
+pub fn format_shutdown_line(at: DateTime<Utc>, cause: &str) -> String {
+    format!("[{}] Lightbulb server {} ({})", at.to_rfc3339(), LOG_SHUTDOWN, cause)
+}
+
+// A tool call a curfew refused, e.g. "Lightbulb DENIED turn_on_lightbulb to kid-tablet (outside allowed hours: bedtime)"
+pub fn format_denied_line(at: DateTime<Utc>, tool: &str, who: Option<&str>, curfew: &str) -> String {
+    let who = who.unwrap_or(UNNAMED_CLIENT);
+    format!("[{}] Lightbulb {} {} to {} (outside allowed hours: {})", at.to_rfc3339(), LOG_DENIED, tool, who, curfew)
+}
+
+// A color or brightness change, e.g. "Lightbulb COLOR #ff8800 by agent" or "Lightbulb BRIGHTNESS 40% by agent"
+pub fn format_attribute_line(at: DateTime<Utc>, entry: &str) -> String {
+    format!("[{}] Lightbulb {}", at.to_rfc3339(), entry)
+}
+
+// A bulb given a name or aliases, e.g. "Lightbulb NAMED \"Desk Lamp\" (aliases: \"reading light\") by agent", or
+// "Lightbulb NAMED no name" once they are cleared
+pub fn format_named_line(at: DateTime<Utc>, name: Option<&str>, aliases: &[String], who: Option<&str>) -> String {
+    let mut line = match name {
+        Some(name) => format!("[{}] Lightbulb {} \"{}\"", at.to_rfc3339(), LOG_NAMED, name),
+        None => format!("[{}] Lightbulb {} no name", at.to_rfc3339(), LOG_NAMED),
+    };
+    if !aliases.is_empty() {
+        let quoted: Vec<String> = aliases.iter().map(|alias| format!("\"{}\"", alias)).collect();
+        line.push_str(&format!(" (aliases: {})", quoted.join(", ")));
+    }
+    if let Some(who) = who {
+        line.push_str(&format!(" by {}", who));
+    }
+    line
+}
+
+// A simulated power outage beginning, or ending with the bulb back ON or OFF as its power_restore behavior has it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutageEntry {
+    Began { secs: u64 },
+    Ended { secs: u64, action: &'static str, restore: &'static str },
+}
+
+// "Lightbulb OUTAGE (power cut for 30s)", then "Lightbulb RESTORE ON (power back after 30s, power_restore = on)"
+pub fn format_outage_line(at: DateTime<Utc>, entry: &OutageEntry) -> String {
+    match entry {
+        OutageEntry::Began { secs } => format!("[{}] Lightbulb {} (power cut for {}s)", at.to_rfc3339(), LOG_OUTAGE, secs),
+        OutageEntry::Ended { secs, action, restore } => {
+            format!("[{}] Lightbulb {} {} (power back after {}s, power_restore = {})", at.to_rfc3339(), LOG_RESTORE, action, secs, restore)
+        },
+    }
+}
+
+// The entry with its number appended
+pub fn number_log_entry(entry: &str, sequence: u64) -> String {
+    format!("{}{}{}", entry, LOG_SEQUENCE_MARKER, sequence)
+}
+
+// Takes the number out of a line, returning the line without it; lines imported or written before entries were
+// numbered have none. The number is usually last, but a line recovered from the journal goes on after it.
+pub fn split_log_sequence(line: &str) -> (String, Option<u64>) {
+    let Some(start) = line.rfind(LOG_SEQUENCE_MARKER) else {
+        return (line.to_string(), None);
+    };
+    let rest = &line[start + LOG_SEQUENCE_MARKER.len()..];
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    match rest[..digits].parse() {
+        Ok(sequence) => (format!("{}{}", &line[..start], &rest[digits..]), Some(sequence)),
+        Err(_) => (line.to_string(), None),
+    }
+}
+
+pub fn parse_log_sequence(line: &str) -> Option<u64> {
+    split_log_sequence(line).1
+}
+
+// Splits "[timestamp] message" into the entry's time and the rest of the line
+pub fn parse_log_timestamp(line: &str) -> Option<(DateTime<Utc>, &str)> {
+    let (timestamp, rest) = line.strip_prefix('[')?.split_once(']')?;
+    let at = DateTime::parse_from_rfc3339(timestamp).ok()?.with_timezone(&Utc);
+    Some((at, rest))
+}
+
+// A read of the log that owns everything it needs, so it can run while the logger carries on writing
+pub type DetachedRead<T = String> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send>>;
+
+// Part of the log, from `offset` up to `end`, cut back to whole lines unless one line is longer than the chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogChunk {
+    pub text: String,
+    pub offset: u64,
+    pub end: u64,
+    // The size of the whole log when the chunk was read
+    pub total: u64,
+}
+
+impl LogChunk {
+    // `read` holds the bytes from `offset` on
+    fn new(mut read: Vec<u8>, offset: u64, total: u64) -> Self {
+        if offset + (read.len() as u64) < total
+            && let Some(last) = read.iter().rposition(|byte| *byte == b'\n')
+        {
+            read.truncate(last + 1);
+        }
+        let end = offset + read.len() as u64;
+        Self { text: String::from_utf8_lossy(&read).into_owned(), offset, end, total }
+    }
+
+    // Where the following chunk starts, if the log goes on past this one
+    pub fn next(&self) -> Option<u64> {
+        (self.end < self.total).then_some(self.end)
+    }
+}
+
+// Trait for logging abstraction
+#[async_trait::async_trait]
+pub trait Logger {
+    async fn append_line(&mut self, line: &str) -> anyhow::Result<()>;
+    async fn read_log(&self) -> anyhow::Result<String>;
+
+    // Confirms new entries could be written, without writing one
+    async fn check_writable(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    // Writes out anything buffered; read_log only sees entries written before the last flush
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    // Up to `limit` bytes of the log from `offset` on, without holding the rest in memory where the logger can
+    async fn read_chunk(&self, offset: u64, limit: usize) -> anyhow::Result<LogChunk> {
+        let log = self.read_log().await?.into_bytes();
+        let offset = offset.min(log.len() as u64);
+        let read = log[offset as usize..].iter().take(limit).copied().collect();
+        Ok(LogChunk::new(read, offset, log.len() as u64))
+    }
+
+    // The last `entries` non-blank lines of the log, oldest first
+    async fn read_tail(&self, entries: usize) -> anyhow::Result<Vec<String>> {
+        Ok(last_lines(&self.read_log().await?, entries))
+    }
+
+    // The entries written at or after `from` and before `to`. The log is written in time order, which loggers
+    // can rely on to find them without reading the rest.
+    async fn read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> anyhow::Result<Vec<String>> {
+        Ok(lines_between(&self.read_log().await?, from, to))
+    }
+
+    // A read of the log as of the last flush that need not hold up the logger, for logs on slow storage;
+    // None reads through read_log instead
+    fn detached_read(&self) -> Option<DetachedRead> {
+        None
+    }
+
+    // As detached_read, for read_chunk
+    fn detached_read_chunk(&self, _offset: u64, _limit: usize) -> Option<DetachedRead<LogChunk>> {
+        None
+    }
+
+    // As detached_read, for read_tail
+    fn detached_read_tail(&self, _entries: usize) -> Option<DetachedRead<Vec<String>>> {
+        None
+    }
+
+    // As detached_read, for read_range
+    fn detached_read_range(&self, _from: DateTime<Utc>, _to: DateTime<Utc>) -> Option<DetachedRead<Vec<String>>> {
+        None
+    }
+
+    // The line as append_line would write it, e.g. signed
+    fn as_written(&self, line: &str) -> String {
+        line.to_string()
+    }
+
+    // Replaces the whole log with `log`, e.g. after compaction, discarding anything still buffered
+    async fn replace_log(&mut self, _log: &str) -> anyhow::Result<()> {
+        anyhow::bail!("This log cannot be rewritten")
+    }
+
+    // Moves the whole log aside to a file named with `suffix`, leaving an empty log, and returns where it went
+    async fn archive_log(&mut self, _suffix: &str) -> anyhow::Result<String> {
+        anyhow::bail!("This log cannot be archived")
+    }
+
+    // When the log was last written to before this process opened it, where the logger can tell
+    async fn modified(&self) -> Option<DateTime<Utc>> {
+        None
+    }
+
+    // The highest entry number in the log, for numbering to carry on from at startup, where the logger can find it
+    async fn last_sequence(&self) -> Option<u64> {
+        None
+    }
+
+    // The entries written at or after `from` and before `to` as the structured records the logger keeps of them,
+    // oldest first; None where it keeps none, leaving the caller to take the log's lines apart
+    async fn read_records(&self, _from: DateTime<Utc>, _to: DateTime<Utc>) -> anyhow::Result<Option<Vec<HistoryEntry>>> {
+        Ok(None)
+    }
+
+    // Returns the line as written, so callers can keep their own view of the log up to date
+    async fn log_event(&mut self, at: DateTime<Utc>, action: &str) -> anyhow::Result<String> {
+        let line = format_log_line(at, action);
+        self.append_line(&line).await?;
+        Ok(line)
+    }
+}
+
+// File-based logger for production. Appends are buffered with the file held open between flushes, and a flush
+// closes the file again, so a rotated log is picked up by the next entry. Every write of the buffer, and every
+// rewrite or move of the log, holds an exclusive advisory lock on the log file, so other processes that take it
+// too, to rotate or append, never interleave with a partial write.
+pub struct FileLogger {
+    file_path: String,
+    file: Option<std::fs::File>,
+    pending: String,
+    buffered: usize,
+}
+
+impl FileLogger {
+    pub fn new(file_path: String) -> Self {
+        Self { file_path, file: None, pending: String::new(), buffered: 0 }
+    }
+
+    // Kept open after writing when `keep_open`. Entries stay buffered for the next write if the lock cannot be had.
+    async fn write_pending(&mut self, keep_open: bool) -> anyhow::Result<()> {
+        self.buffered = 0;
+        if self.pending.is_empty() {
+            if !keep_open {
+                self.file = None;
+            }
+            return Ok(());
+        }
+        let (path, file) = (self.file_path.clone(), self.file.take());
+        let file = tokio::task::spawn_blocking(move || lock_log_file(&path, file)).await??;
+        let pending = std::mem::take(&mut self.pending);
+        let path = self.file_path.clone();
+        let file = tokio::task::spawn_blocking(move || -> anyhow::Result<std::fs::File> {
+            let mut file = file;
+            file.write_all(pending.as_bytes()).with_context(|| format!("Failed to write to log file: {}", path))?;
+            file.unlock().with_context(|| format!("Failed to unlock log file: {}", path))?;
+            Ok(file)
+        })
+        .await??;
+        if keep_open {
+            self.file = Some(file);
+        }
+        Ok(())
+    }
+
+    // Held while the log is rewritten or moved, and released by dropping it
+    async fn lock(&self) -> anyhow::Result<std::fs::File> {
+        let path = self.file_path.clone();
+        tokio::task::spawn_blocking(move || lock_log_file(&path, None)).await?
+    }
+
+    async fn open(&self) -> anyhow::Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file_path)
+            .await
+            .with_context(|| format!("Failed to open log file: {}", self.file_path))
+    }
+}
+
+#[async_trait::async_trait]
+impl Logger for FileLogger {
+    async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+        if self.file.is_none() {
+            self.file = Some(self.open().await?.into_std().await);
+        }
+        self.pending.push_str(line);
+        self.pending.push('\n');
+        self.buffered += 1;
+        if self.buffered >= LOG_FLUSH_ENTRIES {
+            self.write_pending(true).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.write_pending(false).await
+    }
+
+    async fn read_log(&self) -> anyhow::Result<String> {
+        read_log_file(self.file_path.clone()).await
+    }
+
+    async fn read_chunk(&self, offset: u64, limit: usize) -> anyhow::Result<LogChunk> {
+        read_log_file_chunk(self.file_path.clone(), offset, limit).await
+    }
+
+    fn detached_read(&self) -> Option<DetachedRead> {
+        Some(Box::pin(read_log_file(self.file_path.clone())))
+    }
+
+    fn detached_read_chunk(&self, offset: u64, limit: usize) -> Option<DetachedRead<LogChunk>> {
+        Some(Box::pin(read_log_file_chunk(self.file_path.clone(), offset, limit)))
+    }
+
+    async fn read_tail(&self, entries: usize) -> anyhow::Result<Vec<String>> {
+        read_log_file_tail(self.file_path.clone(), entries).await
+    }
+
+    fn detached_read_tail(&self, entries: usize) -> Option<DetachedRead<Vec<String>>> {
+        Some(Box::pin(read_log_file_tail(self.file_path.clone(), entries)))
+    }
+
+    async fn read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> anyhow::Result<Vec<String>> {
+        read_log_file_range(self.file_path.clone(), from, to).await
+    }
+
+    fn detached_read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<DetachedRead<Vec<String>>> {
+        Some(Box::pin(read_log_file_range(self.file_path.clone(), from, to)))
+    }
+
+    async fn check_writable(&self) -> anyhow::Result<()> {
+        self.open().await?;
+        Ok(())
+    }
+
+    // Writes the new log beside the old one and renames it into place, so a crash leaves one or the other whole
+    async fn replace_log(&mut self, log: &str) -> anyhow::Result<()> {
+        self.flush().await?;
+        // Held until the new log is in place; whoever waits on it then finds the log moved and opens the new one
+        let _lock = self.lock().await?;
+        let replacement = format!("{}.compacting", self.file_path);
+        tokio::fs::write(&replacement, log).await.with_context(|| format!("Failed to write log file: {}", replacement))?;
+        tokio::fs::rename(&replacement, &self.file_path)
+            .await
+            .with_context(|| format!("Failed to replace log file: {}", self.file_path))
+    }
+
+    async fn archive_log(&mut self, suffix: &str) -> anyhow::Result<String> {
+        self.flush().await?;
+        let lock = self.lock().await?;
+        let archive = format!("{}.{}", self.file_path, suffix);
+        if tokio::fs::try_exists(&archive).await.unwrap_or(false) {
+            anyhow::bail!("Log archive already exists: {}", archive);
+        }
+        tokio::fs::rename(&self.file_path, &archive)
+            .await
+            .with_context(|| format!("Failed to archive log file: {}", self.file_path))?;
+        drop(lock);
+        self.open().await?;
+        Ok(archive)
+    }
+
+    async fn modified(&self) -> Option<DateTime<Utc>> {
+        let metadata = tokio::fs::metadata(&self.file_path).await.ok()?;
+        metadata.modified().ok().map(DateTime::<Utc>::from)
+    }
+
+    // Near the end, unless entries were imported after it or the log is from before entries were numbered, when
+    // the whole file is searched
+    async fn last_sequence(&self) -> Option<u64> {
+        let tail = read_log_file_tail(self.file_path.clone(), LOG_FLUSH_ENTRIES).await.ok()?;
+        if let Some(sequence) = tail.iter().filter_map(|line| parse_log_sequence(line)).max() {
+            return Some(sequence);
+        }
+        read_log_file(self.file_path.clone()).await.ok()?.lines().filter_map(parse_log_sequence).max()
+    }
+}
+
+// Blocks until the log's advisory lock is free, for up to LOG_LOCK_TIMEOUT. A log renamed away since `file` was
+// opened, or while waiting for its lock, is opened again at `path`, so the lock held is always on the log.
+fn lock_log_file(path: &str, file: Option<std::fs::File>) -> anyhow::Result<std::fs::File> {
+    let open = || std::fs::OpenOptions::new().create(true).append(true).open(path).with_context(|| format!("Failed to open log file: {}", path));
+    let deadline = Instant::now() + LOG_LOCK_TIMEOUT;
+    let mut file = match file {
+        Some(file) => file,
+        None => open()?,
+    };
+    loop {
+        match file.try_lock() {
+            Ok(()) if is_at(&file, path) => return Ok(file),
+            Ok(()) => file = open()?,
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => std::thread::sleep(LOG_LOCK_RETRY),
+            Err(TryLockError::WouldBlock) => anyhow::bail!("The log file is locked by another process: {}", path),
+            Err(TryLockError::Error(e)) => return Err(e).with_context(|| format!("Failed to lock log file: {}", path)),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_at(file: &std::fs::File, path: &str) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(named)) => (open.dev(), open.ino()) == (named.dev(), named.ino()),
+        _ => false,
+    }
+}
+
+// Elsewhere an open file cannot be renamed away
+#[cfg(not(unix))]
+fn is_at(_file: &std::fs::File, _path: &str) -> bool {
+    true
+}
+
+async fn read_log_file(file_path: String) -> anyhow::Result<String> {
+    read_to_string(&file_path).await.with_context(|| format!("Failed to read log file: {}", file_path))
+}
+
+async fn read_log_file_chunk(file_path: String, offset: u64, limit: usize) -> anyhow::Result<LogChunk> {
+    let read = async {
+        let mut file = File::open(&file_path).await?;
+        let total = file.metadata().await?.len();
+        let offset = offset.min(total);
+        file.seek(SeekFrom::Start(offset)).await?;
+        let mut read = Vec::with_capacity(limit.min((total - offset) as usize));
+        file.take(limit as u64).read_to_end(&mut read).await?;
+        Ok::<_, std::io::Error>(LogChunk::new(read, offset, total))
+    };
+    read.await.with_context(|| format!("Failed to read log file: {}", file_path))
+}
+
+// Reads backwards from the end a block at a time, only as far as the last `entries` lines go
+async fn read_log_file_tail(file_path: String, entries: usize) -> anyhow::Result<Vec<String>> {
+    let read = async {
+        let mut file = File::open(&file_path).await?;
+        let mut start = file.metadata().await?.len();
+        let mut tail = Vec::new();
+        while start > 0 && tail.iter().filter(|byte| **byte == b'\n').count() <= entries {
+            let block_start = start.saturating_sub(TAIL_BLOCK_BYTES);
+            let mut block = vec![0; (start - block_start) as usize];
+            file.seek(SeekFrom::Start(block_start)).await?;
+            file.read_exact(&mut block).await?;
+            block.append(&mut tail);
+            (tail, start) = (block, block_start);
+        }
+        // Short of the start of the file, the first line read may be the end of a longer one
+        if start > 0 {
+            let first_line = tail.iter().position(|byte| *byte == b'\n').map_or(tail.len(), |end| end + 1);
+            tail.drain(..first_line);
+        }
+        Ok::<_, std::io::Error>(last_lines(&String::from_utf8_lossy(&tail), entries))
+    };
+    read.await.with_context(|| format!("Failed to read log file: {}", file_path))
+}
+
+// Bisects the file for the first entry at or after `from`, reading a line at each step, so a day of a year-long
+// log costs a few dozen small reads and the day itself
+async fn read_log_file_range(file_path: String, from: DateTime<Utc>, to: DateTime<Utc>) -> anyhow::Result<Vec<String>> {
+    let read = async {
+        let mut file = File::open(&file_path).await?;
+        let (mut start, mut end) = (0, file.metadata().await?.len());
+        while end - start > RANGE_SEARCH_BYTES {
+            let middle = start + (end - start) / 2;
+            match first_entry_after(&mut file, middle).await? {
+                Some(at) if at < from => start = middle,
+                _ => end = middle,
+            }
+        }
+        let mut lines = lines_from(&mut file, start).await?;
+        let mut entries = Vec::new();
+        while let Some(line) = lines.next_line().await? {
+            match parse_log_timestamp(&line) {
+                Some((at, _)) if at >= to => break,
+                Some((at, _)) if at >= from => entries.push(line),
+                _ => {},
+            }
+        }
+        Ok::<_, std::io::Error>(entries)
+    };
+    read.await.with_context(|| format!("Failed to read log file: {}", file_path))
+}
+
+// The lines of the file from the first one starting at or after `offset`
+async fn lines_from(file: &mut File, offset: u64) -> std::io::Result<tokio::io::Lines<BufReader<&mut File>>> {
+    file.seek(SeekFrom::Start(offset.saturating_sub(1))).await?;
+    let mut reader = BufReader::new(file);
+    // Reading on from the byte before `offset` drops the rest of a line it cuts, or the newline ending the last one
+    if offset > 0 {
+        reader.read_line(&mut String::new()).await?;
+    }
+    Ok(reader.lines())
+}
+
+// The time of the first timestamped entry starting at or after `offset`, if any does
+async fn first_entry_after(file: &mut File, offset: u64) -> std::io::Result<Option<DateTime<Utc>>> {
+    let mut lines = lines_from(file, offset).await?;
+    while let Some(line) = lines.next_line().await? {
+        if let Some((at, _)) = parse_log_timestamp(&line) {
+            return Ok(Some(at));
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn lines_between(log: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Vec<String> {
+    log.lines()
+        .filter(|line| parse_log_timestamp(line).is_some_and(|(at, _)| at >= from && at < to))
+        .map(String::from)
+        .collect()
+}
+
+fn last_lines(log: &str, entries: usize) -> Vec<String> {
+    let mut lines: Vec<String> = log.lines().rev().filter(|line| !line.trim().is_empty()).take(entries).map(String::from).collect();
+    lines.reverse();
+    lines
+}
+
+// In-memory logger for testing
+#[derive(Debug, Clone, Default)]
+pub struct InMemoryLogger {
+    entries: VecDeque<String>,
+}
+
+impl InMemoryLogger {
+    pub fn new() -> Self {
+        Self {
+            entries: VecDeque::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Logger for InMemoryLogger {
+    async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+        self.entries.push_back(line.to_owned());
+        Ok(())
+    }
+
+    async fn read_log(&self) -> anyhow::Result<String> {
+        Ok(self.entries.iter().map(|entry| format!("{}\n", entry)).collect())
+    }
+
+    async fn replace_log(&mut self, log: &str) -> anyhow::Result<()> {
+        self.entries = log.lines().map(String::from).collect();
+        Ok(())
+    }
+
+    async fn last_sequence(&self) -> Option<u64> {
+        self.entries.iter().filter_map(|entry| parse_log_sequence(entry)).max()
+    }
+}
+
+// Decorator that appends an ed25519 signature to every entry written by the inner logger
+pub struct SigningLogger {
+    inner: Box<dyn Logger + Send + Sync>,
+    signing_key: SigningKey,
+}
+
+impl SigningLogger {
+    pub fn new(inner: Box<dyn Logger + Send + Sync>, signing_key: SigningKey) -> Self {
+        Self { inner, signing_key }
+    }
+
+    fn sign(&self, line: &str) -> String {
+        let signature = self.signing_key.sign(line.as_bytes());
+        format!("{}{}{}", line, LOG_SIGNATURE_MARKER, BASE64.encode(signature.to_bytes()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Logger for SigningLogger {
+    async fn append_line(&mut self, line: &str) -> anyhow::Result<()> {
+        self.inner.append_line(&self.sign(line)).await
+    }
+
+    async fn log_event(&mut self, at: DateTime<Utc>, action: &str) -> anyhow::Result<String> {
+        let line = self.sign(&format_log_line(at, action));
+        self.inner.append_line(&line).await?;
+        Ok(line)
+    }
+
+    async fn read_log(&self) -> anyhow::Result<String> {
+        self.inner.read_log().await
+    }
+
+    async fn read_chunk(&self, offset: u64, limit: usize) -> anyhow::Result<LogChunk> {
+        self.inner.read_chunk(offset, limit).await
+    }
+
+    fn detached_read(&self) -> Option<DetachedRead> {
+        self.inner.detached_read()
+    }
+
+    fn detached_read_chunk(&self, offset: u64, limit: usize) -> Option<DetachedRead<LogChunk>> {
+        self.inner.detached_read_chunk(offset, limit)
+    }
+
+    async fn read_tail(&self, entries: usize) -> anyhow::Result<Vec<String>> {
+        self.inner.read_tail(entries).await
+    }
+
+    fn detached_read_tail(&self, entries: usize) -> Option<DetachedRead<Vec<String>>> {
+        self.inner.detached_read_tail(entries)
+    }
+
+    async fn read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> anyhow::Result<Vec<String>> {
+        self.inner.read_range(from, to).await
+    }
+
+    fn detached_read_range(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Option<DetachedRead<Vec<String>>> {
+        self.inner.detached_read_range(from, to)
+    }
+
+    async fn check_writable(&self) -> anyhow::Result<()> {
+        self.inner.check_writable().await
+    }
+
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        self.inner.flush().await
+    }
+
+    fn as_written(&self, line: &str) -> String {
+        self.sign(line)
+    }
+
+    async fn replace_log(&mut self, log: &str) -> anyhow::Result<()> {
+        self.inner.replace_log(log).await
+    }
+
+    async fn archive_log(&mut self, suffix: &str) -> anyhow::Result<String> {
+        self.inner.archive_log(suffix).await
+    }
+
+    async fn modified(&self) -> Option<DateTime<Utc>> {
+        self.inner.modified().await
+    }
+
+    async fn last_sequence(&self) -> Option<u64> {
+        self.inner.last_sequence().await
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SignatureCheck {
+    Valid,
+    Invalid,
+    Unsigned,
+}
+
+pub fn verify_log_line(verifying_key: &VerifyingKey, line: &str) -> SignatureCheck {
+    let Some((entry, encoded)) = line.rsplit_once(LOG_SIGNATURE_MARKER) else {
+        return SignatureCheck::Unsigned;
+    };
+    let signature = BASE64.decode(encoded.trim()).ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok());
+    match signature {
+        Some(signature) if verifying_key.verify(entry.as_bytes(), &signature).is_ok() => SignatureCheck::Valid,
+        _ => SignatureCheck::Invalid,
+    }
+}
+
+// Loads the base64-encoded ed25519 seed at `path`, generating a new key file if none exists
+pub fn load_or_create_signing_key(path: &str) -> anyhow::Result<SigningKey> {
+    match std::fs::read_to_string(path) {
+        Ok(encoded) => {
+            let bytes = BASE64.decode(encoded.trim())
+                .with_context(|| format!("Signing key file is not valid base64: {}", path))?;
+            let seed: [u8; 32] = bytes.try_into()
+                .map_err(|_| anyhow::anyhow!("Signing key file must contain a 32-byte ed25519 seed: {}", path))?;
+            Ok(SigningKey::from_bytes(&seed))
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let mut seed = [0u8; 32];
+            getrandom::getrandom(&mut seed)
+                .map_err(|e| anyhow::anyhow!("Failed to generate signing key: {}", e))?;
+            std::fs::write(path, BASE64.encode(seed))
+                .with_context(|| format!("Failed to write signing key file: {}", path))?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+                    .with_context(|| format!("Failed to restrict signing key permissions: {}", path))?;
+            }
+            Ok(SigningKey::from_bytes(&seed))
+        },
+        Err(e) => Err(e).with_context(|| format!("Failed to read signing key file: {}", path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_numbers_come_out_of_any_line() {
+        let line = number_log_entry("[2025-08-01T09:00:00+00:00] Lightbulb turned ON by agent (reason: a seq=9 b)", 42);
+        assert_eq!(split_log_sequence(&line), ("[2025-08-01T09:00:00+00:00] Lightbulb turned ON by agent (reason: a seq=9 b)".to_string(), Some(42)));
+        assert_eq!(parse_log_sequence(&format!("{} (recovered from the journal) sig=abc", number_log_entry("ON", 7))), Some(7));
+        assert_eq!(split_log_sequence("[2025-08-01T09:00:00+00:00] Lightbulb turned OFF"), ("[2025-08-01T09:00:00+00:00] Lightbulb turned OFF".to_string(), None));
+    }
+
+    #[tokio::test]
+    async fn test_verify_log_signatures_detects_tampering() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let verifying_key = signing_key.verifying_key();
+        let mut logger = SigningLogger::new(Box::new(InMemoryLogger::new()), signing_key);
+        let written = logger.log_event(Utc::now(), LOG_ACTION_ON).await.unwrap();
+
+        let line = logger.read_log().await.unwrap();
+        assert_eq!(line.trim(), written);
+        let tampered = line.trim().replace("turned ON", "turned OFF");
+        assert_eq!(verify_log_line(&verifying_key, line.trim()), SignatureCheck::Valid);
+        assert_eq!(verify_log_line(&verifying_key, &tampered), SignatureCheck::Invalid);
+        assert_eq!(verify_log_line(&verifying_key, "[2025-08-02T14:24:27+00:00] Lightbulb turned ON"), SignatureCheck::Unsigned);
+    }
+
+    #[tokio::test]
+    async fn test_file_logger_buffers_until_flushed() {
+        let path = std::env::temp_dir().join(format!("lightbulb-buffered-{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut logger = FileLogger::new(path.display().to_string());
+        logger.append_line("first").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        logger.flush().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\n");
+
+        // A flush closes the file, so entries after a rotation go to the new one
+        std::fs::rename(&path, path.with_extension("log.1")).unwrap();
+        for i in 0..LOG_FLUSH_ENTRIES {
+            logger.append_line(&format!("entry {}", i)).await.unwrap();
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), LOG_FLUSH_ENTRIES);
+        let _ = std::fs::remove_file(path.with_extension("log.1"));
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_writes_wait_for_the_log_lock() {
+        let path = std::env::temp_dir().join(format!("lightbulb-locked-{}.log", std::process::id()));
+        let rotated = path.with_extension("log.1");
+        let _ = std::fs::remove_file(&path);
+        let mut logger = FileLogger::new(path.display().to_string());
+        logger.append_line("first").await.unwrap();
+
+        // Another process rotating the log takes the lock, moves the log aside and lets go
+        let held = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        held.lock().unwrap();
+        let rotator = std::thread::spawn({
+            let (path, rotated) = (path.clone(), rotated.clone());
+            move || {
+                std::thread::sleep(Duration::from_millis(100));
+                std::fs::rename(&path, &rotated).unwrap();
+                drop(held);
+            }
+        });
+        logger.flush().await.unwrap();
+        rotator.join().unwrap();
+        assert_eq!((std::fs::read_to_string(&path).unwrap(), std::fs::read_to_string(&rotated).unwrap()), ("first\n".to_string(), String::new()));
+
+        // A lock never let go fails the write, which keeps the entries for the next one
+        let held = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        held.lock().unwrap();
+        logger.append_line("second").await.unwrap();
+        assert!(logger.flush().await.unwrap_err().to_string().contains("locked by another process"));
+        drop(held);
+        logger.flush().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        let _ = std::fs::remove_file(rotated);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_archiving_moves_the_log_aside() {
+        let path = std::env::temp_dir().join(format!("lightbulb-archive-{}.log", std::process::id()));
+        let mut logger = FileLogger::new(path.display().to_string());
+        logger.append_line("first").await.unwrap();
+        let archive = logger.archive_log("reset").await.unwrap();
+        assert_eq!(archive, format!("{}.reset", path.display()));
+        assert_eq!(std::fs::read_to_string(&archive).unwrap(), "first\n");
+        assert_eq!(logger.read_log().await.unwrap(), "");
+        assert!(logger.archive_log("reset").await.is_err());
+        assert!(InMemoryLogger::new().archive_log("reset").await.is_err());
+        assert!(logger.modified().await.is_some());
+        assert_eq!(InMemoryLogger::new().modified().await, None);
+        let _ = std::fs::remove_file(archive);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_end_on_whole_lines() {
+        let path = std::env::temp_dir().join(format!("lightbulb-chunks-{}.log", std::process::id()));
+        std::fs::write(&path, "first entry\nsecond entry\nthird\n").unwrap();
+        let logger = FileLogger::new(path.display().to_string());
+        let mut memory = InMemoryLogger::new();
+        for line in ["first entry", "second entry", "third"] {
+            memory.append_line(line).await.unwrap();
+        }
+
+        for logger in [&logger as &(dyn Logger + Send + Sync), &memory] {
+            let chunk = logger.read_chunk(0, 20).await.unwrap();
+            assert_eq!((chunk.text.as_str(), chunk.next(), chunk.total), ("first entry\n", Some(12), 31));
+            let chunk = logger.read_chunk(12, 20).await.unwrap();
+            assert_eq!((chunk.text.as_str(), chunk.next()), ("second entry\nthird\n", None));
+            // A line longer than the chunk is split rather than never returned
+            assert_eq!(logger.read_chunk(0, 5).await.unwrap().text, "first");
+            assert_eq!(logger.read_chunk(99, 20).await.unwrap().text, "");
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_tail_reads_only_the_end_of_a_long_log() {
+        let path = std::env::temp_dir().join(format!("lightbulb-tail-{}.log", std::process::id()));
+        let log: String = (0..5_000).map(|i| format!("entry {}\n", i)).collect();
+        std::fs::write(&path, format!("{}\n", log)).unwrap();
+        let logger = FileLogger::new(path.display().to_string());
+
+        let tail = logger.read_tail(3).await.unwrap();
+        assert_eq!(tail, ["entry 4997", "entry 4998", "entry 4999"]);
+        // Enough entries to need several blocks, and more than the log holds
+        assert_eq!(logger.read_tail(2_000).await.unwrap().first().map(String::as_str), Some("entry 3000"));
+        assert_eq!(logger.read_tail(9_000).await.unwrap().len(), 5_000);
+        assert_eq!(logger.read_tail(0).await.unwrap(), Vec::<String>::new());
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_range_reads_find_a_day_in_a_long_log() {
+        let path = std::env::temp_dir().join(format!("lightbulb-range-{}.log", std::process::id()));
+        let start = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap().with_timezone(&Utc);
+        // Twelve entries a day for a year, far more than one search step
+        let log: String = (0..365 * 12).map(|i| format!("{}\n", format_log_line(start + chrono::TimeDelta::hours(2 * i), "ON"))).collect();
+        std::fs::write(&path, &log).unwrap();
+        let logger = FileLogger::new(path.display().to_string());
+        let mut memory = InMemoryLogger::new();
+        memory.replace_log(&log).await.unwrap();
+
+        let from = DateTime::parse_from_rfc3339("2025-07-04T00:00:00Z").unwrap().with_timezone(&Utc);
+        let day = logger.read_range(from, from + chrono::TimeDelta::days(1)).await.unwrap();
+        assert_eq!(day.len(), 12);
+        assert!(day[0].starts_with("[2025-07-04T00:00:00+00:00]") && day[11].starts_with("[2025-07-04T22:00:00+00:00]"), "{:?}", day);
+        assert_eq!(memory.read_range(from, from + chrono::TimeDelta::days(1)).await.unwrap(), day);
+        // The first day, and days before and after the log
+        assert_eq!(logger.read_range(start, start + chrono::TimeDelta::days(1)).await.unwrap().len(), 12);
+        assert!(logger.read_range(start - chrono::TimeDelta::days(1), start).await.unwrap().is_empty());
+        let after = start + chrono::TimeDelta::days(400);
+        assert!(logger.read_range(after, after + chrono::TimeDelta::days(1)).await.unwrap().is_empty());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -1,388 +1,410 @@
-use std::borrow::Cow;
-use std::collections::VecDeque;
-use std::sync::Arc;
+use lightbulb_mcp::LightService;
+use lightbulb_mcp::config::{API_KEY_ENV, Config, SessionMode};
+use lightbulb_mcp::diagnostic;
+use lightbulb_mcp::i18n;
+use lightbulb_mcp::instance::InstanceLock;
+use lightbulb_mcp::registry::BackendRegistry;
+use lightbulb_mcp::shutdown;
+use lightbulb_mcp::stdio::LineTransport;
+use lightbulb_mcp::transport::{TRANSPORT_FLAG, Transport};
+use lightbulb_mcp::verbosity::{self, Verbosity};
+use rmcp::serve_server;
+
+const TUI_FLAG: &str = "--tui";
+const HEADLESS_FLAG: &str = "--headless";
+const GUEST_FLAG: &str = "--guest";
+const DRY_RUN_FLAG: &str = "--dry-run";
+const RECORD_FLAG: &str = "--record";
+const REPLAY_FLAG: &str = "--replay";
+const SOAK_FLAG: &str = "--soak";
+const DAEMON_FLAG: &str = "--daemon";
+const STOP_FLAG: &str = "--stop";
+const STATUS_FLAG: &str = "--status";
+#[cfg(all(feature = "daemon", unix))]
+const PID_FILE_FLAG: &str = "--pid-file";
+#[cfg(all(feature = "daemon", unix))]
+const DIAGNOSTICS_FILE_FLAG: &str = "--diagnostics-file";
+#[cfg(feature = "soak")]
+const SOAK_SEED_FLAG: &str = "--soak-seed";
 
-use anyhow::{Context, Result};
-
-use chrono::Utc;
-use rmcp::handler::server::tool::ToolRouter;
-use rmcp::model::*;
-use rmcp::{ServerHandler, serve_server, tool, tool_handler, tool_router};
-use rmcp::service::RequestContext;
-use tokio::sync::Mutex;
-use tokio::fs::{OpenOptions, read_to_string};
-use tokio::io::AsyncWriteExt;
-
-// Constants to avoid string duplication
-const LIGHTBULB_ON_STATUS: &str = "The lightbulb is on";
-const LIGHTBULB_OFF_STATUS: &str = "The lightbulb is off";
-const LIGHTBULB_ALREADY_ON: &str = "The lightbulb is already on";
-const LIGHTBULB_ALREADY_OFF: &str = "The lightbulb is already off";
-const LIGHTBULB_TURNED_ON: &str = "Lightbulb turned on successfully";
-const LIGHTBULB_TURNED_OFF: &str = "Lightbulb turned off successfully";
-const LOG_FILE_NAME: &str = "lightbulb.log";
-const LOG_ACTION_ON: &str = "ON";
-const LOG_ACTION_OFF: &str = "OFF";
-
-// Trait for logging abstraction
-#[async_trait::async_trait]
-trait Logger {
-    async fn log_event(&mut self, action: &str) -> anyhow::Result<()>;
-    async fn read_log(&self) -> anyhow::Result<String>;
-}
-
-// File-based logger for production
-struct FileLogger {
-    file_path: String,
-}
-
-impl FileLogger {
-    fn new(file_path: String) -> Self {
-        Self { file_path }
-    }
-}
-
-#[async_trait::async_trait]
-impl Logger for FileLogger {
-    async fn log_event(&mut self, action: &str) -> anyhow::Result<()> {
-        let timestamp = Utc::now();
-        let log_entry = format!("[{}] Lightbulb turned {}\n", timestamp.to_rfc3339(), action);
-        
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.file_path)
-            .await
-            .with_context(|| format!("Failed to open log file: {}", self.file_path))?;
-        
-        file.write_all(log_entry.as_bytes()).await
-            .with_context(|| "Failed to write to log file")?;
-        Ok(())
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    verbosity::set(Verbosity::from_args(std::env::args().skip(1)));
+    if has_flag(STOP_FLAG) || has_flag(STATUS_FLAG) {
+        return control_daemon(has_flag(STOP_FLAG));
     }
-    
-    async fn read_log(&self) -> anyhow::Result<String> {
-        read_to_string(&self.file_path).await
-            .with_context(|| format!("Failed to read log file: {}", self.file_path))
+    let tui = has_flag(TUI_FLAG);
+    let headless = has_flag(HEADLESS_FLAG) || has_flag(DAEMON_FLAG);
+    let dry_run = has_flag(DRY_RUN_FLAG);
+    let transport = Transport::from_args(std::env::args().skip(1))?;
+    let mut config = Config::load()?;
+    i18n::set_locale(&config.locale)?;
+    i18n::set_templates(&config.messages)?;
+    let guest = has_flag(GUEST_FLAG) || std::env::var(API_KEY_ENV).is_ok_and(|key| config.is_guest_key(&key));
+    // A guest key listed as an admin one too stays a guest's
+    let admin = !guest && std::env::var(API_KEY_ENV).is_ok_and(|key| config.is_admin_key(&key));
+    if let Some(recording) = flag_value(REPLAY_FLAG) {
+        return replay(&recording, guest).await;
     }
-}
-
-// In-memory logger for testing
-#[derive(Debug, Clone)]
-struct InMemoryLogger {
-    entries: VecDeque<String>,
-}
-
-impl InMemoryLogger {
-    fn new() -> Self {
-        Self {
-            entries: VecDeque::new(),
-        }
+    if let Some(seconds) = flag_value(SOAK_FLAG) {
+        return soak(&config, &seconds).await;
     }
-}
-
-#[async_trait::async_trait]
-impl Logger for InMemoryLogger {
-    async fn log_event(&mut self, action: &str) -> anyhow::Result<()> {
-        let timestamp = Utc::now();
-        let log_entry = format!("[{}] Lightbulb turned {}", timestamp.to_rfc3339(), action);
-        self.entries.push_back(log_entry);
-        Ok(())
+    let record = flag_value(RECORD_FLAG);
+    if transport != Transport::Stdio && (tui || headless || record.is_some() || !config.downstream.is_empty()) {
+        anyhow::bail!(
+            "{} {} serves the local bulb's MCP sessions in place of stdio, so it cannot be combined with {}, {}, {} or [[downstream]] servers",
+            TRANSPORT_FLAG,
+            transport.name(),
+            TUI_FLAG,
+            HEADLESS_FLAG,
+            RECORD_FLAG
+        );
     }
-    
-    async fn read_log(&self) -> anyhow::Result<String> {
-        Ok(self.entries.iter().map(|entry| format!("{}\n", entry)).collect())
+    if dry_run && (tui || headless || !config.downstream.is_empty()) {
+        anyhow::bail!("{} only changes what the stdio session's tools do, so it cannot be combined with {}, {} or [[downstream]] servers", DRY_RUN_FLAG, TUI_FLAG, HEADLESS_FLAG);
     }
-}
-
-struct LightService {
-    tool_router: ToolRouter<Self>,
-    light_state: Arc<Mutex<bool>>,
-    logger: Arc<Mutex<Box<dyn Logger + Send>>>,
-}
-
-#[tool_router]
-impl LightService {
-    #[tool(description = "Get the current status of the lightbulb")]
-    async fn get_lightbulb_status(&self) -> String {
-        let state = self.light_state.lock().await;
-        if *state {
-            LIGHTBULB_ON_STATUS.to_owned()
-        } else {
-            LIGHTBULB_OFF_STATUS.to_owned()
+    // Nothing may write the log, not even the startup jobs that tidy it
+    if dry_run {
+        config.journal = false;
+        config.log_compaction = None;
+        // Nor may sensor readings change the bulb
+        if let Some(adaptive) = &mut config.adaptive_brightness {
+            adaptive.topic = None;
         }
+        for room in &mut config.motion {
+            room.topic = None;
+        }
+        // Nor schedules, nor an import or timers saved by an earlier run
+        config.schedules.clear();
+        config.automation_file = false;
     }
-
-    #[tool(description = "Turn on the lightbulb")]
-    async fn turn_on_lightbulb(&self) -> Result<String, String> {
-        self.change_lightbulb_state(true, LIGHTBULB_ALREADY_ON, LIGHTBULB_TURNED_ON, LOG_ACTION_ON).await
+    if !config.downstream.is_empty() {
+        return serve_aggregator(&config, tui || headless, record, admin).await;
     }
-
-    #[tool(description = "Turn off the lightbulb")]
-    async fn turn_off_lightbulb(&self) -> Result<String, String> {
-        self.change_lightbulb_state(false, LIGHTBULB_ALREADY_OFF, LIGHTBULB_TURNED_OFF, LOG_ACTION_OFF).await
+    #[cfg(not(feature = "rest"))]
+    if headless {
+        anyhow::bail!("{} requires a build with the `rest` feature", HEADLESS_FLAG);
     }
-
-    async fn change_lightbulb_state(
-        &self,
-        target_state: bool,
-        already_message: &str,
-        success_message: &str,
-        log_action: &str,
-    ) -> Result<String, String> {
-        let mut state = self.light_state.lock().await;
-        if *state == target_state {
-            Ok(already_message.to_owned())
-        } else {
-            *state = target_state;
-            self.log_light_event(log_action).await.map_err(|e| format!("Failed to log event: {}", e))?;
-            Ok(success_message.to_owned())
-        }
+    if headless && config.rest.is_none() {
+        anyhow::bail!("{} needs a [rest] section in the config, since it serves no stdio session", HEADLESS_FLAG);
     }
-
-    async fn log_light_event(&self, action: &str) -> anyhow::Result<()> {
-        let mut logger = self.logger.lock().await;
-        logger.log_event(action).await
+    if has_flag(DAEMON_FLAG) {
+        return start_daemon();
     }
-
-    async fn read_log_content(&self) -> anyhow::Result<String> {
-        let logger = self.logger.lock().await;
-        logger.read_log().await
+    let _instance = lock_log(&config)?;
+    #[cfg(all(feature = "daemon", unix))]
+    let _pid_file = lightbulb_mcp::daemon::PidFile::from_env()?;
+    let transport_name = if tui || headless { "rest" } else { transport.name() };
+    let sessions = LightService::session_factory(&config, &BackendRegistry::with_builtin(), transport_name)?;
+    let server = if dry_run { sessions.create().dry_run() } else { sessions.create() };
+    // Held past the end of the session, to flush the log before exiting
+    let light = server.light().clone();
+
+    // The REST API shares the MCP server's light, so both see the same state
+    #[cfg(feature = "rest")]
+    let clients = lightbulb_mcp::rest::ClientRegistry::default();
+    #[cfg(feature = "rest")]
+    if dry_run && config.rest.is_some() {
+        diagnostic!(Warn, "not serving the REST API, which has no dry run");
+    }
+    #[cfg(feature = "rest")]
+    if let Some(rest) = config.rest.as_ref().filter(|_| !dry_run) {
+        let listener = tokio::net::TcpListener::bind(&rest.bind).await?;
+        diagnostic!(Info, "REST API listening on {}", listener.local_addr()?);
+        tokio::spawn(lightbulb_mcp::rest::serve(listener, server.light().clone(), clients.clone()));
+        service_ready(&light);
     }
 
-    async fn generate_usage_summary(&self) -> String {
-        match self.read_log_content().await {
-            Ok(log_content) => {
-                let lines: Vec<&str> = log_content.lines().filter(|line| !line.trim().is_empty()).collect();
-                
-                if lines.is_empty() {
-                    return "Lightbulb Usage Summary:\n\nNo activity recorded yet.".to_string();
-                }
-                
-                let total_actions = lines.len();
-                let on_actions = lines.iter().filter(|line| line.contains("turned ON")).count();
-                let off_actions = lines.iter().filter(|line| line.contains("turned OFF")).count();
-                
-                let current_state = self.light_state.lock().await;
-                let current_status = if *current_state { "ON" } else { "OFF" };
-                
-                // Get first and last action timestamps
-                let first_action = lines.first().map(|line| {
-                    line.split(']').next().unwrap_or("").trim_start_matches('[').to_string()
-                });
-                let last_action = lines.last().map(|line| {
-                    line.split(']').next().unwrap_or("").trim_start_matches('[').to_string()
-                });
-                
-                format!(
-                    "Lightbulb Usage Summary:\n\n\
-                    Current Status: {}\n\
-                    Total Actions: {}\n\
-                    - Turn ON actions: {} ({:.1}%)\n\
-                    - Turn OFF actions: {} ({:.1}%)\n\n\
-                    Activity Period:\n\
-                    - First action: {}\n\
-                    - Last action: {}\n\n\
-                    Recent Activity (last 5 actions):\n{}",
-                    current_status,
-                    total_actions,
-                    on_actions,
-                    if total_actions > 0 { (on_actions as f64 / total_actions as f64) * 100.0 } else { 0.0 },
-                    off_actions,
-                    if total_actions > 0 { (off_actions as f64 / total_actions as f64) * 100.0 } else { 0.0 },
-                    first_action.unwrap_or("N/A".to_string()),
-                    last_action.unwrap_or("N/A".to_string()),
-                    lines.iter().rev().take(5).rev().map(|line| format!("  {}", line)).collect::<Vec<_>>().join("\n")
-                )
-            },
-            Err(_) => "Lightbulb Usage Summary:\n\nLog file not found. No activity recorded yet.".to_string(),
-        }
+    // Without stdio the REST API is the only way in, served until the process is told to stop
+    if headless {
+        let signal = shutdown::signal().await?;
+        return shut_down(&server, signal).await;
     }
 
-    fn new_with_logger(logger: Box<dyn Logger + Send>) -> Self {
-        Self {
-            tool_router: Self::tool_router(),
-            light_state: Arc::new(Mutex::new(false)),
-            logger: Arc::new(Mutex::new(logger)),
+    // The terminal replaces stdio, so the monitor needs a network transport to watch
+    if tui {
+        #[cfg(feature = "tui")]
+        {
+            let Some(rest) = &config.rest else {
+                anyhow::bail!("{} needs a [rest] section in the config, since the terminal takes over stdio", TUI_FLAG);
+            };
+            let result = lightbulb_mcp::tui::run(light.clone(), clients, rest.bind.clone()).await;
+            light.flush_log().await?;
+            return result;
         }
+        #[cfg(not(feature = "tui"))]
+        anyhow::bail!("{} requires a build with the `tui` feature", TUI_FLAG);
     }
 
-    fn new() -> Self {
-        // For production, use file logger
-        let logger = FileLogger::new(LOG_FILE_NAME.to_string());
-        Self::new_with_logger(Box::new(logger) as Box<dyn Logger + Send>)
+    if let Transport::Http { listen } = transport {
+        // Over HTTP a key's access goes with the bearer token of each request, so only --guest applies to every session
+        return serve_http(sessions, &server, listen, &config, has_flag(GUEST_FLAG), dry_run).await;
     }
 
-    #[cfg(test)]
-    fn new_with_in_memory_logger() -> Self {
-        let logger = InMemoryLogger::new();
-        Self::new_with_logger(Box::new(logger))
+    // Only the MCP session is restricted; the REST API and monitor keep the full service
+    let session = match (guest, admin) {
+        (true, _) => server.guest(),
+        (false, true) => server.admin(),
+        (false, false) => server.clone(),
+    };
+    let clock = server.light().clock().clone();
+    tokio::select! {
+        result = serve_stdio(session, record, clock) => {
+            light.flush_log().await?;
+            result
+        },
+        signal = shutdown::signal() => {
+            shut_down(&server, signal?).await?;
+            // The runtime would otherwise wait on its blocked read of stdin, for input that may never come
+            std::process::exit(0)
+        },
     }
 }
 
-#[tool_handler]
-impl ServerHandler for LightService {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            instructions: Some("Service for managing lights".into()),
-            capabilities: ServerCapabilities::builder()
-                .enable_tools()
-                .enable_resources()
-                .enable_logging()
-                .build(),
-            ..Default::default()
-        }
-    }
-
-    async fn list_resources(
-        	&self,
-        _request: Option<PaginatedRequestParam>,
-        _context: RequestContext<rmcp::RoleServer>,
-    ) -> Result<ListResourcesResult, ErrorData> {
-        let resources = vec![
-            Resource {
-                raw: RawResource {
-                    uri: "lightbulb://log".to_string(),
-                    name: "Lightbulb Activity Log".to_string(),
-                    description: Some("Complete history of lightbulb on/off actions with timestamps".to_string()),
-                    mime_type: Some("text/plain".to_string()),
-                    size: None,
-                },
-                annotations: None,
-            },
-            Resource {
-                raw: RawResource {
-                    uri: "lightbulb://summary".to_string(),
-                    name: "Lightbulb Usage Summary".to_string(),
-                    description: Some("Summary statistics of lightbulb usage patterns".to_string()),
-                    mime_type: Some("text/plain".to_string()),
-                    size: None,
-                },
-                annotations: None,
-            },
-        ];
-        
-        Ok(ListResourcesResult {
-            resources,
-            next_cursor: None,
-        })
+// Only a shared bulb writes the configured log; sandbox sessions keep theirs in memory
+fn lock_log(config: &Config) -> anyhow::Result<Option<InstanceLock>> {
+    match config.sessions {
+        SessionMode::Shared => InstanceLock::acquire(&config.log_file).map(Some),
+        SessionMode::Sandbox => Ok(None),
     }
+}
 
-    async fn read_resource(
-        &self,
-        request: ReadResourceRequestParam,
-        _context: RequestContext<rmcp::RoleServer>,
-    ) -> Result<ReadResourceResult, ErrorData> {
-        match request.uri.as_str() {
-            "lightbulb://log" => {
-                let content = match self.read_log_content().await {
-                    Ok(log_content) => {
-                        if log_content.trim().is_empty() {
-                            "No lightbulb activity recorded yet.".to_string()
-                        } else {
-                            format!("Lightbulb Activity Log:\n\n{}", log_content)
-                        }
-                    },
-                    Err(_) => "Lightbulb log file not found. No activity recorded yet.".to_string(),
-                };
-                
-                Ok(ReadResourceResult {
-                    contents: vec![ResourceContents::text(content, &request.uri)],
-                })
-            },
-            "lightbulb://summary" => {
-                let summary = self.generate_usage_summary().await;
-                
-                Ok(ReadResourceResult {
-                    contents: vec![ResourceContents::text(summary, &request.uri)],
-                })
+// Tells systemd the REST API is up, and keeps its watchdog fed while the light answers, when it runs the
+// server as a Type=notify service
+#[cfg(feature = "rest")]
+fn service_ready(light: &lightbulb_mcp::actor::LightHandle) {
+    #[cfg(all(feature = "systemd", unix))]
+    {
+        use lightbulb_mcp::systemd;
+        match systemd::notify("READY=1") {
+            Ok(true) => {
+                if let Some(interval) = systemd::watchdog_interval() {
+                    systemd::spawn_watchdog(light.clone(), interval);
+                }
             },
-            _ => Err(ErrorData {
-                code: ErrorCode(-32602),
-                message: Cow::Borrowed("Unknown resource URI"),
-                data: None,
-            }),
+            Ok(false) => {},
+            Err(e) => diagnostic!(Warn, "could not notify systemd: {}", e),
         }
     }
+    #[cfg(all(feature = "daemon", unix))]
+    lightbulb_mcp::daemon::report_ready();
+    #[cfg(not(all(feature = "systemd", unix)))]
+    let _ = light;
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let logger = FileLogger::new(LOG_FILE_NAME.to_string());
-    let server = LightService::new_with_logger(Box::new(logger));
+fn service_stopping() {
+    #[cfg(all(feature = "systemd", unix))]
+    let _ = lightbulb_mcp::systemd::notify("STOPPING=1");
+}
 
-    let transport = (tokio::io::stdin(), tokio::io::stdout());
-    serve_server(server, transport).await?.waiting().await?;
+// The way out on a signal: a running effect restores the bulb, and the log gets its SHUTDOWN entry and is flushed
+async fn shut_down(server: &LightService, signal: &str) -> anyhow::Result<()> {
+    diagnostic!(Info, "shutting down on {}", signal);
+    shutdown::exit_on_second_signal();
+    service_stopping();
+    server.shutdown(signal).await?;
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[tokio::test]
-    async fn test_initial_lightbulb_state() {
-        let service = LightService::new_with_in_memory_logger();
-        let status = service.get_lightbulb_status().await;
-        assert_eq!(status, "The lightbulb is off");
+// Detaches a headless server from the terminal; the config has been checked, so the usual mistakes are reported
+// here rather than only in the diagnostics file
+fn start_daemon() -> anyhow::Result<()> {
+    #[cfg(all(feature = "daemon", unix))]
+    {
+        use lightbulb_mcp::daemon::{DEFAULT_DIAGNOSTICS_FILE, DEFAULT_PID_FILE, start};
+
+        let pid_file = flag_value(PID_FILE_FLAG).unwrap_or_else(|| DEFAULT_PID_FILE.to_string());
+        let diagnostics_file = flag_value(DIAGNOSTICS_FILE_FLAG).unwrap_or_else(|| DEFAULT_DIAGNOSTICS_FILE.to_string());
+        let mut args: Vec<std::ffi::OsString> = std::env::args_os().skip(1).filter(|arg| arg != DAEMON_FLAG).collect();
+        if !has_flag(HEADLESS_FLAG) {
+            args.push(HEADLESS_FLAG.into());
+        }
+        let pid = start(args, &pid_file, &diagnostics_file)?;
+        println!("lightbulb-mcp started (pid {}), diagnostics in {}", pid, diagnostics_file);
+        Ok(())
     }
+    #[cfg(not(all(feature = "daemon", unix)))]
+    anyhow::bail!("{} requires a Unix build with the `daemon` feature", DAEMON_FLAG)
+}
 
-    #[tokio::test]
-    async fn test_turn_on_lightbulb() {
-        let service = LightService::new_with_in_memory_logger();
-        let result = service.turn_on_lightbulb().await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "Lightbulb turned on successfully");
-
-        let status = service.get_lightbulb_status().await;
-        assert_eq!(status, "The lightbulb is on");
+// `--stop` or `--status` for a server started with `--daemon`
+fn control_daemon(stop: bool) -> anyhow::Result<()> {
+    #[cfg(all(feature = "daemon", unix))]
+    {
+        use lightbulb_mcp::daemon::{DEFAULT_PID_FILE, running};
+
+        let pid_file = flag_value(PID_FILE_FLAG).unwrap_or_else(|| DEFAULT_PID_FILE.to_string());
+        if stop {
+            let pid = lightbulb_mcp::daemon::stop(&pid_file)?;
+            println!("lightbulb-mcp stopped (pid {})", pid);
+            return Ok(());
+        }
+        match running(&pid_file)? {
+            Some(pid) => println!("lightbulb-mcp is running (pid {})", pid),
+            None => anyhow::bail!("lightbulb-mcp is not running (no live process in {})", pid_file),
+        }
+        Ok(())
     }
+    #[cfg(not(all(feature = "daemon", unix)))]
+    {
+        let _ = stop;
+        anyhow::bail!("{} and {} require a Unix build with the `daemon` feature", STOP_FLAG, STATUS_FLAG)
+    }
+}
 
-    #[tokio::test]
-    async fn test_turn_off_lightbulb() {
-        let service = LightService::new_with_in_memory_logger();
-        // First turn it on
-        let _ = service.turn_on_lightbulb().await;
+fn has_flag(flag: &str) -> bool {
+    std::env::args().skip(1).any(|arg| arg == flag)
+}
 
-        let result = service.turn_off_lightbulb().await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "Lightbulb turned off successfully");
+// The value following `flag` on the command line, e.g. the path in `--record session.jsonl`
+fn flag_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    args.find(|arg| arg == flag)?;
+    args.next()
+}
 
-        let status = service.get_lightbulb_status().await;
-        assert_eq!(status, "The lightbulb is off");
+// Serves over stdio, writing every message to the `record` file when one is given
+async fn serve_stdio<S>(server: S, record: Option<String>, clock: lightbulb_mcp::clock::SharedClock) -> anyhow::Result<()>
+where
+    S: rmcp::ServerHandler,
+{
+    let transport = LineTransport::new(tokio::io::stdin(), tokio::io::stdout());
+    diagnostic!(Info, "serving MCP over stdio");
+    let Some(path) = record else {
+        let reason = serve_server(server, transport).await?.waiting().await?;
+        diagnostic!(Info, "stdio session ended ({:?})", reason);
+        return Ok(());
+    };
+    #[cfg(feature = "recording")]
+    {
+        let transport = lightbulb_mcp::recording::RecordingTransport::new(transport, path, clock)?;
+        let reason = serve_server(server, transport).await?.waiting().await?;
+        diagnostic!(Info, "stdio session ended ({:?})", reason);
+        Ok(())
     }
+    #[cfg(not(feature = "recording"))]
+    {
+        let _ = (path, clock);
+        anyhow::bail!("{} requires a build with the `recording` feature", RECORD_FLAG)
+    }
+}
 
-    #[tokio::test]
-    async fn test_turn_on_already_on() {
-        let service = LightService::new_with_in_memory_logger();
-        let _ = service.turn_on_lightbulb().await;
+// Serves every MCP client that connects over HTTP a session of its own, until the process is told to stop
+async fn serve_http(
+    sessions: lightbulb_mcp::SessionFactory,
+    server: &LightService,
+    listen: std::net::SocketAddr,
+    config: &Config,
+    guest: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    #[cfg(feature = "http")]
+    {
+        use lightbulb_mcp::auth::JwtValidator;
+        use lightbulb_mcp::transport::{BEARER_TOKEN_ENV, HttpAuth, MCP_PATH, serve_http};
+
+        let token = std::env::var(BEARER_TOKEN_ENV).ok().filter(|token| !token.is_empty());
+        let jwt = match config.jwt.as_ref() {
+            Some(jwt) => Some(std::sync::Arc::new(JwtValidator::load(jwt).await?)),
+            None => None,
+        };
+        let listener = tokio::net::TcpListener::bind(listen).await?;
+        let address = listener.local_addr()?;
+        let auth = HttpAuth { token, guest_keys: config.guest_keys.clone(), admin_keys: config.admin_keys.clone(), jwt };
+        if auth.is_open() && !address.ip().is_loopback() {
+            diagnostic!(Warn, "serving MCP on {} without {}, API keys or [jwt], so anyone who can reach it can switch the bulb", address, BEARER_TOKEN_ENV);
+        }
+        diagnostic!(Info, "serving MCP over HTTP at http://{}{}", address, MCP_PATH);
+        let session = move || {
+            let session = if dry_run { sessions.create().dry_run() } else { sessions.create() };
+            if guest { session.guest() } else { session }
+        };
+        tokio::select! {
+            result = serve_http(listener, session, auth) => {
+                server.light().flush_log().await?;
+                Ok(result?)
+            },
+            signal = shutdown::signal() => shut_down(server, signal?).await,
+        }
+    }
+    #[cfg(not(feature = "http"))]
+    {
+        let _ = (sessions, server, listen, config, guest, dry_run);
+        anyhow::bail!("{} http requires a build with the `http` feature", TRANSPORT_FLAG)
+    }
+}
 
-        let result = service.turn_on_lightbulb().await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "The lightbulb is already on");
+// Plays a recorded session against a fresh simulated bulb and reports every response that came out differently
+async fn replay(recording: &str, guest: bool) -> anyhow::Result<()> {
+    #[cfg(feature = "recording")]
+    {
+        use lightbulb_mcp::clock::ManualClock;
+        use lightbulb_mcp::logger::InMemoryLogger;
+        use lightbulb_mcp::recording::{read_recording, replay};
+
+        let recording = read_recording(recording)?;
+        let clock = ManualClock::new(recording.first().map(|entry| entry.at).unwrap_or_else(chrono::Utc::now));
+        let service =
+            LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(std::sync::Arc::new(clock.clone())).build();
+        let service = if guest { service.guest() } else { service };
+        let report = replay(&recording, service, &clock).await?;
+        println!("{}", report.describe());
+        if !report.differences.is_empty() {
+            anyhow::bail!("{} of {} responses differed from the recording", report.differences.len(), report.responses);
+        }
+        Ok(())
     }
+    #[cfg(not(feature = "recording"))]
+    {
+        let _ = (recording, guest);
+        anyhow::bail!("{} requires a build with the `recording` feature", REPLAY_FLAG)
+    }
+}
 
-    #[tokio::test]
-    async fn test_turn_off_already_off() {
-        let service = LightService::new_with_in_memory_logger();
+// Hammers the configured bulb from several in-process sessions and fails if any invariant broke
+async fn soak(config: &Config, seconds: &str) -> anyhow::Result<()> {
+    #[cfg(feature = "soak")]
+    {
+        use lightbulb_mcp::soak::{SoakConfig, soak};
 
-        let result = service.turn_off_lightbulb().await;
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), "The lightbulb is already off");
+        if !config.downstream.is_empty() {
+            anyhow::bail!("{} drives a local bulb, which an aggregator does not have", SOAK_FLAG);
+        }
+        let seconds: u64 = seconds.parse().map_err(|_| anyhow::anyhow!("{} takes a duration in seconds, not {:?}", SOAK_FLAG, seconds))?;
+        let mut soak_config = SoakConfig::new(std::time::Duration::from_secs(seconds));
+        if let Some(seed) = flag_value(SOAK_SEED_FLAG) {
+            soak_config.seed = seed.parse().map_err(|_| anyhow::anyhow!("{} takes a number, not {:?}", SOAK_SEED_FLAG, seed))?;
+        }
+        let _instance = lock_log(config)?;
+        let builder = LightService::builder_from_config(config, &BackendRegistry::with_builtin())?.transport("soak");
+        let report = soak(builder, &soak_config).await?;
+        println!("{}", report.describe(&soak_config));
+        if !report.passed() {
+            anyhow::bail!("Soak found {} invariant violations", report.violation_count);
+        }
+        Ok(())
+    }
+    #[cfg(not(feature = "soak"))]
+    {
+        let _ = (config, seconds);
+        anyhow::bail!("{} requires a build with the `soak` feature", SOAK_FLAG)
     }
+}
 
-    #[tokio::test]
-    async fn test_logging_behavior() {
-        let service = LightService::new_with_in_memory_logger();
-        
-        // Turn on the lightbulb
-        let _ = service.turn_on_lightbulb().await;
-        
-        // Turn off the lightbulb
-        let _ = service.turn_off_lightbulb().await;
-        
-        // Check that the log contains both actions
-        let log_content = service.read_log_content().await.expect("Failed to read log content");
-        assert!(log_content.contains("turned ON"));
-        assert!(log_content.contains("turned OFF"));
+// Fronts the configured downstream servers instead of a bulb of our own
+async fn serve_aggregator(config: &Config, rest_only: bool, record: Option<String>, admin: bool) -> anyhow::Result<()> {
+    if rest_only {
+        anyhow::bail!("{} and {} serve a local bulb's REST API, which an aggregator does not have", TUI_FLAG, HEADLESS_FLAG);
+    }
+    #[cfg(feature = "aggregator")]
+    {
+        let server = lightbulb_mcp::aggregator::AggregatorService::from_config(config).await?;
+        let server = if admin { server.admin() } else { server };
+        serve_stdio(server, record, lightbulb_mcp::clock::system_clock()).await
+    }
+    #[cfg(not(feature = "aggregator"))]
+    {
+        let _ = (record, admin);
+        anyhow::bail!("[[downstream]] servers in {} require a build with the `aggregator` feature", config.source.as_deref().unwrap_or("the config"));
     }
 }
@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::backend::LightBackend;
+use crate::color::Gamut;
+use crate::model::{Color, PowerState};
+
+// What the next backend call does: wait, then succeed or fail
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockStep {
+    delay: Duration,
+    error: Option<String>,
+    external_power: Option<PowerState>,
+}
+
+impl MockStep {
+    pub fn ok() -> Self {
+        Self { delay: Duration::ZERO, error: None, external_power: None }
+    }
+
+    pub fn fail(error: impl Into<String>) -> Self {
+        Self { error: Some(error.into()), ..Self::ok() }
+    }
+
+    // Waits on tokio's clock, so paused-time tests do not actually sleep
+    pub fn after(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // Someone flips the bulb outside the server (a wall switch, another app) just before this call
+    pub fn external_change(mut self, power: PowerState) -> Self {
+        self.external_power = Some(power);
+        self
+    }
+}
+
+// A backend call the mock received, with the error it returned, if any
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockCall {
+    SetPower(PowerState, Option<String>),
+    Power(Option<String>),
+    SetColor(Color, Option<String>),
+}
+
+#[derive(Debug)]
+struct MockState {
+    power: PowerState,
+    color: Option<Color>,
+    steps: VecDeque<MockStep>,
+    // Used once the scripted steps run out
+    otherwise: MockStep,
+    calls: Vec<MockCall>,
+}
+
+// Shared handle for scripting a MockBackend and inspecting its calls after it is handed to a service
+#[derive(Debug, Clone)]
+pub struct MockScript {
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockScript {
+    // Queues steps for the next calls, in order
+    pub fn then(&self, step: MockStep) -> &Self {
+        self.lock().steps.push_back(step);
+        self
+    }
+
+    pub fn fail_next(&self, calls: usize, error: &str) -> &Self {
+        for _ in 0..calls {
+            self.then(MockStep::fail(error));
+        }
+        self
+    }
+
+    // What every call does once the queued steps are used up
+    pub fn otherwise(&self, step: MockStep) -> &Self {
+        self.lock().otherwise = step;
+        self
+    }
+
+    // Changes the bulb's real power now, without the server knowing
+    pub fn change_externally(&self, power: PowerState) {
+        self.lock().power = power;
+    }
+
+    pub fn power(&self) -> PowerState {
+        self.lock().power
+    }
+
+    pub fn color(&self) -> Option<Color> {
+        self.lock().color
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.lock().calls.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Takes the step for the next call, applying any external change it carries
+    fn next_step(&self) -> MockStep {
+        let mut state = self.lock();
+        let step = state.steps.pop_front().unwrap_or_else(|| state.otherwise.clone());
+        if let Some(power) = step.external_power {
+            state.power = power;
+        }
+        step
+    }
+
+    // Plays the next step for a call, recording it; `apply` runs only if the step succeeds
+    async fn run<T>(&self, call: impl FnOnce(Option<String>) -> MockCall, apply: impl FnOnce(&mut MockState) -> T) -> anyhow::Result<T> {
+        let step = self.next_step();
+        if !step.delay.is_zero() {
+            tokio::time::sleep(step.delay).await;
+        }
+        let mut state = self.lock();
+        state.calls.push(call(step.error.clone()));
+        match step.error {
+            Some(error) => Err(anyhow::anyhow!(error)),
+            None => Ok(apply(&mut state)),
+        }
+    }
+}
+
+// A backend whose delays, errors and outside changes are scripted per test
+pub struct MockBackend {
+    script: MockScript,
+    supports_color: bool,
+    gamut: Option<Gamut>,
+}
+
+impl MockBackend {
+    // A bulb that is off and answers every call immediately until scripted otherwise
+    pub fn new() -> Self {
+        let state = MockState { power: PowerState::Off, color: None, steps: VecDeque::new(), otherwise: MockStep::ok(), calls: Vec::new() };
+        Self { script: MockScript { state: Arc::new(Mutex::new(state)) }, supports_color: false, gamut: None }
+    }
+
+    pub fn with_color(mut self) -> Self {
+        self.supports_color = true;
+        self
+    }
+
+    // A color bulb that can only show `gamut`
+    pub fn with_gamut(mut self, gamut: Gamut) -> Self {
+        self.supports_color = true;
+        self.gamut = Some(gamut);
+        self
+    }
+
+    pub fn script(&self) -> MockScript {
+        self.script.clone()
+    }
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait::async_trait]
+impl LightBackend for MockBackend {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    async fn set_power(&mut self, power: PowerState) -> anyhow::Result<()> {
+        self.script.run(|error| MockCall::SetPower(power, error), |state| state.power = power).await
+    }
+
+    async fn power(&self) -> anyhow::Result<PowerState> {
+        self.script.run(MockCall::Power, |state| state.power).await
+    }
+
+    fn supports_color(&self) -> bool {
+        self.supports_color
+    }
+
+    async fn set_color(&mut self, color: Color) -> anyhow::Result<()> {
+        self.script.run(|error| MockCall::SetColor(color, error), |state| state.color = Some(color)).await
+    }
+
+    fn gamut(&self) -> Option<Gamut> {
+        self.gamut
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::LightHandle;
+    use crate::diagnostics::{CheckStatus, build_report};
+    use crate::error::LightError;
+    use crate::logger::InMemoryLogger;
+    use crate::state::StateMachine;
+
+    fn light(backend: MockBackend) -> LightHandle {
+        LightHandle::spawn(StateMachine::new(), Box::new(backend), Box::new(InMemoryLogger::new()))
+    }
+
+    #[tokio::test]
+    async fn test_scripted_failure_then_recovery() {
+        let backend = MockBackend::new();
+        let script = backend.script();
+        script.fail_next(1, "no route to bulb");
+        let light = light(backend);
+
+        let error = light.set_power(PowerState::On).await.unwrap_err();
+        assert!(matches!(error, LightError::BackendUnreachable(ref e) if e == "no route to bulb"), "{:?}", error);
+        assert_eq!(light.state().await.unwrap().label(), "UNREACHABLE");
+
+        light.set_power(PowerState::On).await.unwrap();
+        assert_eq!(script.power(), PowerState::On);
+        assert_eq!(
+            script.calls(),
+            vec![
+                MockCall::SetPower(PowerState::On, Some("no route to bulb".to_string())),
+                MockCall::SetPower(PowerState::On, None)
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scripted_delay_uses_tokio_time() {
+        let backend = MockBackend::new();
+        backend.script().then(MockStep::ok().after(Duration::from_secs(30)));
+        let light = light(backend);
+
+        let started = tokio::time::Instant::now();
+        light.set_power(PowerState::On).await.unwrap();
+        assert!(started.elapsed() >= Duration::from_secs(30));
+    }
+
+    #[tokio::test]
+    async fn test_external_change_is_caught_by_diagnostics() {
+        let backend = MockBackend::new();
+        let script = backend.script();
+        let light = light(backend);
+        light.set_power(PowerState::On).await.unwrap();
+
+        script.then(MockStep::ok().external_change(PowerState::Off));
+        let report = build_report(&light.probe().await.unwrap(), light.clock().now());
+        let check = report.checks.iter().find(|check| check.name == "backend").unwrap();
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert_eq!(check.detail, "The mock backend reports the bulb off but the server believes it is on");
+    }
+}
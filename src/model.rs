@@ -0,0 +1,110 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::logger::{LOG_ACTION_OFF, LOG_ACTION_ON};
+
+// Power state of a lightbulb
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerState {
+    #[default]
+    Off,
+    On,
+}
+
+impl PowerState {
+    pub fn is_on(self) -> bool {
+        self == PowerState::On
+    }
+
+    pub fn log_action(self) -> &'static str {
+        match self {
+            PowerState::On => LOG_ACTION_ON,
+            PowerState::Off => LOG_ACTION_OFF,
+        }
+    }
+}
+
+impl From<bool> for PowerState {
+    fn from(on: bool) -> Self {
+        if on { PowerState::On } else { PowerState::Off }
+    }
+}
+
+// RGB color of a lit bulb
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const WHITE: Color = Color { r: 255, g: 255, b: 255 };
+
+    // The color `amount` of the way from this one to `to`, channel by channel
+    pub fn blend(self, to: Color, amount: f64) -> Color {
+        let channel = |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * amount.clamp(0.0, 1.0)).round() as u8;
+        Color { r: channel(self.r, to.r), g: channel(self.g, to.g), b: channel(self.b, to.b) }
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+// Longest crossfade a scene takes, well inside the default tool time limit
+pub const MAX_CROSSFADE_MS: u64 = 20_000;
+
+// How a crossfade moves through its time: evenly, or starting or ending slowly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    // How far along a fade is once `elapsed` of its time, both from 0 to 1, has passed
+    pub fn ease(self, elapsed: f64) -> f64 {
+        let t = elapsed.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut if t < 0.5 => 2.0 * t * t,
+            Easing::EaseInOut => 1.0 - (2.0 - 2.0 * t).powi(2) / 2.0,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease-in",
+            Easing::EaseOut => "ease-out",
+            Easing::EaseInOut => "ease-in-out",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_easing_starts_and_ends_in_place() {
+        for easing in [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!((easing.ease(-1.0), easing.ease(0.0), easing.ease(1.0), easing.ease(2.0)), (0.0, 0.0, 1.0, 1.0), "{}", easing.label());
+        }
+        assert_eq!((Easing::EaseIn.ease(0.5), Easing::EaseOut.ease(0.5), Easing::EaseInOut.ease(0.25)), (0.25, 0.75, 0.125));
+        let amber = Color { r: 255, g: 191, b: 0 };
+        assert_eq!(Color::WHITE.blend(amber, 0.5), Color { r: 255, g: 223, b: 128 });
+        assert_eq!(Color::WHITE.blend(amber, 1.0), amber);
+    }
+}
@@ -0,0 +1,275 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::actor::{LightHandle, PowerChange};
+use crate::config::MotionConfig;
+use crate::error::LightError;
+use crate::model::PowerState;
+
+// Tags every change motion makes, beside the room's name
+pub const MOTION_TAG: &str = "motion";
+
+// What one motion report did
+#[derive(Debug, Clone, PartialEq)]
+pub enum MotionOutcome {
+    // The bulb was off; motion turned it on and started the countdown to turning it off
+    TurnedOn { sequence: u64, off_after: Duration },
+    // Motion had turned the bulb on already, so the countdown started again
+    Extended { off_after: Duration },
+    // Something else turned the bulb on, so motion leaves it to whoever did
+    AlreadyOn,
+}
+
+// The countdown to turning off a bulb that motion turned on
+struct Countdown {
+    // Motion in another room may push the deadline back; whichever room's motion set it is named when it passes
+    room: String,
+    off_after: Duration,
+    deadline: Instant,
+    // The state version motion left the bulb at; any other change cancels the countdown
+    version: u64,
+}
+
+// Motion lighting shared by every session and the MQTT subscriptions
+#[derive(Clone)]
+pub struct MotionLighting {
+    rooms: Arc<Vec<MotionConfig>>,
+    countdown: Arc<Mutex<Option<Countdown>>>,
+}
+
+impl MotionLighting {
+    pub fn new(rooms: Vec<MotionConfig>) -> Self {
+        Self { rooms: Arc::new(rooms), countdown: Arc::default() }
+    }
+
+    pub fn rooms(&self) -> &[MotionConfig] {
+        &self.rooms
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<Countdown>> {
+        self.countdown.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // The named room, which may be left out when only one is configured
+    pub fn room(&self, room: Option<&str>) -> Result<&MotionConfig, LightError> {
+        let names = || self.rooms.iter().map(|room| room.room.as_str()).collect::<Vec<_>>().join(", ");
+        match room {
+            None if self.rooms.len() == 1 => Ok(&self.rooms[0]),
+            None if self.rooms.is_empty() => Err(LightError::InvalidParameter("No motion rooms are configured".to_string())),
+            None => Err(LightError::InvalidParameter(format!("room is required, one of: {}", names()))),
+            Some(name) => self
+                .rooms
+                .iter()
+                .find(|room| room.room == name)
+                .ok_or_else(|| LightError::InvalidParameter(format!("Unknown motion room '{}'; configured rooms: {}", name, names()))),
+        }
+    }
+
+    // Time left before motion turns the bulb off, and the room whose motion it is counting from
+    pub fn pending(&self) -> Option<(String, Duration)> {
+        self.lock().as_ref().map(|countdown| (countdown.room.clone(), countdown.deadline.saturating_duration_since(Instant::now())))
+    }
+
+    // Drops the countdown, leaving the bulb as it is; returns the room it was counting for
+    pub fn cancel(&self) -> Option<String> {
+        self.lock().take().map(|countdown| countdown.room)
+    }
+
+    // What motion in `room` would do, without doing it
+    pub async fn preview(&self, light: &LightHandle, room: &MotionConfig) -> Result<MotionOutcome, LightError> {
+        let status = light.status().await?;
+        if status.state.power() != Some(PowerState::On) {
+            return Ok(MotionOutcome::TurnedOn { sequence: 0, off_after: room.off_after() });
+        }
+        Ok(match self.lock().as_ref().filter(|countdown| countdown.version == status.version) {
+            Some(_) => MotionOutcome::Extended { off_after: room.off_after() },
+            None => MotionOutcome::AlreadyOn,
+        })
+    }
+
+    // Turns the bulb on for motion in `room`, or pushes back the countdown to turning it off
+    pub async fn report(&self, light: &LightHandle, room: &MotionConfig) -> Result<MotionOutcome, LightError> {
+        let status = light.status().await?;
+        if status.state.power() == Some(PowerState::On) {
+            let mut countdown = self.lock();
+            let Some(countdown) = countdown.as_mut().filter(|countdown| countdown.version == status.version) else {
+                return Ok(MotionOutcome::AlreadyOn);
+            };
+            let deadline = Instant::now() + room.off_after();
+            if deadline > countdown.deadline {
+                (countdown.room, countdown.off_after, countdown.deadline) = (room.room.clone(), room.off_after(), deadline);
+            }
+            return Ok(MotionOutcome::Extended { off_after: room.off_after() });
+        }
+        let sensor = sensor_handle(light, room, format!("motion detected in {}", room.room));
+        let applied = sensor.set_power(PowerState::On).await?;
+        if applied.outcome == PowerChange::AlreadyInState {
+            return Ok(MotionOutcome::AlreadyOn);
+        }
+        let version = light.status().await?.version;
+        let countdown = Countdown { room: room.room.clone(), off_after: room.off_after(), deadline: Instant::now() + room.off_after(), version };
+        // One left over from before the bulb was turned off by hand is still being waited out, and its task
+        // picks up the new deadline
+        let replaced = self.lock().replace(countdown).is_some();
+        if !replaced {
+            tokio::spawn(self.clone().count_down(light.clone()));
+        }
+        Ok(MotionOutcome::TurnedOn { sequence: applied.sequence, off_after: room.off_after() })
+    }
+
+    // Waits out the deadline, however often motion pushes it back, then turns the bulb off unless something
+    // else has changed it since motion turned it on
+    async fn count_down(self, light: LightHandle) {
+        let countdown = loop {
+            let Some(deadline) = self.lock().as_ref().map(|countdown| countdown.deadline) else {
+                return;
+            };
+            tokio::time::sleep_until(deadline).await;
+            let mut countdown = self.lock();
+            if countdown.as_ref().is_some_and(|countdown| countdown.deadline <= Instant::now()) {
+                break countdown.take();
+            }
+        };
+        let Some(countdown) = countdown else {
+            return;
+        };
+        match light.status().await {
+            Ok(status) if status.version == countdown.version => {},
+            Ok(_) => return crate::diagnostic!(Info, "motion left the lightbulb alone: it was changed after motion in {} turned it on", countdown.room),
+            Err(_) => return,
+        }
+        let Some(room) = self.rooms.iter().find(|room| room.room == countdown.room) else {
+            return;
+        };
+        let sensor = sensor_handle(&light, room, format!("no motion for {}s", countdown.off_after.as_secs()));
+        if let Err(e) = sensor.set_power(PowerState::Off).await {
+            crate::diagnostic!(Warn, "motion could not turn the lightbulb off after no motion in {}: {}", room.room, e);
+        }
+    }
+}
+
+// Changes motion makes are logged as the room's sensor, tagged with the room so they can be counted by it
+fn sensor_handle(light: &LightHandle, room: &MotionConfig, reason: String) -> LightHandle {
+    light
+        .acting_as(format!("{} motion sensor", room.room))
+        .with_reason(Some(reason))
+        .tagged(vec![MOTION_TAG.to_string(), room.room.clone()])
+}
+
+// Whether a sensor's message reports motion: a bare ON, true or 1, or JSON with `occupancy`, `motion` or
+// `presence` true (as Zigbee2MQTT and Home Assistant publish them). Messages that motion has stopped are
+// ignored, since the countdown turns the bulb off.
+pub fn parse_motion(payload: &[u8]) -> bool {
+    let Ok(text) = std::str::from_utf8(payload) else {
+        return false;
+    };
+    let text = text.trim();
+    if ["on", "true", "1", "motion"].iter().any(|word| text.eq_ignore_ascii_case(word)) {
+        return true;
+    }
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text) else {
+        return false;
+    };
+    ["occupancy", "motion", "presence"].iter().any(|field| value.get(*field).and_then(serde_json::Value::as_bool) == Some(true))
+}
+
+// Reports motion in each room with a topic whenever its sensor publishes
+#[cfg(feature = "mqtt")]
+pub fn spawn_motion_subscribers(
+    motion: MotionLighting,
+    publisher: Arc<dyn crate::mqtt::StatePublisher + Send + Sync>,
+    light: LightHandle,
+) {
+    for room in motion.rooms().iter().filter(|room| room.topic.is_some()).cloned() {
+        let (motion, publisher, light) = (motion.clone(), publisher.clone(), light.clone());
+        tokio::spawn(async move {
+            let topic = room.topic.clone().unwrap_or_default();
+            let mut messages = match publisher.subscribe(&topic).await {
+                Ok(messages) => messages,
+                Err(e) => return crate::diagnostic!(Warn, "could not subscribe to motion topic {}: {}", topic, e),
+            };
+            while let Some(payload) = messages.recv().await {
+                if !parse_motion(&payload) {
+                    continue;
+                }
+                match motion.report(&light, &room).await {
+                    Err(LightError::ActorStopped) => return,
+                    Err(e) => crate::diagnostic!(Warn, "motion in {} could not turn the lightbulb on: {}", room.room, e),
+                    Ok(outcome) => crate::diagnostic!(Debug, "motion in {}: {:?}", room.room, outcome),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::state::StateMachine;
+
+    fn room(name: &str, off_after_secs: u64) -> MotionConfig {
+        MotionConfig { room: name.to_string(), off_after_secs, topic: None }
+    }
+
+    fn spawn_light() -> LightHandle {
+        LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()))
+    }
+
+    #[test]
+    fn test_sensor_payloads() {
+        assert!(parse_motion(b"ON\n") && parse_motion(b"true") && parse_motion(b"1"));
+        assert!(parse_motion(br#"{"occupancy": true, "battery": 80}"#));
+        assert!(!parse_motion(br#"{"occupancy": false}"#));
+        assert!(!parse_motion(b"OFF") && !parse_motion(b"{}"));
+    }
+
+    #[test]
+    fn test_rooms_are_looked_up_by_name() {
+        let motion = MotionLighting::new(vec![room("hallway", 60)]);
+        assert_eq!(motion.room(None).unwrap().room, "hallway");
+        assert!(motion.room(Some("attic")).unwrap_err().to_string().contains("configured rooms: hallway"));
+        assert!(MotionLighting::new(Vec::new()).room(None).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_motion_turns_the_bulb_on_until_it_stops() {
+        let light = spawn_light();
+        let motion = MotionLighting::new(vec![room("hallway", 60), room("kitchen", 300)]);
+        let (hallway, kitchen) = (motion.room(Some("hallway")).unwrap().clone(), motion.room(Some("kitchen")).unwrap().clone());
+        assert!(matches!(motion.report(&light, &hallway).await.unwrap(), MotionOutcome::TurnedOn { .. }));
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::On));
+
+        // Kitchen motion pushes the deadline out to its own, longer timeout
+        tokio::time::sleep(Duration::from_secs(50)).await;
+        assert_eq!(motion.report(&light, &kitchen).await.unwrap(), MotionOutcome::Extended { off_after: Duration::from_secs(300) });
+        tokio::time::sleep(Duration::from_secs(290)).await;
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::On));
+        tokio::time::sleep(Duration::from_secs(20)).await;
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::Off));
+        assert!(motion.pending().is_none());
+
+        let log = light.read_log().await.unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].ends_with("Lightbulb turned ON [motion,hallway] by hallway motion sensor (reason: motion detected in hallway) seq=1"), "{}", log);
+        assert!(lines[1].ends_with("Lightbulb turned OFF [motion,kitchen] by kitchen motion sensor (reason: no motion for 300s) seq=2"), "{}", log);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_motion_leaves_a_bulb_someone_else_changed() {
+        let light = spawn_light();
+        let motion = MotionLighting::new(vec![room("hallway", 60)]);
+        let hallway = motion.room(None).unwrap().clone();
+        light.set_power(PowerState::On).await.unwrap();
+        assert_eq!(motion.report(&light, &hallway).await.unwrap(), MotionOutcome::AlreadyOn);
+
+        light.set_power(PowerState::Off).await.unwrap();
+        motion.report(&light, &hallway).await.unwrap();
+        light.apply(crate::state::Transition::SetColor(crate::model::Color::WHITE)).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::On));
+    }
+}
@@ -0,0 +1,219 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::actor::LightHandle;
+use crate::config::MqttPublisherConfig;
+use crate::events::EventKind;
+use crate::home_assistant::{command_topic, discovery_payload, discovery_topic};
+use crate::model::PowerState;
+use crate::state::StateSnapshot;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const REQUEST_QUEUE: usize = 16;
+
+// Publishes retained messages to the broker and receives messages on subscribed topics
+#[async_trait::async_trait]
+pub trait StatePublisher {
+    async fn publish(&self, topic: &str, payload: Vec<u8>) -> anyhow::Result<()>;
+    async fn subscribe(&self, topic: &str) -> anyhow::Result<mpsc::Receiver<Vec<u8>>>;
+}
+
+type Subscriptions = Arc<Mutex<Vec<(String, mpsc::Sender<Vec<u8>>)>>>;
+
+pub struct MqttClient {
+    client: AsyncClient,
+    subscriptions: Subscriptions,
+}
+
+impl MqttClient {
+    // Connects in the background; messages published while the broker is down are queued
+    pub fn connect(config: &MqttPublisherConfig) -> Self {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(KEEP_ALIVE);
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+        let (client, event_loop) = AsyncClient::new(options, REQUEST_QUEUE);
+        let subscriptions = Subscriptions::default();
+        tokio::spawn(drive(event_loop, client.clone(), subscriptions.clone()));
+        Self { client, subscriptions }
+    }
+}
+
+#[async_trait::async_trait]
+impl StatePublisher for MqttClient {
+    async fn publish(&self, topic: &str, payload: Vec<u8>) -> anyhow::Result<()> {
+        self.client.publish(topic, QoS::AtLeastOnce, true, payload).await?;
+        Ok(())
+    }
+
+    async fn subscribe(&self, topic: &str) -> anyhow::Result<mpsc::Receiver<Vec<u8>>> {
+        let (sender, receiver) = mpsc::channel(REQUEST_QUEUE);
+        lock(&self.subscriptions).push((topic.to_string(), sender));
+        self.client.subscribe(topic, QoS::AtLeastOnce).await?;
+        Ok(receiver)
+    }
+}
+
+fn lock(subscriptions: &Subscriptions) -> std::sync::MutexGuard<'_, Vec<(String, mpsc::Sender<Vec<u8>>)>> {
+    subscriptions.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// rumqttc only makes progress (and reconnects) while its event loop is polled
+async fn drive(mut event_loop: EventLoop, client: AsyncClient, subscriptions: Subscriptions) {
+    loop {
+        match event_loop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                // Subscriptions do not survive a reconnect with a clean session; try_ avoids blocking the loop that drains requests
+                for (topic, _) in lock(&subscriptions).iter() {
+                    let _ = client.try_subscribe(topic.clone(), QoS::AtLeastOnce);
+                }
+            },
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                for (topic, sender) in lock(&subscriptions).iter() {
+                    if *topic == publish.topic {
+                        let _ = sender.try_send(publish.payload.to_vec());
+                    }
+                }
+            },
+            Ok(_) => {},
+            Err(_) => tokio::time::sleep(RECONNECT_DELAY).await,
+        }
+    }
+}
+
+// Publishes the current state, then the new state after every change, so the retained message is always current
+pub fn spawn_state_publisher(config: MqttPublisherConfig, publisher: Arc<dyn StatePublisher + Send + Sync>, light: LightHandle) {
+    let mut events = light.subscribe();
+    tokio::spawn(async move {
+        if config.discovery {
+            announce(&config, publisher.as_ref(), light.clone()).await;
+        }
+        if let Ok(snapshot) = light.snapshot().await {
+            publish(publisher.as_ref(), &config.topic, &snapshot).await;
+        }
+        loop {
+            match events.recv().await {
+                Ok(event) if event.kind == EventKind::StateChanged => publish(publisher.as_ref(), &config.topic, &event.state).await,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {},
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+// Registers the bulb with Home Assistant and carries out the ON/OFF commands it sends back
+async fn announce(config: &MqttPublisherConfig, publisher: &(dyn StatePublisher + Send + Sync), light: LightHandle) {
+    let _ = publisher.publish(&discovery_topic(config), discovery_payload(config).to_string().into_bytes()).await;
+    let Ok(mut commands) = publisher.subscribe(&command_topic(config)).await else {
+        return;
+    };
+    let light = light.acting_as("mqtt");
+    tokio::spawn(async move {
+        while let Some(command) = commands.recv().await {
+            let target = match command.as_slice() {
+                b"ON" => PowerState::On,
+                b"OFF" => PowerState::Off,
+                _ => continue,
+            };
+            // Rejected commands (a locked bulb, say) leave the published state unchanged, which HA shows
+            let _ = light.set_power(target).await;
+        }
+    });
+}
+
+async fn publish(publisher: &(dyn StatePublisher + Send + Sync), topic: &str, snapshot: &StateSnapshot) {
+    if let Ok(payload) = serde_json::to_vec(snapshot) {
+        // The next change publishes the full state again, so a failed publish is not retried
+        let _ = publisher.publish(topic, payload).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::config::Config;
+    use crate::logger::InMemoryLogger;
+    use crate::state::StateMachine;
+
+    #[derive(Default)]
+    struct RecordingPublisher {
+        messages: Mutex<Vec<(String, serde_json::Value)>>,
+        subscriptions: Mutex<Vec<(String, mpsc::Sender<Vec<u8>>)>>,
+    }
+
+    impl RecordingPublisher {
+        async fn deliver(&self, topic: &str, payload: &[u8]) {
+            let sender = self.subscriptions.lock().unwrap().iter().find(|(t, _)| t == topic).unwrap().1.clone();
+            sender.send(payload.to_vec()).await.unwrap();
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl StatePublisher for RecordingPublisher {
+        async fn publish(&self, topic: &str, payload: Vec<u8>) -> anyhow::Result<()> {
+            self.messages.lock().unwrap().push((topic.to_string(), serde_json::from_slice(&payload)?));
+            Ok(())
+        }
+
+        async fn subscribe(&self, topic: &str) -> anyhow::Result<mpsc::Receiver<Vec<u8>>> {
+            let (sender, receiver) = mpsc::channel(4);
+            self.subscriptions.lock().unwrap().push((topic.to_string(), sender));
+            Ok(receiver)
+        }
+    }
+
+    fn config(discovery: bool) -> MqttPublisherConfig {
+        let mut config = Config::parse("[mqtt]\nhost = \"broker\"\ntopic = \"home/lightbulb\"").unwrap().mqtt.unwrap();
+        config.discovery = discovery;
+        config
+    }
+
+    fn spawn_light() -> LightHandle {
+        LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()))
+    }
+
+    #[tokio::test]
+    async fn test_publishes_initial_state_and_changes() {
+        let light = spawn_light();
+        let publisher = Arc::new(RecordingPublisher::default());
+        spawn_state_publisher(config(false), publisher.clone(), light.clone());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        light.set_power(PowerState::On).await.unwrap();
+        // Already on: no change, nothing published
+        light.set_power(PowerState::On).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let messages = publisher.messages.lock().unwrap();
+        let states: Vec<&str> = messages.iter().map(|(_, payload)| payload["state"].as_str().unwrap()).collect();
+        assert_eq!(states, vec!["OFF", "ON"]);
+        assert!(messages.iter().all(|(topic, _)| topic == "home/lightbulb"));
+    }
+
+    #[tokio::test]
+    async fn test_discovery_announces_and_accepts_commands() {
+        let light = spawn_light();
+        let publisher = Arc::new(RecordingPublisher::default());
+        spawn_state_publisher(config(true), publisher.clone(), light.clone());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        {
+            let messages = publisher.messages.lock().unwrap();
+            let (topic, payload) = &messages[0];
+            assert_eq!(topic, "homeassistant/light/lightbulb-mcp/config");
+            assert_eq!(payload["command_topic"], "home/lightbulb/set");
+            assert_eq!(payload["state_topic"], "home/lightbulb");
+        }
+
+        publisher.deliver("home/lightbulb/set", b"ON").await;
+        publisher.deliver("home/lightbulb/set", b"bogus").await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(light.state().await.unwrap().power(), Some(PowerState::On));
+    }
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime};
+use tokio::sync::broadcast;
+
+use crate::clock::format_duration;
+use crate::config::{ChatService, NotificationConfig};
+use crate::events::{EventBus, EventKind, LightEvent};
+use crate::schedule::in_window;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Posts one JSON message to a chat webhook, returning the HTTP status
+#[async_trait::async_trait]
+pub trait ChatSender {
+    async fn post(&self, url: &str, body: String) -> anyhow::Result<u16>;
+}
+
+pub struct HttpChatSender {
+    client: reqwest::Client,
+}
+
+impl HttpChatSender {
+    pub fn new() -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatSender for HttpChatSender {
+    async fn post(&self, url: &str, body: String) -> anyhow::Result<u16> {
+        let response = self.client.post(url).header("Content-Type", "application/json").body(body).send().await?;
+        Ok(response.status().as_u16())
+    }
+}
+
+// A validated notification sink with its quiet hours parsed once up front
+pub struct Notifier {
+    config: NotificationConfig,
+    quiet_hours: Option<(NaiveTime, NaiveTime)>,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let quiet_hours = config.quiet_hours()?;
+        Ok(Self { config, quiet_hours })
+    }
+
+    fn wants(&self, event: &LightEvent, now: NaiveTime) -> bool {
+        match event.kind {
+            EventKind::BackendUnreachable => self.config.backend_unreachable,
+            EventKind::OnTooLong => self.config.on_too_long,
+            EventKind::StateChanged => {
+                self.config.state_changed
+                    && self.config.to.as_deref().is_none_or(|to| to.eq_ignore_ascii_case(event.to))
+                    && self.quiet_hours.is_none_or(|(start, end)| in_window(now, Some(start), Some(end)))
+            },
+        }
+    }
+
+    // Slack and Discord incoming webhooks differ only in the field holding the message
+    fn payload(&self, event: &LightEvent) -> String {
+        let text = message(event);
+        let payload = match self.config.service {
+            ChatService::Slack => serde_json::json!({ "text": text }),
+            ChatService::Discord => serde_json::json!({ "content": text }),
+        };
+        payload.to_string()
+    }
+}
+
+fn message(event: &LightEvent) -> String {
+    let at = event.at.with_timezone(&Local).format("%H:%M");
+    match event.kind {
+        EventKind::StateChanged => format!(
+            "Lightbulb changed from {} to {} at {} ({}, sequence {})",
+            event.from, event.to, at, event.action, event.sequence
+        ),
+        EventKind::BackendUnreachable => format!(
+            "Lightbulb backend unreachable at {} during {} (sequence {})",
+            at, event.action, event.sequence
+        ),
+        EventKind::OnTooLong => format!(
+            "Lightbulb has been on for {} at {}, longer than the safety limit (sequence {})",
+            format_duration(event.state.in_state_secs.unwrap_or_default()), at, event.sequence
+        ),
+    }
+}
+
+// Posts a chat message for every event a notifier wants; failed posts are dropped
+pub fn spawn_notifiers(notifiers: Vec<Notifier>, sender: Arc<dyn ChatSender + Send + Sync>, events: &EventBus) {
+    let mut events = events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let now = event.at.with_timezone(&Local).time();
+            for notifier in notifiers.iter().filter(|notifier| notifier.wants(&event, now)) {
+                let (sender, url, body) = (sender.clone(), notifier.config.webhook_url.clone(), notifier.payload(&event));
+                tokio::spawn(async move {
+                    let _ = sender.post(&url, body).await;
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::config::QuietHours;
+    use crate::state::StateMachine;
+
+    #[derive(Default)]
+    struct RecordingSender {
+        posts: Mutex<Vec<(String, serde_json::Value)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ChatSender for RecordingSender {
+        async fn post(&self, url: &str, body: String) -> anyhow::Result<u16> {
+            self.posts.lock().unwrap().push((url.to_string(), serde_json::from_str(&body)?));
+            Ok(204)
+        }
+    }
+
+    fn event(kind: EventKind, to: &'static str) -> LightEvent {
+        LightEvent {
+            sequence: 4,
+            at: Utc::now(),
+            kind,
+            action: "turn_off".to_string(),
+            from: "ON",
+            to,
+            state: StateMachine::new().snapshot(),
+            log_sequence: None,
+        }
+    }
+
+    fn notifier(service: ChatService, quiet_hours: Option<(&str, &str)>) -> Notifier {
+        Notifier::new(NotificationConfig {
+            service,
+            webhook_url: "https://hooks.example.com/1".to_string(),
+            state_changed: true,
+            backend_unreachable: false,
+            on_too_long: true,
+            to: Some("OFF".to_string()),
+            quiet_hours: quiet_hours.map(|(start, end)| QuietHours { start: start.to_string(), end: end.to_string() }),
+        })
+        .unwrap()
+    }
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_filters_by_type_state_and_quiet_hours() {
+        let notifier = notifier(ChatService::Slack, Some(("22:00", "07:00")));
+        assert!(notifier.wants(&event(EventKind::StateChanged, "OFF"), time("23:30")));
+        assert!(!notifier.wants(&event(EventKind::StateChanged, "OFF"), time("12:00")));
+        assert!(!notifier.wants(&event(EventKind::StateChanged, "ON"), time("23:30")));
+        assert!(!notifier.wants(&event(EventKind::BackendUnreachable, "UNREACHABLE"), time("23:30")));
+    }
+
+    #[tokio::test]
+    async fn test_posts_service_specific_payload() {
+        let sender = Arc::new(RecordingSender::default());
+        let events = EventBus::new();
+        spawn_notifiers(vec![notifier(ChatService::Discord, None)], sender.clone(), &events);
+
+        events.publish(event(EventKind::StateChanged, "OFF"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let posts = sender.posts.lock().unwrap();
+        assert_eq!(posts.len(), 1);
+        let content = posts[0].1["content"].as_str().unwrap();
+        assert!(content.starts_with("Lightbulb changed from ON to OFF at "));
+        assert!(content.ends_with("(turn_off, sequence 4)"));
+    }
+}
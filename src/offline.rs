@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::{Value, json};
+
+use crate::model::{Color, PowerState};
+
+// How often the actor asks an unreachable bulb whether it is back, unless configured otherwise
+pub const DEFAULT_OFFLINE_RETRY: Duration = Duration::from_secs(10);
+// Said of a change held back because the bulb is already known to be unreachable
+pub const HELD_FOR_UNREACHABLE: &str = "the lightbulb is unreachable";
+
+// A change held back until the bulb answers again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingChange {
+    Power(PowerState),
+    Color(Color),
+    // The percentage asked for, and the gamma-corrected level the backend is to be sent
+    Brightness { percent: u8, level: u8 },
+}
+
+impl PendingChange {
+    pub fn property(&self) -> &'static str {
+        match self {
+            PendingChange::Power(_) => "power",
+            PendingChange::Color(_) => "color",
+            PendingChange::Brightness { .. } => "brightness",
+        }
+    }
+
+    // "turn it ON", "set its color to #ff0000" or "set its brightness to 60%"
+    pub fn describe(&self) -> String {
+        match self {
+            PendingChange::Power(power) => format!("turn it {}", power.log_action()),
+            PendingChange::Color(color) => format!("set its color to {}", color),
+            PendingChange::Brightness { percent, .. } => format!("set its brightness to {}%", percent),
+        }
+    }
+
+    fn value(&self) -> Value {
+        match self {
+            PendingChange::Power(power) => json!(power),
+            PendingChange::Color(color) => json!(color.to_string()),
+            PendingChange::Brightness { percent, .. } => json!(percent),
+        }
+    }
+}
+
+// A queued change, with who made it and why, so it is applied and logged as theirs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedChange {
+    pub change: PendingChange,
+    // The sequence the change was answered with when it was queued
+    pub sequence: u64,
+    pub queued_at: DateTime<Utc>,
+    pub by: Option<String>,
+    pub reason: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl QueuedChange {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "property": self.change.property(),
+            "value": self.change.value(),
+            "sequence": self.sequence,
+            "queued_at": self.queued_at,
+            "by": self.by,
+            "reason": self.reason,
+            "tags": self.tags,
+        })
+    }
+}
+
+// Changes waiting for an unreachable bulb, at most one per property: a later change replaces the one before it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OfflineQueue {
+    changes: Vec<QueuedChange>,
+}
+
+impl OfflineQueue {
+    pub fn push(&mut self, queued: QueuedChange) {
+        self.clear(queued.change.property());
+        self.changes.push(queued);
+    }
+
+    // Forgets the queued change to `property`, as one made since has superseded it; true if there was one
+    pub fn clear(&mut self, property: &str) -> bool {
+        let before = self.changes.len();
+        self.changes.retain(|queued| queued.change.property() != property);
+        self.changes.len() != before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> &[QueuedChange] {
+        &self.changes
+    }
+
+    // Empties the queue into the order its changes are to be applied: the order they were made, except that a power
+    // change goes first when `power_first`, as a bulb whose power is unknown takes no other change until it has one
+    pub fn take(&mut self, power_first: bool) -> Vec<QueuedChange> {
+        let mut changes = std::mem::take(&mut self.changes);
+        if power_first {
+            changes.sort_by_key(|queued| !matches!(queued.change, PendingChange::Power(_)));
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(change: PendingChange, sequence: u64) -> QueuedChange {
+        QueuedChange { change, sequence, queued_at: DateTime::<Utc>::MIN_UTC, by: None, reason: None, tags: Vec::new() }
+    }
+
+    #[test]
+    fn test_the_latest_change_to_each_property_wins() {
+        let mut queue = OfflineQueue::default();
+        queue.push(queued(PendingChange::Brightness { percent: 40, level: 30 }, 1));
+        queue.push(queued(PendingChange::Power(PowerState::On), 2));
+        queue.push(queued(PendingChange::Brightness { percent: 60, level: 50 }, 3));
+        let sequences: Vec<u64> = queue.changes().iter().map(|queued| queued.sequence).collect();
+        assert_eq!(sequences, [2, 3]);
+        assert_eq!(queue.changes()[1].to_json()["value"], 60);
+
+        assert!(queue.clear("power") && !queue.clear("color"));
+        assert_eq!(queue.changes().len(), 1);
+    }
+
+    #[test]
+    fn test_power_goes_first_for_a_bulb_of_unknown_power() {
+        let changes = || {
+            let mut queue = OfflineQueue::default();
+            queue.push(queued(PendingChange::Color(Color::WHITE), 1));
+            queue.push(queued(PendingChange::Power(PowerState::Off), 2));
+            queue
+        };
+        let properties = |changes: Vec<QueuedChange>| changes.iter().map(|queued| queued.change.property()).collect::<Vec<_>>();
+        assert_eq!(properties(changes().take(false)), ["color", "power"]);
+        let mut queue = changes();
+        assert_eq!(properties(queue.take(true)), ["power", "color"]);
+        assert!(queue.is_empty());
+        assert_eq!(PendingChange::Power(PowerState::Off).describe(), "turn it OFF");
+    }
+}
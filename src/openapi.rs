@@ -0,0 +1,219 @@
+use schemars::JsonSchema;
+use schemars::generate::{SchemaGenerator, SchemaSettings};
+use serde_json::{Value, json};
+
+use crate::diagnostics::DiagnosticsReport;
+use crate::events::LightEvent;
+use crate::info::VERSION;
+use crate::rest::PowerResponse;
+use crate::state::StateSnapshot;
+use crate::stats::Statistics;
+
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+// The OpenAPI document for the REST API, served at /openapi.json for generating dashboard clients.
+// Response schemas come from the types the handlers serialize, so they cannot drift from what is sent.
+pub fn document() -> Value {
+    let mut schemas = SchemaGenerator::new(SchemaSettings::openapi3().for_serialize());
+    let state = schema::<StateSnapshot>(&mut schemas);
+    let power = schema::<PowerResponse>(&mut schemas);
+    let statistics = schema::<Statistics>(&mut schemas);
+    let readiness = schema::<DiagnosticsReport>(&mut schemas);
+    // Only named in the /events description, since OpenAPI has no way to describe WebSocket frames
+    schema::<LightEvent>(&mut schemas);
+    let mut components = schemas.take_definitions(true);
+    components.insert("Error".to_string(), error_schema());
+    components.insert("Health".to_string(), health_schema());
+
+    json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": "lightbulb-mcp REST API",
+            "version": VERSION,
+            "description": "Plain HTTP access to the same lightbulb the MCP tools drive",
+        },
+        "paths": {
+            "/": {
+                "get": {
+                    "operationId": "dashboard",
+                    "summary": "The built-in dashboard",
+                    "responses": { "200": { "description": "An HTML page built on the endpoints below", "content": { "text/html": { "schema": { "type": "string" } } } } },
+                },
+            },
+            "/state": {
+                "get": {
+                    "operationId": "getState",
+                    "summary": "The lightbulb's state, as get_lightbulb_state reports it",
+                    "responses": ok("The current state", state),
+                },
+            },
+            "/on": {
+                "post": {
+                    "operationId": "turnOn",
+                    "summary": "Turn the lightbulb on",
+                    "responses": power_responses(&power),
+                },
+            },
+            "/off": {
+                "post": {
+                    "operationId": "turnOff",
+                    "summary": "Turn the lightbulb off",
+                    "responses": power_responses(&power),
+                },
+            },
+            "/log": {
+                "get": {
+                    "operationId": "getLog",
+                    "summary": "The activity log as plain text, one entry per line",
+                    "parameters": [
+                        query("since", "Only entries at or after this RFC 3339 timestamp", json!({ "type": "string", "format": "date-time" })),
+                        query("tag", "Only power changes logged with this tag", json!({ "type": "string" })),
+                    ],
+                    "responses": {
+                        "200": { "description": "The matching log entries", "content": { "text/plain": { "schema": { "type": "string" } } } },
+                        "400": error("since is not an RFC 3339 timestamp, or tag is not a valid tag"),
+                    },
+                },
+            },
+            "/statistics": {
+                "get": {
+                    "operationId": "getStatistics",
+                    "summary": "Every get_statistics metric over the whole log, at the default 9 W",
+                    "responses": ok("Counts, on time, energy and the hourly histogram", statistics),
+                },
+            },
+            "/events": {
+                "get": {
+                    "operationId": "streamEvents",
+                    "summary": "A WebSocket sending a JSON text frame, a LightEvent, for every state change",
+                    "responses": { "101": { "description": "Switched to a WebSocket; each frame is a LightEvent (see components)" } },
+                },
+            },
+            "/healthz": {
+                "get": {
+                    "operationId": "liveness",
+                    "summary": "Liveness: the server still works through its commands",
+                    "responses": {
+                        "200": { "description": "Working", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Health" } } } },
+                        "503": { "description": "Stopping, or not answering within 2 seconds", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Health" } } } },
+                    },
+                },
+            },
+            "/readyz": {
+                "get": {
+                    "operationId": "readiness",
+                    "summary": "Readiness: the log takes entries and the backend answers, reported like run_diagnostics",
+                    "responses": {
+                        "200": { "description": "Every check passed or was skipped", "content": { "application/json": { "schema": readiness } } },
+                        "503": { "description": "A check failed", "content": { "application/json": { "schema": readiness } } },
+                    },
+                },
+            },
+            "/openapi.json": {
+                "get": {
+                    "operationId": "openapi",
+                    "summary": "This document",
+                    "responses": { "200": { "description": "The OpenAPI document", "content": { "application/json": { "schema": { "type": "object" } } } } },
+                },
+            },
+        },
+        "components": { "schemas": components },
+    })
+}
+
+fn schema<T: JsonSchema>(schemas: &mut SchemaGenerator) -> Value {
+    schemas.subschema_for::<T>().to_value()
+}
+
+fn ok(description: &str, schema: Value) -> Value {
+    json!({ "200": { "description": description, "content": { "application/json": { "schema": schema } } } })
+}
+
+fn error(description: &str) -> Value {
+    json!({ "description": description, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } })
+}
+
+// Power changes answer like the MCP tools fail, with the status matching the error's code
+fn power_responses(power: &Value) -> Value {
+    json!({
+        "200": { "description": "changed is false if the bulb was already in that state", "content": { "application/json": { "schema": power } } },
+        "409": error("The bulb is locked or cannot make the transition now"),
+        "429": {
+            "description": "Too soon after the last power change",
+            "headers": { "Retry-After": { "description": "Seconds until the bulb may be switched again", "schema": { "type": "integer" } } },
+            "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } },
+        },
+        "502": error("The backend is unreachable"),
+        "503": error("The server is stopping"),
+    })
+}
+
+fn query(name: &str, description: &str, schema: Value) -> Value {
+    json!({ "name": name, "in": "query", "required": false, "description": description, "schema": schema })
+}
+
+// Errors carry a stable code and message, beside whatever context that code has
+fn error_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["code", "message"],
+        "properties": {
+            "code": { "type": "string", "example": "BULB_LOCKED" },
+            "message": { "type": "string" },
+        },
+        "additionalProperties": true,
+    })
+}
+
+fn health_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["status"],
+        "properties": { "status": { "type": "string", "enum": ["ok", "stopping", "unresponsive"] } },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn refs(value: &Value, found: &mut Vec<String>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(Value::String(target)) = map.get("$ref") {
+                    found.push(target.clone());
+                }
+                map.values().for_each(|value| refs(value, found));
+            },
+            Value::Array(items) => items.iter().for_each(|value| refs(value, found)),
+            _ => {},
+        }
+    }
+
+    #[test]
+    fn test_every_reference_resolves() {
+        let document = document();
+        let mut found = Vec::new();
+        refs(&document, &mut found);
+        assert!(found.contains(&"#/components/schemas/StateSnapshot".to_string()), "{:?}", found);
+        for target in found {
+            let name = target.strip_prefix("#/components/schemas/").unwrap_or_else(|| panic!("{} is not a component", target));
+            assert!(document["components"]["schemas"].get(name).is_some(), "{} is not defined", target);
+        }
+        let event = &document["components"]["schemas"]["LightEvent"];
+        assert_eq!(event["properties"]["state"]["$ref"], "#/components/schemas/StateSnapshot");
+    }
+
+    #[test]
+    fn test_schemas_describe_what_is_sent() {
+        let document = document();
+        let state = &document["components"]["schemas"]["StateSnapshot"];
+        assert_eq!(state["properties"]["last_changed"]["nullable"], true);
+        assert_eq!(state["properties"]["power"]["anyOf"][0]["$ref"], "#/components/schemas/PowerState");
+        assert!(state["required"].as_array().unwrap().contains(&"version".into()));
+        // Metrics that were not asked for are left out of the JSON, so they are not required
+        let statistics = &document["components"]["schemas"]["Statistics"];
+        assert_eq!(statistics["required"], json!(["from", "to"]));
+        assert_eq!(document["info"]["version"], VERSION);
+    }
+}
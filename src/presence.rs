@@ -0,0 +1,202 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+
+use chrono::NaiveTime;
+
+use crate::actor::{LightHandle, PowerChange, Sequenced};
+use crate::config::{PresenceConfig, PresenceEvent, PresenceRuleConfig, RulePower};
+use crate::error::LightError;
+use crate::schedule::in_window;
+
+// Who presence rules' changes are logged as; they are tagged with this and the person who came or went
+pub const PRESENCE_CALLER: &str = "presence";
+
+// A validated presence rule with its time window parsed once up front
+struct Rule {
+    config: PresenceRuleConfig,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+}
+
+impl Rule {
+    fn matches(&self, events: &[PresenceEvent], person: &str, now: NaiveTime) -> bool {
+        events.contains(&self.config.when)
+            && self.config.person.as_deref().is_none_or(|only| only == person)
+            && in_window(now, self.after, self.before)
+    }
+}
+
+// What one presence report did
+#[derive(Debug)]
+pub struct PresenceReport {
+    // False when the person was already home, or already away
+    pub changed: bool,
+    // Who is home now
+    pub home: Vec<String>,
+    pub applied: Option<AppliedRule>,
+}
+
+// The rule a presence report applied, and what applying it did
+#[derive(Debug)]
+pub struct AppliedRule {
+    pub name: String,
+    pub power: RulePower,
+    pub result: Result<Sequenced<PowerChange>, LightError>,
+}
+
+// Who of the configured people is home, shared by every session; everyone counts as away until reported home
+#[derive(Clone, Default)]
+pub struct Presence {
+    people: Arc<Vec<String>>,
+    rules: Arc<Vec<Rule>>,
+    home: Arc<Mutex<BTreeSet<String>>>,
+}
+
+impl Presence {
+    pub fn new(config: PresenceConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let rules = config
+            .rules
+            .into_iter()
+            .map(|config| {
+                let (after, before) = config.window()?;
+                Ok(Rule { config, after, before })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { people: Arc::new(config.people), rules: Arc::new(rules), home: Arc::default() })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<String>> {
+        self.home.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn home(&self) -> Vec<String> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn check_person(&self, person: &str) -> Result<(), LightError> {
+        if self.people.iter().any(|known| known == person) {
+            return Ok(());
+        }
+        match self.people.is_empty() {
+            true => Err(LightError::InvalidParameter("No people are configured for presence".to_string())),
+            false => Err(LightError::InvalidParameter(format!("Unknown person '{}'; configured people: {}", person, self.people.join(", ")))),
+        }
+    }
+
+    // The events reporting `person` home or away makes, none when they already were
+    fn events(home: &BTreeSet<String>, person: &str, arriving: bool) -> Vec<PresenceEvent> {
+        match (arriving, home.contains(person)) {
+            (true, false) if home.is_empty() => vec![PresenceEvent::Arrive, PresenceEvent::FirstArrive],
+            (true, false) => vec![PresenceEvent::Arrive],
+            (false, true) if home.len() == 1 => vec![PresenceEvent::Leave, PresenceEvent::LastLeave],
+            (false, true) => vec![PresenceEvent::Leave],
+            _ => Vec::new(),
+        }
+    }
+
+    fn rule_for(&self, events: &[PresenceEvent], person: &str, now: NaiveTime) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(events, person, now))
+    }
+
+    // Whether reporting `person` would change who is home, and the rule it would apply, without doing either
+    pub fn preview(&self, person: &str, arriving: bool, now: NaiveTime) -> Result<(bool, Option<String>), LightError> {
+        self.check_person(person)?;
+        let events = Self::events(&self.lock(), person, arriving);
+        Ok((!events.is_empty(), self.rule_for(&events, person, now).map(|rule| rule.config.name.clone())))
+    }
+
+    // Records `person` as home or away and applies the first rule the change matches, at local time `now`
+    pub async fn report(&self, light: &LightHandle, person: &str, arriving: bool, now: NaiveTime) -> Result<PresenceReport, LightError> {
+        self.check_person(person)?;
+        let (events, home) = {
+            let mut home = self.lock();
+            let events = Self::events(&home, person, arriving);
+            match arriving {
+                true => home.insert(person.to_string()),
+                false => home.remove(person),
+            };
+            (events, home.iter().cloned().collect())
+        };
+        let Some(rule) = self.rule_for(&events, person, now) else {
+            return Ok(PresenceReport { changed: !events.is_empty(), home, applied: None });
+        };
+        let reason = format!("presence rule '{}': {} {}", rule.config.name, person, if arriving { "arrived" } else { "left" });
+        let light = light
+            .acting_as(PRESENCE_CALLER)
+            .with_reason(Some(reason))
+            .tagged(vec![PRESENCE_CALLER.to_string(), person.to_string()]);
+        let result = crate::rules::apply(&light, rule.config.power, rule.config.brightness).await;
+        if let Err(e) = &result {
+            crate::diagnostic!(Warn, "the presence rule '{}' could not be applied: {}", rule.config.name, e);
+        }
+        let applied = AppliedRule { name: rule.config.name.clone(), power: rule.config.power, result };
+        Ok(PresenceReport { changed: true, home, applied: Some(applied) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+    use crate::state::StateMachine;
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    fn rule(name: &str, when: PresenceEvent, power: RulePower, after: Option<&str>) -> PresenceRuleConfig {
+        PresenceRuleConfig {
+            name: name.to_string(),
+            when,
+            person: None,
+            power,
+            brightness: None,
+            after: after.map(String::from),
+            before: None,
+        }
+    }
+
+    fn presence() -> Presence {
+        Presence::new(PresenceConfig {
+            people: vec!["alice".to_string(), "bob".to_string()],
+            rules: vec![
+                rule("everyone left", PresenceEvent::LastLeave, RulePower::Off, None),
+                rule("evening arrival", PresenceEvent::Arrive, RulePower::On, Some("18:00")),
+            ],
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_last_one_out_turns_the_bulb_off() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let presence = presence();
+
+        // Arriving before the evening window applies no rule
+        let report = presence.report(&light, "alice", true, time("12:00")).await.unwrap();
+        assert!(report.changed && report.applied.is_none());
+        let report = presence.report(&light, "bob", true, time("19:00")).await.unwrap();
+        assert_eq!(report.home, ["alice", "bob"]);
+        assert_eq!(report.applied.unwrap().name, "evening arrival");
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+
+        assert!(!presence.report(&light, "bob", true, time("19:30")).await.unwrap().changed);
+        assert!(presence.report(&light, "alice", false, time("20:00")).await.unwrap().applied.is_none());
+        assert_eq!(presence.preview("bob", false, time("21:00")).unwrap(), (true, Some("everyone left".to_string())));
+        let report = presence.report(&light, "bob", false, time("21:00")).await.unwrap();
+        assert!(report.home.is_empty());
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::Off));
+        let log = light.read_log().await.unwrap();
+        assert!(log.contains("turned OFF [presence,bob] by presence (reason: presence rule 'everyone left': bob left)"), "{}", log);
+    }
+
+    #[test]
+    fn test_unknown_people_are_refused() {
+        let error = presence().preview("carol", true, time("12:00")).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid parameter: Unknown person 'carol'; configured people: alice, bob");
+        assert!(Presence::default().preview("alice", true, time("12:00")).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::config::{LogPrivacyConfig, Redaction};
+use crate::logger::{LOG_SIGNATURE_MARKER, UNNAMED_CLIENT};
+use crate::stats::{HistoryEntry, LogAction};
+
+// Hex digits of the keyed hash a hashed name or reason is logged as
+const HASH_DIGITS: usize = 12;
+pub const HASHED_PREFIX: &str = "anon-";
+
+// What of the client and reason behind a change goes into its log entry. Actions, times, tags and numbers are
+// always kept, so statistics and audits still work.
+#[derive(Debug, Clone, Default)]
+pub struct LogPrivacy {
+    clients: Redaction,
+    reasons: Redaction,
+    salt: String,
+}
+
+impl LogPrivacy {
+    pub fn new(config: &LogPrivacyConfig) -> Self {
+        Self { clients: config.clients, reasons: config.reasons, salt: config.salt.clone().unwrap_or_default() }
+    }
+
+    // The client as the log names it, if at all
+    pub fn client(&self, who: &str) -> Option<String> {
+        self.redact(self.clients, who)
+    }
+
+    pub fn reason(&self, reason: &str) -> Option<String> {
+        self.redact(self.reasons, reason)
+    }
+
+    fn redact(&self, redaction: Redaction, text: &str) -> Option<String> {
+        match redaction {
+            Redaction::Keep => Some(text.to_string()),
+            Redaction::Hash => Some(self.hash(text)),
+            Redaction::Omit => None,
+        }
+    }
+
+    // Whether hashes are keyed, so a client name cannot be hashed by anyone and looked for in the log
+    pub fn keyed(&self) -> bool {
+        !self.salt.is_empty()
+    }
+
+    // Keyed with the salt, so the same client always hashes the same but names cannot be guessed and checked
+    fn hash(&self, text: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.salt.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(text.as_bytes());
+        let digest: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{}{}", HASHED_PREFIX, &digest[..HASH_DIGITS])
+    }
+}
+
+// What purge_data does with the client and reason of an old entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeMode {
+    // Hashed as `log_privacy = "hash"` would have logged them, so one client's entries still go together
+    Anonymize,
+    Remove,
+}
+
+// The log after purging, with how many entries lost a client or reason
+#[derive(Debug, Clone, PartialEq)]
+pub struct Purge {
+    pub log: String,
+    pub entries: usize,
+}
+
+// Anonymizes or removes the client and reason of every entry dated before `before`, leaving its time, action,
+// tags and number, so statistics come out as before. `as_written` turns each changed entry into the line the log
+// writes, e.g. signed again. None when no entry had anything to purge.
+pub fn purge(log: &str, before: DateTime<Utc>, mode: PurgeMode, privacy: &LogPrivacy, as_written: impl Fn(&str) -> String) -> Option<Purge> {
+    let mut entries = 0;
+    let lines: Vec<String> = log
+        .lines()
+        .map(|line| match purge_line(line, before, mode, privacy) {
+            Some(purged) => {
+                entries += 1;
+                as_written(&purged)
+            },
+            None => line.to_string(),
+        })
+        .collect();
+    (entries > 0).then(|| Purge { log: lines.iter().map(|line| format!("{}\n", line)).collect(), entries })
+}
+
+// The entry, unsigned, without what identifies its client and reason; None when it is recent or names neither
+fn purge_line(line: &str, before: DateTime<Utc>, mode: PurgeMode, privacy: &LogPrivacy) -> Option<String> {
+    let entry = HistoryEntry::parse(line).filter(|entry| entry.at < before)?;
+    let mut purged = line.rsplit_once(LOG_SIGNATURE_MARKER).map_or(line, |(entry, _)| entry).to_string();
+    // A name or reason hashed already, when it was logged or by an earlier purge, is left as it is
+    let anonymized = |text: &str| match text.starts_with(HASHED_PREFIX) {
+        true => text.to_string(),
+        false => privacy.hash(text),
+    };
+    let mut changed = false;
+    let mut replace = |from: String, to: String, last: bool| {
+        let start = if last { purged.rfind(&from) } else { purged.find(&from) };
+        if let Some(start) = start.filter(|_| from != to) {
+            purged.replace_range(start..start + from.len(), &to);
+            changed = true;
+        }
+    };
+    match entry.action {
+        // The client comes ahead of the reason, so its first mention is the one made of it
+        LogAction::On | LogAction::Off | LogAction::Color | LogAction::Brightness => {
+            if let Some(by) = &entry.by {
+                let to = match mode {
+                    PurgeMode::Anonymize => format!(" by {}", anonymized(by)),
+                    PurgeMode::Remove => String::new(),
+                };
+                replace(format!(" by {}", by), to, false);
+            }
+            if let Some(reason) = &entry.reason {
+                let to = match mode {
+                    PurgeMode::Anonymize => format!(" (reason: {})", anonymized(reason)),
+                    PurgeMode::Remove => String::new(),
+                };
+                replace(format!(" (reason: {})", reason), to, true);
+            }
+        },
+        LogAction::Named => {
+            if let Some(by) = &entry.by {
+                let to = match mode {
+                    PurgeMode::Anonymize => format!(" by {}", anonymized(by)),
+                    PurgeMode::Remove => String::new(),
+                };
+                replace(format!(" by {}", by), to, true);
+            }
+        },
+        LogAction::Denied => {
+            if let Some(by) = entry.by.as_deref().filter(|by| *by != UNNAMED_CLIENT) {
+                let to = match mode {
+                    PurgeMode::Anonymize => anonymized(by),
+                    PurgeMode::Remove => UNNAMED_CLIENT.to_string(),
+                };
+                replace(format!(" to {} (", by), format!(" to {} (", to), false);
+            }
+        },
+        _ => {},
+    }
+    changed.then_some(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_and_reasons_are_kept_hashed_or_left_out() {
+        let config = LogPrivacyConfig { clients: Redaction::Hash, reasons: Redaction::Omit, salt: Some("pepper".to_string()) };
+        let privacy = LogPrivacy::new(&config);
+        let hashed = privacy.client("kitchen-agent").unwrap();
+        assert!(hashed.starts_with(HASHED_PREFIX) && hashed.len() == HASHED_PREFIX.len() + HASH_DIGITS, "{}", hashed);
+        assert_eq!(privacy.client("kitchen-agent"), Some(hashed.clone()));
+        assert_ne!(privacy.client("hallway-agent"), Some(hashed.clone()));
+        assert_eq!(privacy.reason("nobody is home"), None);
+
+        let salted = LogPrivacy::new(&LogPrivacyConfig { salt: Some("salt".to_string()), ..config });
+        assert_ne!(salted.client("kitchen-agent"), Some(hashed));
+        assert_eq!(LogPrivacy::default().reason("movie night").as_deref(), Some("movie night"));
+    }
+
+    #[test]
+    fn test_purge_anonymizes_or_removes_old_clients_and_reasons() {
+        let log = "Lightbulb Activity Log:\n\n\
+            [2025-08-01T10:00:00+00:00] Lightbulb turned ON [movie] by kitchen-agent (reason: film by the fire) seq=1 sig=abc\n\
+            [2025-08-01T11:00:00+00:00] Lightbulb DENIED turn_on_lightbulb to kid-tablet (outside allowed hours: bedtime) seq=2\n\
+            [2025-08-01T12:00:00+00:00] Lightbulb turned OFF seq=3\n\
+            [2025-08-03T10:00:00+00:00] Lightbulb COLOR #ff8800 by kitchen-agent seq=4\n";
+        let before = DateTime::parse_from_rfc3339("2025-08-02T00:00:00Z").unwrap().to_utc();
+        let privacy = LogPrivacy::new(&LogPrivacyConfig { salt: Some("pepper".to_string()), ..LogPrivacyConfig::default() });
+        let purged = purge(log, before, PurgeMode::Remove, &privacy, |line| format!("{} sig=new", line)).unwrap();
+        assert_eq!(purged.entries, 2);
+        let lines: Vec<&str> = purged.log.lines().collect();
+        assert_eq!(lines[0], "Lightbulb Activity Log:");
+        assert_eq!(lines[2], "[2025-08-01T10:00:00+00:00] Lightbulb turned ON [movie] seq=1 sig=new");
+        assert_eq!(lines[3], "[2025-08-01T11:00:00+00:00] Lightbulb DENIED turn_on_lightbulb to an unnamed client (outside allowed hours: bedtime) seq=2 sig=new");
+        assert_eq!(&lines[4..], ["[2025-08-01T12:00:00+00:00] Lightbulb turned OFF seq=3", "[2025-08-03T10:00:00+00:00] Lightbulb COLOR #ff8800 by kitchen-agent seq=4"]);
+        assert_eq!(crate::stats::UsageCounters::from_log(&purged.log).on, crate::stats::UsageCounters::from_log(log).on);
+
+        let anonymized = purge(log, before, PurgeMode::Anonymize, &privacy, str::to_string).unwrap();
+        let (client, reason) = (privacy.hash("kitchen-agent"), privacy.hash("film by the fire"));
+        assert!(anonymized.log.contains(&format!("[movie] by {} (reason: {}) seq=1\n", client, reason)), "{}", anonymized.log);
+        assert!(anonymized.log.contains(&format!("to {} (outside", privacy.hash("kid-tablet"))), "{}", anonymized.log);
+        // A purged log has nothing left to purge
+        assert_eq!(purge(&anonymized.log, before, PurgeMode::Anonymize, &privacy, str::to_string), None);
+        assert_eq!(purge(&purged.log, before, PurgeMode::Remove, &privacy, str::to_string), None);
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rmcp::service::{RxJsonRpcMessage, TxJsonRpcMessage};
+use rmcp::transport::Transport;
+use rmcp::{RoleServer, serve_server};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::task::JoinHandle;
+
+use crate::LightService;
+use crate::clock::{ManualClock, SharedClock};
+
+const DUPLEX_BUFFER: usize = 64 * 1024;
+const MASKED_TIME: &str = "<time>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sender {
+    Client,
+    Server,
+}
+
+// One line of a session recording: a JSON-RPC message and which side sent it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedMessage {
+    pub at: DateTime<Utc>,
+    pub from: Sender,
+    pub message: Value,
+}
+
+// Writes every message passing through `inner` to a JSON Lines file, in the order they happen. The file is
+// written on the blocking pool, so a slow disk delays the recording rather than the session
+pub struct RecordingTransport<T> {
+    inner: T,
+    lines: Option<mpsc::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+    clock: SharedClock,
+}
+
+impl<T> RecordingTransport<T> {
+    pub fn new(inner: T, path: impl AsRef<Path>, clock: SharedClock) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("Failed to create recording: {}", path.display()))?;
+        let (lines, received) = mpsc::channel();
+        let writer = tokio::task::spawn_blocking(move || write_recording(file, received));
+        Ok(Self { inner, lines: Some(lines), writer: Some(writer), clock })
+    }
+
+    fn record(&self, from: Sender, message: &impl Serialize) {
+        let Some(lines) = &self.lines else {
+            return;
+        };
+        let line = serde_json::to_value(message)
+            .and_then(|message| serde_json::to_string(&RecordedMessage { at: self.clock.now(), from, message }));
+        match line {
+            // Fails only once the writer has stopped, which it has already reported
+            Ok(line) => {
+                let _ = lines.send(line);
+            },
+            Err(e) => crate::diagnostic!(Warn, "could not record a message: {}", e),
+        }
+    }
+}
+
+// A failed write stops the recording rather than the session it records
+fn write_recording(file: File, lines: mpsc::Receiver<String>) {
+    let mut file = BufWriter::new(file);
+    while let Ok(line) = lines.recv() {
+        // Everything already queued goes out in one write
+        let written = std::iter::once(line)
+            .chain(lines.try_iter())
+            .try_for_each(|line| writeln!(file, "{}", line))
+            .and_then(|()| file.flush());
+        if let Err(e) = written {
+            crate::diagnostic!(Warn, "stopped recording the session: {:#}", e);
+            return;
+        }
+    }
+}
+
+impl<T: Transport<RoleServer>> Transport<RoleServer> for RecordingTransport<T> {
+    type Error = T::Error;
+
+    fn send(&mut self, item: TxJsonRpcMessage<RoleServer>) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        self.record(Sender::Server, &item);
+        self.inner.send(item)
+    }
+
+    async fn receive(&mut self) -> Option<RxJsonRpcMessage<RoleServer>> {
+        let message = self.inner.receive().await?;
+        self.record(Sender::Client, &message);
+        Some(message)
+    }
+
+    // Waits for the recording to reach the file, so it is complete once the session has ended
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        let result = self.inner.close().await;
+        drop(self.lines.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.await;
+        }
+        result
+    }
+}
+
+pub fn read_recording(path: impl AsRef<Path>) -> anyhow::Result<Vec<RecordedMessage>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("Failed to open recording: {}", path.display()))?;
+    let mut messages = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        messages.push(serde_json::from_str(&line).with_context(|| format!("{}:{}: not a recorded message", path.display(), index + 1))?);
+    }
+    Ok(messages)
+}
+
+// A response that came out differently on replay
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub id: Value,
+    pub method: String,
+    pub expected: Value,
+    pub actual: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayReport {
+    pub responses: usize,
+    pub differences: Vec<Difference>,
+}
+
+impl ReplayReport {
+    pub fn describe(&self) -> String {
+        let mut report = String::new();
+        for difference in &self.differences {
+            report.push_str(&format!("Response {} to {} differs:\n", difference.id, difference.method));
+            report.push_str(&diff_lines(&difference.expected, &difference.actual));
+        }
+        report.push_str(&format!(
+            "Replayed {} responses: {} matched, {} differed",
+            self.responses,
+            self.responses - self.differences.len(),
+            self.differences.len()
+        ));
+        report
+    }
+}
+
+// Sends the recorded client messages to `service` in their original order, waiting wherever the
+// recording shows a response arriving, and compares each response with the recorded one.
+// `clock` should be the service's clock; it is set to each message's recorded time before it is sent.
+// Timestamps are masked before comparing, since they can never match exactly.
+pub async fn replay(recording: &[RecordedMessage], service: LightService, clock: &ManualClock) -> anyhow::Result<ReplayReport> {
+    let (server_side, client_side) = tokio::io::duplex(DUPLEX_BUFFER);
+    // If the server cannot start, the replay fails on the first missing response
+    let server = tokio::spawn(async move {
+        if let Ok(server) = serve_server(service, server_side).await {
+            let _ = server.waiting().await;
+        }
+    });
+    let (read, mut write) = tokio::io::split(client_side);
+    let mut lines = tokio::io::BufReader::new(read).lines();
+
+    let mut methods = HashMap::new();
+    let mut received: HashMap<String, Value> = HashMap::new();
+    let mut report = ReplayReport::default();
+    for entry in recording {
+        match entry.from {
+            Sender::Client => {
+                clock.set(entry.at);
+                if let (Some(id), Some(method)) = (entry.message.get("id"), entry.message["method"].as_str()) {
+                    methods.insert(id.to_string(), method_name(method, &entry.message));
+                }
+                write.write_all(format!("{}\n", entry.message).as_bytes()).await?;
+            },
+            Sender::Server if is_response(&entry.message) => {
+                let id = entry.message["id"].to_string();
+                let actual = loop {
+                    if let Some(actual) = received.remove(&id) {
+                        break actual;
+                    }
+                    let line = lines.next_line().await?.with_context(|| format!("The server closed before responding to {}", id))?;
+                    let message: Value = serde_json::from_str(&line)?;
+                    if is_response(&message) {
+                        received.insert(message["id"].to_string(), message);
+                    }
+                };
+                report.responses += 1;
+                let (expected, actual) = (mask_timestamps(&entry.message), mask_timestamps(&actual));
+                if expected != actual {
+                    let method = methods.get(&id).cloned().unwrap_or_default();
+                    report.differences.push(Difference { id: entry.message["id"].clone(), method, expected, actual });
+                }
+            },
+            // Notifications depend on timing and on other sessions, so they are not compared
+            Sender::Server => {},
+        }
+    }
+    drop(write);
+    server.abort();
+    Ok(report)
+}
+
+fn is_response(message: &Value) -> bool {
+    message.get("id").is_some() && message.get("method").is_none()
+}
+
+// Tool calls are named after the tool, e.g. "tools/call turn_on_lightbulb"
+fn method_name(method: &str, message: &Value) -> String {
+    match message["params"]["name"].as_str() {
+        Some(tool) if method == "tools/call" => format!("{} {}", method, tool),
+        _ => method.to_string(),
+    }
+}
+
+fn mask_timestamps(value: &Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(mask_timestamps_in(text)),
+        Value::Array(items) => Value::Array(items.iter().map(mask_timestamps).collect()),
+        Value::Object(fields) => Value::Object(fields.iter().map(|(key, value)| (key.clone(), mask_timestamps(value))).collect()),
+        other => other.clone(),
+    }
+}
+
+// Replaces RFC 3339 timestamps such as "2025-08-02T14:24:27.652821025+00:00" with a placeholder
+fn mask_timestamps_in(text: &str) -> String {
+    let mut masked = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        masked.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match timestamp_len(rest) {
+            Some(len) => {
+                masked.push_str(MASKED_TIME);
+                rest = &rest[len..];
+            },
+            None => {
+                let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                masked.push_str(&rest[..digits]);
+                rest = &rest[digits..];
+            },
+        }
+    }
+    masked.push_str(rest);
+    masked
+}
+
+fn timestamp_len(text: &str) -> Option<usize> {
+    const SHAPE: &[u8] = b"dddd-dd-ddTdd:dd:dd";
+    let bytes = text.as_bytes();
+    let matches_shape = |at: usize, shape: &[u8]| {
+        bytes.len() >= at + shape.len()
+            && shape.iter().zip(&bytes[at..]).all(|(s, b)| if *s == b'd' { b.is_ascii_digit() } else { b == s })
+    };
+    if !matches_shape(0, SHAPE) {
+        return None;
+    }
+    let mut len = SHAPE.len();
+    if bytes.get(len) == Some(&b'.') {
+        len += 1 + bytes[len + 1..].iter().take_while(|b| b.is_ascii_digit()).count();
+    }
+    if bytes.get(len) == Some(&b'Z') {
+        len += 1;
+    } else if matches!(bytes.get(len), Some(b'+' | b'-')) && matches_shape(len + 1, b"dd:dd") {
+        len += 6;
+    }
+    Some(len)
+}
+
+// The pretty-printed lines that differ, as "-" for the recording and "+" for the replay
+fn diff_lines(expected: &Value, actual: &Value) -> String {
+    let pretty = |value: &Value| serde_json::to_string_pretty(value).unwrap_or_default();
+    let (expected, actual) = (pretty(expected), pretty(actual));
+    let (expected, actual): (Vec<&str>, Vec<&str>) = (expected.lines().collect(), actual.lines().collect());
+    let mut diff = String::new();
+    for index in 0..expected.len().max(actual.len()) {
+        let (old, new) = (expected.get(index), actual.get(index));
+        if old != new {
+            if let Some(old) = old {
+                diff.push_str(&format!("- {}\n", old));
+            }
+            if let Some(new) = new {
+                diff.push_str(&format!("+ {}\n", new));
+            }
+        }
+    }
+    diff
+}
+
+#[cfg(all(test, feature = "core"))]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeDelta;
+    use rmcp::transport::async_rw::AsyncRwTransport;
+    use serde_json::json;
+
+    use super::*;
+    use crate::logger::InMemoryLogger;
+
+    fn start() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2025-08-02T14:00:00Z").unwrap().with_timezone(&Utc)
+    }
+
+    fn service(clock: &ManualClock) -> LightService {
+        LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build()
+    }
+
+    fn session() -> Vec<Value> {
+        vec![
+            json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {
+                "protocolVersion": "2025-03-26", "capabilities": {}, "clientInfo": { "name": "replay-test", "version": "1" } } }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": { "name": "turn_on_lightbulb", "arguments": {} } }),
+            json!({ "jsonrpc": "2.0", "id": 2, "method": "resources/read", "params": { "uri": "lightbulb://log" } }),
+        ]
+    }
+
+    // Plays `session` as a client would through a recording transport, writing the recording to `path`
+    async fn record(path: &Path, clock: &ManualClock) {
+        let (server_side, client_side) = tokio::io::duplex(DUPLEX_BUFFER);
+        let (read, write) = tokio::io::split(server_side);
+        let transport = RecordingTransport::new(AsyncRwTransport::new_server(read, write), path, Arc::new(clock.clone())).unwrap();
+        let server = service(clock);
+        let server = tokio::spawn(async move { serve_server(server, transport).await.unwrap().waiting().await });
+        let (read, mut write) = tokio::io::split(client_side);
+        let mut lines = tokio::io::BufReader::new(read).lines();
+        for message in session() {
+            clock.advance(TimeDelta::seconds(5));
+            write.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+            if message.get("id").is_some() {
+                lines.next_line().await.unwrap().unwrap();
+            }
+        }
+        // The server only sees the end of the stream once both halves are gone
+        drop((write, lines));
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recorded_session_replays_without_differences() {
+        let path = std::env::temp_dir().join(format!("lightbulb-recording-{}.jsonl", std::process::id()));
+        let clock = ManualClock::new(start());
+        record(&path, &clock).await;
+        let recording = read_recording(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(recording.iter().filter(|entry| entry.from == Sender::Client).count(), 4);
+        assert_eq!(recording[0].at, start() + TimeDelta::seconds(5));
+
+        let clock = ManualClock::new(start());
+        let report = replay(&recording, service(&clock), &clock).await.unwrap();
+        assert_eq!((report.responses, report.differences.len()), (3, 0), "{}", report.describe());
+
+        // The same session against a bulb that is already on answers the first call differently
+        let clock = ManualClock::new(start());
+        let service = service(&clock);
+        service.light().set_power(crate::model::PowerState::On).await.unwrap();
+        let report = replay(&recording, service, &clock).await.unwrap();
+        let methods: Vec<&str> = report.differences.iter().map(|difference| difference.method.as_str()).collect();
+        assert_eq!(methods, ["tools/call turn_on_lightbulb", "resources/read"]);
+        assert!(report.describe().ends_with("Replayed 3 responses: 1 matched, 2 differed"));
+    }
+
+    #[test]
+    fn test_timestamps_are_masked() {
+        assert_eq!(
+            mask_timestamps_in("[2025-08-02T14:24:27.652821025+00:00] Lightbulb turned ON, next at 2025-08-02T15:00:00Z"),
+            "[<time>] Lightbulb turned ON, next at <time>"
+        );
+        assert_eq!(mask_timestamps_in("version 2025, sequence 12"), "version 2025, sequence 12");
+        assert_eq!(mask_timestamps_in("ends 2025-08-02T14:24"), "ends 2025-08-02T14:24");
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::backend::{LightBackend, SimulatedBackend};
+use crate::config::BackendConfig;
+use crate::effects::{EffectStep, PRESETS};
+
+pub type BackendOptions = serde_json::Map<String, serde_json::Value>;
+pub type BackendFactory = Box<dyn Fn(&BackendOptions) -> anyhow::Result<Box<dyn LightBackend + Send + Sync>> + Send + Sync>;
+// The steps of an effect run lasting `duration`, each about `period`, with `seed` picking any randomness
+pub type EffectFactory = Box<dyn Fn(Duration, Duration, u64) -> Vec<EffectStep> + Send + Sync>;
+
+// Registration point for backend drivers, so embedders can add their own without forking the crate
+pub struct BackendRegistry {
+    factories: BTreeMap<String, BackendFactory>,
+}
+
+impl BackendRegistry {
+    pub fn empty() -> Self {
+        Self { factories: BTreeMap::new() }
+    }
+
+    // Registry pre-populated with the drivers that ship with this crate
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::empty();
+        registry.register("simulated", |options: &BackendOptions| {
+            Ok(Box::new(SimulatedBackend::from_options(options)?) as Box<dyn LightBackend + Send + Sync>)
+        });
+        #[cfg(feature = "hue")]
+        registry.register("hue", |options: &BackendOptions| {
+            Ok(Box::new(crate::hue::HueBackend::from_options(options)?) as Box<dyn LightBackend + Send + Sync>)
+        });
+        registry
+    }
+
+    // Registers (or replaces) the factory used for backends of type `name`
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&BackendOptions) -> anyhow::Result<Box<dyn LightBackend + Send + Sync>> + Send + Sync + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.keys().map(String::as_str).collect()
+    }
+
+    pub fn create(&self, config: &BackendConfig) -> anyhow::Result<Box<dyn LightBackend + Send + Sync>> {
+        let factory = self.factories.get(&config.kind).ok_or_else(|| {
+            anyhow::anyhow!("Unknown backend type '{}' (available: {})", config.kind, self.names().join(", "))
+        })?;
+        factory(&config.options)
+    }
+}
+
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
+
+// An effect start_effect runs by name, with the step length it uses unless given a period
+pub struct RegisteredEffect {
+    pub name: String,
+    pub description: String,
+    pub period: Duration,
+    factory: EffectFactory,
+}
+
+impl RegisteredEffect {
+    pub fn steps(&self, duration: Duration, period: Duration, seed: u64) -> Vec<EffectStep> {
+        (self.factory)(duration, period, seed)
+    }
+}
+
+// Registration point for the effects start_effect runs, so embedders can add their own beside the presets.
+// Effects are listed in the order they were first registered.
+pub struct EffectRegistry {
+    effects: Vec<RegisteredEffect>,
+}
+
+impl EffectRegistry {
+    pub fn empty() -> Self {
+        Self { effects: Vec::new() }
+    }
+
+    // Registry pre-populated with the presets that ship with this crate
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::empty();
+        for preset in PRESETS {
+            registry.register(preset.name, preset.description, preset.period, |duration, period, seed| preset.steps(duration, period, seed));
+        }
+        registry
+    }
+
+    // Registers (or replaces) the effect called `name`, which must be a lowercase tag to be picked by start_effect
+    pub fn register<F>(&mut self, name: &str, description: &str, period: Duration, factory: F)
+    where
+        F: Fn(Duration, Duration, u64) -> Vec<EffectStep> + Send + Sync + 'static,
+    {
+        let effect = RegisteredEffect { name: name.to_string(), description: description.to_string(), period, factory: Box::new(factory) };
+        match self.effects.iter_mut().find(|registered| registered.name == name) {
+            Some(registered) => *registered = effect,
+            None => self.effects.push(effect),
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.effects.iter().map(|effect| effect.name.as_str()).collect()
+    }
+
+    pub fn all(&self) -> &[RegisteredEffect] {
+        &self.effects
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredEffect> {
+        self.effects.iter().find(|effect| effect.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn complete(&self, prefix: &str) -> Vec<&str> {
+        let prefix = prefix.trim().to_ascii_lowercase();
+        self.names().into_iter().filter(|name| name.starts_with(&prefix)).collect()
+    }
+}
+
+impl Default for EffectRegistry {
+    fn default() -> Self {
+        Self::with_builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PowerState;
+
+    struct FixedBackend(PowerState);
+
+    #[async_trait::async_trait]
+    impl LightBackend for FixedBackend {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        async fn set_power(&mut self, _state: PowerState) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn power(&self) -> anyhow::Result<PowerState> {
+            Ok(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_custom_backend() {
+        let mut registry = BackendRegistry::with_builtin();
+        registry.register("fixed", |options: &BackendOptions| {
+            let on = options.get("on").and_then(|value| value.as_bool()).unwrap_or(false);
+            Ok(Box::new(FixedBackend(on.into())) as Box<dyn LightBackend + Send + Sync>)
+        });
+        let builtin = if cfg!(feature = "hue") { vec!["fixed", "hue", "simulated"] } else { vec!["fixed", "simulated"] };
+        assert_eq!(registry.names(), builtin);
+
+        let mut config = BackendConfig { kind: "fixed".to_string(), ..Default::default() };
+        config.options.insert("on".to_string(), serde_json::Value::Bool(true));
+        let backend = registry.create(&config).unwrap();
+        assert_eq!(backend.name(), "fixed");
+        assert_eq!(backend.power().await.unwrap(), PowerState::On);
+    }
+
+    #[test]
+    fn test_register_custom_effect() {
+        let mut registry = EffectRegistry::with_builtin();
+        registry.register("pulse", "Slow breathing", Duration::from_secs(2), |duration, period, _| {
+            let count = (duration.as_millis() / period.as_millis()) as u8;
+            (0..count).map(|i| EffectStep::new(PowerState::On, period).with_brightness(if i % 2 == 0 { 100 } else { 30 })).collect()
+        });
+        assert_eq!(registry.names(), ["halloween", "christmas", "new_year", "pulse"]);
+        assert_eq!(registry.complete("p"), ["pulse"]);
+
+        let pulse = registry.get(" Pulse ").unwrap();
+        let brightness: Vec<Option<u8>> = pulse.steps(Duration::from_secs(6), pulse.period, 0).iter().map(|step| step.brightness).collect();
+        assert_eq!(brightness, [Some(100), Some(30), Some(100)]);
+
+        registry.register("christmas", "Red only", Duration::from_secs(1), |_, period, _| vec![EffectStep::new(PowerState::On, period)]);
+        assert_eq!(registry.names(), ["halloween", "christmas", "new_year", "pulse"]);
+        assert_eq!(registry.get("christmas").unwrap().description, "Red only");
+        assert!(registry.get("easter").is_none() && EffectRegistry::empty().names().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_backend_is_rejected() {
+        let registry = BackendRegistry::with_builtin();
+        let config = BackendConfig { kind: "zigbee".to_string(), ..Default::default() };
+        let error = registry.create(&config).err().expect("unknown backend should fail");
+        let available = if cfg!(feature = "hue") { "available: hue, simulated" } else { "available: simulated" };
+        assert!(error.to_string().contains(available));
+    }
+}
@@ -0,0 +1,166 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rmcp::model::{CallToolResult, Content, ErrorCode, ErrorData};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::error::{LightError, to_json};
+use crate::model::Color;
+
+// Side of the square color swatch, in pixels
+const SWATCH_SIZE: usize = 32;
+
+// A tool's answer as a sentence for the model, then the same answer as JSON for clients to parse
+pub fn json<T: Serialize + ?Sized>(summary: impl Into<String>, value: &T) -> Result<CallToolResult, ErrorData> {
+    Ok(CallToolResult::success(vec![Content::text(summary), Content::text(to_json(value)?)]))
+}
+
+pub fn text(text: impl Into<String>) -> CallToolResult {
+    CallToolResult::success(vec![Content::text(text)])
+}
+
+// A tool's answer with a swatch of the color the bulb shows, for clients that display images
+pub fn with_swatch(text: impl Into<String>, color: Color) -> CallToolResult {
+    CallToolResult::success(vec![Content::text(text), swatch(color)])
+}
+
+pub fn swatch(color: Color) -> Content {
+    Content::image(BASE64.encode(swatch_png(color)), "image/png")
+}
+
+// A failed call as the client sees it: isError, with the message for the model and the error's data as JSON
+pub fn failure(error: ErrorData) -> CallToolResult {
+    let data = error.data.unwrap_or_else(|| json!({ "code": "INTERNAL_ERROR" }));
+    CallToolResult::error(vec![Content::text(error.message), Content::text(data.to_string())])
+}
+
+// How a tool call ends on the wire. Arguments that did not parse stay a JSON-RPC error, as the tool never ran;
+// a failure of the tool itself is an isError result the model can read and recover from.
+pub fn outcome(result: Result<CallToolResult, ErrorData>) -> Result<CallToolResult, ErrorData> {
+    match result {
+        Err(mut error) if error.data.is_none() && error.code == ErrorCode::INVALID_PARAMS => {
+            error.data = Some(json!({ "code": "INVALID_PARAMETER" }));
+            Err(error)
+        },
+        Err(error) => Ok(failure(error)),
+        ok => ok,
+    }
+}
+
+// The text of a result, one content per line; images and embedded resources are skipped
+pub fn text_of(result: &CallToolResult) -> String {
+    result.content.iter().flatten().filter_map(|content| Some(content.as_text()?.text.as_str())).collect::<Vec<_>>().join("\n")
+}
+
+// The sentence a result opens with, without the JSON that may follow it
+pub fn summary_of(result: &CallToolResult) -> String {
+    result.content.iter().flatten().find_map(|content| Some(content.as_text()?.text.clone())).unwrap_or_default()
+}
+
+// The JSON a tool answered with, the last text content that parses as JSON
+pub fn json_of(result: &CallToolResult) -> Option<Value> {
+    result.content.iter().flatten().rev().find_map(|content| serde_json::from_str(&content.as_text()?.text).ok())
+}
+
+// The error behind an isError result, for clients of a lightbulb server; a result from another server without
+// our data becomes TOOL_FAILED
+pub fn error_of(result: &CallToolResult) -> Option<ErrorData> {
+    if result.is_error != Some(true) {
+        return None;
+    }
+    let texts: Vec<&str> = result.content.iter().flatten().filter_map(|content| Some(content.as_text()?.text.as_str())).collect();
+    let error = match texts.as_slice() {
+        [message, data] => match serde_json::from_str::<Value>(data) {
+            Ok(data) if data["code"].is_string() => ErrorData::new(ErrorCode::INTERNAL_ERROR, message.to_string(), Some(data)),
+            _ => LightError::ToolFailed(texts.join("\n")).into(),
+        },
+        _ => LightError::ToolFailed(texts.join("\n")).into(),
+    };
+    Some(error)
+}
+
+// A square of `color` as a PNG, its pixels stored uncompressed so no image library is needed
+fn swatch_png(color: Color) -> Vec<u8> {
+    // Each row starts with its filter type, none
+    let row: Vec<u8> = std::iter::once(0).chain([color.r, color.g, color.b].repeat(SWATCH_SIZE)).collect();
+    let pixels = row.repeat(SWATCH_SIZE);
+    // A zlib stream of one stored deflate block, which holds up to 64 KiB
+    let mut zlib = vec![0x78, 0x01, 0x01];
+    let len = pixels.len() as u16;
+    zlib.extend_from_slice(&len.to_le_bytes());
+    zlib.extend_from_slice(&(!len).to_le_bytes());
+    zlib.extend_from_slice(&pixels);
+    zlib.extend_from_slice(&adler32(&pixels).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(SWATCH_SIZE as u32).to_be_bytes());
+    header.extend_from_slice(&(SWATCH_SIZE as u32).to_be_bytes());
+    // 8-bit RGB, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    for (kind, data) in [(b"IHDR", header.as_slice()), (b"IDAT", zlib.as_slice()), (b"IEND", &[])] {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        let start = png.len();
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        let crc = crc32(&png[start..]);
+        png.extend_from_slice(&crc.to_be_bytes());
+    }
+    png
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_round_trip_through_a_result() {
+        let result = outcome(Err(LightError::EffectAlreadyRunning("party".to_string()).into())).unwrap();
+        assert_eq!(result.is_error, Some(true));
+        assert_eq!(text_of(&result), "The effect 'party' is already running\n{\"code\":\"EFFECT_ALREADY_RUNNING\",\"effect\":\"party\"}");
+        let error = error_of(&result).unwrap();
+        assert_eq!((error.message.as_ref(), error.data.unwrap()["effect"].as_str()), ("The effect 'party' is already running", Some("party")));
+
+        let bad_arguments = outcome(Err(ErrorData::invalid_params("failed to deserialize parameters", None))).unwrap_err();
+        assert_eq!(bad_arguments.data.unwrap()["code"], "INVALID_PARAMETER");
+        let foreign = CallToolResult::error(vec![Content::text("disk full")]);
+        assert_eq!(error_of(&foreign).unwrap().data.unwrap()["code"], "TOOL_FAILED");
+        assert!(error_of(&CallToolResult::success(vec![Content::text("ok")])).is_none());
+    }
+
+    #[test]
+    fn test_json_replies_carry_a_summary_and_a_swatch_is_a_png() {
+        let result = json("The lightbulb is ON", &json!({ "power": "ON" })).unwrap();
+        assert_eq!(json_of(&result), Some(json!({ "power": "ON" })));
+        assert!(text_of(&result).starts_with("The lightbulb is ON\n{"));
+
+        let swatch = with_swatch("Lightbulb color set to #ff8800", Color { r: 255, g: 136, b: 0 });
+        let image = swatch.content.as_ref().unwrap()[1].as_image().unwrap().clone();
+        let png = BASE64.decode(&image.data).unwrap();
+        assert_eq!((image.mime_type.as_str(), &png[1..4], &png[12..16]), ("image/png", &b"PNG"[..], &b"IHDR"[..]));
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert!(png.ends_with(&[0xae, 0x42, 0x60, 0x82]));
+        assert!(png.windows(3).any(|pixel| pixel == [255, 136, 0]));
+    }
+}
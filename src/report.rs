@@ -0,0 +1,266 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use serde::Serialize;
+
+use crate::clock::format_duration;
+use crate::stats::{Metric, UsageCounters, compute, day_start};
+
+// Days shown in the daily chart, ending today
+const REPORT_DAYS: i64 = 7;
+// Width of the longest bar in a Markdown chart, in block characters
+const BAR_WIDTH: usize = 20;
+const NO_ACTIVITY: &str = "No activity recorded yet.";
+
+// One day of the daily chart
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReportDay {
+    pub day: NaiveDate,
+    pub changes: usize,
+    pub on_secs: i64,
+}
+
+// The usage summary laid out for clients that render Markdown or HTML, with the tables and charts both share;
+// serialized, it is the JSON summary
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+    pub status: &'static str,
+    pub generated: DateTime<Utc>,
+    pub total: usize,
+    pub on: usize,
+    pub off: usize,
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub recent: Vec<String>,
+    pub days: Vec<ReportDay>,
+    // Power changes per hour of the day (UTC), as in get_statistics
+    pub hours: [usize; 24],
+}
+
+impl Report {
+    pub fn new(usage: &UsageCounters, status: &'static str, now: DateTime<Utc>) -> Self {
+        let today = now.date_naive();
+        let days = (0..REPORT_DAYS)
+            .rev()
+            .map(|back| {
+                let day = today - TimeDelta::days(back);
+                let to = day_start(day + TimeDelta::days(1)).min(now);
+                let statistics = compute(&usage.events, &usage.days, &[Metric::Counts, Metric::OnTime], Some(day_start(day)), to, 0.0, None);
+                ReportDay {
+                    day,
+                    changes: statistics.counts.map_or(0, |counts| counts.total),
+                    on_secs: statistics.on_time_secs.unwrap_or(0),
+                }
+            })
+            .collect();
+        let hours = compute(&usage.events, &usage.days, &[Metric::Histogram], None, now, 0.0, None).histogram.unwrap_or_default();
+        Self {
+            status,
+            generated: now,
+            total: usage.total,
+            on: usage.on,
+            off: usage.off,
+            first: usage.first.clone(),
+            last: usage.last.clone(),
+            recent: usage.recent.iter().cloned().collect(),
+            days,
+            hours,
+        }
+    }
+
+    fn share(&self, count: usize) -> f64 {
+        if self.total == 0 { 0.0 } else { count as f64 / self.total as f64 * 100.0 }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut text = String::from("# Lightbulb Usage Report\n\n");
+        if self.total == 0 {
+            text.push_str(NO_ACTIVITY);
+            text.push('\n');
+            return text;
+        }
+        text.push_str("| Metric | Value |\n|---|---|\n");
+        for (label, value) in self.facts() {
+            text.push_str(&format!("| {} | {} |\n", label, value));
+        }
+        text.push_str(&format!(
+            "\n**ON** {} {:.1}% · **OFF** {} {:.1}%\n",
+            bar(self.on, self.total),
+            self.share(self.on),
+            bar(self.off, self.total),
+            self.share(self.off)
+        ));
+
+        text.push_str(&format!("\n## Last {} days\n\n| Day | Changes | Time on | |\n|---|---:|---:|---|\n", REPORT_DAYS));
+        let longest = self.days.iter().map(|day| day.on_secs).max().unwrap_or(0).max(1);
+        for day in &self.days {
+            let chart = bar(day.on_secs as usize, longest as usize);
+            text.push_str(&format!("| {} | {} | {} | `{}` |\n", day.day, day.changes, format_duration(day.on_secs), chart));
+        }
+
+        text.push_str("\n## Changes by hour (UTC)\n\n| Hour | Changes | |\n|---|---:|---|\n");
+        let busiest = self.hours.iter().copied().max().unwrap_or(0).max(1);
+        for (hour, changes) in self.hours.iter().enumerate().filter(|(_, changes)| **changes > 0) {
+            text.push_str(&format!("| {:02}:00 | {} | `{}` |\n", hour, changes, bar(*changes, busiest)));
+        }
+
+        text.push_str(&format!("\n## Recent activity\n\n```text\n{}\n```\n", self.recent.join("\n")));
+        text
+    }
+
+    pub fn html(&self) -> String {
+        let mut body = String::new();
+        if self.total == 0 {
+            body.push_str(&format!("<p>{}</p>\n", NO_ACTIVITY));
+        } else {
+            body.push_str("<table>\n");
+            for (label, value) in self.facts() {
+                body.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape_html(&value)));
+            }
+            body.push_str("</table>\n");
+            body.push_str(&format!(
+                "<div class=\"share\"><div class=\"on\" style=\"width:{:.1}%\">ON {:.1}%</div><div class=\"off\" style=\"width:{:.1}%\">OFF {:.1}%</div></div>\n",
+                self.share(self.on),
+                self.share(self.on),
+                self.share(self.off),
+                self.share(self.off)
+            ));
+
+            body.push_str(&format!("<h2>Last {} days</h2>\n<table>\n<tr><th>Day</th><th>Changes</th><th>Time on</th><th></th></tr>\n", REPORT_DAYS));
+            let longest = self.days.iter().map(|day| day.on_secs).max().unwrap_or(0).max(1);
+            for day in &self.days {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"chart\">{}</td></tr>\n",
+                    day.day,
+                    day.changes,
+                    format_duration(day.on_secs),
+                    html_bar(day.on_secs as usize, longest as usize)
+                ));
+            }
+            body.push_str("</table>\n");
+
+            body.push_str("<h2>Changes by hour (UTC)</h2>\n");
+            body.push_str(&hourly_chart(&self.hours));
+
+            body.push_str("<h2>Recent activity</h2>\n<ul>\n");
+            for line in &self.recent {
+                body.push_str(&format!("<li><code>{}</code></li>\n", escape_html(line)));
+            }
+            body.push_str("</ul>\n");
+        }
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Lightbulb Usage Report</title>\n<style>\n{}</style>\n</head>\n<body>\n<h1>Lightbulb Usage Report</h1>\n{}</body>\n</html>\n",
+            REPORT_CSS, body
+        )
+    }
+
+    fn facts(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("Current status", self.status.to_string()),
+            ("Total actions", self.total.to_string()),
+            ("Turn ON actions", format!("{} ({:.1}%)", self.on, self.share(self.on))),
+            ("Turn OFF actions", format!("{} ({:.1}%)", self.off, self.share(self.off))),
+            ("First action", self.first.clone().unwrap_or("N/A".to_string())),
+            ("Last action", self.last.clone().unwrap_or("N/A".to_string())),
+            ("Generated", self.generated.to_rfc3339()),
+        ]
+    }
+}
+
+const REPORT_CSS: &str = "body { font-family: sans-serif; max-width: 48em; }
+th, td { text-align: left; padding: 0.2em 0.6em; }
+.share { display: flex; margin: 1em 0; }
+.share div { padding: 0.2em 0; white-space: nowrap; overflow: hidden; }
+.on { background: #ffd54f; }
+.off { background: #90a4ae; }
+.chart { width: 50%; }
+.bar { background: #ffb300; height: 0.8em; }
+";
+
+// A bar `value / max` of the chart width long, at least one block for any nonzero value
+fn bar(value: usize, max: usize) -> String {
+    let blocks = match value {
+        0 => 0,
+        _ => (value * BAR_WIDTH).div_ceil(max.max(1)).clamp(1, BAR_WIDTH),
+    };
+    "█".repeat(blocks)
+}
+
+fn html_bar(value: usize, max: usize) -> String {
+    format!("<div class=\"bar\" style=\"width:{:.1}%\"></div>", value as f64 / max.max(1) as f64 * 100.0)
+}
+
+// Twenty-four columns as an inline SVG, so the chart needs no script or stylesheet to draw
+fn hourly_chart(hours: &[usize; 24]) -> String {
+    let busiest = hours.iter().copied().max().unwrap_or(0).max(1);
+    let mut svg = String::from("<svg viewBox=\"0 0 240 110\" width=\"480\" height=\"220\" role=\"img\" aria-label=\"Power changes by hour\">\n");
+    for (hour, changes) in hours.iter().enumerate() {
+        let height = *changes as f64 / busiest as f64 * 90.0;
+        svg.push_str(&format!(
+            "<rect x=\"{}\" y=\"{:.1}\" width=\"8\" height=\"{:.1}\" fill=\"#ffb300\"><title>{:02}:00 {} changes</title></rect>\n",
+            hour * 10 + 1,
+            90.0 - height,
+            height,
+            hour,
+            changes
+        ));
+    }
+    for hour in (0..24).step_by(6) {
+        svg.push_str(&format!("<text x=\"{}\" y=\"105\" font-size=\"9\">{:02}</text>\n", hour * 10, hour));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        let log = "[2025-08-01T09:00:00+00:00] Lightbulb turned ON\n\
+                   [2025-08-01T11:00:00+00:00] Lightbulb turned OFF by <script> (reason: a & b)\n\
+                   [2025-08-02T09:30:00+00:00] Lightbulb turned ON\n";
+        let now = DateTime::parse_from_rfc3339("2025-08-02T10:00:00Z").unwrap().with_timezone(&Utc);
+        Report::new(&UsageCounters::from_log(log), "ON", now)
+    }
+
+    #[test]
+    fn test_report_charts_days_and_hours() {
+        let report = report();
+        assert_eq!(report.days.len(), 7);
+        let (yesterday, today) = (&report.days[5], &report.days[6]);
+        assert_eq!((yesterday.changes, yesterday.on_secs, today.changes, today.on_secs), (2, 7200, 1, 1800));
+        assert_eq!((report.hours[9], report.hours[11]), (2, 1));
+
+        let markdown = report.markdown();
+        assert!(markdown.contains("| Turn ON actions | 2 (66.7%) |"), "{}", markdown);
+        assert!(markdown.contains("| 2025-08-01 | 2 | 2h 0m | `████████████████████` |"), "{}", markdown);
+        assert!(markdown.contains("| 2025-08-02 | 1 | 30m 0s | `█████` |"), "{}", markdown);
+        assert!(markdown.contains("| 09:00 | 2 |"), "{}", markdown);
+        assert!(!markdown.contains("| 10:00 |"), "{}", markdown);
+    }
+
+    #[test]
+    fn test_html_report_escapes_log_entries() {
+        let html = report().html();
+        assert!(html.contains("by &lt;script&gt; (reason: a &amp; b)"), "{}", html);
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<svg"));
+        let empty = Report::new(&UsageCounters::default(), "OFF", Utc::now());
+        assert!(empty.html().contains("<p>No activity recorded yet.</p>"));
+        assert_eq!(empty.markdown(), "# Lightbulb Usage Report\n\nNo activity recorded yet.\n");
+    }
+}
@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, FromRef, Query, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use chrono::DateTime;
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast;
+
+use crate::actor::{LightHandle, PowerChange};
+use crate::diagnostics::{CheckStatus, DiagnosticCheck, DiagnosticsReport, build_readiness_report};
+use crate::error::{LightError, panic_message};
+use crate::events::LightEvent;
+use crate::logger::parse_log_timestamp;
+use crate::model::PowerState;
+use crate::state::StateSnapshot;
+use crate::stats::{DEFAULT_WATTS, Metric, Statistics, compute, parse_power_events};
+use crate::tags::normalize_tag;
+
+const DASHBOARD: &str = include_str!("dashboard.html");
+const DASHBOARD_METRICS: [Metric; 4] = [Metric::Counts, Metric::OnTime, Metric::Energy, Metric::Histogram];
+// Clients without an open stream are forgotten after this long without a request
+const CLIENT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+// Probes fail rather than hang when the light actor is stuck behind a slow command
+const LIVENESS_TIMEOUT: Duration = Duration::from_secs(2);
+const READINESS_TIMEOUT: Duration = Duration::from_secs(5);
+
+// One peer address seen by the REST server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,
+    pub last_seen: Instant,
+    pub requests: u64,
+    // Open /events WebSocket streams
+    pub streams: usize,
+}
+
+// Who has been talking to the REST server recently, for monitoring
+#[derive(Debug, Clone, Default)]
+pub struct ClientRegistry {
+    clients: Arc<Mutex<HashMap<SocketAddr, ClientInfo>>>,
+}
+
+impl ClientRegistry {
+    // Streaming clients first, then the most recently seen
+    pub fn list(&self) -> Vec<ClientInfo> {
+        let mut clients = self.lock();
+        clients.retain(|_, client| client.streams > 0 || client.last_seen.elapsed() < CLIENT_IDLE_TIMEOUT);
+        let mut list: Vec<ClientInfo> = clients.values().cloned().collect();
+        list.sort_by(|a, b| (b.streams > 0).cmp(&(a.streams > 0)).then(b.last_seen.cmp(&a.last_seen)));
+        list
+    }
+
+    fn seen(&self, addr: SocketAddr) {
+        let mut clients = self.lock();
+        let client = clients.entry(addr).or_insert(ClientInfo { addr, last_seen: Instant::now(), requests: 0, streams: 0 });
+        client.last_seen = Instant::now();
+        client.requests += 1;
+    }
+
+    fn open_stream(&self, addr: SocketAddr) -> StreamGuard {
+        crate::diagnostic!(Info, "{} opened an /events stream", addr);
+        if let Some(client) = self.lock().get_mut(&addr) {
+            client.streams += 1;
+        }
+        StreamGuard { clients: self.clone(), addr }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<SocketAddr, ClientInfo>> {
+        self.clients.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Marks a stream closed however the connection ends
+struct StreamGuard {
+    clients: ClientRegistry,
+    addr: SocketAddr,
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        crate::diagnostic!(Info, "{} closed its /events stream", self.addr);
+        if let Some(client) = self.clients.lock().get_mut(&self.addr) {
+            client.streams = client.streams.saturating_sub(1);
+            client.last_seen = Instant::now();
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ApiState {
+    light: LightHandle,
+    clients: ClientRegistry,
+}
+
+impl FromRef<ApiState> for LightHandle {
+    fn from_ref(state: &ApiState) -> Self {
+        state.light.clone()
+    }
+}
+
+impl FromRef<ApiState> for ClientRegistry {
+    fn from_ref(state: &ApiState) -> Self {
+        state.clients.clone()
+    }
+}
+
+// Plain HTTP access to the same light the MCP tools drive, for curl and dashboards
+pub fn router(light: LightHandle, clients: ClientRegistry) -> Router {
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/state", get(state))
+        .route("/on", post(turn_on))
+        .route("/off", post(turn_off))
+        .route("/log", get(log))
+        .route("/statistics", get(statistics))
+        .route("/events", get(events))
+        .layer(middleware::from_fn_with_state(clients.clone(), track_client))
+        // Added after the layer, so a container's probes do not show up as clients
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/openapi.json", get(openapi))
+        .layer(middleware::from_fn(contain_panics))
+        .with_state(ApiState { light, clients })
+}
+
+pub async fn serve(listener: TcpListener, light: LightHandle, clients: ClientRegistry) -> std::io::Result<()> {
+    let app = router(light, clients).into_make_service_with_connect_info::<SocketAddr>();
+    axum::serve(listener, app).await
+}
+
+async fn track_client(State(clients): State<ClientRegistry>, request: Request, next: Next) -> Response {
+    if let Some(ConnectInfo(addr)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        clients.seen(*addr);
+    }
+    next.run(request).await
+}
+
+// A handler that panics answers with an internal error instead of dropping the connection
+async fn contain_panics(request: Request, next: Next) -> Response {
+    match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => response,
+        Err(panic) => ApiError(LightError::Internal(panic_message(panic.as_ref()))).into_response(),
+    }
+}
+
+// Errors carry the same stable codes and context as MCP errors' data, with the message beside them:
+// {"code": ..., "message": ..., ...}
+struct ApiError(LightError);
+
+impl From<LightError> for ApiError {
+    fn from(error: LightError) -> Self {
+        Self(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self.0 {
+            LightError::InvalidParameter(_) => StatusCode::BAD_REQUEST,
+            LightError::InvalidTransition(_) => StatusCode::CONFLICT,
+            LightError::BackendUnreachable(_) => StatusCode::BAD_GATEWAY,
+            LightError::ActorStopped => StatusCode::SERVICE_UNAVAILABLE,
+            LightError::ToggleTooSoon { .. } => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let mut body = self.0.data();
+        body["message"] = self.0.message().into();
+        let mut response = (status, Json(body)).into_response();
+        if let LightError::ToggleTooSoon { retry_after_ms, .. } = self.0 {
+            response.headers_mut().insert(header::RETRY_AFTER, retry_after_ms.div_ceil(1000).into());
+        }
+        response
+    }
+}
+
+#[derive(Debug, Serialize, schemars::JsonSchema)]
+pub(crate) struct PowerResponse {
+    sequence: u64,
+    changed: bool,
+    // Held in the offline queue until the bulb answers again; only present when true
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    queued: bool,
+}
+
+// A single page built on the endpoints below, so it shows exactly what other clients see
+async fn dashboard() -> Html<&'static str> {
+    Html(DASHBOARD)
+}
+
+// Every get_statistics metric over the whole log, for the dashboard's charts
+async fn statistics(State(light): State<LightHandle>) -> Result<Json<Statistics>, ApiError> {
+    let usage = light.usage().await?;
+    Ok(Json(compute(&usage.events, &usage.days, &DASHBOARD_METRICS, None, light.clock().now(), DEFAULT_WATTS, None)))
+}
+
+async fn state(State(light): State<LightHandle>) -> Result<Json<StateSnapshot>, ApiError> {
+    Ok(Json(light.snapshot().await?))
+}
+
+async fn turn_on(State(light): State<LightHandle>, peer: Option<Extension<ConnectInfo<SocketAddr>>>) -> Result<Json<PowerResponse>, ApiError> {
+    set_power(&light, peer, PowerState::On).await
+}
+
+async fn turn_off(State(light): State<LightHandle>, peer: Option<Extension<ConnectInfo<SocketAddr>>>) -> Result<Json<PowerResponse>, ApiError> {
+    set_power(&light, peer, PowerState::Off).await
+}
+
+// Changes are attributed to the peer address, since REST requests carry no client name
+async fn set_power(
+    light: &LightHandle,
+    peer: Option<Extension<ConnectInfo<SocketAddr>>>,
+    target: PowerState,
+) -> Result<Json<PowerResponse>, ApiError> {
+    let who = peer.map_or("rest".to_string(), |Extension(ConnectInfo(addr))| format!("rest {}", addr));
+    let result = light.acting_as(who).set_power(target).await?;
+    Ok(Json(PowerResponse { sequence: result.sequence, changed: result.outcome == PowerChange::Changed, queued: result.outcome == PowerChange::Queued }))
+}
+
+#[derive(Debug, Deserialize)]
+struct LogQuery {
+    // RFC 3339 timestamp; only entries at or after it are returned
+    since: Option<String>,
+    // Only power changes logged with this tag are returned
+    tag: Option<String>,
+}
+
+async fn log(State(light): State<LightHandle>, Query(query): Query<LogQuery>) -> Result<String, ApiError> {
+    let since = query
+        .since
+        .map(|since| {
+            DateTime::parse_from_rfc3339(&since)
+                .map_err(|_| LightError::InvalidParameter(format!("since must be an RFC 3339 timestamp, got '{}'", since)))
+        })
+        .transpose()?;
+    let tag = query.tag.as_deref().map(normalize_tag).transpose()?;
+    let log = light.read_log().await?;
+    if since.is_none() && tag.is_none() {
+        return Ok(log);
+    }
+    Ok(log
+        .lines()
+        .filter(|line| since.is_none_or(|since| parse_log_timestamp(line).is_some_and(|(at, _)| at >= since)))
+        .filter(|line| tag.as_ref().is_none_or(|tag| parse_power_events(line).iter().any(|event| event.tags.contains(tag))))
+        .map(|line| format!("{}\n", line))
+        .collect())
+}
+
+// Liveness: the light actor still works through its queue
+async fn healthz(State(light): State<LightHandle>) -> (StatusCode, Json<serde_json::Value>) {
+    match tokio::time::timeout(LIVENESS_TIMEOUT, light.ping()).await {
+        Ok(Ok(())) => (StatusCode::OK, Json(serde_json::json!({ "status": "ok" }))),
+        Ok(Err(_)) => (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "status": "stopping" }))),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, Json(serde_json::json!({ "status": "unresponsive" }))),
+    }
+}
+
+// Readiness: the log takes entries and the backend answers, reported like run_diagnostics
+async fn readyz(State(light): State<LightHandle>) -> (StatusCode, Json<DiagnosticsReport>) {
+    let report = match tokio::time::timeout(READINESS_TIMEOUT, light.readiness()).await {
+        Ok(Ok(probe)) => build_readiness_report(&probe),
+        Ok(Err(e)) => unready(e.to_string()),
+        Err(_) => unready(format!("The light did not answer within {}s", READINESS_TIMEOUT.as_secs())),
+    };
+    let status = if report.healthy { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
+
+fn unready(detail: String) -> DiagnosticsReport {
+    DiagnosticsReport { healthy: false, checks: vec![DiagnosticCheck { name: "actor", status: CheckStatus::Fail, detail }] }
+}
+
+async fn openapi() -> Json<serde_json::Value> {
+    Json(crate::openapi::document())
+}
+
+// Each WebSocket client gets its own subscription, independent of any MCP session
+async fn events(
+    State(light): State<LightHandle>,
+    State(clients): State<ClientRegistry>,
+    peer: Option<Extension<ConnectInfo<SocketAddr>>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let events = light.subscribe();
+    upgrade.on_upgrade(move |socket| async move {
+        let _stream = peer.map(|Extension(ConnectInfo(addr))| clients.open_stream(addr));
+        stream_events(socket, events).await;
+    })
+}
+
+// Sends every light event as a JSON text frame until the client disconnects
+async fn stream_events(mut socket: WebSocket, mut events: broadcast::Receiver<LightEvent>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Ok(frame) = serde_json::to_string(&event) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(frame.into())).await.is_err() {
+                        return;
+                    }
+                },
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // Frames from the client are ignored; only a close or error ends the stream
+            incoming = socket.recv() => if !matches!(incoming, Some(Ok(_))) {
+                return;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::Request;
+    use futures_util::StreamExt;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::state::{StateMachine, Transition};
+
+    fn spawn_light() -> LightHandle {
+        LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()))
+    }
+
+    async fn call(light: &LightHandle, method: &str, uri: &str) -> (StatusCode, String) {
+        let request = Request::builder().method(method).uri(uri).body(Body::empty()).unwrap();
+        let response = router(light.clone(), ClientRegistry::default()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_turn_on_is_shared_with_state() {
+        let light = spawn_light();
+        let (status, body) = call(&light, "POST", "/on").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"sequence":1,"changed":true}"#);
+
+        let (_, body) = call(&light, "GET", "/state").await;
+        let state: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(state["state"], "ON");
+        assert_eq!(state["changed_by"], "rest");
+    }
+
+    #[tokio::test]
+    async fn test_errors_use_stable_codes() {
+        let light = spawn_light();
+        light.apply(Transition::Lock).await.unwrap();
+        let (status, body) = call(&light, "POST", "/on").await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(body.contains(r#""code":"BULB_LOCKED""#));
+
+        let (status, body) = call(&light, "GET", "/log?since=yesterday").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("INVALID_PARAMETER"));
+
+        let response = ApiError(LightError::ToggleTooSoon { retry_after_ms: 1200, min_interval_ms: 2000 }).into_response();
+        assert_eq!((response.status(), response.headers()[header::RETRY_AFTER].to_str().unwrap()), (StatusCode::TOO_MANY_REQUESTS, "2"));
+    }
+
+    #[tokio::test]
+    async fn test_log_since_filters_entries() {
+        let light = spawn_light();
+        light.set_power(PowerState::On).await.unwrap();
+        let (_, body) = call(&light, "GET", "/log").await;
+        assert_eq!(body.lines().count(), 1);
+
+        let (_, body) = call(&light, "GET", "/log?since=2999-01-01T00:00:00Z").await;
+        assert!(body.is_empty());
+
+        light.tagged(vec!["movie".to_string()]).set_power(PowerState::Off).await.unwrap();
+        let (_, body) = call(&light, "GET", "/log?tag=movie").await;
+        assert_eq!(body.lines().collect::<Vec<_>>().len(), 1);
+        assert!(body.contains("turned OFF [movie]"));
+    }
+
+    #[tokio::test]
+    async fn test_a_panicking_handler_answers_with_an_internal_error() {
+        async fn broken() -> &'static str {
+            panic!("handler bug")
+        }
+        let app = Router::new().route("/broken", get(broken)).layer(middleware::from_fn(contain_panics));
+        let response = app.oneshot(Request::builder().uri("/broken").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "INTERNAL_ERROR");
+        assert!(body["message"].as_str().unwrap().contains("handler bug"));
+    }
+
+    #[tokio::test]
+    async fn test_health_and_readiness_probes() {
+        let light = spawn_light();
+        assert_eq!(call(&light, "GET", "/healthz").await, (StatusCode::OK, r#"{"status":"ok"}"#.to_string()));
+        let (status, body) = call(&light, "GET", "/readyz").await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let checks: Vec<&str> = report["checks"].as_array().unwrap().iter().filter_map(|check| check["name"].as_str()).collect();
+        assert_eq!(checks, ["log_writable", "backend", "scheduler"]);
+
+        let backend = crate::mock::MockBackend::new();
+        backend.script().otherwise(crate::mock::MockStep::fail("no route to bulb"));
+        let unreachable = LightHandle::spawn(StateMachine::new(), Box::new(backend), Box::new(InMemoryLogger::new()));
+        let (status, body) = call(&unreachable, "GET", "/readyz").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains(r#""healthy":false"#) && body.contains("unreachable"), "{}", body);
+        assert_eq!(call(&unreachable, "GET", "/healthz").await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_and_statistics() {
+        let light = spawn_light();
+        let (status, body) = call(&light, "GET", "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<title>Lightbulb</title>"));
+
+        light.set_power(PowerState::On).await.unwrap();
+        let (_, body) = call(&light, "GET", "/statistics").await;
+        let statistics: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(statistics["counts"]["on"], 1);
+        assert_eq!(statistics["histogram"].as_array().unwrap().len(), 24);
+    }
+
+    #[tokio::test]
+    async fn test_openapi_document_covers_every_route() {
+        let light = spawn_light();
+        let (status, body) = call(&light, "GET", "/openapi.json").await;
+        assert_eq!(status, StatusCode::OK);
+        let document: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let paths: Vec<&String> = document["paths"].as_object().unwrap().keys().collect();
+        assert_eq!(paths, ["/", "/events", "/healthz", "/log", "/off", "/on", "/openapi.json", "/readyz", "/state", "/statistics"]);
+        for path in paths {
+            assert_ne!(call(&light, if path == "/on" || path == "/off" { "POST" } else { "GET" }, path).await.0, StatusCode::NOT_FOUND, "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_streams_events_to_every_client() {
+        let light = spawn_light();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/events", listener.local_addr().unwrap());
+        let clients = ClientRegistry::default();
+        tokio::spawn(serve(listener, light.clone(), clients.clone()));
+
+        let (mut first, _) = tokio_tungstenite::connect_async(url.as_str()).await.unwrap();
+        let (mut second, _) = tokio_tungstenite::connect_async(url.as_str()).await.unwrap();
+        light.set_power(PowerState::On).await.unwrap();
+
+        for client in [&mut first, &mut second] {
+            let frame = client.next().await.unwrap().unwrap().into_text().unwrap();
+            let event: serde_json::Value = serde_json::from_str(&frame).unwrap();
+            assert_eq!((event["kind"].as_str(), event["to"].as_str()), (Some("state_changed"), Some("ON")));
+        }
+        let streaming: Vec<usize> = clients.list().iter().map(|client| client.streams).collect();
+        assert_eq!(streaming, vec![1, 1]);
+
+        drop(first);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(clients.list().iter().filter(|client| client.streams > 0).count(), 1);
+    }
+}
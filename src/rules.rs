@@ -0,0 +1,18 @@
+use crate::actor::{LightHandle, PowerChange, Sequenced};
+use crate::config::RulePower;
+use crate::error::LightError;
+use crate::model::PowerState;
+
+// Carries out a rule's action through `light`, which carries the rule's attribution and reason. A brightness is
+// set before the bulb is turned on, so it never shows the old one lit; only the first command checks the handle's
+// expected version, since each one moves the version on.
+pub async fn apply(light: &LightHandle, power: RulePower, brightness: Option<u8>) -> Result<Sequenced<PowerChange>, LightError> {
+    match (power, brightness) {
+        (RulePower::Off, _) => light.set_power(PowerState::Off).await,
+        (RulePower::On, Some(brightness)) => {
+            light.set_brightness(brightness).await?;
+            light.expecting(None).set_power(PowerState::On).await
+        },
+        (RulePower::On, None) => light.set_power(PowerState::On).await,
+    }
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::actor::LightHandle;
+use crate::clock::format_duration;
+use crate::config::{SafetyConfig, WatchdogAction};
+use crate::model::PowerState;
+use crate::state::StateSnapshot;
+
+// Who the safety watchdog's changes are logged as
+pub const WATCHDOG_CALLER: &str = "safety watchdog";
+
+// When the bulb's current spell of being on began, if it is on
+fn on_since(snapshot: &StateSnapshot) -> Option<DateTime<Utc>> {
+    snapshot.state_since.filter(|_| snapshot.power == Some(PowerState::On))
+}
+
+// Waits out each spell of the bulb being on, turning it off or warning once, when it has lasted longer than the
+// configured limit; any change that turns the bulb off, or back on, starts the count again
+pub fn spawn_safety_watchdog(config: SafetyConfig, light: LightHandle) {
+    let mut events = light.subscribe();
+    tokio::spawn(async move {
+        let limit = config.max_on();
+        // The spell of being on the watchdog has already stepped in for, by when it began
+        let mut handled = None;
+        loop {
+            let Ok(snapshot) = light.snapshot().await else {
+                return;
+            };
+            let due = on_since(&snapshot).filter(|since| handled != Some(*since)).map(|since| since + limit);
+            let wait = due.map(|due| (due - light.clock().now()).to_std().unwrap_or(Duration::ZERO)).unwrap_or_default();
+            tokio::select! {
+                event = events.recv() => match event {
+                    Err(RecvError::Closed) => return,
+                    _ => continue,
+                },
+                _ = tokio::time::sleep(wait), if due.is_some() => {},
+            }
+            // A clock of its own may not have caught up with the sleep yet
+            let Some(since) = on_since(&snapshot).filter(|since| light.clock().now() >= *since + limit) else {
+                continue;
+            };
+            handled = Some(since);
+            let on_for = format_duration((light.clock().now() - since).num_seconds());
+            match config.action {
+                WatchdogAction::Off => {
+                    let watchdog = light
+                        .acting_as(WATCHDOG_CALLER)
+                        .expecting(Some(snapshot.version))
+                        .with_reason(Some(format!("on for {}, past the {}h limit", on_for, config.max_on_hours)));
+                    match watchdog.watchdog_off().await {
+                        Ok(_) => crate::diagnostic!(Warn, "the safety watchdog turned the lightbulb off after {} on", on_for),
+                        Err(e) => crate::diagnostic!(Warn, "the safety watchdog could not turn the lightbulb off after {} on: {}", on_for, e),
+                    }
+                },
+                WatchdogAction::Warn => {
+                    if light.warn_on_too_long().await.is_ok() {
+                        crate::diagnostic!(Warn, "the lightbulb has been on for {}, past the {}h safety limit", on_for, config.max_on_hours);
+                    }
+                },
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::clock::ManualClock;
+    use crate::events::EventKind;
+    use crate::logger::InMemoryLogger;
+    use crate::state::StateMachine;
+
+    fn light(clock: &ManualClock) -> LightHandle {
+        LightHandle::spawn(StateMachine::with_clock(Arc::new(clock.clone())), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()))
+    }
+
+    async fn pass(clock: &ManualClock, duration: TimeDelta) {
+        clock.advance(duration);
+        tokio::time::sleep(duration.to_std().unwrap()).await;
+        tokio::task::yield_now().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watchdog_turns_off_a_bulb_left_on() {
+        let clock = ManualClock::new(Utc::now());
+        let light = light(&clock);
+        spawn_safety_watchdog(SafetyConfig { max_on_hours: 2.0, action: WatchdogAction::Off }, light.clone());
+        light.acting_as("agent").set_power(PowerState::On).await.unwrap();
+
+        pass(&clock, TimeDelta::minutes(119)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+        pass(&clock, TimeDelta::minutes(2)).await;
+        let snapshot = light.snapshot().await.unwrap();
+        assert_eq!((snapshot.power, snapshot.cause.as_deref()), (Some(PowerState::Off), Some("watchdog_off")));
+        let log = light.read_log().await.unwrap();
+        assert!(log.contains("turned OFF (WATCHDOG_OFF) by safety watchdog (reason: on for 2h 1m, past the 2h limit)"), "{}", log);
+
+        // Turning the bulb back on starts a new count
+        light.set_power(PowerState::On).await.unwrap();
+        pass(&clock, TimeDelta::minutes(90)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+        pass(&clock, TimeDelta::minutes(31)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::Off));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_watchdog_warns_once_and_leaves_the_bulb_on() {
+        let clock = ManualClock::new(Utc::now());
+        let light = light(&clock);
+        let mut events = light.subscribe();
+        light.set_power(PowerState::On).await.unwrap();
+        // A bulb already on when the watchdog starts is timed from when it was turned on
+        spawn_safety_watchdog(SafetyConfig { max_on_hours: 1.0, action: WatchdogAction::Warn }, light.clone());
+
+        pass(&clock, TimeDelta::minutes(61)).await;
+        pass(&clock, TimeDelta::hours(3)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+        let mut warnings = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            if event.kind == EventKind::OnTooLong {
+                warnings.push(event);
+            }
+        }
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((warnings[0].action.as_str(), warnings[0].state.in_state_secs), ("watchdog_warning", Some(3660)));
+    }
+}
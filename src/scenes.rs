@@ -0,0 +1,327 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::actor::{LightHandle, PowerChange, Sequenced};
+use crate::color::{MAX_KELVIN, MIN_KELVIN, MiredRange, kelvin_to_mireds, mireds_to_rgb};
+use crate::config::SceneConfig;
+use crate::error::LightError;
+use crate::model::{Color, Easing, MAX_CROSSFADE_MS, PowerState};
+use crate::palette::resolve_color;
+
+// Everyday lighting ready to use without any configuration: bright neutral white to read by, dim warm
+// white for a film, and a low amber glow to wind down with
+const BUILT_IN: [(&str, u8, u32); 3] = [("reading", 100, 4000), ("movie", 20, 2700), ("relax", 40, 2200)];
+// How often a crossfade moves the bulb on towards the scene
+const CROSSFADE_STEP: Duration = Duration::from_millis(100);
+// Where a crossfade starts on a bulb that was off
+const CROSSFADE_FROM_OFF: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SceneSource {
+    BuiltIn,
+    Config,
+    // Loaded with import_automation
+    Imported,
+}
+
+impl SceneSource {
+    pub fn label(self) -> &'static str {
+        match self {
+            SceneSource::BuiltIn => "built-in",
+            SceneSource::Config => "config",
+            SceneSource::Imported => "imported",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scene {
+    pub name: String,
+    pub brightness: u8,
+    pub kelvin: Option<u32>,
+    pub color: Option<Color>,
+    pub source: SceneSource,
+}
+
+impl Scene {
+    // The configuration must already have been validated
+    fn from_config(config: &SceneConfig, source: SceneSource) -> Self {
+        Self {
+            name: config.name.clone(),
+            brightness: config.brightness,
+            kelvin: config.kelvin,
+            color: config.color.as_deref().and_then(resolve_color).map(|(_, color)| color),
+            source,
+        }
+    }
+
+    // The color the scene sets, with its temperature moved into the bulb's range when it declares one
+    pub fn color_for(&self, range: Option<MiredRange>) -> Option<Color> {
+        let mireds = self.kelvin.map(kelvin_to_mireds)?;
+        Some(mireds_to_rgb(range.map_or(mireds, |range| range.clamp(mireds))))
+    }
+
+    // The scene as the bulb `bulb` of a group has it: with its own brightness, and its own temperature or color in
+    // place of the scene's. A bad override is the bulb's, not the scene's, and is named so.
+    pub fn overridden(&self, bulb: &str, brightness: Option<u8>, kelvin: Option<u32>, color: Option<&str>) -> Result<Scene, LightError> {
+        let invalid = |problem: String| LightError::InvalidParameter(format!("the override for lightbulb '{}' {}", bulb, problem));
+        if let Some(brightness) = brightness
+            && !(1..=100).contains(&brightness)
+        {
+            return Err(invalid(format!("must set brightness between 1 and 100, got {}", brightness)));
+        }
+        if let Some(kelvin) = kelvin
+            && !(MIN_KELVIN..=MAX_KELVIN).contains(&kelvin)
+        {
+            return Err(invalid(format!("must set kelvin between {} and {}, got {}", MIN_KELVIN, MAX_KELVIN, kelvin)));
+        }
+        let color = match (kelvin, color) {
+            (Some(_), Some(_)) => return Err(invalid("gives both kelvin and color; give one of them".to_string())),
+            (_, Some(color)) => Some(resolve_color(color).ok_or_else(|| invalid(format!("color '{}' is not a palette color or #rrggbb code", color)))?.1),
+            (_, None) => None,
+        };
+        let mut scene = Scene { brightness: brightness.unwrap_or(self.brightness), ..self.clone() };
+        if kelvin.is_some() || color.is_some() {
+            (scene.kelvin, scene.color) = (kelvin, color);
+        }
+        Ok(scene)
+    }
+
+    pub fn describe(&self) -> String {
+        match (self.kelvin, self.color) {
+            (Some(kelvin), _) => format!("{}% at {}K", self.brightness, kelvin),
+            (None, Some(color)) => format!("{}% in {}", self.brightness, color),
+            (None, None) => format!("{}%", self.brightness),
+        }
+    }
+}
+
+// The scenes apply_scene knows: the built-in ones, with configured scenes replacing any of the same name and
+// imported scenes replacing both. Clones share the scenes, so an import is seen by every session and schedule.
+#[derive(Debug, Clone)]
+pub struct Scenes {
+    configured: Arc<Vec<SceneConfig>>,
+    scenes: Arc<RwLock<Vec<Scene>>>,
+}
+
+impl Scenes {
+    pub fn new(configured: &[SceneConfig]) -> Self {
+        let scenes = Self::assemble(configured, &[]);
+        Self { configured: Arc::new(configured.to_vec()), scenes: Arc::new(RwLock::new(scenes)) }
+    }
+
+    fn assemble(configured: &[SceneConfig], imported: &[SceneConfig]) -> Vec<Scene> {
+        let replaced = |name: &str, by: &[SceneConfig]| by.iter().any(|scene| scene.name == name);
+        let mut scenes: Vec<Scene> = BUILT_IN
+            .iter()
+            .filter(|(name, ..)| !replaced(name, configured) && !replaced(name, imported))
+            .map(|&(name, brightness, kelvin)| Scene {
+                name: name.to_string(),
+                brightness,
+                kelvin: Some(kelvin),
+                color: None,
+                source: SceneSource::BuiltIn,
+            })
+            .collect();
+        scenes.extend(configured.iter().filter(|scene| !replaced(&scene.name, imported)).map(|scene| Scene::from_config(scene, SceneSource::Config)));
+        scenes.extend(imported.iter().map(|scene| Scene::from_config(scene, SceneSource::Imported)));
+        scenes
+    }
+
+    // Replaces the scenes imported before with `imported`, which must already have been validated
+    pub fn import(&self, imported: &[SceneConfig]) {
+        *self.scenes.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Self::assemble(&self.configured, imported);
+    }
+
+    pub fn all(&self) -> Vec<Scene> {
+        self.scenes.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    // Whether `name` is a scene without any import, built in or configured
+    pub fn is_own(&self, name: &str) -> bool {
+        BUILT_IN.iter().any(|(built_in, ..)| *built_in == name) || self.configured.iter().any(|scene| scene.name == name)
+    }
+
+    pub fn get(&self, name: &str) -> Result<Scene, LightError> {
+        let key = name.trim().to_ascii_lowercase();
+        let scenes = self.all();
+        scenes.iter().find(|scene| scene.name == key).cloned().ok_or_else(|| {
+            let names: Vec<&str> = scenes.iter().map(|scene| scene.name.as_str()).collect();
+            LightError::InvalidParameter(format!("Unknown scene '{}'; try one of: {}", name.trim(), names.join(", ")))
+        })
+    }
+
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let prefix = prefix.trim().to_ascii_lowercase();
+        self.all().into_iter().map(|scene| scene.name).filter(|name| name.starts_with(&prefix)).collect()
+    }
+}
+
+// Sets the scene's color and brightness before turning the bulb on, so it never shows the old look lit. Only
+// the first command checks the handle's expected version, since each one moves the version on.
+pub async fn apply(light: &LightHandle, scene: &Scene) -> Result<Sequenced<PowerChange>, LightError> {
+    let mut next = light.clone();
+    if let Some(color) = scene.color.or_else(|| scene.color_for(light.metadata().mired_range)) {
+        next.set_color(color).await?;
+        next = next.expecting(None);
+    }
+    next.set_brightness(scene.brightness).await?;
+    next.expecting(None).set_power(PowerState::On).await
+}
+
+// How long a scene takes to fade in, and how it moves through that time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Crossfade {
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Crossfade {
+    // None for no fade at all
+    pub fn new(duration_ms: u64, easing: Easing) -> Result<Option<Self>, LightError> {
+        if duration_ms > MAX_CROSSFADE_MS {
+            return Err(LightError::InvalidParameter(format!("transition_ms must be at most {}, got {}", MAX_CROSSFADE_MS, duration_ms)));
+        }
+        Ok((duration_ms > 0).then(|| Self { duration: Duration::from_millis(duration_ms), easing }))
+    }
+
+    // "over 1.5s, ease-in-out"
+    pub fn describe(&self) -> String {
+        format!("over {}s, {}", self.duration.as_secs_f64(), self.easing.label())
+    }
+}
+
+// As `apply`, but moving the bulb from how it looks to the scene a step at a time. A bulb that is off is turned
+// on at 1%, already in the scene's color, and brightened from there. Each step expects the version the one
+// before it left, so any other change to the bulb stops the fade where it is.
+pub async fn crossfade(light: &LightHandle, scene: &Scene, fade: Crossfade) -> Result<Sequenced<PowerChange>, LightError> {
+    let start = light.snapshot().await?;
+    let target = scene.color.or_else(|| scene.color_for(light.metadata().mired_range));
+    let mut turned_on = None;
+    let (from_brightness, from_color) = match start.power {
+        Some(PowerState::On) => (start.brightness, start.color),
+        _ => {
+            let mut next = light.clone();
+            if let Some(color) = target {
+                next.set_color(color).await?;
+                next = next.expecting(None);
+            }
+            next.set_brightness(CROSSFADE_FROM_OFF).await?;
+            turned_on = Some(next.expecting(None).set_power(PowerState::On).await?);
+            (CROSSFADE_FROM_OFF, target.unwrap_or(start.color))
+        },
+    };
+    let steps = (fade.duration.as_millis() / CROSSFADE_STEP.as_millis()).max(1) as u32;
+    let (mut brightness, mut color) = (from_brightness, from_color);
+    let mut next = match turned_on {
+        Some(_) => light.expecting(Some(light.snapshot().await?.version)),
+        None => light.clone(),
+    };
+    for step in 1..=steps {
+        tokio::time::sleep(fade.duration / steps).await;
+        let eased = fade.easing.ease(step as f64 / steps as f64);
+        let stepped_color = target.map_or(color, |target| from_color.blend(target, eased));
+        let stepped_brightness = (from_brightness as f64 + (scene.brightness as f64 - from_brightness as f64) * eased).round() as u8;
+        if stepped_color != color {
+            next.set_color(stepped_color).await?;
+            next = light.expecting(Some(light.snapshot().await?.version));
+            color = stepped_color;
+        }
+        if stepped_brightness != brightness {
+            next.set_brightness(stepped_brightness).await?;
+            next = light.expecting(Some(light.snapshot().await?.version));
+            brightness = stepped_brightness;
+        }
+    }
+    match turned_on {
+        Some(turned_on) => Ok(turned_on),
+        None => next.set_power(PowerState::On).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::state::{LightState, StateMachine};
+
+    fn scene(name: &str, brightness: u8, color: Option<&str>) -> SceneConfig {
+        SceneConfig { name: name.to_string(), brightness, kelvin: None, color: color.map(String::from) }
+    }
+
+    #[test]
+    fn test_configured_scenes_replace_built_in_ones() {
+        let scenes = Scenes::new(&[scene("movie", 5, Some("amber")), scene("cleaning", 100, None)]);
+        let names: Vec<String> = scenes.all().into_iter().map(|scene| scene.name).collect();
+        assert_eq!(names, ["reading", "relax", "movie", "cleaning"]);
+        let movie = scenes.get(" Movie ").unwrap();
+        assert_eq!((movie.brightness, movie.source, movie.describe()), (5, SceneSource::Config, "5% in #ffbf00".to_string()));
+        assert_eq!(scenes.get("reading").unwrap().describe(), "100% at 4000K");
+        assert_eq!(scenes.complete("re"), ["reading", "relax"]);
+        let dimmer = movie.overridden("lamp", Some(2), Some(3000), None).unwrap();
+        assert_eq!((dimmer.describe(), movie.overridden("lamp", None, None, None).unwrap()), ("2% at 3000K".to_string(), movie.clone()));
+        assert!(movie.overridden("lamp", Some(0), None, None).is_err() && movie.overridden("lamp", None, Some(3000), Some("amber")).is_err());
+        let error = movie.overridden("lamp", None, Some(100_000), None).unwrap_err().to_string();
+        assert!(error.contains("the override for lightbulb 'lamp' must set kelvin between 1000 and 40000, got 100000") && !error.contains("movie"), "{}", error);
+        let error = scenes.get("party").unwrap_err().to_string();
+        assert!(error.contains("try one of: reading, relax, movie, cleaning"), "{}", error);
+
+        // An import replaces the one before it, and any scene of the same name, in every clone
+        let shared = scenes.clone();
+        scenes.import(&[scene("reading", 80, None), scene("party", 100, Some("magenta"))]);
+        scenes.import(&[scene("movie", 15, None)]);
+        let sources: Vec<String> = shared.all().iter().map(|scene| format!("{}:{}", scene.name, scene.source.label())).collect();
+        assert_eq!(sources, ["reading:built-in", "relax:built-in", "cleaning:config", "movie:imported"]);
+        assert!(shared.is_own("movie") && !shared.is_own("party") && shared.get("party").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_lights_the_bulb_in_the_scene() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let scenes = Scenes::new(&[]);
+        let movie = scenes.get("movie").unwrap();
+        let version = light.status().await.unwrap().version;
+        let applied = apply(&light.expecting(Some(version)), &movie).await.unwrap();
+        assert_eq!(applied.outcome, PowerChange::Changed);
+        let color = movie.color_for(None).unwrap();
+        assert_eq!(light.state().await.unwrap(), LightState::On { brightness: 20, color });
+        // The log records the color, brightness and power the scene changed
+        let log = light.read_log().await.unwrap();
+        assert_eq!(log.lines().count(), 3, "{}", log);
+        assert!(log.contains(&format!("Lightbulb COLOR {}", color)) && log.contains("Lightbulb BRIGHTNESS 20%"), "{}", log);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_crossfade_moves_to_the_scene_a_step_at_a_time() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        light.set_power(PowerState::On).await.unwrap();
+        let movie = Scenes::new(&[]).get("movie").unwrap();
+        let fade = Crossfade::new(1_000, Easing::Linear).unwrap().unwrap();
+        assert_eq!(fade.describe(), "over 1s, linear");
+        assert!(Crossfade::new(0, Easing::EaseIn).unwrap().is_none() && Crossfade::new(MAX_CROSSFADE_MS + 1, Easing::Linear).is_err());
+
+        let fading = tokio::spawn({
+            let (light, movie) = (light.clone(), movie.clone());
+            async move { crossfade(&light, &movie, fade).await }
+        });
+        tokio::time::sleep(Duration::from_millis(550)).await;
+        let halfway = light.snapshot().await.unwrap();
+        assert_eq!(halfway.brightness, 60);
+        assert_eq!(halfway.color, Color::WHITE.blend(movie.color_for(None).unwrap(), 0.5));
+        assert_eq!(fading.await.unwrap().unwrap().outcome, PowerChange::AlreadyInState);
+        assert_eq!(light.state().await.unwrap(), LightState::On { brightness: 20, color: movie.color_for(None).unwrap() });
+
+        // Another change part way through stops the fade where it is
+        let reading = Scenes::new(&[]).get("reading").unwrap();
+        let fading = tokio::spawn({
+            let light = light.clone();
+            async move { crossfade(&light, &reading, fade).await }
+        });
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        light.set_brightness(50).await.unwrap();
+        let error = fading.await.unwrap().unwrap_err();
+        assert!(matches!(error, LightError::VersionConflict { .. }), "{}", error);
+        assert_eq!(light.snapshot().await.unwrap().brightness, 50);
+    }
+}
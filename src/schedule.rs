@@ -0,0 +1,139 @@
+use anyhow::Context;
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Timelike};
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// Whether `now` falls in [after, before); a missing bound is open, and `after` later than `before` wraps past midnight
+pub fn in_window(now: NaiveTime, after: Option<NaiveTime>, before: Option<NaiveTime>) -> bool {
+    match (after, before) {
+        (Some(after), Some(before)) if after > before => now >= after || now < before,
+        (after, before) => after.is_none_or(|after| now >= after) && before.is_none_or(|before| now < before),
+    }
+}
+
+// When a schedule runs: at a local time of day, on the days it lists, or at every minute a cron expression matches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleTime {
+    Daily(NaiveTime),
+    Cron(Cron),
+}
+
+// A five-field cron expression, "minute hour day-of-month month day-of-week". Each field is `*`, a number, a range
+// `a-b` or a list of them, each optionally stepped with `/n`; months and days of the week may also be named, e.g.
+// "jan" or "mon", and Sunday is 0 or 7. Each field is kept as a bit per value it matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cron {
+    expression: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // As in cron, when both the day of the month and the day of the week are restricted, either one matching will do
+    either_day: bool,
+}
+
+impl Cron {
+    pub fn parse(expression: &str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            anyhow::bail!("A cron expression has five fields, minute hour day-of-month month day-of-week, got {}", fields.len());
+        };
+        let weekdays = cron_field(weekday, "day-of-week", 0, 7, &WEEKDAYS)?;
+        Ok(Self {
+            expression: fields.join(" "),
+            minutes: cron_field(minute, "minute", 0, 59, &[])?,
+            hours: cron_field(hour, "hour", 0, 23, &[])?,
+            days: cron_field(day, "day-of-month", 1, 31, &[])?,
+            months: cron_field(month, "month", 1, 12, &MONTHS)?,
+            // Sunday as 7 is Sunday as 0
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            either_day: !day.starts_with('*') && !weekday.starts_with('*'),
+        })
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.expression
+    }
+
+    // Whether the expression matches the minute `at` falls in
+    pub fn matches(&self, at: NaiveDateTime) -> bool {
+        let has = |set: u64, value: u32| set & (1 << value) != 0;
+        let (day, weekday) = (has(self.days, at.day()), has(self.weekdays, at.weekday().num_days_from_sunday()));
+        let day_matches = if self.either_day { day || weekday } else { day && weekday };
+        has(self.minutes, at.minute()) && has(self.hours, at.hour()) && has(self.months, at.month()) && day_matches
+    }
+}
+
+// The values between `min` and `max` a field matches, as bits; `names` are the values from `min` on, by name
+fn cron_field(field: &str, what: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u64> {
+    let value = |value: &str| {
+        let named = names.iter().position(|name| name.eq_ignore_ascii_case(value)).map(|index| index as u32 + min);
+        named
+            .or_else(|| value.parse().ok())
+            .filter(|value| (min..=max).contains(value))
+            .with_context(|| format!("Cron {} '{}' must be between {} and {}", what, value, min, max))
+    };
+    let mut set = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).with_context(|| format!("Cron {} step '{}' must be a positive number", what, step))?),
+            None => (part, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // "5/15" runs from 5 on
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if from > to {
+            anyhow::bail!("Cron {} range '{}' runs backwards", what, range);
+        }
+        set |= (from..=to).step_by(step as usize).fold(0, |set, value| set | 1 << value);
+    }
+    Ok(set)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_window_wraps_past_midnight() {
+        let (after, before) = (Some(time("23:00")), Some(time("06:00")));
+        assert!(in_window(time("23:30"), after, before));
+        assert!(in_window(time("02:00"), after, before));
+        assert!(!in_window(time("12:00"), after, before));
+        assert!(in_window(time("12:00"), None, None));
+        assert!(!in_window(time("22:59"), after, None));
+    }
+
+    fn local(at: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_cron_expressions() {
+        let evenings = Cron::parse("*/15 18-23 * * fri,SAT").unwrap();
+        // 2026-10-16 is a Friday
+        assert!(evenings.matches(local("2026-10-16 18:45")));
+        assert!(!evenings.matches(local("2026-10-16 18:50")));
+        assert!(!evenings.matches(local("2026-10-15 18:45")));
+        assert!(Cron::parse("30 6 * * 7").unwrap().matches(local("2026-10-18 06:30")), "7 is Sunday");
+        // Restricting both days matches either, as cron does
+        let either = Cron::parse("0 12 1 * mon").unwrap();
+        assert!(either.matches(local("2026-10-01 12:00")) && either.matches(local("2026-10-19 12:00")));
+        assert!(!either.matches(local("2026-10-20 12:00")));
+        assert!(Cron::parse("0 9 1 jan-mar *").unwrap().matches(local("2026-02-01 09:00")));
+        assert_eq!(Cron::parse(" 0  7 * * *").unwrap().expression(), "0 7 * * *");
+        for broken in ["0 7 * *", "60 7 * * *", "0 7 * * 8", "0 */0 * * *", "0 9-5 * * *", "0 7 * foo *"] {
+            assert!(Cron::parse(broken).is_err(), "{}", broken);
+        }
+    }
+}
@@ -0,0 +1,3250 @@
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDate, SecondsFormat, TimeDelta, Utc};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use futures_util::FutureExt;
+use rmcp::handler::server::tool::{ToolCallContext, ToolRoute, ToolRouter};
+use rmcp::model::*;
+use rmcp::ServerHandler;
+use rmcp::service::{NotificationContext, RequestContext};
+
+use crate::actor::{Change, DEFAULT_STATE_TTL, LightHandle, Preview};
+#[cfg(feature = "adaptive")]
+use crate::adaptive::AdaptiveBrightness;
+#[cfg(all(feature = "adaptive", feature = "mqtt"))]
+use crate::adaptive::spawn_lux_subscriber;
+#[cfg(feature = "contact")]
+use crate::contact::ContactSensors;
+#[cfg(all(feature = "contact", feature = "mqtt"))]
+use crate::contact::spawn_contact_subscribers;
+#[cfg(feature = "motion")]
+use crate::motion::MotionLighting;
+#[cfg(feature = "presence")]
+use crate::presence::Presence;
+#[cfg(feature = "scenes")]
+use crate::scenes::Scenes;
+#[cfg(feature = "bulbs")]
+use crate::bulbs::{BulbActors, Bulbs, bulb_log_path};
+#[cfg(feature = "schedules")]
+use crate::automation::{Automation, Schedules, automation_path, schedule_problems, spawn_scheduler, spawn_timer};
+#[cfg(all(feature = "motion", feature = "mqtt"))]
+use crate::motion::spawn_motion_subscribers;
+use crate::backend::{FaultInjector, LightBackend, SimulatedBackend};
+use crate::brightness::{BrightnessCurve, BrightnessSchedule, MAX_OUTPUT};
+use crate::clock::{SharedClock, system_clock};
+use crate::compaction::spawn_log_compaction;
+use crate::curfew::Curfews;
+#[cfg(feature = "adaptive")]
+use crate::config::AdaptiveBrightnessConfig;
+#[cfg(feature = "contact")]
+use crate::config::ContactConfig;
+#[cfg(feature = "motion")]
+use crate::config::MotionConfig;
+#[cfg(feature = "scenes")]
+use crate::config::SceneConfig;
+#[cfg(feature = "schedules")]
+use crate::config::ScheduleConfig;
+#[cfg(feature = "bulbs")]
+use crate::config::BulbConfig;
+#[cfg(feature = "analytics")]
+use crate::config::TariffConfig;
+#[cfg(feature = "safety")]
+use crate::config::SafetyConfig;
+#[cfg(feature = "weather")]
+use crate::config::WeatherConfig;
+use crate::config::{Config, MAIN_BULB, SessionMode, ToolTimeoutsConfig, validate_room};
+#[cfg(feature = "jobs")]
+use crate::jobs::JobRegistry;
+use crate::confirm::Confirmations;
+#[cfg(feature = "mqtt")]
+use crate::config::MqttPublisherConfig;
+#[cfg(feature = "webhooks")]
+use crate::config::WebhookConfig;
+use crate::effects::EffectRunner;
+#[cfg(feature = "encryption")]
+use crate::encryption::{EncryptingLogger, LogCipher, salt_path};
+use crate::info::Deployment;
+use crate::error::{LightError, panic_message, to_json, with_context};
+use crate::reply;
+use crate::idempotency::IdempotencyCache;
+use crate::i18n::Message;
+use crate::history_file::{HistoryLogger, HistoryRotation, history_path};
+use crate::journal::{Journal, journal_path};
+use crate::usage_cache::{UsageCache, usage_cache_path};
+use crate::logger::{FileLogger, InMemoryLogger, LOG_FILE_NAME, LogChunk, Logger, SigningLogger, load_or_create_signing_key, parse_log_sequence};
+use crate::model::{Color, PowerState};
+use crate::privacy::LogPrivacy;
+#[cfg(feature = "notifications")]
+use crate::notifications::{ChatSender, HttpChatSender, Notifier, spawn_notifiers};
+#[cfg(feature = "mqtt")]
+use crate::mqtt::{MqttClient, StatePublisher, spawn_state_publisher};
+use crate::palette::{CSS_COLORS, DESCRIPTIONS, complete_color, resolve_color};
+use crate::registry::BackendRegistry;
+#[cfg(feature = "effects")]
+use crate::registry::EffectRegistry;
+use crate::report::Report;
+#[cfg(feature = "safety")]
+use crate::safety::spawn_safety_watchdog;
+use crate::state::{StateChange, StateMachine, TransitionHook};
+use crate::stats::{LogRecord, day_start};
+use crate::tags::{MAX_TAGS, TagRule, normalize_tags};
+use watch::{ResourceSubscriptions, SessionLogLevel, spawn_change_notifier};
+#[cfg(feature = "weather")]
+use crate::weather::{OpenMeteo, WeatherSource, spawn_weather_scheduler};
+#[cfg(feature = "triggers")]
+use crate::triggers::{HttpSender, Trigger, TriggerSender, spawn_triggers};
+#[cfg(feature = "webhooks")]
+use crate::webhooks::{DeliveryLog, HttpTransport, WebhookTransport, spawn_dispatchers};
+
+#[cfg(feature = "adaptive")]
+mod adaptive;
+#[cfg(feature = "analytics")]
+mod analytics;
+#[cfg(feature = "audit")]
+mod audit;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+#[cfg(feature = "effects")]
+mod effects;
+#[cfg(feature = "history")]
+mod history;
+#[cfg(feature = "home-assistant")]
+mod home_assistant;
+#[cfg(feature = "macros")]
+mod macros;
+#[cfg(feature = "contact")]
+mod contact;
+#[cfg(feature = "motion")]
+mod motion;
+#[cfg(feature = "presence")]
+mod presence;
+#[cfg(feature = "scenes")]
+mod scenes;
+#[cfg(feature = "schedules")]
+mod schedules;
+#[cfg(feature = "bulbs")]
+mod bulbs;
+#[cfg(feature = "jobs")]
+mod jobs;
+#[cfg(any(feature = "analytics", feature = "diagnostics"))]
+mod prompts;
+#[cfg(feature = "core")]
+mod power;
+#[cfg(feature = "simulation")]
+mod simulation;
+mod watch;
+
+#[cfg(feature = "adaptive")]
+pub use adaptive::{ReportAmbientLightRequest, SetAdaptiveBrightnessRequest};
+#[cfg(feature = "analytics")]
+pub use analytics::GetStatisticsRequest;
+#[cfg(feature = "effects")]
+pub use effects::{FlashMorseRequest, IdentifyRequest};
+#[cfg(feature = "jobs")]
+pub use jobs::JobRequest;
+#[cfg(feature = "macros")]
+pub use macros::{MacroAction, RunMacroRequest};
+#[cfg(feature = "contact")]
+pub use contact::ReportContactRequest;
+#[cfg(feature = "motion")]
+pub use motion::ReportMotionRequest;
+#[cfg(feature = "presence")]
+pub use presence::ReportPresenceRequest;
+#[cfg(feature = "scenes")]
+pub use scenes::ApplySceneRequest;
+#[cfg(feature = "schedules")]
+pub use schedules::{CancelScheduleRequest, ImportAutomationRequest, SetScheduleRequest, TurnOffAfterRequest};
+#[cfg(feature = "bulbs")]
+pub use bulbs::{AddLightbulbRequest, BulbAliasesRequest, RenameBulbRequest, RoomPowerRequest};
+#[cfg(feature = "core")]
+pub use power::SetColorByNameRequest;
+#[cfg(feature = "simulation")]
+pub use simulation::FaultInjectionRequest;
+
+const LOG_URI: &str = "lightbulb://log";
+const LOG_PAGE_PREFIX: &str = "lightbulb://log?offset=";
+const LOG_PAGE_TEMPLATE: &str = "lightbulb://log{?offset}";
+// The log as it is written, for clients that would rather read it than parse it
+const LOG_TEXT_URI: &str = "lightbulb://log.txt";
+const LOG_TEXT_PAGE_PREFIX: &str = "lightbulb://log.txt?offset=";
+const LOG_TEXT_TEMPLATE: &str = "lightbulb://log.txt{?offset}";
+// Logs longer than this are served a page at a time, so reading a long history never holds all of it in memory
+pub const LOG_PAGE_BYTES: usize = 256 * 1024;
+// Starts the last line of every page of the text log but the final one, followed by the next page's URI
+pub const LOG_NEXT_PAGE: &str = "Next page: ";
+// The same pages as lightbulb://log, under the name they had before it served JSON
+const LOG_JSON_URI: &str = "lightbulb://log.json";
+const LOG_JSON_PAGE_PREFIX: &str = "lightbulb://log.json?offset=";
+const LOG_JSON_TEMPLATE: &str = "lightbulb://log.json{?offset}";
+const LOG_TAIL_URI: &str = "lightbulb://log/tail";
+const LOG_TAIL_PREFIX: &str = "lightbulb://log/tail?entries=";
+const LOG_TAIL_TEMPLATE: &str = "lightbulb://log/tail{?entries}";
+const LOG_SINCE_PREFIX: &str = "lightbulb://log?since=";
+const LOG_SINCE_TEMPLATE: &str = "lightbulb://log{?since}";
+const LOG_DAY_PREFIX: &str = "lightbulb://log/";
+const LOG_DAY_TEMPLATE: &str = "lightbulb://log/{date}";
+// Dates a log day URI takes besides YYYY-MM-DD, offered first when completing one
+const LOG_DAY_NAMES: [&str; 2] = ["today", "yesterday"];
+const DEFAULT_TAIL_ENTRIES: usize = 20;
+// Key of the `_meta` time a read can pass to get the resource only if it changed since
+pub const IF_MODIFIED_SINCE: &str = "ifModifiedSince";
+const MAX_TAIL_ENTRIES: usize = 1_000;
+const COLORS_URI: &str = "lightbulb://colors";
+const CONFIG_URI: &str = "lightbulb://config";
+const BULB_URI: &str = "lightbulb://bulb";
+const SUMMARY_URI: &str = "lightbulb://summary";
+const SUMMARY_JSON_URI: &str = "lightbulb://summary.json";
+const REPORT_MARKDOWN_URI: &str = "lightbulb://report.md";
+const REPORT_HTML_URI: &str = "lightbulb://report.html";
+#[cfg(feature = "webhooks")]
+const WEBHOOKS_URI: &str = "lightbulb://webhooks";
+const COLOR_URI_PREFIX: &str = "lightbulb://colors/";
+const COLOR_URI_TEMPLATE: &str = "lightbulb://colors/{name}";
+#[cfg(feature = "scenes")]
+const SCENES_URI: &str = "lightbulb://scenes";
+#[cfg(feature = "schedules")]
+const SCHEDULES_URI: &str = "lightbulb://schedules";
+// Resource updates a session's notifier may fall behind by before it misses some
+const RESOURCE_UPDATES: usize = 16;
+// Each bulb's own activity log, `lightbulb://main/log` being the server's own bulb's
+const BULB_LOG_TEMPLATE: &str = "lightbulb://{id}/log";
+const BULB_LOG_SUFFIX: &str = "/log";
+// Each bulb's state, as get_lightbulb_state gives the server's own bulb's
+const BULB_STATE_TEMPLATE: &str = "lightbulb://{id}/state";
+const BULB_STATE_SUFFIX: &str = "/state";
+#[cfg(feature = "effects")]
+const EFFECTS_URI: &str = "lightbulb://effects";
+#[cfg(feature = "effects")]
+const EFFECT_URI_PREFIX: &str = "lightbulb://effects/";
+#[cfg(feature = "effects")]
+const EFFECT_URI_TEMPLATE: &str = "lightbulb://effects/{name}";
+// Each resource's size in bytes, keyed by URI, with the timestamp it was measured at
+type MeasuredSizes = HashMap<String, (DateTime<Utc>, u32)>;
+// Room for a sentence or two of context, short enough to keep log lines readable
+const MAX_REASON_LEN: usize = 200;
+// Everything a guest session can call; guests also see only the color palette resources
+const GUEST_TOOLS: [&str; 4] = ["get_lightbulb_status", "get_lightbulb_state", "turn_on_lightbulb", "turn_off_lightbulb"];
+
+// Optional precondition, retry key, reason and tags accepted by every tool that changes the bulb
+#[derive(Debug, Default, serde::Deserialize, schemars::JsonSchema)]
+pub struct ChangeRequest {
+    /// Only make the change if the state version (reported by the status tools) still equals this
+    pub expected_version: Option<u64>,
+    /// A unique key for this change; retrying with the same key within 10 minutes returns the first result instead of changing the bulb again
+    pub idempotency_key: Option<String>,
+    /// Why the change is being made, e.g. "user asked for movie lighting"; recorded in the log with power changes
+    pub reason: Option<String>,
+    /// Tags for the log entry of a power change, e.g. ["movie"], so statistics can be filtered by them later
+    #[schemars(length(max = MAX_TAGS))]
+    pub tags: Option<Vec<String>>,
+    /// How much the confirmation says: "brief" for just the resulting state and version, "detailed" to add the
+    /// state before, who made the change and when; defaults to the session's choice from set_response_detail
+    pub detail: Option<ResponseDetail>,
+}
+
+// How much a change's confirmation says, chosen per call or for the rest of a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseDetail {
+    // "ON, version 4", for callers that only check the outcome
+    Brief,
+    #[default]
+    Normal,
+    // The confirmation with the state before, the caller and the time of the change
+    Detailed,
+}
+
+// Groups of tools that can be served or embedded independently, each behind its own cargo feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToolGroup {
+    // Status, on/off and lock/unlock (`core`)
+    #[cfg(feature = "core")]
+    Core,
+    // Undo, redo, factory reset and log import (`history`)
+    #[cfg(feature = "history")]
+    History,
+    // Log signature verification (`audit`)
+    #[cfg(feature = "audit")]
+    Audit,
+    // Runtime fault injection for the simulated backend (`simulation`)
+    #[cfg(feature = "simulation")]
+    Simulation,
+    // Background light effects such as Morse code (`effects`)
+    #[cfg(feature = "effects")]
+    Effects,
+    // Multi-step macros built from the core actions (`macros`)
+    #[cfg(feature = "macros")]
+    Macros,
+    // Self-checks for troubleshooting (`diagnostics`)
+    #[cfg(feature = "diagnostics")]
+    Diagnostics,
+    // Usage statistics computed from the log (`analytics`)
+    #[cfg(feature = "analytics")]
+    Analytics,
+    // Brightness that follows ambient light readings (`adaptive`)
+    #[cfg(feature = "adaptive")]
+    Adaptive,
+    // Lighting turned on by motion and off after a spell without it (`motion`)
+    #[cfg(feature = "motion")]
+    Motion,
+    // Rules applied as people arrive home and leave (`presence`)
+    #[cfg(feature = "presence")]
+    Presence,
+    // Lighting switched by door and window sensors (`contact`)
+    #[cfg(feature = "contact")]
+    Contact,
+    // Named lighting presets applied in one call (`scenes`)
+    #[cfg(feature = "scenes")]
+    Scenes,
+    // Schedules, and scenes and schedules imported from JSON (`schedules`)
+    #[cfg(feature = "schedules")]
+    Schedules,
+    // Named bulbs beside the server's own, and switching a room of them at once (`bulbs`)
+    #[cfg(feature = "bulbs")]
+    Bulbs,
+    // Configuration export for Home Assistant (`home-assistant`)
+    #[cfg(feature = "home-assistant")]
+    HomeAssistant,
+    // Status and cancellation of background jobs (`jobs`)
+    #[cfg(feature = "jobs")]
+    Jobs,
+}
+
+impl ToolGroup {
+    // Every group compiled into this build
+    pub const ALL: &'static [ToolGroup] = &[
+        #[cfg(feature = "core")]
+        ToolGroup::Core,
+        #[cfg(feature = "history")]
+        ToolGroup::History,
+        #[cfg(feature = "audit")]
+        ToolGroup::Audit,
+        #[cfg(feature = "simulation")]
+        ToolGroup::Simulation,
+        #[cfg(feature = "effects")]
+        ToolGroup::Effects,
+        #[cfg(feature = "macros")]
+        ToolGroup::Macros,
+        #[cfg(feature = "diagnostics")]
+        ToolGroup::Diagnostics,
+        #[cfg(feature = "analytics")]
+        ToolGroup::Analytics,
+        #[cfg(feature = "adaptive")]
+        ToolGroup::Adaptive,
+        #[cfg(feature = "motion")]
+        ToolGroup::Motion,
+        #[cfg(feature = "presence")]
+        ToolGroup::Presence,
+        #[cfg(feature = "contact")]
+        ToolGroup::Contact,
+        #[cfg(feature = "scenes")]
+        ToolGroup::Scenes,
+        #[cfg(feature = "schedules")]
+        ToolGroup::Schedules,
+        #[cfg(feature = "bulbs")]
+        ToolGroup::Bulbs,
+        #[cfg(feature = "home-assistant")]
+        ToolGroup::HomeAssistant,
+        #[cfg(feature = "jobs")]
+        ToolGroup::Jobs,
+    ];
+
+    fn router(self) -> ToolRouter<LightService> {
+        match self {
+            #[cfg(feature = "core")]
+            ToolGroup::Core => LightService::power_tools(),
+            #[cfg(feature = "history")]
+            ToolGroup::History => LightService::history_tools(),
+            #[cfg(feature = "audit")]
+            ToolGroup::Audit => LightService::audit_tools(),
+            #[cfg(feature = "simulation")]
+            ToolGroup::Simulation => LightService::simulation_tools(),
+            #[cfg(feature = "effects")]
+            ToolGroup::Effects => LightService::effect_tools(),
+            #[cfg(feature = "macros")]
+            ToolGroup::Macros => LightService::macro_tools(),
+            #[cfg(feature = "diagnostics")]
+            ToolGroup::Diagnostics => LightService::diagnostic_tools(),
+            #[cfg(feature = "analytics")]
+            ToolGroup::Analytics => LightService::analytics_tools(),
+            #[cfg(feature = "adaptive")]
+            ToolGroup::Adaptive => LightService::adaptive_tools(),
+            #[cfg(feature = "motion")]
+            ToolGroup::Motion => LightService::motion_tools(),
+            #[cfg(feature = "presence")]
+            ToolGroup::Presence => LightService::presence_tools(),
+            #[cfg(feature = "contact")]
+            ToolGroup::Contact => LightService::contact_tools(),
+            #[cfg(feature = "scenes")]
+            ToolGroup::Scenes => LightService::scene_tools(),
+            #[cfg(feature = "schedules")]
+            ToolGroup::Schedules => LightService::schedule_tools(),
+            #[cfg(feature = "bulbs")]
+            ToolGroup::Bulbs => LightService::bulb_tools(),
+            #[cfg(feature = "home-assistant")]
+            ToolGroup::HomeAssistant => LightService::home_assistant_tools(),
+            #[cfg(feature = "jobs")]
+            ToolGroup::Jobs => LightService::job_tools(),
+        }
+    }
+}
+
+// Tools from the given groups for a host server that embeds a LightService, so they
+// can be merged into the host's own ToolRouter
+pub fn tool_router_for<S>(groups: &[ToolGroup]) -> ToolRouter<S>
+where
+    S: AsRef<LightService> + Send + Sync + 'static,
+{
+    let mut router = ToolRouter::new();
+    for route in LightService::router_for(groups) {
+        let call = route.call;
+        router.add_route(ToolRoute::new_dyn(route.attr, move |context: ToolCallContext<'_, S>| {
+            call(ToolCallContext {
+                request_context: context.request_context,
+                service: context.service.as_ref(),
+                name: context.name,
+                arguments: context.arguments,
+            })
+        }));
+    }
+    router
+}
+
+fn bulb_log_uri(id: &str) -> String {
+    format!("lightbulb://{}{}", id, BULB_LOG_SUFFIX)
+}
+
+fn bulb_state_uri(id: &str) -> String {
+    format!("lightbulb://{}{}", id, BULB_STATE_SUFFIX)
+}
+
+// The bulb a bulb's own resource names, by id, name or alias, and whether it is the bulb's log or its state
+fn bulb_resource(uri: &str) -> Option<(String, &'static str)> {
+    let key = uri.strip_prefix("lightbulb://").filter(|_| !uri.starts_with(COLOR_URI_PREFIX))?;
+    [BULB_LOG_SUFFIX, BULB_STATE_SUFFIX].into_iter().find_map(|suffix| Some((percent_decode(key.strip_suffix(suffix)?)?, suffix)))
+}
+
+// Whether the resource a subscription follows at `uri` changes with the bulb `bulb_id`, so that its subscribers
+// hear of every change to it; the server-wide log, summary and reports are the server's own bulb's
+fn follows_light(uri: &str, bulb_id: &str) -> bool {
+    let server_wide = uri.starts_with(LOG_URI) || [SUMMARY_URI, SUMMARY_JSON_URI, REPORT_MARKDOWN_URI, REPORT_HTML_URI].contains(&uri);
+    (server_wide && bulb_id == MAIN_BULB) || uri == bulb_log_uri(bulb_id) || uri == bulb_state_uri(bulb_id)
+}
+
+// The scopes of the bearer JWT the HTTP transport validated for this request; None for the static token and stdio
+#[cfg(feature = "http")]
+fn request_grant(context: &RequestContext<rmcp::RoleServer>) -> Option<&crate::auth::Grant> {
+    context.extensions.get::<axum::http::request::Parts>().and_then(|parts| parts.extensions.get())
+}
+
+// The guest or admin key the HTTP transport matched this request's bearer token to
+#[cfg(feature = "http")]
+fn request_key(context: &RequestContext<rmcp::RoleServer>) -> Option<crate::auth::KeyAccess> {
+    context.extensions.get::<axum::http::request::Parts>().and_then(|parts| parts.extensions.get()).copied()
+}
+
+// Decodes %XX escapes in a URI segment, or None if one is malformed or the bytes are not UTF-8
+fn percent_decode(segment: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(segment.len());
+    let mut rest = segment.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = rest.get(..2).and_then(|hex| std::str::from_utf8(hex).ok())?;
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        rest = &rest[2..];
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[derive(Clone)]
+pub struct LightService {
+    tool_router: ToolRouter<Self>,
+    light: LightHandle,
+    #[cfg_attr(not(feature = "audit"), allow(dead_code))]
+    verifying_key: Option<VerifyingKey>,
+    #[cfg_attr(not(feature = "simulation"), allow(dead_code))]
+    faults: Option<FaultInjector>,
+    #[cfg_attr(not(any(feature = "core", feature = "effects", feature = "history")), allow(dead_code))]
+    effects: EffectRunner,
+    #[cfg(feature = "effects")]
+    effect_registry: Arc<EffectRegistry>,
+    #[cfg_attr(not(feature = "diagnostics"), allow(dead_code))]
+    deployment: Deployment,
+    #[cfg_attr(not(feature = "diagnostics"), allow(dead_code))]
+    started_at: Instant,
+    // When the resources that only change with a restart, such as the palette and config, were last modified
+    resources_since: DateTime<Utc>,
+    // Listed resource sizes with the timestamp they were measured at, measured again once the resource changes
+    sizes: Arc<Mutex<MeasuredSizes>>,
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    idempotency: IdempotencyCache,
+    #[cfg_attr(not(feature = "history"), allow(dead_code))]
+    confirmations: Confirmations,
+    #[cfg(feature = "webhooks")]
+    webhook_deliveries: Option<DeliveryLog>,
+    #[cfg(feature = "adaptive")]
+    adaptive: AdaptiveBrightness,
+    #[cfg(feature = "motion")]
+    motion: MotionLighting,
+    #[cfg(feature = "presence")]
+    presence: Presence,
+    #[cfg(feature = "contact")]
+    contacts: ContactSensors,
+    #[cfg(feature = "scenes")]
+    scenes: Scenes,
+    #[cfg(feature = "schedules")]
+    automation: Automation,
+    #[cfg(feature = "bulbs")]
+    bulbs: Bulbs,
+    #[cfg(feature = "analytics")]
+    tariff: Option<TariffConfig>,
+    #[cfg(feature = "jobs")]
+    jobs: JobRegistry,
+    // What the log hashes names and reasons with, for purge_data to anonymize old entries the same way
+    #[cfg(feature = "history")]
+    privacy: LogPrivacy,
+    guest: bool,
+    // Started with one of the config's admin keys, so no curfew applies
+    admin: bool,
+    curfews: Arc<Curfews>,
+    tool_timeouts: Arc<ToolTimeoutsConfig>,
+    // Set by --dry-run: changing tools report what they would do and change nothing
+    dry_run: bool,
+    log_level: SessionLogLevel,
+    subscriptions: ResourceSubscriptions,
+    // Resources that changed other than with the bulb, for every session's subscribers
+    resource_updates: tokio::sync::broadcast::Sender<String>,
+    // Set by set_response_detail, for change calls that do not choose their own
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    response_detail: Arc<Mutex<ResponseDetail>>,
+}
+
+impl AsRef<LightService> for LightService {
+    fn as_ref(&self) -> &LightService {
+        self
+    }
+}
+
+// Produces the LightService for each new MCP session; a server holds just one, so its size does not matter
+#[allow(clippy::large_enum_variant)]
+pub enum SessionFactory {
+    // Sessions share one service, and so one bulb
+    Shared(LightService),
+    // Each session gets a fresh simulated bulb whose state and log vanish with it
+    Sandbox { signing_key: Option<SigningKey>, deployment: Deployment },
+}
+
+impl SessionFactory {
+    pub fn create(&self) -> LightService {
+        match self {
+            SessionFactory::Shared(service) => service.new_session(),
+            SessionFactory::Sandbox { signing_key, deployment } => {
+                let mut builder = LightService::builder().logger(Box::new(InMemoryLogger::new()));
+                if let Some(signing_key) = signing_key {
+                    builder = builder.signing_key(signing_key.clone());
+                }
+                builder.deployment = deployment.clone();
+                builder.build()
+            },
+        }
+    }
+}
+
+// Assembles a LightService from its parts, serving every compiled-in tool group unless told otherwise
+pub struct LightServiceBuilder {
+    logger: Option<Box<dyn Logger + Send + Sync>>,
+    backend: Option<Box<dyn LightBackend + Send + Sync>>,
+    signing_key: Option<SigningKey>,
+    #[cfg(feature = "encryption")]
+    log_cipher: Option<LogCipher>,
+    clock: Option<SharedClock>,
+    tag_rules: Vec<TagRule>,
+    privacy: LogPrivacy,
+    curfews: Curfews,
+    tool_timeouts: ToolTimeoutsConfig,
+    state_ttl: Duration,
+    min_toggle_interval: Duration,
+    offline_retry: Option<Duration>,
+    brightness: BrightnessCurve,
+    brightness_schedule: BrightnessSchedule,
+    compaction: Option<u32>,
+    journal: Option<String>,
+    usage_cache: Option<String>,
+    history: Option<(String, HistoryRotation)>,
+    transition_hooks: Vec<TransitionHook>,
+    groups: Vec<ToolGroup>,
+    deployment: Deployment,
+    #[cfg(feature = "webhooks")]
+    webhooks: Option<(Vec<WebhookConfig>, Arc<dyn WebhookTransport + Send + Sync>)>,
+    #[cfg(feature = "mqtt")]
+    state_publisher: Option<(MqttPublisherConfig, Arc<dyn StatePublisher + Send + Sync>)>,
+    #[cfg(feature = "triggers")]
+    triggers: Option<(Vec<Trigger>, Arc<dyn TriggerSender + Send + Sync>)>,
+    #[cfg(feature = "notifications")]
+    notifiers: Option<(Vec<Notifier>, Arc<dyn ChatSender + Send + Sync>)>,
+    #[cfg(feature = "adaptive")]
+    adaptive: Option<AdaptiveBrightnessConfig>,
+    #[cfg(feature = "motion")]
+    motion: Vec<MotionConfig>,
+    #[cfg(feature = "presence")]
+    presence: Presence,
+    #[cfg(feature = "contact")]
+    contacts: Vec<ContactConfig>,
+    #[cfg(feature = "scenes")]
+    scenes: Vec<SceneConfig>,
+    #[cfg(feature = "schedules")]
+    schedules: Vec<ScheduleConfig>,
+    #[cfg(feature = "schedules")]
+    automation_file: Option<String>,
+    #[cfg(feature = "bulbs")]
+    room: Option<String>,
+    #[cfg(feature = "bulbs")]
+    bulbs: Vec<(BulbConfig, Box<dyn LightBackend + Send + Sync>)>,
+    #[cfg(feature = "bulbs")]
+    bulb_logs: Option<String>,
+    #[cfg(feature = "analytics")]
+    tariff: Option<TariffConfig>,
+    #[cfg(feature = "safety")]
+    safety: Option<SafetyConfig>,
+    #[cfg(feature = "weather")]
+    weather: Option<(WeatherConfig, Arc<dyn WeatherSource + Send + Sync>)>,
+    #[cfg(feature = "effects")]
+    effect_registry: EffectRegistry,
+}
+
+impl LightServiceBuilder {
+    // A history file set up for an earlier logger holds records of that logger's log, not this one's
+    pub fn logger(mut self, logger: Box<dyn Logger + Send + Sync>) -> Self {
+        self.logger = Some(logger);
+        self.history = None;
+        self
+    }
+
+    pub fn backend(mut self, backend: Box<dyn LightBackend + Send + Sync>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    // Signs every log entry with this key and enables signature verification
+    pub fn signing_key(mut self, signing_key: SigningKey) -> Self {
+        self.signing_key = Some(signing_key);
+        self
+    }
+
+    // Encrypts every log entry as it is stored and decrypts it as it is read back
+    #[cfg(feature = "encryption")]
+    pub fn log_cipher(mut self, cipher: LogCipher) -> Self {
+        self.log_cipher = Some(cipher);
+        self
+    }
+
+    // Times state changes, log entries, rule windows and statistics with `clock` instead of the system clock
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    // Tags every logged power change that matches one of these rules
+    pub fn tag_rules(mut self, tag_rules: Vec<TagRule>) -> Self {
+        self.tag_rules = tag_rules;
+        self
+    }
+
+    // Hashes or leaves out the client and reason of every log entry as `privacy` says
+    pub fn log_privacy(mut self, privacy: LogPrivacy) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
+    // Keeps the tools these curfews cover from everyone but admin sessions during their hours
+    pub fn curfews(mut self, curfews: Curfews) -> Self {
+        self.curfews = curfews;
+        self
+    }
+
+    // Fails tool calls that run longer than these allow with TOOL_TIMED_OUT
+    pub fn tool_timeouts(mut self, timeouts: ToolTimeoutsConfig) -> Self {
+        self.tool_timeouts = timeouts;
+        self
+    }
+
+    // Trusts the power the backend last reported for `ttl` before status reads ask it again
+    pub fn state_ttl(mut self, ttl: Duration) -> Self {
+        self.state_ttl = ttl;
+        self
+    }
+
+    // Refuses power changes that come within `interval` of the last one; zero allows any rate
+    pub fn min_toggle_interval(mut self, interval: Duration) -> Self {
+        self.min_toggle_interval = interval;
+        self
+    }
+
+    // Queues changes an unreachable bulb misses, asking it every `retry` whether it is back; None fails them at once
+    pub fn offline_queue(mut self, retry: Option<Duration>) -> Self {
+        self.offline_retry = retry;
+        self
+    }
+
+    // Gamma corrects brightness before the backend is sent it
+    pub fn brightness_curve(mut self, curve: BrightnessCurve) -> Self {
+        self.brightness = curve;
+        self
+    }
+
+    // Caps the brightness commands get by the time of day
+    pub fn brightness_schedule(mut self, schedule: BrightnessSchedule) -> Self {
+        self.brightness_schedule = schedule;
+        self
+    }
+
+    // Journals each logged change in `path` before making it, and repairs the log from it on startup
+    pub fn journal(mut self, path: String) -> Self {
+        self.journal = Some(path);
+        self
+    }
+
+    // Saves the usage counters in `path` at shutdown, so the next start need not count the whole log again
+    pub fn usage_cache(mut self, path: String) -> Self {
+        self.usage_cache = Some(path);
+        self
+    }
+
+    // Keeps a structured record of each log entry in `path`, rotated as `rotation` says, for query_history; added
+    // bulbs keep theirs beside their own logs
+    pub fn history_file(mut self, path: String, rotation: HistoryRotation) -> Self {
+        self.history = Some((path, rotation));
+        self
+    }
+
+    // Keeps collapsing log entries older than `keep_days` whole days into daily totals while the service runs
+    pub fn log_compaction(mut self, keep_days: u32) -> Self {
+        self.compaction = Some(keep_days);
+        self
+    }
+
+    // Runs `hook` after every transition the light's state machine applies, e.g. to count them in tests
+    pub fn transition_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&StateChange) + Send + Sync + 'static,
+    {
+        self.transition_hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn tool_groups(mut self, groups: &[ToolGroup]) -> Self {
+        self.groups = groups.to_vec();
+        self
+    }
+
+    // POSTs every matching light event to these webhooks through `transport`
+    #[cfg(feature = "webhooks")]
+    pub fn webhooks(mut self, webhooks: Vec<WebhookConfig>, transport: Arc<dyn WebhookTransport + Send + Sync>) -> Self {
+        self.webhooks = Some((webhooks, transport));
+        self
+    }
+
+    // Keeps a retained copy of the bulb's state on the configured topic, updated after every change
+    #[cfg(feature = "mqtt")]
+    pub fn state_publisher(mut self, config: MqttPublisherConfig, publisher: Arc<dyn StatePublisher + Send + Sync>) -> Self {
+        self.state_publisher = Some((config, publisher));
+        self
+    }
+
+    // Sends each trigger's templated request through `sender` when a matching event occurs
+    #[cfg(feature = "triggers")]
+    pub fn triggers(mut self, triggers: Vec<Trigger>, sender: Arc<dyn TriggerSender + Send + Sync>) -> Self {
+        self.triggers = Some((triggers, sender));
+        self
+    }
+
+    // Posts a chat message through `sender` for each event a notifier wants
+    #[cfg(feature = "notifications")]
+    pub fn notifiers(mut self, notifiers: Vec<Notifier>, sender: Arc<dyn ChatSender + Send + Sync>) -> Self {
+        self.notifiers = Some((notifiers, sender));
+        self
+    }
+
+    // Starts with adaptive brightness on, reading the MQTT topic it names if the state publisher is set
+    #[cfg(feature = "adaptive")]
+    pub fn adaptive_brightness(mut self, config: AdaptiveBrightnessConfig) -> Self {
+        self.adaptive = Some(config);
+        self
+    }
+
+    // Tracks who is home for report_presence, applying the rules their arrivals and departures match
+    #[cfg(feature = "presence")]
+    pub fn presence(mut self, presence: Presence) -> Self {
+        self.presence = presence;
+        self
+    }
+
+    // Switches the bulb as these door and window sensors open and close, subscribing to their MQTT topics if the
+    // state publisher is set
+    #[cfg(feature = "contact")]
+    pub fn contact_sensors(mut self, sensors: Vec<ContactConfig>) -> Self {
+        self.contacts = sensors;
+        self
+    }
+
+    // Turns the bulb on for motion in these rooms, subscribing to their MQTT topics if the state publisher is set
+    #[cfg(feature = "motion")]
+    pub fn motion_rooms(mut self, rooms: Vec<MotionConfig>) -> Self {
+        self.motion = rooms;
+        self
+    }
+
+    // Scenes of the user's own, replacing built-in scenes of the same name
+    #[cfg(feature = "scenes")]
+    pub fn scenes(mut self, scenes: Vec<SceneConfig>) -> Self {
+        self.scenes = scenes;
+        self
+    }
+
+    // Applies scenes, or turns the bulb on or off, at times of day
+    #[cfg(feature = "schedules")]
+    pub fn schedules(mut self, schedules: Vec<ScheduleConfig>) -> Self {
+        self.schedules = schedules;
+        self
+    }
+
+    // Keeps what import_automation loads in this file, and loads it again when built
+    #[cfg(feature = "schedules")]
+    pub fn automation_file(mut self, path: String) -> Self {
+        self.automation_file = Some(path);
+        self
+    }
+
+    // The room the server's own bulb is in
+    #[cfg(feature = "bulbs")]
+    pub fn room(mut self, room: String) -> Self {
+        self.room = Some(room);
+        self
+    }
+
+    // A bulb beside the server's own, switched by its id
+    #[cfg(feature = "bulbs")]
+    pub fn bulb(mut self, config: BulbConfig, backend: Box<dyn LightBackend + Send + Sync>) -> Self {
+        self.bulbs.push((config, backend));
+        self
+    }
+
+    // Keeps each added bulb's log in a file beside `log_file`, signed and encrypted as it is; without this they
+    // are kept in memory
+    #[cfg(feature = "bulbs")]
+    pub fn bulb_logs(mut self, log_file: String) -> Self {
+        self.bulb_logs = Some(log_file);
+        self
+    }
+
+    // What electricity costs, for the energy-savings prompt; without it the prompt leaves the rate to the client
+    #[cfg(feature = "analytics")]
+    pub fn tariff(mut self, tariff: TariffConfig) -> Self {
+        self.tariff = Some(tariff);
+        self
+    }
+
+    // Turns the bulb off, or warns, once it has been on for longer than the limit
+    #[cfg(feature = "safety")]
+    pub fn safety(mut self, safety: SafetyConfig) -> Self {
+        self.safety = Some(safety);
+        self
+    }
+
+    // Reads the weather from `source` every poll and applies the first rule it starts matching
+    #[cfg(feature = "weather")]
+    pub fn weather(mut self, weather: WeatherConfig, source: Arc<dyn WeatherSource + Send + Sync>) -> Self {
+        self.weather = Some((weather, source));
+        self
+    }
+
+    // The effects start_effect runs by name, the presets unless replaced with a registry of the embedder's own
+    #[cfg(feature = "effects")]
+    pub fn effect_registry(mut self, registry: EffectRegistry) -> Self {
+        self.effect_registry = registry;
+        self
+    }
+
+    // Name of the transport the service is served over, reported by server_info
+    pub fn transport(mut self, transport: &str) -> Self {
+        self.deployment.transport = Some(transport.to_string());
+        self
+    }
+
+    // Spawns the light actor, so this must be called from within a tokio runtime
+    pub fn build(mut self) -> LightService {
+        let backend = self.backend.unwrap_or_else(|| Box::new(SimulatedBackend::new()));
+        let mut logger = self.logger.unwrap_or_else(|| Box::new(FileLogger::new(LOG_FILE_NAME.to_string())));
+        #[cfg(feature = "bulbs")]
+        let bulb_actors: BulbActors = {
+            let (log_file, signing_key) = (self.bulb_logs.take(), self.signing_key.clone());
+            let rotation = self.history.as_ref().map(|(_, rotation)| *rotation);
+            #[cfg(feature = "encryption")]
+            let cipher = self.log_cipher.clone();
+            let clock = self.clock.clone().unwrap_or_else(system_clock);
+            let (tag_rules, privacy, journal) = (self.tag_rules.clone(), self.privacy.clone(), self.journal.clone());
+            let (min_toggle_interval, offline_retry, state_ttl) = (self.min_toggle_interval, self.offline_retry, self.state_ttl);
+            let (brightness, brightness_schedule) = (self.brightness, self.brightness_schedule.clone());
+            // Added bulbs are held to the settings the server's own bulb is, each with a log and journal of its own
+            Arc::new(move |id: &str, backend: Box<dyn LightBackend + Send + Sync>, tag: TagRule| {
+                let mut logger: Box<dyn Logger + Send + Sync> = match &log_file {
+                    Some(log_file) => Box::new(FileLogger::new(bulb_log_path(log_file, id))),
+                    None => Box::new(InMemoryLogger::new()),
+                };
+                #[cfg(feature = "encryption")]
+                if let Some(cipher) = &cipher {
+                    logger = Box::new(EncryptingLogger::new(logger, cipher.clone()));
+                }
+                if let Some(signing_key) = &signing_key {
+                    logger = Box::new(SigningLogger::new(logger, signing_key.clone()));
+                }
+                if let (Some(log_file), Some(rotation)) = (&log_file, rotation) {
+                    let history = HistoryLogger::new(logger, history_path(&bulb_log_path(log_file, id)), rotation);
+                    #[cfg(feature = "encryption")]
+                    let history = match &cipher {
+                        Some(cipher) => history.with_cipher(cipher.clone()),
+                        None => history,
+                    };
+                    logger = Box::new(history);
+                }
+                let mut machine = StateMachine::with_clock(clock.clone());
+                machine.set_min_toggle_interval(min_toggle_interval);
+                let journal = journal.as_ref().map(|journal| Journal::new(bulb_log_path(journal, id)));
+                #[cfg(feature = "encryption")]
+                let journal = match (journal, &cipher) {
+                    (Some(journal), Some(cipher)) => Some(journal.with_cipher(cipher.clone())),
+                    (journal, _) => journal,
+                };
+                let tag_rules = std::iter::once(tag).chain(tag_rules.iter().cloned()).collect();
+                LightHandle::spawn_with_log_privacy(machine, backend, logger, tag_rules, journal, offline_retry, None, privacy.clone())
+                    .with_state_ttl(state_ttl)
+                    .with_brightness_curve(brightness)
+                    .with_brightness_schedule(brightness_schedule.clone())
+            })
+        };
+        // Entries are signed before they are encrypted, so signatures are checked against what is read back
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = self.log_cipher.clone() {
+            logger = Box::new(EncryptingLogger::new(logger, cipher));
+        }
+        let verifying_key = self.signing_key.as_ref().map(SigningKey::verifying_key);
+        if let Some(signing_key) = self.signing_key {
+            logger = Box::new(SigningLogger::new(logger, signing_key));
+        }
+        // The records would tell what an encrypted log keeps to itself, so they are sealed as its entries are
+        if let Some((path, rotation)) = self.history {
+            let history = HistoryLogger::new(logger, path, rotation);
+            #[cfg(feature = "encryption")]
+            let history = match self.log_cipher.clone() {
+                Some(cipher) => history.with_cipher(cipher),
+                None => history,
+            };
+            logger = Box::new(history);
+        }
+        self.deployment.backend = backend.name().to_string();
+        let faults = backend.fault_injector();
+        let mut machine = StateMachine::with_clock(self.clock.unwrap_or_else(system_clock));
+        machine.set_min_toggle_interval(self.min_toggle_interval);
+        for hook in self.transition_hooks {
+            machine.add_hook(hook);
+        }
+        let journal = self.journal.map(Journal::new);
+        // The journal holds entries on their way to the log, so it is sealed as the log is
+        #[cfg(feature = "encryption")]
+        let journal = match (journal, self.log_cipher) {
+            (Some(journal), Some(cipher)) => Some(journal.with_cipher(cipher)),
+            (journal, _) => journal,
+        };
+        let usage_cache = self.usage_cache.map(UsageCache::new);
+        let light = LightHandle::spawn_with_log_privacy(machine, backend, logger, self.tag_rules, journal, self.offline_retry, usage_cache, self.privacy.clone())
+            .with_state_ttl(self.state_ttl)
+            .with_brightness_curve(self.brightness)
+            .with_brightness_schedule(self.brightness_schedule);
+        if let Some(keep_days) = self.compaction {
+            spawn_log_compaction(light.clone(), keep_days);
+        }
+        #[cfg(feature = "adaptive")]
+        let adaptive = AdaptiveBrightness::new(self.adaptive.clone());
+        #[cfg(feature = "motion")]
+        let motion = MotionLighting::new(self.motion);
+        #[cfg(feature = "contact")]
+        let contacts = ContactSensors::new(self.contacts);
+        #[cfg(feature = "mqtt")]
+        if let Some((config, publisher)) = self.state_publisher {
+            #[cfg(feature = "adaptive")]
+            if let Some(topic) = self.adaptive.and_then(|adaptive| adaptive.topic) {
+                spawn_lux_subscriber(adaptive.clone(), publisher.clone(), topic, light.clone());
+            }
+            #[cfg(feature = "motion")]
+            spawn_motion_subscribers(motion.clone(), publisher.clone(), light.clone());
+            #[cfg(feature = "contact")]
+            spawn_contact_subscribers(contacts.clone(), publisher.clone(), light.clone());
+            spawn_state_publisher(config, publisher, light.clone());
+        }
+        #[cfg(feature = "triggers")]
+        if let Some((triggers, sender)) = self.triggers {
+            spawn_triggers(triggers, sender, light.events(), light.clock().clone());
+        }
+        #[cfg(feature = "notifications")]
+        if let Some((notifiers, sender)) = self.notifiers {
+            spawn_notifiers(notifiers, sender, light.events());
+        }
+        #[cfg(feature = "safety")]
+        if let Some(safety) = self.safety {
+            spawn_safety_watchdog(safety, light.clone());
+        }
+        #[cfg(feature = "weather")]
+        if let Some((weather, source)) = self.weather {
+            spawn_weather_scheduler(weather, source, light.clone());
+        }
+        #[cfg(feature = "scenes")]
+        let scenes = Scenes::new(&self.scenes);
+        #[cfg(feature = "schedules")]
+        let automation = Automation::new(scenes.clone(), Schedules::new(&self.schedules), self.automation_file);
+        #[cfg(feature = "schedules")]
+        {
+            if let Err(e) = automation.load() {
+                crate::diagnostic!(Warn, "{:#}", e);
+            }
+            if let Err(e) = automation.timers.load() {
+                crate::diagnostic!(Warn, "{:#}", e);
+            }
+            spawn_scheduler(automation.clone(), light.clone());
+            for timer in automation.timers.all() {
+                spawn_timer(automation.timers.clone(), light.clone(), timer);
+            }
+        }
+        #[cfg(feature = "bulbs")]
+        let bulbs = Bulbs::new(light.clone(), self.room, bulb_actors);
+        #[cfg(feature = "bulbs")]
+        for (config, backend) in self.bulbs {
+            if let Err(e) = bulbs.add(&config, backend) {
+                crate::diagnostic!(Warn, "bulb '{}' was not added: {}", config.id, e);
+            }
+        }
+        let resources_since = light.clock().now();
+        #[cfg(feature = "jobs")]
+        let jobs = JobRegistry::new(light.clock().clone());
+        #[allow(unused_mut)]
+        let mut tool_router = LightService::router_for(&self.groups);
+        #[cfg(feature = "effects")]
+        if let Some(route) = tool_router.map.get_mut("start_effect") {
+            // The schema names the registered effects rather than only the presets it was derived with
+            let schema = Arc::make_mut(&mut route.attr.input_schema);
+            if let Some(effect) = schema.get_mut("properties").and_then(|properties| properties.get_mut("effect")) {
+                effect["enum"] = serde_json::json!(self.effect_registry.names());
+            }
+        }
+        LightService {
+            tool_router,
+            faults,
+            #[cfg(feature = "webhooks")]
+            webhook_deliveries: self
+                .webhooks
+                .map(|(webhooks, transport)| spawn_dispatchers(webhooks, transport, light.events(), light.clock().clone())),
+            light,
+            verifying_key,
+            effects: EffectRunner::new(),
+            #[cfg(feature = "effects")]
+            effect_registry: Arc::new(self.effect_registry),
+            #[cfg(feature = "adaptive")]
+            adaptive,
+            #[cfg(feature = "motion")]
+            motion,
+            #[cfg(feature = "presence")]
+            presence: self.presence,
+            #[cfg(feature = "contact")]
+            contacts,
+            #[cfg(feature = "scenes")]
+            scenes,
+            #[cfg(feature = "schedules")]
+            automation,
+            #[cfg(feature = "bulbs")]
+            bulbs,
+            #[cfg(feature = "analytics")]
+            tariff: self.tariff,
+            #[cfg(feature = "jobs")]
+            jobs,
+            #[cfg(feature = "history")]
+            privacy: self.privacy,
+            deployment: self.deployment,
+            started_at: Instant::now(),
+            resources_since,
+            sizes: Arc::default(),
+            idempotency: IdempotencyCache::default(),
+            confirmations: Confirmations::default(),
+            guest: false,
+            admin: false,
+            curfews: Arc::new(self.curfews),
+            tool_timeouts: Arc::new(self.tool_timeouts),
+            dry_run: false,
+            log_level: SessionLogLevel::default(),
+            subscriptions: ResourceSubscriptions::default(),
+            resource_updates: tokio::sync::broadcast::channel(RESOURCE_UPDATES).0,
+            response_detail: Arc::default(),
+        }
+    }
+}
+
+impl LightService {
+    pub fn builder() -> LightServiceBuilder {
+        LightServiceBuilder {
+            logger: None,
+            backend: None,
+            signing_key: None,
+            #[cfg(feature = "encryption")]
+            log_cipher: None,
+            clock: None,
+            tag_rules: Vec::new(),
+            privacy: LogPrivacy::default(),
+            curfews: Curfews::default(),
+            tool_timeouts: ToolTimeoutsConfig::default(),
+            state_ttl: DEFAULT_STATE_TTL,
+            min_toggle_interval: Duration::ZERO,
+            offline_retry: None,
+            brightness: BrightnessCurve::default(),
+            brightness_schedule: BrightnessSchedule::default(),
+            compaction: None,
+            journal: None,
+            usage_cache: None,
+            history: None,
+            transition_hooks: Vec::new(),
+            groups: ToolGroup::ALL.to_vec(),
+            deployment: Deployment::default(),
+            #[cfg(feature = "webhooks")]
+            webhooks: None,
+            #[cfg(feature = "mqtt")]
+            state_publisher: None,
+            #[cfg(feature = "triggers")]
+            triggers: None,
+            #[cfg(feature = "notifications")]
+            notifiers: None,
+            #[cfg(feature = "adaptive")]
+            adaptive: None,
+            #[cfg(feature = "motion")]
+            motion: Vec::new(),
+            #[cfg(feature = "presence")]
+            presence: Presence::default(),
+            #[cfg(feature = "contact")]
+            contacts: Vec::new(),
+            #[cfg(feature = "scenes")]
+            scenes: Vec::new(),
+            #[cfg(feature = "schedules")]
+            schedules: Vec::new(),
+            #[cfg(feature = "schedules")]
+            automation_file: None,
+            #[cfg(feature = "bulbs")]
+            room: None,
+            #[cfg(feature = "bulbs")]
+            bulbs: Vec::new(),
+            #[cfg(feature = "bulbs")]
+            bulb_logs: None,
+            #[cfg(feature = "analytics")]
+            tariff: None,
+            #[cfg(feature = "safety")]
+            safety: None,
+            #[cfg(feature = "weather")]
+            weather: None,
+            #[cfg(feature = "effects")]
+            effect_registry: EffectRegistry::with_builtin(),
+        }
+    }
+
+    fn router_for(groups: &[ToolGroup]) -> ToolRouter<Self> {
+        let mut router = ToolRouter::new();
+        for group in groups {
+            router.merge(group.router());
+        }
+        router
+    }
+
+    // Mutating tools report the position their command was applied in
+    // A failure the backend caused names the backend
+    fn error_context(&self, error: ErrorData) -> ErrorData {
+        match error.data.as_ref().and_then(|data| data["code"].as_str()) {
+            Some("BACKEND_UNREACHABLE") => with_context(error, "backend", self.deployment.backend.clone()),
+            _ => error,
+        }
+    }
+
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    fn with_sequence(message: &str, sequence: u64) -> String {
+        format!("{} (sequence {})", message, sequence)
+    }
+
+    // The answer to a change held in the offline queue, `change` saying what it will do, e.g. "turn it ON"
+    #[cfg_attr(not(any(feature = "core", feature = "contact", feature = "scenes")), allow(dead_code))]
+    fn queued(change: &str, sequence: u64) -> String {
+        Self::with_sequence(
+            &format!("Queued, not yet applied: the lightbulb is unreachable, so this will {} once it answers (see get_offline_queue)", change),
+            sequence,
+        )
+    }
+
+    // A status or confirmation message, which a template in the config may reword with the bulb's state and
+    // brightness
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    async fn say(&self, message: Message) -> String {
+        match self.light.snapshot().await {
+            Ok(snapshot) => message.text(&[("state", &snapshot.state.to_lowercase()), ("brightness", &snapshot.brightness)]),
+            Err(_) => message.text(&[]),
+        }
+    }
+
+    // Makes a change once per idempotency key, wording its confirmation at the detail the call asked for, or
+    // else the session
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    async fn run_change(
+        &self,
+        request: &ChangeRequest,
+        tool: &str,
+        change: impl Future<Output = Result<String, ErrorData>>,
+    ) -> Result<String, ErrorData> {
+        self.run_change_on(&self.light, request, tool, change).await
+    }
+
+    // As `run_change`, for a change to the bulb `light` switches
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    async fn run_change_on(
+        &self,
+        light: &LightHandle,
+        request: &ChangeRequest,
+        tool: &str,
+        change: impl Future<Output = Result<String, ErrorData>>,
+    ) -> Result<String, ErrorData> {
+        let detail = request.detail.unwrap_or_else(|| *self.response_detail.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        let before = light.snapshot().await?;
+        let text = self.idempotency.run(request.idempotency_key.as_deref(), tool, change).await?;
+        let after = light.snapshot().await?;
+        Ok(match detail {
+            ResponseDetail::Brief => format!("{}, version {}", after.state, after.version),
+            ResponseDetail::Normal => text,
+            ResponseDetail::Detailed => {
+                let by = after.changed_by.as_deref().unwrap_or("an unnamed caller");
+                let at = after.last_changed.map(|at| at.to_rfc3339_opts(SecondsFormat::Secs, true)).unwrap_or_else(|| "an unknown time".to_string());
+                format!("{}; was {} (version {}), now {} (version {}), changed by {} at {}", text, before.state, before.version, after.state, after.version, by, at)
+            },
+        })
+    }
+
+    // A dry run's answer to a power, lock, color or history change, which is neither made nor remembered for
+    // its idempotency key
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    async fn dry_run_change(&self, request: &ChangeRequest, change: Change) -> Result<String, ErrorData> {
+        self.dry_run_change_on(&self.light, request, change).await
+    }
+
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    async fn dry_run_change_on(&self, light: &LightHandle, request: &ChangeRequest, change: Change) -> Result<String, ErrorData> {
+        let previews = self.change_handle_on(light, request)?.preview(vec![change]).await?;
+        let preview = previews.into_iter().next().ok_or(LightError::Internal("a dry run previewed nothing".to_string()))??;
+        Ok(format!("{}: {}", self.dry_run_prefix(), Self::describe_preview(&preview)))
+    }
+
+    #[cfg_attr(not(any(feature = "core", feature = "history", feature = "effects", feature = "macros", feature = "simulation")), allow(dead_code))]
+    fn dry_run_prefix(&self) -> String {
+        format!("Dry run against the {} backend, nothing was changed", self.deployment.backend)
+    }
+
+    #[cfg_attr(not(any(feature = "core", feature = "history", feature = "macros")), allow(dead_code))]
+    fn describe_preview(preview: &Preview) -> String {
+        let mut text = match preview.changed {
+            true => format!("the lightbulb would go from {} to {}", preview.from, preview.to),
+            false => format!("the lightbulb would stay {}", preview.to),
+        };
+        if let Some(entry) = &preview.log_entry {
+            text.push_str(&format!(" and the log would record \"{}\"", entry));
+        }
+        text
+    }
+
+    // The backend's capabilities, with the level each brightness is sent as so the gamma correction can be checked
+    fn bulb_metadata(&self) -> serde_json::Value {
+        let metadata = self.light.metadata();
+        let mut value = serde_json::to_value(metadata).unwrap_or_default();
+        let levels: serde_json::Map<String, serde_json::Value> =
+            [1, 25, 50, 75, 100].into_iter().map(|percent| (format!("{}%", percent), metadata.brightness.output(percent).into())).collect();
+        value["brightness"]["max_level"] = MAX_OUTPUT.into();
+        value["brightness"]["levels"] = levels.into();
+        if let Some(faults) = &self.faults {
+            value["firmware"] = faults.firmware().into();
+        }
+        value
+    }
+
+    // The handle a change goes through, carrying its precondition, reason and tags
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    fn change_handle(&self, request: &ChangeRequest) -> Result<LightHandle, LightError> {
+        self.change_handle_on(&self.light, request)
+    }
+
+    #[cfg_attr(not(any(feature = "core", feature = "history")), allow(dead_code))]
+    fn change_handle_on(&self, light: &LightHandle, request: &ChangeRequest) -> Result<LightHandle, LightError> {
+        // Log entries are one line each, so line breaks in the reason are flattened
+        let reason = request
+            .reason
+            .as_deref()
+            .map(|reason| reason.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|reason| !reason.is_empty());
+        if let Some(reason) = &reason
+            && reason.chars().count() > MAX_REASON_LEN
+        {
+            return Err(LightError::InvalidParameter(format!("The reason must be at most {} characters", MAX_REASON_LEN)));
+        }
+        let tags = normalize_tags(request.tags.as_deref().unwrap_or_default())?;
+        Ok(light.expecting(request.expected_version).with_reason(reason).tagged(tags))
+    }
+
+    // The bulb a call names by id, name or alias, acting as this session; the server's own bulb when it names none
+    #[cfg_attr(not(feature = "core"), allow(dead_code))]
+    fn bulb_handle(&self, bulb_id: Option<&str>) -> Result<LightHandle, LightError> {
+        match bulb_id.map(str::trim) {
+            None | Some(MAIN_BULB) => Ok(self.light.clone()),
+            #[cfg(feature = "bulbs")]
+            Some(key) => match self.bulbs.get(key)? {
+                bulb if bulb.id == MAIN_BULB => Ok(self.light.clone()),
+                bulb => Ok(bulb.light.acting_like(&self.light)),
+            },
+            #[cfg(not(feature = "bulbs"))]
+            Some(id) => Err(LightError::UnknownBulb(id.to_string())),
+        }
+    }
+
+    // The id and name of the bulb a call names, once bulb_handle has found it
+    #[cfg_attr(not(feature = "core"), allow(dead_code))]
+    fn bulb_names(&self, bulb_id: Option<&str>) -> (String, Option<String>) {
+        let key = bulb_id.map(str::trim).unwrap_or(MAIN_BULB);
+        #[cfg(feature = "bulbs")]
+        if let Ok(bulb) = self.bulbs.get(key) {
+            return (bulb.id, bulb.name);
+        }
+        (key.to_string(), None)
+    }
+
+    #[cfg(any(feature = "audit", test))]
+    async fn read_log_content(&self) -> Result<String, LightError> {
+        self.light.read_log().await
+    }
+
+    async fn read_log_tail(&self, entries: usize) -> String {
+        match self.light.read_log_tail(entries).await {
+            Ok(tail) if tail.is_empty() => "No lightbulb activity recorded yet.".to_string(),
+            Ok(tail) => format!("Last {} lightbulb log entries:\n\n{}\n", tail.len(), tail.join("\n")),
+            Err(_) => "Lightbulb log file not found. No activity recorded yet.".to_string(),
+        }
+    }
+
+    // The numbered entries after entry `sequence`, for a reader catching up on what it has not seen yet
+    async fn read_log_since(&self, sequence: u64) -> String {
+        let Ok(log) = self.light.read_log().await else {
+            return "Lightbulb log file not found. No activity recorded yet.".to_string();
+        };
+        let mut entries = log.lines().filter(|line| parse_log_sequence(line).is_some_and(|seq| seq > sequence));
+        let page: Vec<&str> = entries.by_ref().take(MAX_TAIL_ENTRIES).collect();
+        let Some(last) = page.last().and_then(|line| parse_log_sequence(line)) else {
+            return format!("No lightbulb activity recorded since entry {}.", sequence);
+        };
+        let mut text = format!("Lightbulb activity since entry {}:\n\n{}\n", sequence, page.join("\n"));
+        if entries.next().is_some() {
+            text.push_str(&format!("{}{}{}\n", LOG_NEXT_PAGE, LOG_SINCE_PREFIX, last));
+        }
+        text
+    }
+
+    // The entry number of a log since URI
+    fn since_sequence(uri: &str) -> Result<u64, LightError> {
+        uri[LOG_SINCE_PREFIX.len()..]
+            .parse()
+            .map_err(|_| LightError::InvalidParameter(format!("{} takes the sequence number of an entry", LOG_SINCE_TEMPLATE)))
+    }
+
+    async fn read_log_day(&self, day: NaiveDate) -> String {
+        match self.light.read_log_range(day_start(day), day_start(day + TimeDelta::days(1))).await {
+            Ok(entries) if entries.is_empty() => format!("No lightbulb activity recorded on {} (UTC).", day),
+            Ok(entries) => format!("Lightbulb activity on {} (UTC):\n\n{}\n", day, entries.join("\n")),
+            Err(_) => "Lightbulb log file not found. No activity recorded yet.".to_string(),
+        }
+    }
+
+    // The UTC day a log day URI names
+    fn log_day(&self, uri: &str) -> Result<NaiveDate, LightError> {
+        let today = self.light.clock().now().date_naive();
+        match &uri[LOG_DAY_PREFIX.len()..] {
+            "today" => Ok(today),
+            "yesterday" => Ok(today - TimeDelta::days(1)),
+            date => NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| {
+                LightError::InvalidParameter(format!("{} takes a UTC date as YYYY-MM-DD, today or yesterday", LOG_DAY_TEMPLATE))
+            }),
+        }
+    }
+
+    // The whole log when it fits on one page, as it always has, and otherwise the page starting at `offset`
+    async fn read_log_page(&self, offset: Option<u64>) -> String {
+        let Ok(chunk) = self.light.read_log_chunk(offset.unwrap_or_default(), LOG_PAGE_BYTES).await else {
+            return "Lightbulb log file not found. No activity recorded yet.".to_string();
+        };
+        match (offset, chunk.next()) {
+            (None, None) if chunk.text.trim().is_empty() => "No lightbulb activity recorded yet.".to_string(),
+            (None, None) => format!("Lightbulb Activity Log:\n\n{}", chunk.text),
+            (_, next) => {
+                let mut page =
+                    format!("Lightbulb Activity Log (bytes {}-{} of {}):\n\n{}", chunk.offset, chunk.end, chunk.total, chunk.text);
+                if let Some(next) = next {
+                    page.push_str(&format!("{}{}{}\n", LOG_NEXT_PAGE, LOG_TEXT_PAGE_PREFIX, next));
+                }
+                page
+            },
+        }
+    }
+
+    // The same pages as read_log_page, with each entry taken apart, linked by `next` under `prefix`
+    async fn read_log_json(&self, offset: Option<u64>, prefix: &str) -> serde_json::Value {
+        let chunk = self.light.read_log_chunk(offset.unwrap_or_default(), LOG_PAGE_BYTES).await;
+        let chunk = chunk.unwrap_or(LogChunk { text: String::new(), offset: 0, end: 0, total: 0 });
+        let entries: Vec<LogRecord> = chunk.text.lines().filter(|line| !line.trim().is_empty()).map(LogRecord::parse).collect();
+        serde_json::json!({
+            "offset": chunk.offset,
+            "end": chunk.end,
+            "total_bytes": chunk.total,
+            "entries": entries,
+            "next": chunk.next().map(|next| format!("{}{}", prefix, next)),
+        })
+    }
+
+    // The byte offset of a log page URI, or None for the first page
+    fn page_offset(uri: &str, prefix: &str, template: &str) -> Result<Option<u64>, LightError> {
+        match uri.strip_prefix(prefix).map(str::parse) {
+            Some(Ok(offset)) => Ok(Some(offset)),
+            Some(Err(_)) => Err(LightError::InvalidParameter(format!("{} needs a byte offset", template))),
+            None => Ok(None),
+        }
+    }
+
+    // The entry count of a log tail URI, or the default for the bare resource
+    fn tail_entries(uri: &str) -> Result<usize, LightError> {
+        match uri.strip_prefix(LOG_TAIL_PREFIX).map(str::parse) {
+            Some(Ok(entries)) if (1..=MAX_TAIL_ENTRIES).contains(&entries) => Ok(entries),
+            Some(_) => Err(LightError::InvalidParameter(format!("{} takes between 1 and {} entries", LOG_TAIL_TEMPLATE, MAX_TAIL_ENTRIES))),
+            None => Ok(DEFAULT_TAIL_ENTRIES),
+        }
+    }
+
+    // When the resource at `uri` last changed, for listings and conditional reads; None for resources read afresh
+    // every time, and for ones that do not exist or the session cannot see
+    async fn last_modified(&self, uri: &str) -> Option<DateTime<Utc>> {
+        if self.guest && !uri.starts_with(COLORS_URI) {
+            return None;
+        }
+        let log = self.light.log_modified();
+        match uri {
+            LOG_URI | LOG_TEXT_URI | LOG_JSON_URI | LOG_TAIL_URI => log,
+            uri if uri.starts_with(LOG_SINCE_PREFIX) => Self::since_sequence(uri).ok().and(log),
+            uri if uri.starts_with(LOG_PAGE_PREFIX) => Self::page_offset(uri, LOG_PAGE_PREFIX, LOG_PAGE_TEMPLATE).ok().and(log),
+            uri if uri.starts_with(LOG_TEXT_PAGE_PREFIX) => Self::page_offset(uri, LOG_TEXT_PAGE_PREFIX, LOG_TEXT_TEMPLATE).ok().and(log),
+            uri if uri.starts_with(LOG_JSON_PAGE_PREFIX) => Self::page_offset(uri, LOG_JSON_PAGE_PREFIX, LOG_JSON_TEMPLATE).ok().and(log),
+            uri if uri.starts_with(LOG_TAIL_PREFIX) => Self::tail_entries(uri).ok().and(log),
+            uri if uri.starts_with(LOG_DAY_PREFIX) => self.log_day(uri).ok().and(log),
+            SUMMARY_URI => log.max(self.light.snapshot().await.ok()?.last_changed),
+            SUMMARY_JSON_URI | REPORT_MARKDOWN_URI | REPORT_HTML_URI => {
+                let snapshot = self.light.snapshot().await.ok()?;
+                // Today's time on goes up for as long as the bulb stays on
+                match snapshot.power {
+                    Some(PowerState::On) => Some(self.light.clock().now()),
+                    _ => log.max(snapshot.last_changed),
+                }
+            },
+            CONFIG_URI if self.deployment.config.is_some() => Some(self.resources_since),
+            #[cfg(feature = "schedules")]
+            SCENES_URI | SCHEDULES_URI => Some(self.automation.imported_at().unwrap_or(self.resources_since)),
+            #[cfg(all(feature = "scenes", not(feature = "schedules")))]
+            SCENES_URI => Some(self.resources_since),
+            #[cfg(feature = "effects")]
+            EFFECTS_URI => Some(self.resources_since),
+            #[cfg(feature = "effects")]
+            uri if uri.starts_with(EFFECT_URI_PREFIX) => {
+                percent_decode(&uri[EFFECT_URI_PREFIX.len()..]).and_then(|name| self.effect_registry.get(&name).map(|_| self.resources_since))
+            },
+            COLORS_URI => Some(self.resources_since),
+            uri => uri.strip_prefix(COLOR_URI_PREFIX).and_then(percent_decode).and_then(|name| resolve_color(&name)).map(|_| self.resources_since),
+        }
+    }
+
+    // The bytes a read of `uri` returns, or for the log the whole log however many pages it takes. Resources
+    // with a timestamp are only measured again once it moves on.
+    async fn size(&self, uri: &str, modified: Option<DateTime<Utc>>) -> Option<u32> {
+        if let Some(modified) = modified
+            && let Some(&(measured, size)) = self.lock_sizes().get(uri)
+            && measured == modified
+        {
+            return Some(size);
+        }
+        let bytes = match uri {
+            LOG_TEXT_URI => self.light.read_log_chunk(0, 0).await.ok()?.total,
+            uri => {
+                let read = self.read_resource_contents(ReadResourceRequestParam { uri: uri.to_string() }).await.ok()?;
+                read.contents
+                    .iter()
+                    .map(|contents| match contents {
+                        ResourceContents::TextResourceContents { text, .. } => text.len() as u64,
+                        ResourceContents::BlobResourceContents { .. } => 0,
+                    })
+                    .sum()
+            },
+        };
+        let size = u32::try_from(bytes).unwrap_or(u32::MAX);
+        if let Some(modified) = modified {
+            self.lock_sizes().insert(uri.to_string(), (modified, size));
+        }
+        Some(size)
+    }
+
+    fn lock_sizes(&self) -> std::sync::MutexGuard<'_, MeasuredSizes> {
+        self.sizes.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // The time a conditional read passed as `ifModifiedSince` in its `_meta`
+    fn if_modified_since(meta: &Meta) -> Result<Option<DateTime<Utc>>, LightError> {
+        let Some(since) = meta.get(IF_MODIFIED_SINCE) else {
+            return Ok(None);
+        };
+        since
+            .as_str()
+            .and_then(|since| DateTime::parse_from_rfc3339(since).ok())
+            .map(|since| Some(since.with_timezone(&Utc)))
+            .ok_or_else(|| LightError::InvalidParameter(format!("{} must be an RFC 3339 time, e.g. a resource's listed timestamp", IF_MODIFIED_SINCE)))
+    }
+
+    fn contents(uri: &str, mime_type: &str, text: String) -> ResourceContents {
+        ResourceContents::TextResourceContents { uri: uri.to_string(), mime_type: Some(mime_type.to_string()), text }
+    }
+
+    fn palette_resource() -> Resource {
+        Resource {
+            raw: RawResource {
+                uri: COLORS_URI.to_string(),
+                name: "Lightbulb Color Palette".to_string(),
+                description: Some("Color names and descriptions understood by set_color_by_name, with their RGB values".to_string()),
+                mime_type: Some("text/plain".to_string()),
+                size: None,
+            },
+            annotations: None,
+        }
+    }
+
+    fn describe_palette() -> String {
+        let list = |colors: &[(&str, Color)]| {
+            colors.iter().map(|(name, color)| format!("  {} {}", color, name)).collect::<Vec<_>>().join("\n")
+        };
+        format!(
+            "Lightbulb Color Palette:\n\n\
+            Lighting descriptions:\n{}\n\n\
+            CSS/X11 colors:\n{}\n\n\
+            Any #rrggbb hex code is also accepted.",
+            list(DESCRIPTIONS),
+            list(CSS_COLORS),
+        )
+    }
+
+    // Each scene with the color it sets on this bulb, after moving its temperature into the bulb's range
+    #[cfg(feature = "scenes")]
+    fn describe_scenes(&self) -> serde_json::Value {
+        let range = self.light.metadata().mired_range;
+        let scenes: Vec<serde_json::Value> = self
+            .scenes
+            .all()
+            .iter()
+            .map(|scene| {
+                serde_json::json!({
+                    "name": scene.name,
+                    "brightness": scene.brightness,
+                    "kelvin": scene.kelvin,
+                    "color": scene.color.or_else(|| scene.color_for(range)).map(|color| color.to_string()),
+                    "source": scene.source.label(),
+                })
+            })
+            .collect();
+        serde_json::Value::Array(scenes)
+    }
+
+    // Tells every session subscribed to `uri` that it changed
+    #[cfg(feature = "schedules")]
+    fn resource_updated(&self, uri: &str) {
+        // Nobody listening is no one to tell
+        let _ = self.resource_updates.send(uri.to_string());
+    }
+
+    #[cfg(feature = "schedules")]
+    fn describe_schedules(&self) -> serde_json::Value {
+        let schedules: Vec<serde_json::Value> = self
+            .automation
+            .schedules
+            .all()
+            .iter()
+            .map(|schedule| {
+                serde_json::json!({
+                    "name": schedule.config.name,
+                    "runs": schedule.describe(),
+                    "source": if schedule.imported { "imported" } else { "config" },
+                })
+            })
+            .collect();
+        serde_json::Value::Array(schedules)
+    }
+
+    #[cfg(feature = "effects")]
+    fn describe_effect(effect: &crate::registry::RegisteredEffect) -> String {
+        format!("  {}: {} (steps of {}ms)", effect.name, effect.description, effect.period.as_millis())
+    }
+
+    async fn generate_usage_summary(&self) -> String {
+        match self.light.usage().await {
+            Ok(usage) => {
+                if usage.total == 0 {
+                    return "Lightbulb Usage Summary:\n\nNo activity recorded yet.".to_string();
+                }
+                
+                let total_actions = usage.total;
+                let on_actions = usage.on;
+                let off_actions = usage.off;
+                
+                let current_status = match self.light.state().await {
+                    Ok(state) => state.label(),
+                    Err(_) => "UNKNOWN",
+                };
+                
+                format!(
+                    "Lightbulb Usage Summary:\n\n\
+                    Current Status: {}\n\
+                    Total Actions: {}\n\
+                    - Turn ON actions: {} ({:.1}%)\n\
+                    - Turn OFF actions: {} ({:.1}%)\n\n\
+                    Activity Period:\n\
+                    - First action: {}\n\
+                    - Last action: {}\n\n\
+                    Recent Activity (last 5 actions):\n{}",
+                    current_status,
+                    total_actions,
+                    on_actions,
+                    if total_actions > 0 { (on_actions as f64 / total_actions as f64) * 100.0 } else { 0.0 },
+                    off_actions,
+                    if total_actions > 0 { (off_actions as f64 / total_actions as f64) * 100.0 } else { 0.0 },
+                    usage.first.unwrap_or("N/A".to_string()),
+                    usage.last.unwrap_or("N/A".to_string()),
+                    usage.recent.iter().map(|line| format!("  {}", line)).collect::<Vec<_>>().join("\n")
+                )
+            },
+            Err(_) => "Lightbulb Usage Summary:\n\nLog file not found. No activity recorded yet.".to_string(),
+        }
+    }
+
+    // An unreadable log reports no activity, as the summary does
+    async fn usage_report(&self) -> Report {
+        let usage = self.light.usage().await.unwrap_or_default();
+        let status = self.light.state().await.map_or("UNKNOWN", |state| state.label());
+        Report::new(&usage, status, self.light.clock().now())
+    }
+
+    pub fn new_with_logger(logger: Box<dyn Logger + Send + Sync>) -> Self {
+        Self::builder().logger(logger).build()
+    }
+
+    pub fn new_with_logger_and_backend(
+        logger: Box<dyn Logger + Send + Sync>,
+        backend: Box<dyn LightBackend + Send + Sync>,
+    ) -> Self {
+        Self::builder().logger(logger).backend(backend).build()
+    }
+
+    pub fn new_with_signing_logger(logger: Box<dyn Logger + Send + Sync>, signing_key: SigningKey) -> Self {
+        Self::builder().logger(logger).signing_key(signing_key).build()
+    }
+
+    // The service run by the binary, over stdio
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self::session_factory(&Config::load()?, &BackendRegistry::with_builtin(), "stdio")?.create())
+    }
+
+    // Sandbox sessions have no shared bulb for integrations to watch, so those must not be configured
+    pub fn session_factory(config: &Config, registry: &BackendRegistry, transport: &str) -> anyhow::Result<SessionFactory> {
+        if config.sessions == SessionMode::Shared {
+            return Ok(SessionFactory::Shared(Self::builder_from_config(config, registry)?.transport(transport).build()));
+        }
+        let integrations = [
+            ("webhooks", !config.webhooks.is_empty()),
+            ("mqtt", config.mqtt.is_some()),
+            ("triggers", !config.triggers.is_empty()),
+            ("notifications", !config.notifications.is_empty()),
+            ("weather", config.weather.is_some()),
+            ("rest", config.rest.is_some()),
+        ];
+        if let Some((name, _)) = integrations.iter().find(|(_, configured)| *configured) {
+            anyhow::bail!("Sandbox sessions have no shared bulb, so `{}` cannot be configured with them", name);
+        }
+        let signing_key = config.signing_key.as_deref().map(load_or_create_signing_key).transpose()?;
+        let deployment = Deployment {
+            transport: Some(transport.to_string()),
+            config_file: config.source.clone(),
+            signing_key_file: config.signing_key.clone(),
+            available_backends: registry.names().into_iter().map(String::from).collect(),
+            config: Some(config.redacted()),
+            ..Deployment::default()
+        };
+        Ok(SessionFactory::Sandbox { signing_key, deployment })
+    }
+
+    // The same bulb with only the toggle and status tools, and without the log, summary and admin resources
+    pub fn guest(&self) -> Self {
+        let mut tool_router = ToolRouter::new();
+        for route in self.tool_router.clone() {
+            if GUEST_TOOLS.contains(&route.name()) {
+                tool_router.add_route(route);
+            }
+        }
+        Self { tool_router, guest: true, ..self.clone() }
+    }
+
+    // The same bulb, held to no curfew
+    pub fn admin(&self) -> Self {
+        Self { admin: true, ..self.clone() }
+    }
+
+    // The session as the request's bearer token may use it, where that is not as the session was made: a guest
+    // key's requests are served as the guest session's and an admin key's as the admin one's, so over HTTP each
+    // client is held to its own key. A guest session stays one whatever key reaches it.
+    fn scoped(&self, _context: &RequestContext<rmcp::RoleServer>) -> Option<Self> {
+        #[cfg(feature = "http")]
+        match request_key(_context) {
+            Some(crate::auth::KeyAccess::Guest) if !self.guest => return Some(self.guest()),
+            Some(crate::auth::KeyAccess::Admin) if !self.guest && !self.admin => return Some(self.admin()),
+            _ => {},
+        }
+        None
+    }
+
+    // The same bulb, with every tool that would change it, its backend or the log only saying what it would do
+    pub fn dry_run(&self) -> Self {
+        Self { dry_run: true, ..self.clone() }
+    }
+
+    // The light behind the tools, for serving it over other interfaces
+    // Shares the bulb, but not per-session settings such as the logging level
+    fn new_session(&self) -> Self {
+        Self { log_level: SessionLogLevel::default(), subscriptions: ResourceSubscriptions::default(), response_detail: Arc::default(), ..self.clone() }
+    }
+
+    pub fn light(&self) -> &LightHandle {
+        &self.light
+    }
+
+    // Stops any running effect, letting it restore the bulb, then writes the log's final SHUTDOWN entry
+    pub async fn shutdown(&self, cause: &str) -> Result<(), LightError> {
+        if let Some(effect) = self.effects.stop().await {
+            crate::diagnostic!(Info, "stopped effect {} for shutdown", effect);
+        }
+        // A dry run leaves the log as it found it
+        if self.dry_run {
+            return Ok(());
+        }
+        #[cfg(feature = "bulbs")]
+        for bulb in self.bulbs.all().into_iter().filter(|bulb| bulb.id != MAIN_BULB) {
+            if let Err(e) = bulb.light.shutdown(cause).await {
+                crate::diagnostic!(Warn, "bulb '{}' did not log its shutdown: {}", bulb.id, e);
+            }
+        }
+        self.light.shutdown(cause).await
+    }
+
+    pub fn from_config(config: &Config, registry: &BackendRegistry) -> anyhow::Result<Self> {
+        Ok(Self::builder_from_config(config, registry)?.build())
+    }
+
+    // For production, use file logger, signing entries when a key file is configured
+    pub fn builder_from_config(config: &Config, registry: &BackendRegistry) -> anyhow::Result<LightServiceBuilder> {
+        let mut builder = Self::builder()
+            .logger(Box::new(FileLogger::new(config.log_file.clone())))
+            .backend(registry.create(&config.backend)?)
+            .state_ttl(config.backend.state_ttl())
+            .min_toggle_interval(config.backend.min_toggle_interval())
+            .offline_queue(config.backend.offline_retry())
+            .brightness_curve(BrightnessCurve::new(config.backend.gamma)?)
+            .brightness_schedule(BrightnessSchedule::new(&config.brightness_schedule)?);
+        if let Some(key_path) = &config.signing_key {
+            builder = builder.signing_key(load_or_create_signing_key(key_path)?);
+        }
+        if let Some(compaction) = &config.log_compaction {
+            builder = builder.log_compaction(compaction.keep_days);
+        }
+        if config.journal {
+            builder = builder.journal(journal_path(&config.log_file));
+        }
+        if let Some(encryption) = &config.log_encryption {
+            encryption.validate()?;
+            #[cfg(feature = "encryption")]
+            {
+                let cipher = match encryption.passphrase()? {
+                    Some(passphrase) => LogCipher::from_passphrase(&passphrase, &salt_path(&config.log_file))?,
+                    None => LogCipher::from_key_file(encryption.key_file.as_deref().unwrap_or_default())?,
+                };
+                builder = builder.log_cipher(cipher);
+            }
+            #[cfg(not(feature = "encryption"))]
+            anyhow::bail!("Log encryption is configured but this build does not include the `encryption` feature");
+        }
+        // Saved counters would tell what an encrypted log keeps to itself
+        if config.stats_cache && config.log_encryption.is_none() {
+            builder = builder.usage_cache(usage_cache_path(&config.log_file));
+        }
+        config.log_history.validate()?;
+        if config.log_history.enabled {
+            builder = builder.history_file(history_path(&config.log_file), config.log_history.rotation());
+        }
+        let tag_rules = config.tag_rules.iter().cloned().map(TagRule::new).collect::<anyhow::Result<_>>()?;
+        builder = builder.tag_rules(tag_rules);
+        config.log_privacy.validate()?;
+        builder = builder.log_privacy(LogPrivacy::new(&config.log_privacy));
+        let curfews = Curfews::new(&config.curfews)?;
+        let tools = Self::router_for(ToolGroup::ALL);
+        for curfew in curfews.iter() {
+            if !curfew.bulbs().is_empty() {
+                anyhow::bail!("Curfew '{}' names bulbs, which only an aggregator has; this server's curfews cover its one bulb", curfew.name());
+            }
+            if let Some(tool) = curfew.tools().iter().find(|tool| !tools.has_route(tool)) {
+                anyhow::bail!("Curfew '{}' covers '{}', which is not a tool of this server", curfew.name(), tool);
+            }
+        }
+        builder = builder.curfews(curfews);
+        if let Some(tool) = config.tool_timeouts.tools.keys().find(|tool| !tools.has_route(tool)) {
+            anyhow::bail!("[tool_timeouts] sets a timeout for '{}', which is not a tool of this server", tool);
+        }
+        builder = builder.tool_timeouts(config.tool_timeouts.clone());
+        if let Some(room) = &config.room {
+            validate_room(room)?;
+        }
+        for (i, bulb) in config.bulbs.iter().enumerate() {
+            bulb.validate()?;
+            if config.bulbs[..i].iter().any(|other| other.id == bulb.id) {
+                anyhow::bail!("Bulb '{}' is configured twice", bulb.id);
+            }
+            let names = |bulb: &crate::config::BulbConfig| {
+                let names = std::iter::once(bulb.id.clone()).chain(bulb.name.clone()).chain(bulb.aliases.clone());
+                names.map(|name| name.to_lowercase()).collect::<Vec<_>>()
+            };
+            if let Some(other) = config.bulbs[..i].iter().find(|other| names(other).iter().any(|name| names(bulb).contains(name))) {
+                anyhow::bail!("Bulbs '{}' and '{}' share a name or alias", other.id, bulb.id);
+            }
+        }
+        #[cfg(feature = "bulbs")]
+        {
+            builder = builder.bulb_logs(config.log_file.clone());
+            if let Some(room) = &config.room {
+                builder = builder.room(room.clone());
+            }
+            for bulb in &config.bulbs {
+                builder = builder.bulb(bulb.clone(), registry.create(&bulb.backend)?);
+            }
+        }
+        #[cfg(not(feature = "bulbs"))]
+        if config.room.is_some() || !config.bulbs.is_empty() {
+            anyhow::bail!("Bulbs are configured but this build does not include the `bulbs` feature");
+        }
+        for webhook in &config.webhooks {
+            webhook.validate()?;
+        }
+        if !config.webhooks.is_empty() {
+            #[cfg(feature = "webhooks")]
+            {
+                builder = builder.webhooks(config.webhooks.clone(), Arc::new(HttpTransport::new()?));
+            }
+            #[cfg(not(feature = "webhooks"))]
+            anyhow::bail!("Webhooks are configured but this build does not include the `webhooks` feature");
+        }
+        if let Some(mqtt) = &config.mqtt {
+            mqtt.validate()?;
+            #[cfg(feature = "mqtt")]
+            {
+                builder = builder.state_publisher(mqtt.clone(), Arc::new(MqttClient::connect(mqtt)));
+            }
+            #[cfg(not(feature = "mqtt"))]
+            anyhow::bail!("MQTT publishing is configured but this build does not include the `mqtt` feature");
+        }
+        for trigger in &config.triggers {
+            trigger.validate()?;
+        }
+        if !config.triggers.is_empty() {
+            #[cfg(feature = "triggers")]
+            {
+                let triggers = config.triggers.iter().cloned().map(Trigger::new).collect::<anyhow::Result<_>>()?;
+                builder = builder.triggers(triggers, Arc::new(HttpSender::new()?));
+            }
+            #[cfg(not(feature = "triggers"))]
+            anyhow::bail!("Triggers are configured but this build does not include the `triggers` feature");
+        }
+        for notification in &config.notifications {
+            notification.validate()?;
+        }
+        if !config.notifications.is_empty() {
+            #[cfg(feature = "notifications")]
+            {
+                let notifiers = config.notifications.iter().cloned().map(Notifier::new).collect::<anyhow::Result<_>>()?;
+                builder = builder.notifiers(notifiers, Arc::new(HttpChatSender::new()?));
+            }
+            #[cfg(not(feature = "notifications"))]
+            anyhow::bail!("Notifications are configured but this build does not include the `notifications` feature");
+        }
+        if let Some(adaptive) = &config.adaptive_brightness {
+            adaptive.validate()?;
+            if adaptive.topic.is_some() && config.mqtt.is_none() {
+                anyhow::bail!("Adaptive brightness reads its topic from the MQTT broker, so it needs an [mqtt] section");
+            }
+            #[cfg(feature = "adaptive")]
+            {
+                builder = builder.adaptive_brightness(adaptive.clone());
+            }
+            #[cfg(not(feature = "adaptive"))]
+            anyhow::bail!("Adaptive brightness is configured but this build does not include the `adaptive` feature");
+        }
+        for (i, room) in config.motion.iter().enumerate() {
+            room.validate()?;
+            if config.motion[..i].iter().any(|other| other.room == room.room) {
+                anyhow::bail!("Motion room '{}' is configured twice", room.room);
+            }
+            if room.topic.is_some() && config.mqtt.is_none() {
+                anyhow::bail!("Motion room '{}' reads its topic from the MQTT broker, so it needs an [mqtt] section", room.room);
+            }
+        }
+        if !config.motion.is_empty() {
+            #[cfg(feature = "motion")]
+            {
+                builder = builder.motion_rooms(config.motion.clone());
+            }
+            #[cfg(not(feature = "motion"))]
+            anyhow::bail!("Motion rooms are configured but this build does not include the `motion` feature");
+        }
+        for (i, sensor) in config.contact.iter().enumerate() {
+            sensor.validate()?;
+            if config.contact[..i].iter().any(|other| other.sensor == sensor.sensor) {
+                anyhow::bail!("Contact sensor '{}' is configured twice", sensor.sensor);
+            }
+            if !sensor.bulbs.is_empty() {
+                anyhow::bail!("Contact sensor '{}' names bulbs, which only an aggregator has", sensor.sensor);
+            }
+            if sensor.topic.is_some() && config.mqtt.is_none() {
+                anyhow::bail!("Contact sensor '{}' reads its topic from the MQTT broker, so it needs an [mqtt] section", sensor.sensor);
+            }
+        }
+        if !config.contact.is_empty() {
+            #[cfg(feature = "contact")]
+            {
+                builder = builder.contact_sensors(config.contact.clone());
+            }
+            #[cfg(not(feature = "contact"))]
+            anyhow::bail!("Contact sensors are configured but this build does not include the `contact` feature");
+        }
+        for (i, scene) in config.scenes.iter().enumerate() {
+            scene.validate()?;
+            if config.scenes[..i].iter().any(|other| other.name == scene.name) {
+                anyhow::bail!("Scene '{}' is configured twice", scene.name);
+            }
+        }
+        if !config.scenes.is_empty() {
+            #[cfg(feature = "scenes")]
+            {
+                builder = builder.scenes(config.scenes.clone());
+            }
+            #[cfg(not(feature = "scenes"))]
+            anyhow::bail!("Scenes are configured but this build does not include the `scenes` feature");
+        }
+        #[cfg(feature = "schedules")]
+        {
+            let problems = schedule_problems(&config.schedules, &Scenes::new(&config.scenes), &[]);
+            if !problems.is_empty() {
+                anyhow::bail!("{}", problems.join("; "));
+            }
+            if !config.schedules.is_empty() {
+                builder = builder.schedules(config.schedules.clone());
+            }
+            if config.automation_file {
+                builder = builder.automation_file(automation_path(&config.log_file));
+            }
+        }
+        #[cfg(not(feature = "schedules"))]
+        if !config.schedules.is_empty() {
+            anyhow::bail!("Schedules are configured but this build does not include the `schedules` feature");
+        }
+        if let Some(tariff) = &config.tariff {
+            tariff.validate()?;
+            #[cfg(feature = "analytics")]
+            {
+                builder = builder.tariff(tariff.clone());
+            }
+            #[cfg(not(feature = "analytics"))]
+            anyhow::bail!("A tariff is configured but this build does not include the `analytics` feature");
+        }
+        if let Some(safety) = &config.safety {
+            safety.validate()?;
+            #[cfg(feature = "safety")]
+            {
+                builder = builder.safety(safety.clone());
+            }
+            #[cfg(not(feature = "safety"))]
+            anyhow::bail!("A safety limit is configured but this build does not include the `safety` feature");
+        }
+        if let Some(presence) = &config.presence {
+            presence.validate()?;
+            #[cfg(feature = "presence")]
+            {
+                builder = builder.presence(Presence::new(presence.clone())?);
+            }
+            #[cfg(not(feature = "presence"))]
+            anyhow::bail!("Presence rules are configured but this build does not include the `presence` feature");
+        }
+        if let Some(weather) = &config.weather {
+            weather.validate()?;
+            #[cfg(feature = "weather")]
+            {
+                builder = builder.weather(weather.clone(), Arc::new(OpenMeteo::new(weather)?));
+            }
+            #[cfg(not(feature = "weather"))]
+            anyhow::bail!("Weather rules are configured but this build does not include the `weather` feature");
+        }
+        if let Some(rest) = &config.rest {
+            rest.validate()?;
+            #[cfg(not(feature = "rest"))]
+            anyhow::bail!("The REST API is configured but this build does not include the `rest` feature");
+        }
+        if let Some(jwt) = &config.jwt {
+            jwt.validate()?;
+            #[cfg(not(feature = "http"))]
+            anyhow::bail!("JWT authentication is configured but this build does not include the `http` feature");
+        }
+        builder.deployment = Deployment {
+            config_file: config.source.clone(),
+            log_file: Some(config.log_file.clone()),
+            signing_key_file: config.signing_key.clone(),
+            available_backends: registry.names().into_iter().map(String::from).collect(),
+            config: Some(config.redacted()),
+            ..builder.deployment
+        };
+        Ok(builder)
+    }
+
+    #[cfg(test)]
+    fn new_with_in_memory_logger() -> Self {
+        let logger = InMemoryLogger::new();
+        Self::new_with_logger(Box::new(logger))
+    }
+
+    // Every resource a full session lists
+    fn resources(&self) -> Vec<Resource> {
+        let mut resources = vec![
+            Resource {
+                raw: RawResource {
+                    uri: LOG_URI.to_string(),
+                    name: "Lightbulb Activity Log".to_string(),
+                    description: Some(
+                        "Complete history of lightbulb actions as JSON, each entry taken apart into its time, message, power change, tags and signature; long logs come in pages linked by `next`"
+                            .to_string(),
+                    ),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+            Resource {
+                raw: RawResource {
+                    uri: LOG_TEXT_URI.to_string(),
+                    name: "Lightbulb Activity Log (text)".to_string(),
+                    description: Some(
+                        "The activity log as it is written, one timestamped line per entry; long logs come in pages, each ending with a link to the next"
+                            .to_string(),
+                    ),
+                    mime_type: Some("text/plain".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+            Resource {
+                raw: RawResource {
+                    uri: LOG_TAIL_URI.to_string(),
+                    name: "Recent Lightbulb Activity".to_string(),
+                    description: Some(format!("The last {} entries of the activity log", DEFAULT_TAIL_ENTRIES)),
+                    mime_type: Some("text/plain".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+            Self::palette_resource(),
+            Resource {
+                raw: RawResource {
+                    uri: SUMMARY_URI.to_string(),
+                    name: "Lightbulb Usage Summary".to_string(),
+                    description: Some("Summary statistics of lightbulb usage patterns".to_string()),
+                    mime_type: Some("text/plain".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+            Resource {
+                raw: RawResource {
+                    uri: SUMMARY_JSON_URI.to_string(),
+                    name: "Lightbulb Usage Summary (JSON)".to_string(),
+                    description: Some("The usage summary as JSON, with the on time of each of the last 7 days and changes by hour".to_string()),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+            Resource {
+                raw: RawResource {
+                    uri: REPORT_MARKDOWN_URI.to_string(),
+                    name: "Lightbulb Usage Report (Markdown)".to_string(),
+                    description: Some("The usage summary as Markdown tables, with bar charts of the last week's on time and of changes by hour".to_string()),
+                    mime_type: Some("text/markdown".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+            Resource {
+                raw: RawResource {
+                    uri: REPORT_HTML_URI.to_string(),
+                    name: "Lightbulb Usage Report (HTML)".to_string(),
+                    description: Some("The usage summary as a standalone HTML page, with inline charts of the last week's on time and of changes by hour".to_string()),
+                    mime_type: Some("text/html".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            },
+        ];
+        resources.push(Resource {
+            raw: RawResource {
+                uri: BULB_URI.to_string(),
+                name: "Bulb Metadata".to_string(),
+                description: Some(
+                    "What the bulb can do: color, its gamut and color temperature range, and the gamma correction applied to brightness".to_string(),
+                ),
+                mime_type: Some("application/json".to_string()),
+                size: None,
+            },
+            annotations: None,
+        });
+        if self.deployment.config.is_some() {
+            resources.push(Resource {
+                raw: RawResource {
+                    uri: CONFIG_URI.to_string(),
+                    name: "Effective Configuration".to_string(),
+                    description: Some(
+                        "The configuration the server is running with, defaults filled in and secrets redacted, with its transport and resolved paths"
+                            .to_string(),
+                    ),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            });
+        }
+        #[cfg(feature = "scenes")]
+        resources.push(Resource {
+            raw: RawResource {
+                uri: SCENES_URI.to_string(),
+                name: "Scenes".to_string(),
+                description: Some("Scenes apply_scene can apply, built in, configured and imported, with their brightness and color".to_string()),
+                mime_type: Some("application/json".to_string()),
+                size: None,
+            },
+            annotations: None,
+        });
+        #[cfg(feature = "schedules")]
+        resources.push(Resource {
+            raw: RawResource {
+                uri: SCHEDULES_URI.to_string(),
+                name: "Schedules".to_string(),
+                description: Some("Schedules the bulb runs at times of day, configured and imported".to_string()),
+                mime_type: Some("application/json".to_string()),
+                size: None,
+            },
+            annotations: None,
+        });
+        #[cfg(feature = "effects")]
+        resources.push(Resource {
+            raw: RawResource {
+                uri: EFFECTS_URI.to_string(),
+                name: "Effect Presets".to_string(),
+                description: Some("Themed effects start_effect can run, with what they look like and their default step length".to_string()),
+                mime_type: Some("text/plain".to_string()),
+                size: None,
+            },
+            annotations: None,
+        });
+        #[cfg(feature = "webhooks")]
+        if self.webhook_deliveries.is_some() {
+            resources.push(Resource {
+                raw: RawResource {
+                    uri: WEBHOOKS_URI.to_string(),
+                    name: "Webhook Deliveries".to_string(),
+                    description: Some("The most recent webhook deliveries, with attempts and final status".to_string()),
+                    mime_type: Some("application/json".to_string()),
+                    size: None,
+                },
+                annotations: None,
+            });
+        }
+        resources
+    }
+
+    async fn read_resource_contents(&self, request: ReadResourceRequestParam) -> Result<ReadResourceResult, ErrorData> {
+        match request.uri.as_str() {
+            // Hidden resources look the same to a guest as ones that do not exist
+            uri if self.guest && !uri.starts_with(COLORS_URI) => Err(LightError::UnknownResource(request.uri).into()),
+            uri if uri.starts_with(LOG_SINCE_PREFIX) => {
+                let sequence = Self::since_sequence(uri)?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "text/plain", self.read_log_since(sequence).await)],
+                })
+            },
+            uri if uri == LOG_URI || uri.starts_with(LOG_PAGE_PREFIX) => {
+                let offset = Self::page_offset(uri, LOG_PAGE_PREFIX, LOG_PAGE_TEMPLATE)?;
+                let page = to_json(&self.read_log_json(offset, LOG_PAGE_PREFIX).await)?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "application/json", page)],
+                })
+            },
+            uri if uri == LOG_TEXT_URI || uri.starts_with(LOG_TEXT_PAGE_PREFIX) => {
+                let offset = Self::page_offset(uri, LOG_TEXT_PAGE_PREFIX, LOG_TEXT_TEMPLATE)?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "text/plain", self.read_log_page(offset).await)],
+                })
+            },
+            uri if uri == LOG_JSON_URI || uri.starts_with(LOG_JSON_PAGE_PREFIX) => {
+                let offset = Self::page_offset(uri, LOG_JSON_PAGE_PREFIX, LOG_JSON_TEMPLATE)?;
+                let page = to_json(&self.read_log_json(offset, LOG_JSON_PAGE_PREFIX).await)?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "application/json", page)],
+                })
+            },
+            uri if uri == LOG_TAIL_URI || uri.starts_with(LOG_TAIL_PREFIX) => {
+                let entries = Self::tail_entries(uri)?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(self.read_log_tail(entries).await, uri)],
+                })
+            },
+            uri if uri.starts_with(LOG_DAY_PREFIX) => {
+                let day = self.log_day(uri)?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "text/plain", self.read_log_day(day).await)],
+                })
+            },
+            SUMMARY_URI => Ok(ReadResourceResult {
+                contents: vec![Self::contents(&request.uri, "text/plain", self.generate_usage_summary().await)],
+            }),
+            SUMMARY_JSON_URI => {
+                let summary = to_json(&self.usage_report().await)?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(&request.uri, "application/json", summary)],
+                })
+            },
+            REPORT_MARKDOWN_URI => Ok(ReadResourceResult {
+                contents: vec![Self::contents(&request.uri, "text/markdown", self.usage_report().await.markdown())],
+            }),
+            REPORT_HTML_URI => Ok(ReadResourceResult {
+                contents: vec![Self::contents(&request.uri, "text/html", self.usage_report().await.html())],
+            }),
+            #[cfg(feature = "webhooks")]
+            WEBHOOKS_URI if self.webhook_deliveries.is_some() => {
+                let deliveries = self.webhook_deliveries.as_ref().map(DeliveryLog::entries).unwrap_or_default();
+                let content = to_json(&deliveries)?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(content, &request.uri)],
+                })
+            },
+            BULB_URI => {
+                let content = to_json(&self.bulb_metadata())?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(content, &request.uri)],
+                })
+            },
+            CONFIG_URI if self.deployment.config.is_some() => {
+                let config = self.deployment.effective_config().unwrap_or_default();
+                let content = to_json(&config)?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(content, &request.uri)],
+                })
+            },
+            #[cfg(feature = "scenes")]
+            SCENES_URI => {
+                let content = to_json(&self.describe_scenes())?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(content, &request.uri)],
+                })
+            },
+            #[cfg(feature = "schedules")]
+            SCHEDULES_URI => {
+                let content = to_json(&self.describe_schedules())?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(content, &request.uri)],
+                })
+            },
+            #[cfg(feature = "effects")]
+            EFFECTS_URI => {
+                let list: Vec<String> = self.effect_registry.all().iter().map(Self::describe_effect).collect();
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(format!("Effect presets for start_effect:\n\n{}", list.join("\n")), &request.uri)],
+                })
+            },
+            #[cfg(feature = "effects")]
+            uri if uri.starts_with(EFFECT_URI_PREFIX) => match percent_decode(&uri[EFFECT_URI_PREFIX.len()..]).and_then(|name| self.effect_registry.get(&name)) {
+                Some(effect) => Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(Self::describe_effect(effect).trim_start().to_string(), uri)],
+                }),
+                None => Err(LightError::UnknownResource(request.uri).into()),
+            },
+            COLORS_URI => Ok(ReadResourceResult {
+                contents: vec![ResourceContents::text(Self::describe_palette(), &request.uri)],
+            }),
+            uri if bulb_resource(uri).is_some_and(|(_, suffix)| suffix == BULB_STATE_SUFFIX) => {
+                let key = bulb_resource(uri).map(|(key, _)| key).unwrap_or_default();
+                let light = self.bulb_handle(Some(&key)).map_err(|_| LightError::UnknownResource(request.uri.clone()))?;
+                let mut snapshot = light.snapshot().await?;
+                // Effects and timers are the server's own bulb's
+                if self.bulb_names(Some(&key)).0 == MAIN_BULB {
+                    snapshot.active_effect = self.effects.active();
+                    #[cfg(feature = "schedules")]
+                    {
+                        snapshot.timers = self.automation.timers.all().iter().map(|timer| timer.describe()).collect();
+                    }
+                }
+                let state = serde_json::to_string_pretty(&snapshot).map_err(|e| LightError::Internal(e.to_string()))?;
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "application/json", state)],
+                })
+            },
+            uri if bulb_resource(uri).is_some() => {
+                let key = bulb_resource(uri).map(|(key, _)| key).unwrap_or_default();
+                let light = self.bulb_handle(Some(&key)).map_err(|_| LightError::UnknownResource(request.uri.clone()))?;
+                // A bulb that has logged nothing yet has no log file; any other failure to read it is the caller's to hear of
+                let log = match light.read_log().await {
+                    Ok(log) => log,
+                    Err(_) if light.log_modified().is_none() => String::new(),
+                    Err(e) => return Err(e.into()),
+                };
+                let log = if log.is_empty() { "No lightbulb activity recorded yet.".to_string() } else { log };
+                Ok(ReadResourceResult {
+                    contents: vec![Self::contents(uri, "text/plain", log)],
+                })
+            },
+            uri => match uri.strip_prefix(COLOR_URI_PREFIX).and_then(percent_decode).and_then(|name| resolve_color(&name)) {
+                Some((name, color)) => Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::text(
+                        format!("{}: {} (r={}, g={}, b={})", name, color, color.r, color.g, color.b),
+                        uri,
+                    )],
+                }),
+                None => Err(LightError::UnknownResource(request.uri).into()),
+            },
+        }
+    }
+}
+
+impl ServerHandler for LightService {
+    fn get_info(&self) -> ServerInfo {
+        #[allow(unused_mut)]
+        let mut info = ServerInfo {
+            instructions: Some("Service for managing lights".into()),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_resources_subscribe()
+                .enable_logging()
+                .enable_completions()
+                .build(),
+            ..Default::default()
+        };
+        #[cfg(any(feature = "analytics", feature = "diagnostics"))]
+        {
+            info.capabilities.prompts = Some(PromptsCapability::default());
+        }
+        info
+    }
+
+    #[cfg(any(feature = "analytics", feature = "diagnostics"))]
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListPromptsResult, ErrorData> {
+        Ok(ListPromptsResult { prompts: self.prompts(), next_cursor: None })
+    }
+
+    #[cfg(any(feature = "analytics", feature = "diagnostics"))]
+    async fn get_prompt(&self, request: GetPromptRequestParam, _context: RequestContext<rmcp::RoleServer>) -> Result<GetPromptResult, ErrorData> {
+        self.prompt(&request.name, request.arguments.as_ref()).await
+    }
+
+    // Keeps the client's view current by telling it about changes made by other clients, effects and integrations
+    async fn on_initialized(&self, context: NotificationContext<rmcp::RoleServer>) {
+        let client = context.peer.peer_info().map(|info| info.client_info.name.clone());
+        match context.peer.peer_info() {
+            Some(info) => crate::diagnostic!(Info, "client {} {} connected", info.client_info.name, info.client_info.version),
+            None => crate::diagnostic!(Info, "client connected"),
+        }
+        let updates = self.resource_updates.subscribe();
+        #[cfg(feature = "bulbs")]
+        let bulb_events = Some(self.bulbs.subscribe());
+        #[cfg(not(feature = "bulbs"))]
+        let bulb_events = None;
+        let subscriptions = self.subscriptions.clone();
+        spawn_change_notifier(context.peer, self.light.subscribe(), bulb_events, updates, client, self.log_level.clone(), subscriptions);
+    }
+
+    // Subscribers hear of each change to the bulb's log, summary and reports, to each bulb's own log and state, and
+    // of schedules being set
+    async fn subscribe(&self, request: SubscribeRequestParam, context: RequestContext<rmcp::RoleServer>) -> Result<(), ErrorData> {
+        let scoped = self.scoped(&context);
+        let this = scoped.as_ref().unwrap_or(self);
+        let unknown = || LightError::UnknownResource(request.uri.clone());
+        if this.guest && !request.uri.starts_with(COLORS_URI) {
+            return Err(unknown().into());
+        }
+        // A bulb's own resource follows the bulb by its id, whichever of its names the subscriber gave
+        let follows = match bulb_resource(&request.uri) {
+            Some((key, suffix)) => {
+                this.bulb_handle(Some(&key)).map_err(|_| unknown())?;
+                format!("lightbulb://{}{}", this.bulb_names(Some(&key)).0, suffix)
+            },
+            None if follows_light(&request.uri, MAIN_BULB) || this.last_modified(&request.uri).await.is_some() => request.uri.clone(),
+            None => return Err(unknown().into()),
+        };
+        this.subscriptions.subscribe(request.uri, follows);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, request: UnsubscribeRequestParam, _context: RequestContext<rmcp::RoleServer>) -> Result<(), ErrorData> {
+        self.subscriptions.unsubscribe(&request.uri);
+        Ok(())
+    }
+
+    async fn set_level(&self, request: SetLevelRequestParam, _context: RequestContext<rmcp::RoleServer>) -> Result<(), ErrorData> {
+        self.log_level.set(request.level);
+        Ok(())
+    }
+
+    // Changes a tool makes are attributed to the client that called it, by the name it gave at initialization
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<CallToolResult, ErrorData> {
+        let scoped = self.scoped(&context);
+        let this = scoped.as_ref().unwrap_or(self);
+        let client = context.peer.peer_info().map(|info| info.client_info.name.clone());
+        let (tool, caller) = (request.name.clone(), client.clone().unwrap_or_else(|| "an unnamed client".to_string()));
+        if !this.tool_router.has_route(&tool) {
+            return Err(LightError::UnknownTool(tool.to_string()).into());
+        }
+        // A bearer JWT's scopes decide which tools it may call, and light:admin exempts it from curfews as an admin key does
+        #[cfg(feature = "http")]
+        let admin = match request_grant(&context) {
+            Some(grant) if !grant.allows_tool(&tool) => {
+                let error = LightError::ScopeRequired { tool: tool.to_string(), scope: crate::auth::tool_scope(&tool).name() };
+                crate::diagnostic!(Info, "tool {} called by {}: {}", tool, caller, error);
+                return Err(error.into());
+            },
+            Some(grant) => this.admin || grant.allows(crate::auth::Scope::Admin),
+            None => this.admin,
+        };
+        #[cfg(not(feature = "http"))]
+        let admin = this.admin;
+        let curfew = match admin {
+            true => Ok(()),
+            false => this.curfews.check(&tool, None, this.light.clock().local_time()),
+        };
+        let service = match client {
+            Some(name) => LightService { light: this.light.acting_as(name), ..this.clone() },
+            None => this.clone(),
+        };
+        if let Err(error) = curfew {
+            crate::diagnostic!(Info, "tool {} called by {}: {}", tool, caller, error);
+            // A dry run leaves the log as it found it
+            if let (LightError::OutsideAllowedHours { curfew, .. }, false) = (&error, this.dry_run)
+                && let Err(e) = service.light.log_denied(&tool, curfew).await
+            {
+                crate::diagnostic!(Warn, "could not log the refused call to {}: {}", tool, e);
+            }
+            return reply::outcome(Err(error.into()));
+        }
+        let started = Instant::now();
+        // A tool that panics fails its call with an internal error rather than leaving the client waiting
+        let call = AssertUnwindSafe(this.tool_router.call(ToolCallContext::new(&service, request, context))).catch_unwind();
+        let finished = match this.tool_timeouts.timeout(&tool) {
+            Some(timeout) => tokio::time::timeout(timeout, call).await.map_err(|_| timeout),
+            None => Ok(call.await),
+        };
+        let result = match finished {
+            Ok(Ok(result)) => result,
+            Ok(Err(panic)) => Err(LightError::Internal(panic_message(panic.as_ref())).into()),
+            // The call stops where it stood; a command it had already handed the actor is still carried out
+            Err(timeout) => Err(LightError::ToolTimedOut { tool: tool.to_string(), timeout_secs: timeout.as_secs() }.into()),
+        }
+        .map_err(|error| this.error_context(error));
+        match &result {
+            Ok(_) => crate::diagnostic!(Debug, "tool {} called by {}: ok in {:?}", tool, caller, started.elapsed()),
+            Err(e) => {
+                crate::diagnostic!(Debug, "tool {} called by {}: failed after {:?}: {}", tool, caller, started.elapsed(), e.message);
+                crate::verbosity::remember(format!("tool {} called by {} failed: {}", tool, caller, e.message));
+            },
+        }
+        reply::outcome(result)
+    }
+
+    // A request with a bearer JWT only lists the tools its scopes allow
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListToolsResult, ErrorData> {
+        let scoped = self.scoped(&_context);
+        let this = scoped.as_ref().unwrap_or(self);
+        #[allow(unused_mut)]
+        let mut tools = this.tool_router.list_all();
+        #[cfg(feature = "http")]
+        if let Some(grant) = request_grant(&_context) {
+            tools.retain(|tool| grant.allows_tool(&tool.name));
+        }
+        Ok(ListToolsResult::with_all_items(tools))
+    }
+
+    async fn list_resources(
+        	&self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListResourcesResult, ErrorData> {
+        let scoped = self.scoped(&context);
+        let this = scoped.as_ref().unwrap_or(self);
+        let mut resources = match this.guest {
+            true => vec![Self::palette_resource()],
+            false => this.resources(),
+        };
+        for resource in &mut resources {
+            let modified = this.last_modified(&resource.raw.uri).await;
+            resource.raw.size = this.size(&resource.raw.uri, modified).await;
+            if let Some(at) = modified {
+                resource.annotations = Some(Annotations { timestamp: Some(at), ..Default::default() });
+            }
+        }
+        Ok(ListResourcesResult {
+            resources,
+            next_cursor: None,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ReadResourceResult, ErrorData> {
+        let scoped = self.scoped(&context);
+        let this = scoped.as_ref().unwrap_or(self);
+        // Not modified comes back as no contents at all, before any work goes into reading the resource
+        if let Some(since) = Self::if_modified_since(&context.meta)?
+            && this.last_modified(&request.uri).await.is_some_and(|modified| modified <= since)
+        {
+            return Ok(ReadResourceResult { contents: Vec::new() });
+        }
+        this.read_resource_contents(request).await
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, ErrorData> {
+        let scoped = self.scoped(&context);
+        let this = scoped.as_ref().unwrap_or(self);
+        let color = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: COLOR_URI_TEMPLATE.to_string(),
+                name: "Lightbulb Color".to_string(),
+                description: Some("RGB value of a palette color name or description".to_string()),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        };
+        if this.guest {
+            return Ok(ListResourceTemplatesResult { resource_templates: vec![color], next_cursor: None });
+        }
+        let log_page = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: LOG_PAGE_TEMPLATE.to_string(),
+                name: "Lightbulb Activity Log Page".to_string(),
+                description: Some("The entries of up to 256 KiB of the activity log as JSON, starting at a byte offset".to_string()),
+                mime_type: Some("application/json".to_string()),
+            },
+            annotations: None,
+        };
+        let log_text_page = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: LOG_TEXT_TEMPLATE.to_string(),
+                name: "Lightbulb Activity Log Page (text)".to_string(),
+                description: Some("Up to 256 KiB of the activity log as written, starting at a byte offset and ending on a whole entry".to_string()),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        };
+        let log_since = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: LOG_SINCE_TEMPLATE.to_string(),
+                name: "Lightbulb Activity Since an Entry".to_string(),
+                description: Some(format!("Up to {} activity log entries numbered after the given sequence number", MAX_TAIL_ENTRIES)),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        };
+        let log_tail = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: LOG_TAIL_TEMPLATE.to_string(),
+                name: "Recent Lightbulb Activity".to_string(),
+                description: Some(format!("The last 1 to {} entries of the activity log", MAX_TAIL_ENTRIES)),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        };
+        let log_day = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: LOG_DAY_TEMPLATE.to_string(),
+                name: "Lightbulb Activity on a Day".to_string(),
+                description: Some("The activity log entries of one UTC day, given as YYYY-MM-DD, today or yesterday".to_string()),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        };
+        let bulb_log = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: BULB_LOG_TEMPLATE.to_string(),
+                name: "Lightbulb Activity Log of a Bulb".to_string(),
+                description: Some("The activity log of one bulb, by the id, name or alias list_lightbulbs gives it; main is the server's own".to_string()),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        };
+        let bulb_state = ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: BULB_STATE_TEMPLATE.to_string(),
+                name: "Lightbulb State of a Bulb".to_string(),
+                description: Some("One bulb's complete state as JSON, by the id, name or alias list_lightbulbs gives it, as get_lightbulb_state gives the server's own".to_string()),
+                mime_type: Some("application/json".to_string()),
+            },
+            annotations: None,
+        };
+        #[allow(unused_mut)]
+        let mut resource_templates = vec![color, log_page, log_text_page, log_since, log_tail, log_day, bulb_log, bulb_state];
+        #[cfg(feature = "effects")]
+        resource_templates.push(ResourceTemplate {
+            raw: RawResourceTemplate {
+                uri_template: EFFECT_URI_TEMPLATE.to_string(),
+                name: "Effect Preset".to_string(),
+                description: Some("What a start_effect preset looks like and its default step length".to_string()),
+                mime_type: Some("text/plain".to_string()),
+            },
+            annotations: None,
+        });
+        Ok(ListResourceTemplatesResult { resource_templates, next_cursor: None })
+    }
+
+    async fn complete(
+        &self,
+        request: CompleteRequestParam,
+        context: RequestContext<rmcp::RoleServer>,
+    ) -> Result<CompleteResult, ErrorData> {
+        let scoped = self.scoped(&context);
+        let this = scoped.as_ref().unwrap_or(self);
+        let values = match &request.r#ref {
+            Reference::Resource(reference) if reference.uri == COLOR_URI_TEMPLATE && request.argument.name == "name" => {
+                complete_color(&request.argument.value).into_iter().map(String::from).collect()
+            },
+            Reference::Resource(reference) if reference.uri == LOG_DAY_TEMPLATE && request.argument.name == "date" && !this.guest => {
+                let today = this.light.clock().now().date_naive();
+                let days = (0..7).map(|back| (today - TimeDelta::days(back)).to_string());
+                LOG_DAY_NAMES.into_iter().map(String::from).chain(days).filter(|day| day.starts_with(request.argument.value.trim())).collect()
+            },
+            #[cfg(feature = "analytics")]
+            Reference::Prompt(reference) if reference.name == prompts::ENERGY_PROMPT && request.argument.name == "period" && !this.guest => {
+                Self::complete_period(&request.argument.value)
+            },
+            #[cfg(feature = "effects")]
+            Reference::Resource(reference) if reference.uri == EFFECT_URI_TEMPLATE && request.argument.name == "name" && !this.guest => {
+                this.effect_registry.complete(&request.argument.value).into_iter().map(String::from).collect()
+            },
+            _ => Vec::new(),
+        };
+        Ok(CompleteResult {
+            completion: CompletionInfo { total: Some(values.len() as u32), values, has_more: Some(false) },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::PowerState;
+
+    pub(crate) struct UnreachableBackend;
+
+    #[async_trait::async_trait]
+    impl LightBackend for UnreachableBackend {
+        fn name(&self) -> &str {
+            "unreachable"
+        }
+
+        async fn set_power(&mut self, _state: PowerState) -> anyhow::Result<()> {
+            anyhow::bail!("no route to bulb")
+        }
+
+        async fn power(&self) -> anyhow::Result<PowerState> {
+            anyhow::bail!("no route to bulb")
+        }
+    }
+
+    fn tool_names<S: Send + Sync + 'static>(router: &ToolRouter<S>) -> Vec<String> {
+        let mut names: Vec<String> = router.list_all().into_iter().map(|tool| tool.name.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[cfg(feature = "core")]
+    #[tokio::test]
+    async fn test_guest_sees_only_toggle_and_status_tools() {
+        let service = LightService::new_with_in_memory_logger();
+        let guest = service.guest();
+        assert_eq!(tool_names(&guest.tool_router), vec!["get_lightbulb_state", "get_lightbulb_status", "turn_off_lightbulb", "turn_on_lightbulb"]);
+        assert!(tool_names(&service.tool_router).contains(&"run_macro".to_string()));
+
+        guest.light.set_power(PowerState::On).await.unwrap();
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::On));
+    }
+
+    #[cfg(all(feature = "core", feature = "effects"))]
+    #[tokio::test]
+    async fn test_tool_schemas_carry_ranges_and_formats() {
+        let service = LightService::new_with_in_memory_logger();
+        let tools = service.tool_router.list_all();
+        let schema = |name: &str| serde_json::Value::Object((*tools.iter().find(|tool| tool.name == name).unwrap().input_schema).clone());
+
+        let brightness = schema("set_brightness");
+        assert_eq!((brightness["properties"]["percent"]["minimum"].as_u64(), brightness["properties"]["percent"]["maximum"].as_u64()), (Some(1), Some(100)));
+        assert_eq!(brightness["required"], serde_json::json!(["percent"]));
+        assert_eq!(brightness["properties"]["tags"]["maxItems"], MAX_TAGS);
+        assert_eq!(schema("set_color")["properties"]["rgb"]["pattern"], "^#[0-9A-Fa-f]{6}$");
+        assert_eq!(schema("start_effect")["properties"]["effect"]["enum"], serde_json::json!(["halloween", "christmas", "new_year"]));
+        #[cfg(feature = "analytics")]
+        assert_eq!(schema("diff_state")["properties"]["from"]["format"], "date-time");
+        #[cfg(feature = "schedules")]
+        assert_eq!((schema("set_schedule")["properties"]["brightness"]["minimum"].as_u64(), schema("set_schedule")["properties"]["brightness"]["maximum"].as_u64()), (Some(1), Some(100)));
+    }
+
+    #[cfg(all(feature = "core", feature = "history"))]
+    #[tokio::test]
+    async fn test_dry_run_reports_without_changing_anything() {
+        use rmcp::handler::server::tool::Parameters;
+
+        let service = LightService::new_with_in_memory_logger();
+        let dry_run = service.dry_run();
+        let request = ChangeRequest { reason: Some("movie night".to_string()), tags: Some(vec!["movie".to_string()]), ..Default::default() };
+        assert_eq!(
+            dry_run.turn_on_lightbulb(Parameters(request.into())).await.unwrap(),
+            "Dry run against the simulated backend, nothing was changed: the lightbulb would go from OFF to ON and the log would record \"ON [movie] (reason: movie night)\""
+        );
+        let report = dry_run.turn_off_lightbulb(Parameters(Default::default())).await.unwrap();
+        assert!(report.ends_with("the lightbulb would stay OFF"), "{}", report);
+        let error = dry_run.undo_last_change(Parameters(Default::default())).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "NOTHING_TO_UNDO");
+        let stale = ChangeRequest { expected_version: Some(7), ..Default::default() };
+        let error = dry_run.lock_lightbulb(Parameters(stale)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "VERSION_CONFLICT");
+
+        let status = service.light.status().await.unwrap();
+        assert_eq!((status.state.power(), status.version), (Some(PowerState::Off), 0));
+        service.light.flush_log().await.unwrap();
+        assert_eq!(service.light.read_log().await.unwrap(), "");
+        // Nothing was remembered for a retry with the same key either
+        let keyed = ChangeRequest { idempotency_key: Some("k".to_string()), ..Default::default() };
+        assert!(dry_run.turn_on_lightbulb(Parameters(keyed.into())).await.unwrap().starts_with("Dry run"));
+        let keyed = ChangeRequest { idempotency_key: Some("k".to_string()), ..Default::default() };
+        assert_eq!(service.turn_on_lightbulb(Parameters(keyed.into())).await.unwrap(), "Lightbulb turned on successfully (sequence 1)");
+    }
+
+    #[tokio::test]
+    async fn test_builder_serves_only_selected_groups() {
+        let service = LightService::builder()
+            .logger(Box::new(InMemoryLogger::new()))
+            .tool_groups(&[ToolGroup::Core])
+            .build();
+        let names = tool_names(&service.tool_router);
+        assert!(names.contains(&"turn_on_lightbulb".to_string()));
+        assert!(!names.contains(&"undo_last_change".to_string()));
+    }
+
+    #[cfg(feature = "encryption")]
+    #[tokio::test]
+    async fn test_builder_seals_the_history_file_under_the_log_cipher() {
+        let log_file = std::env::temp_dir().join(format!("lightbulb-sealed-history-{}.log", std::process::id())).display().to_string();
+        let history = history_path(&log_file);
+        let _ = (std::fs::remove_file(&log_file), std::fs::remove_file(&history));
+        let service = LightService::builder()
+            .logger(Box::new(FileLogger::new(log_file.clone())))
+            .log_cipher(LogCipher::new([5; 32]))
+            .history_file(history.clone(), HistoryRotation::default())
+            .build();
+        service.light.acting_as("agent").set_power(PowerState::On).await.unwrap();
+        service.light.flush_log().await.unwrap();
+
+        let stored = std::fs::read_to_string(&history).unwrap();
+        let records = service.light.read_log_records(DateTime::<Utc>::MIN_UTC, DateTime::<Utc>::MAX_UTC).await.unwrap().unwrap();
+        let _ = (std::fs::remove_file(&log_file), std::fs::remove_file(&history));
+        assert!(stored.starts_with(crate::encryption::ENCRYPTED_LINE_PREFIX) && !stored.contains("agent"), "{}", stored);
+        assert_eq!((records.len(), records[0].by.as_deref()), (1, Some("agent")));
+    }
+
+    #[cfg(feature = "history")]
+    #[tokio::test]
+    async fn test_tool_router_for_host_server() {
+        struct HostServer {
+            light: LightService,
+        }
+
+        impl AsRef<LightService> for HostServer {
+            fn as_ref(&self) -> &LightService {
+                &self.light
+            }
+        }
+
+        let host = HostServer { light: LightService::new_with_in_memory_logger() };
+        let router = tool_router_for::<HostServer>(&[ToolGroup::History]);
+        assert_eq!(tool_names(&router), vec!["factory_reset", "import_log", "purge_data", "redo_change", "undo_last_change"]);
+        assert!(host.light.tool_router.has_route("undo_last_change"));
+    }
+
+    #[cfg(feature = "history")]
+    #[tokio::test]
+    async fn test_factory_reset_needs_confirming() {
+        use history::FactoryResetRequest;
+        use rmcp::handler::server::tool::Parameters;
+
+        use crate::state::LightState;
+
+        let service = LightService::new_with_in_memory_logger();
+        let reset = |archive_log: Option<bool>, confirm: Option<&str>| {
+            service.factory_reset(Parameters(FactoryResetRequest { archive_log, confirm: confirm.map(String::from) }))
+        };
+        service.light.set_power(PowerState::On).await.unwrap();
+        let dry_run = service.dry_run().factory_reset(Parameters(FactoryResetRequest { archive_log: None, confirm: None })).await.unwrap();
+        assert!(dry_run.contains(": would turn the lightbulb OFF, set it to white at full brightness, forget its undo history, erase its 1 log entries"), "{}", dry_run);
+
+        let asked = reset(None, None).await.unwrap();
+        assert!(asked.starts_with("This will turn the lightbulb OFF"), "{}", asked);
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::On));
+        let token = asked.rsplit('"').nth(1).unwrap().to_string();
+        // The token is for erasing the log, so it cannot confirm archiving it, and is used up trying
+        assert!(reset(Some(true), Some(&token)).await.is_err());
+        assert!(reset(None, Some(&token)).await.is_err());
+
+        let asked = reset(None, None).await.unwrap();
+        let token = asked.rsplit('"').nth(1).unwrap().to_string();
+        let done = reset(None, Some(&token)).await.unwrap();
+        assert_eq!(done, "Factory reset done: the lightbulb is OFF with no history (1 log entries erased) (sequence 2)");
+        assert_eq!(service.light.state().await.unwrap(), LightState::Off);
+        assert!(service.read_log_content().await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "history")]
+    #[tokio::test]
+    async fn test_import_log_merges_entries_in_time_order() {
+        use history::ImportLogRequest;
+        use rmcp::handler::server::tool::Parameters;
+
+        use crate::import::ImportFormat;
+
+        let service = LightService::new_with_in_memory_logger();
+        service.light.set_power(PowerState::On).await.unwrap();
+        let import = |format: ImportFormat, content: &str| service.import_log(Parameters(ImportLogRequest { format, content: content.to_string() }));
+        let csv = "timestamp,state,by\n2020-05-01T07:00:00Z,on,old-hub\n2020-05-01T08:00:00Z,off,old-hub\nsoon,on,old-hub\n";
+
+        let dry_run = service.dry_run().import_log(Parameters(ImportLogRequest { format: ImportFormat::Csv, content: csv.to_string() })).await.unwrap();
+        assert!(dry_run.ends_with(": would import 2 log entries; skipped 1 (line 4: unreadable timestamp 'soon')"), "{}", dry_run);
+        assert_eq!(import(ImportFormat::Csv, csv).await.unwrap(), "Imported 2 log entries; skipped 1 (line 4: unreadable timestamp 'soon')");
+        let log = service.read_log_content().await.unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines[..2], ["[2020-05-01T07:00:00+00:00] Lightbulb turned ON by old-hub", "[2020-05-01T08:00:00+00:00] Lightbulb turned OFF by old-hub"]);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(service.light.usage().await.unwrap().on, 2);
+
+        // Importing the log back into itself finds every entry already there
+        let again = import(ImportFormat::Text, &log).await.unwrap();
+        assert_eq!(again, "Imported 0 log entries; skipped 3 (line 1: already in the log; line 2: already in the log; line 3: already in the log)");
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::On));
+    }
+
+    #[cfg(feature = "history")]
+    #[tokio::test]
+    async fn test_purge_data_anonymizes_only_old_entries() {
+        use history::PurgeDataRequest;
+        use rmcp::handler::server::tool::Parameters;
+
+        use crate::privacy::PurgeMode;
+
+        let mut logger = InMemoryLogger::new();
+        logger.append_line("[2025-06-01T07:00:00+00:00] Lightbulb turned ON by kid-tablet (reason: homework)").await.unwrap();
+        logger.append_line("[2025-08-01T07:00:00+00:00] Lightbulb turned OFF by kid-tablet").await.unwrap();
+        let now = DateTime::parse_from_rfc3339("2025-08-02T07:00:00Z").unwrap().with_timezone(&Utc);
+        let config = Config::parse("[log_privacy]\nsalt = \"pepper\"").unwrap();
+        let service = LightService::builder_from_config(&config, &BackendRegistry::with_builtin())
+            .unwrap()
+            .logger(Box::new(logger))
+            .clock(Arc::new(crate::clock::ManualClock::new(now)))
+            .build();
+        let purge = |older_than_days: u32, mode: Option<PurgeMode>| service.purge_data(Parameters(PurgeDataRequest { older_than_days, mode }));
+
+        let dry_run = service.dry_run().purge_data(Parameters(PurgeDataRequest { older_than_days: 30, mode: None })).await.unwrap();
+        assert!(dry_run.ends_with(": would anonymize the clients and reasons of 1 log entries older than 30 days"), "{}", dry_run);
+        assert!(purge(0, None).await.is_err());
+        assert_eq!(purge(30, None).await.unwrap(), "Anonymized the clients and reasons of 1 log entries older than 30 days");
+        let log = service.read_log_content().await.unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].starts_with("[2025-06-01T07:00:00+00:00] Lightbulb turned ON by anon-"), "{}", log);
+        assert!(!lines[0].contains("kid-tablet") && !lines[0].contains("homework"), "{}", log);
+        assert_eq!(lines[1], "[2025-08-01T07:00:00+00:00] Lightbulb turned OFF by kid-tablet");
+
+        // Hashed names are left as they are, so purging again finds only what it has not seen
+        assert_eq!(purge(30, None).await.unwrap(), "No log entry older than 30 days names a client or gives a reason");
+        assert_eq!(purge(30, Some(PurgeMode::Remove)).await.unwrap(), "Removed the clients and reasons from 1 log entries older than 30 days");
+        let log = service.read_log_content().await.unwrap();
+        assert_eq!(log.lines().next().unwrap(), "[2025-06-01T07:00:00+00:00] Lightbulb turned ON");
+        assert_eq!(service.light.usage().await.unwrap().on, 1);
+
+        let unsalted = LightService::new_with_in_memory_logger();
+        let error = unsalted.purge_data(Parameters(PurgeDataRequest { older_than_days: 30, mode: None })).await.unwrap_err();
+        assert!(error.message.contains("[log_privacy] salt"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn test_sandbox_sessions_are_isolated() {
+        let config = Config::parse("sessions = \"sandbox\"").unwrap();
+        let factory = LightService::session_factory(&config, &BackendRegistry::with_builtin(), "test").unwrap();
+        let (first, second) = (factory.create(), factory.create());
+
+        first.light.set_power(PowerState::On).await.unwrap();
+        assert_eq!(first.light.state().await.unwrap().power(), Some(PowerState::On));
+        assert_eq!(second.light.state().await.unwrap().power(), Some(PowerState::Off));
+        assert!(second.read_log_content().await.unwrap().is_empty());
+
+        let config = Config::parse("sessions = \"sandbox\"\n[rest]\nbind = \"127.0.0.1:8080\"").unwrap();
+        assert!(LightService::session_factory(&config, &BackendRegistry::with_builtin(), "test").is_err());
+    }
+
+    #[test]
+    fn test_percent_decode_rejects_malformed_escapes() {
+        assert_eq!(percent_decode("sky%20blue").as_deref(), Some("sky blue"));
+        assert_eq!(percent_decode("%23ff8000").as_deref(), Some("#ff8000"));
+        assert_eq!(percent_decode("caf%C3%A9").as_deref(), Some("café"));
+        for malformed in ["sky%2", "%zz", "%+1", "%ff", "100%"] {
+            assert!(percent_decode(malformed).is_none(), "{}", malformed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_long_logs_are_read_a_page_at_a_time() {
+        let mut logger = InMemoryLogger::new();
+        for i in 0..10_000 {
+            logger.append_line(&format!("[2025-08-02T14:00:00+00:00] Lightbulb turned ON by agent-{}", i)).await.unwrap();
+        }
+        let whole = logger.read_log().await.unwrap();
+        let service = LightService::builder().logger(Box::new(logger)).build();
+
+        let mut pages = vec![service.read_log_page(None).await];
+        while let Some(next) = pages.last().unwrap().lines().last().and_then(|line| line.strip_prefix(LOG_NEXT_PAGE)) {
+            let offset = next.strip_prefix(LOG_TEXT_PAGE_PREFIX).unwrap().parse().unwrap();
+            pages.push(service.read_log_page(Some(offset)).await);
+        }
+        assert_eq!(pages.len(), whole.len().div_ceil(LOG_PAGE_BYTES));
+        assert!(pages[0].starts_with("Lightbulb Activity Log (bytes 0-"));
+        assert!(pages.iter().all(|page| page.len() < LOG_PAGE_BYTES + 200));
+        let entries: String = pages
+            .iter()
+            .flat_map(|page| page.lines().filter(|line| line.starts_with('[')))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        assert_eq!(entries, whole);
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_log_tail_resource() {
+        let service = LightService::new_with_in_memory_logger();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        assert_eq!(client.read(LOG_TAIL_URI).await.unwrap(), "No lightbulb activity recorded yet.");
+        for power in [PowerState::On, PowerState::Off, PowerState::On] {
+            service.light.set_power(power).await.unwrap();
+        }
+        let tail = client.read(&format!("{}2", LOG_TAIL_PREFIX)).await.unwrap();
+        assert!(tail.starts_with("Last 2 lightbulb log entries:\n\n"), "{}", tail);
+        let entries: Vec<&str> = tail.lines().skip(2).collect();
+        assert!(entries.len() == 2 && entries[0].ends_with("turned OFF seq=2") && entries[1].ends_with("turned ON seq=3"), "{}", tail);
+        let error = client.read(&format!("{}0", LOG_TAIL_PREFIX)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let since = client.read(&format!("{}1", LOG_SINCE_PREFIX)).await.unwrap();
+        assert!(since.starts_with("Lightbulb activity since entry 1:\n\n") && since.trim_end().ends_with("turned ON seq=3"), "{}", since);
+        assert_eq!(since.lines().skip(2).count(), 2, "{}", since);
+        assert_eq!(client.read(&format!("{}3", LOG_SINCE_PREFIX)).await.unwrap(), "No lightbulb activity recorded since entry 3.");
+        assert!(client.read(&format!("{}x", LOG_SINCE_PREFIX)).await.is_err());
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_log_and_summary_come_as_json_too() {
+        let service = LightService::new_with_in_memory_logger();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        service.light.tagged(vec!["movie".to_string()]).set_power(PowerState::On).await.unwrap();
+        service.light.set_power(PowerState::Off).await.unwrap();
+
+        let log: serde_json::Value = serde_json::from_str(&client.read(LOG_URI).await.unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&client.read(LOG_JSON_URI).await.unwrap()).unwrap(), log);
+        assert_eq!(log["entries"].as_array().map(Vec::len), Some(2));
+        assert_eq!((&log["entries"][0]["power"], &log["entries"][0]["tags"]), (&serde_json::json!("on"), &serde_json::json!(["movie"])));
+        assert_eq!(log["entries"][1]["message"], "Lightbulb turned OFF");
+        assert!(log["next"].is_null());
+        let error = client.read(&format!("{}x", LOG_JSON_PAGE_PREFIX)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let summary: serde_json::Value = serde_json::from_str(&client.read(SUMMARY_JSON_URI).await.unwrap()).unwrap();
+        assert_eq!((&summary["status"], &summary["total"], &summary["on"]), (&serde_json::json!("OFF"), &serde_json::json!(2), &serde_json::json!(1)));
+        assert_eq!(summary["days"].as_array().map(Vec::len), Some(7));
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_log_days_hold_that_days_entries() {
+        let mut logger = InMemoryLogger::new();
+        logger.append_line("[2025-08-01T23:59:59+00:00] Lightbulb turned ON").await.unwrap();
+        logger.append_line("[2025-08-02T09:00:00+00:00] Lightbulb turned OFF").await.unwrap();
+        let service = LightService::new_with_logger(Box::new(logger));
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+
+        let day = client.read(&format!("{}2025-08-02", LOG_DAY_PREFIX)).await.unwrap();
+        assert_eq!(day, "Lightbulb activity on 2025-08-02 (UTC):\n\n[2025-08-02T09:00:00+00:00] Lightbulb turned OFF\n");
+        assert_eq!(client.read(&format!("{}2025-08-03", LOG_DAY_PREFIX)).await.unwrap(), "No lightbulb activity recorded on 2025-08-03 (UTC).");
+        service.light.set_power(PowerState::On).await.unwrap();
+        assert!(client.read(&format!("{}today", LOG_DAY_PREFIX)).await.unwrap().contains("Lightbulb turned ON"));
+        let error = client.read(&format!("{}last-week", LOG_DAY_PREFIX)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        // The tail is not a day
+        assert!(client.read(LOG_TAIL_URI).await.unwrap().starts_with("Last 3 lightbulb log entries"));
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_listings_give_resource_sizes() {
+        let service = LightService::new_with_in_memory_logger();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        let size = |listed: &[Resource], uri: &str| listed.iter().find(|resource| resource.raw.uri == uri).and_then(|resource| resource.raw.size);
+        let listed = client.listed().await.unwrap();
+        assert_eq!(size(&listed, LOG_TEXT_URI), Some(0));
+        assert_eq!(size(&listed, SUMMARY_URI), Some(client.read(SUMMARY_URI).await.unwrap().len() as u32));
+
+        service.light.set_power(PowerState::On).await.unwrap();
+        let listed = client.listed().await.unwrap();
+        assert_eq!(size(&listed, LOG_TEXT_URI), Some(service.light.read_log().await.unwrap().len() as u32));
+        assert_eq!(size(&listed, LOG_URI), Some(client.read(LOG_URI).await.unwrap().len() as u32));
+        assert_eq!(size(&listed, SUMMARY_URI), Some(client.read(SUMMARY_URI).await.unwrap().len() as u32));
+        assert_eq!(size(&listed, LOG_TAIL_URI), Some(client.read(LOG_TAIL_URI).await.unwrap().len() as u32));
+        assert_eq!(size(&listed, COLORS_URI), Some(LightService::describe_palette().len() as u32));
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_reads_can_wait_for_a_change() {
+        let service = LightService::new_with_in_memory_logger();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        service.light.set_power(PowerState::On).await.unwrap();
+        let listed = client.listed().await.unwrap();
+        let stamp = |uri: &str| listed.iter().find(|resource| resource.raw.uri == uri).and_then(|resource| resource.annotations.as_ref()?.timestamp);
+        let since = stamp(LOG_URI).unwrap().to_rfc3339();
+        assert_eq!(stamp(BULB_URI), None);
+
+        assert_eq!(client.read_if_modified(LOG_URI, &since).await.unwrap(), None);
+        assert_eq!(client.read_if_modified(COLORS_URI, &since).await.unwrap(), None);
+        // The report's time on keeps growing while the bulb is on
+        assert!(client.read_if_modified(REPORT_MARKDOWN_URI, &since).await.unwrap().is_some());
+        assert!(client.read_if_modified(BULB_URI, &since).await.unwrap().is_some());
+        assert!(client.read_if_modified("lightbulb://colors/%zz", &since).await.is_err());
+
+        service.light.set_power(PowerState::Off).await.unwrap();
+        let log = client.read_if_modified(LOG_URI, &since).await.unwrap().unwrap();
+        assert!(log.contains("Lightbulb turned OFF"), "{}", log);
+        let error = client.read_if_modified(LOG_URI, "yesterday").await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_report_resources_render_the_summary() {
+        let service = LightService::new_with_in_memory_logger();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        let uris = client.resources().await.unwrap();
+        assert!(uris.iter().any(|uri| uri == REPORT_MARKDOWN_URI) && uris.iter().any(|uri| uri == REPORT_HTML_URI), "{:?}", uris);
+        service.light.set_power(PowerState::On).await.unwrap();
+
+        let markdown = client.read(REPORT_MARKDOWN_URI).await.unwrap();
+        assert!(markdown.starts_with("# Lightbulb Usage Report\n"), "{}", markdown);
+        assert!(markdown.contains("| Current status | ON |") && markdown.contains("| Turn ON actions | 1 (100.0%) |"), "{}", markdown);
+        let html = client.read(REPORT_HTML_URI).await.unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>") && html.contains("<tr><th>Total actions</th><td>1</td></tr>"), "{}", html);
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_bulb_resource_documents_the_bulb() {
+        let config = Config::parse("[backend]\ngamma = 1.0\noptions = { gamut = \"C\", min_mireds = 153, max_mireds = 500 }").unwrap();
+        let service = LightService::builder_from_config(&config, &BackendRegistry::with_builtin()).unwrap().logger(Box::new(InMemoryLogger::new())).build();
+        let client = crate::testing::TestClient::connect(service).await.unwrap();
+        let bulb: serde_json::Value = serde_json::from_str(&client.read(BULB_URI).await.unwrap()).unwrap();
+        assert_eq!((bulb["backend"].as_str(), bulb["supports_brightness"].as_bool()), (Some("simulated"), Some(true)));
+        assert_eq!(bulb["gamut"]["green"], serde_json::json!({ "x": 0.17, "y": 0.7 }));
+        assert_eq!(bulb["mired_range"], serde_json::json!({ "min": 153, "max": 500 }));
+        assert_eq!(bulb["brightness"]["gamma"], 1.0);
+        assert_eq!(bulb["brightness"]["levels"]["50%"], 128);
+        client.close().await.unwrap();
+
+        let error = LightService::builder_from_config(&Config::parse("[backend]\ngamma = 0").unwrap(), &BackendRegistry::with_builtin()).err().unwrap();
+        assert!(error.to_string().contains("gamma must be between"), "{}", error);
+    }
+
+    #[cfg(all(feature = "test-support", feature = "scenes"))]
+    #[tokio::test]
+    async fn test_scenes_resource_lists_configured_scenes_over_built_in_ones() {
+        let config = Config::parse("[[scenes]]\nname = \"movie\"\nbrightness = 5\ncolor = \"amber\"").unwrap();
+        let service = LightService::builder_from_config(&config, &BackendRegistry::with_builtin()).unwrap().logger(Box::new(InMemoryLogger::new())).build();
+        let client = crate::testing::TestClient::connect(service).await.unwrap();
+        let scenes: serde_json::Value = serde_json::from_str(&client.read(SCENES_URI).await.unwrap()).unwrap();
+        assert_eq!(scenes[0], serde_json::json!({ "name": "reading", "brightness": 100, "kelvin": 4000, "color": "#ffcea6", "source": "built-in" }));
+        assert_eq!(scenes[2], serde_json::json!({ "name": "movie", "brightness": 5, "kelvin": null, "color": "#ffbf00", "source": "config" }));
+        client.close().await.unwrap();
+
+        let twice = Config::parse("[[scenes]]\nname = \"movie\"\nbrightness = 5\n[[scenes]]\nname = \"movie\"\nbrightness = 9").unwrap();
+        let error = LightService::builder_from_config(&twice, &BackendRegistry::with_builtin()).err().unwrap();
+        assert_eq!(error.to_string(), "Scene 'movie' is configured twice");
+    }
+
+    #[cfg(all(feature = "test-support", feature = "effects"))]
+    #[tokio::test]
+    async fn test_effect_resources_list_the_presets() {
+        let client = crate::testing::TestClient::connect(LightService::new_with_in_memory_logger()).await.unwrap();
+        assert!(client.resources().await.unwrap().iter().any(|uri| uri == EFFECTS_URI));
+        let presets = client.read(EFFECTS_URI).await.unwrap();
+        assert!(presets.contains("  christmas: Red and green alternation (steps of 1000ms)"), "{}", presets);
+        assert_eq!(client.read("lightbulb://effects/New_Year").await.unwrap(), "new_year: Fast white strobe (steps of 100ms)");
+        assert!(client.read("lightbulb://effects/easter").await.is_err());
+        client.close().await.unwrap();
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_curfews_refuse_and_log_calls_from_everyone_but_admins() {
+        let config = Config::parse(
+            "admin_keys = [\"parent-key\"]\n[[curfews]]\nname = \"bedtime\"\nafter = \"20:30\"\nbefore = \"07:00\"\ntools = [\"turn_on_lightbulb\"]",
+        )
+        .unwrap();
+        let night = DateTime::parse_from_rfc3339("2025-08-01T22:00:00Z").unwrap().with_timezone(&Utc);
+        let service = LightService::builder_from_config(&config, &BackendRegistry::with_builtin())
+            .unwrap()
+            .logger(Box::new(InMemoryLogger::new()))
+            .clock(Arc::new(crate::clock::ManualClock::new(night)))
+            .build();
+        let kid = crate::testing::TestClient::connect_as(service.clone(), "kid-tablet").await.unwrap();
+        let error = kid.call("turn_on_lightbulb", serde_json::json!({})).await.unwrap_err();
+        let data = error.data.unwrap();
+        assert_eq!((data["code"].as_str(), data["allowed_from"].as_str()), (Some("OUTSIDE_ALLOWED_HOURS"), Some("07:00")));
+        assert!(kid.call("get_lightbulb_status", serde_json::json!({})).await.is_ok());
+        kid.close().await.unwrap();
+
+        // Calling itself an admin's client gets a session no further
+        let spoofed = crate::testing::TestClient::connect_as(service.clone(), "parent-laptop").await.unwrap();
+        let error = spoofed.call("turn_on_lightbulb", serde_json::json!({})).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "OUTSIDE_ALLOWED_HOURS");
+        spoofed.close().await.unwrap();
+
+        let parent = crate::testing::TestClient::connect_as(service.admin(), "parent-laptop").await.unwrap();
+        parent.call("turn_on_lightbulb", serde_json::json!({})).await.unwrap();
+        parent.close().await.unwrap();
+        let log = service.light.read_log().await.unwrap();
+        assert!(log.lines().next().unwrap().ends_with("Lightbulb DENIED turn_on_lightbulb to kid-tablet (outside allowed hours: bedtime) seq=1"), "{}", log);
+        assert!(log.lines().nth(1).unwrap().ends_with("Lightbulb DENIED turn_on_lightbulb to parent-laptop (outside allowed hours: bedtime) seq=2"), "{}", log);
+        assert!(log.lines().nth(2).unwrap().ends_with("turned ON by parent-laptop seq=3"), "{}", log);
+
+        let bulbs = Config::parse("[[curfews]]\nname = \"bedtime\"\nafter = \"20:30\"\nbefore = \"07:00\"\nbulbs = [\"kids-room\"]").unwrap();
+        let error = LightService::builder_from_config(&bulbs, &BackendRegistry::with_builtin()).err().unwrap();
+        assert!(error.to_string().contains("which only an aggregator has"), "{}", error);
+        let typo = Config::parse("[[curfews]]\nname = \"bedtime\"\nafter = \"20:30\"\nbefore = \"07:00\"\ntools = [\"turn_on\"]").unwrap();
+        let error = LightService::builder_from_config(&typo, &BackendRegistry::with_builtin()).err().unwrap();
+        assert_eq!(error.to_string(), "Curfew 'bedtime' covers 'turn_on', which is not a tool of this server");
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_a_stalled_tool_call_times_out() {
+        let config = Config::parse("[tool_timeouts]\nturn_on_lightbulb = 1").unwrap();
+        let service = LightService::builder_from_config(&config, &BackendRegistry::with_builtin()).unwrap().logger(Box::new(InMemoryLogger::new())).build();
+        let faults = service.faults.clone().unwrap();
+        faults.set_config(crate::backend::FaultConfig { latency_ms: 1_500, ..Default::default() }).unwrap();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        let error = client.call("turn_on_lightbulb", serde_json::json!({})).await.unwrap_err();
+        assert_eq!(error.data.unwrap(), serde_json::json!({ "code": "TOOL_TIMED_OUT", "tool": "turn_on_lightbulb", "timeout_secs": 1 }));
+        assert!(error.message.starts_with("'turn_on_lightbulb' did not finish within 1s"), "{}", error.message);
+        client.close().await.unwrap();
+
+        let typo = Config::parse("[tool_timeouts]\nturn_on = 5").unwrap();
+        let error = LightService::builder_from_config(&typo, &BackendRegistry::with_builtin()).err().unwrap();
+        assert_eq!(error.to_string(), "[tool_timeouts] sets a timeout for 'turn_on', which is not a tool of this server");
+    }
+
+    #[cfg(feature = "test-support")]
+    #[tokio::test]
+    async fn test_config_resource_is_redacted() {
+        let bare = crate::testing::TestClient::connect(LightService::new_with_in_memory_logger()).await.unwrap();
+        assert!(!bare.resources().await.unwrap().iter().any(|uri| uri == CONFIG_URI));
+        bare.close().await.unwrap();
+
+        let config = Config::parse("guest_keys = [\"guest-123\"]\nlog_compaction = { keep_days = 7 }").unwrap();
+        let service = LightService::builder_from_config(&config, &BackendRegistry::with_builtin())
+            .unwrap()
+            .logger(Box::new(InMemoryLogger::new()))
+            .transport("stdio")
+            .build();
+        let client = crate::testing::TestClient::connect(service.clone()).await.unwrap();
+        assert!(client.resources().await.unwrap().iter().any(|uri| uri == CONFIG_URI));
+        let effective: serde_json::Value = serde_json::from_str(&client.read(CONFIG_URI).await.unwrap()).unwrap();
+        assert_eq!((effective["transport"].as_str(), effective["backend"].as_str()), (Some("stdio"), Some("simulated")));
+        assert!(effective["paths"]["log_file"].as_str().unwrap().ends_with("lightbulb.log"));
+        assert_eq!(effective["config"]["log_compaction"]["keep_days"], 7);
+        assert_eq!(effective["config"]["guest_keys"], serde_json::json!(["[redacted]"]));
+        client.close().await.unwrap();
+
+        let guest = crate::testing::TestClient::connect(service.guest()).await.unwrap();
+        assert_eq!(guest.read(CONFIG_URI).await.unwrap_err().data.unwrap()["code"], "UNKNOWN_RESOURCE");
+        guest.close().await.unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::LightService;
+use crate::adaptive::Adjustment;
+use crate::config::{AdaptiveBrightnessConfig, MAX_LUX};
+use crate::error::LightError;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ReportAmbientLightRequest {
+    /// Illuminance measured in the room, in lux
+    #[schemars(range(min = 0.0, max = MAX_LUX))]
+    pub lux: f64,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SetAdaptiveBrightnessRequest {
+    /// Whether ambient light readings adjust the lightbulb's brightness
+    pub enabled: bool,
+    /// Illuminance to hold the room at, in lux (default 300, or as configured)
+    #[schemars(range(max = MAX_LUX), extend("exclusiveMinimum" = 0.0))]
+    pub target_lux: Option<f64>,
+    /// How far readings may stray from the target before brightness is corrected, in lux (default 30, or as configured)
+    #[schemars(range(min = 0.0))]
+    pub hysteresis_lux: Option<f64>,
+    /// The most brightness changes by for one reading, from 1 to 100 percent (default 20, or as configured)
+    #[schemars(range(min = 1, max = 100))]
+    pub max_step_percent: Option<u8>,
+}
+
+// Brightness that follows ambient light readings, gated behind the `adaptive` feature
+#[tool_router(router = adaptive_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Report an ambient light sensor reading in lux; with adaptive brightness on, the lightbulb's brightness is adjusted to hold the target light level")]
+    pub(super) async fn report_ambient_light(&self, Parameters(request): Parameters<ReportAmbientLightRequest>) -> Result<String, ErrorData> {
+        let settings = self.adaptive.settings();
+        let adjustment = self.adaptive.report(&self.light, request.lux, self.dry_run).await?;
+        let text = Self::describe_adjustment(request.lux, &settings, &adjustment, self.dry_run);
+        if self.dry_run {
+            return Ok(format!("{}: {}", self.dry_run_prefix(), text));
+        }
+        Ok(text)
+    }
+
+    #[tool(description = "Turn adaptive brightness on or off, optionally changing the target light level it holds and how closely")]
+    pub(super) async fn set_adaptive_brightness(&self, Parameters(request): Parameters<SetAdaptiveBrightnessRequest>) -> Result<String, ErrorData> {
+        let current = self.adaptive.settings();
+        let settings = AdaptiveBrightnessConfig {
+            target_lux: request.target_lux.unwrap_or(current.target_lux),
+            hysteresis_lux: request.hysteresis_lux.unwrap_or(current.hysteresis_lux),
+            max_step_percent: request.max_step_percent.unwrap_or(current.max_step_percent),
+            ..current
+        };
+        settings.validate().map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        let text = match request.enabled {
+            true => format!(
+                "Adaptive brightness on: holding {} lux to within {} lux, changing brightness by up to {}% a reading",
+                settings.target_lux, settings.hysteresis_lux, settings.max_step_percent
+            ),
+            false => "Adaptive brightness off; the lightbulb's brightness is left where it is".to_string(),
+        };
+        if self.dry_run {
+            return Ok(format!("{}: would turn {}", self.dry_run_prefix(), text.replacen("Adaptive brightness", "adaptive brightness", 1)));
+        }
+        self.adaptive.configure(request.enabled.then_some(settings));
+        Ok(text)
+    }
+}
+
+impl LightService {
+    fn describe_adjustment(lux: f64, settings: &AdaptiveBrightnessConfig, adjustment: &Adjustment, dry_run: bool) -> String {
+        let relation = if lux < settings.target_lux { "below" } else { "above" };
+        match adjustment {
+            Adjustment::Disabled => format!("Adaptive brightness is off, so {} lux changes nothing", lux),
+            Adjustment::NotLit(label) => format!("The lightbulb is {}, so {} lux changes nothing", label, lux),
+            Adjustment::Holding(brightness) => {
+                format!("{} lux is close enough to the {} lux target, so brightness stays at {}%", lux, settings.target_lux, brightness)
+            },
+            Adjustment::AtLimit(brightness) => {
+                format!("{} lux is {} the {} lux target, but the lightbulb is already at {}%", lux, relation, settings.target_lux, brightness)
+            },
+            Adjustment::Changed { from, to } => format!(
+                "{} lux is {} the {} lux target, so brightness {} from {}% to {}%",
+                lux,
+                relation,
+                settings.target_lux,
+                if dry_run { "would go" } else { "went" },
+                from,
+                to
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+
+    #[tokio::test]
+    async fn test_adaptive_brightness_follows_readings() {
+        let service = LightService::new_with_logger(Box::new(InMemoryLogger::new()));
+        let report = |lux: f64| service.report_ambient_light(Parameters(ReportAmbientLightRequest { lux }));
+        assert_eq!(report(100.0).await.unwrap(), "Adaptive brightness is off, so 100 lux changes nothing");
+
+        let enable = |target_lux: Option<f64>| SetAdaptiveBrightnessRequest { enabled: true, target_lux, hysteresis_lux: None, max_step_percent: None };
+        let reply = service.set_adaptive_brightness(Parameters(enable(Some(200.0)))).await.unwrap();
+        assert_eq!(reply, "Adaptive brightness on: holding 200 lux to within 30 lux, changing brightness by up to 20% a reading");
+        assert!(service.set_adaptive_brightness(Parameters(enable(Some(-5.0)))).await.is_err());
+        assert_eq!(report(100.0).await.unwrap(), "The lightbulb is OFF, so 100 lux changes nothing");
+
+        service.light().set_power(PowerState::On).await.unwrap();
+        assert_eq!(report(400.0).await.unwrap(), "400 lux is above the 200 lux target, so brightness went from 100% to 80%");
+        assert_eq!(report(190.0).await.unwrap(), "190 lux is close enough to the 200 lux target, so brightness stays at 80%");
+        let dry_run = service.dry_run().report_ambient_light(Parameters(ReportAmbientLightRequest { lux: 0.0 })).await.unwrap();
+        assert!(dry_run.ends_with(": 0 lux is below the 200 lux target, so brightness would go from 80% to 100%"), "{}", dry_run);
+        assert!(report(f64::NAN).await.is_err());
+    }
+}
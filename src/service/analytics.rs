@@ -0,0 +1,331 @@
+use chrono::{DateTime, Utc};
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::LightService;
+use crate::error::LightError;
+use crate::reply;
+use crate::clock::format_duration;
+use crate::model::PowerState;
+use crate::stats::{DEFAULT_WATTS, HistoryEntry, LogAction, MAX_WATTS, Metric, StateDiff, Statistics, compute, diff, history_entries};
+use crate::tags::normalize_tag;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct GetStatisticsRequest {
+    /// Metrics to compute: any of "counts", "on_time", "energy" and "histogram"
+    #[schemars(length(min = 1))]
+    pub metrics: Vec<Metric>,
+    /// Start of the time range (RFC 3339); defaults to the beginning of the log
+    #[schemars(extend("format" = "date-time"))]
+    pub from: Option<String>,
+    /// End of the time range (RFC 3339); defaults to now
+    #[schemars(extend("format" = "date-time"))]
+    pub to: Option<String>,
+    /// Bulb power draw in watts for the energy estimate (default 9)
+    #[schemars(range(max = MAX_WATTS), extend("exclusiveMinimum" = 0.0))]
+    pub watts: Option<f64>,
+    /// Only count power changes logged with this tag, e.g. "movie"
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct DiffStateRequest {
+    /// Earlier point in time (RFC 3339)
+    #[schemars(extend("format" = "date-time"))]
+    pub from: String,
+    /// Later point in time (RFC 3339); defaults to now
+    #[schemars(extend("format" = "date-time"))]
+    pub to: Option<String>,
+}
+
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const MAX_HISTORY_LIMIT: usize = 1000;
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct QueryHistoryRequest {
+    /// Start of the time range (RFC 3339); defaults to the beginning of the log
+    #[schemars(extend("format" = "date-time"))]
+    pub from: Option<String>,
+    /// End of the time range (RFC 3339); defaults to now
+    #[schemars(extend("format" = "date-time"))]
+    pub to: Option<String>,
+    /// Only entries recording this action
+    pub action: Option<LogAction>,
+    /// Only entries made by this client, by the name the log gives it
+    pub by: Option<String>,
+    /// How many of the most recent matching entries to return (default 100)
+    #[schemars(range(min = 1, max = MAX_HISTORY_LIMIT))]
+    pub limit: Option<usize>,
+}
+
+// Usage statistics, gated behind the `analytics` feature
+#[tool_router(router = analytics_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Compute selected usage metrics (counts, on_time, energy, histogram) over a time range, optionally only for changes with a tag, returned as JSON")]
+    async fn get_statistics(&self, Parameters(request): Parameters<GetStatisticsRequest>) -> Result<CallToolResult, ErrorData> {
+        if request.metrics.is_empty() {
+            return Err(LightError::InvalidParameter("at least one metric is required".to_string()).into());
+        }
+        let from = request.from.as_deref().map(|from| parse_time("from", from)).transpose()?;
+        let to = request.to.as_deref().map(|to| parse_time("to", to)).transpose()?.unwrap_or_else(|| self.light.clock().now());
+        if from.is_some_and(|from| from >= to) {
+            return Err(LightError::InvalidParameter("from must be earlier than to".to_string()).into());
+        }
+        let watts = request.watts.unwrap_or(DEFAULT_WATTS);
+        if !(watts > 0.0 && watts <= MAX_WATTS) {
+            return Err(LightError::InvalidParameter(format!("watts must be above 0 and at most {}", MAX_WATTS)).into());
+        }
+
+        let tag = request.tag.as_deref().map(normalize_tag).transpose()?;
+
+        let usage = self.light.usage().await?;
+        let statistics = compute(&usage.events, &usage.days, &request.metrics, from, to, watts, tag.as_deref());
+        reply::json(describe_statistics(&statistics), &statistics)
+    }
+
+    #[tool(description = "Compare the lightbulb's state at two points in time, reconstructed from the log: its power at each, the power changes in between and the time it was on, returned as JSON; e.g. for what happened overnight")]
+    async fn diff_state(&self, Parameters(request): Parameters<DiffStateRequest>) -> Result<CallToolResult, ErrorData> {
+        let from = parse_time("from", &request.from)?;
+        let to = request.to.as_deref().map(|to| parse_time("to", to)).transpose()?.unwrap_or_else(|| self.light.clock().now());
+        if from >= to {
+            return Err(LightError::InvalidParameter("from must be earlier than to".to_string()).into());
+        }
+        let usage = self.light.usage().await?;
+        let diff = diff(&usage.events, &usage.days, from, to);
+        reply::json(describe_diff(&diff), &diff)
+    }
+
+    #[tool(description = "Query the activity log as structured entries (time, action, client, reason, tags and the power before a power change), filtered by time range, action and client, returned as JSON")]
+    async fn query_history(&self, Parameters(request): Parameters<QueryHistoryRequest>) -> Result<CallToolResult, ErrorData> {
+        let from = request.from.as_deref().map(|from| parse_time("from", from)).transpose()?;
+        let to = request.to.as_deref().map(|to| parse_time("to", to)).transpose()?.unwrap_or_else(|| self.light.clock().now());
+        if from.is_some_and(|from| from >= to) {
+            return Err(LightError::InvalidParameter("from must be earlier than to".to_string()).into());
+        }
+        let limit = request.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+        if !(1..=MAX_HISTORY_LIMIT).contains(&limit) {
+            return Err(LightError::InvalidParameter(format!("limit must be between 1 and {}", MAX_HISTORY_LIMIT)).into());
+        }
+        let by = request.by.as_deref().map(str::trim);
+
+        // Without a `to`, entries written this very moment are still in range
+        let end = if request.to.is_some() { to } else { DateTime::<Utc>::MAX_UTC };
+        let start = from.unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let entries = match self.light.read_log_records(start, end).await? {
+            Some(records) => records,
+            // Without a history file the log is taken apart from its start, so each power change follows the one
+            // logged before it
+            None => {
+                let lines = self.light.read_log_range(DateTime::<Utc>::MIN_UTC, end).await?;
+                let entries = history_entries(lines.iter().map(String::as_str), &mut None);
+                entries.into_iter().filter(|entry| entry.at >= start).collect()
+            },
+        };
+        let mut entries: Vec<HistoryEntry> = entries
+            .into_iter()
+            .filter(|entry| request.action.is_none_or(|action| entry.action == action) && by.is_none_or(|by| entry.by.as_deref() == Some(by)))
+            .collect();
+        let matched = entries.len();
+        entries.drain(..matched.saturating_sub(limit));
+
+        let mut summary = format!("{} log entries", matched);
+        if let Some(action) = request.action {
+            summary.push_str(&format!(" recording {}", action.label()));
+        }
+        if let Some(by) = by {
+            summary.push_str(&format!(" by {}", by));
+        }
+        match from {
+            Some(from) => summary.push_str(&format!(" from {} to {}", from.to_rfc3339(), to.to_rfc3339())),
+            None => summary.push_str(&format!(" up to {}", to.to_rfc3339())),
+        }
+        if matched > entries.len() {
+            summary.push_str(&format!("; the latest {} are listed", entries.len()));
+        }
+        reply::json(summary, &entries)
+    }
+}
+
+// e.g. "From 2025-08-02T22:00:00+00:00 to 2025-08-03T07:00:00+00:00 the lightbulb went from ON to OFF: 3 power
+// changes (1 on, 2 off), on for 1h 5m"
+fn describe_diff(diff: &StateDiff) -> String {
+    let power = |power: Option<PowerState>| power.map_or("unknown", PowerState::log_action);
+    let change = match (diff.power_to, diff.changed) {
+        (None, _) => "had no power change logged yet".to_string(),
+        (Some(_), true) => format!("went from {} to {}", power(diff.power_from), power(diff.power_to)),
+        (Some(_), false) => format!("was {} at both ends", power(diff.power_to)),
+    };
+    format!(
+        "From {} to {} the lightbulb {}: {} power changes ({} on, {} off), on for {}",
+        diff.from.to_rfc3339(),
+        diff.to.to_rfc3339(),
+        change,
+        diff.transitions.total,
+        diff.transitions.on,
+        diff.transitions.off,
+        format_duration(diff.on_time_secs)
+    )
+}
+
+// The metrics in a sentence, e.g. "12 power changes (7 on, 5 off), on for 3h 20m, 30.0 Wh at 9W since 2025-08-01"
+fn describe_statistics(statistics: &Statistics) -> String {
+    let mut facts = Vec::new();
+    if let Some(counts) = &statistics.counts {
+        facts.push(format!("{} power changes ({} on, {} off)", counts.total, counts.on, counts.off));
+    }
+    if let Some(secs) = statistics.on_time_secs {
+        facts.push(format!("on for {}", format_duration(secs)));
+    }
+    if let Some(energy) = &statistics.energy {
+        facts.push(format!("{:.1} Wh at {}W", energy.watt_hours, energy.watts));
+    }
+    if let Some(busiest) = statistics.histogram.and_then(|hours| (0..24).rev().filter(|hour| hours[*hour] > 0).max_by_key(|hour| hours[*hour])) {
+        facts.push(format!("busiest at {:02}:00 UTC", busiest));
+    }
+    let mut text = format!("Usage statistics: {}", facts.join(", "));
+    if let Some(tag) = &statistics.tag {
+        text.push_str(&format!(" for changes tagged '{}'", tag));
+    }
+    match statistics.from {
+        Some(from) => text.push_str(&format!(" from {} to {}", from.to_rfc3339(), statistics.to.to_rfc3339())),
+        None => text.push_str(&format!(" up to {}", statistics.to.to_rfc3339())),
+    }
+    text
+}
+
+fn parse_time(field: &str, value: &str) -> Result<DateTime<Utc>, LightError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| LightError::InvalidParameter(format!("{} is not an RFC 3339 timestamp: {}", field, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::TimeDelta;
+
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+
+    fn request(metrics: Vec<Metric>, from: Option<&str>) -> Parameters<GetStatisticsRequest> {
+        Parameters(GetStatisticsRequest { metrics, from: from.map(String::from), to: None, watts: None, tag: None })
+    }
+
+    #[tokio::test]
+    async fn test_get_statistics_returns_only_requested_metrics() {
+        let service = LightService::new_with_in_memory_logger();
+        service.light.set_power(PowerState::On).await.unwrap();
+        service.light.set_power(PowerState::Off).await.unwrap();
+
+        let statistics: serde_json::Value =
+            reply::json_of(&service.get_statistics(request(vec![Metric::Counts], None)).await.unwrap()).unwrap();
+        assert_eq!(statistics["counts"], serde_json::json!({ "total": 2, "on": 1, "off": 1 }));
+        assert!(statistics.get("energy").is_none());
+        assert!(statistics.get("histogram").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_statistics_filters_by_tag() {
+        let service = LightService::new_with_in_memory_logger();
+        service.light.tagged(vec!["movie".to_string()]).set_power(PowerState::On).await.unwrap();
+        service.light.set_power(PowerState::Off).await.unwrap();
+
+        let mut tagged = request(vec![Metric::Counts], None);
+        tagged.0.tag = Some("Movie".to_string());
+        let statistics: serde_json::Value = reply::json_of(&service.get_statistics(tagged).await.unwrap()).unwrap();
+        assert_eq!(statistics["counts"], serde_json::json!({ "total": 1, "on": 1, "off": 0 }));
+        assert_eq!(statistics["tag"], "movie");
+    }
+
+    #[tokio::test]
+    async fn test_get_statistics_uses_the_service_clock() {
+        let clock = ManualClock::new(DateTime::parse_from_rfc3339("2025-08-02T10:15:00Z").unwrap().with_timezone(&Utc));
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build();
+        service.light.set_power(PowerState::On).await.unwrap();
+        clock.advance(TimeDelta::hours(2));
+        service.light.set_power(PowerState::Off).await.unwrap();
+        clock.advance(TimeDelta::minutes(30));
+
+        let metrics = vec![Metric::OnTime, Metric::Histogram];
+        let result = service.get_statistics(request(metrics, None)).await.unwrap();
+        assert_eq!(reply::text_of(&result).lines().next(), Some("Usage statistics: on for 2h 0m, busiest at 10:00 UTC up to 2025-08-02T12:45:00+00:00"));
+        let statistics = reply::json_of(&result).unwrap();
+        assert_eq!(statistics["to"], "2025-08-02T12:45:00Z");
+        assert_eq!(statistics["on_time_secs"], 7200);
+        assert_eq!((statistics["histogram"][10].as_u64(), statistics["histogram"][12].as_u64()), (Some(1), Some(1)));
+    }
+
+    #[tokio::test]
+    async fn test_diff_state_reports_what_happened_overnight() {
+        let clock = ManualClock::new(DateTime::parse_from_rfc3339("2025-08-02T22:00:00Z").unwrap().with_timezone(&Utc));
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build();
+        service.light.set_power(PowerState::On).await.unwrap();
+        clock.advance(TimeDelta::hours(2));
+        service.light.set_power(PowerState::Off).await.unwrap();
+        clock.advance(TimeDelta::hours(3));
+        service.light.set_power(PowerState::On).await.unwrap();
+        clock.advance(TimeDelta::minutes(30));
+        service.light.set_power(PowerState::Off).await.unwrap();
+        clock.advance(TimeDelta::hours(3));
+
+        let request = DiffStateRequest { from: "2025-08-02T23:00:00Z".to_string(), to: None };
+        let result = service.diff_state(Parameters(request)).await.unwrap();
+        assert_eq!(
+            reply::text_of(&result).lines().next(),
+            Some("From 2025-08-02T23:00:00+00:00 to 2025-08-03T06:30:00+00:00 the lightbulb went from ON to OFF: 3 power changes (1 on, 2 off), on for 1h 30m")
+        );
+        let diff = reply::json_of(&result).unwrap();
+        assert_eq!((diff["power_from"].as_str(), diff["changed"].as_bool()), (Some("on"), Some(true)));
+
+        let request = DiffStateRequest { from: "2025-08-02T20:00:00Z".to_string(), to: Some("2025-08-02T21:00:00Z".to_string()) };
+        let text = reply::text_of(&service.diff_state(Parameters(request)).await.unwrap());
+        assert!(text.starts_with("From 2025-08-02T20:00:00+00:00 to 2025-08-02T21:00:00+00:00 the lightbulb had no power change logged yet: 0 power changes"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn test_get_statistics_validates_range() {
+        let service = LightService::new_with_in_memory_logger();
+        let error = service.get_statistics(request(vec![Metric::Counts], Some("yesterday"))).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let error = service.get_statistics(request(vec![Metric::Counts], Some("2999-01-01T00:00:00Z"))).await.unwrap_err();
+        assert_eq!(error.message, "Invalid parameter: from must be earlier than to");
+    }
+
+    #[tokio::test]
+    async fn test_query_history_filters_by_action_client_and_time() {
+        let clock = ManualClock::new(DateTime::parse_from_rfc3339("2025-08-02T20:00:00Z").unwrap().with_timezone(&Utc));
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build();
+        service.light.acting_as("agent").set_power(PowerState::On).await.unwrap();
+        clock.advance(TimeDelta::hours(1));
+        service.light.acting_as("kitchen").set_power(PowerState::Off).await.unwrap();
+        clock.advance(TimeDelta::hours(1));
+        service.light.acting_as("agent").set_power(PowerState::On).await.unwrap();
+        clock.advance(TimeDelta::hours(1));
+        service.light.acting_as("agent").set_power(PowerState::Off).await.unwrap();
+
+        let query = |request: QueryHistoryRequest| service.query_history(Parameters(request));
+        let result = query(QueryHistoryRequest { by: Some("agent".to_string()), limit: Some(2), ..Default::default() }).await.unwrap();
+        assert_eq!(reply::text_of(&result).lines().next(), Some("3 log entries by agent up to 2025-08-02T23:00:00+00:00; the latest 2 are listed"));
+        let entries = reply::json_of(&result).unwrap();
+        assert_eq!(entries[1]["action"], "off");
+        assert_eq!((entries[1]["by"].as_str(), entries[1]["previous"].as_str(), entries[1]["sequence"].as_u64()), (Some("agent"), Some("on"), Some(4)));
+        assert_eq!(entries[0]["previous"], "off");
+
+        let evening = QueryHistoryRequest { from: Some("2025-08-02T20:30:00Z".to_string()), to: Some("2025-08-02T22:30:00Z".to_string()), action: Some(LogAction::On), ..Default::default() };
+        let entries = reply::json_of(&query(evening).await.unwrap()).unwrap();
+        assert_eq!((entries.as_array().unwrap().len(), entries[0]["at"].as_str()), (1, Some("2025-08-02T22:00:00Z")));
+        let error = query(QueryHistoryRequest { limit: Some(0), ..Default::default() }).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        // A change logged in the same instant as the one before it follows it
+        service.light.acting_as("agent").set_power(PowerState::On).await.unwrap();
+        let entries = reply::json_of(&query(QueryHistoryRequest { limit: Some(1), ..Default::default() }).await.unwrap()).unwrap();
+        assert_eq!((entries[0]["at"].as_str(), entries[0]["previous"].as_str()), (Some("2025-08-02T23:00:00Z"), Some("off")));
+    }
+}
@@ -0,0 +1,70 @@
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+
+use super::LightService;
+use crate::error::LightError;
+use crate::logger::{SignatureCheck, verify_log_line};
+
+// Log verification tools, gated behind the `audit` feature
+#[tool_router(router = audit_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Verify the ed25519 signatures of all entries in the lightbulb log")]
+    async fn verify_log_signatures(&self) -> Result<String, ErrorData> {
+        let verifying_key = self.verifying_key.ok_or(LightError::SigningDisabled)?;
+        let log_content = self.read_log_content().await?;
+
+        let mut valid = 0;
+        let mut unsigned = 0;
+        let mut invalid_lines = Vec::new();
+        for (index, line) in log_content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match verify_log_line(&verifying_key, line) {
+                SignatureCheck::Valid => valid += 1,
+                SignatureCheck::Unsigned => unsigned += 1,
+                SignatureCheck::Invalid => invalid_lines.push((index + 1).to_string()),
+            }
+        }
+
+        let mut report = format!(
+            "Log signature verification:\n\
+            - Valid signatures: {}\n\
+            - Invalid signatures: {}\n\
+            - Unsigned entries: {}",
+            valid,
+            invalid_lines.len(),
+            unsigned,
+        );
+        if !invalid_lines.is_empty() {
+            report.push_str(&format!("\n\nInvalid entries on lines: {}", invalid_lines.join(", ")));
+        }
+        Ok(report)
+    }
+}
+
+// These drive the bulb through the core tools
+#[cfg(all(test, feature = "core"))]
+mod tests {
+    use ed25519_dalek::SigningKey;
+    use rmcp::handler::server::tool::Parameters;
+
+    use super::*;
+    use crate::logger::InMemoryLogger;
+
+    #[tokio::test]
+    async fn test_verify_log_signatures() {
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let service = LightService::new_with_signing_logger(Box::new(InMemoryLogger::new()), signing_key);
+
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        let _ = service.turn_off_lightbulb(Parameters(Default::default())).await;
+
+        let report = service.verify_log_signatures().await.expect("Verification should run");
+        assert!(report.contains("Valid signatures: 2"));
+        assert!(report.contains("Invalid signatures: 0"));
+    }
+
+    #[tokio::test]
+    async fn test_verify_log_signatures_requires_signing() {
+        let service = LightService::new_with_in_memory_logger();
+        assert!(service.verify_log_signatures().await.is_err());
+    }
+}
@@ -0,0 +1,511 @@
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+use serde::{Deserialize, Serialize};
+
+use super::{ChangeRequest, LightService, bulb_log_uri};
+use crate::actor::{Change, PowerChange};
+use crate::backend::SimulatedBackend;
+use crate::bulbs::MAX_BULBS;
+use crate::color::{MAX_KELVIN, MIN_KELVIN};
+use crate::config::{BackendConfig, BulbConfig, MAX_BULB_ALIASES, validate_room};
+use crate::error::LightError;
+use crate::model::{Easing, MAX_CROSSFADE_MS, PowerState};
+use crate::reply;
+use crate::scenes::{Crossfade, Scene, apply, crossfade};
+
+// Long enough to see a room light up bulb by bulb, short enough that a full room is done within a minute
+const MAX_STAGGER_MS: u64 = 1_500;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct AddLightbulbRequest {
+    /// The new bulb's id, a lowercase tag such as "desk-lamp"
+    pub id: String,
+    /// The room it is in, a lowercase tag such as "living-room"
+    pub room: Option<String>,
+    /// What people call it, e.g. "Desk Lamp"; tools take it wherever they take a bulb_id
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RenameBulbRequest {
+    /// The bulb to rename, by id, name or alias
+    pub bulb_id: String,
+    /// Its new name, e.g. "Desk Lamp"; leave out to clear it
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct BulbAliasesRequest {
+    /// The bulb, by id, name or alias
+    pub bulb_id: String,
+    /// Every name it should also answer to, e.g. ["reading light"], replacing those it had; empty to clear them
+    #[schemars(length(max = MAX_BULB_ALIASES))]
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RoomPowerRequest {
+    /// The room whose bulbs to switch, as list_lightbulbs names it
+    pub room: String,
+    /// true to turn every bulb in the room on, false to turn them all off
+    pub on: bool,
+    /// Why, e.g. "going to bed"; recorded in each bulb's log
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct BulbSceneOverride {
+    /// The bulb, by id, name or alias; it must be in the room
+    pub bulb_id: String,
+    /// Its own brightness, 1 to 100, in place of the scene's
+    #[schemars(range(min = 1, max = 100))]
+    pub brightness: Option<u8>,
+    /// Its own white temperature in kelvin, between 1000 and 40000, in place of the scene's temperature or color
+    #[schemars(range(min = MIN_KELVIN, max = MAX_KELVIN))]
+    pub kelvin: Option<u32>,
+    /// Its own palette color or #rrggbb code, in place of the scene's temperature or color
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct GroupSceneRequest {
+    /// Scene to apply, as apply_scene takes it
+    pub name: String,
+    /// The room whose bulbs to apply it to, as list_lightbulbs names it
+    pub room: String,
+    /// Bulbs of the room that should look different from the scene
+    #[serde(default)]
+    #[schemars(length(max = MAX_BULBS))]
+    pub overrides: Vec<BulbSceneOverride>,
+    /// Start each bulb this many milliseconds after the one before it, at most 1500, so the room lights up in
+    /// turn; 0 or omitted starts them all at once
+    #[schemars(range(max = MAX_STAGGER_MS))]
+    pub stagger_ms: Option<u64>,
+    /// Fade each bulb into the scene over this many milliseconds, at most 20000, as apply_scene does
+    #[schemars(range(max = MAX_CROSSFADE_MS))]
+    pub transition_ms: Option<u64>,
+    /// How each fade moves through its time: "linear" (the default), "ease-in", "ease-out" or "ease-in-out"
+    pub easing: Option<Easing>,
+    /// Why, e.g. "movie night"; recorded in each bulb's log
+    pub reason: Option<String>,
+}
+
+// How one bulb of a group took a scene, as apply_scene_to_group reports it
+#[derive(Debug, Serialize)]
+struct GroupSceneOutcome {
+    bulb_id: String,
+    scene: String,
+    overridden: bool,
+    applied: bool,
+    // Waiting for an unreachable bulb to come back
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    queued: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// A bulb as list_lightbulbs reports it
+#[derive(Debug, Serialize)]
+struct BulbReport {
+    id: String,
+    room: Option<String>,
+    name: Option<String>,
+    aliases: Vec<String>,
+    backend: String,
+    state: &'static str,
+    version: u64,
+    log: String,
+}
+
+// Named bulbs beside the server's own, gated behind the `bulbs` feature
+#[tool_router(router = bulb_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "List the lightbulbs this server switches, the server's own \"main\" first among them by id, with each one's room, name, aliases, backend, state and log resource")]
+    pub(super) async fn list_lightbulbs(&self) -> Result<CallToolResult, ErrorData> {
+        let mut reports = Vec::new();
+        for bulb in self.bulbs.all() {
+            let snapshot = bulb.light.snapshot().await?;
+            reports.push(BulbReport {
+                log: bulb_log_uri(&bulb.id),
+                backend: bulb.light.metadata().backend.clone(),
+                state: snapshot.state,
+                version: snapshot.version,
+                id: bulb.id,
+                room: bulb.room,
+                name: bulb.name,
+                aliases: bulb.aliases,
+            });
+        }
+        let listed: Vec<String> = reports
+            .iter()
+            .map(|report| {
+                let name = report.name.as_deref().map(|name| format!(" \"{}\"", name)).unwrap_or_default();
+                match &report.room {
+                    Some(room) => format!("{}{} ({}) {}", report.id, name, room, report.state),
+                    None => format!("{}{} {}", report.id, name, report.state),
+                }
+            })
+            .collect();
+        reply::json(format!("{} lightbulbs: {}", reports.len(), listed.join(", ")), &reports)
+    }
+
+    #[tool(description = "Add a simulated lightbulb beside the server's own, with a log of its own; the on/off and status tools switch it by its id as bulb_id")]
+    pub(super) async fn add_lightbulb(&self, Parameters(request): Parameters<AddLightbulbRequest>) -> Result<String, ErrorData> {
+        let config = BulbConfig {
+            id: request.id.trim().to_string(),
+            room: request.room.map(|room| room.trim().to_string()),
+            name: request.name.map(|name| name.trim().to_string()),
+            aliases: Vec::new(),
+            backend: BackendConfig::default(),
+        };
+        self.bulbs.check(&config)?;
+        let room = config.room.as_deref().map(|room| format!(" in room {}", room)).unwrap_or_default();
+        if self.dry_run {
+            return Ok(format!("{}: would add lightbulb '{}'{}, on the simulated backend", self.dry_run_prefix(), config.id, room));
+        }
+        let bulb = self.bulbs.add(&config, Box::new(SimulatedBackend::new()))?;
+        if bulb.name.is_some() {
+            bulb.light.acting_like(&self.light).log_named(bulb.name.as_deref(), &bulb.aliases).await?;
+        }
+        Ok(format!("Added lightbulb '{}'{}, on the simulated backend; its log is {}", bulb.label(), room, bulb_log_uri(&bulb.id)))
+    }
+
+    #[tool(
+        description = "Give a lightbulb a name people call it by, e.g. \"Desk Lamp\", or clear it; tools and the bulb's resources take the name, in any case, wherever they take a bulb_id. The change is logged in the bulb's log"
+    )]
+    pub(super) async fn rename_bulb(&self, Parameters(request): Parameters<RenameBulbRequest>) -> Result<String, ErrorData> {
+        let name = request.name.as_deref().map(str::trim);
+        let current = self.bulbs.get(request.bulb_id.trim())?;
+        let bulb = self.bulbs.check_names(&current.id, name, &current.aliases)?;
+        let described = match &bulb.name {
+            Some(name) => format!("named \"{}\"", name),
+            None => "without a name".to_string(),
+        };
+        if self.dry_run {
+            return Ok(format!("{}: would leave lightbulb '{}' {}", self.dry_run_prefix(), bulb.id, described));
+        }
+        self.set_bulb_names(&bulb.id, bulb.name.as_deref(), &bulb.aliases).await?;
+        Ok(format!("Lightbulb '{}' is now {}", bulb.id, described))
+    }
+
+    #[tool(
+        description = "Set the other names a lightbulb answers to, e.g. [\"reading light\"], replacing those it had; like its name, tools and resources take them wherever they take a bulb_id. The change is logged in the bulb's log"
+    )]
+    pub(super) async fn set_bulb_aliases(&self, Parameters(request): Parameters<BulbAliasesRequest>) -> Result<String, ErrorData> {
+        let aliases: Vec<String> = request.aliases.iter().map(|alias| alias.trim().to_string()).collect();
+        let current = self.bulbs.get(request.bulb_id.trim())?;
+        let bulb = self.bulbs.check_names(&current.id, current.name.as_deref(), &aliases)?;
+        let described = match bulb.aliases.is_empty() {
+            true => "no aliases".to_string(),
+            false => format!("the aliases {}", bulb.aliases.iter().map(|alias| format!("\"{}\"", alias)).collect::<Vec<_>>().join(", ")),
+        };
+        if self.dry_run {
+            return Ok(format!("{}: would give lightbulb '{}' {}", self.dry_run_prefix(), bulb.label(), described));
+        }
+        self.set_bulb_names(&bulb.id, bulb.name.as_deref(), &bulb.aliases).await?;
+        Ok(format!("Lightbulb '{}' now has {}", bulb.label(), described))
+    }
+
+    #[tool(description = "Turn every lightbulb in a room on or off at once, e.g. everything in the living-room; each bulb's change is logged in its own log")]
+    pub(super) async fn set_room_power(&self, Parameters(request): Parameters<RoomPowerRequest>) -> Result<String, ErrorData> {
+        let room = request.room.trim();
+        validate_room(room).map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        let bulbs = self.bulbs.in_room(room);
+        if bulbs.is_empty() {
+            return Err(LightError::InvalidParameter(format!("no lightbulb is in room '{}'; list_lightbulbs lists the rooms", room)).into());
+        }
+        let target = if request.on { PowerState::On } else { PowerState::Off };
+        let change = ChangeRequest { reason: request.reason, ..Default::default() };
+        let mut lights = Vec::new();
+        for bulb in &bulbs {
+            lights.push(self.change_handle_on(&self.bulb_handle(Some(&bulb.id))?, &change)?);
+        }
+        if self.dry_run {
+            let mut previews = Vec::new();
+            for (bulb, light) in bulbs.iter().zip(&lights) {
+                let preview = light.preview(vec![Change::Power(target)]).await?.into_iter().next();
+                let preview = preview.ok_or(LightError::Internal("a dry run previewed nothing".to_string()))??;
+                previews.push(format!("{}: {}", bulb.id, Self::describe_preview(&preview)));
+            }
+            return Ok(format!("{}: {}", self.dry_run_prefix(), previews.join("; ")));
+        }
+        let outcomes = join_all(lights.iter().map(|light| light.set_power(target))).await;
+        let action = target.log_action().to_lowercase();
+        let mut switched = 0;
+        let described: Vec<String> = bulbs
+            .iter()
+            .zip(outcomes)
+            .map(|(bulb, outcome)| match outcome {
+                Ok(applied) => {
+                    switched += 1;
+                    match applied.outcome {
+                        PowerChange::Changed => format!("{} (turned {})", bulb.id, action),
+                        PowerChange::AlreadyInState => format!("{} (already {})", bulb.id, action),
+                        PowerChange::Queued => format!("{} (queued until it is back)", bulb.id),
+                    }
+                },
+                Err(e) => format!("{} (failed: {})", bulb.id, e),
+            })
+            .collect();
+        let summary = format!("Turned {} {} of {} lightbulbs in {}: {}", action, switched, bulbs.len(), room, described.join(", "));
+        match switched {
+            0 => Err(LightError::ToolFailed(summary).into()),
+            _ => Ok(summary),
+        }
+    }
+
+    #[tool(
+        description = "Apply a scene to every lightbulb in a room, with some bulbs at their own brightness, temperature or color, at once or a bulb at a time with stagger_ms; reports which bulbs took it. Each bulb's change is logged in its own log, tagged with the scene"
+    )]
+    pub(super) async fn apply_scene_to_group(&self, Parameters(request): Parameters<GroupSceneRequest>) -> Result<CallToolResult, ErrorData> {
+        let scene = self.scenes.get(&request.name)?;
+        let room = request.room.trim();
+        validate_room(room).map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        let bulbs = self.bulbs.in_room(room);
+        if bulbs.is_empty() {
+            return Err(LightError::InvalidParameter(format!("no lightbulb is in room '{}'; list_lightbulbs lists the rooms", room)).into());
+        }
+        let stagger = request.stagger_ms.unwrap_or(0);
+        if stagger > MAX_STAGGER_MS {
+            return Err(LightError::InvalidParameter(format!("stagger_ms must be at most {}, got {}", MAX_STAGGER_MS, stagger)).into());
+        }
+        let fade = Crossfade::new(request.transition_ms.unwrap_or(0), request.easing.unwrap_or_default())?;
+        // Each bulb's scene, with its override if it has one
+        let mut scenes: Vec<Option<Scene>> = vec![None; bulbs.len()];
+        for wanted in &request.overrides {
+            let id = self.bulbs.get(wanted.bulb_id.trim())?.id;
+            let Some(i) = bulbs.iter().position(|bulb| bulb.id == id) else {
+                return Err(LightError::InvalidParameter(format!("lightbulb '{}' is not in room '{}'", id, room)).into());
+            };
+            if scenes[i].is_some() {
+                return Err(LightError::InvalidParameter(format!("lightbulb '{}' is overridden twice", id)).into());
+            }
+            scenes[i] = Some(scene.overridden(&id, wanted.brightness, wanted.kelvin, wanted.color.as_deref().map(str::trim))?);
+        }
+        let scenes: Vec<(Scene, bool)> = scenes.into_iter().map(|own| own.map_or((scene.clone(), false), |own| (own, true))).collect();
+        let change = ChangeRequest { reason: request.reason, tags: Some(vec![scene.name.clone()]), ..Default::default() };
+        let mut lights = Vec::new();
+        for bulb in &bulbs {
+            lights.push(self.change_handle_on(&self.bulb_handle(Some(&bulb.id))?, &change)?);
+        }
+        let fading = fade.map(|fade| format!(", fading in {}", fade.describe())).unwrap_or_default();
+        let staggered = match stagger {
+            0 => String::new(),
+            ms => format!(", {}ms apart", ms),
+        };
+        if self.dry_run {
+            let mut previews = Vec::new();
+            for ((bulb, light), (scene, _)) in bulbs.iter().zip(&lights).zip(&scenes) {
+                let changes = vec![Change::Brightness(scene.brightness), Change::Power(PowerState::On)];
+                let preview = light.preview(changes).await?.into_iter().last();
+                let preview = preview.ok_or(LightError::Internal("a dry run previewed nothing".to_string()))??;
+                previews.push(format!("{} ({}): {}", bulb.id, scene.describe(), Self::describe_preview(&preview)));
+            }
+            let summary = format!("{}: would apply scene '{}' to {} lightbulbs in {}{}{}; {}", self.dry_run_prefix(), scene.name, bulbs.len(), room, fading, staggered, previews.join("; "));
+            return Ok(reply::text(summary));
+        }
+        let applying = lights.iter().zip(&scenes).enumerate().map(|(i, (light, (scene, _)))| async move {
+            tokio::time::sleep(Duration::from_millis(stagger * i as u64)).await;
+            match fade {
+                Some(fade) => crossfade(light, scene, fade).await,
+                None => apply(light, scene).await,
+            }
+        });
+        let outcomes = join_all(applying).await;
+        let reports: Vec<GroupSceneOutcome> = bulbs
+            .iter()
+            .zip(scenes)
+            .zip(outcomes)
+            .map(|((bulb, (scene, overridden)), outcome)| {
+                let (queued, sequence, error) = match outcome {
+                    Ok(applied) => (applied.outcome == PowerChange::Queued, Some(applied.sequence), None),
+                    Err(e) => (false, None, Some(e.to_string())),
+                };
+                let applied = !queued && error.is_none();
+                GroupSceneOutcome { bulb_id: bulb.id.clone(), scene: scene.describe(), overridden, applied, queued, sequence, error }
+            })
+            .collect();
+        let took = reports.iter().filter(|report| report.applied).count();
+        let described: Vec<String> = reports
+            .iter()
+            .map(|report| match &report.error {
+                Some(error) => format!("{} (failed: {})", report.bulb_id, error),
+                None if report.queued => format!("{} (queued until it is back)", report.bulb_id),
+                None if report.overridden => format!("{} ({}, overridden)", report.bulb_id, report.scene),
+                None => format!("{} ({})", report.bulb_id, report.scene),
+            })
+            .collect();
+        let summary = format!("Applied scene '{}' to {} of {} lightbulbs in {}{}{}: {}", scene.name, took, reports.len(), room, fading, staggered, described.join(", "));
+        match took {
+            0 => Err(LightError::ToolFailed(summary).into()),
+            _ => reply::json(summary, &reports),
+        }
+    }
+}
+
+impl LightService {
+    // Renames the bulb, then logs it there as this session
+    async fn set_bulb_names(&self, id: &str, name: Option<&str>, aliases: &[String]) -> Result<(), LightError> {
+        self.bulbs.set_names(id, name, aliases)?;
+        self.bulb_handle(Some(id))?.log_named(name, aliases).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::InMemoryLogger;
+    use crate::service::power::{PowerRequest, StatusRequest};
+
+    fn add(id: &str, room: Option<&str>) -> Parameters<AddLightbulbRequest> {
+        Parameters(AddLightbulbRequest { id: id.to_string(), room: room.map(str::to_string), name: None })
+    }
+
+    #[tokio::test]
+    async fn test_bulbs_are_switched_by_id_and_by_room() {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).room("living-room".to_string()).build();
+        let added = service.add_lightbulb(add("lamp", Some("living-room"))).await.unwrap();
+        assert_eq!(added, "Added lightbulb 'lamp' in room living-room, on the simulated backend; its log is lightbulb://lamp/log");
+        service.add_lightbulb(add("desk", Some("study"))).await.unwrap();
+        let error = service.add_lightbulb(add("lamp", None)).await.unwrap_err();
+        assert!(error.message.contains("already exists"), "{}", error.message);
+        let dry_run = service.dry_run().add_lightbulb(add("hall", None)).await.unwrap();
+        assert!(dry_run.ends_with(": would add lightbulb 'hall', on the simulated backend"), "{}", dry_run);
+
+        let desk = PowerRequest { bulb_id: Some("desk".to_string()), ..Default::default() };
+        assert_eq!(service.turn_on_lightbulb(Parameters(desk)).await.unwrap(), "Lightbulb turned on successfully (sequence 1)");
+        let status = service.get_lightbulb_status(Parameters(StatusRequest { bulb_id: Some("desk".to_string()), ..Default::default() })).await.unwrap();
+        assert!(reply::summary_of(&status).starts_with("The lightbulb is on"), "{:?}", status);
+        let status = reply::json_of(&status).unwrap();
+        assert_eq!((status["bulb_id"].as_str(), status["power"].as_str()), (Some("desk"), Some("on")));
+        assert!(reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap()).starts_with("The lightbulb is off"));
+        let attic = PowerRequest { bulb_id: Some("attic".to_string()), ..Default::default() };
+        assert_eq!(service.turn_on_lightbulb(Parameters(attic)).await.unwrap_err().data.unwrap()["code"], "UNKNOWN_BULB");
+
+        let on = RoomPowerRequest { room: "living-room".to_string(), on: true, reason: Some("guests".to_string()) };
+        assert_eq!(service.set_room_power(Parameters(on)).await.unwrap(), "Turned on 2 of 2 lightbulbs in living-room: lamp (turned on), main (turned on)");
+        let lamp = service.bulbs.get("lamp").unwrap().light.read_log().await.unwrap();
+        assert!(lamp.contains("Lightbulb turned ON [lamp]") && lamp.contains("guests"), "{}", lamp);
+        assert!(!service.read_log_content().await.unwrap().contains("[lamp]"));
+        let nowhere = RoomPowerRequest { room: "attic".to_string(), on: false, reason: None };
+        assert_eq!(service.set_room_power(Parameters(nowhere)).await.unwrap_err().data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let listed = reply::json_of(&service.list_lightbulbs().await.unwrap()).unwrap();
+        assert_eq!(
+            listed[0],
+            serde_json::json!({ "id": "desk", "room": "study", "name": null, "aliases": [], "backend": "simulated", "state": "ON", "version": 2, "log": "lightbulb://desk/log" })
+        );
+        assert_eq!((listed[2]["id"].as_str(), listed[2]["room"].as_str()), (Some("main"), Some("living-room")));
+    }
+
+    #[tokio::test]
+    async fn test_bulbs_are_switched_by_name_and_alias() {
+        let service = crate::service::LightService::new_with_in_memory_logger();
+        let desk = AddLightbulbRequest { id: "desk".to_string(), room: None, name: Some("Desk Lamp".to_string()) };
+        assert_eq!(service.add_lightbulb(Parameters(desk)).await.unwrap(), "Added lightbulb 'desk (\"Desk Lamp\")', on the simulated backend; its log is lightbulb://desk/log");
+        service.add_lightbulb(add("hall", None)).await.unwrap();
+        let aliases = |bulb_id: &str, aliases: &[&str]| BulbAliasesRequest { bulb_id: bulb_id.to_string(), aliases: aliases.iter().map(|alias| alias.to_string()).collect() };
+
+        let dry_run = service.dry_run().set_bulb_aliases(Parameters(aliases("desk lamp", &["reading light"]))).await.unwrap();
+        assert!(dry_run.ends_with(": would give lightbulb 'desk (\"Desk Lamp\")' the aliases \"reading light\""), "{}", dry_run);
+        assert_eq!(service.set_bulb_aliases(Parameters(aliases("desk lamp", &["reading light"]))).await.unwrap(), "Lightbulb 'desk (\"Desk Lamp\")' now has the aliases \"reading light\"");
+        let clash = service.set_bulb_aliases(Parameters(aliases("hall", &["Reading Light"]))).await.unwrap_err();
+        assert!(clash.message.contains("already names lightbulb 'desk'"), "{}", clash.message);
+        let rename = RenameBulbRequest { bulb_id: "Reading Light".to_string(), name: Some("Study Lamp".to_string()) };
+        assert_eq!(service.rename_bulb(Parameters(rename)).await.unwrap(), "Lightbulb 'desk' is now named \"Study Lamp\"");
+        let main = RenameBulbRequest { bulb_id: "main".to_string(), name: Some("Ceiling".to_string()) };
+        service.rename_bulb(Parameters(main)).await.unwrap();
+
+        let on = PowerRequest { bulb_id: Some("study lamp".to_string()), ..Default::default() };
+        service.turn_on_lightbulb(Parameters(on)).await.unwrap();
+        let status = service.get_lightbulb_status(Parameters(StatusRequest { bulb_id: Some("READING LIGHT".to_string()), ..Default::default() })).await.unwrap();
+        let status = reply::json_of(&status).unwrap();
+        assert_eq!((status["bulb_id"].as_str(), status["name"].as_str(), status["power"].as_str()), (Some("desk"), Some("Study Lamp"), Some("on")));
+        let ceiling = PowerRequest { bulb_id: Some("ceiling".to_string()), ..Default::default() };
+        service.turn_on_lightbulb(Parameters(ceiling)).await.unwrap();
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::On));
+
+        let log = service.bulbs.get("desk").unwrap().light.read_log().await.unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].contains("Lightbulb NAMED \"Desk Lamp\""), "{}", log);
+        assert!(lines[1].contains("Lightbulb NAMED \"Desk Lamp\" (aliases: \"reading light\")"), "{}", log);
+        assert!(lines[2].contains("Lightbulb NAMED \"Study Lamp\" (aliases: \"reading light\")"), "{}", log);
+        assert!(service.read_log_content().await.unwrap().lines().next().unwrap().contains("Lightbulb NAMED \"Ceiling\""));
+        let entry = crate::stats::HistoryEntry::parse(lines[2]).unwrap();
+        assert_eq!(entry.action, crate::stats::LogAction::Named);
+
+        let clear = RenameBulbRequest { bulb_id: "study lamp".to_string(), name: None };
+        assert_eq!(service.rename_bulb(Parameters(clear)).await.unwrap(), "Lightbulb 'desk' is now without a name");
+        assert_eq!(service.bulbs.get("reading light").unwrap().name, None);
+        assert!(service.bulbs.get("study lamp").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_added_bulbs_are_held_to_the_configured_log_privacy_and_toggle_interval() {
+        use crate::config::{LogPrivacyConfig, Redaction};
+        use crate::privacy::LogPrivacy;
+
+        let privacy = LogPrivacyConfig { clients: Redaction::Hash, reasons: Redaction::Omit, salt: Some("pepper".to_string()) };
+        let service = LightService::builder()
+            .logger(Box::new(InMemoryLogger::new()))
+            .log_privacy(LogPrivacy::new(&privacy))
+            .min_toggle_interval(Duration::from_secs(60))
+            .build();
+        service.add_lightbulb(add("lamp", None)).await.unwrap();
+        let lamp = service.bulbs.get("lamp").unwrap().light;
+        lamp.acting_as("tester").with_reason(Some("movie night".to_string())).set_power(PowerState::On).await.unwrap();
+        let log = lamp.read_log().await.unwrap();
+        assert!(log.contains("Lightbulb turned ON [lamp] by "), "{}", log);
+        assert!(!log.contains("tester") && !log.contains("movie night"), "{}", log);
+
+        let error = lamp.set_power(PowerState::Off).await.unwrap_err();
+        assert!(matches!(error, LightError::ToggleTooSoon { min_interval_ms: 60000, .. }), "{:?}", error);
+        assert_eq!(lamp.state().await.unwrap().power(), Some(PowerState::On));
+    }
+
+    #[tokio::test]
+    async fn test_scene_is_applied_to_a_room_with_overrides() {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).room("living-room".to_string()).build();
+        service.add_lightbulb(add("lamp", Some("living-room"))).await.unwrap();
+        service.add_lightbulb(add("desk", Some("study"))).await.unwrap();
+        let lamp = BulbSceneOverride { bulb_id: "lamp".to_string(), brightness: Some(5), kelvin: None, color: Some("amber".to_string()) };
+        let request = |overrides: Vec<BulbSceneOverride>, stagger_ms: Option<u64>| GroupSceneRequest {
+            name: "movie".to_string(),
+            room: "living-room".to_string(),
+            overrides,
+            stagger_ms,
+            transition_ms: None,
+            easing: None,
+            reason: Some("film".to_string()),
+        };
+
+        let dry_run = reply::text_of(&service.dry_run().apply_scene_to_group(Parameters(request(Vec::new(), None))).await.unwrap());
+        assert!(dry_run.contains(": would apply scene 'movie' to 2 lightbulbs in living-room; lamp (20% at 2700K): "), "{}", dry_run);
+        let desk = BulbSceneOverride { bulb_id: "desk".to_string(), brightness: Some(5), kelvin: None, color: None };
+        assert!(service.apply_scene_to_group(Parameters(request(vec![desk], None))).await.unwrap_err().message.contains("not in room"));
+        let dark = BulbSceneOverride { bulb_id: "lamp".to_string(), brightness: Some(0), kelvin: None, color: None };
+        let error = service.apply_scene_to_group(Parameters(request(vec![dark], None))).await.unwrap_err().message;
+        assert!(error.contains("the override for lightbulb 'lamp' must set brightness between 1 and 100, got 0"), "{}", error);
+        assert!(service.apply_scene_to_group(Parameters(request(Vec::new(), Some(60_000)))).await.is_err());
+
+        let applied = service.apply_scene_to_group(Parameters(request(vec![lamp], Some(50)))).await.unwrap();
+        assert_eq!(
+            reply::summary_of(&applied),
+            "Applied scene 'movie' to 2 of 2 lightbulbs in living-room, 50ms apart: lamp (5% in #ffbf00, overridden), main (20% at 2700K)"
+        );
+        let reports = reply::json_of(&applied).unwrap();
+        assert_eq!((reports[0]["bulb_id"].as_str(), reports[0]["applied"].as_bool(), reports[0]["overridden"].as_bool()), (Some("lamp"), Some(true), Some(true)));
+        let lamp = service.bulbs.get("lamp").unwrap().light;
+        assert_eq!(lamp.snapshot().await.unwrap().brightness, 5);
+        assert_eq!(service.light.snapshot().await.unwrap().brightness, 20);
+        let log = lamp.read_log().await.unwrap();
+        assert!(log.contains("Lightbulb turned ON [movie,lamp]") && log.contains("film"), "{}", log);
+        assert!(service.bulbs.get("desk").unwrap().light.read_log().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,67 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::LightService;
+use crate::actor::PowerChange;
+use crate::config::RulePower;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ReportContactRequest {
+    /// Door or window sensor, as configured
+    pub sensor: String,
+    /// True when it opened, false when it closed
+    pub open: bool,
+}
+
+// Door and window sensors, gated behind the `contact` feature
+#[tool_router(router = contact_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Report a door or window sensor opening or closing, switching the lightbulb as configured for it, e.g. on while a closet door is open")]
+    pub(super) async fn report_contact(&self, Parameters(request): Parameters<ReportContactRequest>) -> Result<String, ErrorData> {
+        let sensor = self.contacts.sensor(&request.sensor)?;
+        let opened = if request.open { "opened" } else { "closed" };
+        let target = match sensor.action(request.open) {
+            RulePower::On => "ON",
+            RulePower::Off => "OFF",
+        };
+        if self.dry_run {
+            return Ok(format!("{}: {} {} would turn the lightbulb {}", self.dry_run_prefix(), sensor.sensor, opened, target));
+        }
+        let applied = self.contacts.report(&self.light, sensor, request.open).await?;
+        Ok(match applied.outcome {
+            PowerChange::Changed => Self::with_sequence(&format!("{} {} and turned the lightbulb {}", sensor.sensor, opened, target), applied.sequence),
+            PowerChange::AlreadyInState => format!("{} {}; the lightbulb was already {}", sensor.sensor, opened, target),
+            PowerChange::Queued => format!("{} {}. {}", sensor.sensor, opened, Self::queued(&format!("turn it {}", target), applied.sequence)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ContactConfig;
+    use crate::logger::InMemoryLogger;
+
+    #[tokio::test]
+    async fn test_report_contact() {
+        let closet = ContactConfig {
+            sensor: "closet-door".to_string(),
+            topic: None,
+            bulbs: Vec::new(),
+            on_open: RulePower::On,
+            on_close: RulePower::Off,
+            brightness: None,
+        };
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).contact_sensors(vec![closet]).build();
+        let report = |sensor: &str, open: bool| service.report_contact(Parameters(ReportContactRequest { sensor: sensor.to_string(), open }));
+
+        let dry_run = service.dry_run().report_contact(Parameters(ReportContactRequest { sensor: "closet-door".to_string(), open: true })).await.unwrap();
+        assert!(dry_run.ends_with(": closet-door opened would turn the lightbulb ON"), "{}", dry_run);
+        assert_eq!(report("closet-door", true).await.unwrap(), "closet-door opened and turned the lightbulb ON (sequence 1)");
+        assert_eq!(report("closet-door", true).await.unwrap(), "closet-door opened; the lightbulb was already ON");
+        assert_eq!(report("closet-door", false).await.unwrap(), "closet-door closed and turned the lightbulb OFF (sequence 3)");
+        assert!(report("garage", true).await.unwrap_err().message.contains("Unknown contact sensor 'garage'"));
+    }
+}
@@ -0,0 +1,106 @@
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+
+use super::LightService;
+use crate::clock::format_duration;
+use crate::diagnostics::{CheckStatus, DiagnosticsReport, HealthProbe, build_report};
+use crate::i18n::Message;
+use crate::info::ServerInfoReport;
+use crate::reply;
+
+const DIAGNOSTICS_PASSED: Message = Message::new("diagnostics-passed", "All { $total } checks passed or were skipped");
+const DIAGNOSTICS_FAILED: Message = Message::new("diagnostics-failed", "{ $failed } of { $total } checks failed: { $checks }");
+
+// Self-checks for support, gated behind the `diagnostics` feature
+#[tool_router(router = diagnostic_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Check log writability and integrity, backend reachability, scheduler health and clock sanity, returning a JSON pass/fail report")]
+    async fn run_diagnostics(&self) -> Result<CallToolResult, ErrorData> {
+        let probe = self.health_probe().await?;
+        let report = build_report(&probe, self.light.clock().now());
+        reply::json(describe_report(&report), &report)
+    }
+
+    #[tool(description = "Report the server version, git hash, enabled features, uptime, transport, backends and file paths as JSON")]
+    async fn server_info(&self) -> Result<CallToolResult, ErrorData> {
+        let report = ServerInfoReport::new(&self.deployment, self.started_at);
+        let summary = format!("lightbulb-mcp {} on the {} backend, up {}", report.version, report.backend, format_duration(report.uptime_secs as i64));
+        reply::json(summary, &report)
+    }
+}
+
+impl LightService {
+    // What the bulb's actor can see of its own health, along with the files the schedules and timers are kept in
+    pub(super) async fn health_probe(&self) -> Result<HealthProbe, ErrorData> {
+        #[allow(unused_mut)]
+        let mut probe = self.light.probe().await?;
+        #[cfg(feature = "schedules")]
+        probe.state_files.extend(self.automation.state_files());
+        Ok(probe)
+    }
+}
+
+fn describe_report(report: &DiagnosticsReport) -> String {
+    let failed: Vec<&str> = report.checks.iter().filter(|check| check.status == CheckStatus::Fail).map(|check| check.name).collect();
+    match failed.as_slice() {
+        [] => DIAGNOSTICS_PASSED.text(&[("total", &report.checks.len())]),
+        failed => DIAGNOSTICS_FAILED.text(&[("failed", &failed.len()), ("total", &report.checks.len()), ("checks", &failed.join(", "))]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::InMemoryLogger;
+    use crate::service::tests::UnreachableBackend;
+
+    #[tokio::test]
+    async fn test_run_diagnostics_reports_backend_failure() {
+        let service = LightService::new_with_in_memory_logger();
+        let report: serde_json::Value = reply::json_of(&service.run_diagnostics().await.unwrap()).unwrap();
+        assert_eq!(report["healthy"], true);
+
+        let service = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(UnreachableBackend));
+        let result = service.run_diagnostics().await.unwrap();
+        assert!(reply::text_of(&result).starts_with("1 of "), "{}", reply::text_of(&result));
+        assert!(reply::text_of(&result).contains("checks failed: backend\n"), "{}", reply::text_of(&result));
+        let report = reply::json_of(&result).unwrap();
+        assert_eq!(report["healthy"], false);
+        let backend = report["checks"].as_array().unwrap().iter().find(|check| check["name"] == "backend").unwrap();
+        assert_eq!(backend["status"], "fail");
+    }
+
+    #[cfg(feature = "schedules")]
+    #[tokio::test]
+    async fn test_run_diagnostics_reads_back_the_automation_file() {
+        let file = std::env::temp_dir().join(format!("lightbulb-diagnostics-{}.automation.json", std::process::id())).display().to_string();
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).automation_file(file.clone()).build();
+        let check = |report: &serde_json::Value, name: &str| report["checks"].as_array().unwrap().iter().find(|check| check["name"] == name).unwrap().clone();
+
+        let report = reply::json_of(&service.run_diagnostics().await.unwrap()).unwrap();
+        assert_eq!((check(&report, "state_file")["status"].clone(), check(&report, "scheduler")["status"].clone()), ("pass".into(), "pass".into()), "{}", report);
+
+        std::fs::write(&file, "{ not json").unwrap();
+        let report = reply::json_of(&service.run_diagnostics().await.unwrap()).unwrap();
+        let _ = std::fs::remove_file(&file);
+        let state_file = check(&report, "state_file");
+        assert_eq!(state_file["status"], "fail");
+        assert!(state_file["detail"].as_str().unwrap().contains(&file), "{}", state_file);
+    }
+
+    #[tokio::test]
+    async fn test_server_info_reports_build_and_deployment() {
+        let config = crate::config::Config::parse("log_file = \"custom.log\"").unwrap();
+        let service = LightService::builder_from_config(&config, &crate::registry::BackendRegistry::with_builtin())
+            .unwrap()
+            .logger(Box::new(InMemoryLogger::new()))
+            .transport("stdio")
+            .build();
+        let info: serde_json::Value = reply::json_of(&service.server_info().await.unwrap()).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(info["transport"], "stdio");
+        assert_eq!(info["backend"], "simulated");
+        assert_eq!(info["log_file"], "custom.log");
+        assert!(info["features"].as_array().unwrap().contains(&serde_json::json!("diagnostics")));
+    }
+}
@@ -0,0 +1,408 @@
+use std::time::Duration;
+
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+use tokio::sync::oneshot;
+
+use super::LightService;
+use crate::actor::LightHandle;
+use crate::effects::{EffectStep, PRESETS, flash_steps, morse_steps, party_steps};
+use crate::error::LightError;
+#[cfg(feature = "jobs")]
+use crate::jobs::JobStatus;
+use crate::model::PowerState;
+use crate::state::{LightState, Transition, TransitionError};
+
+const DEFAULT_MORSE_UNIT_MS: u64 = 200;
+const MIN_MORSE_UNIT_MS: u64 = 50;
+const MAX_MORSE_UNIT_MS: u64 = 2000;
+const MAX_MORSE_TEXT_LEN: usize = 32;
+const DEFAULT_IDENTIFY_FLASHES: u32 = 3;
+const MAX_IDENTIFY_FLASHES: u32 = 10;
+const IDENTIFY_PERIOD: Duration = Duration::from_millis(500);
+const DEFAULT_EFFECT_SECS: u64 = 30;
+const MAX_EFFECT_SECS: u64 = 600;
+const MIN_EFFECT_PERIOD_MS: u64 = 50;
+const MAX_EFFECT_PERIOD_MS: u64 = 5000;
+const DEFAULT_PARTY_BPM: u32 = 120;
+const MIN_PARTY_BPM: u32 = 30;
+const MAX_PARTY_BPM: u32 = 240;
+const PARTY_EFFECT: &str = "party";
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct FlashMorseRequest {
+    /// Text to flash, using letters, digits and spaces (at most 32 characters)
+    #[schemars(length(min = 1, max = MAX_MORSE_TEXT_LEN), pattern(r"^[A-Za-z0-9 ]+$"))]
+    pub text: String,
+    /// Length of one Morse unit (a dot) in milliseconds, between 50 and 2000 (default 200)
+    #[schemars(range(min = MIN_MORSE_UNIT_MS, max = MAX_MORSE_UNIT_MS))]
+    pub unit_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct IdentifyRequest {
+    /// Which lightbulb to flash, by the id, name or alias list_lightbulbs gives it (default: the server's own, "main")
+    pub bulb_id: Option<String>,
+    /// Number of times to flash, between 1 and 10 (default 3)
+    #[schemars(range(min = 1, max = MAX_IDENTIFY_FLASHES))]
+    pub flashes: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct StartEffectRequest {
+    /// Name of the effect: a preset (halloween, christmas or new_year) or one the server registers, as the lightbulb://effects resource lists
+    #[schemars(extend("enum" = PRESETS.iter().map(|preset| preset.name).collect::<Vec<_>>()))]
+    pub effect: String,
+    /// How long to run the effect, between 1 and 600 seconds (default 30)
+    #[schemars(range(min = 1, max = MAX_EFFECT_SECS))]
+    pub duration_secs: Option<u64>,
+    /// Length of each step in milliseconds, between 50 and 5000 (defaults to the preset's own)
+    #[schemars(range(min = MIN_EFFECT_PERIOD_MS, max = MAX_EFFECT_PERIOD_MS))]
+    pub period_ms: Option<u64>,
+    /// Seed for effects with randomness, such as the Halloween flicker, so a run can be repeated exactly
+    pub seed: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct PartyModeRequest {
+    /// Beats per minute, between 30 and 240 (default 120); the color changes and brightness pulses on every beat
+    #[schemars(range(min = MIN_PARTY_BPM, max = MAX_PARTY_BPM))]
+    pub bpm: Option<u32>,
+    /// How long to party, between 1 and 600 seconds (default 30)
+    #[schemars(range(min = 1, max = MAX_EFFECT_SECS))]
+    pub duration_secs: Option<u64>,
+    /// Seed for the random hues, so a run can be repeated exactly
+    pub seed: Option<u64>,
+}
+
+// Light effects run in the background, gated behind the `effects` feature
+#[tool_router(router = effect_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Blink the lightbulb in Morse code for a short text, in the background")]
+    async fn flash_morse(&self, Parameters(request): Parameters<FlashMorseRequest>) -> Result<String, ErrorData> {
+        let text = request.text.trim();
+        if text.is_empty() || text.chars().count() > MAX_MORSE_TEXT_LEN {
+            return Err(LightError::InvalidParameter(format!("text must be 1 to {} characters", MAX_MORSE_TEXT_LEN)).into());
+        }
+        let unit_ms = request.unit_ms.unwrap_or(DEFAULT_MORSE_UNIT_MS);
+        if !(MIN_MORSE_UNIT_MS..=MAX_MORSE_UNIT_MS).contains(&unit_ms) {
+            return Err(LightError::InvalidParameter(format!(
+                "unit_ms must be between {} and {}, got {}",
+                MIN_MORSE_UNIT_MS, MAX_MORSE_UNIT_MS, unit_ms
+            ))
+            .into());
+        }
+        let steps = morse_steps(text, Duration::from_millis(unit_ms))
+            .map_err(|c| LightError::InvalidParameter(format!("'{}' has no Morse code", c)))?;
+
+        let duration: Duration = steps.iter().map(|step| step.duration).sum();
+        let job = self.begin_effect(format!("morse: {}", text), steps).await?;
+        if self.dry_run {
+            return Ok(format!("{}: would flash '{}' in Morse code (about {:.1}s)", self.dry_run_prefix(), text, duration.as_secs_f64()));
+        }
+        Ok(format!(
+            "Flashing '{}' in Morse code (about {:.1}s); call stop_effect to cancel{}",
+            text,
+            duration.as_secs_f64(),
+            job
+        ))
+    }
+
+    #[tool(description = "Briefly flash the lightbulb a few times so it can be picked out, then restore its state")]
+    pub(super) async fn identify_bulb(&self, Parameters(request): Parameters<IdentifyRequest>) -> Result<String, ErrorData> {
+        let flashes = request.flashes.unwrap_or(DEFAULT_IDENTIFY_FLASHES);
+        if !(1..=MAX_IDENTIFY_FLASHES).contains(&flashes) {
+            return Err(LightError::InvalidParameter(format!(
+                "flashes must be between 1 and {}, got {}",
+                MAX_IDENTIFY_FLASHES, flashes
+            ))
+            .into());
+        }
+        let light = self.bulb_handle(request.bulb_id.as_deref())?;
+        let which = match request.bulb_id {
+            Some(bulb_id) => format!("lightbulb '{}'", bulb_id.trim()),
+            None => "the lightbulb".to_string(),
+        };
+        let job = self.begin_effect_on(light, "identify".to_string(), flash_steps(flashes, IDENTIFY_PERIOD)).await?;
+        if self.dry_run {
+            return Ok(format!("{}: would flash {} {} times to identify it", self.dry_run_prefix(), which, flashes));
+        }
+        Ok(format!("Flashing {} {} times to identify it{}", which, flashes, job))
+    }
+
+    #[tool(description = "Start a themed effect (halloween, christmas, new_year or one the server registers) in the background, restoring the lightbulb afterwards")]
+    async fn start_effect(&self, Parameters(request): Parameters<StartEffectRequest>) -> Result<String, ErrorData> {
+        let effect = self.effect_registry.get(&request.effect).ok_or_else(|| {
+            LightError::InvalidParameter(format!("unknown effect '{}'; try one of: {}", request.effect.trim(), self.effect_registry.names().join(", ")))
+        })?;
+        let secs = request.duration_secs.unwrap_or(DEFAULT_EFFECT_SECS);
+        if !(1..=MAX_EFFECT_SECS).contains(&secs) {
+            return Err(LightError::InvalidParameter(format!("duration_secs must be between 1 and {}, got {}", MAX_EFFECT_SECS, secs)).into());
+        }
+        let period = match request.period_ms {
+            Some(ms) if !(MIN_EFFECT_PERIOD_MS..=MAX_EFFECT_PERIOD_MS).contains(&ms) => {
+                return Err(LightError::InvalidParameter(format!(
+                    "period_ms must be between {} and {}, got {}",
+                    MIN_EFFECT_PERIOD_MS, MAX_EFFECT_PERIOD_MS, ms
+                ))
+                .into());
+            },
+            Some(ms) => Duration::from_millis(ms),
+            None => effect.period,
+        };
+        let seed = request.seed.unwrap_or_else(rand::random);
+        let steps = effect.steps(Duration::from_secs(secs), period, seed);
+
+        let job = self.begin_effect(effect.name.clone(), steps).await?;
+        if self.dry_run {
+            return Ok(format!("{}: would run effect '{}' for {}s", self.dry_run_prefix(), effect.name, secs));
+        }
+        Ok(format!("Running effect '{}' ({}) for {}s; call stop_effect to cancel{}", effect.name, effect.description.to_lowercase(), secs, job))
+    }
+
+    #[tool(description = "Party mode: a random hue on every beat at the given BPM with pulsing brightness, in the background; stop_effect restores the lightbulb")]
+    async fn start_party_mode(&self, Parameters(request): Parameters<PartyModeRequest>) -> Result<String, ErrorData> {
+        let bpm = request.bpm.unwrap_or(DEFAULT_PARTY_BPM);
+        if !(MIN_PARTY_BPM..=MAX_PARTY_BPM).contains(&bpm) {
+            return Err(LightError::InvalidParameter(format!("bpm must be between {} and {}, got {}", MIN_PARTY_BPM, MAX_PARTY_BPM, bpm)).into());
+        }
+        let secs = request.duration_secs.unwrap_or(DEFAULT_EFFECT_SECS);
+        if !(1..=MAX_EFFECT_SECS).contains(&secs) {
+            return Err(LightError::InvalidParameter(format!("duration_secs must be between 1 and {}, got {}", MAX_EFFECT_SECS, secs)).into());
+        }
+        let steps = party_steps(Duration::from_secs(secs), bpm, request.seed.unwrap_or_else(rand::random));
+        let changes = steps.len();
+
+        let job = self.begin_effect(PARTY_EFFECT.to_string(), steps).await?;
+        if self.dry_run {
+            return Ok(format!("{}: would party at {} BPM for {}s ({} changes)", self.dry_run_prefix(), bpm, secs, changes));
+        }
+        Ok(format!("Partying at {} BPM for {}s ({} changes); call stop_effect to end the party{}", bpm, secs, changes, job))
+    }
+
+    #[tool(description = "Stop the running light effect and restore the lightbulb")]
+    async fn stop_effect(&self) -> Result<String, ErrorData> {
+        if self.dry_run {
+            let name = self.effects.active().ok_or(LightError::NoActiveEffect)?;
+            return Ok(format!("{}: would stop effect '{}'", self.dry_run_prefix(), name));
+        }
+        let name = self.effects.cancel().ok_or(LightError::NoActiveEffect)?;
+        Ok(format!("Stopped effect '{}'", name))
+    }
+
+    // Effects need a bulb that is idle, not locked and able to show them, so refuse up front rather than failing
+    // in the background. A dry run stops once it knows the effect would start. Returns the note naming the
+    // effect's job for the reply.
+    async fn begin_effect(&self, name: String, steps: Vec<EffectStep>) -> Result<String, LightError> {
+        self.begin_effect_on(self.light.clone(), name, steps).await
+    }
+
+    // As begin_effect, on the bulb `light` is the handle of; one effect runs at a time, whichever bulb it is on
+    async fn begin_effect_on(&self, light: LightHandle, name: String, steps: Vec<EffectStep>) -> Result<String, LightError> {
+        let state = light.state().await?;
+        if !matches!(state, LightState::Off | LightState::On { .. }) {
+            return Err(TransitionError { state: state.label(), transition: Transition::Begin(PowerState::On) }.into());
+        }
+        let metadata = light.metadata();
+        if !metadata.supports_color && steps.iter().any(|step| step.color.is_some()) {
+            return Err(LightError::ColorUnsupported);
+        }
+        if !metadata.supports_brightness && steps.iter().any(|step| step.brightness.is_some()) {
+            return Err(LightError::BrightnessUnsupported);
+        }
+        if self.dry_run {
+            return self.effects.active().map_or(Ok(String::new()), |active| Err(LightError::EffectAlreadyRunning(active)));
+        }
+        let done = self.effects.start(light, name.clone(), steps).map_err(LightError::EffectAlreadyRunning)?;
+        self.effect_job(name, done)
+    }
+
+    // With the `jobs` feature every effect is also a job, which cancel_job stops like stop_effect
+    #[cfg(feature = "jobs")]
+    fn effect_job(&self, name: String, mut done: oneshot::Receiver<bool>) -> Result<String, LightError> {
+        let effects = self.effects.clone();
+        let job = self.jobs.start("effect", format!("Effect '{}'", name), |_, cancel| async move {
+            let finished = tokio::select! {
+                biased;
+                finished = &mut done => finished.unwrap_or(false),
+                _ = cancel.cancelled() => {
+                    effects.cancel();
+                    done.await.unwrap_or(false)
+                },
+            };
+            match finished {
+                true => (JobStatus::Completed, format!("Effect '{}' ran to its end", name)),
+                false => (JobStatus::Cancelled, format!("Effect '{}' was stopped", name)),
+            }
+        });
+        match job {
+            Ok(id) => Ok(format!(" ({})", id)),
+            Err(e) => {
+                self.effects.cancel();
+                Err(e)
+            },
+        }
+    }
+
+    #[cfg(not(feature = "jobs"))]
+    fn effect_job(&self, _name: String, _done: oneshot::Receiver<bool>) -> Result<String, LightError> {
+        Ok(String::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn morse(text: &str, unit_ms: Option<u64>) -> Parameters<FlashMorseRequest> {
+        Parameters(FlashMorseRequest { text: text.to_string(), unit_ms })
+    }
+
+    // What replies end with when effects run as jobs
+    fn job_note(id: &str) -> String {
+        if cfg!(feature = "jobs") { format!(" ({})", id) } else { String::new() }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flash_morse_runs_until_stopped() {
+        let service = LightService::new_with_in_memory_logger();
+        assert!(service.flash_morse(morse("SOS", Some(100))).await.is_ok());
+
+        let error = service.flash_morse(morse("SOS", None)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "EFFECT_ALREADY_RUNNING");
+
+        assert_eq!(service.stop_effect().await.unwrap(), "Stopped effect 'morse: SOS'");
+        let error = service.stop_effect().await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "NO_ACTIVE_EFFECT");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_identify_restores_previous_state() {
+        let service = LightService::new_with_in_memory_logger();
+        service.light.set_power(PowerState::On).await.unwrap();
+        assert!(service.identify_bulb(Parameters(IdentifyRequest { bulb_id: None, flashes: Some(2) })).await.is_ok());
+
+        tokio::time::sleep(Duration::from_millis(750)).await;
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::Off));
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert!(service.effects.active().is_none());
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::On));
+
+        let error = service.identify_bulb(Parameters(IdentifyRequest { bulb_id: None, flashes: Some(0) })).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+    }
+
+    #[cfg(feature = "bulbs")]
+    #[tokio::test(start_paused = true)]
+    async fn test_identify_flashes_an_added_bulb() {
+        use crate::service::bulbs::AddLightbulbRequest;
+
+        let service = LightService::new_with_in_memory_logger();
+        let desk = AddLightbulbRequest { id: "desk".to_string(), room: None, name: Some("Desk Lamp".to_string()) };
+        service.add_lightbulb(Parameters(desk)).await.unwrap();
+        let desk = service.bulbs.get("desk").unwrap().light;
+        desk.set_power(PowerState::On).await.unwrap();
+
+        let identify = IdentifyRequest { bulb_id: Some("desk lamp".to_string()), flashes: Some(2) };
+        let reply = service.identify_bulb(Parameters(identify)).await.unwrap();
+        assert!(reply.starts_with("Flashing lightbulb 'desk lamp' 2 times to identify it"), "{}", reply);
+        tokio::time::sleep(Duration::from_millis(750)).await;
+        assert_eq!(desk.state().await.unwrap().power(), Some(PowerState::Off));
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::Off));
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(desk.state().await.unwrap().power(), Some(PowerState::On));
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::Off));
+
+        let attic = IdentifyRequest { bulb_id: Some("attic".to_string()), flashes: None };
+        assert_eq!(service.identify_bulb(Parameters(attic)).await.unwrap_err().data.unwrap()["code"], "UNKNOWN_BULB");
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_effects_do_not_start() {
+        let service = LightService::new_with_in_memory_logger().dry_run();
+        let report = service.flash_morse(morse("SOS", Some(100))).await.unwrap();
+        assert!(report.starts_with("Dry run against the simulated backend, nothing was changed: would flash 'SOS'"), "{}", report);
+        assert!(service.effects.active().is_none());
+        let error = service.stop_effect().await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "NO_ACTIVE_EFFECT");
+        let error = service.flash_morse(morse("SOS?", None)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_start_effect_runs_a_preset() {
+        let service = LightService::new_with_in_memory_logger();
+        let start = |effect: &str, duration_secs: Option<u64>, period_ms: Option<u64>| {
+            Parameters(StartEffectRequest { effect: effect.to_string(), duration_secs, period_ms, seed: Some(1) })
+        };
+        let reply = service.start_effect(start("Christmas", Some(10), None)).await.unwrap();
+        assert_eq!(reply, format!("Running effect 'christmas' (red and green alternation) for 10s; call stop_effect to cancel{}", job_note("job-1")));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(service.light.snapshot().await.unwrap().color, crate::model::Color { r: 0, g: 255, b: 0 });
+        assert_eq!(service.stop_effect().await.unwrap(), "Stopped effect 'christmas'");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let restored = service.light.snapshot().await.unwrap();
+        assert_eq!((restored.power, restored.color), (Some(PowerState::Off), crate::model::Color::WHITE));
+
+        let error = service.start_effect(start("easter", None, None)).await.unwrap_err();
+        assert!(error.message.contains("try one of: halloween, christmas, new_year"), "{}", error.message);
+        for request in [start("halloween", Some(0), None), start("new_year", None, Some(10))] {
+            let error = service.start_effect(request).await.unwrap_err();
+            assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_start_effect_runs_a_registered_effect() {
+        let mut registry = crate::registry::EffectRegistry::with_builtin();
+        registry.register("pulse", "Slow breathing", Duration::from_millis(500), |duration, period, _| {
+            (0..duration.as_millis() / period.as_millis()).map(|i| EffectStep::new(PowerState::On, period).with_brightness(if i % 2 == 0 { 100 } else { 30 })).collect()
+        });
+        let service = LightService::builder().logger(Box::new(crate::logger::InMemoryLogger::new())).effect_registry(registry).build();
+        let schema = service.tool_router.list_all().into_iter().find(|tool| tool.name == "start_effect").unwrap().input_schema;
+        assert_eq!(schema["properties"]["effect"]["enum"], serde_json::json!(["halloween", "christmas", "new_year", "pulse"]));
+
+        let request = StartEffectRequest { effect: "pulse".to_string(), duration_secs: Some(5), period_ms: None, seed: None };
+        let reply = service.start_effect(Parameters(request)).await.unwrap();
+        assert_eq!(reply, format!("Running effect 'pulse' (slow breathing) for 5s; call stop_effect to cancel{}", job_note("job-1")));
+        tokio::time::sleep(Duration::from_millis(750)).await;
+        assert_eq!(service.light.snapshot().await.unwrap().brightness, 30);
+        assert_eq!(service.stop_effect().await.unwrap(), "Stopped effect 'pulse'");
+
+        let error = service.start_effect(Parameters(StartEffectRequest { effect: "easter".to_string(), duration_secs: None, period_ms: None, seed: None })).await.unwrap_err();
+        assert!(error.message.contains("try one of: halloween, christmas, new_year, pulse"), "{}", error.message);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_party_mode_restores_the_bulb() {
+        let service = LightService::new_with_in_memory_logger();
+        service.light.set_power(PowerState::On).await.unwrap();
+        service.light.set_brightness(60).await.unwrap();
+        let party = |bpm: Option<u32>| Parameters(PartyModeRequest { bpm, duration_secs: Some(4), seed: Some(9) });
+
+        let reply = service.start_party_mode(party(Some(60))).await.unwrap();
+        assert_eq!(reply, format!("Partying at 60 BPM for 4s (8 changes); call stop_effect to end the party{}", job_note("job-1")));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let during = service.light.snapshot().await.unwrap();
+        assert_ne!(during.color, crate::model::Color::WHITE);
+        assert_eq!(during.brightness, 100);
+        assert_eq!(service.effects.active().as_deref(), Some("party"));
+
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert!(service.effects.active().is_none());
+        assert_eq!(service.light.state().await.unwrap(), LightState::On { brightness: 60, color: crate::model::Color::WHITE });
+        let error = service.start_party_mode(party(Some(500))).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+    }
+
+    #[tokio::test]
+    async fn test_flash_morse_validates_input() {
+        let service = LightService::new_with_in_memory_logger();
+        for request in [morse("", None), morse("SOS?", None), morse("SOS", Some(10))] {
+            let error = service.flash_morse(request).await.unwrap_err();
+            assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        }
+    }
+}
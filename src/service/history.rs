@@ -0,0 +1,223 @@
+use chrono::TimeDelta;
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::{ChangeRequest, LightService};
+use crate::actor::{Change, LightHandle};
+use crate::config::MAIN_BULB;
+use crate::error::LightError;
+use crate::i18n::Message;
+use crate::import::{ImportFormat, Skipped, merge, parse};
+use crate::model::PowerState;
+use crate::privacy::{PurgeMode, purge};
+use crate::state::{LightState, Transition, TransitionError};
+
+const LIGHTBULB_UNDONE_ON: Message = Message::new("undone-on", "Undid the last change: the lightbulb is on");
+const LIGHTBULB_UNDONE_OFF: Message = Message::new("undone-off", "Undid the last change: the lightbulb is off");
+const LIGHTBULB_REDONE_ON: Message = Message::new("redone-on", "Redid the last undone change: the lightbulb is on");
+const LIGHTBULB_REDONE_OFF: Message = Message::new("redone-off", "Redid the last undone change: the lightbulb is off");
+// What a confirmation token is issued for, so one given for erasing the log cannot be used to archive it
+const RESET_ERASING_LOG: &str = "a factory reset erasing the log";
+const RESET_ARCHIVING_LOG: &str = "a factory reset archiving the log";
+// Skipped entries listed by name in an import's reply; the rest are only counted
+const LISTED_SKIPS: usize = 5;
+// Ten years, well past any log this server keeps
+const MAX_PURGE_DAYS: u32 = 3650;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct FactoryResetRequest {
+    /// Move the log aside to a timestamped file beside it instead of erasing it (default false)
+    pub archive_log: Option<bool>,
+    /// The confirmation token returned by a first call without it; the reset only happens with one
+    pub confirm: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ImportLogRequest {
+    /// "text" (this server's own log), "json" (a lightbulb://log.json page, an array of entries or JSON lines) or
+    /// "csv" (with a header row naming timestamp, power and optionally by, reason and tags columns)
+    pub format: ImportFormat,
+    /// The log itself, up to 1 MiB
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct PurgeDataRequest {
+    /// Entries older than this many days lose their client names and reasons
+    #[schemars(range(min = 1, max = MAX_PURGE_DAYS))]
+    pub older_than_days: u32,
+    /// "anonymize" (the default) hashes them with the [log_privacy] salt, so one client's entries still go
+    /// together; "remove" leaves them out
+    pub mode: Option<PurgeMode>,
+}
+
+// Undo/redo, factory reset, log import and purge tools, gated behind the `history` feature
+#[tool_router(router = history_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Undo the most recent change to the lightbulb")]
+    pub(super) async fn undo_last_change(&self, Parameters(request): Parameters<ChangeRequest>) -> Result<String, ErrorData> {
+        if self.dry_run {
+            return self.dry_run_change(&request, Change::Undo).await;
+        }
+        let change = async {
+            let applied = self.change_handle(&request)?.undo().await?;
+            let message = if applied.outcome == PowerState::On { LIGHTBULB_UNDONE_ON } else { LIGHTBULB_UNDONE_OFF };
+            Ok(Self::with_sequence(&self.say(message).await, applied.sequence))
+        };
+        self.run_change(&request, "undo_last_change", change).await
+    }
+
+    #[tool(description = "Redo the most recently undone change to the lightbulb")]
+    pub(super) async fn redo_change(&self, Parameters(request): Parameters<ChangeRequest>) -> Result<String, ErrorData> {
+        if self.dry_run {
+            return self.dry_run_change(&request, Change::Redo).await;
+        }
+        let change = async {
+            let applied = self.change_handle(&request)?.redo().await?;
+            let message = if applied.outcome == PowerState::On { LIGHTBULB_REDONE_ON } else { LIGHTBULB_REDONE_OFF };
+            Ok(Self::with_sequence(&self.say(message).await, applied.sequence))
+        };
+        self.run_change(&request, "redo_change", change).await
+    }
+
+    #[tool(
+        description = "Return the lightbulb to factory condition: off, white at full brightness, with no undo history and an empty log (optionally archived first). The first call only describes the reset and returns a confirmation token; call again with `confirm` set to that token within two minutes to go ahead"
+    )]
+    pub(super) async fn factory_reset(&self, Parameters(request): Parameters<FactoryResetRequest>) -> Result<String, ErrorData> {
+        let archive = request.archive_log.unwrap_or(false);
+        let action = if archive { RESET_ARCHIVING_LOG } else { RESET_ERASING_LOG };
+        let token = request.confirm.as_deref().filter(|token| !token.trim().is_empty());
+        if self.dry_run {
+            return Ok(format!("{}: would {}", self.dry_run_prefix(), self.describe_factory_reset(archive).await?));
+        }
+        let Some(token) = token else {
+            let plan = self.describe_factory_reset(archive).await?;
+            let token = self.confirmations.issue(action);
+            let secs = self.confirmations.window().as_secs();
+            return Ok(format!("This will {}. To go ahead, call factory_reset again within {}s with confirm: \"{}\"", plan, secs, token));
+        };
+        self.confirmations.redeem(token, action)?;
+        // A running effect would put its own look back over the reset once it ends
+        self.effects.stop().await;
+        let reset = self.light.factory_reset(archive).await?;
+        #[cfg(feature = "motion")]
+        self.motion.cancel();
+        #[cfg(feature = "adaptive")]
+        self.adaptive.reset();
+        self.idempotency.clear();
+        self.confirmations.clear();
+        let log = match &reset.outcome.archived_to {
+            Some(archive) => format!("{} log entries archived to {}", reset.outcome.entries, archive),
+            None => format!("{} log entries erased", reset.outcome.entries),
+        };
+        Ok(Self::with_sequence(&format!("Factory reset done: the lightbulb is OFF with no history ({})", log), reset.sequence))
+    }
+
+    #[tool(
+        description = "Import power changes from another log, such as one exported from this or another server, a JSON export or a CSV file, merging them into the history in time order; entries that cannot be read, are already logged or are dated in the future are skipped and reported"
+    )]
+    pub(super) async fn import_log(&self, Parameters(request): Parameters<ImportLogRequest>) -> Result<String, ErrorData> {
+        let parsed = parse(request.format, &request.content)?;
+        let noun = if request.format == ImportFormat::Json { "entry" } else { "line" };
+        if self.dry_run {
+            let log = self.light.read_log().await?;
+            let merged = merge(&log, parsed.entries, parsed.skipped, self.light.clock().now(), str::to_string);
+            return Ok(format!("{}: would import {} log entries{}", self.dry_run_prefix(), merged.imported, describe_skips(&merged.skipped, noun)));
+        }
+        let merged = self.light.import_log(parsed).await?;
+        Ok(format!("Imported {} log entries{}", merged.imported, describe_skips(&merged.skipped, noun)))
+    }
+
+    #[tool(
+        description = "Anonymize or remove the client names and reasons of log entries older than a number of days, in every lightbulb's log. Times, actions, tags and entry numbers are kept, so statistics come out the same"
+    )]
+    pub(super) async fn purge_data(&self, Parameters(request): Parameters<PurgeDataRequest>) -> Result<String, ErrorData> {
+        let days = request.older_than_days;
+        if !(1..=MAX_PURGE_DAYS).contains(&days) {
+            return Err(LightError::InvalidParameter(format!("older_than_days must be between 1 and {}", MAX_PURGE_DAYS)).into());
+        }
+        let mode = request.mode.unwrap_or(PurgeMode::Anonymize);
+        if mode == PurgeMode::Anonymize && !self.privacy.keyed() {
+            return Err(LightError::InvalidParameter("anonymizing hashes with the [log_privacy] salt, which is not set; set one, or use mode \"remove\"".to_string()).into());
+        }
+        let before = self.light.clock().now() - TimeDelta::days(days.into());
+        let mut purged = Vec::new();
+        for (id, light) in self.purged_logs() {
+            let entries = match self.dry_run {
+                true => purge(&light.read_log().await?, before, mode, &self.privacy, str::to_string),
+                false => light.purge_log(before, mode, self.privacy.clone()).await?,
+            };
+            purged.push((id, entries.map_or(0, |purge| purge.entries)));
+        }
+        let total: usize = purged.iter().map(|(_, entries)| entries).sum();
+        if total == 0 {
+            return Ok(format!("No log entry older than {} days names a client or gives a reason", days));
+        }
+        let verb = match (mode, self.dry_run) {
+            (PurgeMode::Anonymize, false) => "Anonymized the clients and reasons of",
+            (PurgeMode::Remove, false) => "Removed the clients and reasons from",
+            (PurgeMode::Anonymize, true) => "would anonymize the clients and reasons of",
+            (PurgeMode::Remove, true) => "would remove the clients and reasons from",
+        };
+        let mut text = format!("{} {} log entries older than {} days", verb, total, days);
+        if purged.len() > 1 {
+            let each: Vec<String> = purged.iter().filter(|(_, entries)| *entries > 0).map(|(id, entries)| format!("{} {}", id, entries)).collect();
+            text.push_str(&format!(" ({})", each.join(", ")));
+        }
+        Ok(match self.dry_run {
+            true => format!("{}: {}", self.dry_run_prefix(), text),
+            false => text,
+        })
+    }
+}
+
+// "; skipped 2 (line 1: not a log entry; line 9: already in the log)", or nothing when none were
+fn describe_skips(skipped: &[Skipped], noun: &str) -> String {
+    if skipped.is_empty() {
+        return String::new();
+    }
+    let mut listed: Vec<String> = skipped.iter().take(LISTED_SKIPS).map(|skip| format!("{} {}: {}", noun, skip.position, skip.reason)).collect();
+    if skipped.len() > LISTED_SKIPS {
+        listed.push(format!("and {} more", skipped.len() - LISTED_SKIPS));
+    }
+    format!("; skipped {} ({})", skipped.len(), listed.join("; "))
+}
+
+impl LightService {
+    // The server's own log first, then each named bulb's
+    fn purged_logs(&self) -> Vec<(String, LightHandle)> {
+        #[allow(unused_mut)]
+        let mut logs = vec![(MAIN_BULB.to_string(), self.light.clone())];
+        #[cfg(feature = "bulbs")]
+        logs.extend(self.bulbs.all().into_iter().filter(|bulb| bulb.id != MAIN_BULB).map(|bulb| (bulb.id, bulb.light)));
+        logs
+    }
+
+    // What a factory reset would do, refusing up front when the bulb is locked so no token is handed out for a
+    // reset that cannot happen
+    async fn describe_factory_reset(&self, archive: bool) -> Result<String, LightError> {
+        if matches!(self.light.state().await?, LightState::Locked { .. }) {
+            return Err(TransitionError { state: "LOCKED", transition: Transition::Begin(PowerState::Off) }.into());
+        }
+        let entries = self.light.read_log().await?.lines().filter(|line| !line.trim().is_empty()).count();
+        let mut plan = vec![
+            "turn the lightbulb OFF".to_string(),
+            "set it to white at full brightness".to_string(),
+            "forget its undo history".to_string(),
+            format!("{} its {} log entries", if archive { "archive" } else { "erase" }, entries),
+        ];
+        if let Some(effect) = self.effects.active() {
+            plan.push(format!("stop the {} effect", effect));
+        }
+        #[cfg(feature = "motion")]
+        if let Some((room, _)) = self.motion.pending() {
+            plan.push(format!("cancel the motion countdown for {}", room));
+        }
+        #[cfg(feature = "adaptive")]
+        plan.push("return adaptive brightness to its startup settings".to_string());
+        let last = plan.pop().unwrap_or_default();
+        Ok(format!("{} and {}", plan.join(", "), last))
+    }
+}
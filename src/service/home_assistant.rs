@@ -0,0 +1,100 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use super::LightService;
+use crate::config::{MqttPublisherConfig, RestConfig};
+use crate::error::LightError;
+use crate::home_assistant::{DEFAULT_ENTITY_NAME, HomeAssistantFormat, discovery_payload, discovery_topic, mqtt_yaml, rest_yaml};
+use crate::reply;
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct ExportHomeAssistantRequest {
+    /// "yaml" (the default) for configuration.yaml entries, or "mqtt_discovery" for the discovery message
+    #[serde(default)]
+    pub format: HomeAssistantFormat,
+    /// Name of the light in Home Assistant (default "Lightbulb")
+    pub name: Option<String>,
+}
+
+// Home Assistant export, gated behind the `home-assistant` feature
+#[tool_router(router = home_assistant_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Export this lightbulb as Home Assistant configuration: configuration.yaml entries for an MQTT light, or a template light over the REST API, or the MQTT discovery message; for moving to Home Assistant or running both")]
+    pub(super) async fn export_home_assistant(&self, Parameters(request): Parameters<ExportHomeAssistantRequest>) -> Result<CallToolResult, ErrorData> {
+        let name = request.name.as_deref().map(str::trim).filter(|name| !name.is_empty()).unwrap_or(DEFAULT_ENTITY_NAME);
+        let mqtt = self.configured::<MqttPublisherConfig>("mqtt");
+        match request.format {
+            HomeAssistantFormat::Yaml => {
+                if let Some(mqtt) = mqtt {
+                    return Ok(reply::text(mqtt_yaml(&mqtt, name)));
+                }
+                match self.configured::<RestConfig>("rest") {
+                    Some(rest) => Ok(reply::text(rest_yaml(&rest.bind, name))),
+                    None => Err(LightError::InvalidParameter(
+                        "Home Assistant can only reach this lightbulb through an MQTT broker or the REST API; configure [mqtt] or [rest] first".to_string(),
+                    )
+                    .into()),
+                }
+            },
+            HomeAssistantFormat::MqttDiscovery => {
+                let Some(mqtt) = mqtt else {
+                    return Err(LightError::InvalidParameter("MQTT discovery needs an [mqtt] section in the configuration".to_string()).into());
+                };
+                let mut payload = discovery_payload(&mqtt);
+                payload["device"]["name"] = name.into();
+                let topic = discovery_topic(&mqtt);
+                let summary = match mqtt.discovery {
+                    true => format!("The server publishes this to {} itself at startup", topic),
+                    false => format!("Publish this retained to {}; the server does not, since discovery is off", topic),
+                };
+                reply::json(summary, &serde_json::json!({ "topic": topic, "payload": payload }))
+            },
+        }
+    }
+}
+
+impl LightService {
+    // A section of the configuration the service was deployed with, if it has one
+    fn configured<T: DeserializeOwned>(&self, section: &str) -> Option<T> {
+        let value = self.deployment.config.as_ref()?.get(section)?;
+        serde_json::from_value(value.clone()).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::logger::InMemoryLogger;
+
+    fn service(config: &str) -> LightService {
+        let mut service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+        service.deployment.config = Some(Config::parse(config).unwrap().redacted());
+        service
+    }
+
+    fn export(format: HomeAssistantFormat, name: Option<&str>) -> Parameters<ExportHomeAssistantRequest> {
+        Parameters(ExportHomeAssistantRequest { format, name: name.map(String::from) })
+    }
+
+    #[tokio::test]
+    async fn test_export_follows_the_configured_integrations() {
+        let rest = service("[rest]\nbind = \"127.0.0.1:8080\"");
+        let yaml = reply::text_of(&rest.export_home_assistant(export(HomeAssistantFormat::Yaml, Some("Desk Lamp"))).await.unwrap());
+        assert!(yaml.starts_with("rest_command:\n  desk_lamp_turn_on:\n"), "{}", yaml);
+        assert!(rest.export_home_assistant(export(HomeAssistantFormat::MqttDiscovery, None)).await.is_err());
+
+        let mqtt = service("[mqtt]\nhost = \"broker\"\npassword = \"secret\"\ndiscovery = false\n[rest]\nbind = \"127.0.0.1:8080\"");
+        let yaml = reply::text_of(&mqtt.export_home_assistant(export(HomeAssistantFormat::Yaml, None)).await.unwrap());
+        assert!(yaml.starts_with("mqtt:\n  light:\n    - name: \"Lightbulb\"\n"), "{}", yaml);
+        let result = mqtt.export_home_assistant(export(HomeAssistantFormat::MqttDiscovery, Some("Desk Lamp"))).await.unwrap();
+        assert!(reply::text_of(&result).starts_with("Publish this retained to homeassistant/light/lightbulb-mcp/config;"));
+        assert_eq!(reply::json_of(&result).unwrap()["payload"]["device"]["name"], "Desk Lamp");
+
+        let error = service("").export_home_assistant(export(HomeAssistantFormat::Yaml, None)).await.unwrap_err();
+        assert!(error.message.contains("configure [mqtt] or [rest] first"), "{}", error.message);
+    }
+}
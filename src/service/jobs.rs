@@ -0,0 +1,138 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::LightService;
+use crate::jobs::{JobInfo, JobStatus};
+use crate::i18n::Message;
+use crate::reply;
+
+const JOBS_SUMMARY: Message = Message::new("jobs-summary", "{ $total } jobs kept, { $running } of them running");
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct JobRequest {
+    /// The job's ID, as returned when it started, e.g. "job-3"
+    pub job_id: String,
+}
+
+// Background jobs for long operations, gated behind the `jobs` feature
+#[tool_router(router = job_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Report a background job's status, progress and, once it has finished, its result, as JSON")]
+    async fn get_job_status(&self, Parameters(request): Parameters<JobRequest>) -> Result<CallToolResult, ErrorData> {
+        let job = self.jobs.get(&request.job_id)?;
+        reply::json(describe_job(&job), &job)
+    }
+
+    #[tool(description = "Ask a running background job to stop; get_job_status reports it cancelled once it has")]
+    async fn cancel_job(&self, Parameters(request): Parameters<JobRequest>) -> Result<String, ErrorData> {
+        if self.dry_run {
+            let job = self.jobs.get(&request.job_id)?;
+            return Ok(format!("{}: would cancel {} ({})", self.dry_run_prefix(), job.id, job.description.to_lowercase()));
+        }
+        let job = self.jobs.cancel(&request.job_id)?;
+        Ok(format!("Cancelling {} ({}); get_job_status reports when it has stopped", job.id, job.description.to_lowercase()))
+    }
+
+    #[tool(description = "List the running background jobs and the most recently finished ones, oldest first, as JSON")]
+    async fn list_jobs(&self) -> Result<CallToolResult, ErrorData> {
+        let jobs = self.jobs.list();
+        let running = jobs.iter().filter(|job| job.status == JobStatus::Running).count();
+        reply::json(JOBS_SUMMARY.text(&[("total", &jobs.len()), ("running", &running)]), &jobs)
+    }
+}
+
+// E.g. "job-2 (macro of 3 steps) is running: Step 2 of 3: wait 60000ms (66%)"
+fn describe_job(job: &JobInfo) -> String {
+    let status = serde_json::to_value(job.status).ok().and_then(|status| status.as_str().map(String::from)).unwrap_or_default();
+    let mut text = format!("{} ({}) is {}", job.id, job.description.to_lowercase(), status);
+    match (&job.result, &job.message) {
+        (Some(result), _) => text.push_str(&format!(": {}", result)),
+        (None, Some(message)) => text.push_str(&format!(": {}", message)),
+        (None, None) => {},
+    }
+    if let (JobStatus::Running, Some(progress)) = (job.status, job.progress) {
+        text.push_str(&format!(" ({}%)", progress));
+    }
+    text
+}
+
+// These start their jobs through the simulation, macro and effect tools
+#[cfg(all(test, feature = "simulation", feature = "macros", feature = "effects"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::service::IdentifyRequest;
+    use crate::service::macros::MacroAction;
+    use crate::service::simulation::UpdateFirmwareRequest;
+
+    fn job(job_id: &str) -> Parameters<JobRequest> {
+        Parameters(JobRequest { job_id: job_id.to_string() })
+    }
+
+    async fn status(service: &LightService, job_id: &str) -> serde_json::Value {
+        reply::json_of(&service.get_job_status(job(job_id)).await.unwrap()).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_background_firmware_update_reports_progress() {
+        let service = LightService::new_with_in_memory_logger();
+        let request = UpdateFirmwareRequest { duration_secs: Some(10), background: Some(true), ..Default::default() };
+        let reply = service.start_firmware_job(request).unwrap();
+        assert_eq!(reply, "Started job-1: firmware update from 1.0.0 to 1.0.1 over 10s; get_job_status reports its progress");
+        let again = UpdateFirmwareRequest { background: Some(true), ..Default::default() };
+        assert_eq!(service.start_firmware_job(again).unwrap_err().data.unwrap()["code"], "FIRMWARE_UPDATE_IN_PROGRESS");
+
+        tokio::time::sleep(Duration::from_millis(6100)).await;
+        let summary = reply::text_of(&service.get_job_status(job("job-1")).await.unwrap());
+        assert!(summary.starts_with("job-1 (firmware update from 1.0.0 to 1.0.1 over 10s) is running: Flashing firmware 1.0.1 (60%)\n"), "{}", summary);
+        let running = status(&service, "job-1").await;
+        assert_eq!((&running["status"], &running["progress"], &running["message"]), (&"running".into(), &60.into(), &"Flashing firmware 1.0.1".into()));
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        let done = status(&service, "job-1").await;
+        assert_eq!((&done["status"], &done["progress"]), (&"completed".into(), &100.into()));
+        assert_eq!(done["result"], "Firmware updated from 1.0.0 to 1.0.1 in 10s");
+        assert_eq!(service.cancel_job(job("job-1")).await.unwrap_err().data.unwrap()["code"], "JOB_FINISHED");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancelled_macro_job_reports_where_it_stopped() {
+        let service = LightService::new_with_in_memory_logger();
+        let steps = vec![MacroAction::On, MacroAction::Wait { ms: 60_000 }, MacroAction::Off];
+        let reply = service.start_macro_job(steps).unwrap();
+        assert_eq!(reply, "Started job-1: macro of 3 steps; get_job_status reports its progress and result");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(status(&service, "job-1").await["message"], "Step 2 of 3: wait 60000ms");
+
+        let reply = service.cancel_job(job("job-1")).await.unwrap();
+        assert_eq!(reply, "Cancelling job-1 (macro of 3 steps); get_job_status reports when it has stopped");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let cancelled = status(&service, "job-1").await;
+        assert_eq!((&cancelled["status"], cancelled["cancel_requested"].as_bool()), (&"cancelled".into(), Some(true)));
+        assert!(cancelled["result"].as_str().unwrap().starts_with("Macro cancelled at step 2 of 3"));
+
+        let jobs = service.list_jobs().await.unwrap();
+        assert_eq!(reply::text_of(&jobs).lines().next(), Some("1 jobs kept, 0 of them running"));
+        assert_eq!(reply::json_of(&jobs).unwrap().as_array().unwrap().len(), 1);
+        let error = service.get_job_status(job("job-7")).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "UNKNOWN_JOB");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_effects_run_as_jobs() {
+        let service = LightService::new_with_in_memory_logger();
+        let reply = service.identify_bulb(Parameters(IdentifyRequest { bulb_id: None, flashes: Some(1) })).await.unwrap();
+        assert_eq!(reply, "Flashing the lightbulb 1 times to identify it (job-1)");
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        assert_eq!(status(&service, "job-1").await["result"], "Effect 'identify' ran to its end");
+
+        service.identify_bulb(Parameters(IdentifyRequest { bulb_id: None, flashes: Some(5) })).await.unwrap();
+        service.cancel_job(job("job-2")).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(service.effects.active().is_none());
+        let stopped = status(&service, "job-2").await;
+        assert_eq!((&stopped["status"], &stopped["result"]), (&"cancelled".into(), &"Effect 'identify' was stopped".into()));
+    }
+}
@@ -0,0 +1,315 @@
+use std::time::Duration;
+
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::service::RequestContext;
+use rmcp::{RoleServer, tool, tool_router};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::LightService;
+use super::power::SetBrightnessRequest;
+use crate::actor::Change;
+use crate::error::LightError;
+#[cfg(feature = "jobs")]
+use crate::jobs::JobStatus;
+use crate::model::PowerState;
+use crate::state::Transition;
+
+const MAX_MACRO_STEPS: usize = 50;
+const MAX_MACRO_WAIT_MS: u64 = 600_000;
+const MAX_MACRO_WAIT: Duration = Duration::from_millis(MAX_MACRO_WAIT_MS);
+
+// A single action in a macro, e.g. { "action": "wait", "ms": 500 }
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum MacroAction {
+    On,
+    Off,
+    Lock,
+    Unlock,
+    SetBrightness {
+        /// How bright the bulb should look, from 1 to 100 percent
+        #[schemars(range(min = 1, max = 100))]
+        percent: u8,
+    },
+    Wait {
+        /// How long to wait before the next step, in milliseconds
+        #[schemars(range(max = MAX_MACRO_WAIT_MS))]
+        ms: u64,
+    },
+}
+
+impl MacroAction {
+    fn describe(&self) -> String {
+        match self {
+            MacroAction::On => "on".to_string(),
+            MacroAction::Off => "off".to_string(),
+            MacroAction::Lock => "lock".to_string(),
+            MacroAction::Unlock => "unlock".to_string(),
+            MacroAction::SetBrightness { percent } => format!("set brightness {}%", percent),
+            MacroAction::Wait { ms } => format!("wait {}ms", ms),
+        }
+    }
+
+    fn change(&self) -> Option<Change> {
+        match self {
+            MacroAction::On => Some(Change::Power(PowerState::On)),
+            MacroAction::Off => Some(Change::Power(PowerState::Off)),
+            MacroAction::Lock => Some(Change::Apply(Transition::Lock)),
+            MacroAction::Unlock => Some(Change::Apply(Transition::Unlock)),
+            MacroAction::SetBrightness { percent } => Some(Change::Brightness(*percent)),
+            MacroAction::Wait { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct RunMacroRequest {
+    /// Actions to run in order (at most 50); the macro stops at the first failing step
+    #[schemars(length(min = 1, max = MAX_MACRO_STEPS))]
+    pub steps: Vec<MacroAction>,
+    /// Return a job ID at once and run the macro in the background, for get_job_status and cancel_job
+    #[cfg(feature = "jobs")]
+    pub background: Option<bool>,
+}
+
+// How a macro ended, which a background macro's job reports as its status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MacroOutcome {
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+// Multi-step macros, gated behind the `macros` feature
+#[tool_router(router = macro_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Run an ordered list of lightbulb actions (on, off, lock, unlock, set_brightness, wait) and report the result of each step")]
+    async fn run_macro(
+        &self,
+        Parameters(request): Parameters<RunMacroRequest>,
+        context: RequestContext<RoleServer>,
+    ) -> Result<String, ErrorData> {
+        #[cfg(feature = "jobs")]
+        if request.background == Some(true) && !self.dry_run {
+            return self.start_macro_job(request.steps);
+        }
+        self.execute_macro(request.steps, context.ct).await
+    }
+
+    async fn execute_macro(&self, steps: Vec<MacroAction>, cancel: CancellationToken) -> Result<String, ErrorData> {
+        Self::check_macro(&steps)?;
+        if self.dry_run {
+            return self.preview_macro(&steps).await;
+        }
+        Ok(self.run_macro_steps(&steps, cancel, mpsc::unbounded_channel().0).await.1)
+    }
+
+    #[cfg(feature = "jobs")]
+    pub(super) fn start_macro_job(&self, steps: Vec<MacroAction>) -> Result<String, ErrorData> {
+        Self::check_macro(&steps)?;
+        let service = self.clone();
+        let description = format!("Macro of {} steps", steps.len());
+        let id = self.jobs.start("macro", description.clone(), |progress, cancel| async move {
+            let (outcome, report) = service.run_macro_steps(&steps, cancel, progress).await;
+            let status = match outcome {
+                MacroOutcome::Completed => JobStatus::Completed,
+                MacroOutcome::Failed => JobStatus::Failed,
+                MacroOutcome::Cancelled => JobStatus::Cancelled,
+            };
+            (status, report)
+        })?;
+        Ok(format!("Started {}: {}; get_job_status reports its progress and result", id, description.to_lowercase()))
+    }
+
+    fn check_macro(steps: &[MacroAction]) -> Result<(), ErrorData> {
+        if steps.is_empty() || steps.len() > MAX_MACRO_STEPS {
+            return Err(LightError::InvalidParameter(format!("a macro needs 1 to {} steps", MAX_MACRO_STEPS)).into());
+        }
+        if let Some((index, percent)) = steps.iter().enumerate().find_map(|(index, step)| match step {
+            MacroAction::SetBrightness { percent } if !(1..=100).contains(percent) => Some((index, percent)),
+            _ => None,
+        }) {
+            return Err(LightError::InvalidParameter(format!("step {} must set the brightness between 1 and 100 percent, got {}", index + 1, percent)).into());
+        }
+        let total_wait = steps.iter().fold(0u64, |total, step| match step {
+            MacroAction::Wait { ms } => total.saturating_add(*ms),
+            _ => total,
+        });
+        if Duration::from_millis(total_wait) > MAX_MACRO_WAIT {
+            return Err(LightError::InvalidParameter(format!(
+                "a macro may wait at most {}s in total",
+                MAX_MACRO_WAIT.as_secs()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    // Steps are applied one at a time, so other clients' commands may land between them. Progress is reported as
+    // each step begins.
+    async fn run_macro_steps(
+        &self,
+        steps: &[MacroAction],
+        cancel: CancellationToken,
+        progress: mpsc::UnboundedSender<(u32, String)>,
+    ) -> (MacroOutcome, String) {
+        let mut results = Vec::new();
+        let (mut outcome, mut summary) = (MacroOutcome::Completed, "Macro completed".to_string());
+        for (index, step) in steps.iter().enumerate() {
+            let _ = progress.send(((index * 100 / steps.len()) as u32, format!("Step {} of {}: {}", index + 1, steps.len(), step.describe())));
+            let result = tokio::select! {
+                result = self.run_macro_step(step) => result,
+                _ = cancel.cancelled() => {
+                    (outcome, summary) = (MacroOutcome::Cancelled, format!("Macro cancelled at step {} of {}", index + 1, steps.len()));
+                    results.push(format!("{}. {}: cancelled", index + 1, step.describe()));
+                    break;
+                },
+            };
+            match result {
+                Ok(message) => results.push(format!("{}. {}: {}", index + 1, step.describe(), message)),
+                Err(error) => {
+                    let code = error.data.as_ref().and_then(|data| data["code"].as_str()).unwrap_or("UNKNOWN").to_string();
+                    (outcome, summary) = (MacroOutcome::Failed, format!("Macro failed at step {} of {}", index + 1, steps.len()));
+                    results.push(format!("{}. {}: failed ({}: {})", index + 1, step.describe(), code, error.message));
+                    break;
+                },
+            }
+        }
+        for (index, step) in steps.iter().enumerate().skip(results.len()) {
+            results.push(format!("{}. {}: skipped", index + 1, step.describe()));
+        }
+        if outcome == MacroOutcome::Completed {
+            let _ = progress.send((100, "Macro completed".to_string()));
+        }
+        (outcome, format!("{}:\n{}", summary, results.join("\n")))
+    }
+
+    // Works the steps through in one go, each seeing what the ones before it would have done, without waiting
+    async fn preview_macro(&self, steps: &[MacroAction]) -> Result<String, ErrorData> {
+        let changes = steps.iter().filter_map(MacroAction::change).collect();
+        let mut previews = self.light.preview(changes).await?.into_iter();
+        let mut results = Vec::new();
+        let mut outcome = "macro would complete".to_string();
+        for (index, step) in steps.iter().enumerate() {
+            let result = match step {
+                MacroAction::Wait { ms } => format!("would wait {}ms", ms),
+                _ => match previews.next() {
+                    Some(Ok(preview)) => Self::describe_preview(&preview).replacen("the lightbulb ", "", 1),
+                    Some(Err(error)) => {
+                        outcome = format!("macro would fail at step {} of {}", index + 1, steps.len());
+                        results.push(format!("{}. {}: would fail ({}: {})", index + 1, step.describe(), error.code(), error));
+                        break;
+                    },
+                    None => break,
+                },
+            };
+            results.push(format!("{}. {}: {}", index + 1, step.describe(), result));
+        }
+        for (index, step) in steps.iter().enumerate().skip(results.len()) {
+            results.push(format!("{}. {}: skipped", index + 1, step.describe()));
+        }
+        Ok(format!("{}, {}:\n{}", self.dry_run_prefix(), outcome, results.join("\n")))
+    }
+
+    async fn run_macro_step(&self, step: &MacroAction) -> Result<String, ErrorData> {
+        match step {
+            MacroAction::On => self.turn_on_lightbulb(Parameters(Default::default())).await,
+            MacroAction::Off => self.turn_off_lightbulb(Parameters(Default::default())).await,
+            MacroAction::Lock => self.lock_lightbulb(Parameters(Default::default())).await,
+            MacroAction::Unlock => self.unlock_lightbulb(Parameters(Default::default())).await,
+            MacroAction::SetBrightness { percent } => {
+                self.set_brightness(Parameters(SetBrightnessRequest { percent: *percent, change: Default::default() })).await
+            },
+            MacroAction::Wait { ms } => {
+                tokio::time::sleep(Duration::from_millis(*ms)).await;
+                Ok("done".to_string())
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_macro_reports_partial_failure() {
+        let service = LightService::new_with_in_memory_logger();
+        let steps = vec![MacroAction::On, MacroAction::Lock, MacroAction::Off, MacroAction::Unlock];
+        let report = service.execute_macro(steps, CancellationToken::new()).await.unwrap();
+        assert_eq!(
+            report,
+            "Macro failed at step 3 of 4:\n\
+            1. on: Lightbulb turned on successfully (sequence 1)\n\
+            2. lock: Lightbulb locked successfully (sequence 2)\n\
+            3. off: failed (BULB_LOCKED: Cannot turn off while the lightbulb is locked)\n\
+            4. unlock: skipped"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_macro_sets_the_brightness() {
+        let service = LightService::new_with_in_memory_logger();
+        let steps = vec![MacroAction::On, MacroAction::SetBrightness { percent: 40 }];
+        let report = service.execute_macro(steps, CancellationToken::new()).await.unwrap();
+        assert!(report.starts_with("Macro completed:\n1. on: Lightbulb turned on successfully (sequence 1)\n2. set brightness 40%: "), "{}", report);
+        assert_eq!(service.light.snapshot().await.unwrap().brightness, 40);
+
+        let error = service.execute_macro(vec![MacroAction::SetBrightness { percent: 0 }], CancellationToken::new()).await.unwrap_err();
+        assert!(error.message.contains("step 1 must set the brightness between 1 and 100 percent, got 0"), "{}", error.message);
+
+        let schema = serde_json::to_value(schemars::schema_for!(RunMacroRequest)).unwrap().to_string();
+        assert!(schema.contains(r#""maximum":100"#) && schema.contains(&format!(r#""maximum":{}"#, MAX_MACRO_WAIT_MS)), "{}", schema);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_macro_can_be_cancelled() {
+        let service = LightService::new_with_in_memory_logger();
+        let cancel = CancellationToken::new();
+        let stopper = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            stopper.cancel();
+        });
+
+        let steps = vec![MacroAction::On, MacroAction::Wait { ms: 5000 }, MacroAction::Off];
+        let report = service.execute_macro(steps, cancel).await.unwrap();
+        assert!(report.starts_with("Macro cancelled at step 2 of 3"));
+        assert!(report.ends_with("3. off: skipped"));
+        assert!(crate::reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap()).starts_with("The lightbulb is on for 0s (version 2,"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dry_run_macro_changes_nothing() {
+        let service = LightService::new_with_in_memory_logger().dry_run();
+        let steps = vec![MacroAction::On, MacroAction::Wait { ms: 500_000 }, MacroAction::Lock, MacroAction::Off, MacroAction::Unlock];
+        let report = service.execute_macro(steps, CancellationToken::new()).await.unwrap();
+        assert_eq!(
+            report,
+            "Dry run against the simulated backend, nothing was changed, macro would fail at step 4 of 5:\n\
+            1. on: would go from OFF to ON and the log would record \"ON\"\n\
+            2. wait 500000ms: would wait 500000ms\n\
+            3. lock: would go from ON to LOCKED\n\
+            4. off: would fail (BULB_LOCKED: Cannot turn off while the lightbulb is locked)\n\
+            5. unlock: skipped"
+        );
+        assert_eq!(service.light.state().await.unwrap().power(), Some(PowerState::Off));
+    }
+
+    #[tokio::test]
+    async fn test_macro_validates_steps() {
+        let service = LightService::new_with_in_memory_logger();
+        let error = service.execute_macro(Vec::new(), CancellationToken::new()).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let error = service.execute_macro(vec![MacroAction::Wait { ms: 601_000 }], CancellationToken::new()).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let waits = vec![MacroAction::Wait { ms: u64::MAX }, MacroAction::Wait { ms: u64::MAX }];
+        let error = service.execute_macro(waits, CancellationToken::new()).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+    }
+}
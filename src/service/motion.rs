@@ -0,0 +1,64 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::LightService;
+use crate::motion::MotionOutcome;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ReportMotionRequest {
+    /// Room the motion was seen in, as configured; may be left out when only one room is configured
+    pub room: Option<String>,
+}
+
+// Motion-triggered lighting, gated behind the `motion` feature
+#[tool_router(router = motion_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Report motion in a room: turns the lightbulb on, and off again once the room's timeout passes without more motion")]
+    pub(super) async fn report_motion(&self, Parameters(request): Parameters<ReportMotionRequest>) -> Result<String, ErrorData> {
+        let room = self.motion.room(request.room.as_deref())?;
+        let secs = room.off_after_secs;
+        if self.dry_run {
+            let text = match self.motion.preview(&self.light, room).await? {
+                MotionOutcome::TurnedOn { .. } => format!("motion in {} would turn the lightbulb ON until {}s pass without motion", room.room, secs),
+                MotionOutcome::Extended { .. } => format!("motion in {} would keep the lightbulb on for another {}s", room.room, secs),
+                MotionOutcome::AlreadyOn => format!("motion in {} would leave the lightbulb alone, since motion did not turn it on", room.room),
+            };
+            return Ok(format!("{}: {}", self.dry_run_prefix(), text));
+        }
+        Ok(match self.motion.report(&self.light, room).await? {
+            MotionOutcome::TurnedOn { sequence, .. } => Self::with_sequence(
+                &format!("Motion in {} turned the lightbulb ON; it turns off after {}s without motion", room.room, secs),
+                sequence,
+            ),
+            MotionOutcome::Extended { .. } => {
+                let (room, left) = self.motion.pending().unwrap_or((room.room.clone(), room.off_after()));
+                format!("Motion seen; the lightbulb now turns off after {}s without motion in {}", left.as_secs(), room)
+            },
+            MotionOutcome::AlreadyOn => {
+                format!("Motion in {} seen, but motion did not turn the lightbulb on, so it is left for whoever did", room.room)
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MotionConfig;
+    use crate::logger::InMemoryLogger;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_report_motion() {
+        let hallway = MotionConfig { room: "hallway".to_string(), off_after_secs: 120, topic: None };
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).motion_rooms(vec![hallway]).build();
+        let report = |room: Option<&str>| service.report_motion(Parameters(ReportMotionRequest { room: room.map(String::from) }));
+
+        let dry_run = service.dry_run().report_motion(Parameters(ReportMotionRequest { room: None })).await.unwrap();
+        assert!(dry_run.ends_with(": motion in hallway would turn the lightbulb ON until 120s pass without motion"), "{}", dry_run);
+        assert_eq!(report(None).await.unwrap(), "Motion in hallway turned the lightbulb ON; it turns off after 120s without motion (sequence 1)");
+        assert_eq!(report(Some("hallway")).await.unwrap(), "Motion seen; the lightbulb now turns off after 120s without motion in hallway");
+        assert!(report(Some("attic")).await.is_err());
+    }
+}
@@ -0,0 +1,870 @@
+use std::fmt::Display;
+
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{ChangeRequest, LightService, ResponseDetail};
+use crate::brightness::MAX_OUTPUT;
+use crate::clock::format_duration;
+use crate::actor::{BackendReading, Change, ColorChange, LightHandle, PowerChange};
+use crate::offline::PendingChange;
+use crate::color::{
+    Hsv, MAX_KELVIN, MAX_MIREDS, MIN_KELVIN, MIN_MIREDS, Xy, hsv_to_rgb, kelvin_to_mireds, mireds_to_kelvin, mireds_to_rgb, rgb_to_hsv, rgb_to_xy,
+    xy_to_rgb,
+};
+use crate::error::LightError;
+use crate::i18n::Message;
+use crate::model::PowerState;
+use crate::palette::{complete_color, parse_hex, resolve_color};
+use crate::reply;
+use crate::state::{LightState, StateSnapshot, Transition};
+
+const LIGHTBULB_ON_STATUS: Message = Message::new("status-on", "The lightbulb is on");
+const LIGHTBULB_OFF_STATUS: Message = Message::new("status-off", "The lightbulb is off");
+const LIGHTBULB_TRANSITIONING_STATUS: Message = Message::new("status-transitioning", "The lightbulb is changing state");
+const LIGHTBULB_UNREACHABLE_STATUS: Message = Message::new("status-unreachable", "The lightbulb is unreachable");
+const LIGHTBULB_LOCKED_STATUS: Message = Message::new("status-locked", "{ $status } (locked)");
+const LIGHTBULB_LOCKED: Message = Message::new("locked", "Lightbulb locked successfully");
+const LIGHTBULB_UNLOCKED: Message = Message::new("unlocked", "Lightbulb unlocked successfully");
+const LIGHTBULB_ALREADY_ON: Message = Message::new("already-on", "The lightbulb is already on");
+const LIGHTBULB_ALREADY_OFF: Message = Message::new("already-off", "The lightbulb is already off");
+const LIGHTBULB_TURNED_ON: Message = Message::new("turned-on", "Lightbulb turned on successfully");
+const LIGHTBULB_TURNED_OFF: Message = Message::new("turned-off", "Lightbulb turned off successfully");
+const STATE_SUMMARY: Message = Message::new("state-summary", "The lightbulb is { $state } (version { $version })");
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct StatusRequest {
+    /// Ask the device for its power now instead of trusting its last reading, which is kept for a few seconds
+    #[serde(default)]
+    pub refresh: bool,
+    /// Which lightbulb, by the id, name or alias list_lightbulbs gives it (default: the server's own, "main")
+    pub bulb_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct PowerRequest {
+    /// Which lightbulb to switch, by the id, name or alias list_lightbulbs gives it (default: the server's own, "main")
+    pub bulb_id: Option<String>,
+    #[serde(flatten)]
+    pub change: ChangeRequest,
+}
+
+impl From<ChangeRequest> for PowerRequest {
+    fn from(change: ChangeRequest) -> Self {
+        Self { bulb_id: None, change }
+    }
+}
+
+// The state get_lightbulb_state reports, with how long the server has been up and when the device last answered,
+// so clients can tell how stale the state may be
+#[derive(Debug, Serialize)]
+struct StateReport {
+    #[serde(flatten)]
+    snapshot: StateSnapshot,
+    uptime_secs: i64,
+    backend_contacted_at: Option<DateTime<Utc>>,
+    backend_contacted_secs_ago: Option<i64>,
+    // Only with a brightness schedule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness_cap: Option<u8>,
+}
+
+// What get_lightbulb_status answers with beside its sentence: the bulb's full state, and what the device said of
+// its power when it was asked
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    bulb_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(flatten)]
+    snapshot: StateSnapshot,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness_cap: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_power: Option<PowerState>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device_checked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ResponseDetailRequest {
+    /// "brief", "normal" (the default) or "detailed"
+    pub detail: ResponseDetail,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SetColorRequest {
+    /// An sRGB color as a #rrggbb hex code
+    #[schemars(pattern(r"^#[0-9A-Fa-f]{6}$"))]
+    pub rgb: Option<String>,
+    /// A color as hue, saturation and value
+    pub hsv: Option<Hsv>,
+    /// A CIE 1931 xy chromaticity, as Hue and Matter bulbs take colors; sets the brightest color of that tint
+    pub xy: Option<Xy>,
+    #[serde(flatten)]
+    pub change: ChangeRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SetColorTemperatureRequest {
+    /// White temperature in kelvin, between 1000 (candlelight) and 40000 (blue sky)
+    #[schemars(range(min = MIN_KELVIN, max = MAX_KELVIN))]
+    pub kelvin: Option<u32>,
+    /// White temperature in mireds (a million over kelvin), between 25 and 1000, as Zigbee, Matter and Hue take it
+    #[schemars(range(min = MIN_MIREDS, max = MAX_MIREDS))]
+    pub mireds: Option<u32>,
+    #[serde(flatten)]
+    pub change: ChangeRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SetBrightnessRequest {
+    /// How bright the bulb should look, from 1 to 100 percent; the level sent to the bulb is gamma corrected
+    #[schemars(range(min = 1, max = 100))]
+    pub percent: u8,
+    #[serde(flatten)]
+    pub change: ChangeRequest,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SetColorByNameRequest {
+    /// A CSS/X11 color name ("skyblue"), a lighting description ("warm white") or a #rrggbb hex code
+    pub name: String,
+    #[serde(flatten)]
+    pub change: ChangeRequest,
+}
+
+// Core on/off tools, gated behind the `core` feature
+#[tool_router(router = power_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Get the current status of the lightbulb, how long it has been in it, its state version and what changed it last, and whether the device itself disagrees, with its power, brightness, color and lock as JSON")]
+    pub(super) async fn get_lightbulb_status(&self, Parameters(request): Parameters<StatusRequest>) -> Result<CallToolResult, ErrorData> {
+        let light = self.bulb_handle(request.bulb_id.as_deref())?;
+        let status = light.status().await?;
+        let snapshot = light.snapshot().await?;
+        let mut text = Self::describe_state(&status.state, &snapshot);
+        if let Some(secs) = status.in_state_secs {
+            text.push_str(&format!(" for {}", format_duration(secs)));
+        }
+        text.push_str(&format!(" (version {}", status.version));
+        if let Some(last_changed) = status.last_changed {
+            let cause = status.cause.as_deref().unwrap_or("change");
+            let by = status.changed_by.map(|who| format!(" by {}", who)).unwrap_or_default();
+            let ago = format_duration((light.clock().now() - last_changed).num_seconds().max(0));
+            text.push_str(&format!(", last change: {}{} {} ago", cause, by, ago));
+        }
+        text.push(')');
+        let brightness_cap = light.brightness_cap();
+        if let Some(cap) = brightness_cap.filter(|cap| *cap < 100) {
+            text.push_str(&format!("; brightness is capped at {}% at this time of day", cap));
+        }
+        let (bulb_id, name) = self.bulb_names(request.bulb_id.as_deref());
+        let mut report = StatusReport { bulb_id, name, snapshot, brightness_cap, device_power: None, device_error: None, device_checked_at: None };
+        // A bulb mid-change is not compared with the device, so the read never waits behind the change
+        if let Some(expected) = status.state.power().filter(|_| !matches!(status.state, LightState::Transitioning { .. })) {
+            let reading = light.backend_power(request.refresh).await?;
+            if let Some(description) = self.describe_reading(&light, expected, &reading, request.refresh) {
+                text.push_str(&format!("; {}", description));
+            }
+            report.device_checked_at = Some(reading.at);
+            match reading.power {
+                Ok(power) => report.device_power = Some(power),
+                Err(e) => report.device_error = Some(e),
+            }
+        } else if status.state == LightState::Unreachable {
+            text.push_str(&format!("; the device {}", self.describe_contact(&light)));
+        }
+        reply::json(text, &report)
+    }
+
+    #[tool(description = "Get the complete lightbulb state (power, brightness, color, effect, timers, lock, last change, time in state, version, who or what changed it) as JSON")]
+    pub(super) async fn get_lightbulb_state(&self) -> Result<CallToolResult, ErrorData> {
+        let mut snapshot = self.light.snapshot().await?;
+        snapshot.active_effect = self.effects.active();
+        #[cfg(feature = "schedules")]
+        {
+            snapshot.timers = self.automation.timers.all().iter().map(|timer| timer.describe()).collect();
+        }
+        let summary = STATE_SUMMARY.text(&[("state", &snapshot.state), ("version", &snapshot.version)]);
+        let (color, now) = (snapshot.color, self.light.clock().now());
+        let contacted_at = self.light.last_contact();
+        let report = StateReport {
+            snapshot,
+            uptime_secs: self.uptime_secs(),
+            backend_contacted_at: contacted_at,
+            backend_contacted_secs_ago: contacted_at.map(|at| (now - at).num_seconds().max(0)),
+            brightness_cap: self.light.brightness_cap(),
+        };
+        let mut result = reply::json(summary, &report)?;
+        if self.light.metadata().supports_color {
+            result.content.get_or_insert_default().push(reply::swatch(color));
+        }
+        Ok(result)
+    }
+
+    #[tool(description = "List the changes waiting for an unreachable lightbulb, which are applied in order once it answers again; at most one each for power, color and brightness")]
+    pub(super) async fn get_offline_queue(&self) -> Result<CallToolResult, ErrorData> {
+        let queued = self.light.queued_changes();
+        let summary = match queued.len() {
+            0 if !self.light.queues_offline() => "Nothing is queued; changes that cannot reach the lightbulb fail at once, since the offline queue is off".to_string(),
+            0 => "Nothing is queued".to_string(),
+            1 => format!("1 change is queued for the unreachable lightbulb: {}", queued[0].change.describe()),
+            count => format!(
+                "{} changes are queued for the unreachable lightbulb: {}",
+                count,
+                queued.iter().map(|queued| queued.change.describe()).collect::<Vec<_>>().join(", then ")
+            ),
+        };
+        let changes: Vec<serde_json::Value> = queued.iter().map(|queued| queued.to_json()).collect();
+        reply::json(summary, &changes)
+    }
+
+    #[tool(description = "Turn on the lightbulb")]
+    pub(super) async fn turn_on_lightbulb(&self, Parameters(request): Parameters<PowerRequest>) -> Result<String, ErrorData> {
+        let light = self.bulb_handle(request.bulb_id.as_deref())?;
+        let change = self.change_lightbulb_state(&light, PowerState::On, &request.change, LIGHTBULB_ALREADY_ON, LIGHTBULB_TURNED_ON);
+        if self.dry_run {
+            return self.dry_run_change_on(&light, &request.change, Change::Power(PowerState::On)).await;
+        }
+        self.run_change_on(&light, &request.change, "turn_on_lightbulb", change).await
+    }
+
+    #[tool(description = "Turn off the lightbulb")]
+    pub(super) async fn turn_off_lightbulb(&self, Parameters(request): Parameters<PowerRequest>) -> Result<String, ErrorData> {
+        let light = self.bulb_handle(request.bulb_id.as_deref())?;
+        let change = self.change_lightbulb_state(&light, PowerState::Off, &request.change, LIGHTBULB_ALREADY_OFF, LIGHTBULB_TURNED_OFF);
+        if self.dry_run {
+            return self.dry_run_change_on(&light, &request.change, Change::Power(PowerState::Off)).await;
+        }
+        self.run_change_on(&light, &request.change, "turn_off_lightbulb", change).await
+    }
+
+    #[tool(description = "Lock the lightbulb in its current state so it cannot be turned on or off")]
+    pub(super) async fn lock_lightbulb(&self, Parameters(request): Parameters<ChangeRequest>) -> Result<String, ErrorData> {
+        let change = self.apply_transition(Transition::Lock, &request, LIGHTBULB_LOCKED);
+        if self.dry_run {
+            return self.dry_run_change(&request, Change::Apply(Transition::Lock)).await;
+        }
+        self.run_change(&request, "lock_lightbulb", change).await
+    }
+
+    #[tool(description = "Unlock the lightbulb so it can be turned on or off again")]
+    pub(super) async fn unlock_lightbulb(&self, Parameters(request): Parameters<ChangeRequest>) -> Result<String, ErrorData> {
+        let change = self.apply_transition(Transition::Unlock, &request, LIGHTBULB_UNLOCKED);
+        if self.dry_run {
+            return self.dry_run_change(&request, Change::Apply(Transition::Unlock)).await;
+        }
+        self.run_change(&request, "unlock_lightbulb", change).await
+    }
+
+    #[tool(description = "Choose how much change confirmations say for the rest of this session: brief (the resulting state and version), normal, or detailed (adding the state before, who made the change and when)")]
+    pub(super) async fn set_response_detail(&self, Parameters(request): Parameters<ResponseDetailRequest>) -> Result<String, ErrorData> {
+        *self.response_detail.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = request.detail;
+        let detail = match request.detail {
+            ResponseDetail::Brief => "brief",
+            ResponseDetail::Normal => "normal",
+            ResponseDetail::Detailed => "detailed",
+        };
+        Ok(format!("Change confirmations are {} for the rest of this session; a change call's own detail still wins", detail))
+    }
+
+    #[tool(description = "Set the lightbulb color by name, e.g. \"sky blue\" or \"warm amber\"; see the lightbulb://colors resource for the palette")]
+    pub(super) async fn set_color_by_name(&self, Parameters(request): Parameters<SetColorByNameRequest>) -> Result<CallToolResult, ErrorData> {
+        let Some((name, color)) = resolve_color(&request.name) else {
+            let prefix: String = request.name.trim().chars().take(3).collect();
+            let suggestions = complete_color(&prefix);
+            let hint = if suggestions.is_empty() || prefix.is_empty() {
+                "see lightbulb://colors for the palette".to_string()
+            } else {
+                format!("did you mean one of: {}", suggestions.into_iter().take(5).collect::<Vec<_>>().join(", "))
+            };
+            return Err(LightError::InvalidParameter(format!("Unknown color '{}'; {}", request.name, hint)).into());
+        };
+        if self.dry_run {
+            return self.dry_run_change(&request.change, Change::Color(color)).await.map(reply::text);
+        }
+        let change = async {
+            let applied = self.change_handle(&request.change)?.set_color(color).await?;
+            if applied.outcome.queued {
+                return Ok(Self::queued(&PendingChange::Color(applied.outcome.applied).describe(), applied.sequence));
+            }
+            let message = if name == color.to_string() {
+                format!("Lightbulb color set to {}", color)
+            } else {
+                format!("Lightbulb color set to {} ({})", name, color)
+            };
+            Ok(Self::with_sequence(&Self::describe_clamping(message, &applied.outcome), applied.sequence))
+        };
+        let text = self.run_change(&request.change, "set_color_by_name", change).await?;
+        Ok(self.color_reply(text).await)
+    }
+
+    #[tool(description = "Set the lightbulb color as exactly one of rgb (#rrggbb), hsv or CIE xy; colors outside the bulb's gamut are moved to the nearest it can show, and the color applied is reported back")]
+    pub(super) async fn set_color(&self, Parameters(request): Parameters<SetColorRequest>) -> Result<CallToolResult, ErrorData> {
+        let color = match (&request.rgb, request.hsv, request.xy) {
+            (Some(rgb), None, None) => {
+                parse_hex(rgb.trim()).ok_or_else(|| LightError::InvalidParameter(format!("rgb must be a #rrggbb hex code, got '{}'", rgb)))?
+            },
+            (None, Some(hsv), None) => {
+                hsv.validate().map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+                hsv_to_rgb(hsv)
+            },
+            (None, None, Some(xy)) => {
+                xy.validate().map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+                xy_to_rgb(xy)
+            },
+            _ => return Err(LightError::InvalidParameter("give exactly one of rgb, hsv or xy".to_string()).into()),
+        };
+        if self.dry_run {
+            return self.dry_run_change(&request.change, Change::Color(color)).await.map(reply::text);
+        }
+        let change = async {
+            let applied = self.change_handle(&request.change)?.set_color(color).await?;
+            if applied.outcome.queued {
+                return Ok(Self::queued(&PendingChange::Color(applied.outcome.applied).describe(), applied.sequence));
+            }
+            let color = applied.outcome.applied;
+            let message = format!("Lightbulb color set to {} (xy {}; hsv {})", color, rgb_to_xy(color), rgb_to_hsv(color));
+            Ok(Self::with_sequence(&Self::describe_clamping(message, &applied.outcome), applied.sequence))
+        };
+        let text = self.run_change(&request.change, "set_color", change).await?;
+        Ok(self.color_reply(text).await)
+    }
+
+    #[tool(description = "Set the lightbulb to a white color temperature, in kelvin or in mireds; temperatures outside the bulb's range are moved to the nearest it can show")]
+    pub(super) async fn set_color_temperature(&self, Parameters(request): Parameters<SetColorTemperatureRequest>) -> Result<CallToolResult, ErrorData> {
+        let requested = match (request.kelvin, request.mireds) {
+            (Some(kelvin), None) if (MIN_KELVIN..=MAX_KELVIN).contains(&kelvin) => kelvin_to_mireds(kelvin),
+            (None, Some(mireds)) if (MIN_MIREDS..=MAX_MIREDS).contains(&mireds) => mireds,
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(LightError::InvalidParameter(format!(
+                    "a color temperature must be {}K to {}K, or {} to {} mireds",
+                    MIN_KELVIN, MAX_KELVIN, MIN_MIREDS, MAX_MIREDS
+                ))
+                .into());
+            },
+            _ => return Err(LightError::InvalidParameter("give exactly one of kelvin or mireds".to_string()).into()),
+        };
+        let range = self.light.metadata().mired_range;
+        let mireds = range.map_or(requested, |range| range.clamp(requested));
+        let color = mireds_to_rgb(mireds);
+        if self.dry_run {
+            return self.dry_run_change(&request.change, Change::Color(color)).await.map(reply::text);
+        }
+        let change = async {
+            let applied = self.change_handle(&request.change)?.set_color(color).await?;
+            if applied.outcome.queued {
+                return Ok(Self::queued(&PendingChange::Color(applied.outcome.applied).describe(), applied.sequence));
+            }
+            let kelvin = request.kelvin.filter(|_| mireds == requested).unwrap_or_else(|| mireds_to_kelvin(mireds));
+            let mut message = format!("Lightbulb color temperature set to {}K ({} mireds, {})", kelvin, mireds, applied.outcome.applied);
+            if let Some(range) = range.filter(|_| mireds != requested) {
+                message.push_str(&format!("; {} mireds is outside the bulb's range of {}, so the nearest it can show was used", requested, range));
+            }
+            Ok(Self::with_sequence(&Self::describe_clamping(message, &applied.outcome), applied.sequence))
+        };
+        let text = self.run_change(&request.change, "set_color_temperature", change).await?;
+        Ok(self.color_reply(text).await)
+    }
+
+    #[tool(description = "Set how bright the lightbulb looks, from 1 to 100 percent; see lightbulb://bulb for the gamma correction applied")]
+    pub(super) async fn set_brightness(&self, Parameters(request): Parameters<SetBrightnessRequest>) -> Result<String, ErrorData> {
+        if !(1..=100).contains(&request.percent) {
+            return Err(LightError::InvalidParameter(format!("percent must be between 1 and 100, got {}; turn the bulb off instead of setting 0", request.percent)).into());
+        }
+        if self.dry_run {
+            return self.dry_run_change(&request.change, Change::Brightness(request.percent)).await;
+        }
+        let change = async {
+            let applied = self.change_handle(&request.change)?.set_brightness(request.percent).await?;
+            if applied.outcome.queued {
+                return Ok(Self::queued(&PendingChange::Brightness { percent: applied.outcome.percent, level: applied.outcome.level }.describe(), applied.sequence));
+            }
+            let gamma = self.light.metadata().brightness.gamma;
+            let mut message = format!("Lightbulb brightness set to {}% (level {} of {} after gamma {})", applied.outcome.percent, applied.outcome.level, MAX_OUTPUT, gamma);
+            if let Some(cap) = self.light.brightness_cap().filter(|cap| *cap < applied.outcome.percent) {
+                message.push_str(&format!(", shown at {}% as the time of day caps it", cap));
+            }
+            Ok(Self::with_sequence(&message, applied.sequence))
+        };
+        self.run_change(&request.change, "set_brightness", change).await
+    }
+
+    // A color change's answer with a swatch of the color the bulb now shows, or the text alone if the bulb
+    // cannot be read back
+    async fn color_reply(&self, text: String) -> CallToolResult {
+        match self.light.snapshot().await {
+            Ok(snapshot) => reply::with_swatch(text, snapshot.color),
+            Err(_) => reply::text(text),
+        }
+    }
+
+    // Says what was asked for when the bulb could not show it
+    fn describe_clamping(mut message: String, change: &ColorChange) -> String {
+        if change.applied != change.requested {
+            message.push_str(&format!("; {} is outside the bulb's gamut, so the nearest color it can show was used", change.requested));
+        }
+        message
+    }
+
+    async fn apply_transition(&self, transition: Transition, request: &ChangeRequest, message: Message) -> Result<String, ErrorData> {
+        let light = self.change_handle(request)?;
+        let applied = light.apply(transition).await?;
+        Ok(Self::with_sequence(&self.say(message).await, applied.sequence))
+    }
+
+    async fn change_lightbulb_state(
+        &self,
+        light: &LightHandle,
+        target_state: PowerState,
+        request: &ChangeRequest,
+        already_message: Message,
+        success_message: Message,
+    ) -> Result<String, ErrorData> {
+        let light = self.change_handle_on(light, request)?;
+        let applied = light.set_power(target_state).await?;
+        let message = match applied.outcome {
+            PowerChange::AlreadyInState => already_message,
+            PowerChange::Changed => success_message,
+            PowerChange::Queued => return Ok(Self::queued(&PendingChange::Power(target_state).describe(), applied.sequence)),
+        };
+        Ok(Self::with_sequence(&self.say(message).await, applied.sequence))
+    }
+
+    // What the device said, when it disagrees, could not answer or was asked explicitly
+    fn describe_reading(&self, light: &LightHandle, expected: PowerState, reading: &BackendReading, refresh: bool) -> Option<String> {
+        let report = match &reading.power {
+            Ok(power) if *power == expected && !refresh => return None,
+            Ok(power) => format!("the device reports it {}", power.log_action().to_lowercase()),
+            Err(e) => format!("the device did not answer ({})", e),
+        };
+        let age = format_duration((light.clock().now() - reading.at).num_seconds().max(0));
+        match reading.power {
+            Ok(_) => Some(format!("{}, checked {} ago", report, age)),
+            Err(_) => Some(format!("{}, checked {} ago; it {}", report, age, self.describe_contact(light))),
+        }
+    }
+
+    // The service started when the resources that only change with a restart did
+    fn uptime_secs(&self) -> i64 {
+        (self.light.clock().now() - self.resources_since).num_seconds().max(0)
+    }
+
+    // When the device last answered, so a state it has not confirmed for a while reads as possibly stale
+    fn describe_contact(&self, light: &LightHandle) -> String {
+        match light.last_contact() {
+            Some(at) => format!("last answered {} ago", format_duration((light.clock().now() - at).num_seconds().max(0))),
+            None => format!("has not answered since the server started {} ago", format_duration(self.uptime_secs())),
+        }
+    }
+
+    fn describe_state(state: &LightState, snapshot: &StateSnapshot) -> String {
+        let name = snapshot.state.to_lowercase();
+        let arguments: [(&str, &dyn Display); 2] = [("state", &name), ("brightness", &snapshot.brightness)];
+        match state {
+            LightState::Off => LIGHTBULB_OFF_STATUS.text(&arguments),
+            LightState::On { .. } => LIGHTBULB_ON_STATUS.text(&arguments),
+            LightState::Transitioning { .. } => LIGHTBULB_TRANSITIONING_STATUS.text(&arguments),
+            LightState::Locked { inner } => {
+                let status = Self::describe_state(inner, snapshot);
+                LIGHTBULB_LOCKED_STATUS.text(&[arguments[0], arguments[1], ("status", &status)])
+            },
+            LightState::Unreachable => LIGHTBULB_UNREACHABLE_STATUS.text(&arguments),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use chrono::{DateTime, TimeDelta, Utc};
+
+    use super::*;
+    use crate::backend::{LightBackend, SimulatedBackend};
+    use crate::brightness::{BrightnessCurve, BrightnessSchedule};
+    use crate::clock::ManualClock;
+    use crate::color::{GAMUT_B, MiredRange};
+    use crate::logger::InMemoryLogger;
+    use crate::model::Color;
+    use crate::service::tests::UnreachableBackend;
+
+    #[tokio::test]
+    async fn test_initial_lightbulb_state() {
+        let service = LightService::new_with_in_memory_logger();
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert_eq!(status, "The lightbulb is off (version 0)");
+    }
+
+    #[tokio::test]
+    async fn test_turn_on_lightbulb() {
+        let service = LightService::new_with_in_memory_logger();
+        let result = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "Lightbulb turned on successfully (sequence 1)");
+
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.starts_with("The lightbulb is on for 0s (version 2, last change: turn_on "), "{}", status);
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_time_in_state() {
+        let clock = ManualClock::new(Utc::now());
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        clock.advance(TimeDelta::seconds(312));
+        let _ = service.lock_lightbulb(Parameters(Default::default())).await;
+        clock.advance(TimeDelta::seconds(40));
+
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert_eq!(status, "The lightbulb is on (locked) for 5m 52s (version 3, last change: lock 40s ago)");
+    }
+
+    #[tokio::test]
+    async fn test_turn_off_lightbulb() {
+        let service = LightService::new_with_in_memory_logger();
+        // First turn it on
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+
+        let result = service.turn_off_lightbulb(Parameters(Default::default())).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "Lightbulb turned off successfully (sequence 2)");
+
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.starts_with("The lightbulb is off for 0s (version 4, last change: turn_off "), "{}", status);
+    }
+
+    #[tokio::test]
+    async fn test_turn_on_already_on() {
+        let service = LightService::new_with_in_memory_logger();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+
+        let result = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "The lightbulb is already on (sequence 2)");
+    }
+
+    #[tokio::test]
+    async fn test_confirmations_follow_the_call_then_the_session_detail() {
+        let clock = ManualClock::new("2026-03-01T20:00:00Z".parse().unwrap());
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build();
+        let brief = ChangeRequest { detail: Some(ResponseDetail::Brief), ..Default::default() };
+        assert_eq!(service.turn_on_lightbulb(Parameters(brief.into())).await.unwrap(), "ON, version 2");
+
+        let reply = service.set_response_detail(Parameters(ResponseDetailRequest { detail: ResponseDetail::Detailed })).await.unwrap();
+        assert!(reply.starts_with("Change confirmations are detailed"), "{}", reply);
+        let reply = service.turn_off_lightbulb(Parameters(Default::default())).await.unwrap();
+        assert_eq!(
+            reply,
+            "Lightbulb turned off successfully (sequence 2); was ON (version 2), now OFF (version 4), changed by an unnamed caller at 2026-03-01T20:00:00Z"
+        );
+        let normal = ChangeRequest { detail: Some(ResponseDetail::Normal), ..Default::default() };
+        assert_eq!(service.lock_lightbulb(Parameters(normal)).await.unwrap(), "Lightbulb locked successfully (sequence 3)");
+        // Another session keeps the default
+        assert_eq!(service.new_session().unlock_lightbulb(Parameters(Default::default())).await.unwrap(), "Lightbulb unlocked successfully (sequence 4)");
+    }
+
+    #[tokio::test]
+    async fn test_turn_off_already_off() {
+        let service = LightService::new_with_in_memory_logger();
+
+        let result = service.turn_off_lightbulb(Parameters(Default::default())).await;
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), "The lightbulb is already off (sequence 1)");
+    }
+
+    #[tokio::test]
+    async fn test_logging_behavior() {
+        let service = LightService::new_with_in_memory_logger();
+        
+        // Turn on the lightbulb
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        
+        // Turn off the lightbulb
+        let _ = service.turn_off_lightbulb(Parameters(Default::default())).await;
+        
+        // Check that the log contains both actions
+        let log_content = service.read_log_content().await.expect("Failed to read log content");
+        assert!(log_content.contains("turned ON"));
+        assert!(log_content.contains("turned OFF"));
+    }
+
+    #[tokio::test]
+    async fn test_get_lightbulb_state_returns_json() {
+        let service = LightService::new_with_in_memory_logger();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+
+        let state: serde_json::Value = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert_eq!(state["state"], "ON");
+        assert_eq!(state["power"], "on");
+        assert_eq!(state["brightness"], 100);
+        assert_eq!(state["color"], serde_json::json!({ "r": 255, "g": 255, "b": 255 }));
+        assert_eq!(state["locked"], false);
+        assert!(state["active_effect"].is_null());
+        assert!(state["last_changed"].is_string());
+        assert_eq!(state["version"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_expected_version_conflict() {
+        let service = LightService::new_with_in_memory_logger();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+
+        let stale = ChangeRequest { expected_version: Some(0), ..Default::default() };
+        let error = service.turn_off_lightbulb(Parameters(stale.into())).await.unwrap_err();
+        assert_eq!(error.message, "The lightbulb state has changed: expected version 0, found 2");
+        let data = error.data.unwrap();
+        assert_eq!((data["code"].as_str(), data["actual_version"].as_u64()), (Some("VERSION_CONFLICT"), Some(2)));
+
+        let current = ChangeRequest { expected_version: Some(2), ..Default::default() };
+        assert!(service.turn_off_lightbulb(Parameters(current.into())).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_retried_call_with_idempotency_key_is_not_repeated() {
+        let service = LightService::new_with_in_memory_logger();
+        let keyed = || Parameters(ChangeRequest { idempotency_key: Some("retry-1".to_string()), ..Default::default() }.into());
+        assert_eq!(service.turn_on_lightbulb(keyed()).await.unwrap(), "Lightbulb turned on successfully (sequence 1)");
+        let _ = service.turn_off_lightbulb(Parameters(Default::default())).await;
+
+        assert_eq!(service.turn_on_lightbulb(keyed()).await.unwrap(), "Lightbulb turned on successfully (sequence 1)");
+        assert!(reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap()).starts_with("The lightbulb is off"));
+        assert_eq!(service.read_log_content().await.unwrap().matches("turned ON").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reason_is_logged_and_bounded() {
+        let service = LightService::new_with_in_memory_logger();
+        let reason = |text: &str| Parameters(ChangeRequest { reason: Some(text.to_string()), ..Default::default() }.into());
+        service.turn_on_lightbulb(reason("user asked for\nmovie lighting")).await.unwrap();
+        assert!(service.read_log_content().await.unwrap().contains("turned ON (reason: user asked for movie lighting)"));
+
+        let error = service.turn_off_lightbulb(reason(&"x".repeat(201))).await.unwrap_err();
+        assert!(error.message.contains("at most 200 characters"));
+        assert!(reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap()).starts_with("The lightbulb is on"));
+    }
+
+    #[tokio::test]
+    async fn test_set_color_by_name() {
+        let service = LightService::new_with_in_memory_logger();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        let request = SetColorByNameRequest { name: "Sky Blue".to_string(), change: Default::default() };
+        let result = service.set_color_by_name(Parameters(request)).await.unwrap();
+        assert_eq!(reply::text_of(&result), "Lightbulb color set to skyblue (#87ceeb) (sequence 2)");
+        assert_eq!(result.content.unwrap()[1], reply::swatch(Color { r: 135, g: 206, b: 235 }));
+
+        let state: serde_json::Value = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert_eq!(state["color"], serde_json::json!({ "r": 135, "g": 206, "b": 235 }));
+
+        let request = SetColorByNameRequest { name: "warm ambre".to_string(), change: Default::default() };
+        let error = service.set_color_by_name(Parameters(request)).await.unwrap_err();
+        assert!(error.message.contains("did you mean one of: warm white, warm amber"));
+    }
+
+    fn color_request(rgb: Option<&str>, hsv: Option<Hsv>, xy: Option<Xy>) -> Parameters<SetColorRequest> {
+        Parameters(SetColorRequest { rgb: rgb.map(String::from), hsv, xy, change: Default::default() })
+    }
+
+    #[tokio::test]
+    async fn test_set_color_clamps_to_the_bulb_gamut() {
+        let backend = SimulatedBackend::new().with_gamut(GAMUT_B);
+        let service = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(backend));
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        let result = reply::text_of(&service.set_color(color_request(None, Some(Hsv { h: 30.0, s: 0.5, v: 1.0 }), None)).await.unwrap());
+        assert_eq!(result, "Lightbulb color set to #ffbf80 (xy 0.4181, 0.3940; hsv 30°, 50%, 100%) (sequence 2)");
+        let result = reply::text_of(&service.set_color(color_request(None, None, Some(Xy { x: 0.17, y: 0.7 }))).await.unwrap());
+        assert_eq!(
+            result,
+            "Lightbulb color set to #eeff00 (xy 0.4075, 0.5147; hsv 64°, 100%, 100%); #00ff0c is outside the bulb's gamut, so the nearest color it can show was used (sequence 3)"
+        );
+
+        let state: serde_json::Value = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert_eq!(state["color"], serde_json::json!({ "r": 238, "g": 255, "b": 0 }));
+        for request in [color_request(None, None, None), color_request(Some("#00ff00"), None, Some(Xy { x: 0.3, y: 0.3 })), color_request(Some("green"), None, None), color_request(None, None, Some(Xy { x: 0.7, y: 0.4 }))] {
+            let error = service.set_color(request).await.unwrap_err();
+            assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_color_temperature_honors_the_bulb_range() {
+        let backend = SimulatedBackend::new().with_mired_range(MiredRange::new(153, 500).unwrap());
+        let service = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(backend));
+        let temperature = |kelvin, mireds| Parameters(SetColorTemperatureRequest { kelvin, mireds, change: Default::default() });
+        let result = reply::text_of(&service.set_color_temperature(temperature(Some(2700), None)).await.unwrap());
+        assert_eq!(result, "Lightbulb color temperature set to 2700K (370 mireds, #ffa758) (sequence 1)");
+        let result = reply::text_of(&service.set_color_temperature(temperature(None, Some(370))).await.unwrap());
+        assert_eq!(result, "Lightbulb color temperature set to 2703K (370 mireds, #ffa758) (sequence 2)");
+        let result = reply::text_of(&service.set_color_temperature(temperature(None, Some(1000))).await.unwrap());
+        assert_eq!(
+            result,
+            "Lightbulb color temperature set to 2000K (500 mireds, #ff890e); 1000 mireds is outside the bulb's range of 153-500 mireds (2000K-6536K), so the nearest it can show was used (sequence 3)"
+        );
+        for request in [temperature(None, None), temperature(Some(2700), Some(370)), temperature(Some(500), None), temperature(None, Some(0))] {
+            let error = service.set_color_temperature(request).await.unwrap_err();
+            assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_brightness_is_gamma_corrected() {
+        let service = LightService::new_with_in_memory_logger();
+        let brightness = |percent| Parameters(SetBrightnessRequest { percent, change: Default::default() });
+        let result = service.set_brightness(brightness(50)).await.unwrap();
+        assert_eq!(result, "Lightbulb brightness set to 50% (level 55 of 255 after gamma 2.2) (sequence 1)");
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        let state: serde_json::Value = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert_eq!(state["brightness"], 50);
+        for percent in [0, 101] {
+            let error = service.set_brightness(brightness(percent)).await.unwrap_err();
+            assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        }
+
+        let service = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(UnreachableBackend));
+        let error = service.set_brightness(brightness(50)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "BRIGHTNESS_UNSUPPORTED");
+    }
+
+    #[tokio::test]
+    async fn test_brightness_schedule_caps_what_the_bulb_shows() {
+        let clock = ManualClock::new(DateTime::parse_from_rfc3339("2026-10-14T12:00:00Z").unwrap().with_timezone(&Utc));
+        let point = |at: &str, max| crate::config::BrightnessPointConfig { at: at.to_string(), max };
+        let schedule = BrightnessSchedule::new(&[point("07:00", 100), point("22:00", 100), point("23:00", 20)]).unwrap();
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).brightness_schedule(schedule).build();
+        let brightness = |percent| Parameters(SetBrightnessRequest { percent, change: Default::default() });
+        let level = |service: &LightService| service.faults.as_ref().unwrap().level();
+
+        assert_eq!(service.set_brightness(brightness(80)).await.unwrap(), "Lightbulb brightness set to 80% (level 156 of 255 after gamma 2.2) (sequence 1)");
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(!status.contains("capped"), "{}", status);
+
+        // Turned on later, the bulb is sent the capped level and keeps the brightness asked for
+        clock.advance(TimeDelta::hours(11));
+        service.turn_on_lightbulb(Parameters(Default::default())).await.unwrap();
+        assert_eq!(level(&service), Some(BrightnessCurve::default().output(20)));
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.ends_with("; brightness is capped at 20% at this time of day"), "{}", status);
+        let state: serde_json::Value = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert_eq!((state["brightness"].as_u64(), state["brightness_cap"].as_u64()), (Some(80), Some(20)));
+
+        clock.advance(TimeDelta::minutes(-30));
+        let result = service.set_brightness(brightness(90)).await.unwrap();
+        assert!(result.contains("set to 90% (level 83 of 255 after gamma 2.2), shown at 60% as the time of day caps it"), "{}", result);
+        assert!(service.set_brightness(brightness(50)).await.unwrap().starts_with("Lightbulb brightness set to 50% (level 55 of 255"));
+    }
+
+    #[tokio::test]
+    async fn test_backend_failure_marks_unreachable() {
+        let service = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(UnreachableBackend));
+
+        let result = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        assert!(result.is_err());
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.starts_with("The lightbulb is unreachable for 0s (version 2, last change: turn_on "), "{}", status);
+        assert!(status.ends_with("; the device has not answered since the server started 0s ago"), "{}", status);
+        assert!(service.read_log_content().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_offline_queue_answers_changes_for_an_unreachable_bulb() {
+        let service = LightService::builder()
+            .logger(Box::new(InMemoryLogger::new()))
+            .backend(Box::new(UnreachableBackend))
+            .offline_queue(Some(std::time::Duration::from_secs(60)))
+            .build();
+        let empty = service.get_offline_queue().await.unwrap();
+        assert_eq!(reply::text_of(&empty).lines().next(), Some("Nothing is queued"));
+
+        let queued = service.turn_on_lightbulb(Parameters(Default::default())).await.unwrap();
+        assert_eq!(queued, "Queued, not yet applied: the lightbulb is unreachable, so this will turn it ON once it answers (see get_offline_queue) (sequence 1)");
+        let result = service.get_offline_queue().await.unwrap();
+        assert!(reply::text_of(&result).starts_with("1 change is queued for the unreachable lightbulb: turn it ON"));
+        assert_eq!(reply::json_of(&result).unwrap()[0]["value"], "on");
+
+        let off = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(UnreachableBackend));
+        assert!(reply::text_of(&off.get_offline_queue().await.unwrap()).starts_with("Nothing is queued; changes that cannot reach the lightbulb fail at once"));
+    }
+
+    #[tokio::test]
+    async fn test_status_and_state_say_when_the_device_last_answered() {
+        let clock = ManualClock::new("2026-03-01T20:00:00Z".parse().unwrap());
+        let backend = SimulatedBackend::new();
+        let faults = backend.fault_injector().unwrap();
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).backend(Box::new(backend)).clock(Arc::new(clock.clone())).build();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        clock.advance(TimeDelta::minutes(10));
+        faults.set_unreachable_for(std::time::Duration::from_secs(60));
+
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.ends_with(", checked 0s ago; it last answered 10m 0s ago"), "{}", status);
+        let state: serde_json::Value = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert_eq!((state["uptime_secs"].as_i64(), state["backend_contacted_secs_ago"].as_i64()), (Some(600), Some(600)));
+        assert_eq!(state["backend_contacted_at"], "2026-03-01T20:00:00Z");
+    }
+
+    // Accepts every change but always reports the bulb off, as if someone flipped the switch by hand
+    struct SwitchedOffBackend(Arc<AtomicUsize>);
+
+    #[async_trait::async_trait]
+    impl LightBackend for SwitchedOffBackend {
+        fn name(&self) -> &str {
+            "switched-off"
+        }
+
+        async fn set_power(&mut self, _state: PowerState) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        async fn power(&self) -> anyhow::Result<PowerState> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(PowerState::Off)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_status_caches_the_device_reading() {
+        let (clock, reads) = (ManualClock::new(Utc::now()), Arc::new(AtomicUsize::new(0)));
+        let service = LightService::builder()
+            .logger(Box::new(InMemoryLogger::new()))
+            .backend(Box::new(SwitchedOffBackend(reads.clone())))
+            .clock(Arc::new(clock.clone()))
+            .build();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.ends_with("ago)"), "{}", status);
+
+        clock.advance(TimeDelta::seconds(6));
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.ends_with("; the device reports it off, checked 0s ago"), "{}", status);
+        clock.advance(TimeDelta::seconds(2));
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.ends_with("checked 2s ago"), "{}", status);
+        assert_eq!(reads.load(Ordering::SeqCst), 1);
+
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(StatusRequest { refresh: true, ..Default::default() })).await.unwrap());
+        assert!(status.ends_with("checked 0s ago"), "{}", status);
+        assert_eq!(reads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_locked_lightbulb_rejects_changes() {
+        let service = LightService::new_with_in_memory_logger();
+        let _ = service.turn_on_lightbulb(Parameters(Default::default())).await;
+        assert!(service.lock_lightbulb(Parameters(Default::default())).await.is_ok());
+
+        let error = service.turn_off_lightbulb(Parameters(Default::default())).await.unwrap_err();
+        assert_eq!(error.message, "Cannot turn off while the lightbulb is locked");
+        assert_eq!(error.data.unwrap()["code"], "BULB_LOCKED");
+        let status = reply::summary_of(&service.get_lightbulb_status(Parameters(Default::default())).await.unwrap());
+        assert!(status.starts_with("The lightbulb is on (locked) for 0s (version 3, last change: lock "), "{}", status);
+
+        assert!(service.unlock_lightbulb(Parameters(Default::default())).await.is_ok());
+        assert!(service.turn_off_lightbulb(Parameters(Default::default())).await.is_ok());
+    }
+}
@@ -0,0 +1,97 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::LightService;
+use crate::actor::PowerChange;
+use crate::config::RulePower;
+use crate::presence::AppliedRule;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ReportPresenceRequest {
+    /// Person who arrived or left, as configured
+    pub person: String,
+    /// True when they arrived home, false when they left
+    pub home: bool,
+}
+
+fn who_is_home(home: &[String]) -> String {
+    match home.is_empty() {
+        true => "no one is home".to_string(),
+        false => format!("home now: {}", home.join(", ")),
+    }
+}
+
+// Arrival and departure rules, gated behind the `presence` feature
+#[tool_router(router = presence_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Report a person arriving home or leaving, applying the configured presence rule it matches, such as turning the lightbulb off once everyone has left")]
+    pub(super) async fn report_presence(&self, Parameters(request): Parameters<ReportPresenceRequest>) -> Result<String, ErrorData> {
+        let (person, arriving) = (request.person.as_str(), request.home);
+        let now = self.light.clock().local_time();
+        let arrival = if arriving { "arrived home" } else { "left" };
+        if self.dry_run {
+            let text = match self.presence.preview(person, arriving, now)? {
+                (false, _) => format!("{} is already {}", person, if arriving { "home" } else { "away" }),
+                (true, Some(rule)) => format!("{} {} would apply the presence rule '{}'", person, arrival, rule),
+                (true, None) => format!("{} {} would apply no presence rule", person, arrival),
+            };
+            return Ok(format!("{}: {}", self.dry_run_prefix(), text));
+        }
+        let report = self.presence.report(&self.light, person, arriving, now).await?;
+        if !report.changed {
+            return Ok(format!("{} was already {}; {}", person, if arriving { "home" } else { "away" }, who_is_home(&report.home)));
+        }
+        let text = format!("{} {}; {}", person, arrival, who_is_home(&report.home));
+        let Some(AppliedRule { name: rule, power, result }) = report.applied else {
+            return Ok(text);
+        };
+        let target = match power {
+            RulePower::On => "ON",
+            RulePower::Off => "OFF",
+        };
+        Ok(match result {
+            Ok(applied) if applied.outcome == PowerChange::Changed => {
+                Self::with_sequence(&format!("{}. The presence rule '{}' turned the lightbulb {}", text, rule, target), applied.sequence)
+            },
+            Ok(applied) if applied.outcome == PowerChange::Queued => {
+                format!("{}. The presence rule '{}': {}", text, rule, Self::queued(&format!("turn it {}", target), applied.sequence))
+            },
+            Ok(_) => format!("{}. The presence rule '{}' found the lightbulb already {}", text, rule, target),
+            Err(e) => format!("{}. The presence rule '{}' could not be applied: {}", text, rule, e),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{PresenceConfig, PresenceEvent, PresenceRuleConfig};
+    use crate::logger::InMemoryLogger;
+    use crate::presence::Presence;
+
+    #[tokio::test]
+    async fn test_report_presence() {
+        let rule = PresenceRuleConfig {
+            name: "everyone left".to_string(),
+            when: PresenceEvent::LastLeave,
+            person: None,
+            power: RulePower::Off,
+            brightness: None,
+            after: None,
+            before: None,
+        };
+        let presence = Presence::new(PresenceConfig { people: vec!["alice".to_string()], rules: vec![rule] }).unwrap();
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).presence(presence).build();
+        let report = |home: bool| service.report_presence(Parameters(ReportPresenceRequest { person: "alice".to_string(), home }));
+
+        assert_eq!(report(true).await.unwrap(), "alice arrived home; home now: alice");
+        assert_eq!(report(true).await.unwrap(), "alice was already home; home now: alice");
+        let dry_run = service.dry_run().report_presence(Parameters(ReportPresenceRequest { person: "alice".to_string(), home: false })).await.unwrap();
+        assert!(dry_run.ends_with(": alice left would apply the presence rule 'everyone left'"), "{}", dry_run);
+        assert_eq!(report(false).await.unwrap(), "alice left; no one is home. The presence rule 'everyone left' found the lightbulb already OFF");
+        let error = service.report_presence(Parameters(ReportPresenceRequest { person: "bob".to_string(), home: true })).await.unwrap_err();
+        assert!(error.message.contains("Unknown person 'bob'"), "{}", error.message);
+    }
+}
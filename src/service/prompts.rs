@@ -0,0 +1,379 @@
+#[cfg(feature = "analytics")]
+use chrono::TimeDelta;
+use rmcp::model::{ErrorData, GetPromptResult, JsonObject, Prompt, PromptArgument, PromptMessage, PromptMessageRole};
+
+use super::LightService;
+#[cfg(feature = "analytics")]
+use crate::clock::format_duration;
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics::build_report;
+use crate::error::LightError;
+#[cfg(feature = "diagnostics")]
+use crate::error::to_json;
+#[cfg(feature = "diagnostics")]
+use crate::info::ServerInfoReport;
+#[cfg(feature = "analytics")]
+use crate::stats::{DEFAULT_WATTS, Metric, compute, day_start};
+
+#[cfg(feature = "analytics")]
+pub const ENERGY_PROMPT: &str = "energy_savings_advisor";
+#[cfg(feature = "diagnostics")]
+pub const TROUBLESHOOT_PROMPT: &str = "troubleshoot_lightbulb";
+// Periods the energy prompt's `period` argument takes by name, besides a number of days
+#[cfg(feature = "analytics")]
+const PERIODS: [(&str, i64); 4] = [("day", 1), ("week", 7), ("month", 30), ("year", 365)];
+#[cfg(feature = "analytics")]
+const DEFAULT_PERIOD: &str = "week";
+#[cfg(feature = "analytics")]
+const MAX_PERIOD_DAYS: i64 = 365;
+// Periods up to a month long also list the time on of each day
+#[cfg(feature = "analytics")]
+const DAILY_DETAIL_DAYS: i64 = 31;
+// The troubleshooting prompt's log slices: the end of the log, and the latest earlier lines that share a word with the symptom
+#[cfg(feature = "diagnostics")]
+const TAIL_LINES: usize = 20;
+#[cfg(feature = "diagnostics")]
+const MATCHING_LINES: usize = 20;
+
+// Prompts: the energy advisor with the `analytics` feature, and troubleshooting with `diagnostics`
+impl LightService {
+    pub(super) fn prompts(&self) -> Vec<Prompt> {
+        let mut prompts = Vec::new();
+        if self.guest {
+            return prompts;
+        }
+        #[cfg(feature = "analytics")]
+        {
+            let period = PromptArgument {
+                name: "period".to_string(),
+                description: Some(format!("How far back to look: day, week, month, year or a number of days up to {} (default {})", MAX_PERIOD_DAYS, DEFAULT_PERIOD)),
+                required: Some(false),
+            };
+            prompts.push(Prompt::new(
+                ENERGY_PROMPT,
+                Some("Ask for concrete energy-saving recommendations, given the lightbulb's usage over a period and the configured tariff"),
+                Some(vec![period]),
+            ));
+        }
+        #[cfg(feature = "diagnostics")]
+        {
+            let symptom = PromptArgument {
+                name: "symptom".to_string(),
+                description: Some("What is going wrong, e.g. \"the bulb turns on by itself at night\"".to_string()),
+                required: Some(true),
+            };
+            prompts.push(Prompt::new(
+                TROUBLESHOOT_PROMPT,
+                Some("Ask for help debugging the lightbulb setup, given a symptom, the server's diagnostics, its recent problems and the log"),
+                Some(vec![symptom]),
+            ));
+        }
+        prompts
+    }
+
+    pub(super) async fn prompt(&self, name: &str, arguments: Option<&JsonObject>) -> Result<GetPromptResult, ErrorData> {
+        match name {
+            #[cfg(feature = "analytics")]
+            ENERGY_PROMPT if !self.guest => self.energy_prompt(arguments).await,
+            #[cfg(feature = "diagnostics")]
+            TROUBLESHOOT_PROMPT if !self.guest => self.troubleshoot_prompt(arguments).await,
+            _ => Err(LightError::InvalidParameter(format!("Unknown prompt '{}'", name)).into()),
+        }
+    }
+
+    #[cfg(feature = "analytics")]
+    pub(super) fn complete_period(prefix: &str) -> Vec<String> {
+        let prefix = prefix.trim().to_ascii_lowercase();
+        PERIODS.iter().map(|(name, _)| name.to_string()).filter(|name| name.starts_with(&prefix)).collect()
+    }
+
+    #[cfg(feature = "analytics")]
+    async fn energy_prompt(&self, arguments: Option<&JsonObject>) -> Result<GetPromptResult, ErrorData> {
+        let period = match arguments.and_then(|arguments| arguments.get("period")) {
+            Some(period) => period.as_str().ok_or_else(|| LightError::InvalidParameter("period must be a string".to_string()))?,
+            None => DEFAULT_PERIOD,
+        };
+        let (label, days) = period_days(period)?;
+        let now = self.light.clock().now();
+        let from = now - TimeDelta::days(days);
+        let watts = self.tariff.as_ref().map_or(DEFAULT_WATTS, |tariff| tariff.watts);
+        let usage = self.light.usage().await?;
+        let metrics = [Metric::Counts, Metric::OnTime, Metric::Energy, Metric::Histogram];
+        let statistics = compute(&usage.events, &usage.days, &metrics, Some(from), now, watts, None);
+        let (total, on, off) = statistics.counts.map_or((0, 0, 0), |counts| (counts.total, counts.on, counts.off));
+        let on_secs = statistics.on_time_secs.unwrap_or(0);
+        let kwh = statistics.energy.map_or(0.0, |energy| energy.watt_hours / 1000.0);
+        let snapshot = self.light.snapshot().await?;
+
+        let mut text = format!(
+            "I'd like to cut the electricity my smart lightbulb uses. Here is how it was used over the last {} ({} to {}, UTC).\n\n",
+            label,
+            from.format("%Y-%m-%d %H:%M"),
+            now.format("%Y-%m-%d %H:%M")
+        );
+        text.push_str(&format!("- Power changes: {} ({} on, {} off)\n", total, on, off));
+        text.push_str(&format!(
+            "- Time on: {} ({:.1}% of the period)\n",
+            format_duration(on_secs),
+            on_secs as f64 / (days * 24 * 60 * 60) as f64 * 100.0
+        ));
+        text.push_str(&format!("- Energy: {:.3} kWh, taking the bulb to draw {} W whenever it is on\n", kwh, watts));
+        match &self.tariff {
+            Some(tariff) => {
+                text.push_str(&format!(
+                    "- Cost: about {:.2} {} at the standard rate of {} {} per kWh\n",
+                    kwh * tariff.price_per_kwh,
+                    tariff.currency,
+                    tariff.price_per_kwh,
+                    tariff.currency
+                ));
+                if let Some(off_peak) = &tariff.off_peak {
+                    text.push_str(&format!(
+                        "- Off-peak rate: {} {} per kWh from {} to {}, in my local time\n",
+                        off_peak.price_per_kwh, tariff.currency, off_peak.start, off_peak.end
+                    ));
+                }
+            },
+            None => text.push_str("- Tariff: none configured, so assume a typical rate for electricity and say which one you used\n"),
+        }
+        text.push_str(&format!("- Right now: {} at {}% brightness\n", snapshot.state, snapshot.brightness));
+
+        let hours = statistics.histogram.unwrap_or_default();
+        if hours.iter().any(|changes| *changes > 0) {
+            text.push_str("\nPower changes by hour of the day (UTC):\n");
+            for (hour, changes) in hours.iter().enumerate().filter(|(_, changes)| **changes > 0) {
+                text.push_str(&format!("- {:02}:00: {}\n", hour, changes));
+            }
+        }
+        if days <= DAILY_DETAIL_DAYS && on_secs > 0 {
+            text.push_str("\nTime on by day (UTC):\n");
+            let today = now.date_naive();
+            for back in (0..days).rev() {
+                let day = today - TimeDelta::days(back);
+                let (start, end) = (day_start(day).max(from), day_start(day + TimeDelta::days(1)).min(now));
+                let day_on = compute(&usage.events, &usage.days, &[Metric::OnTime], Some(start), end, watts, None).on_time_secs.unwrap_or(0);
+                text.push_str(&format!("- {}: {}\n", day, format_duration(day_on)));
+            }
+        }
+
+        let mut tools: Vec<String> = self.tool_router.list_all().into_iter().map(|tool| tool.name.to_string()).collect();
+        tools.sort();
+        text.push_str(&format!("\nThe tools this lightbulb server offers: {}.\n\n", tools.join(", ")));
+        text.push_str(
+            "Give me 3 to 5 concrete recommendations to cut the bulb's energy use and cost, the most valuable first. For each, \
+             say what to change (a habit, a schedule or the brightness), estimate the savings per month in kWh and in money, and \
+             name the tools above that would carry it out. Base them on the patterns in this data, and say so where it is too \
+             sparse to back a recommendation.",
+        );
+        Ok(GetPromptResult {
+            description: Some(format!("Energy-saving recommendations for the lightbulb's last {}", label)),
+            messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+        })
+    }
+
+    #[cfg(feature = "diagnostics")]
+    async fn troubleshoot_prompt(&self, arguments: Option<&JsonObject>) -> Result<GetPromptResult, ErrorData> {
+        let symptom = match arguments.and_then(|arguments| arguments.get("symptom")) {
+            Some(symptom) => symptom.as_str().ok_or_else(|| LightError::InvalidParameter("symptom must be a string".to_string()))?.trim(),
+            None => "",
+        };
+        if symptom.is_empty() {
+            return Err(LightError::InvalidParameter("symptom is required: say what is going wrong".to_string()).into());
+        }
+        let now = self.light.clock().now();
+        let probe = self.health_probe().await?;
+        let report = to_json(&build_report(&probe, now))?;
+        let info = to_json(&ServerInfoReport::new(&self.deployment, self.started_at))?;
+        let snapshot = self.light.snapshot().await?;
+
+        let mut text = format!(
+            "Something is wrong with my smart lightbulb, which I control through an MCP server. The symptom: {}\n\n\
+             Here is what the server reports about itself at {} UTC.\n\n",
+            symptom,
+            now.format("%Y-%m-%d %H:%M:%S")
+        );
+        text.push_str(&format!("Right now the bulb is {} at {}% brightness.\n\n", snapshot.state, snapshot.brightness));
+        text.push_str(&format!("Diagnostics:\n```json\n{}\n```\n\nServer:\n```json\n{}\n```\n\n", report, info));
+
+        let problems = crate::verbosity::recent_problems();
+        match problems.is_empty() {
+            true => text.push_str("Warnings and failed tool calls since the server started: none.\n\n"),
+            false => {
+                text.push_str("Recent warnings and failed tool calls, oldest first:\n");
+                for (at, problem) in &problems {
+                    text.push_str(&format!("- [{}] {}\n", at.format("%Y-%m-%d %H:%M:%S"), problem));
+                }
+                text.push('\n');
+            },
+        }
+
+        match &probe.log_content {
+            Ok(log) => {
+                let (earlier, tail) = log_slices(log, symptom);
+                if !earlier.is_empty() {
+                    text.push_str(&format!("Earlier log lines that mention the symptom:\n```text\n{}\n```\n\n", earlier.join("\n")));
+                }
+                match tail.is_empty() {
+                    true => text.push_str("The log is empty.\n\n"),
+                    false => text.push_str(&format!("The end of the log:\n```text\n{}\n```\n\n", tail.join("\n"))),
+                }
+            },
+            Err(e) => text.push_str(&format!("The log could not be read: {}\n\n", e)),
+        }
+        text.push_str(
+            "Work out the most likely cause of the symptom and say which of the evidence above points to it. Then give me the \
+             steps to fix it, naming the server's tools or configuration settings that would help. Where the evidence is not \
+             enough to tell, say what I should check next.",
+        );
+        Ok(GetPromptResult {
+            description: Some(format!("Troubleshooting the lightbulb: {}", symptom)),
+            messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+        })
+    }
+}
+
+// The latest lines before the log's tail that share a word with the symptom, and the tail itself. Words found in
+// most of the log, such as "lightbulb", say nothing about which lines matter and are left out.
+#[cfg(feature = "diagnostics")]
+fn log_slices<'a>(log: &'a str, symptom: &str) -> (Vec<&'a str>, Vec<&'a str>) {
+    let lines: Vec<&str> = log.lines().filter(|line| !line.trim().is_empty()).collect();
+    let (earlier, tail) = lines.split_at(lines.len().saturating_sub(TAIL_LINES));
+    let lowered: Vec<String> = lines.iter().map(|line| line.to_lowercase()).collect();
+    let mut words: Vec<String> = symptom
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(String::from)
+        .collect();
+    words.sort();
+    words.dedup();
+    words.retain(|word| lowered.iter().filter(|line| line.contains(word.as_str())).count() * 2 <= lines.len());
+    let mut matching: Vec<&str> = earlier
+        .iter()
+        .zip(&lowered)
+        .rev()
+        .filter(|(_, line)| words.iter().any(|word| line.contains(word.as_str())))
+        .map(|(line, _)| *line)
+        .take(MATCHING_LINES)
+        .collect();
+    matching.reverse();
+    (matching, tail.to_vec())
+}
+
+// The period's name for the prompt text and its length in days
+#[cfg(feature = "analytics")]
+fn period_days(period: &str) -> Result<(String, i64), LightError> {
+    let period = period.trim().to_ascii_lowercase();
+    if let Some(&(name, days)) = PERIODS.iter().find(|(name, _)| *name == period) {
+        return Ok((name.to_string(), days));
+    }
+    match period.parse::<i64>() {
+        Ok(1) => Ok(("day".to_string(), 1)),
+        Ok(days) if (2..=MAX_PERIOD_DAYS).contains(&days) => Ok((format!("{} days", days), days)),
+        _ => Err(LightError::InvalidParameter(format!(
+            "period must be day, week, month, year or a number of days from 1 to {}, got '{}'",
+            MAX_PERIOD_DAYS, period
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "analytics")]
+    use std::sync::Arc;
+
+    use super::*;
+    #[cfg(feature = "analytics")]
+    use crate::clock::ManualClock;
+    #[cfg(feature = "analytics")]
+    use crate::config::{OffPeakConfig, TariffConfig};
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+
+    fn text(result: &GetPromptResult) -> String {
+        serde_json::to_value(&result.messages[0]).unwrap()["content"]["text"].as_str().unwrap().to_string()
+    }
+
+    #[cfg(feature = "analytics")]
+    #[test]
+    fn test_periods_are_named_or_counted_in_days() {
+        assert_eq!(period_days(" Month ").unwrap(), ("month".to_string(), 30));
+        assert_eq!(period_days("14").unwrap(), ("14 days".to_string(), 14));
+        assert!(period_days("0").is_err() && period_days("366").is_err() && period_days("fortnight").is_err());
+        assert_eq!(LightService::complete_period("w"), ["week"]);
+    }
+
+    #[cfg(feature = "analytics")]
+    #[tokio::test]
+    async fn test_energy_prompt_packages_usage_and_tariff() {
+        let clock = ManualClock::new("2025-08-02T18:00:00Z".parse().unwrap());
+        let tariff = TariffConfig {
+            price_per_kwh: 0.5,
+            currency: "EUR".to_string(),
+            watts: 10.0,
+            off_peak: Some(OffPeakConfig { price_per_kwh: 0.2, start: "23:00".to_string(), end: "07:00".to_string() }),
+        };
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).tariff(tariff).build();
+        service.light.set_power(PowerState::On).await.unwrap();
+        clock.advance(TimeDelta::hours(2));
+        service.light.set_power(PowerState::Off).await.unwrap();
+        clock.advance(TimeDelta::minutes(30));
+
+        let result = service.prompt(ENERGY_PROMPT, None).await.unwrap();
+        let text = text(&result);
+        assert!(text.contains("over the last week (2025-07-26 20:30 to 2025-08-02 20:30, UTC)"), "{}", text);
+        assert!(text.contains("- Power changes: 2 (1 on, 1 off)\n- Time on: 2h 0m (1.2% of the period)"), "{}", text);
+        assert!(text.contains("- Energy: 0.020 kWh, taking the bulb to draw 10 W"), "{}", text);
+        assert!(text.contains("- Cost: about 0.01 EUR at the standard rate of 0.5 EUR per kWh"), "{}", text);
+        assert!(text.contains("- Off-peak rate: 0.2 EUR per kWh from 23:00 to 07:00"), "{}", text);
+        assert!(text.contains("- 18:00: 1\n- 20:00: 1\n"), "{}", text);
+        assert!(text.contains("- 2025-08-02: 2h 0m\n"), "{}", text);
+        assert!(text.contains("turn_on_lightbulb"), "{}", text);
+
+        let mut arguments = JsonObject::new();
+        arguments.insert("period".to_string(), "fortnight".into());
+        assert!(service.prompt(ENERGY_PROMPT, Some(&arguments)).await.is_err());
+        assert!(service.prompt("poem", None).await.is_err());
+        assert!(service.guest().prompts().is_empty());
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[test]
+    fn test_log_slices_pick_lines_that_share_a_word_with_the_symptom() {
+        let mut log = String::from("[2025-08-01T03:00:00+00:00] Lightbulb turned ON by motion (reason: motion in hallway)\n");
+        for minute in 0..TAIL_LINES + 2 {
+            log.push_str(&format!("[2025-08-01T09:{:02}:00+00:00] Lightbulb turned OFF by desk\n", minute));
+        }
+        let (earlier, tail) = log_slices(&log, "The lightbulb comes on in the HALLWAY at night");
+        assert_eq!(earlier, [log.lines().next().unwrap()]);
+        assert_eq!(tail.len(), TAIL_LINES);
+        assert!(tail.last().unwrap().starts_with("[2025-08-01T09:21:00"));
+        // "lightbulb" is on every line, so it picks out none of them
+        assert!(log_slices(&log, "lightbulb").0.is_empty());
+    }
+
+    #[cfg(feature = "diagnostics")]
+    #[tokio::test]
+    async fn test_troubleshoot_prompt_packages_diagnostics_problems_and_log() {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+        service.light.set_power(PowerState::On).await.unwrap();
+        crate::verbosity::remember("could not subscribe to motion topic home/hall: refused".to_string());
+
+        let mut arguments = JsonObject::new();
+        arguments.insert("symptom".to_string(), " the bulb stays on ".into());
+        let result = service.prompt(TROUBLESHOOT_PROMPT, Some(&arguments)).await.unwrap();
+        assert_eq!(result.description.as_deref(), Some("Troubleshooting the lightbulb: the bulb stays on"));
+        let text = text(&result);
+        assert!(text.contains("The symptom: the bulb stays on\n"), "{}", text);
+        assert!(text.contains("Right now the bulb is ON at 100% brightness."), "{}", text);
+        assert!(text.contains("\"healthy\": true"), "{}", text);
+        assert!(text.contains(concat!("\"version\": \"", env!("CARGO_PKG_VERSION"))), "{}", text);
+        assert!(text.contains("] could not subscribe to motion topic home/hall: refused\n"), "{}", text);
+        assert!(text.contains("The end of the log:\n```text\n[") && text.contains("Lightbulb turned ON"), "{}", text);
+
+        assert!(service.prompt(TROUBLESHOOT_PROMPT, None).await.is_err());
+        let names: Vec<String> = service.prompts().into_iter().map(|prompt| prompt.name).collect();
+        assert!(names.contains(&TROUBLESHOOT_PROMPT.to_string()));
+        assert!(service.guest().prompt(TROUBLESHOOT_PROMPT, Some(&arguments)).await.is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::ErrorData;
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::{ChangeRequest, LightService};
+use crate::actor::{Change, PowerChange};
+use crate::error::LightError;
+use crate::model::{Easing, MAX_CROSSFADE_MS, PowerState};
+use crate::scenes::{Crossfade, apply, crossfade};
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ApplySceneRequest {
+    /// Scene to apply: reading, movie or relax, or one from the configuration (see the lightbulb://scenes resource)
+    pub name: String,
+    /// Fade from how the bulb looks to the scene over this many milliseconds, at most 20000; 0 or omitted sets
+    /// the scene at once
+    #[schemars(range(max = MAX_CROSSFADE_MS))]
+    pub transition_ms: Option<u64>,
+    /// How the fade moves through its time: "linear" (the default), "ease-in", "ease-out" or "ease-in-out"
+    pub easing: Option<Easing>,
+    #[serde(flatten)]
+    pub change: ChangeRequest,
+}
+
+// Named lighting presets, gated behind the `scenes` feature
+#[tool_router(router = scene_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(
+        description = "Apply a scene: turn the lightbulb on at the scene's brightness and color temperature or color, at once or crossfading over transition_ms. Built in are reading, movie and relax"
+    )]
+    pub(super) async fn apply_scene(&self, Parameters(request): Parameters<ApplySceneRequest>) -> Result<String, ErrorData> {
+        let scene = self.scenes.get(&request.name)?;
+        let fade = Crossfade::new(request.transition_ms.unwrap_or(0), request.easing.unwrap_or_default())?;
+        let fading = fade.map(|fade| format!(", fading in {}", fade.describe())).unwrap_or_default();
+        // The power change is tagged with the scene, so statistics can tell how often each is used
+        let mut tags = request.change.tags.clone().unwrap_or_default();
+        if !tags.iter().any(|tag| tag.eq_ignore_ascii_case(&scene.name)) {
+            tags.push(scene.name.clone());
+        }
+        let change = ChangeRequest { tags: Some(tags), ..request.change };
+        let light = self.change_handle(&change)?;
+        if self.dry_run {
+            let previews = light.preview(vec![Change::Brightness(scene.brightness), Change::Power(PowerState::On)]).await?;
+            let preview = previews.into_iter().last().ok_or(LightError::Internal("a dry run previewed nothing".to_string()))??;
+            return Ok(format!("{}: would apply scene '{}' ({}{}), and {}", self.dry_run_prefix(), scene.name, scene.describe(), fading, Self::describe_preview(&preview)));
+        }
+        let applied = async {
+            let applied = match fade {
+                Some(fade) => crossfade(&light, &scene, fade).await?,
+                None => apply(&light, &scene).await?,
+            };
+            let message = match applied.outcome {
+                PowerChange::Changed => format!("Applied scene '{}' ({}{}); the lightbulb is ON", scene.name, scene.describe(), fading),
+                PowerChange::AlreadyInState => format!("Applied scene '{}' ({}{}) to the lit lightbulb", scene.name, scene.describe(), fading),
+                PowerChange::Queued => return Ok(Self::queued(&format!("apply scene '{}' ({})", scene.name, scene.describe()), applied.sequence)),
+            };
+            Ok(Self::with_sequence(&message, applied.sequence))
+        };
+        self.run_change(&change, "apply_scene", applied).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SceneConfig;
+    use crate::logger::InMemoryLogger;
+
+    fn apply_scene(name: &str) -> Parameters<ApplySceneRequest> {
+        Parameters(ApplySceneRequest { name: name.to_string(), transition_ms: None, easing: None, change: Default::default() })
+    }
+
+    #[tokio::test]
+    async fn test_apply_scene() {
+        let dim = SceneConfig { name: "relax".to_string(), brightness: 10, kelvin: None, color: Some("amber".to_string()) };
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).scenes(vec![dim]).build();
+
+        let dry_run = service.dry_run().apply_scene(apply_scene("movie")).await.unwrap();
+        assert!(dry_run.ends_with(": would apply scene 'movie' (20% at 2700K), and the lightbulb would go from OFF to ON and the log would record \"ON [movie]\""), "{}", dry_run);
+        assert_eq!(service.apply_scene(apply_scene("Movie")).await.unwrap(), "Applied scene 'movie' (20% at 2700K); the lightbulb is ON (sequence 3)");
+        assert_eq!(service.apply_scene(apply_scene("relax")).await.unwrap(), "Applied scene 'relax' (10% in #ffbf00) to the lit lightbulb (sequence 6)");
+        assert!(service.light.read_log().await.unwrap().contains("Lightbulb turned ON [movie]"));
+
+        let fade = ApplySceneRequest { transition_ms: Some(1_500), easing: Some(Easing::EaseInOut), ..apply_scene("movie").0 };
+        let dry_run = service.dry_run().apply_scene(Parameters(fade)).await.unwrap();
+        assert!(dry_run.contains(": would apply scene 'movie' (20% at 2700K, fading in over 1.5s, ease-in-out), and "), "{}", dry_run);
+        let too_slow = ApplySceneRequest { transition_ms: Some(60_000), ..apply_scene("movie").0 };
+        assert_eq!(service.apply_scene(Parameters(too_slow)).await.unwrap_err().data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let error = service.apply_scene(apply_scene("party")).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+    }
+}
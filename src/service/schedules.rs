@@ -0,0 +1,259 @@
+use chrono::{TimeDelta, Weekday};
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{CallToolResult, ErrorData};
+use rmcp::{tool, tool_router};
+use serde::Deserialize;
+
+use super::{LightService, SCENES_URI, SCHEDULES_URI};
+use crate::automation::{AutomationDocument, Schedule, schedule_problems, spawn_timer};
+use crate::clock::format_duration;
+use crate::config::{RulePower, ScheduleConfig};
+use crate::error::LightError;
+use crate::reply;
+
+// A week
+const MAX_TIMER_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct ImportAutomationRequest {
+    /// JSON object of up to 1 MiB with "scenes" (each with name, brightness and kelvin or color) and "schedules"
+    /// (each with name, "HH:MM" at, optional days such as ["sat", "sun"], and a scene or power and brightness)
+    pub document: String,
+    /// Check the document and say what it would replace, importing nothing
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct TurnOffAfterRequest {
+    /// How long from now to turn the lightbulb off, between 1 second and 7 days (604800 seconds)
+    #[schemars(range(min = 1, max = MAX_TIMER_SECS))]
+    pub after_secs: u64,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct SetScheduleRequest {
+    /// The schedule's name, a lowercase tag such as "evening"; a schedule set or imported with the name before is replaced
+    pub name: String,
+    /// Local time of day to run at, as "HH:MM", or a five-field cron expression such as "*/30 18-22 * * fri" to run at every minute it matches
+    pub at: String,
+    /// Days to run on, such as ["sat", "sun"]; every day when left out, and taken from the expression for a cron `at`
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// "on" (the default) or "off"
+    #[serde(default)]
+    pub power: RulePower,
+    /// Brightness between 1 and 100 to turn the lightbulb on at
+    #[schemars(range(min = 1, max = 100))]
+    pub brightness: Option<u8>,
+    /// A scene to apply, instead of power and brightness
+    pub scene: Option<String>,
+}
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct CancelScheduleRequest {
+    /// A schedule's name, or a timer's id such as "timer-1", as list_schedules lists them
+    pub name: String,
+}
+
+// Schedules, timers and the import of scenes and schedules, gated behind the `schedules` feature
+#[tool_router(router = schedule_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(
+        description = "Import scenes and schedules from one JSON document, replacing those imported before. The whole document is checked first and nothing is imported if any of it is invalid"
+    )]
+    pub(super) async fn import_automation(&self, Parameters(request): Parameters<ImportAutomationRequest>) -> Result<String, ErrorData> {
+        let document = AutomationDocument::parse(&request.document)?;
+        self.automation.check(&document)?;
+        let plan = describe_import(&document, &self.automation.imported());
+        if self.dry_run {
+            return Ok(format!("{}: would import {}", self.dry_run_prefix(), plan));
+        }
+        if request.dry_run {
+            return Ok(format!("Nothing was imported: the document is valid and would import {}", plan));
+        }
+        self.automation.apply(document, self.light.clock().now()).await?;
+        self.resource_updated(SCENES_URI);
+        self.resource_updated(SCHEDULES_URI);
+        Ok(format!("Imported {}", plan))
+    }
+
+    #[tool(description = "Turn the lightbulb off after a while, e.g. in 30 minutes; the timer is kept across restarts until it fires or cancel_schedule cancels it")]
+    pub(super) async fn turn_off_after(&self, Parameters(request): Parameters<TurnOffAfterRequest>) -> Result<String, ErrorData> {
+        if !(1..=MAX_TIMER_SECS).contains(&request.after_secs) {
+            return Err(LightError::InvalidParameter(format!("after_secs must be between 1 and {} seconds, got {}", MAX_TIMER_SECS, request.after_secs)).into());
+        }
+        let after = request.after_secs as i64;
+        let at = self.light.clock().now() + TimeDelta::seconds(after);
+        if self.dry_run {
+            return Ok(format!("{}: would turn the lightbulb off in {}", self.dry_run_prefix(), format_duration(after)));
+        }
+        let timer = self.automation.timers.add(at, self.light.caller().map(String::from)).await?;
+        spawn_timer(self.automation.timers.clone(), self.light.clone(), timer.clone());
+        Ok(format!("Set {}, in {}; cancel_schedule cancels it", timer.describe(), format_duration(after)))
+    }
+
+    #[tool(
+        description = "Set a schedule that runs at a local time of day, every day or on given days, turning the lightbulb on or off or applying a scene. It is kept with the imported schedules, across restarts"
+    )]
+    pub(super) async fn set_schedule(&self, Parameters(request): Parameters<SetScheduleRequest>) -> Result<String, ErrorData> {
+        let days = request
+            .days
+            .iter()
+            .map(|day| day.trim().parse::<Weekday>().map_err(|_| LightError::InvalidParameter(format!("'{}' is not a day such as mon or sat", day))))
+            .collect::<Result<Vec<_>, _>>()?;
+        let config = ScheduleConfig {
+            name: request.name.trim().to_string(),
+            at: request.at.trim().to_string(),
+            days,
+            power: request.power,
+            brightness: request.brightness,
+            scene: request.scene.map(|scene| scene.trim().to_string()),
+        };
+        let problems = schedule_problems(std::slice::from_ref(&config), &self.scenes, &self.automation.imported().scenes);
+        if !problems.is_empty() {
+            return Err(LightError::InvalidParameter(problems.join("; ")).into());
+        }
+        let runs = Schedule::new(&config, true).describe();
+        if self.dry_run {
+            return Ok(format!("{}: would set schedule '{}' to run {}", self.dry_run_prefix(), config.name, runs));
+        }
+        let name = config.name.clone();
+        let replaced = self.automation.set_schedule(config, self.light.clock().now()).await?;
+        self.resource_updated(SCHEDULES_URI);
+        Ok(format!("{} schedule '{}', which runs {}", if replaced { "Replaced" } else { "Set" }, name, runs))
+    }
+
+    #[tool(description = "List the schedules, configured, imported and set with set_schedule, and the timers turn_off_after set, as JSON")]
+    pub(super) async fn list_schedules(&self) -> Result<CallToolResult, ErrorData> {
+        let schedules: Vec<String> = self.automation.schedules.all().into_iter().map(|schedule| schedule.config.name).collect();
+        let timers = self.automation.timers.all();
+        let ids: Vec<&str> = timers.iter().map(|timer| timer.id.as_str()).collect();
+        let summary = format!("{} and {}", counted(&schedules.iter().map(String::as_str).collect::<Vec<_>>(), "schedule"), counted(&ids, "timer"));
+        reply::json(summary, &serde_json::json!({ "schedules": self.describe_schedules(), "timers": timers }))
+    }
+
+    #[tool(description = "Cancel a timer, or remove a schedule set with set_schedule or imported; schedules in the config file can only be removed there")]
+    pub(super) async fn cancel_schedule(&self, Parameters(request): Parameters<CancelScheduleRequest>) -> Result<String, ErrorData> {
+        let name = request.name.trim();
+        if let Some(timer) = self.automation.timers.get(name) {
+            if self.dry_run {
+                return Ok(format!("{}: would cancel {}", self.dry_run_prefix(), timer.describe()));
+            }
+            return match self.automation.timers.remove(&timer).await? {
+                true => {
+                    self.light.tasks().finish(&timer.id);
+                    Ok(format!("Cancelled {}", timer.describe()))
+                },
+                false => Err(LightError::InvalidParameter(format!("Timer '{}' has already fired", name)).into()),
+            };
+        }
+        if self.dry_run && self.automation.imported().schedules.iter().any(|schedule| schedule.name == name) {
+            return Ok(format!("{}: would remove schedule '{}'", self.dry_run_prefix(), name));
+        }
+        self.automation.cancel_schedule(name, self.light.clock().now()).await?;
+        self.resource_updated(SCHEDULES_URI);
+        // An imported schedule stood in for a configured one of the same name, which runs again
+        match self.automation.schedules.all().into_iter().find(|schedule| schedule.config.name == name) {
+            Some(configured) => Ok(format!("Removed schedule '{}'; the config's schedule of that name runs {} again", name, configured.describe())),
+            None => Ok(format!("Removed schedule '{}'", name)),
+        }
+    }
+}
+
+// "2 scenes (party, relax) and 1 schedule (evening), replacing 1 scene and 2 schedules imported before"
+fn describe_import(document: &AutomationDocument, before: &AutomationDocument) -> String {
+    let scenes: Vec<&str> = document.scenes.iter().map(|scene| scene.name.as_str()).collect();
+    let schedules: Vec<&str> = document.schedules.iter().map(|schedule| schedule.name.as_str()).collect();
+    let mut plan = format!("{} and {}", counted(&scenes, "scene"), counted(&schedules, "schedule"));
+    if before != &AutomationDocument::default() {
+        plan.push_str(&format!(", replacing {} and {} imported before", count(before.scenes.len(), "scene"), count(before.schedules.len(), "schedule")));
+    }
+    plan
+}
+
+fn count(count: usize, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+fn counted(names: &[&str], noun: &str) -> String {
+    match names.is_empty() {
+        true => count(0, noun),
+        false => format!("{} ({})", count(names.len(), noun), names.join(", ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logger::InMemoryLogger;
+
+    const DOCUMENT: &str = r#"{
+        "scenes": [{ "name": "party", "brightness": 100, "color": "magenta" }],
+        "schedules": [{ "name": "evening", "at": "20:00", "scene": "party" }, { "name": "night", "at": "23:30", "days": ["fri", "sat"], "power": "off" }]
+    }"#;
+
+    fn import(document: &str, dry_run: bool) -> Parameters<ImportAutomationRequest> {
+        Parameters(ImportAutomationRequest { document: document.to_string(), dry_run })
+    }
+
+    #[tokio::test]
+    async fn test_import_automation() {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+
+        let preview = service.import_automation(import(DOCUMENT, true)).await.unwrap();
+        assert_eq!(preview, "Nothing was imported: the document is valid and would import 1 scene (party) and 2 schedules (evening, night)");
+        let dry_run = service.dry_run().import_automation(import(DOCUMENT, false)).await.unwrap();
+        assert!(dry_run.ends_with(": would import 1 scene (party) and 2 schedules (evening, night)"), "{}", dry_run);
+        assert!(service.scenes.get("party").is_err());
+
+        assert_eq!(service.import_automation(import(DOCUMENT, false)).await.unwrap(), "Imported 1 scene (party) and 2 schedules (evening, night)");
+        assert_eq!(service.describe_schedules()[1], serde_json::json!({ "name": "night", "runs": "at 23:30 on fri, sat: turn the bulb off", "source": "imported" }));
+        assert_eq!(service.scenes.get("party").unwrap().brightness, 100);
+
+        // A document with any problem changes nothing
+        let broken = r#"{ "schedules": [{ "name": "wake", "at": "07:00", "scene": "sunrise" }] }"#;
+        let error = service.import_automation(import(broken, false)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        assert!(error.message.contains("scene 'sunrise', which does not exist"), "{}", error.message);
+        assert_eq!(service.automation.schedules.all().len(), 2);
+        assert!(service.import_automation(import(r#"{ "lights": [] }"#, false)).await.is_err());
+
+        let replaced = service.import_automation(import(r#"{ "scenes": [{ "name": "sunrise", "brightness": 30, "kelvin": 2200 }] }"#, false)).await.unwrap();
+        assert_eq!(replaced, "Imported 1 scene (sunrise) and 0 schedules, replacing 1 scene and 2 schedules imported before");
+        assert!(service.scenes.get("party").is_err());
+    }
+
+    fn set(name: &str, at: &str, days: &[&str], power: RulePower) -> Parameters<SetScheduleRequest> {
+        let days = days.iter().map(|day| day.to_string()).collect();
+        Parameters(SetScheduleRequest { name: name.to_string(), at: at.to_string(), days, power, brightness: None, scene: None })
+    }
+
+    #[tokio::test]
+    async fn test_schedules_and_timers_are_set_listed_and_cancelled() {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+        assert_eq!(service.set_schedule(set("night", "23:30", &["fri", "sat"], RulePower::Off)).await.unwrap(), "Set schedule 'night', which runs at 23:30 on fri, sat: turn the bulb off");
+        assert_eq!(service.set_schedule(set("night", "23:00", &[], RulePower::Off)).await.unwrap(), "Replaced schedule 'night', which runs at 23:00 every day: turn the bulb off");
+        let error = service.set_schedule(set("wake", "07:00", &["someday"], RulePower::On)).await.unwrap_err();
+        assert!(error.message.ends_with("'someday' is not a day such as mon or sat"), "{}", error.message);
+        assert!(service.set_schedule(set("wake", "25:00", &[], RulePower::On)).await.unwrap_err().message.contains("is not HH:MM"));
+
+        let set = service.turn_off_after(Parameters(TurnOffAfterRequest { after_secs: 1800 })).await.unwrap();
+        assert!(set.starts_with("Set timer-1: turn the bulb off at ") && set.ends_with(", in 30m 0s; cancel_schedule cancels it"), "{}", set);
+        assert!(service.turn_off_after(Parameters(TurnOffAfterRequest { after_secs: 0 })).await.is_err());
+        let state = reply::json_of(&service.get_lightbulb_state().await.unwrap()).unwrap();
+        assert!(state["timers"][0].as_str().unwrap().starts_with("timer-1: turn the bulb off at "), "{}", state);
+
+        let listed = service.list_schedules().await.unwrap();
+        assert!(reply::text_of(&listed).starts_with("1 schedule (night) and 1 timer (timer-1)\n"));
+        assert_eq!(reply::json_of(&listed).unwrap()["schedules"][0]["runs"], "at 23:00 every day: turn the bulb off");
+
+        let cancel = |name: &str| Parameters(CancelScheduleRequest { name: name.to_string() });
+        assert!(service.dry_run().cancel_schedule(cancel("timer-1")).await.unwrap().contains("would cancel timer-1"));
+        assert!(service.cancel_schedule(cancel("timer-1")).await.unwrap().starts_with("Cancelled timer-1: turn the bulb off at "));
+        assert_eq!(service.cancel_schedule(cancel("night")).await.unwrap(), "Removed schedule 'night'");
+        let error = service.cancel_schedule(cancel("night")).await.unwrap_err();
+        assert!(error.message.ends_with("There is no schedule or timer 'night'; list_schedules lists them"), "{}", error.message);
+        assert!(service.automation.timers.all().is_empty() && service.automation.schedules.all().is_empty());
+    }
+}
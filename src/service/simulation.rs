@@ -0,0 +1,475 @@
+use std::time::Duration;
+
+use rmcp::handler::server::tool::Parameters;
+use rmcp::model::{ErrorData, ProgressNotificationParam};
+use rmcp::service::RequestContext;
+use rmcp::{RoleServer, tool, tool_router};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::LightService;
+use crate::backend::{DeviceReport, FaultConfig, FaultInjector, FirmwareStage, MAX_LATENCY_MS, MAX_UNREACHABLE, PowerRestore};
+use crate::error::LightError;
+use crate::logger::OutageEntry;
+use crate::model::PowerState;
+use crate::palette::parse_hex;
+#[cfg(feature = "jobs")]
+use crate::jobs::JobStatus;
+
+const DEFAULT_UPDATE_SECS: u64 = 10;
+const MAX_UPDATE_SECS: u64 = 300;
+const MAX_FIRMWARE_VERSION_LEN: usize = 32;
+const DEFAULT_OUTAGE_SECS: u64 = 10;
+const MAX_OUTAGE_SECS: u64 = 3600;
+// The percentage of the update each stage takes
+const FIRMWARE_STAGES: [(FirmwareStage, u32); 3] = [(FirmwareStage::Downloading, 50), (FirmwareStage::Flashing, 30), (FirmwareStage::Rebooting, 20)];
+// Progress is reported every this many percent
+const PROGRESS_STEP: u32 = 5;
+
+#[derive(Debug, Deserialize, schemars::JsonSchema)]
+pub struct FaultInjectionRequest {
+    /// Delay added before every backend call, in milliseconds, at most 60000 (unchanged if omitted)
+    #[schemars(range(max = MAX_LATENCY_MS))]
+    pub latency_ms: Option<u64>,
+    /// Probability between 0.0 and 1.0 that a backend call fails (unchanged if omitted)
+    #[schemars(range(min = 0.0, max = 1.0))]
+    pub failure_rate: Option<f64>,
+    /// Make the bulb unreachable for this many seconds, starting now, at most 86400
+    #[schemars(range(max = MAX_UNREACHABLE.as_secs()))]
+    pub unreachable_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct UpdateFirmwareRequest {
+    /// Firmware version to install, e.g. "1.1.0" (defaults to the next patch version)
+    #[schemars(length(min = 1, max = MAX_FIRMWARE_VERSION_LEN), pattern(r"^[A-Za-z0-9.+-]+$"))]
+    pub version: Option<String>,
+    /// How long the whole update takes, between 1 and 300 seconds (default 10)
+    #[schemars(range(min = 1, max = MAX_UPDATE_SECS))]
+    pub duration_secs: Option<u64>,
+    /// Return a job ID at once and run the update in the background, for get_job_status and cancel_job
+    #[cfg(feature = "jobs")]
+    pub background: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct ChangeAtDeviceRequest {
+    /// true to switch the bulb on at its own switch, false to switch it off
+    pub on: Option<bool>,
+    /// Color picked in the bulb's app, as #rrggbb
+    #[schemars(pattern(r"^#[0-9A-Fa-f]{6}$"))]
+    pub color: Option<String>,
+    /// Brightness set with the bulb's dimmer, from 1 to 100 percent
+    #[schemars(range(min = 1, max = 100))]
+    pub brightness: Option<u8>,
+}
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct PowerOutageRequest {
+    /// How long the power stays off, between 1 and 3600 seconds (default 10)
+    #[schemars(range(min = 1, max = MAX_OUTAGE_SECS))]
+    pub duration_secs: Option<u64>,
+}
+
+// A checked update request, from the installed version to the new one
+struct FirmwareUpdate {
+    from: String,
+    to: String,
+    secs: u64,
+}
+
+// Fault injection tools, gated behind the `simulation` feature
+#[tool_router(router = simulation_tools, vis = "pub(super)")]
+impl LightService {
+    #[tool(description = "Inject latency, random failures or an unreachable period into the simulated backend")]
+    async fn set_fault_injection(&self, Parameters(request): Parameters<FaultInjectionRequest>) -> Result<String, ErrorData> {
+        let faults = self.faults.as_ref().ok_or(LightError::FaultInjectionUnsupported)?;
+        if let Some(unreachable_secs) = request.unreachable_secs.filter(|secs| *secs > MAX_UNREACHABLE.as_secs()) {
+            return Err(LightError::InvalidParameter(format!(
+                "unreachable_secs must be at most {}, got {}",
+                MAX_UNREACHABLE.as_secs(),
+                unreachable_secs
+            ))
+            .into());
+        }
+        let mut config = faults.config();
+        if let Some(latency_ms) = request.latency_ms {
+            config.latency_ms = latency_ms;
+        }
+        if let Some(failure_rate) = request.failure_rate {
+            config.failure_rate = failure_rate;
+        }
+        if self.dry_run {
+            config.validate().map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+            let unreachable = request.unreachable_secs.map(|secs| format!(", unreachable for {}s", secs)).unwrap_or_default();
+            return Ok(format!(
+                "{}: would inject {}ms latency and a {:.0}% failure rate{}",
+                self.dry_run_prefix(),
+                config.latency_ms,
+                config.failure_rate * 100.0,
+                unreachable
+            ));
+        }
+        faults.set_config(config).map_err(|e| LightError::InvalidParameter(e.to_string()))?;
+        if let Some(unreachable_secs) = request.unreachable_secs {
+            faults.set_unreachable_for(Duration::from_secs(unreachable_secs));
+        }
+        Ok(Self::describe_faults(faults))
+    }
+
+    #[tool(description = "Remove all injected faults from the simulated backend")]
+    async fn clear_fault_injection(&self) -> Result<String, ErrorData> {
+        let faults = self.faults.as_ref().ok_or(LightError::FaultInjectionUnsupported)?;
+        if self.dry_run {
+            return Ok(format!("{}: would clear every injected fault", self.dry_run_prefix()));
+        }
+        faults.clear();
+        Ok(Self::describe_faults(faults))
+    }
+
+    #[tool(description = "Change the simulated bulb the way someone at the device would, with its wall switch, dimmer or app, and have it push the change to the server as a real bulb with an event stream does; the server logs a power change as by \"device\"")]
+    async fn change_at_device(&self, Parameters(request): Parameters<ChangeAtDeviceRequest>) -> Result<String, ErrorData> {
+        let faults = self.faults.as_ref().ok_or(LightError::FaultInjectionUnsupported)?;
+        let mut reports = Vec::new();
+        if let Some(on) = request.on {
+            reports.push(DeviceReport::Power(if on { PowerState::On } else { PowerState::Off }));
+        }
+        if let Some(color) = &request.color {
+            let color = parse_hex(color).ok_or_else(|| LightError::InvalidParameter(format!("color must be #rrggbb, got '{}'", color)))?;
+            reports.push(DeviceReport::Color(color));
+        }
+        if let Some(percent) = request.brightness {
+            if !(1..=100).contains(&percent) {
+                return Err(LightError::InvalidParameter(format!("brightness must be between 1 and 100, got {}", percent)).into());
+            }
+            reports.push(DeviceReport::Brightness(percent));
+        }
+        if reports.is_empty() {
+            return Err(LightError::InvalidParameter("give at least one of on, color and brightness".to_string()).into());
+        }
+        let changes = reports.iter().map(|report| describe_report(*report)).collect::<Vec<_>>().join(", ");
+        if self.dry_run {
+            return Ok(format!("{}: would change the bulb at the device: {}", self.dry_run_prefix(), changes));
+        }
+        let mut pushed = true;
+        for report in reports {
+            pushed &= faults.change_at_device(report);
+        }
+        Ok(match pushed {
+            true => format!("Changed at the device: {}; the bulb pushed the change to the server", changes),
+            false => format!("Changed at the device: {}; the bulb is offline, so the server finds out only when it next reads it", changes),
+        })
+    }
+
+    #[tool(
+        description = "Cut the simulated bulb's power for a while: it goes dark and answers nothing, then comes back as its power_restore option has it (on, off or last) and tells the server; OUTAGE and RESTORE entries are logged"
+    )]
+    async fn simulate_power_outage(&self, Parameters(request): Parameters<PowerOutageRequest>) -> Result<String, ErrorData> {
+        let faults = self.faults.as_ref().ok_or(LightError::FaultInjectionUnsupported)?;
+        let secs = request.duration_secs.unwrap_or(DEFAULT_OUTAGE_SECS);
+        if !(1..=MAX_OUTAGE_SECS).contains(&secs) {
+            return Err(LightError::InvalidParameter(format!("duration_secs must be between 1 and {}, got {}", MAX_OUTAGE_SECS, secs)).into());
+        }
+        let restore = faults.power_restore();
+        if self.dry_run {
+            let back = restore.power(faults.power());
+            return Ok(format!("{}: would cut the power for {}s, the bulb then coming back {} (power_restore = {})", self.dry_run_prefix(), secs, back.log_action(), restore.label()));
+        }
+        faults.begin_outage(Duration::from_secs(secs)).map_err(|left| LightError::ToolFailed(format!("a power outage is already under way, for another {}s", left.as_secs())))?;
+        let (restoring, light) = (faults.clone(), self.light.clone());
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            let power = restoring.end_outage();
+            let entry = OutageEntry::Ended { secs, action: power.log_action(), restore: restore.label() };
+            if let Err(e) = light.log_outage(entry).await {
+                crate::diagnostic!(Warn, "the end of the simulated power outage went unlogged: {}", e);
+            }
+        });
+        self.light.log_outage(OutageEntry::Began { secs }).await?;
+        Ok(format!(
+            "Cut the power to the simulated bulb for {}s; it answers nothing until the power is back, then comes back {} (power_restore = {})",
+            secs,
+            match restore {
+                PowerRestore::Last => "as it was",
+                PowerRestore::On => "ON",
+                PowerRestore::Off => "OFF",
+            },
+            restore.label()
+        ))
+    }
+
+    #[tool(description = "Run a simulated firmware update (download, flash, reboot), sending progress notifications; the bulb is unavailable while it flashes and reboots")]
+    async fn update_firmware(&self, Parameters(request): Parameters<UpdateFirmwareRequest>, context: RequestContext<RoleServer>) -> Result<String, ErrorData> {
+        #[cfg(feature = "jobs")]
+        if request.background == Some(true) && !self.dry_run {
+            return self.start_firmware_job(request);
+        }
+        let (progress, mut updates) = mpsc::unbounded_channel::<(u32, String)>();
+        // Notifications go out in order on their own task, so a slow client never holds the update up
+        let forwarder = context.meta.get_progress_token().map(|progress_token| {
+            let peer = context.peer.clone();
+            tokio::spawn(async move {
+                while let Some((progress, message)) = updates.recv().await {
+                    let update = ProgressNotificationParam { progress_token: progress_token.clone(), progress, total: Some(100), message: Some(message) };
+                    let _ = peer.notify_progress(update).await;
+                }
+            })
+        });
+        let result = self.execute_firmware_update(request, progress, context.ct).await;
+        if let Some(forwarder) = forwarder {
+            let _ = forwarder.await;
+        }
+        result
+    }
+
+    async fn execute_firmware_update(
+        &self,
+        request: UpdateFirmwareRequest,
+        progress: mpsc::UnboundedSender<(u32, String)>,
+        cancel: CancellationToken,
+    ) -> Result<String, ErrorData> {
+        let (faults, update) = self.check_firmware_update(&request)?;
+        if self.dry_run {
+            if let Some(stage) = faults.firmware_update() {
+                return Err(LightError::FirmwareUpdateInProgress(stage.label()).into());
+            }
+            return Ok(format!(
+                "{}: would update the firmware from {} to {}, the bulb being unavailable for the last {:.1}s",
+                self.dry_run_prefix(),
+                update.from,
+                update.to,
+                update.secs as f64 / 2.0
+            ));
+        }
+        faults.begin_firmware_update().map_err(|stage| LightError::FirmwareUpdateInProgress(stage.label()))?;
+        Ok(flash_firmware(faults, &update, progress, cancel).await)
+    }
+
+    // Claims the bulb for the update before handing back the job's ID, so a second update is refused at once
+    #[cfg(feature = "jobs")]
+    pub(super) fn start_firmware_job(&self, request: UpdateFirmwareRequest) -> Result<String, ErrorData> {
+        let (faults, update) = self.check_firmware_update(&request)?;
+        faults.begin_firmware_update().map_err(|stage| LightError::FirmwareUpdateInProgress(stage.label()))?;
+        let description = format!("Firmware update from {} to {} over {}s", update.from, update.to, update.secs);
+        let flashing = faults.clone();
+        let id = self
+            .jobs
+            .start("firmware_update", description.clone(), |progress, cancel| async move {
+                let report = flash_firmware(&flashing, &update, progress, cancel).await;
+                match flashing.firmware() == update.to {
+                    true => (JobStatus::Completed, report),
+                    false => (JobStatus::Cancelled, report),
+                }
+            })
+            .inspect_err(|_| faults.finish_firmware_update(None))?;
+        Ok(format!("Started {}: {}; get_job_status reports its progress", id, description.to_lowercase()))
+    }
+
+    fn check_firmware_update(&self, request: &UpdateFirmwareRequest) -> Result<(&FaultInjector, FirmwareUpdate), ErrorData> {
+        let faults = self.faults.as_ref().ok_or(LightError::FirmwareUpdateUnsupported)?;
+        let from = faults.firmware();
+        let to = match request.version.as_deref().map(str::trim) {
+            Some(version) if version.is_empty() || version.len() > MAX_FIRMWARE_VERSION_LEN || !version.chars().all(|c| c.is_ascii_alphanumeric() || ".-+".contains(c)) => {
+                return Err(LightError::InvalidParameter(format!(
+                    "version must be 1 to {} letters, digits, '.', '-' or '+', got '{}'",
+                    MAX_FIRMWARE_VERSION_LEN, version
+                ))
+                .into());
+            },
+            Some(version) => version.to_string(),
+            None => next_patch_version(&from),
+        };
+        if to == from {
+            return Err(LightError::InvalidParameter(format!("firmware {} is already installed", from)).into());
+        }
+        let secs = request.duration_secs.unwrap_or(DEFAULT_UPDATE_SECS);
+        if !(1..=MAX_UPDATE_SECS).contains(&secs) {
+            return Err(LightError::InvalidParameter(format!("duration_secs must be between 1 and {}, got {}", MAX_UPDATE_SECS, secs)).into());
+        }
+        Ok((faults, FirmwareUpdate { from, to, secs }))
+    }
+
+    fn describe_faults(faults: &FaultInjector) -> String {
+        let FaultConfig { latency_ms, failure_rate } = faults.config();
+        let unreachable = match faults.unreachable_remaining() {
+            Some(remaining) => format!("for another {}s", remaining.as_secs()),
+            None => "no".to_string(),
+        };
+        format!(
+            "Fault injection:\n\
+            - Latency: {}ms\n\
+            - Failure rate: {:.0}%\n\
+            - Unreachable: {}",
+            latency_ms,
+            failure_rate * 100.0,
+            unreachable,
+        )
+    }
+}
+
+fn describe_report(report: DeviceReport) -> String {
+    match report {
+        DeviceReport::Power(power) => format!("switched {}", power.log_action()),
+        DeviceReport::Color(color) => format!("color {}", color),
+        DeviceReport::Brightness(percent) => format!("brightness {}%", percent),
+    }
+}
+
+// Runs an update already begun on `faults`. Cancelling only stops an update that is still downloading; once
+// flashing has begun it runs to the end.
+async fn flash_firmware(faults: &FaultInjector, update: &FirmwareUpdate, progress: mpsc::UnboundedSender<(u32, String)>, cancel: CancellationToken) -> String {
+    let tick = Duration::from_secs(update.secs) * PROGRESS_STEP / 100;
+    let mut done = 0;
+    for (stage, share) in FIRMWARE_STAGES {
+        faults.set_firmware_stage(stage);
+        let message = match stage {
+            FirmwareStage::Downloading => format!("Downloading firmware {}", update.to),
+            FirmwareStage::Flashing => format!("Flashing firmware {}", update.to),
+            FirmwareStage::Rebooting => "Rebooting".to_string(),
+        };
+        for _ in 0..share / PROGRESS_STEP {
+            let _ = progress.send((done, message.clone()));
+            if stage.offline() {
+                tokio::time::sleep(tick).await;
+            } else {
+                tokio::select! {
+                    _ = tokio::time::sleep(tick) => {},
+                    _ = cancel.cancelled() => {
+                        faults.finish_firmware_update(None);
+                        return format!("Firmware update cancelled while downloading; the bulb still runs {}", update.from);
+                    },
+                }
+            }
+            done += PROGRESS_STEP;
+        }
+    }
+    faults.finish_firmware_update(Some(&update.to));
+    let _ = progress.send((100, format!("Firmware {} installed", update.to)));
+    format!("Firmware updated from {} to {} in {}s", update.from, update.to, update.secs)
+}
+
+// "1.0.9" becomes "1.0.10"; a version not ending in a number gets ".1" added
+fn next_patch_version(version: &str) -> String {
+    match version.rsplit_once('.').map(|(head, last)| (head, last.parse::<u64>())) {
+        Some((head, Ok(patch))) => format!("{}.{}", head, patch + 1),
+        _ => format!("{}.1", version),
+    }
+}
+
+// These drive the bulb through the core tools
+#[cfg(all(test, feature = "core"))]
+mod tests {
+    use super::*;
+    use crate::logger::InMemoryLogger;
+    use crate::service::tests::UnreachableBackend;
+
+    #[tokio::test]
+    async fn test_fault_injection_toggles_at_runtime() {
+        let service = LightService::new_with_in_memory_logger();
+        let request = FaultInjectionRequest { latency_ms: None, failure_rate: Some(1.0), unreachable_secs: None };
+        let report = service.set_fault_injection(Parameters(request)).await.unwrap();
+        assert!(report.contains("Failure rate: 100%"));
+
+        let error = service.turn_on_lightbulb(Parameters(Default::default())).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "BACKEND_UNREACHABLE");
+
+        service.clear_fault_injection().await.unwrap();
+        assert!(service.turn_on_lightbulb(Parameters(Default::default())).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection_rejects_invalid_rate() {
+        let service = LightService::new_with_in_memory_logger();
+        let request = FaultInjectionRequest { latency_ms: None, failure_rate: Some(2.0), unreachable_secs: None };
+        let error = service.set_fault_injection(Parameters(request)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        let request = FaultInjectionRequest { latency_ms: None, failure_rate: None, unreachable_secs: Some(u64::MAX) };
+        let error = service.set_fault_injection(Parameters(request)).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_firmware_update_reports_progress_and_takes_the_bulb_offline() {
+        let service = LightService::new_with_in_memory_logger();
+        let (progress, mut updates) = mpsc::unbounded_channel();
+        let request = UpdateFirmwareRequest { version: None, duration_secs: Some(10), ..Default::default() };
+        let update = tokio::spawn({
+            let service = service.clone();
+            async move { service.execute_firmware_update(request, progress, CancellationToken::new()).await }
+        });
+
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        let error = service.turn_on_lightbulb(Parameters(Default::default())).await.unwrap_err();
+        assert!(error.message.contains("simulated bulb is flashing a firmware update"), "{}", error.message);
+        let again = UpdateFirmwareRequest { version: Some("2.0.0".to_string()), ..Default::default() };
+        let error = service.execute_firmware_update(again, mpsc::unbounded_channel().0, CancellationToken::new()).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "FIRMWARE_UPDATE_IN_PROGRESS");
+
+        assert_eq!(update.await.unwrap().unwrap(), "Firmware updated from 1.0.0 to 1.0.1 in 10s");
+        let mut reports = Vec::new();
+        while let Ok(report) = updates.try_recv() {
+            reports.push(report);
+        }
+        assert_eq!(reports.len(), 21);
+        assert_eq!(reports[0], (0, "Downloading firmware 1.0.1".to_string()));
+        assert_eq!(reports[10], (50, "Flashing firmware 1.0.1".to_string()));
+        assert_eq!(reports[16], (80, "Rebooting".to_string()));
+        assert_eq!(reports[20], (100, "Firmware 1.0.1 installed".to_string()));
+        assert!(service.turn_on_lightbulb(Parameters(Default::default())).await.is_ok());
+        assert_eq!(service.bulb_metadata()["firmware"], "1.0.1");
+        assert_eq!(next_patch_version("2.3.9"), "2.3.10");
+        assert_eq!(next_patch_version("beta"), "beta.1");
+    }
+
+    #[tokio::test]
+    async fn test_changes_at_the_device_reach_the_server() {
+        let service = LightService::new_with_in_memory_logger();
+        let mut events = service.light.subscribe();
+        let request = ChangeAtDeviceRequest { on: Some(true), color: Some("#ff8000".to_string()), brightness: None };
+        let answer = service.change_at_device(Parameters(request)).await.unwrap();
+        assert_eq!(answer, "Changed at the device: switched ON, color #ff8000; the bulb pushed the change to the server");
+        events.recv().await.unwrap();
+        events.recv().await.unwrap();
+        let snapshot = service.light.snapshot().await.unwrap();
+        assert_eq!((snapshot.power, snapshot.color.to_string().as_str()), (Some(PowerState::On), "#ff8000"));
+        assert!(service.read_log_content().await.unwrap().contains("ON by device"));
+
+        let error = service.change_at_device(Parameters(ChangeAtDeviceRequest::default())).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        service.set_fault_injection(Parameters(FaultInjectionRequest { latency_ms: None, failure_rate: None, unreachable_secs: Some(60) })).await.unwrap();
+        let request = ChangeAtDeviceRequest { on: Some(false), ..Default::default() };
+        assert!(service.change_at_device(Parameters(request)).await.unwrap().ends_with("the server finds out only when it next reads it"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_power_outage_takes_the_bulb_offline_and_restores_it() {
+        let service = LightService::new_with_in_memory_logger();
+        let answer = service.simulate_power_outage(Parameters(PowerOutageRequest { duration_secs: Some(30) })).await.unwrap();
+        assert_eq!(answer, "Cut the power to the simulated bulb for 30s; it answers nothing until the power is back, then comes back ON (power_restore = on)");
+        let error = service.turn_on_lightbulb(Parameters(Default::default())).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "BACKEND_UNREACHABLE");
+        let error = service.simulate_power_outage(Parameters(PowerOutageRequest::default())).await.unwrap_err();
+        assert!(error.message.contains("a power outage is already under way"), "{}", error.message);
+        let error = service.simulate_power_outage(Parameters(PowerOutageRequest { duration_secs: Some(0) })).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+
+        tokio::time::sleep(Duration::from_secs(31)).await;
+        assert_eq!(service.light.snapshot().await.unwrap().power, Some(PowerState::On));
+        let log = service.read_log_content().await.unwrap();
+        assert!(log.contains("Lightbulb OUTAGE (power cut for 30s)"), "{}", log);
+        assert!(log.contains("Lightbulb RESTORE ON (power back after 30s, power_restore = on)"), "{}", log);
+        assert!(log.contains("ON by device"), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn test_fault_injection_requires_simulated_backend() {
+        let service = LightService::new_with_logger_and_backend(Box::new(InMemoryLogger::new()), Box::new(UnreachableBackend));
+        let error = service.clear_fault_injection().await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "FAULT_INJECTION_UNSUPPORTED");
+        let request = UpdateFirmwareRequest::default();
+        let error = service.execute_firmware_update(request, mpsc::unbounded_channel().0, CancellationToken::new()).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "FIRMWARE_UPDATE_UNSUPPORTED");
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use rmcp::model::{LoggingLevel, LoggingMessageNotificationParam, ResourceUpdatedNotificationParam};
+use rmcp::{Peer, RoleServer};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::clock::format_duration;
+use crate::config::MAIN_BULB;
+use crate::events::{EventKind, LightEvent};
+
+const LOGGER_NAME: &str = "lightbulb";
+
+// The least severe level a session asked to hear about with logging/setLevel
+#[derive(Debug, Clone)]
+pub(super) struct SessionLogLevel(Arc<Mutex<LoggingLevel>>);
+
+impl SessionLogLevel {
+    pub(super) fn set(&self, level: LoggingLevel) {
+        *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = level;
+    }
+
+    fn allows(&self, level: LoggingLevel) -> bool {
+        level as u8 >= *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) as u8
+    }
+}
+
+impl Default for SessionLogLevel {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(LoggingLevel::Info)))
+    }
+}
+
+// The resources a session subscribed to with resources/subscribe, each with the URI it follows: a bulb's own
+// resources by the bulb's id, whichever of its names the session gave, and any other by itself
+#[derive(Debug, Clone, Default)]
+pub(super) struct ResourceSubscriptions(Arc<Mutex<BTreeMap<String, String>>>);
+
+impl ResourceSubscriptions {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, String>> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub(super) fn subscribe(&self, uri: String, follows: String) {
+        self.lock().insert(uri, follows);
+    }
+
+    pub(super) fn unsubscribe(&self, uri: &str) {
+        self.lock().remove(uri);
+    }
+
+    // The subscribed URIs whose followed URI is `updated`
+    fn matching(&self, updated: impl Fn(&str) -> bool) -> Vec<String> {
+        self.lock().iter().filter(|(_, follows)| updated(follows)).map(|(uri, _)| uri.clone()).collect()
+    }
+}
+
+// The next change to one of the bulbs beside the server's own, or never when there are none to hear of
+async fn next_bulb_event(events: &mut Option<broadcast::Receiver<(String, LightEvent)>>) -> Result<(String, LightEvent), RecvError> {
+    match events {
+        Some(events) => events.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+// Sends the client a logging notification for every change someone else makes to the server's own bulb, and a
+// resource-updated one for each subscribed resource that a change to any bulb, or an update sent on `updates`,
+// touches, until the session closes
+pub(super) fn spawn_change_notifier(
+    peer: Peer<RoleServer>,
+    mut events: broadcast::Receiver<LightEvent>,
+    mut bulb_events: Option<broadcast::Receiver<(String, LightEvent)>>,
+    mut updates: broadcast::Receiver<String>,
+    client: Option<String>,
+    level: SessionLogLevel,
+    subscriptions: ResourceSubscriptions,
+) {
+    tokio::spawn(async move {
+        let mut updates_open = true;
+        loop {
+            let received = tokio::select! {
+                received = events.recv() => received,
+                update = updates.recv(), if updates_open => {
+                    match update {
+                        Ok(uri) => {
+                            for uri in subscriptions.matching(|subscribed| subscribed == uri) {
+                                if peer.notify_resource_updated(ResourceUpdatedNotificationParam { uri }).await.is_err() {
+                                    return;
+                                }
+                            }
+                        },
+                        Err(RecvError::Lagged(_)) => {},
+                        Err(RecvError::Closed) => updates_open = false,
+                    }
+                    continue;
+                },
+                bulb_event = next_bulb_event(&mut bulb_events) => {
+                    match bulb_event {
+                        Ok((bulb, _)) => {
+                            for uri in subscriptions.matching(|follows| super::follows_light(follows, &bulb)) {
+                                if peer.notify_resource_updated(ResourceUpdatedNotificationParam { uri }).await.is_err() {
+                                    return;
+                                }
+                            }
+                        },
+                        Err(RecvError::Lagged(_)) => {},
+                        Err(RecvError::Closed) => bulb_events = None,
+                    }
+                    continue;
+                },
+            };
+            let event = match received {
+                Ok(event) => event,
+                // Later events carry the full state, so a missed one does not leave the client behind
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            };
+            for uri in subscriptions.matching(|follows| super::follows_light(follows, MAIN_BULB)) {
+                if peer.notify_resource_updated(ResourceUpdatedNotificationParam { uri }).await.is_err() {
+                    return;
+                }
+            }
+            let Some(notification) = change_notification(&event, client.as_deref()) else {
+                continue;
+            };
+            if level.allows(notification.level) && peer.notify_logging_message(notification).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn change_notification(event: &LightEvent, client: Option<&str>) -> Option<LoggingMessageNotificationParam> {
+    // A client learns about its own changes from the tool result; a warning about the bulb is for everyone
+    if event.kind != EventKind::OnTooLong && client.is_some() && event.state.changed_by.as_deref() == client {
+        return None;
+    }
+    let by = event.state.changed_by.as_deref().map(|who| format!(" by {}", who)).unwrap_or_default();
+    let (level, message) = match event.kind {
+        EventKind::StateChanged => (
+            LoggingLevel::Info,
+            format!("The lightbulb changed from {} to {}{} (version {})", event.from, event.to, by, event.state.version),
+        ),
+        EventKind::BackendUnreachable => {
+            (LoggingLevel::Warning, format!("The lightbulb could not be reached during {}{}", event.action, by))
+        },
+        EventKind::OnTooLong => (
+            LoggingLevel::Warning,
+            format!("The lightbulb has been on for {}, longer than the safety limit", format_duration(event.state.in_state_secs.unwrap_or_default())),
+        ),
+    };
+    Some(LoggingMessageNotificationParam {
+        level,
+        logger: Some(LOGGER_NAME.to_string()),
+        data: serde_json::json!({ "message": message, "event": event }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::state::StateMachine;
+
+    fn event(kind: EventKind, changed_by: Option<&str>) -> LightEvent {
+        let mut state = StateMachine::new().snapshot();
+        state.version = 2;
+        state.changed_by = changed_by.map(String::from);
+        LightEvent { sequence: 1, at: Utc::now(), kind, action: "turn_on".to_string(), from: "OFF", to: "ON", state, log_sequence: None }
+    }
+
+    #[test]
+    fn test_only_other_clients_changes_are_notified() {
+        assert!(change_notification(&event(EventKind::StateChanged, Some("agent-a")), Some("agent-a")).is_none());
+
+        let notification = change_notification(&event(EventKind::StateChanged, Some("agent-b")), Some("agent-a")).unwrap();
+        assert_eq!(notification.level, LoggingLevel::Info);
+        assert_eq!(notification.data["message"], "The lightbulb changed from OFF to ON by agent-b (version 2)");
+        assert_eq!(notification.data["event"]["state"]["version"], 2);
+
+        let notification = change_notification(&event(EventKind::BackendUnreachable, None), Some("agent-a")).unwrap();
+        assert_eq!(notification.data["message"], "The lightbulb could not be reached during turn_on");
+    }
+
+    #[test]
+    fn test_subscriptions_match_the_resources_a_change_touches() {
+        let subscriptions = ResourceSubscriptions::default();
+        let follows = |bulb: &'static str| move |uri: &str| super::super::follows_light(uri, bulb);
+        for uri in ["lightbulb://log/tail", "lightbulb://colors", "lightbulb://schedules", "lightbulb://main/state"] {
+            subscriptions.subscribe(uri.to_string(), uri.to_string());
+        }
+        subscriptions.subscribe("lightbulb://Desk%20Lamp/log".to_string(), "lightbulb://desk/log".to_string());
+        subscriptions.subscribe("lightbulb://desk/state".to_string(), "lightbulb://desk/state".to_string());
+        assert_eq!(subscriptions.matching(follows(MAIN_BULB)), ["lightbulb://log/tail", "lightbulb://main/state"]);
+        assert_eq!(subscriptions.matching(follows("desk")), ["lightbulb://Desk%20Lamp/log", "lightbulb://desk/state"]);
+        assert!(subscriptions.matching(follows("hall")).is_empty());
+        subscriptions.unsubscribe("lightbulb://log/tail");
+        assert_eq!(subscriptions.matching(follows(MAIN_BULB)), ["lightbulb://main/state"]);
+    }
+
+    #[test]
+    fn test_session_level_filters_notifications() {
+        let level = SessionLogLevel::default();
+        assert!(level.allows(LoggingLevel::Info));
+        level.set(LoggingLevel::Warning);
+        assert!(!level.allows(LoggingLevel::Info));
+        assert!(level.allows(LoggingLevel::Warning));
+    }
+}
@@ -0,0 +1,47 @@
+// Waits for the first request to stop, returning its name for the log: SIGTERM from a service manager or
+// `kill`, SIGINT or Ctrl-C from a terminal, or on Windows a console closing, logging off or shutting down
+pub async fn signal() -> std::io::Result<&'static str> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        tokio::select! {
+            _ = terminate.recv() => Ok("SIGTERM"),
+            _ = interrupt.recv() => Ok("SIGINT"),
+        }
+    }
+    #[cfg(windows)]
+    {
+        use tokio::signal::windows;
+
+        let mut ctrl_c = windows::ctrl_c()?;
+        let mut ctrl_break = windows::ctrl_break()?;
+        let mut ctrl_close = windows::ctrl_close()?;
+        let mut ctrl_logoff = windows::ctrl_logoff()?;
+        let mut ctrl_shutdown = windows::ctrl_shutdown()?;
+        tokio::select! {
+            _ = ctrl_c.recv() => Ok("CTRL_C"),
+            _ = ctrl_break.recv() => Ok("CTRL_BREAK"),
+            _ = ctrl_close.recv() => Ok("CTRL_CLOSE"),
+            _ = ctrl_logoff.recv() => Ok("CTRL_LOGOFF"),
+            _ = ctrl_shutdown.recv() => Ok("CTRL_SHUTDOWN"),
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        tokio::signal::ctrl_c().await?;
+        Ok("CTRL_C")
+    }
+}
+
+// After the first signal, a second one exits at once, for a shutdown stuck behind a hung backend
+pub fn exit_on_second_signal() {
+    tokio::spawn(async {
+        if let Ok(name) = signal().await {
+            crate::diagnostic!(Warn, "{} during shutdown; exiting without finishing it", name);
+            std::process::exit(130);
+        }
+    });
+}
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{Value, json};
+use tokio::time::Instant;
+
+use crate::state::Transition;
+use crate::testing::TestClient;
+use crate::{LightServiceBuilder, SessionFactory};
+
+pub const DEFAULT_SOAK_SESSIONS: usize = 4;
+pub const DEFAULT_MAX_MEMORY_GROWTH: u64 = 64 * 1024 * 1024;
+// Only the first violations are kept; the rest are counted
+const MAX_REPORTED_VIOLATIONS: usize = 20;
+const PAGE_SIZE: u64 = 4096;
+const MIB: f64 = 1024.0 * 1024.0;
+const COLORS: [&str; 4] = ["red", "warm white", "skyblue", "#ff8800"];
+
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    pub duration: Duration,
+    pub sessions: usize,
+    // Seeds each session's call sequence, so a failing run can be repeated
+    pub seed: u64,
+    // Resident memory may grow by this many bytes between the end of the warm-up and the end of the run
+    pub max_memory_growth: u64,
+}
+
+impl SoakConfig {
+    pub fn new(duration: Duration) -> Self {
+        Self { duration, sessions: DEFAULT_SOAK_SESSIONS, seed: rand::random(), max_memory_growth: DEFAULT_MAX_MEMORY_GROWTH }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SoakReport {
+    pub calls: u64,
+    // Calls the server rejected with a LightError, by error code
+    pub rejections: BTreeMap<String, u64>,
+    pub log_entries: usize,
+    pub completed_changes: usize,
+    // Resident memory after the warm-up and at the end, where the platform reports it
+    pub memory: Option<(u64, u64)>,
+    pub violations: Vec<String>,
+    pub violation_count: usize,
+}
+
+impl SoakReport {
+    pub fn passed(&self) -> bool {
+        self.violation_count == 0
+    }
+
+    fn violation(&mut self, violation: String) {
+        self.violation_count += 1;
+        if self.violations.len() < MAX_REPORTED_VIOLATIONS {
+            self.violations.push(violation);
+        }
+    }
+
+    fn merge(&mut self, other: SoakReport) {
+        self.calls += other.calls;
+        for (code, count) in other.rejections {
+            *self.rejections.entry(code).or_default() += count;
+        }
+        self.violation_count += other.violation_count - other.violations.len();
+        for violation in other.violations {
+            self.violation(violation);
+        }
+    }
+
+    pub fn describe(&self, config: &SoakConfig) -> String {
+        let rejected: u64 = self.rejections.values().sum();
+        let mut lines = vec![format!(
+            "Soaked for {}s with {} sessions (seed {}): {} calls, {} rejected",
+            config.duration.as_secs(),
+            config.sessions,
+            config.seed,
+            self.calls,
+            rejected
+        )];
+        if !self.rejections.is_empty() {
+            let codes: Vec<String> = self.rejections.iter().map(|(code, count)| format!("{} {}", code, count)).collect();
+            lines.push(format!("Rejections: {}", codes.join(", ")));
+        }
+        lines.push(format!("Log: {} entries for {} completed power changes", self.log_entries, self.completed_changes));
+        if let Some((baseline, end)) = self.memory {
+            lines.push(format!("Memory: {} -> {} ({:+.1} MiB)", mebibytes(baseline), mebibytes(end), (end as f64 - baseline as f64) / MIB));
+        }
+        if self.passed() {
+            lines.push("No invariant violations".to_string());
+        } else {
+            lines.push(format!("{} invariant violations:", self.violation_count));
+            lines.extend(self.violations.iter().map(|violation| format!("- {}", violation)));
+        }
+        lines.join("\n")
+    }
+}
+
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / MIB)
+}
+
+// Resident set size from /proc, where there is one
+fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * PAGE_SIZE)
+}
+
+// A weighted random tool call, limited to tools the service offers
+fn next_call(rng: &mut StdRng, tools: &[String]) -> (&'static str, Value) {
+    loop {
+        let (tool, arguments) = match rng.random_range(0..19) {
+            0..=2 => ("turn_on_lightbulb", json!({})),
+            3..=5 => ("turn_off_lightbulb", json!({})),
+            6 => ("lock_lightbulb", json!({})),
+            7 => ("unlock_lightbulb", json!({})),
+            8 => ("undo_last_change", json!({})),
+            9 => ("redo_change", json!({})),
+            10 => ("set_color_by_name", json!({ "name": COLORS[rng.random_range(0..COLORS.len())] })),
+            11 => ("set_fault_injection", json!({ "failure_rate": if rng.random_bool(0.5) { 0.2 } else { 0.0 } })),
+            12..=14 => ("get_lightbulb_status", json!({})),
+            15 | 16 => ("get_lightbulb_state", json!({})),
+            _ => ("get_statistics", json!({ "metrics": ["counts", "on_time"] })),
+        };
+        if tools.iter().any(|name| name == tool) {
+            return (tool, arguments);
+        }
+    }
+}
+
+fn logged_changes(log: &str) -> usize {
+    log.lines().filter(|line| line.contains("] Lightbulb turned ")).count()
+}
+
+// One session calling tools until `deadline`, checking the state it sees after every call
+async fn run_session(client: &TestClient, tools: &[String], mut rng: StdRng, deadline: Instant) -> SoakReport {
+    let mut report = SoakReport::default();
+    let mut last_version = 0;
+    while Instant::now() < deadline {
+        let (tool, arguments) = next_call(&mut rng, tools);
+        report.calls += 1;
+        if let Err(error) = client.call(tool, arguments).await {
+            match error.data.as_ref().and_then(|data| data["code"].as_str()) {
+                Some(code) => *report.rejections.entry(code.to_string()).or_default() += 1,
+                None => report.violation(format!("{} failed without a LightError: {}", tool, error.message)),
+            }
+        }
+        // Nothing reads the change notifications, so they are dropped rather than left to pile up
+        client.drain_log_messages().await;
+
+        let state = match client.call_json("get_lightbulb_state", json!({})).await {
+            Ok(state) => state,
+            Err(error) => {
+                report.violation(format!("get_lightbulb_state failed after {}: {}", tool, error.message));
+                continue;
+            },
+        };
+        let version = state["version"].as_u64().unwrap_or_default();
+        if version < last_version {
+            report.violation(format!("{} moved the version back from {} to {}", tool, last_version, version));
+        }
+        if state["locked"].as_bool() != Some(state["state"] == "LOCKED") {
+            report.violation(format!("after {} the state is {} but locked is {}", tool, state["state"], state["locked"]));
+        }
+        last_version = version;
+    }
+    report
+}
+
+// Hammers a service with random tool calls from several sessions for `config.duration`, checking that every
+// call answers with a result or a LightError, that the state stays consistent, that the log gains one entry
+// per completed power change and that memory stops growing once warmed up
+pub async fn soak(builder: LightServiceBuilder, config: &SoakConfig) -> anyhow::Result<SoakReport> {
+    let completed = Arc::new(AtomicUsize::new(0));
+    let counter = completed.clone();
+    let service = builder
+        .transition_hook(move |change| {
+            if change.transition == Transition::Complete {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .build();
+    let sessions = SessionFactory::Shared(service);
+    let mut clients = Vec::new();
+    for i in 0..config.sessions.max(1) {
+        clients.push(TestClient::connect_as(sessions.create(), &format!("soak-{}", i + 1)).await?);
+    }
+    let tools = clients[0].tools().await?;
+    if !tools.iter().any(|tool| tool == "get_lightbulb_state") {
+        anyhow::bail!("Soaking needs the get_lightbulb_state tool, which this service does not offer");
+    }
+    let log_before = logged_changes(&clients[0].read("lightbulb://log.txt").await?);
+
+    let started = Instant::now();
+    let deadline = started + config.duration;
+    let runs: Vec<_> = clients
+        .into_iter()
+        .enumerate()
+        .map(|(i, client)| {
+            let (tools, rng) = (tools.clone(), StdRng::seed_from_u64(config.seed.wrapping_add(i as u64)));
+            tokio::spawn(async move {
+                let report = run_session(&client, &tools, rng, deadline).await;
+                (client, report)
+            })
+        })
+        .collect();
+    tokio::time::sleep_until(started + config.duration / 10).await;
+    let baseline = resident_memory();
+
+    let mut report = SoakReport::default();
+    let mut clients = Vec::new();
+    for run in runs {
+        let (client, session) = run.await?;
+        clients.push(client);
+        report.merge(session);
+    }
+    // Leave the deployment's bulb answering normally
+    if tools.iter().any(|tool| tool == "set_fault_injection") {
+        let _ = clients[0].call("set_fault_injection", json!({ "failure_rate": 0.0 })).await;
+    }
+    report.log_entries = logged_changes(&clients[0].read("lightbulb://log.txt").await?).saturating_sub(log_before);
+    report.completed_changes = completed.load(Ordering::SeqCst);
+    if report.log_entries != report.completed_changes {
+        report.violation(format!(
+            "the log gained {} entries for {} completed power changes",
+            report.log_entries, report.completed_changes
+        ));
+    }
+    if let (Some(baseline), Some(end)) = (baseline, resident_memory()) {
+        report.memory = Some((baseline, end));
+        if end.saturating_sub(baseline) > config.max_memory_growth {
+            report.violation(format!(
+                "resident memory grew from {} to {}, more than the {} allowed",
+                mebibytes(baseline),
+                mebibytes(end),
+                mebibytes(config.max_memory_growth)
+            ));
+        }
+    }
+    for client in clients {
+        client.close().await?;
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "core"))]
+mod tests {
+    use super::*;
+    use crate::LightService;
+    use crate::logger::InMemoryLogger;
+
+    fn config(seed: u64) -> SoakConfig {
+        SoakConfig { duration: Duration::from_millis(300), sessions: 3, seed, max_memory_growth: DEFAULT_MAX_MEMORY_GROWTH }
+    }
+
+    #[tokio::test]
+    async fn test_short_soak_passes() {
+        let builder = LightService::builder().logger(Box::new(InMemoryLogger::new()));
+        let report = soak(builder, &config(7)).await.unwrap();
+        assert!(report.passed(), "{}", report.describe(&config(7)));
+        assert!(report.calls > 0);
+        assert_eq!(report.log_entries, report.completed_changes);
+    }
+
+    #[test]
+    fn test_report_keeps_the_first_violations() {
+        let mut report = SoakReport::default();
+        for i in 0..MAX_REPORTED_VIOLATIONS + 5 {
+            report.violation(format!("violation {}", i));
+        }
+        let mut merged = SoakReport::default();
+        merged.merge(report);
+        assert_eq!((merged.violations.len(), merged.violation_count), (MAX_REPORTED_VIOLATIONS, MAX_REPORTED_VIOLATIONS + 5));
+        assert!(merged.describe(&config(1)).contains("25 invariant violations:\n- violation 0\n"));
+    }
+
+    #[test]
+    fn test_calls_stay_within_offered_tools() {
+        let tools = vec!["turn_on_lightbulb".to_string(), "get_lightbulb_state".to_string()];
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let (tool, _) = next_call(&mut rng, &tools);
+            assert!(tools.iter().any(|name| name == tool));
+        }
+    }
+}
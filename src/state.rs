@@ -0,0 +1,519 @@
+use std::fmt;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::clock::{SharedClock, system_clock};
+use crate::model::{Color, PowerState};
+
+pub const DEFAULT_BRIGHTNESS: u8 = 100;
+
+// Every state the bulb can be in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightState {
+    Off,
+    On { brightness: u8, color: Color },
+    Transitioning { from: Box<LightState>, target: PowerState },
+    Locked { inner: Box<LightState> },
+    Unreachable,
+}
+
+impl LightState {
+    // Power the bulb is known to have, if any
+    pub fn power(&self) -> Option<PowerState> {
+        match self {
+            LightState::Off => Some(PowerState::Off),
+            LightState::On { .. } => Some(PowerState::On),
+            LightState::Transitioning { from, .. } => from.power(),
+            LightState::Locked { inner } => inner.power(),
+            LightState::Unreachable => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LightState::Off => "OFF",
+            LightState::On { .. } => "ON",
+            LightState::Transitioning { .. } => "TRANSITIONING",
+            LightState::Locked { .. } => "LOCKED",
+            LightState::Unreachable => "UNREACHABLE",
+        }
+    }
+}
+
+// Events that move the bulb between states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Begin(PowerState),
+    Complete,
+    Fail,
+    Lock,
+    Unlock,
+    SetColor(Color),
+    SetBrightness(u8),
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transition::Begin(PowerState::On) => write!(f, "turn on"),
+            Transition::Begin(PowerState::Off) => write!(f, "turn off"),
+            Transition::Complete => write!(f, "complete a transition"),
+            Transition::Fail => write!(f, "fail a transition"),
+            Transition::Lock => write!(f, "lock"),
+            Transition::Unlock => write!(f, "unlock"),
+            Transition::SetColor(_) => write!(f, "change color"),
+            Transition::SetBrightness(_) => write!(f, "change brightness"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Cannot {transition} while the lightbulb is {}", .state.to_lowercase())]
+pub struct TransitionError {
+    pub state: &'static str,
+    pub transition: Transition,
+}
+
+// Machine-readable view of everything the bulb is doing
+#[derive(Debug, Clone, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct StateSnapshot {
+    pub state: &'static str,
+    pub power: Option<PowerState>,
+    // Brightness and color the bulb shows (or will show) when lit
+    pub brightness: u8,
+    pub color: Color,
+    pub active_effect: Option<String>,
+    pub timers: Vec<String>,
+    pub locked: bool,
+    pub last_changed: Option<DateTime<Utc>>,
+    // When the bulb's power last changed or it became unreachable; locking and color changes leave it alone
+    pub state_since: Option<DateTime<Utc>>,
+    pub in_state_secs: Option<i64>,
+    // Increases with every transition, so clients can detect changes made by someone else
+    pub version: u64,
+    // The client, effect or integration behind the last transition, when known
+    pub changed_by: Option<String>,
+    // The command behind the last transition, e.g. "turn_on", "undo" or "lock"
+    pub cause: Option<String>,
+}
+
+impl StateSnapshot {
+    // The snapshot as it reads at `now`, for one taken earlier
+    pub fn as_of(mut self, now: DateTime<Utc>) -> Self {
+        self.in_state_secs = self.state_since.map(|since| (now - since).num_seconds().max(0));
+        self
+    }
+}
+
+// A validated transition, passed to hooks after it has been applied
+pub struct StateChange<'a> {
+    pub from: &'a LightState,
+    pub transition: Transition,
+    pub to: &'a LightState,
+}
+
+pub type TransitionHook = Box<dyn Fn(&StateChange) + Send + Sync>;
+
+// Owns the current state, rejecting transitions that are not valid from it
+pub struct StateMachine {
+    state: LightState,
+    last_on: (u8, Color),
+    last_changed: Option<DateTime<Utc>>,
+    state_since: Option<DateTime<Utc>>,
+    last_toggled: Option<DateTime<Utc>>,
+    min_toggle_interval: Duration,
+    version: u64,
+    acting: Option<String>,
+    acting_cause: Option<String>,
+    changed_by: Option<String>,
+    cause: Option<String>,
+    clock: SharedClock,
+    hooks: Vec<TransitionHook>,
+}
+
+impl StateMachine {
+    pub fn new() -> Self {
+        Self::with_clock(system_clock())
+    }
+
+    // Times every transition with `clock`, which the actor also uses for events and log entries
+    pub fn with_clock(clock: SharedClock) -> Self {
+        Self {
+            state: LightState::Off,
+            last_on: (DEFAULT_BRIGHTNESS, Color::WHITE),
+            last_changed: None,
+            state_since: None,
+            last_toggled: None,
+            min_toggle_interval: Duration::ZERO,
+            version: 0,
+            acting: None,
+            acting_cause: None,
+            changed_by: None,
+            cause: None,
+            clock,
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn state(&self) -> &LightState {
+        &self.state
+    }
+
+    pub fn clock(&self) -> &SharedClock {
+        &self.clock
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // Transitions applied from now on are attributed to `who`, running the command `cause`
+    pub fn attribute_to(&mut self, who: Option<String>, cause: impl Into<String>) {
+        self.acting = who;
+        self.acting_cause = Some(cause.into());
+    }
+
+    pub fn acting(&self) -> Option<&str> {
+        self.acting.as_deref()
+    }
+
+    // The machine does not know about effects or timers, so callers fill those in
+    pub fn snapshot(&self) -> StateSnapshot {
+        let (brightness, color) = self.last_on;
+        StateSnapshot {
+            state: self.state.label(),
+            power: self.state.power(),
+            brightness,
+            color,
+            active_effect: None,
+            timers: Vec::new(),
+            locked: matches!(self.state, LightState::Locked { .. }),
+            last_changed: self.last_changed,
+            state_since: self.state_since,
+            in_state_secs: None,
+            version: self.version,
+            changed_by: self.changed_by.clone(),
+            cause: self.cause.clone(),
+        }
+        .as_of(self.clock.now())
+    }
+
+    // A copy without the hooks, to try transitions on without anyone hearing of them
+    pub fn scratch(&self) -> StateMachine {
+        StateMachine {
+            state: self.state.clone(),
+            last_on: self.last_on,
+            last_changed: self.last_changed,
+            state_since: self.state_since,
+            last_toggled: self.last_toggled,
+            min_toggle_interval: self.min_toggle_interval,
+            version: self.version,
+            acting: self.acting.clone(),
+            acting_cause: self.acting_cause.clone(),
+            changed_by: self.changed_by.clone(),
+            cause: self.cause.clone(),
+            clock: self.clock.clone(),
+            hooks: Vec::new(),
+        }
+    }
+
+    // Back to the state of a new machine, except the version, which carries on so that versions read before
+    // the reset no longer match. Hooks are not run, since no transition took place.
+    pub fn reset(&mut self) {
+        self.state = LightState::Off;
+        self.last_on = (DEFAULT_BRIGHTNESS, Color::WHITE);
+        self.last_changed = None;
+        self.state_since = None;
+        self.changed_by = None;
+        self.cause = None;
+        self.version += 1;
+    }
+
+    // Protects the bulb's relay from being switched more often than once every `interval`; zero allows any rate
+    pub fn set_min_toggle_interval(&mut self, interval: Duration) {
+        self.min_toggle_interval = interval;
+    }
+
+    pub fn min_toggle_interval(&self) -> Duration {
+        self.min_toggle_interval
+    }
+
+    // How much longer the bulb must be left before its power is switched again, if the last switch was too
+    // recent. Only completed switches count, so a failed attempt can be retried at once.
+    pub fn toggle_wait(&self) -> Option<Duration> {
+        let elapsed = (self.clock.now() - self.last_toggled?).to_std().unwrap_or_default();
+        self.min_toggle_interval.checked_sub(elapsed).filter(|wait| !wait.is_zero())
+    }
+
+    // Registers a hook run after every successful transition
+    pub fn add_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&StateChange) + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn apply(&mut self, transition: Transition) -> Result<&LightState, TransitionError> {
+        let next = self.next_state(transition).ok_or(TransitionError {
+            state: self.state.label(),
+            transition,
+        })?;
+        if let LightState::On { brightness, color } = &next {
+            self.last_on = (*brightness, *color);
+        }
+        match transition {
+            Transition::SetColor(color) => self.last_on.1 = color,
+            Transition::SetBrightness(brightness) => self.last_on.0 = brightness,
+            _ => {},
+        }
+        let now = self.clock.now();
+        self.last_changed = Some(now);
+        if next.power() != self.state.power() || matches!(next, LightState::Unreachable) {
+            self.state_since = Some(now);
+        }
+        if transition == Transition::Complete {
+            self.last_toggled = Some(now);
+        }
+        self.version += 1;
+        self.changed_by = self.acting.clone();
+        self.cause = self.acting_cause.clone();
+        let previous = std::mem::replace(&mut self.state, next);
+        let change = StateChange { from: &previous, transition, to: &self.state };
+        for hook in &self.hooks {
+            hook(&change);
+        }
+        Ok(&self.state)
+    }
+
+    // Checks a transition is valid from the current state without applying it
+    pub fn check(&self, transition: Transition) -> Result<(), TransitionError> {
+        match self.next_state(transition) {
+            Some(_) => Ok(()),
+            None => Err(TransitionError { state: self.state.label(), transition }),
+        }
+    }
+
+    fn next_state(&self, transition: Transition) -> Option<LightState> {
+        match (&self.state, transition) {
+            (LightState::Off | LightState::On { .. } | LightState::Unreachable, Transition::Begin(target))
+                if self.state.power() != Some(target) =>
+            {
+                Some(LightState::Transitioning { from: Box::new(self.state.clone()), target })
+            },
+            (LightState::Transitioning { target: PowerState::On, .. }, Transition::Complete) => {
+                let (brightness, color) = self.last_on;
+                Some(LightState::On { brightness, color })
+            },
+            (LightState::Transitioning { target: PowerState::Off, .. }, Transition::Complete) => Some(LightState::Off),
+            (LightState::Transitioning { .. }, Transition::Fail) => Some(LightState::Unreachable),
+            (LightState::Off | LightState::On { .. }, Transition::Lock) => {
+                Some(LightState::Locked { inner: Box::new(self.state.clone()) })
+            },
+            (LightState::Locked { inner }, Transition::Unlock) => Some((**inner).clone()),
+            // A color chosen while off is remembered for the next time the bulb comes on
+            (LightState::On { brightness, .. }, Transition::SetColor(color)) => {
+                Some(LightState::On { brightness: *brightness, color })
+            },
+            (LightState::Off, Transition::SetColor(_)) => Some(LightState::Off),
+            (LightState::On { color, .. }, Transition::SetBrightness(brightness)) => {
+                Some(LightState::On { brightness, color: *color })
+            },
+            (LightState::Off, Transition::SetBrightness(_)) => Some(LightState::Off),
+            _ => None,
+        }
+    }
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_turn_on_goes_through_transitioning() {
+        let mut machine = StateMachine::new();
+        let state = machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        assert_eq!(state.label(), "TRANSITIONING");
+        assert_eq!(state.power(), Some(PowerState::Off));
+
+        let state = machine.apply(Transition::Complete).unwrap();
+        assert_eq!(state, &LightState::On { brightness: DEFAULT_BRIGHTNESS, color: Color::WHITE });
+    }
+
+    #[test]
+    fn test_invalid_transitions_are_rejected() {
+        let mut machine = StateMachine::new();
+        assert!(machine.apply(Transition::Begin(PowerState::Off)).is_err());
+        assert!(machine.apply(Transition::Complete).is_err());
+        assert!(machine.apply(Transition::Unlock).is_err());
+
+        machine.apply(Transition::Lock).unwrap();
+        let error = machine.apply(Transition::Begin(PowerState::On)).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot turn on while the lightbulb is locked");
+        assert_eq!(machine.apply(Transition::Unlock).unwrap(), &LightState::Off);
+    }
+
+    #[test]
+    fn test_reset_forgets_everything_but_the_version() {
+        let mut machine = StateMachine::new();
+        machine.attribute_to(Some("alice".to_string()), "set_power");
+        machine.apply(Transition::SetColor(Color { r: 255, g: 0, b: 0 })).unwrap();
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        machine.apply(Transition::Complete).unwrap();
+        machine.reset();
+        let snapshot = machine.snapshot();
+        assert_eq!(machine.state(), &LightState::Off);
+        assert_eq!((snapshot.color, snapshot.version, snapshot.changed_by, snapshot.last_changed), (Color::WHITE, 4, None, None));
+    }
+
+    #[test]
+    fn test_failed_transition_marks_unreachable_and_allows_retry() {
+        let mut machine = StateMachine::new();
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        assert_eq!(machine.apply(Transition::Fail).unwrap(), &LightState::Unreachable);
+
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        assert_eq!(machine.apply(Transition::Complete).unwrap().power(), Some(PowerState::On));
+    }
+
+    #[test]
+    fn test_snapshot_tracks_lock_and_last_change() {
+        let mut machine = StateMachine::new();
+        assert!(machine.snapshot().last_changed.is_none());
+
+        machine.apply(Transition::Lock).unwrap();
+        let snapshot = machine.snapshot();
+        assert!(snapshot.locked);
+        assert_eq!(snapshot.power, Some(PowerState::Off));
+        assert_eq!(snapshot.brightness, DEFAULT_BRIGHTNESS);
+        assert!(snapshot.last_changed.is_some());
+    }
+
+    #[test]
+    fn test_version_counts_successful_transitions() {
+        let mut machine = StateMachine::new();
+        assert_eq!(machine.version(), 0);
+        machine.apply(Transition::Lock).unwrap();
+        assert!(machine.apply(Transition::Begin(PowerState::On)).is_err());
+        assert_eq!(machine.version(), 1);
+        assert_eq!(machine.snapshot().version, 1);
+    }
+
+    #[test]
+    fn test_state_since_follows_power_not_locks() {
+        let mut machine = StateMachine::new();
+        machine.attribute_to(Some("agent".to_string()), "turn_on");
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        assert!(machine.snapshot().state_since.is_none());
+        machine.apply(Transition::Complete).unwrap();
+        let on_since = machine.snapshot().state_since;
+        assert!(on_since.is_some());
+
+        machine.attribute_to(None, "lock");
+        machine.apply(Transition::Lock).unwrap();
+        let snapshot = machine.snapshot();
+        assert_eq!(snapshot.state_since, on_since);
+        assert_eq!(snapshot.in_state_secs, Some(0));
+        assert_eq!((snapshot.changed_by, snapshot.cause.as_deref()), (None, Some("lock")));
+    }
+
+    #[test]
+    fn test_toggles_wait_out_the_minimum_interval() {
+        let clock = ManualClock::new(DateTime::UNIX_EPOCH);
+        let mut machine = StateMachine::with_clock(Arc::new(clock.clone()));
+        machine.set_min_toggle_interval(Duration::from_secs(2));
+        assert_eq!(machine.toggle_wait(), None);
+
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        machine.apply(Transition::Fail).unwrap();
+        assert_eq!(machine.toggle_wait(), None);
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        machine.apply(Transition::Complete).unwrap();
+        clock.advance(chrono::TimeDelta::milliseconds(500));
+        assert_eq!(machine.toggle_wait(), Some(Duration::from_millis(1500)));
+        clock.advance(chrono::TimeDelta::milliseconds(1500));
+        assert_eq!(machine.toggle_wait(), None);
+    }
+
+    #[test]
+    fn test_color_set_while_off_applies_on_next_turn_on() {
+        let mut machine = StateMachine::new();
+        let amber = Color { r: 255, g: 191, b: 0 };
+        assert_eq!(machine.apply(Transition::SetColor(amber)).unwrap(), &LightState::Off);
+        assert_eq!(machine.apply(Transition::SetBrightness(40)).unwrap(), &LightState::Off);
+
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        let state = machine.apply(Transition::Complete).unwrap();
+        assert_eq!(state, &LightState::On { brightness: 40, color: amber });
+        let state = machine.apply(Transition::SetBrightness(DEFAULT_BRIGHTNESS)).unwrap();
+        assert_eq!(state, &LightState::On { brightness: DEFAULT_BRIGHTNESS, color: amber });
+
+        machine.apply(Transition::Lock).unwrap();
+        assert!(machine.check(Transition::SetColor(Color::WHITE)).is_err());
+        assert!(machine.check(Transition::SetBrightness(10)).is_err());
+    }
+
+    #[test]
+    fn test_hooks_observe_transitions() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut machine = StateMachine::new();
+        let recorder = seen.clone();
+        machine.add_hook(move |change| {
+            recorder.lock().unwrap().push(format!("{} -> {}", change.from.label(), change.to.label()));
+        });
+
+        machine.apply(Transition::Begin(PowerState::On)).unwrap();
+        machine.apply(Transition::Complete).unwrap();
+        let _ = machine.apply(Transition::Complete);
+
+        assert_eq!(*seen.lock().unwrap(), vec!["OFF -> TRANSITIONING", "TRANSITIONING -> ON"]);
+    }
+
+    fn transition() -> impl Strategy<Value = Transition> {
+        prop_oneof![
+            any::<bool>().prop_map(|on| Transition::Begin(if on { PowerState::On } else { PowerState::Off })),
+            Just(Transition::Complete),
+            Just(Transition::Fail),
+            Just(Transition::Lock),
+            Just(Transition::Unlock),
+            Just(Transition::SetColor(Color::WHITE)),
+            Just(Transition::SetBrightness(50)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_every_applied_transition_is_counted_once(transitions in prop::collection::vec(transition(), 0..64)) {
+            let hooked = Arc::new(Mutex::new(0u64));
+            let mut machine = StateMachine::new();
+            let counter = hooked.clone();
+            machine.add_hook(move |_| *counter.lock().unwrap() += 1);
+
+            let mut applied = 0;
+            for transition in transitions {
+                let checked = machine.check(transition).is_ok();
+                let before = machine.state().clone();
+                let result = machine.apply(transition).map(|_| ());
+                prop_assert_eq!(checked, result.is_ok());
+                match result {
+                    Ok(()) => applied += 1,
+                    Err(_) => prop_assert_eq!(machine.state(), &before),
+                }
+                prop_assert_eq!((machine.version(), *hooked.lock().unwrap()), (applied, applied));
+                prop_assert_eq!(machine.snapshot().locked, matches!(machine.state(), LightState::Locked { .. }));
+            }
+        }
+    }
+}
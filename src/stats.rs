@@ -0,0 +1,601 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::journal::RECOVERED_SUFFIX;
+use crate::logger::{LOG_ACTION_OFF, LOG_ACTION_ON, LOG_BRIGHTNESS, LOG_COLOR, LOG_DENIED, LOG_NAMED, LOG_OUTAGE, LOG_RESTORE, LOG_SHUTDOWN, LOG_SIGNATURE_MARKER, parse_log_timestamp, split_log_sequence};
+use crate::model::PowerState;
+use crate::tags::parse_tags;
+
+pub const LOG_EVENT_PREFIX: &str = "Lightbulb turned ";
+const DAILY_TOTAL_PREFIX: &str = "Lightbulb daily total ";
+const RECENT_ENTRIES: usize = 5;
+// Typical draw of an LED bulb, used for energy estimates when the caller gives none
+pub const DEFAULT_WATTS: f64 = 9.0;
+// Well past any household bulb, so a larger draw is a typo
+pub const MAX_WATTS: f64 = 10_000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Metric {
+    Counts,
+    OnTime,
+    Energy,
+    Histogram,
+}
+
+// A power change recovered from one log line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PowerEvent {
+    pub at: DateTime<Utc>,
+    pub power: PowerState,
+    pub tags: Vec<String>,
+}
+
+// Reads "[timestamp] Lightbulb turned ON (UNDO) [movie] sig=..." style lines, skipping anything else
+pub fn parse_power_events(log: &str) -> Vec<PowerEvent> {
+    log.lines().filter_map(parse_power_event).collect()
+}
+
+pub fn parse_power_event(line: &str) -> Option<PowerEvent> {
+    let (at, rest) = parse_log_timestamp(line)?;
+    let mut words = rest.trim_start().strip_prefix(LOG_EVENT_PREFIX)?.split_whitespace();
+    let power = match words.next()? {
+        LOG_ACTION_ON => PowerState::On,
+        LOG_ACTION_OFF => PowerState::Off,
+        _ => return None,
+    };
+    Some(PowerEvent { at, power, tags: parse_tags(words) })
+}
+
+// One log line taken apart for the JSON log: when it was written, its number, what it says, the power change and
+// tags it records if it records one, and its signature if it is signed. Lines without a timestamp keep only the
+// message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LogRecord {
+    pub at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<u64>,
+    pub message: String,
+    pub power: Option<PowerState>,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl LogRecord {
+    pub fn parse(line: &str) -> Self {
+        let (entry, signature) = match line.rsplit_once(LOG_SIGNATURE_MARKER) {
+            Some((entry, signature)) => (entry, Some(signature.to_string())),
+            None => (line, None),
+        };
+        let (entry, sequence) = split_log_sequence(entry);
+        let entry = entry.as_str();
+        let event = parse_power_event(entry);
+        let (at, message) = match parse_log_timestamp(entry) {
+            Some((at, message)) => (Some(at), message),
+            None => (None, entry),
+        };
+        Self {
+            at,
+            sequence,
+            message: message.trim().to_string(),
+            power: event.as_ref().map(|event| event.power),
+            tags: event.map(|event| event.tags).unwrap_or_default(),
+            signature,
+        }
+    }
+}
+
+// What a log entry records, as query_history filters on it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, schemars::JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogAction {
+    On,
+    Off,
+    Color,
+    Brightness,
+    Denied,
+    Named,
+    Outage,
+    Restore,
+    Shutdown,
+    DailyTotal,
+    Other,
+}
+
+impl LogAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            LogAction::On => "on",
+            LogAction::Off => "off",
+            LogAction::Color => "color",
+            LogAction::Brightness => "brightness",
+            LogAction::Denied => "denied",
+            LogAction::Named => "named",
+            LogAction::Outage => "outage",
+            LogAction::Restore => "restore",
+            LogAction::Shutdown => "shutdown",
+            LogAction::DailyTotal => "daily_total",
+            LogAction::Other => "other",
+        }
+    }
+}
+
+// A timestamped log entry taken apart for query_history: what it records, who made it and why, and for a power
+// change the power the bulb had before it, which the log itself does not say
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub at: DateTime<Utc>,
+    pub sequence: Option<u64>,
+    pub action: LogAction,
+    pub by: Option<String>,
+    pub reason: Option<String>,
+    pub tags: Vec<String>,
+    pub previous: Option<PowerState>,
+    pub message: String,
+}
+
+impl HistoryEntry {
+    pub fn parse(line: &str) -> Option<Self> {
+        let record = LogRecord::parse(line);
+        let at = record.at?;
+        let text = record.message.strip_prefix("Lightbulb ").unwrap_or(&record.message);
+        let text = text.strip_suffix(RECOVERED_SUFFIX).map_or(text, str::trim_end);
+        let mut words = text.split_whitespace();
+        let action = match (record.power, words.next(), words.next()) {
+            (Some(PowerState::On), ..) => LogAction::On,
+            (Some(PowerState::Off), ..) => LogAction::Off,
+            (None, Some(LOG_COLOR), _) => LogAction::Color,
+            (None, Some(LOG_BRIGHTNESS), _) => LogAction::Brightness,
+            (None, Some(LOG_DENIED), _) => LogAction::Denied,
+            (None, Some(LOG_NAMED), _) => LogAction::Named,
+            (None, Some(LOG_OUTAGE), _) => LogAction::Outage,
+            (None, Some(LOG_RESTORE), _) => LogAction::Restore,
+            (None, Some("server"), Some(LOG_SHUTDOWN)) => LogAction::Shutdown,
+            _ if parse_daily_total(line).is_some() => LogAction::DailyTotal,
+            _ => LogAction::Other,
+        };
+        // Reasons come last, so a " by " inside one is not taken for the client
+        let (by, reason) = match action {
+            LogAction::On | LogAction::Off | LogAction::Color | LogAction::Brightness => match text.split_once(" (reason: ") {
+                Some((head, reason)) => (after(head, " by "), Some(reason.strip_suffix(')').unwrap_or(reason).to_string())),
+                None => (after(text, " by "), None),
+            },
+            LogAction::Denied => (after(text.split(" (outside allowed hours: ").next().unwrap_or(text), " to "), None),
+            // Names are quoted, so the client follows the last quote
+            LogAction::Named => (after(text.rsplit_once('"').map_or(text, |(_, tail)| tail), " by "), None),
+            _ => (None, None),
+        };
+        Some(Self { at, sequence: record.sequence, action, by, reason, tags: record.tags, previous: None, message: record.message })
+    }
+}
+
+// The timestamped entries of `lines` taken apart in log order, each power change with the power the one before it
+// left the bulb in, so two changes logged in the same instant still follow each other. `power` is what the bulb
+// had before the first line, and is left as the last one leaves it.
+pub fn history_entries<'a>(lines: impl IntoIterator<Item = &'a str>, power: &mut Option<PowerState>) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    for line in lines {
+        let Some(mut entry) = HistoryEntry::parse(line) else {
+            continue;
+        };
+        match entry.action {
+            LogAction::On | LogAction::Off => {
+                entry.previous = *power;
+                *power = Some(if entry.action == LogAction::On { PowerState::On } else { PowerState::Off });
+            },
+            // A compacted day says how it ended
+            LogAction::DailyTotal => {
+                if let Some(total) = parse_daily_total(line).filter(|total| total.left_on || total.on + total.off > 0) {
+                    *power = Some(if total.left_on { PowerState::On } else { PowerState::Off });
+                }
+            },
+            _ => {},
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+fn after(text: &str, marker: &str) -> Option<String> {
+    text.split_once(marker).map(|(_, rest)| rest.to_string())
+}
+
+// One UTC day of the log collapsed by compaction: how many entries it had, its power changes and its on time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyTotal {
+    pub day: NaiveDate,
+    // The bracketed timestamp text of the day's first and last entries, as written; None for a day with no entries
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub entries: usize,
+    pub on: usize,
+    pub off: usize,
+    pub on_secs: i64,
+    // Whether the bulb was on at midnight, so its on time carries into the next day
+    pub left_on: bool,
+}
+
+impl DailyTotal {
+    pub fn new(day: NaiveDate) -> Self {
+        Self { day, first: None, last: None, entries: 0, on: 0, off: 0, on_secs: 0, left_on: false }
+    }
+
+    pub fn start(&self) -> DateTime<Utc> {
+        day_start(self.day)
+    }
+
+    pub fn end(&self) -> DateTime<Utc> {
+        day_start(self.day.succ_opt().unwrap_or(self.day))
+    }
+
+    // "[first entry] Lightbulb daily total 2025-08-01: entries=7 on=3 off=3 on_secs=5400 left_on=false last=..."
+    pub fn log_line(&self) -> String {
+        let stamp = self.first.clone().unwrap_or_else(|| self.start().to_rfc3339());
+        let mut line = format!(
+            "[{}] {}{}: entries={} on={} off={} on_secs={} left_on={}",
+            stamp, DAILY_TOTAL_PREFIX, self.day, self.entries, self.on, self.off, self.on_secs, self.left_on
+        );
+        if let Some(last) = &self.last {
+            line.push_str(&format!(" last={}", last));
+        }
+        line
+    }
+}
+
+pub fn day_start(day: NaiveDate) -> DateTime<Utc> {
+    day.and_time(chrono::NaiveTime::MIN).and_utc()
+}
+
+// Reads a line written by DailyTotal::log_line, ignoring any signature after it
+pub fn parse_daily_total(line: &str) -> Option<DailyTotal> {
+    let (stamp, rest) = line.strip_prefix('[')?.split_once(']')?;
+    let (day, fields) = rest.trim_start().strip_prefix(DAILY_TOTAL_PREFIX)?.split_once(':')?;
+    let mut total = DailyTotal::new(day.parse().ok()?);
+    for (key, value) in fields.split_whitespace().filter_map(|field| field.split_once('=')) {
+        match key {
+            "entries" => total.entries = value.parse().ok()?,
+            "on" => total.on = value.parse().ok()?,
+            "off" => total.off = value.parse().ok()?,
+            "on_secs" => total.on_secs = value.parse().ok()?,
+            "left_on" => total.left_on = value.parse().ok()?,
+            "last" => total.last = Some(value.to_string()),
+            _ => {},
+        }
+    }
+    total.first = (total.entries > 0).then(|| stamp.to_string());
+    Some(total)
+}
+
+// Running totals over the log, updated as entries are written so summaries and statistics need not re-read it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageCounters {
+    // Non-blank lines, and those among them recording a turn on or off
+    pub total: usize,
+    pub on: usize,
+    pub off: usize,
+    // The bracketed timestamp text of the first and last lines, as written
+    pub first: Option<String>,
+    pub last: Option<String>,
+    pub recent: VecDeque<String>,
+    // Shared so readers can take them without copying; appending only copies while a reader still holds them
+    pub events: Arc<Vec<PowerEvent>>,
+    // Compacted days, which come before every event
+    pub days: Arc<Vec<DailyTotal>>,
+}
+
+impl UsageCounters {
+    pub fn from_log(log: &str) -> Self {
+        let mut counters = Self::default();
+        counters.record(log);
+        counters
+    }
+
+    // Counts lines appended to the log
+    pub fn record(&mut self, lines: &str) {
+        for line in lines.lines().filter(|line| !line.trim().is_empty()) {
+            if let Some(day) = parse_daily_total(line) {
+                self.record_day(line, day);
+                continue;
+            }
+            self.total += 1;
+            // Counted from the entry taken apart, so a reason or client name that reads "turned ON" is not a change
+            let event = parse_power_event(line);
+            self.on += usize::from(event.as_ref().is_some_and(|event| event.power.is_on()));
+            self.off += usize::from(event.as_ref().is_some_and(|event| !event.power.is_on()));
+            let timestamp = line.split(']').next().unwrap_or("").trim_start_matches('[').to_string();
+            if self.first.is_none() {
+                self.first = Some(timestamp.clone());
+            }
+            self.last = Some(timestamp);
+            if self.recent.len() == RECENT_ENTRIES {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(line.to_string());
+            if let Some(event) = event {
+                Arc::make_mut(&mut self.events).push(event);
+            }
+        }
+    }
+
+    // A compacted day counts as the entries it replaced
+    fn record_day(&mut self, line: &str, day: DailyTotal) {
+        self.total += day.entries;
+        self.on += day.on;
+        self.off += day.off;
+        if let Some(first) = &day.first {
+            self.first.get_or_insert_with(|| first.clone());
+            self.last = day.last.clone().or_else(|| Some(first.clone()));
+            if self.recent.len() == RECENT_ENTRIES {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(line.to_string());
+        }
+        Arc::make_mut(&mut self.days).push(day);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Counts {
+    pub total: usize,
+    pub on: usize,
+    pub off: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Energy {
+    pub watts: f64,
+    pub watt_hours: f64,
+}
+
+// Only the requested metrics are present; the rest are left out of the JSON
+#[derive(Debug, Clone, PartialEq, Serialize, schemars::JsonSchema)]
+pub struct Statistics {
+    pub from: Option<DateTime<Utc>>,
+    pub to: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counts: Option<Counts>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub on_time_secs: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub energy: Option<Energy>,
+    // Power changes per hour of the day (UTC), index 0 being midnight to 1am
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub histogram: Option<[usize; 24]>,
+}
+
+// Computes `metrics` over the events in [from, to); the bulb is assumed off before its first logged event.
+// With a tag, only events carrying it are counted, and only time after a tagged turn-on counts as on time.
+// Compacted days add their counts and on time when the whole day lies in the range and there is no tag;
+// they are not in the histogram.
+pub fn compute(
+    events: &[PowerEvent],
+    days: &[DailyTotal],
+    metrics: &[Metric],
+    from: Option<DateTime<Utc>>,
+    to: DateTime<Utc>,
+    watts: f64,
+    tag: Option<&str>,
+) -> Statistics {
+    let tagged = |event: &PowerEvent| tag.is_none_or(|tag| event.tags.iter().any(|t| t == tag));
+    let in_range: Vec<&PowerEvent> = events
+        .iter()
+        .filter(|event| from.is_none_or(|from| event.at >= from) && event.at < to && tagged(event))
+        .collect();
+    let whole_days: Vec<&DailyTotal> = match tag {
+        Some(_) => Vec::new(),
+        None => days.iter().filter(|day| from.is_none_or(|from| day.start() >= from) && day.end() <= to).collect(),
+    };
+    let carried_on = days.last().filter(|day| day.left_on && tag.is_none()).map(DailyTotal::end);
+    let on_time_secs = on_time(events, carried_on, from, to, tagged) + whole_days.iter().map(|day| day.on_secs).sum::<i64>();
+
+    let mut statistics = Statistics { from, to, tag: tag.map(String::from), counts: None, on_time_secs: None, energy: None, histogram: None };
+    for metric in metrics {
+        match metric {
+            Metric::Counts => {
+                let on = in_range.iter().filter(|event| event.power.is_on()).count() + whole_days.iter().map(|day| day.on).sum::<usize>();
+                let off = in_range.iter().filter(|event| !event.power.is_on()).count() + whole_days.iter().map(|day| day.off).sum::<usize>();
+                statistics.counts = Some(Counts { total: on + off, on, off });
+            },
+            Metric::OnTime => statistics.on_time_secs = Some(on_time_secs),
+            Metric::Energy => {
+                let watt_hours = watts * on_time_secs as f64 / 3600.0;
+                statistics.energy = Some(Energy { watts, watt_hours: (watt_hours * 1000.0).round() / 1000.0 });
+            },
+            Metric::Histogram => {
+                let mut histogram = [0; 24];
+                for event in &in_range {
+                    histogram[event.at.hour() as usize] += 1;
+                }
+                statistics.histogram = Some(histogram);
+            },
+        }
+    }
+    statistics
+}
+
+// How the bulb's power at `from` compares with its power at `to`, and what happened in between
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StateDiff {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    // None when nothing logged before the time says what the bulb was
+    pub power_from: Option<PowerState>,
+    pub power_to: Option<PowerState>,
+    pub changed: bool,
+    pub transitions: Counts,
+    pub on_time_secs: i64,
+}
+
+// The bulb's power just before `at`: that of the last power change logged earlier, or, before every event, of
+// the last compacted day to end by then
+pub fn power_at(events: &[PowerEvent], days: &[DailyTotal], at: DateTime<Utc>) -> Option<PowerState> {
+    match events.iter().take_while(|event| event.at < at).last() {
+        Some(event) => Some(event.power),
+        None => days.iter().rfind(|day| day.end() <= at).map(|day| PowerState::from(day.left_on)),
+    }
+}
+
+// Reconstructs the bulb's power at `from` and at `to` from the log, with the changes in [from, to) between them
+pub fn diff(events: &[PowerEvent], days: &[DailyTotal], from: DateTime<Utc>, to: DateTime<Utc>) -> StateDiff {
+    let statistics = compute(events, days, &[Metric::Counts, Metric::OnTime], Some(from), to, DEFAULT_WATTS, None);
+    let (power_from, power_to) = (power_at(events, days, from), power_at(events, days, to));
+    StateDiff {
+        from,
+        to,
+        power_from,
+        power_to,
+        changed: power_from.unwrap_or_default() != power_to.unwrap_or_default(),
+        transitions: statistics.counts.unwrap_or(Counts { total: 0, on: 0, off: 0 }),
+        on_time_secs: statistics.on_time_secs.unwrap_or_default(),
+    }
+}
+
+// `on_since` is when the bulb was on from before the first event, if it was
+fn on_time(
+    events: &[PowerEvent],
+    mut on_since: Option<DateTime<Utc>>,
+    from: Option<DateTime<Utc>>,
+    to: DateTime<Utc>,
+    tagged: impl Fn(&PowerEvent) -> bool,
+) -> i64 {
+    let mut total = 0;
+    for event in events.iter().take_while(|event| event.at < to) {
+        match (event.power, on_since) {
+            (PowerState::On, None) if tagged(event) => on_since = Some(event.at),
+            (PowerState::Off, Some(since)) => {
+                total += clipped_secs(since, event.at, from);
+                on_since = None;
+            },
+            _ => {},
+        }
+    }
+    if let Some(since) = on_since {
+        total += clipped_secs(since, to, from);
+    }
+    total
+}
+
+fn clipped_secs(start: DateTime<Utc>, end: DateTime<Utc>, from: Option<DateTime<Utc>>) -> i64 {
+    let start = from.map_or(start, |from| start.max(from));
+    (end - start).num_seconds().max(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "\
+[2025-08-02T10:00:00+00:00] Lightbulb turned ON
+[2025-08-02T10:30:00+00:00] Lightbulb turned OFF sig=abc
+[2025-08-02T11:00:00+00:00] Lightbulb turned ON (UNDO) [movie] by agent
+not a log line
+[2025-08-02T13:00:00+00:00] Lightbulb turned OFF
+";
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_usage_counters_follow_appended_lines() {
+        let mut counters = UsageCounters::from_log(LOG);
+        assert_eq!((counters.total, counters.on, counters.off), (5, 2, 2));
+        assert_eq!(counters.first.as_deref(), Some("2025-08-02T10:00:00+00:00"));
+        assert_eq!(counters.last.as_deref(), Some("2025-08-02T13:00:00+00:00"));
+
+        let readers_copy = counters.events.clone();
+        counters.record("[2025-08-02T14:00:00+00:00] Lightbulb turned ON by agent\n\n[2025-08-02T15:00:00+00:00] Lightbulb turned OFF");
+        assert_eq!((counters.total, counters.on, counters.off), (7, 3, 3));
+        assert_eq!(counters.recent.len(), RECENT_ENTRIES);
+        assert_eq!(counters.recent.front().map(String::as_str), Some("[2025-08-02T11:00:00+00:00] Lightbulb turned ON (UNDO) [movie] by agent"));
+        assert_eq!((counters.events.len(), readers_copy.len()), (6, 4));
+        assert_eq!(counters, UsageCounters::from_log(&format!("{}{}", LOG, "[2025-08-02T14:00:00+00:00] Lightbulb turned ON by agent\n[2025-08-02T15:00:00+00:00] Lightbulb turned OFF\n")));
+    }
+
+    #[test]
+    fn test_parses_power_events() {
+        let events = parse_power_events(LOG);
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2], PowerEvent { at: at("2025-08-02T11:00:00Z"), power: PowerState::On, tags: vec!["movie".to_string()] });
+    }
+
+    #[test]
+    fn test_log_records_take_lines_apart() {
+        let records: Vec<LogRecord> = LOG.lines().map(LogRecord::parse).collect();
+        let signed = &records[1];
+        assert_eq!((signed.message.as_str(), signed.power, signed.signature.as_deref()), ("Lightbulb turned OFF", Some(PowerState::Off), Some("abc")));
+        assert_eq!((records[2].at, &records[2].tags), (Some(at("2025-08-02T11:00:00Z")), &vec!["movie".to_string()]));
+        assert_eq!(records[3], LogRecord { at: None, sequence: None, message: "not a log line".to_string(), power: None, tags: Vec::new(), signature: None });
+        let json = serde_json::to_value(&records[0]).unwrap();
+        assert_eq!(json, serde_json::json!({ "at": "2025-08-02T10:00:00Z", "message": "Lightbulb turned ON", "power": "on", "tags": [] }));
+        let numbered = LogRecord::parse("[2025-08-02T12:00:00+00:00] Lightbulb turned ON [movie] seq=17 sig=abc");
+        assert_eq!((numbered.sequence, numbered.message.as_str(), numbered.tags.len()), (Some(17), "Lightbulb turned ON [movie]", 1));
+    }
+
+    #[test]
+    fn test_history_entries_name_the_action_client_and_reason() {
+        let entry = HistoryEntry::parse("[2025-08-02T12:00:00+00:00] Lightbulb turned OFF [movie] by agent (reason: stand by me) seq=4").unwrap();
+        assert_eq!((entry.action, entry.by.as_deref(), entry.reason.as_deref()), (LogAction::Off, Some("agent"), Some("stand by me")));
+        assert_eq!((entry.sequence, entry.tags.len()), (Some(4), 1));
+        let entry = HistoryEntry::parse("[2025-08-02T12:00:00+00:00] Lightbulb turned ON (reason: passed by) (recovered from the journal)").unwrap();
+        assert_eq!((entry.by, entry.reason.as_deref()), (None, Some("passed by")));
+        let denied = HistoryEntry::parse("[2025-08-02T21:00:00+00:00] Lightbulb DENIED turn_on_lightbulb to kid tablet (outside allowed hours: bedtime)").unwrap();
+        assert_eq!((denied.action, denied.by.as_deref()), (LogAction::Denied, Some("kid tablet")));
+        let shutdown = HistoryEntry::parse("[2025-08-02T23:00:00+00:00] Lightbulb server SHUTDOWN (SIGTERM)").unwrap();
+        assert_eq!(shutdown.action, LogAction::Shutdown);
+        assert_eq!(HistoryEntry::parse("not a log line"), None);
+
+        let counters = UsageCounters::from_log("[2025-08-02T12:00:00+00:00] Lightbulb turned OFF by agent (reason: nobody turned ON the fan)\n");
+        assert_eq!((counters.on, counters.off), (0, 1));
+    }
+
+    #[test]
+    fn test_only_requested_metrics_are_computed() {
+        let events = parse_power_events(LOG);
+        let statistics = compute(&events, &[], &[Metric::OnTime, Metric::Energy], None, at("2025-08-03T00:00:00Z"), 10.0, None);
+        assert_eq!(statistics.on_time_secs, Some(9000));
+        assert_eq!(statistics.energy, Some(Energy { watts: 10.0, watt_hours: 25.0 }));
+        assert!(statistics.counts.is_none());
+        assert!(statistics.histogram.is_none());
+    }
+
+    #[test]
+    fn test_range_clips_on_time_and_counts() {
+        let events = parse_power_events(LOG);
+        let metrics = [Metric::Counts, Metric::OnTime, Metric::Histogram];
+        let statistics = compute(&events, &[], &metrics, Some(at("2025-08-02T10:15:00Z")), at("2025-08-02T12:00:00Z"), 10.0, None);
+        assert_eq!(statistics.counts, Some(Counts { total: 2, on: 1, off: 1 }));
+        assert_eq!(statistics.on_time_secs, Some(15 * 60 + 60 * 60));
+        assert_eq!(statistics.histogram.unwrap()[10], 1);
+    }
+
+    #[test]
+    fn test_diff_reconstructs_the_state_at_each_end() {
+        let events = parse_power_events(LOG);
+        let diff = diff(&events, &[], at("2025-08-02T10:15:00Z"), at("2025-08-02T14:00:00Z"));
+        assert_eq!((diff.power_from, diff.power_to, diff.changed), (Some(PowerState::On), Some(PowerState::Off), true));
+        assert_eq!(diff.transitions, Counts { total: 3, on: 1, off: 2 });
+        assert_eq!(diff.on_time_secs, 15 * 60 + 2 * 60 * 60);
+
+        assert_eq!(power_at(&events, &[], at("2025-08-02T09:00:00Z")), None);
+        let mut day = DailyTotal::new(NaiveDate::from_ymd_opt(2025, 8, 1).unwrap());
+        day.left_on = true;
+        assert_eq!(power_at(&events, &[day], at("2025-08-02T09:00:00Z")), Some(PowerState::On));
+    }
+
+    #[test]
+    fn test_tag_filters_counts_and_on_time() {
+        let events = parse_power_events(LOG);
+        let metrics = [Metric::Counts, Metric::OnTime];
+        let statistics = compute(&events, &[], &metrics, None, at("2025-08-03T00:00:00Z"), 10.0, Some("movie"));
+        assert_eq!(statistics.counts, Some(Counts { total: 1, on: 1, off: 0 }));
+        assert_eq!(statistics.on_time_secs, Some(2 * 60 * 60));
+        assert_eq!(statistics.tag.as_deref(), Some("movie"));
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rmcp::RoleServer;
+use rmcp::model::{ErrorCode, JsonRpcBatchRequestItem, JsonRpcMessage};
+use rmcp::service::{RxJsonRpcMessage, TxJsonRpcMessage};
+use rmcp::transport::Transport;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+use tokio::sync::{Mutex, watch};
+
+// How long the end of input waits for the responses still owed before ending the session
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+// Requests a client may send, so an unparseable one can be told apart from an unknown method
+const CLIENT_METHODS: [&str; 13] = [
+    "initialize",
+    "ping",
+    "completion/complete",
+    "logging/setLevel",
+    "prompts/get",
+    "prompts/list",
+    "resources/list",
+    "resources/templates/list",
+    "resources/read",
+    "resources/subscribe",
+    "resources/unsubscribe",
+    "tools/call",
+    "tools/list",
+];
+
+// Newline-delimited JSON-RPC, like rmcp's AsyncRwTransport, except that a line it cannot parse is answered
+// with a JSON-RPC error instead of ending the session, and the end of input waits for the responses to the
+// requests before it, so piping a file of requests gets every answer
+pub struct LineTransport<R, W> {
+    lines: Lines<BufReader<R>>,
+    write: Arc<Mutex<W>>,
+    // Requests received but not yet answered
+    pending: Arc<watch::Sender<usize>>,
+}
+
+impl<R: AsyncRead + Unpin, W> LineTransport<R, W> {
+    pub fn new(read: R, write: W) -> Self {
+        Self { lines: BufReader::new(read).lines(), write: Arc::new(Mutex::new(write)), pending: Arc::new(watch::Sender::new(0)) }
+    }
+}
+
+fn requests_in(message: &RxJsonRpcMessage<RoleServer>) -> usize {
+    match message {
+        JsonRpcMessage::Request(_) => 1,
+        JsonRpcMessage::BatchRequest(items) => items.iter().filter(|item| matches!(item, JsonRpcBatchRequestItem::Request(_))).count(),
+        _ => 0,
+    }
+}
+
+fn responses_in(message: &TxJsonRpcMessage<RoleServer>) -> usize {
+    match message {
+        JsonRpcMessage::Response(_) | JsonRpcMessage::Error(_) => 1,
+        JsonRpcMessage::BatchResponse(items) => items.len(),
+        _ => 0,
+    }
+}
+
+async fn write_line<W: AsyncWrite + Unpin>(write: &Mutex<W>, mut line: String) -> std::io::Result<()> {
+    line.push('\n');
+    let mut write = write.lock().await;
+    write.write_all(line.as_bytes()).await?;
+    write.flush().await
+}
+
+fn error_reply(id: Value, code: ErrorCode, message: String) -> String {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code.0, "message": message } }).to_string()
+}
+
+// The reply owed for a line that is not a message the server understands, if any; notifications never get one
+fn reply_to_unparseable(line: &str, error: serde_json::Error) -> Option<String> {
+    let Ok(value) = serde_json::from_str::<Value>(line) else {
+        return Some(error_reply(Value::Null, ErrorCode::PARSE_ERROR, format!("Parse error: {}", error)));
+    };
+    let id = value.get("id").filter(|id| id.is_string() || id.is_number()).cloned();
+    match (id, value.get("method").and_then(Value::as_str)) {
+        (None, Some(_)) => None,
+        (Some(id), Some(method)) if !CLIENT_METHODS.contains(&method) => {
+            Some(error_reply(id, ErrorCode::METHOD_NOT_FOUND, format!("Method not found: {}", method)))
+        },
+        (Some(id), Some(_)) => Some(error_reply(id, ErrorCode::INVALID_PARAMS, format!("Invalid params: {}", error))),
+        (id, None) => Some(error_reply(id.unwrap_or_default(), ErrorCode::INVALID_REQUEST, format!("Invalid request: {}", error))),
+    }
+}
+
+impl<R, W> Transport<RoleServer> for LineTransport<R, W>
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    type Error = std::io::Error;
+
+    fn send(&mut self, item: TxJsonRpcMessage<RoleServer>) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let (write, pending) = (self.write.clone(), self.pending.clone());
+        async move {
+            let result = write_line(&write, serde_json::to_string(&item)?).await;
+            pending.send_modify(|pending| *pending = pending.saturating_sub(responses_in(&item)));
+            result
+        }
+    }
+
+    // Ends the session at the end of input or when the reply to a bad line cannot be written
+    async fn receive(&mut self) -> Option<RxJsonRpcMessage<RoleServer>> {
+        loop {
+            let Some(line) = self.lines.next_line().await.ok().flatten() else {
+                let mut pending = self.pending.subscribe();
+                let _ = tokio::time::timeout(DRAIN_TIMEOUT, pending.wait_for(|pending| *pending == 0)).await;
+                return None;
+            };
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(message) => {
+                    self.pending.send_modify(|pending| *pending += requests_in(&message));
+                    return Some(message);
+                },
+                Err(error) => {
+                    if let Some(reply) = reply_to_unparseable(line, error) {
+                        write_line(&self.write, reply).await.ok()?;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Self::Error> {
+        self.write.lock().await.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(line: &str) -> Option<Value> {
+        let error = serde_json::from_str::<RxJsonRpcMessage<RoleServer>>(line).unwrap_err();
+        reply_to_unparseable(line, error).map(|reply| serde_json::from_str(&reply).unwrap())
+    }
+
+    #[test]
+    fn test_unparseable_lines_get_json_rpc_errors() {
+        let unknown = reply(r#"{"jsonrpc":"2.0","id":4,"method":"lights/dim"}"#).unwrap();
+        assert_eq!((unknown["id"].as_u64(), unknown["error"]["code"].as_i64()), (Some(4), Some(-32601)));
+        let malformed = reply("{not json").unwrap();
+        assert_eq!((malformed["id"].is_null(), malformed["error"]["code"].as_i64()), (true, Some(-32700)));
+        let bad_params = reply(r#"{"jsonrpc":"2.0","id":"a","method":"resources/read","params":{"uri":7}}"#).unwrap();
+        assert_eq!((bad_params["id"].as_str(), bad_params["error"]["code"].as_i64()), (Some("a"), Some(-32602)));
+        assert!(reply(r#"{"jsonrpc":"2.0","method":"lights/dimmed","params":7}"#).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_survives_a_bad_line() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (read, write) = tokio::io::split(server);
+        let mut transport = LineTransport::new(read, write);
+        let (client_read, mut client_write) = tokio::io::split(client);
+        client_write.write_all(b"nonsense\n\n{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n").await.unwrap();
+
+        let message = transport.receive().await.unwrap();
+        assert!(matches!(message, RxJsonRpcMessage::<RoleServer>::Request(ref request) if request.id.to_string() == "1"));
+        let reply = BufReader::new(client_read).lines().next_line().await.unwrap().unwrap();
+        assert!(reply.contains("\"code\":-32700"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_end_of_input_waits_for_owed_responses() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (read, write) = tokio::io::split(server);
+        let mut transport = LineTransport::new(read, write);
+        let (client_read, mut client_write) = tokio::io::split(client);
+        client_write.write_all(b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"ping\"}\n").await.unwrap();
+        client_write.shutdown().await.unwrap();
+        drop(client_read);
+        transport.receive().await.unwrap();
+
+        let started = tokio::time::Instant::now();
+        let response = JsonRpcMessage::response(rmcp::model::ServerResult::empty(()), rmcp::model::NumberOrString::Number(1));
+        let answer = transport.send(response);
+        let (end, _) = tokio::join!(transport.receive(), async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            answer.await
+        });
+        assert!(end.is_none());
+        assert!(started.elapsed() >= Duration::from_secs(1) && started.elapsed() < DRAIN_TIMEOUT);
+    }
+}
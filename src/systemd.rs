@@ -0,0 +1,102 @@
+use std::ffi::OsStr;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+use tokio::time::MissedTickBehavior;
+
+use crate::actor::LightHandle;
+use crate::error::LightError;
+
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+
+// Sends `state`, e.g. "READY=1", to the service manager; false when not started as a Type=notify service
+pub fn notify(state: &str) -> std::io::Result<bool> {
+    match std::env::var_os(NOTIFY_SOCKET_ENV) {
+        Some(socket) => notify_socket(&socket, state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+// A path, or a name in the abstract namespace when it starts with '@'
+fn notify_socket(socket: &OsStr, state: &str) -> std::io::Result<()> {
+    let sender = UnixDatagram::unbound()?;
+    match socket.to_str().and_then(|socket| socket.strip_prefix('@')) {
+        Some(name) => send_abstract(&sender, name, state),
+        None => sender.send_to(state.as_bytes(), socket).map(|_| ()),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(sender: &UnixDatagram, name: &str, state: &str) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+    sender.send_to_addr(state.as_bytes(), &address).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_sender: &UnixDatagram, name: &str, _state: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, format!("abstract notify socket @{} needs Linux", name)))
+}
+
+// How often systemd expects a watchdog ping, if WatchdogSec= is set for this process
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec = std::env::var(WATCHDOG_USEC_ENV).ok()?;
+    parse_watchdog(&usec, std::env::var(WATCHDOG_PID_ENV).ok().as_deref(), std::process::id())
+}
+
+fn parse_watchdog(usec: &str, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec.parse().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec))
+}
+
+// Pings the watchdog twice an interval for as long as the light actor keeps answering, so systemd restarts a
+// server whose actor has hung
+pub fn spawn_watchdog(light: LightHandle, interval: Duration) {
+    tokio::spawn(async move {
+        let period = interval / 2;
+        let mut ticks = tokio::time::interval(period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            match tokio::time::timeout(period, light.ping()).await {
+                Ok(Ok(())) => {
+                    if let Err(e) = notify("WATCHDOG=1") {
+                        crate::diagnostic!(Warn, "could not ping the systemd watchdog: {}", e);
+                    }
+                },
+                Ok(Err(LightError::ActorStopped)) => return,
+                _ => {},
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_is_only_for_its_own_process() {
+        assert_eq!(parse_watchdog("30000000", None, 42), Some(Duration::from_secs(30)));
+        assert_eq!(parse_watchdog("30000000", Some("42"), 42), Some(Duration::from_secs(30)));
+        assert_eq!(parse_watchdog("30000000", Some("7"), 42), None);
+        assert_eq!(parse_watchdog("0", None, 42), None);
+    }
+
+    #[test]
+    fn test_notify_sends_one_datagram() {
+        let path = std::env::temp_dir().join(format!("lightbulb-notify-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+        notify_socket(path.as_os_str(), "READY=1").unwrap();
+        let mut buffer = [0; 64];
+        let read = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..read], b"READY=1");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,126 @@
+use chrono::NaiveTime;
+
+use crate::config::TagRuleConfig;
+use crate::error::LightError;
+use crate::model::PowerState;
+use crate::schedule::in_window;
+
+pub const MAX_TAGS: usize = 8;
+const MAX_TAG_LEN: usize = 32;
+
+// Tags are short lowercase words, so they stay one token in the log line and keep queries simple
+pub fn normalize_tag(tag: &str) -> Result<String, LightError> {
+    let tag = tag.trim().to_lowercase();
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if tag.is_empty() || tag.len() > MAX_TAG_LEN || !tag.chars().all(valid_char) {
+        return Err(LightError::InvalidParameter(format!(
+            "Tag '{}' must be 1 to {} letters, digits, '-' or '_'",
+            tag, MAX_TAG_LEN
+        )));
+    }
+    Ok(tag)
+}
+
+// Normalizes and de-duplicates explicit tags, keeping their order
+pub fn normalize_tags(tags: &[String]) -> Result<Vec<String>, LightError> {
+    if tags.len() > MAX_TAGS {
+        return Err(LightError::InvalidParameter(format!("At most {} tags can be given", MAX_TAGS)));
+    }
+    let mut normalized = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag)?;
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    Ok(normalized)
+}
+
+// A config rule that tags every matching power change, e.g. "after-midnight" between 00:00 and 05:00
+#[derive(Debug, Clone)]
+pub struct TagRule {
+    config: TagRuleConfig,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+}
+
+impl TagRule {
+    pub fn new(mut config: TagRuleConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let (after, before) = config.window()?;
+        config.tag = normalize_tag(&config.tag)?;
+        Ok(Self { config, after, before })
+    }
+
+    fn matches(&self, power: PowerState, who: Option<&str>, now: NaiveTime) -> bool {
+        let power_matches = self.config.to.as_deref().is_none_or(|to| to.eq_ignore_ascii_case(power.log_action()));
+        let who_matches = self.config.by.as_deref().is_none_or(|by| who == Some(by));
+        power_matches && who_matches && in_window(now, self.after, self.before)
+    }
+}
+
+// The explicit tags followed by those of every matching rule
+pub fn tags_for(explicit: &[String], rules: &[TagRule], power: PowerState, who: Option<&str>, now: NaiveTime) -> Vec<String> {
+    let mut tags = explicit.to_vec();
+    for rule in rules.iter().filter(|rule| rule.matches(power, who, now)) {
+        if !tags.contains(&rule.config.tag) {
+            tags.push(rule.config.tag.clone());
+        }
+    }
+    tags
+}
+
+// Written right after the action (and its UNDO/REDO marker): "Lightbulb turned ON [movie,after-midnight] by ..."
+pub fn format_tags(tags: &[String]) -> String {
+    format!("[{}]", tags.join(","))
+}
+
+// Reads tags back from the words following the action in a log entry
+pub fn parse_tags<'a>(mut words: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let word = words.find(|word| !(word.starts_with('(') && word.ends_with(')')));
+    word.and_then(|word| word.strip_prefix('[')?.strip_suffix(']'))
+        .map(|tags| tags.split(',').filter(|tag| !tag.is_empty()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(time: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(time, "%H:%M").unwrap()
+    }
+
+    #[test]
+    fn test_tags_are_normalized() {
+        let tags = normalize_tags(&[" Movie ".to_string(), "movie".to_string(), "late_night".to_string()]).unwrap();
+        assert_eq!(tags, vec!["movie", "late_night"]);
+        assert!(normalize_tag("movie night").is_err());
+        assert!(normalize_tag("").is_err());
+        assert!(normalize_tags(&vec!["a".to_string(); MAX_TAGS + 1]).is_err());
+    }
+
+    #[test]
+    fn test_rules_add_matching_tags() {
+        let config = TagRuleConfig {
+            tag: "after-midnight".to_string(),
+            after: Some("00:00".to_string()),
+            before: Some("05:00".to_string()),
+            to: Some("on".to_string()),
+            by: None,
+        };
+        let rules = [TagRule::new(config).unwrap()];
+        let explicit = vec!["movie".to_string()];
+        assert_eq!(tags_for(&explicit, &rules, PowerState::On, None, time("01:30")), vec!["movie", "after-midnight"]);
+        assert_eq!(tags_for(&explicit, &rules, PowerState::Off, None, time("01:30")), vec!["movie"]);
+        assert_eq!(tags_for(&[], &rules, PowerState::On, Some("agent"), time("22:00")), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_parse_tags_after_the_action() {
+        let tags = |words: &str| parse_tags(words.split_whitespace());
+        assert_eq!(tags("(UNDO) [movie,after-midnight] by agent"), vec!["movie", "after-midnight"]);
+        assert_eq!(tags("[movie] sig=abc"), vec!["movie"]);
+        assert!(tags("by [agent] (reason: [not,tags])").is_empty());
+    }
+}
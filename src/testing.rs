@@ -0,0 +1,190 @@
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, ClientInfo, ClientRequest, Implementation, LoggingMessageNotificationParam, Meta, ReadResourceRequest,
+    ReadResourceRequestParam, ReadResourceResult, Resource, ResourceContents, ResourceUpdatedNotificationParam, ServerResult, SubscribeRequestParam,
+};
+use rmcp::service::{NotificationContext, PeerRequestOptions, RunningService};
+use rmcp::{ClientHandler, ErrorData, RoleClient, ServiceError, ServiceExt, serve_server};
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+
+use crate::LightService;
+use crate::reply;
+use crate::service::IF_MODIFIED_SINCE;
+
+const DUPLEX_BUFFER: usize = 64 * 1024;
+pub const TEST_CLIENT_NAME: &str = "test-client";
+
+// Client side of a test session, keeping the logging and resource-updated notifications the server sends
+struct Recorder {
+    info: ClientInfo,
+    logged: mpsc::UnboundedSender<LoggingMessageNotificationParam>,
+    updated: mpsc::UnboundedSender<String>,
+}
+
+impl ClientHandler for Recorder {
+    fn get_info(&self) -> ClientInfo {
+        self.info.clone()
+    }
+
+    async fn on_logging_message(&self, params: LoggingMessageNotificationParam, _context: NotificationContext<RoleClient>) {
+        let _ = self.logged.send(params);
+    }
+
+    async fn on_resource_updated(&self, params: ResourceUpdatedNotificationParam, _context: NotificationContext<RoleClient>) {
+        let _ = self.updated.send(params.uri);
+    }
+}
+
+// An MCP client connected to a LightService over an in-memory transport, so tests go through the real protocol path
+pub struct TestClient {
+    client: RunningService<RoleClient, Recorder>,
+    server: JoinHandle<anyhow::Result<()>>,
+    logged: Mutex<mpsc::UnboundedReceiver<LoggingMessageNotificationParam>>,
+    updated: Mutex<mpsc::UnboundedReceiver<String>>,
+}
+
+impl TestClient {
+    pub async fn connect(service: LightService) -> anyhow::Result<Self> {
+        Self::connect_as(service, TEST_CLIENT_NAME).await
+    }
+
+    // Connects with `name` as the client name, which the server attributes changes to
+    pub async fn connect_as(service: LightService, name: &str) -> anyhow::Result<Self> {
+        let (server_side, client_side) = tokio::io::duplex(DUPLEX_BUFFER);
+        let server = tokio::spawn(async move {
+            serve_server(service, server_side).await?.waiting().await?;
+            Ok(())
+        });
+        let (sender, logged) = mpsc::unbounded_channel();
+        let (updates, updated) = mpsc::unbounded_channel();
+        let info = ClientInfo {
+            client_info: Implementation { name: name.to_string(), version: env!("CARGO_PKG_VERSION").to_string() },
+            ..Default::default()
+        };
+        let client = Recorder { info, logged: sender, updated: updates }.serve(client_side).await?;
+        Ok(Self { client, server, logged: Mutex::new(logged), updated: Mutex::new(updated) })
+    }
+
+    // Calls a tool, returning its text or the error the server sent, whether as a JSON-RPC error or an isError result
+    pub async fn call(&self, tool: &str, arguments: serde_json::Value) -> Result<String, ErrorData> {
+        Ok(reply::text_of(&self.call_result(tool, arguments).await?))
+    }
+
+    // Calls a tool that answers with JSON, returning the JSON
+    pub async fn call_json(&self, tool: &str, arguments: serde_json::Value) -> Result<serde_json::Value, ErrorData> {
+        let result = self.call_result(tool, arguments).await?;
+        reply::json_of(&result).ok_or_else(|| ErrorData::internal_error(format!("{} did not answer with JSON", tool), None))
+    }
+
+    // Calls a tool, returning its whole result, images and all
+    pub async fn call_result(&self, tool: &str, arguments: serde_json::Value) -> Result<CallToolResult, ErrorData> {
+        let request = CallToolRequestParam { name: tool.to_string().into(), arguments: arguments.as_object().cloned() };
+        let result = self.client.call_tool(request).await.map_err(into_error_data)?;
+        match reply::error_of(&result) {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    }
+
+    pub async fn tools(&self) -> Result<Vec<String>, ErrorData> {
+        let tools = self.client.list_all_tools().await.map_err(into_error_data)?;
+        Ok(tools.into_iter().map(|tool| tool.name.to_string()).collect())
+    }
+
+    pub async fn resources(&self) -> Result<Vec<String>, ErrorData> {
+        let resources = self.client.list_all_resources().await.map_err(into_error_data)?;
+        Ok(resources.into_iter().map(|resource| resource.raw.uri).collect())
+    }
+
+    // Every resource the server lists, with its annotations
+    pub async fn listed(&self) -> Result<Vec<Resource>, ErrorData> {
+        self.client.list_all_resources().await.map_err(into_error_data)
+    }
+
+    // Reads a text resource
+    pub async fn read(&self, uri: &str) -> Result<String, ErrorData> {
+        let request = ReadResourceRequestParam { uri: uri.to_string() };
+        let result = self.client.read_resource(request).await.map_err(into_error_data)?;
+        Ok(text_of(result))
+    }
+
+    // Reads a text resource only if it changed since `since`, returning None when the server says it did not
+    pub async fn read_if_modified(&self, uri: &str, since: &str) -> Result<Option<String>, ErrorData> {
+        let request = ClientRequest::ReadResourceRequest(ReadResourceRequest::new(ReadResourceRequestParam { uri: uri.to_string() }));
+        let mut condition = Meta::new();
+        condition.insert(IF_MODIFIED_SINCE.to_string(), since.into());
+        let options = PeerRequestOptions { meta: Some(condition), ..PeerRequestOptions::no_options() };
+        let handle = self.client.send_cancellable_request(request, options).await.map_err(into_error_data)?;
+        match handle.await_response().await.map_err(into_error_data)? {
+            ServerResult::ReadResourceResult(result) if result.contents.is_empty() => Ok(None),
+            ServerResult::ReadResourceResult(result) => Ok(Some(text_of(result))),
+            other => Err(ErrorData::internal_error(format!("Unexpected response to a resource read: {:?}", other), None)),
+        }
+    }
+
+    // Waits for the next logging notification; wrap it in a timeout when one may never come
+    pub async fn next_log_message(&self) -> Option<LoggingMessageNotificationParam> {
+        self.logged.lock().await.recv().await
+    }
+
+    pub async fn subscribe(&self, uri: &str) -> Result<(), ErrorData> {
+        self.client.subscribe(SubscribeRequestParam { uri: uri.to_string() }).await.map_err(into_error_data)
+    }
+
+    // Waits for the next resource-updated notification, returning the resource's URI
+    pub async fn next_resource_update(&self) -> Option<String> {
+        self.updated.lock().await.recv().await
+    }
+
+    // Discards the logging notifications received so far, returning how many there were
+    pub async fn drain_log_messages(&self) -> usize {
+        let mut logged = self.logged.lock().await;
+        std::iter::from_fn(|| logged.try_recv().ok()).count()
+    }
+
+    // Ends the session and waits for the server side to finish
+    pub async fn close(self) -> anyhow::Result<()> {
+        self.client.cancel().await?;
+        self.server.await?
+    }
+}
+
+fn text_of(result: ReadResourceResult) -> String {
+    result
+        .contents
+        .into_iter()
+        .filter_map(|contents| match contents {
+            ResourceContents::TextResourceContents { text, .. } => Some(text),
+            ResourceContents::BlobResourceContents { .. } => None,
+        })
+        .collect()
+}
+
+fn into_error_data(error: ServiceError) -> ErrorData {
+    match error {
+        ServiceError::McpError(error) => error,
+        other => ErrorData::internal_error(other.to_string(), None),
+    }
+}
+
+#[cfg(all(test, feature = "core"))]
+mod tests {
+    use super::*;
+    use crate::logger::InMemoryLogger;
+
+    #[tokio::test]
+    async fn test_client_calls_tools_over_the_protocol() {
+        let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).build();
+        let client = TestClient::connect(service).await.unwrap();
+        assert!(client.tools().await.unwrap().contains(&"turn_on_lightbulb".to_string()));
+        assert!(client.call("turn_on_lightbulb", serde_json::json!({})).await.unwrap().starts_with("Lightbulb turned on"));
+        assert!(client.read("lightbulb://log").await.unwrap().contains("turned ON by test-client"));
+
+        let error = client.call("set_color_by_name", serde_json::json!({ "name": "no such color" })).await.unwrap_err();
+        assert_eq!(error.data.unwrap()["code"], "INVALID_PARAMETER");
+        let result = client.call_result("set_color_by_name", serde_json::json!({ "name": "red" })).await.unwrap();
+        assert_eq!(result.content.unwrap()[1].as_image().map(|image| image.mime_type.as_str()), Some("image/png"));
+        assert_eq!(client.call_json("get_lightbulb_state", serde_json::json!({})).await.unwrap()["color"], serde_json::json!({ "r": 255, "g": 0, "b": 0 }));
+        client.close().await.unwrap();
+    }
+}
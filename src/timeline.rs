@@ -0,0 +1,88 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::stats::LogRecord;
+
+// One entry of a merged history, with the bulb whose log it came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BulbEntry {
+    pub bulb: String,
+    #[serde(flatten)]
+    pub record: LogRecord,
+}
+
+impl BulbEntry {
+    // "[timestamp] bulb: message", or "bulb: message" for a line without a timestamp
+    pub fn line(&self) -> String {
+        match self.record.at {
+            Some(at) => format!("[{}] {}: {}", at.to_rfc3339(), self.bulb, self.record.message),
+            None => format!("{}: {}", self.bulb, self.record.message),
+        }
+    }
+}
+
+// Several bulbs' logs as one timeline
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MergedHistory {
+    pub entries: Vec<BulbEntry>,
+    // Entries left out because the same bulb's logs already had them, e.g. from two servers sharing a log
+    pub duplicates: usize,
+}
+
+impl MergedHistory {
+    pub fn text(&self) -> String {
+        self.entries.iter().map(|entry| format!("{}\n", entry.line())).collect()
+    }
+}
+
+// Merges the logs of several servers, given as (bulb, log) pairs, into one timeline. Each entry is attributed to its
+// bulb, and one a bulb's logs hold twice, signed or not, is kept once; the same change on two bulbs is two entries.
+// A line without a timestamp stays behind the entry it followed.
+pub fn merge_histories<'a>(logs: impl IntoIterator<Item = (&'a str, &'a str)>) -> MergedHistory {
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    let mut duplicates = 0;
+    for (bulb, log) in logs {
+        let mut last = DateTime::<Utc>::MIN_UTC;
+        for line in log.lines().filter(|line| !line.trim().is_empty()) {
+            let record = LogRecord::parse(line);
+            last = record.at.unwrap_or(last);
+            if !seen.insert((bulb.to_string(), record.at, record.message.clone())) {
+                duplicates += 1;
+                continue;
+            }
+            entries.push((last, BulbEntry { bulb: bulb.to_string(), record }));
+        }
+    }
+    entries.sort_by_key(|(at, _)| *at);
+    MergedHistory { entries: entries.into_iter().map(|(_, entry)| entry).collect(), duplicates }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histories_merge_per_bulb_without_duplicates() {
+        let kitchen = "[2025-08-02T10:00:00+00:00] Lightbulb turned ON [morning]\n[2025-08-02T12:00:00+00:00] Lightbulb turned OFF\n";
+        // A second server sharing the kitchen's log signs its copy of the same entry
+        let kitchen_copy = "[2025-08-02T12:00:00+00:00] Lightbulb turned OFF sig=abc\nnot a dated line\n";
+        let porch = "[2025-08-02T11:00:00+00:00] Lightbulb turned ON\n[2025-08-02T12:00:00+00:00] Lightbulb turned OFF\n";
+        let merged = merge_histories([("kitchen", kitchen), ("porch", porch), ("kitchen", kitchen_copy)]);
+
+        assert_eq!(merged.duplicates, 1);
+        let lines: Vec<String> = merged.entries.iter().map(BulbEntry::line).collect();
+        assert_eq!(lines, [
+            "[2025-08-02T10:00:00+00:00] kitchen: Lightbulb turned ON [morning]",
+            "[2025-08-02T11:00:00+00:00] porch: Lightbulb turned ON",
+            "[2025-08-02T12:00:00+00:00] kitchen: Lightbulb turned OFF",
+            "[2025-08-02T12:00:00+00:00] porch: Lightbulb turned OFF",
+            "kitchen: not a dated line",
+        ]);
+        assert_eq!(merged.entries[0].record.tags, ["morning"]);
+        let json = serde_json::to_value(&merged.entries[1]).unwrap();
+        assert_eq!((json["bulb"].as_str(), json["power"].as_str()), (Some("porch"), Some("on")));
+    }
+}
@@ -0,0 +1,328 @@
+use std::net::SocketAddr;
+
+pub const TRANSPORT_FLAG: &str = "--transport";
+pub const LISTEN_FLAG: &str = "--listen";
+// When set, every HTTP request must carry it, a guest or admin key, or a JWT `[jwt]` accepts, as
+// `Authorization: Bearer <token>`
+pub const BEARER_TOKEN_ENV: &str = "LIGHTBULB_BEARER_TOKEN";
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+#[cfg(feature = "http")]
+pub const MCP_PATH: &str = "/mcp";
+
+// How MCP clients reach the server, from `--transport` and `--listen`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Transport {
+    Stdio,
+    // rmcp's streamable HTTP transport, at MCP_PATH
+    Http { listen: SocketAddr },
+}
+
+impl Transport {
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let (mut kind, mut listen) = (None, None);
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let slot = match arg.as_str() {
+                TRANSPORT_FLAG => &mut kind,
+                LISTEN_FLAG => &mut listen,
+                _ => continue,
+            };
+            *slot = Some(args.next().ok_or_else(|| anyhow::anyhow!("{} needs a value", arg))?);
+        }
+        match kind.as_deref() {
+            None | Some("stdio") if listen.is_some() => anyhow::bail!("{} only applies to {} http", LISTEN_FLAG, TRANSPORT_FLAG),
+            None | Some("stdio") => Ok(Transport::Stdio),
+            Some("http") => {
+                let listen = listen.as_deref().unwrap_or(DEFAULT_LISTEN);
+                let listen = listen.parse().map_err(|_| anyhow::anyhow!("{} takes an address such as {}, not {:?}", LISTEN_FLAG, DEFAULT_LISTEN, listen))?;
+                Ok(Transport::Http { listen })
+            },
+            Some(other) => anyhow::bail!("Unknown transport {:?}; use stdio or http", other),
+        }
+    }
+
+    // As the server reports it in server_info and lightbulb://config
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transport::Stdio => "stdio",
+            Transport::Http { .. } => "http",
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+pub use http::{HttpAuth, router, serve_http};
+
+#[cfg(feature = "http")]
+mod http {
+    use std::sync::Arc;
+
+    use axum::extract::{Request, State};
+    use axum::http::{StatusCode, header};
+    use axum::middleware::{self, Next};
+    use axum::response::{IntoResponse, Response};
+    use axum::Router;
+    use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
+    use rmcp::transport::streamable_http_server::{StreamableHttpServerConfig, StreamableHttpService};
+    use tokio::net::TcpListener;
+
+    use super::MCP_PATH;
+    use crate::LightService;
+    use crate::auth::{JwtValidator, KeyAccess};
+
+    // The bearer tokens requests at MCP_PATH are let in with: the static token, which may call every tool, guest
+    // and admin keys, whose requests are served as guest and admin sessions', and JWTs the validator accepts, which
+    // may call the tools their scopes allow; with none of them, every request is let in
+    #[derive(Debug, Clone, Default)]
+    pub struct HttpAuth {
+        pub token: Option<String>,
+        pub guest_keys: Vec<String>,
+        pub admin_keys: Vec<String>,
+        pub jwt: Option<Arc<JwtValidator>>,
+    }
+
+    impl HttpAuth {
+        // Nothing to check a bearer token against, so every request gets through
+        pub fn is_open(&self) -> bool {
+            self.token.is_none() && self.guest_keys.is_empty() && self.admin_keys.is_empty() && self.jwt.is_none()
+        }
+
+        // A guest key listed as an admin one too stays a guest's
+        fn key_access(&self, given: &str) -> Option<KeyAccess> {
+            let matches = |keys: &[String]| keys.iter().fold(false, |found, key| found | same(given.as_bytes(), key.as_bytes()));
+            match (matches(&self.guest_keys), matches(&self.admin_keys)) {
+                (true, _) => Some(KeyAccess::Guest),
+                (false, true) => Some(KeyAccess::Admin),
+                (false, false) => None,
+            }
+        }
+    }
+
+    // Serves a session of its own, made by `session`, to every client that initializes one at MCP_PATH
+    pub fn router<F>(session: F, auth: HttpAuth) -> Router
+    where
+        F: Fn() -> LightService + Send + Sync + 'static,
+    {
+        let service = StreamableHttpService::new(move || Ok(session()), Arc::new(LocalSessionManager::default()), StreamableHttpServerConfig::default());
+        let router = Router::new().nest_service(MCP_PATH, service);
+        match auth.is_open() {
+            true => router,
+            false => router.layer(middleware::from_fn_with_state(Arc::new(auth), require_bearer)),
+        }
+    }
+
+    pub async fn serve_http<F>(listener: TcpListener, session: F, auth: HttpAuth) -> std::io::Result<()>
+    where
+        F: Fn() -> LightService + Send + Sync + 'static,
+    {
+        axum::serve(listener, router(session, auth)).await
+    }
+
+    // A key's access or a JWT's scopes go along with the request, in its extensions, for the service to serve it
+    // by. They are decided for each request, so every client of the server is held to its own token.
+    async fn require_bearer(State(auth): State<Arc<HttpAuth>>, mut request: Request, next: Next) -> Response {
+        let given = request.headers().get(header::AUTHORIZATION).and_then(|value| value.to_str().ok()).and_then(|value| value.strip_prefix("Bearer "));
+        let Some(given) = given else {
+            return unauthorized("Bearer".to_string(), "A valid bearer token is required");
+        };
+        if auth.token.as_ref().is_some_and(|token| same(given.as_bytes(), token.as_bytes())) {
+            return next.run(request).await;
+        }
+        if let Some(access) = auth.key_access(given) {
+            request.extensions_mut().insert(access);
+            return next.run(request).await;
+        }
+        let Some(jwt) = &auth.jwt else {
+            return unauthorized("Bearer".to_string(), "A valid bearer token is required");
+        };
+        match jwt.validate(given) {
+            Ok(grant) if grant.scopes.is_empty() => {
+                let challenge = r#"Bearer error="insufficient_scope", scope="light:read light:write light:admin""#;
+                (StatusCode::FORBIDDEN, [(header::WWW_AUTHENTICATE, challenge)], "The bearer token grants no light: scope\n").into_response()
+            },
+            Ok(grant) => {
+                request.extensions_mut().insert(grant);
+                next.run(request).await
+            },
+            Err(error) => {
+                let challenge = format!(r#"Bearer error="invalid_token", error_description="{}""#, error.to_string().replace('"', "'"));
+                unauthorized(challenge, &format!("The bearer token is invalid: {}", error))
+            },
+        }
+    }
+
+    fn unauthorized(challenge: String, message: &str) -> Response {
+        (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, challenge)], format!("{}\n", message)).into_response()
+    }
+
+    // Takes as long whichever byte differs, so the token cannot be guessed a byte at a time
+    fn same(given: &[u8], token: &[u8]) -> bool {
+        given.len() == token.len() && given.iter().zip(token).fold(0, |differ, (a, b)| differ | (a ^ b)) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> anyhow::Result<Transport> {
+        Transport::from_args(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn test_transport_flags() {
+        assert_eq!(parse("--guest").unwrap(), Transport::Stdio);
+        assert_eq!(parse("--transport http").unwrap(), Transport::Http { listen: DEFAULT_LISTEN.parse().unwrap() });
+        assert_eq!(parse("--transport http --listen 0.0.0.0:9000").unwrap().name(), "http");
+        assert!(parse("--listen 0.0.0.0:9000").is_err());
+        assert!(parse("--transport http --listen localhost").is_err());
+        assert!(parse("--transport sse").is_err());
+        assert!(parse("--transport").is_err());
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_http_requires_the_bearer_token() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use tower::ServiceExt;
+
+        use crate::logger::InMemoryLogger;
+        use crate::{LightService, SessionFactory};
+
+        let sessions = SessionFactory::Shared(LightService::builder().logger(Box::new(InMemoryLogger::new())).build());
+        let app = router(move || sessions.create(), HttpAuth { token: Some("s3cret".to_string()), ..HttpAuth::default() });
+        let initialize = |token: Option<&str>| {
+            let body = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26","capabilities":{},"clientInfo":{"name":"test","version":"1"}}}"#;
+            let request = Request::post(MCP_PATH).header("Content-Type", "application/json").header("Accept", "application/json, text/event-stream");
+            let request = match token {
+                Some(token) => request.header("Authorization", format!("Bearer {}", token)),
+                None => request,
+            };
+            request.body(Body::from(body)).unwrap()
+        };
+        assert_eq!(app.clone().oneshot(initialize(None)).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(app.clone().oneshot(initialize(Some("guess"))).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let response = app.oneshot(initialize(Some("s3cret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key("mcp-session-id"));
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_http_jwt_scopes_decide_the_tools_a_client_may_call() {
+        use std::sync::Arc;
+
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use serde_json::{Value, json};
+        use tower::ServiceExt;
+
+        use crate::auth::{JwtValidator, hs256_token};
+        use crate::config::Config;
+        use crate::logger::InMemoryLogger;
+        use crate::{LightService, SessionFactory};
+
+        let secret = "0123456789abcdef0123456789abcdef";
+        let config = Config::parse(&format!("[jwt]\nsecret = \"{}\"", secret)).unwrap();
+        let jwt = Some(Arc::new(JwtValidator::new(config.jwt.as_ref().unwrap(), None).unwrap()));
+        let sessions = SessionFactory::Shared(LightService::builder().logger(Box::new(InMemoryLogger::new())).build());
+        let app = router(move || sessions.create(), HttpAuth { jwt, ..HttpAuth::default() });
+        let now = chrono::Utc::now().timestamp();
+        let token = |scope: &str, exp: i64| hs256_token(secret, &json!({ "sub": "tablet", "exp": exp, "scope": scope }));
+        let post = |token: &str, session: Option<&str>, body: Value| {
+            let request = Request::post(MCP_PATH)
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json, text/event-stream")
+                .header("Authorization", format!("Bearer {}", token));
+            let request = match session {
+                Some(session) => request.header("mcp-session-id", session),
+                None => request,
+            };
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+        // The first JSON-RPC message of a response streamed as server-sent events
+        async fn message(response: axum::response::Response) -> Value {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            let data = body.lines().find_map(|line| line.strip_prefix("data:")).unwrap_or_else(|| panic!("no event in {:?}", body));
+            serde_json::from_str(data.trim()).unwrap()
+        }
+        let initialize = json!({"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26","capabilities":{},"clientInfo":{"name":"test","version":"1"}}});
+
+        let response = app.clone().oneshot(post(&token("light:read", now - 3600), None, initialize.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers()["www-authenticate"].to_str().unwrap().contains(r#"error="invalid_token""#));
+        let forged = hs256_token("fedcba9876543210fedcba9876543210", &json!({ "exp": now + 60, "scope": "light:admin" }));
+        assert_eq!(app.clone().oneshot(post(&forged, None, initialize.clone())).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+        let response = app.clone().oneshot(post(&token("openid", now + 60), None, initialize.clone())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let reader = token("light:read", now + 60);
+        let response = app.clone().oneshot(post(&reader, None, initialize)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let session = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+        let initialized = json!({"jsonrpc":"2.0","method":"notifications/initialized"});
+        assert_eq!(app.clone().oneshot(post(&reader, Some(&session), initialized)).await.unwrap().status(), StatusCode::ACCEPTED);
+
+        let list = app.clone().oneshot(post(&reader, Some(&session), json!({"jsonrpc":"2.0","id":2,"method":"tools/list"}))).await.unwrap();
+        let tools: Vec<String> = message(list).await["result"]["tools"].as_array().unwrap().iter().map(|tool| tool["name"].as_str().unwrap().to_string()).collect();
+        assert!(tools.contains(&"get_lightbulb_status".to_string()), "{:?}", tools);
+        assert!(!tools.contains(&"turn_on_lightbulb".to_string()), "{:?}", tools);
+
+        let call = json!({"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"turn_on_lightbulb","arguments":{}}});
+        let refused = message(app.clone().oneshot(post(&reader, Some(&session), call.clone())).await.unwrap()).await;
+        assert_eq!(refused["error"]["data"], json!({ "code": "INSUFFICIENT_SCOPE", "tool": "turn_on_lightbulb", "scope": "light:write" }));
+        // The session's scopes come from each request's token, not from the one it was initialized with
+        let writer = token("light:write", now + 60);
+        let switched = message(app.oneshot(post(&writer, Some(&session), call)).await.unwrap()).await;
+        assert_eq!(switched["result"]["isError"], false, "{}", switched);
+    }
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn test_http_guest_key_is_held_to_guest_tools_on_a_shared_session() {
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use serde_json::{Value, json};
+        use tower::ServiceExt;
+
+        use crate::logger::InMemoryLogger;
+        use crate::{LightService, SessionFactory};
+
+        let sessions = SessionFactory::Shared(LightService::builder().logger(Box::new(InMemoryLogger::new())).build());
+        let auth = HttpAuth { token: Some("s3cret".to_string()), guest_keys: vec!["guest-123".to_string()], ..HttpAuth::default() };
+        let app = router(move || sessions.create(), auth);
+        let post = |token: &str, session: Option<&str>, body: Value| {
+            let request = Request::post(MCP_PATH)
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json, text/event-stream")
+                .header("Authorization", format!("Bearer {}", token));
+            let request = match session {
+                Some(session) => request.header("mcp-session-id", session),
+                None => request,
+            };
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+        let tools = |response: axum::response::Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            let data = body.lines().find_map(|line| line.strip_prefix("data:")).unwrap_or_else(|| panic!("no event in {:?}", body));
+            let message: Value = serde_json::from_str(data.trim()).unwrap();
+            message["result"]["tools"].as_array().unwrap().iter().map(|tool| tool["name"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        };
+        let initialize = json!({"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2025-03-26","capabilities":{},"clientInfo":{"name":"test","version":"1"}}});
+
+        let response = app.clone().oneshot(post("guest-123", None, initialize)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let session = response.headers()["mcp-session-id"].to_str().unwrap().to_string();
+        let initialized = json!({"jsonrpc":"2.0","method":"notifications/initialized"});
+        assert_eq!(app.clone().oneshot(post("guest-123", Some(&session), initialized)).await.unwrap().status(), StatusCode::ACCEPTED);
+
+        let list = json!({"jsonrpc":"2.0","id":2,"method":"tools/list"});
+        let guest = tools(app.clone().oneshot(post("guest-123", Some(&session), list.clone())).await.unwrap()).await;
+        assert_eq!(guest.len(), 4, "{:?}", guest);
+        // The guest key does not make the session a guest's for the server token on it too
+        let owner = tools(app.oneshot(post("s3cret", Some(&session), list)).await.unwrap()).await;
+        assert!(owner.len() > guest.len(), "{:?}", owner);
+    }
+}
@@ -0,0 +1,189 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use tokio::sync::broadcast;
+
+use crate::clock::SharedClock;
+use crate::config::{TriggerConfig, TriggerMethod};
+use crate::events::{EventBus, LightEvent};
+use crate::schedule::in_window;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Sends one trigger request, returning the HTTP status
+#[async_trait::async_trait]
+pub trait TriggerSender {
+    async fn send(&self, method: TriggerMethod, url: &str, body: Option<&str>) -> anyhow::Result<u16>;
+}
+
+pub struct HttpSender {
+    client: reqwest::Client,
+}
+
+impl HttpSender {
+    pub fn new() -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait::async_trait]
+impl TriggerSender for HttpSender {
+    async fn send(&self, method: TriggerMethod, url: &str, body: Option<&str>) -> anyhow::Result<u16> {
+        let request = match method {
+            TriggerMethod::Get => self.client.get(url),
+            TriggerMethod::Post => self
+                .client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body.unwrap_or_default().to_string()),
+        };
+        Ok(request.send().await?.status().as_u16())
+    }
+}
+
+// A validated trigger with its time window parsed once up front
+pub struct Trigger {
+    config: TriggerConfig,
+    after: Option<NaiveTime>,
+    before: Option<NaiveTime>,
+}
+
+impl Trigger {
+    pub fn new(config: TriggerConfig) -> anyhow::Result<Self> {
+        config.validate()?;
+        let (after, before) = config.window()?;
+        Ok(Self { config, after, before })
+    }
+
+    fn matches(&self, event: &LightEvent, now: NaiveTime) -> bool {
+        let kind_matches = self.config.events.is_empty() || self.config.events.iter().any(|kind| kind == event.kind.as_str());
+        let state_matches = self.config.to.as_deref().is_none_or(|to| to.eq_ignore_ascii_case(event.to));
+        kind_matches && state_matches && in_window(now, self.after, self.before)
+    }
+}
+
+// Fills {event}, {action}, {from}, {to}, {sequence} and {at}, passing each value through `encode`
+fn render(template: &str, event: &LightEvent, encode: fn(&str) -> String) -> String {
+    let values = [
+        ("{event}", event.kind.as_str().to_string()),
+        ("{action}", event.action.clone()),
+        ("{from}", event.from.to_string()),
+        ("{to}", event.to.to_string()),
+        ("{sequence}", event.sequence.to_string()),
+        ("{at}", event.at.to_rfc3339()),
+    ];
+    values
+        .iter()
+        .fold(template.to_string(), |rendered, (placeholder, value)| rendered.replace(placeholder, &encode(value)))
+}
+
+fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// Fires matching triggers for every light event; each request runs on its own task and failures are dropped
+pub fn spawn_triggers(triggers: Vec<Trigger>, sender: Arc<dyn TriggerSender + Send + Sync>, events: &EventBus, clock: SharedClock) {
+    let mut events = events.subscribe();
+    tokio::spawn(async move {
+        loop {
+            let event = match events.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            let now = clock.local_time();
+            for trigger in triggers.iter().filter(|trigger| trigger.matches(&event, now)) {
+                let url = render(&trigger.config.url, &event, url_encode);
+                let body = trigger.config.body.as_ref().map(|body| render(body, &event, str::to_string));
+                let (sender, method) = (sender.clone(), trigger.config.method);
+                tokio::spawn(async move {
+                    let _ = sender.send(method, &url, body.as_deref()).await;
+                });
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::clock::system_clock;
+    use crate::events::EventKind;
+    use crate::state::StateMachine;
+
+    #[derive(Default)]
+    struct RecordingSender {
+        requests: Mutex<Vec<(String, Option<String>)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl TriggerSender for RecordingSender {
+        async fn send(&self, _method: TriggerMethod, url: &str, body: Option<&str>) -> anyhow::Result<u16> {
+            self.requests.lock().unwrap().push((url.to_string(), body.map(String::from)));
+            Ok(200)
+        }
+    }
+
+    fn event(to: &'static str) -> LightEvent {
+        LightEvent {
+            sequence: 3,
+            at: Utc::now(),
+            kind: EventKind::StateChanged,
+            action: "turn_on".to_string(),
+            from: "OFF",
+            to,
+            state: StateMachine::new().snapshot(),
+            log_sequence: None,
+        }
+    }
+
+    fn trigger(to: Option<&str>) -> TriggerConfig {
+        TriggerConfig {
+            name: "test".to_string(),
+            url: "https://example.com/{action}?to={to}".to_string(),
+            method: TriggerMethod::Post,
+            body: Some(r#"{"value1": "{to}", "value2": "{sequence}"}"#.to_string()),
+            events: Vec::new(),
+            to: to.map(String::from),
+            after: None,
+            before: None,
+        }
+    }
+
+    #[test]
+    fn test_render_encodes_urls_only() {
+        let event = event("ON");
+        let url = render("https://example.com/hook?at={at}&n={sequence}", &event, url_encode);
+        assert!(url.ends_with("&n=3"));
+        assert!(!url.contains('+') && url.contains("%3A"));
+        assert_eq!(render("{from} -> {to}", &event, str::to_string), "OFF -> ON");
+    }
+
+    #[tokio::test]
+    async fn test_only_matching_triggers_fire() {
+        let sender = Arc::new(RecordingSender::default());
+        let events = EventBus::new();
+        spawn_triggers(vec![Trigger::new(trigger(Some("ON"))).unwrap()], sender.clone(), &events, system_clock());
+
+        events.publish(event("OFF"));
+        events.publish(event("ON"));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let requests = sender.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0, "https://example.com/turn_on?to=ON");
+        assert_eq!(requests[0].1.as_deref(), Some(r#"{"value1": "ON", "value2": "3"}"#));
+    }
+}
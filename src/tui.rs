@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color as TermColor, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use tokio::runtime::Handle;
+
+use crate::actor::LightHandle;
+use crate::rest::{ClientInfo, ClientRegistry};
+use crate::state::StateSnapshot;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+const LOG_LINES: usize = 50;
+
+// Everything one frame shows, gathered before drawing
+struct View {
+    snapshot: Option<StateSnapshot>,
+    log: Vec<String>,
+    clients: Vec<ClientInfo>,
+}
+
+impl View {
+    async fn load(light: &LightHandle, clients: &ClientRegistry) -> Self {
+        let mut log = light.read_log_tail(LOG_LINES).await.unwrap_or_default();
+        log.reverse();
+        Self {
+            snapshot: light.snapshot().await.ok(),
+            log,
+            clients: clients.list(),
+        }
+    }
+}
+
+// Takes over the terminal until the user presses q, Esc or Ctrl-C
+pub async fn run(light: LightHandle, clients: ClientRegistry, address: String) -> anyhow::Result<()> {
+    let runtime = Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let mut terminal = ratatui::init();
+        let result = event_loop(&mut terminal, &runtime, &light, &clients, &address);
+        ratatui::restore();
+        result
+    })
+    .await?
+}
+
+fn event_loop(
+    terminal: &mut DefaultTerminal,
+    runtime: &Handle,
+    light: &LightHandle,
+    clients: &ClientRegistry,
+    address: &str,
+) -> anyhow::Result<()> {
+    loop {
+        let view = runtime.block_on(View::load(light, clients));
+        terminal.draw(|frame| draw(frame, &view, address))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && (matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                || (key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)))
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, view: &View, address: &str) {
+    let [state_area, log_area, clients_area] =
+        Layout::vertical([Constraint::Length(7), Constraint::Min(5), Constraint::Length(8)]).areas(frame.area());
+
+    let state = match &view.snapshot {
+        Some(snapshot) => {
+            let color = TermColor::Rgb(snapshot.color.r, snapshot.color.g, snapshot.color.b);
+            let lamp = if snapshot.power.is_some_and(|power| power.is_on()) { Span::styled("●", Style::new().fg(color)) } else { Span::raw("○") };
+            vec![
+                Line::from(vec![lamp, Span::raw(" "), Span::raw(snapshot.state).bold(), Span::raw(if snapshot.locked { " (locked)" } else { "" })]),
+                Line::from(format!("Color: {}   Brightness: {}%", snapshot.color, snapshot.brightness)),
+                Line::from(format!("Effect: {}", snapshot.active_effect.as_deref().unwrap_or("none"))),
+                Line::from(format!(
+                    "Last changed: {}{}",
+                    snapshot.last_changed.map_or("never".to_string(), |at| at.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+                    snapshot.changed_by.as_ref().map_or(String::new(), |who| format!(" by {}", who))
+                )),
+            ]
+        },
+        None => vec![Line::from("The light actor has stopped").red()],
+    };
+    let title = format!(" Lightbulb on {} (q to quit) ", address);
+    frame.render_widget(Paragraph::new(state).block(Block::bordered().title(title)), state_area);
+
+    let log = List::new(view.log.iter().map(String::as_str)).block(Block::bordered().title(" Recent activity "));
+    frame.render_widget(log, log_area);
+
+    let clients = view.clients.iter().map(|client| {
+        let streaming = if client.streams > 0 { format!(", {} stream(s) open", client.streams) } else { String::new() };
+        format!("{}  {} request(s), last seen {}s ago{}", client.addr, client.requests, client.last_seen.elapsed().as_secs(), streaming)
+    });
+    let title = format!(" Clients ({}) ", view.clients.len());
+    frame.render_widget(List::new(clients).block(Block::bordered().title(title)), clients_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::logger::InMemoryLogger;
+    use crate::model::PowerState;
+    use crate::state::StateMachine;
+
+    #[tokio::test]
+    async fn test_draws_state_and_log() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        light.set_power(PowerState::On).await.unwrap();
+        let view = View::load(&light, &ClientRegistry::default()).await;
+
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| draw(frame, &view, "127.0.0.1:8080")).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("● ON"));
+        assert!(screen.contains("Lightbulb turned ON"));
+        assert!(screen.contains("Clients (0)"));
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::logger::LOG_SIGNATURE_MARKER;
+use crate::stats::UsageCounters;
+
+// Bumped whenever the counters change what they mean, so a file saved by an older server is scanned again
+const USAGE_CACHE_VERSION: u32 = 1;
+// How far back from the end of the log the saved counters are looked for, and so how many entries written
+// after the save (by a server that crashed before saving again) can be caught up without a full scan
+pub const CATCH_UP_ENTRIES: usize = 1_000;
+
+pub fn usage_cache_path(log_file: &str) -> String {
+    format!("{}.stats", log_file)
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedUsage {
+    version: u32,
+    usage: UsageCounters,
+}
+
+// The usage counters saved beside the log at shutdown, so the first summary after a restart need not scan
+// the whole log again
+#[derive(Debug)]
+pub struct UsageCache {
+    path: String,
+}
+
+impl UsageCache {
+    pub fn new(path: String) -> Self {
+        Self { path }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // The saved counters, or None when nothing was saved or an older server saved them
+    pub async fn load(&self) -> anyhow::Result<Option<UsageCounters>> {
+        let saved = match tokio::fs::read_to_string(&self.path).await {
+            Ok(saved) => saved,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read saved statistics: {}", self.path)),
+        };
+        let saved: SavedUsage = serde_json::from_str(&saved).with_context(|| format!("Unreadable saved statistics: {}", self.path))?;
+        Ok((saved.version == USAGE_CACHE_VERSION).then_some(saved.usage))
+    }
+
+    // Written beside the file and renamed over it, so a crash mid-save leaves the last save whole
+    pub async fn save(&self, usage: &UsageCounters) -> anyhow::Result<()> {
+        let saved = serde_json::to_string(&SavedUsage { version: USAGE_CACHE_VERSION, usage: usage.clone() })?;
+        let temporary = format!("{}.tmp", self.path);
+        tokio::fs::write(&temporary, saved).await.with_context(|| format!("Failed to save statistics: {}", temporary))?;
+        tokio::fs::rename(&temporary, &self.path).await.with_context(|| format!("Failed to save statistics: {}", self.path))
+    }
+}
+
+// Checks saved counters against `tail`, the last entries of the log, holding the whole log when `whole`. The
+// entries the counters saw last must appear in it in order; those after them are counted. None when they do
+// not, as the log was rotated, edited or rewritten since, and must be scanned again.
+pub fn catch_up(mut usage: UsageCounters, tail: &[String], whole: bool) -> Option<UsageCounters> {
+    let recent: Vec<&String> = usage.recent.iter().collect();
+    let seen = match recent.len() {
+        0 if whole && usage == UsageCounters::default() => 0,
+        0 => return None,
+        len => tail.windows(len).rposition(|window| window.iter().zip(&recent).all(|(line, seen)| unsigned(line) == unsigned(seen)))? + len,
+    };
+    for line in &tail[seen..] {
+        usage.record(line);
+    }
+    Some(usage)
+}
+
+// Entries the server wrote itself are counted before they are signed
+fn unsigned(line: &str) -> &str {
+    line.rsplit_once(LOG_SIGNATURE_MARKER).map_or(line, |(entry, _)| entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOG: &str = "[2025-08-02T10:00:00+00:00] Lightbulb turned ON seq=1\n[2025-08-02T11:00:00+00:00] Lightbulb turned OFF seq=2\n";
+
+    fn lines(log: &str) -> Vec<String> {
+        log.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_saved_counters_catch_up_with_the_log() {
+        let saved = UsageCounters::from_log(LOG);
+        let later = format!("{}[2025-08-02T12:00:00+00:00] Lightbulb turned ON seq=3 sig=abc\n", LOG);
+        assert_eq!(catch_up(saved.clone(), &lines(&later), true), Some(UsageCounters::from_log(&later)));
+        assert_eq!(catch_up(saved.clone(), &lines(LOG), true), Some(saved.clone()));
+        // Only the end of a long log is read, which still has what the counters saw last
+        let long: String = (0..7).map(|hour| format!("[2025-08-02T1{}:00:00+00:00] Lightbulb turned ON seq={}\n", hour, hour + 1)).collect();
+        let later = format!("{}[2025-08-02T18:00:00+00:00] Lightbulb turned OFF seq=8\n", long);
+        let usage = catch_up(UsageCounters::from_log(&long), &lines(&later)[2..], false).unwrap();
+        assert_eq!((usage.total, usage.on, usage.off), (8, 7, 1));
+
+        let rotated = "[2025-08-03T10:00:00+00:00] Lightbulb turned ON seq=1\n";
+        assert_eq!(catch_up(saved.clone(), &lines(rotated), true), None);
+        assert_eq!(catch_up(UsageCounters::default(), &lines(rotated), true), Some(UsageCounters::from_log(rotated)));
+        assert_eq!(catch_up(UsageCounters::default(), &lines(rotated), false), None);
+    }
+
+    #[tokio::test]
+    async fn test_counters_survive_a_save() {
+        let path = std::env::temp_dir().join(format!("lightbulb-usage-{}.stats", std::process::id())).display().to_string();
+        let cache = UsageCache::new(path.clone());
+        assert!(cache.load().await.unwrap().is_none());
+        let usage = UsageCounters::from_log(LOG);
+        cache.save(&usage).await.unwrap();
+        assert_eq!(cache.load().await.unwrap(), Some(usage.clone()));
+
+        tokio::fs::write(&path, serde_json::json!({ "version": 0, "usage": usage }).to_string()).await.unwrap();
+        assert!(cache.load().await.unwrap().is_none());
+        tokio::fs::write(&path, "not json").await.unwrap();
+        assert!(cache.load().await.is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use chrono::{DateTime, Utc};
+
+// How much the server says on stderr, which is all it may say: stdout carries the MCP session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // Nothing but the error that ends the process
+    Quiet,
+    // Problems the server carries on through, such as a failed compaction
+    #[default]
+    Warn,
+    // Connections and sessions opening and closing, and background jobs finishing (`-v`)
+    Info,
+    // Every tool call and backend call, with how it went and how long it took (`-vv`)
+    Debug,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Warn as u8);
+// Warnings and failed tool calls kept for the troubleshooting prompt whatever the verbosity, oldest first
+const RECENT_PROBLEMS: usize = 32;
+static PROBLEMS: Mutex<VecDeque<(DateTime<Utc>, String)>> = Mutex::new(VecDeque::new());
+
+impl Verbosity {
+    // `-q` wins over any `-v`; otherwise each `v`, as in `-v -v` or `-vv`, adds a level
+    pub fn from_args<S: AsRef<str>>(args: impl IntoIterator<Item = S>) -> Self {
+        let mut verbose = 0;
+        for arg in args {
+            match arg.as_ref() {
+                "-q" | "--quiet" => return Verbosity::Quiet,
+                "--verbose" => verbose += 1,
+                arg if arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|c| c == 'v') => verbose += arg.len() - 1,
+                _ => {},
+            }
+        }
+        match verbose {
+            0 => Verbosity::Warn,
+            1 => Verbosity::Info,
+            _ => Verbosity::Debug,
+        }
+    }
+
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Verbosity::Quiet,
+            1 => Verbosity::Warn,
+            2 => Verbosity::Info,
+            _ => Verbosity::Debug,
+        }
+    }
+}
+
+pub fn set(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn current() -> Verbosity {
+    Verbosity::from_u8(VERBOSITY.load(Ordering::Relaxed))
+}
+
+pub fn enabled(verbosity: Verbosity) -> bool {
+    verbosity != Verbosity::Quiet && current() >= verbosity
+}
+
+pub fn emit(verbosity: Verbosity, message: fmt::Arguments) {
+    if verbosity == Verbosity::Warn {
+        remember(message.to_string());
+    }
+    if enabled(verbosity) {
+        eprintln!("lightbulb-mcp: {}", message);
+    }
+}
+
+pub fn remember(problem: String) {
+    let mut problems = PROBLEMS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if problems.len() == RECENT_PROBLEMS {
+        problems.pop_front();
+    }
+    problems.push_back((Utc::now(), problem));
+}
+
+pub fn recent_problems() -> Vec<(DateTime<Utc>, String)> {
+    PROBLEMS.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).iter().cloned().collect()
+}
+
+// Writes a line to stderr when the server runs at `$level` or above, e.g. `diagnostic!(Info, "listening on {}", addr)`;
+// warnings are also kept for recent_problems
+#[macro_export]
+macro_rules! diagnostic {
+    ($level:ident, $($arg:tt)+) => {
+        $crate::verbosity::emit($crate::verbosity::Verbosity::$level, format_args!($($arg)+))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_set_the_verbosity() {
+        assert_eq!(Verbosity::from_args(["--guest"]), Verbosity::Warn);
+        assert_eq!(Verbosity::from_args(["-v"]), Verbosity::Info);
+        assert_eq!(Verbosity::from_args(["-vv"]), Verbosity::Debug);
+        assert_eq!(Verbosity::from_args(["-v", "--verbose"]), Verbosity::Debug);
+        assert_eq!(Verbosity::from_args(["-vvv", "-q"]), Verbosity::Quiet);
+        assert_eq!(Verbosity::from_args(["-", "-vx"]), Verbosity::Warn);
+    }
+
+    #[test]
+    fn test_levels_include_the_ones_below() {
+        assert!(Verbosity::Debug > Verbosity::Info && Verbosity::Info > Verbosity::Warn);
+        assert_eq!(Verbosity::from_u8(Verbosity::Info as u8), Verbosity::Info);
+    }
+
+    #[test]
+    fn test_warnings_are_kept_at_any_verbosity() {
+        crate::diagnostic!(Warn, "the {} is out", "bulb");
+        crate::diagnostic!(Debug, "a detail");
+        let problems = recent_problems();
+        assert!(problems.iter().any(|(_, problem)| problem == "the bulb is out"));
+        assert!(!problems.iter().any(|(_, problem)| problem == "a detail"));
+        assert!(problems.len() <= RECENT_PROBLEMS);
+    }
+}
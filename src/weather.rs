@@ -0,0 +1,224 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::actor::LightHandle;
+use crate::config::{WeatherCondition, WeatherConfig, WeatherRuleConfig};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Who the weather scheduler's changes are logged as
+pub const WEATHER_CALLER: &str = "weather";
+
+// The weather where the bulb is, as of one reading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weather {
+    pub condition: WeatherCondition,
+    pub cloud_cover: u8,
+    pub daytime: bool,
+}
+
+impl fmt::Display for Weather {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = if self.daytime { "daytime" } else { "night" };
+        write!(f, "{}, {}% cloud cover, {}", label(self.condition), self.cloud_cover, time)
+    }
+}
+
+fn label(condition: WeatherCondition) -> &'static str {
+    match condition {
+        WeatherCondition::Clear => "clear",
+        WeatherCondition::Cloudy => "cloudy",
+        WeatherCondition::Overcast => "overcast",
+        WeatherCondition::Fog => "fog",
+        WeatherCondition::Rain => "rain",
+        WeatherCondition::Snow => "snow",
+        WeatherCondition::Thunderstorm => "thunderstorm",
+    }
+}
+
+// The condition a WMO weather code describes; codes outside the table count as cloudy
+fn condition_of(code: u16) -> WeatherCondition {
+    match code {
+        0 => WeatherCondition::Clear,
+        3 => WeatherCondition::Overcast,
+        45 | 48 => WeatherCondition::Fog,
+        51..=67 | 80..=82 => WeatherCondition::Rain,
+        71..=77 | 85 | 86 => WeatherCondition::Snow,
+        95..=99 => WeatherCondition::Thunderstorm,
+        _ => WeatherCondition::Cloudy,
+    }
+}
+
+// Reads the current weather
+#[async_trait::async_trait]
+pub trait WeatherSource {
+    async fn current(&self) -> anyhow::Result<Weather>;
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    current: CurrentWeather,
+}
+
+#[derive(Deserialize)]
+struct CurrentWeather {
+    weather_code: u16,
+    cloud_cover: f64,
+    is_day: u8,
+}
+
+fn parse_forecast(body: &str) -> anyhow::Result<Weather> {
+    let current = serde_json::from_str::<ForecastResponse>(body)?.current;
+    Ok(Weather {
+        condition: condition_of(current.weather_code),
+        cloud_cover: current.cloud_cover.clamp(0.0, 100.0).round() as u8,
+        daytime: current.is_day == 1,
+    })
+}
+
+pub struct OpenMeteo {
+    client: reqwest::Client,
+    url: String,
+    latitude: f64,
+    longitude: f64,
+}
+
+impl OpenMeteo {
+    pub fn new(config: &WeatherConfig) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self { client, url: config.url.clone(), latitude: config.latitude, longitude: config.longitude })
+    }
+}
+
+#[async_trait::async_trait]
+impl WeatherSource for OpenMeteo {
+    async fn current(&self) -> anyhow::Result<Weather> {
+        let query = [
+            ("latitude", self.latitude.to_string()),
+            ("longitude", self.longitude.to_string()),
+            ("current", "weather_code,cloud_cover,is_day".to_string()),
+        ];
+        let response = self.client.get(&self.url).query(&query).send().await?.error_for_status()?;
+        parse_forecast(&response.text().await?)
+    }
+}
+
+fn matches(rule: &WeatherRuleConfig, weather: &Weather) -> bool {
+    rule.condition.is_none_or(|condition| condition == weather.condition)
+        && rule.min_cloud_cover.is_none_or(|cover| weather.cloud_cover >= cover)
+        && rule.daytime.is_none_or(|daytime| daytime == weather.daytime)
+}
+
+// Reads the weather every poll and applies the first rule it matches, once when it starts matching, so a change
+// made by hand while the weather holds is left alone; failed readings are reported and the next poll tries again
+pub fn spawn_weather_scheduler(config: WeatherConfig, source: Arc<dyn WeatherSource + Send + Sync>, light: LightHandle) {
+    tokio::spawn(async move {
+        // The rule the last reading matched
+        let mut matched: Option<String> = None;
+        loop {
+            match source.current().await {
+                Err(e) => crate::diagnostic!(Warn, "the weather scheduler could not read the weather: {}", e),
+                Ok(weather) => {
+                    let rule = config.rules.iter().find(|rule| matches(rule, &weather));
+                    if let Some(rule) = rule.filter(|rule| matched.as_deref() != Some(rule.name.as_str())) {
+                        let Ok(snapshot) = light.snapshot().await else {
+                            return;
+                        };
+                        let scheduler = light
+                            .acting_as(WEATHER_CALLER)
+                            .expecting(Some(snapshot.version))
+                            .with_reason(Some(format!("weather rule '{}': {}", rule.name, weather)));
+                        match crate::rules::apply(&scheduler, rule.power, rule.brightness).await {
+                            Ok(_) => crate::diagnostic!(Info, "the weather rule '{}' applied for {}", rule.name, weather),
+                            Err(e) => crate::diagnostic!(Warn, "the weather rule '{}' could not be applied: {}", rule.name, e),
+                        }
+                    }
+                    matched = rule.map(|rule| rule.name.clone());
+                },
+            }
+            tokio::time::sleep(config.poll_every()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::backend::SimulatedBackend;
+    use crate::config::RulePower;
+    use crate::model::PowerState;
+    use crate::logger::InMemoryLogger;
+    use crate::state::StateMachine;
+
+    struct FakeSource {
+        weather: Mutex<Weather>,
+    }
+
+    #[async_trait::async_trait]
+    impl WeatherSource for FakeSource {
+        async fn current(&self) -> anyhow::Result<Weather> {
+            Ok(*self.weather.lock().unwrap())
+        }
+    }
+
+    fn weather(condition: WeatherCondition, cloud_cover: u8) -> Weather {
+        Weather { condition, cloud_cover, daytime: true }
+    }
+
+    fn config() -> WeatherConfig {
+        WeatherConfig {
+            latitude: 52.52,
+            longitude: 13.41,
+            url: "https://api.open-meteo.com/v1/forecast".to_string(),
+            poll_minutes: 15,
+            rules: vec![WeatherRuleConfig {
+                name: "gloomy day".to_string(),
+                condition: Some(WeatherCondition::Overcast),
+                min_cloud_cover: None,
+                daytime: Some(true),
+                power: RulePower::On,
+                brightness: Some(50),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_parse_open_meteo_forecast() {
+        let body = r#"{"latitude": 52.52, "current": {"time": "2026-10-14T12:00", "weather_code": 61, "cloud_cover": 87.0, "is_day": 1}}"#;
+        assert_eq!(parse_forecast(body).unwrap(), weather(WeatherCondition::Rain, 87));
+        assert_eq!(condition_of(2), WeatherCondition::Cloudy);
+        assert_eq!(condition_of(96), WeatherCondition::Thunderstorm);
+        assert!(parse_forecast(r#"{"error": true, "reason": "Latitude must be in range"}"#).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rules_apply_once_when_the_weather_starts_matching() {
+        let light = LightHandle::spawn(StateMachine::new(), Box::new(SimulatedBackend::new()), Box::new(InMemoryLogger::new()));
+        let source = Arc::new(FakeSource { weather: Mutex::new(weather(WeatherCondition::Overcast, 95)) });
+        spawn_weather_scheduler(config(), source.clone(), light.clone());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let snapshot = light.snapshot().await.unwrap();
+        assert_eq!((snapshot.power, snapshot.brightness), (Some(PowerState::On), 50));
+        let log = light.read_log().await.unwrap();
+        assert!(log.contains("by weather (reason: weather rule 'gloomy day': overcast, 95% cloud cover, daytime)"), "{}", log);
+
+        // Turned off by hand while it stays overcast, the bulb is left off
+        light.set_power(PowerState::Off).await.unwrap();
+        tokio::time::sleep(Duration::from_secs(15 * 60)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::Off));
+
+        // Once the sky clears, the next spell of overcast applies the rule again
+        *source.weather.lock().unwrap() = weather(WeatherCondition::Clear, 5);
+        tokio::time::sleep(Duration::from_secs(15 * 60)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::Off));
+        *source.weather.lock().unwrap() = weather(WeatherCondition::Overcast, 90);
+        tokio::time::sleep(Duration::from_secs(15 * 60)).await;
+        assert_eq!(light.snapshot().await.unwrap().power, Some(PowerState::On));
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::broadcast;
+
+use crate::clock::SharedClock;
+use crate::config::WebhookConfig;
+use crate::events::{EventBus, LightEvent};
+
+const DELIVERY_LOG_LIMIT: usize = 100;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+pub const SIGNATURE_HEADER: &str = "X-Lightbulb-Signature";
+pub const EVENT_HEADER: &str = "X-Lightbulb-Event";
+
+// Sends one webhook request, returning the HTTP status
+#[async_trait::async_trait]
+pub trait WebhookTransport {
+    async fn post(&self, url: &str, headers: &[(&'static str, String)], body: &str) -> anyhow::Result<u16>;
+}
+
+pub struct HttpTransport {
+    client: reqwest::Client,
+}
+
+impl HttpTransport {
+    pub fn new() -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder().timeout(REQUEST_TIMEOUT).build()?;
+        Ok(Self { client })
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookTransport for HttpTransport {
+    async fn post(&self, url: &str, headers: &[(&'static str, String)], body: &str) -> anyhow::Result<u16> {
+        let mut request = self.client.post(url).header("Content-Type", "application/json").body(body.to_string());
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        Ok(request.send().await?.status().as_u16())
+    }
+}
+
+// "sha256=<hex>" HMAC of the request body, so receivers can check it came from this server
+pub fn sign_payload(secret: &str, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body.as_bytes());
+    let digest: String = mac.finalize().into_bytes().iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("sha256={}", digest)
+}
+
+// Outcome of delivering one event to one webhook, after any retries
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Delivery {
+    pub at: DateTime<Utc>,
+    pub url: String,
+    pub sequence: u64,
+    pub event: &'static str,
+    pub attempts: u32,
+    pub delivered: bool,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+// The most recent deliveries across all webhooks, newest last
+#[derive(Debug, Clone, Default)]
+pub struct DeliveryLog {
+    entries: Arc<Mutex<VecDeque<Delivery>>>,
+}
+
+impl DeliveryLog {
+    pub fn entries(&self) -> Vec<Delivery> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn record(&self, delivery: Delivery) {
+        let mut entries = self.lock();
+        if entries.len() == DELIVERY_LOG_LIMIT {
+            entries.pop_front();
+        }
+        entries.push_back(delivery);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<Delivery>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// Starts one delivery task per webhook, so a slow endpoint cannot hold up the others
+pub fn spawn_dispatchers(
+    webhooks: Vec<WebhookConfig>,
+    transport: Arc<dyn WebhookTransport + Send + Sync>,
+    events: &EventBus,
+    clock: SharedClock,
+) -> DeliveryLog {
+    let log = DeliveryLog::default();
+    for webhook in webhooks {
+        let receiver = events.subscribe();
+        tokio::spawn(dispatch(webhook, transport.clone(), receiver, log.clone(), clock.clone()));
+    }
+    log
+}
+
+async fn dispatch(
+    webhook: WebhookConfig,
+    transport: Arc<dyn WebhookTransport + Send + Sync>,
+    mut events: broadcast::Receiver<LightEvent>,
+    log: DeliveryLog,
+    clock: SharedClock,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        if webhook.events.is_empty() || webhook.events.iter().any(|kind| kind == event.kind.as_str()) {
+            log.record(deliver(&webhook, transport.as_ref(), &event, &clock).await);
+        }
+    }
+}
+
+async fn deliver(
+    webhook: &WebhookConfig,
+    transport: &(dyn WebhookTransport + Send + Sync),
+    event: &LightEvent,
+    clock: &SharedClock,
+) -> Delivery {
+    let body = serde_json::to_string(event).unwrap_or_default();
+    let mut headers = vec![(EVENT_HEADER, event.kind.as_str().to_string())];
+    if let Some(secret) = &webhook.secret {
+        headers.push((SIGNATURE_HEADER, sign_payload(secret, &body)));
+    }
+
+    let mut delivery = Delivery {
+        at: clock.now(),
+        url: webhook.url.clone(),
+        sequence: event.sequence,
+        event: event.kind.as_str(),
+        attempts: 0,
+        delivered: false,
+        status: None,
+        error: None,
+    };
+    while delivery.attempts <= webhook.max_retries {
+        if delivery.attempts > 0 {
+            tokio::time::sleep(RETRY_BASE_DELAY * 2u32.pow(delivery.attempts - 1)).await;
+        }
+        delivery.attempts += 1;
+        match transport.post(&webhook.url, &headers, &body).await {
+            Ok(status) => {
+                delivery.status = Some(status);
+                delivery.error = None;
+                if (200..300).contains(&status) {
+                    delivery.delivered = true;
+                    break;
+                }
+            },
+            Err(e) => delivery.error = Some(e.to_string()),
+        }
+    }
+    delivery.at = clock.now();
+    delivery
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::system_clock;
+    use crate::events::EventKind;
+    use crate::state::StateMachine;
+
+    type Headers = Vec<(&'static str, String)>;
+
+    // Fails the first `failures` requests, then accepts; records every request it sees
+    struct FlakyTransport {
+        failures: Mutex<u32>,
+        requests: Mutex<Vec<(String, Headers)>>,
+    }
+
+    #[async_trait::async_trait]
+    impl WebhookTransport for FlakyTransport {
+        async fn post(&self, url: &str, headers: &[(&'static str, String)], _body: &str) -> anyhow::Result<u16> {
+            self.requests.lock().unwrap().push((url.to_string(), headers.to_vec()));
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Ok(503);
+            }
+            Ok(204)
+        }
+    }
+
+    fn event(kind: EventKind) -> LightEvent {
+        LightEvent {
+            sequence: 7,
+            at: Utc::now(),
+            kind,
+            action: "turn_on".to_string(),
+            from: "OFF",
+            to: "ON",
+            state: StateMachine::new().snapshot(),
+            log_sequence: None,
+        }
+    }
+
+    fn webhook(events: Vec<String>) -> WebhookConfig {
+        WebhookConfig { url: "https://example.com/hook".to_string(), secret: Some("key".to_string()), events, max_retries: 2 }
+    }
+
+    #[test]
+    fn test_sign_payload_matches_reference() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delivery_retries_until_accepted() {
+        let transport = FlakyTransport { failures: Mutex::new(2), requests: Mutex::new(Vec::new()) };
+        let delivery = deliver(&webhook(Vec::new()), &transport, &event(EventKind::StateChanged), &system_clock()).await;
+        assert!(delivery.delivered);
+        assert_eq!((delivery.attempts, delivery.status), (3, Some(204)));
+
+        let requests = transport.requests.lock().unwrap();
+        let headers = &requests[0].1;
+        assert!(headers.iter().any(|(name, value)| *name == SIGNATURE_HEADER && value.starts_with("sha256=")));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_dispatcher_filters_events_and_gives_up() {
+        let transport = Arc::new(FlakyTransport { failures: Mutex::new(10), requests: Mutex::new(Vec::new()) });
+        let events = EventBus::new();
+        let log = spawn_dispatchers(vec![webhook(vec!["backend_unreachable".to_string()])], transport.clone(), &events, system_clock());
+
+        events.publish(event(EventKind::StateChanged));
+        events.publish(event(EventKind::BackendUnreachable));
+        tokio::time::sleep(Duration::from_secs(10)).await;
+
+        let entries = log.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event, "backend_unreachable");
+        assert!(!entries[0].delivered);
+        assert_eq!((entries[0].attempts, entries[0].status), (3, Some(503)));
+    }
+}
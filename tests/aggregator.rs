@@ -0,0 +1,52 @@
+use std::path::PathBuf;
+
+use rmcp::ServiceExt;
+use rmcp::model::{CallToolRequestParam, ReadResourceRequestParam, ResourceContents};
+use rmcp::transport::TokioChildProcess;
+use tokio::process::Command;
+
+const SERVER: &str = env!("CARGO_BIN_EXE_lightbulb-mcp");
+
+// An aggregator over two local servers, each in its own subdirectory so their logs stay apart
+fn setup(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lightbulb-aggregator-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let mut config = String::new();
+    for id in ["kitchen", "porch"] {
+        std::fs::create_dir_all(dir.join(id)).unwrap();
+        config.push_str(&format!("[[downstream]]\nid = \"{}\"\ncommand = {:?}\ndir = {:?}\n", id, SERVER, dir.join(id)));
+    }
+    std::fs::write(dir.join("lightbulb.toml"), config).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn test_fans_out_to_downstream_servers() {
+    let dir = setup("fan-out");
+    let mut command = Command::new(SERVER);
+    command.current_dir(&dir).env_remove("LIGHTBULB_CONFIG");
+    let client = ().serve(TokioChildProcess::new(command).unwrap()).await.unwrap();
+
+    let call = |name: &'static str, arguments: serde_json::Value| {
+        let request = CallToolRequestParam { name: name.into(), arguments: arguments.as_object().cloned() };
+        let client = &client;
+        async move {
+            let result = client.call_tool(request).await.unwrap();
+            result.content.iter().flatten().filter_map(|content| Some(content.as_text()?.text.clone())).collect::<String>()
+        }
+    };
+    let report = call("turn_on_bulbs", serde_json::json!({ "bulbs": ["porch"] })).await;
+    assert_eq!(report, "porch: Lightbulb turned on successfully (sequence 1)");
+    let report = call("list_bulbs", serde_json::json!({})).await;
+    assert!(report.contains("kitchen: The lightbulb is off (version 0)"));
+    assert!(report.contains("porch: The lightbulb is on for "));
+    assert!(report.contains("(version 2, last change: turn_on by lightbulb-aggregator "));
+
+    let log = client.read_resource(ReadResourceRequestParam { uri: "lightbulb://log".to_string() }).await.unwrap();
+    let ResourceContents::TextResourceContents { text, .. } = &log.contents[0] else { panic!("log is not text") };
+    assert!(text.trim_end().ends_with("porch: Lightbulb turned ON by lightbulb-aggregator"));
+    assert!(std::fs::read_to_string(dir.join("kitchen/lightbulb.log")).unwrap_or_default().is_empty());
+
+    client.cancel().await.unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Runs lightbulb-cli against the freshly built server, in a scratch directory so the log starts empty
+fn run_cli(name: &str, args: &[&str]) -> Output {
+    let dir: PathBuf = std::env::temp_dir().join(format!("lightbulb-cli-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_lightbulb-cli"))
+        .args(["--server", env!("CARGO_BIN_EXE_lightbulb-mcp")])
+        .args(args)
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+    output
+}
+
+#[test]
+fn test_commands_share_one_session() {
+    let output = run_cli("session", &["on", "status", "log", "summary"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Lightbulb turned on successfully (sequence 1)"));
+    assert!(stdout.contains("(version 2, last change: turn_on by lightbulb-cli "));
+    assert!(stdout.contains("] Lightbulb turned ON by lightbulb-cli"));
+    assert!(stdout.contains("Current Status: ON"));
+}
+
+#[test]
+fn test_unknown_command_is_a_usage_error() {
+    let output = run_cli("usage", &["on", "dim"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown command 'dim'"));
+}
@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+const SERVER: &str = env!("CARGO_BIN_EXE_lightbulb-mcp");
+
+// A scratch directory with a config serving the REST API on any free port
+struct Scratch {
+    dir: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("lightbulb-daemon-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("lightbulb.toml"), "[rest]\nbind = \"127.0.0.1:0\"\n").unwrap();
+        Self { dir }
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(SERVER).args(args).current_dir(&self.dir).env_remove("LIGHTBULB_CONFIG").stdin(Stdio::null()).output().unwrap()
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = Command::new(SERVER).arg("--stop").current_dir(&self.dir).output();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+fn exists(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok()
+}
+
+#[test]
+fn test_daemon_starts_reports_and_stops() {
+    let scratch = Scratch::new("lifecycle");
+    let started = scratch.run(&["--daemon"]);
+    assert!(started.status.success(), "{}", stderr(&started));
+    assert!(stdout(&started).starts_with("lightbulb-mcp started (pid "), "{}", stdout(&started));
+    let pid = std::fs::read_to_string(scratch.path("lightbulb-mcp.pid")).unwrap();
+    assert!(stdout(&started).contains(&format!("(pid {})", pid.trim())));
+
+    let status = scratch.run(&["--status"]);
+    assert_eq!(stdout(&status).trim(), format!("lightbulb-mcp is running (pid {})", pid.trim()));
+    let again = scratch.run(&["--daemon"]);
+    assert!(!again.status.success());
+    assert!(stderr(&again).contains("already running"), "{}", stderr(&again));
+
+    let stopped = scratch.run(&["--stop"]);
+    assert!(stopped.status.success(), "{}", stderr(&stopped));
+    assert!(!exists(&scratch.path("lightbulb-mcp.pid")));
+    let status = scratch.run(&["--status"]);
+    assert!(!status.status.success());
+    assert!(stderr(&status).contains("not running"));
+}
+
+#[test]
+fn test_failed_startup_points_at_the_diagnostics() {
+    let scratch = Scratch::new("failed");
+    std::fs::write(scratch.path("lightbulb.toml"), "[rest]\nbind = \"not an address\"\n").unwrap();
+    let started = scratch.run(&["--daemon", "--diagnostics-file", "startup.err"]);
+    assert!(!started.status.success());
+    assert!(stderr(&started).contains("see startup.err"), "{}", stderr(&started));
+    assert!(!std::fs::read_to_string(scratch.path("startup.err")).unwrap().is_empty());
+    assert!(!exists(&scratch.path("lightbulb-mcp.pid")));
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use lightbulb_mcp::logger::InMemoryLogger;
+use lightbulb_mcp::state::Transition;
+use lightbulb_mcp::testing::TestClient;
+use lightbulb_mcp::{LightService, SessionFactory};
+use proptest::prelude::*;
+use serde_json::{Value, json};
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    TurnOn,
+    TurnOff,
+    Lock,
+    Unlock,
+    Undo,
+    Redo,
+    SetColor,
+    // Makes every backend call fail, or succeed again
+    Failing(bool),
+}
+
+impl Op {
+    fn call(self) -> (&'static str, Value) {
+        match self {
+            Op::TurnOn => ("turn_on_lightbulb", json!({})),
+            Op::TurnOff => ("turn_off_lightbulb", json!({})),
+            Op::Lock => ("lock_lightbulb", json!({})),
+            Op::Unlock => ("unlock_lightbulb", json!({})),
+            Op::Undo => ("undo_last_change", json!({})),
+            Op::Redo => ("redo_change", json!({})),
+            Op::SetColor => ("set_color_by_name", json!({ "name": "red" })),
+            Op::Failing(failing) => ("set_fault_injection", json!({ "failure_rate": if failing { 1.0 } else { 0.0 } })),
+        }
+    }
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        3 => Just(Op::TurnOn),
+        3 => Just(Op::TurnOff),
+        1 => Just(Op::Lock),
+        1 => Just(Op::Unlock),
+        2 => Just(Op::Undo),
+        2 => Just(Op::Redo),
+        1 => Just(Op::SetColor),
+        1 => any::<bool>().prop_map(Op::Failing),
+    ]
+}
+
+// A service whose hook counts completed power transitions, each of which should be logged exactly once
+fn counted_service() -> (LightService, Arc<AtomicUsize>) {
+    let completed = Arc::new(AtomicUsize::new(0));
+    let counter = completed.clone();
+    let service = LightService::builder()
+        .logger(Box::new(InMemoryLogger::new()))
+        .transition_hook(move |change| {
+            if change.transition == Transition::Complete {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        })
+        .build();
+    (service, completed)
+}
+
+async fn state(client: &TestClient) -> Value {
+    client.call_json("get_lightbulb_state", json!({})).await.unwrap()
+}
+
+async fn logged_changes(client: &TestClient) -> usize {
+    client.read("lightbulb://log.txt").await.unwrap().lines().filter(|line| line.contains("] Lightbulb turned ")).count()
+}
+
+// Either outcome of each call is fine, as long as the server answers with a LightError rather than failing
+async fn drive(client: &TestClient, ops: Vec<Op>) {
+    for op in ops {
+        let (tool, arguments) = op.call();
+        if let Err(error) = client.call(tool, arguments).await {
+            assert!(error.data.is_some(), "{:?} failed without a LightError: {}", op, error.message);
+        }
+    }
+}
+
+fn run(test: impl Future<Output = ()>) {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(test);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn test_status_follows_every_call(ops in prop::collection::vec(op(), 1..40)) {
+        run(async {
+            let (service, completed) = counted_service();
+            let client = TestClient::connect(service).await.unwrap();
+            let mut before = state(&client).await;
+            for op in ops {
+                let (tool, arguments) = op.call();
+                let result = client.call(tool, arguments).await;
+                let after = state(&client).await;
+                let (label, version) = (after["state"].as_str().unwrap(), after["version"].as_u64().unwrap());
+                assert!(version >= before["version"].as_u64().unwrap(), "{:?} moved the version back", op);
+                assert_eq!(after["locked"], label == "LOCKED");
+                match (&result, op) {
+                    (Ok(_), Op::TurnOn) => assert_eq!(label, "ON"),
+                    (Ok(_), Op::TurnOff) => assert_eq!(label, "OFF"),
+                    (Ok(_), Op::Lock) => assert_eq!(label, "LOCKED"),
+                    (Ok(_), Op::Unlock) => assert_ne!(label, "LOCKED"),
+                    (Ok(text), Op::Undo | Op::Redo) => assert!(text.contains(&format!("the lightbulb is {}", label.to_lowercase()))),
+                    (Ok(_), Op::SetColor) => assert_eq!(after["state"], before["state"]),
+                    (Ok(_), Op::Failing(_)) => assert_eq!(after, before),
+                    (Err(error), _) => match error.data.as_ref().and_then(|data| data["code"].as_str()) {
+                        // An undo or redo that fails putting a color back does so before touching the power
+                        Some("BACKEND_UNREACHABLE") if matches!(op, Op::Undo | Op::Redo) && label != "UNREACHABLE" => assert_eq!(after, before),
+                        // A failed power change leaves the bulb unreachable; a failed color change leaves it as it was
+                        Some("BACKEND_UNREACHABLE") if !matches!(op, Op::SetColor) => assert_eq!(label, "UNREACHABLE"),
+                        code => assert_eq!(version, before["version"].as_u64().unwrap(), "{:?} failed with {:?} but changed the state", op, code),
+                    },
+                }
+                before = after;
+            }
+            assert_eq!(logged_changes(&client).await, completed.load(Ordering::SeqCst));
+            client.close().await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_concurrent_sessions_keep_the_log_consistent(
+        first in prop::collection::vec(op(), 1..20),
+        second in prop::collection::vec(op(), 1..20),
+    ) {
+        run(async {
+            let (service, completed) = counted_service();
+            let sessions = SessionFactory::Shared(service);
+            let kitchen = TestClient::connect_as(sessions.create(), "kitchen-agent").await.unwrap();
+            let desk = TestClient::connect_as(sessions.create(), "desk-agent").await.unwrap();
+            tokio::join!(drive(&kitchen, first), drive(&desk, second));
+
+            let after = state(&desk).await;
+            assert_eq!(after["locked"], after["state"] == "LOCKED");
+            assert_eq!(logged_changes(&kitchen).await, completed.load(Ordering::SeqCst));
+            kitchen.close().await.unwrap();
+            desk.close().await.unwrap();
+        });
+    }
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+
+use lightbulb_mcp::logger::InMemoryLogger;
+use lightbulb_mcp::testing::TestClient;
+use lightbulb_mcp::{LightService, SessionFactory};
+use serde_json::json;
+
+fn service() -> LightService {
+    LightService::builder().logger(Box::new(InMemoryLogger::new())).build()
+}
+
+#[tokio::test]
+async fn test_other_sessions_are_notified_of_changes() {
+    let sessions = SessionFactory::Shared(service());
+    let kitchen = TestClient::connect_as(sessions.create(), "kitchen-agent").await.unwrap();
+    let desk = TestClient::connect_as(sessions.create(), "desk-agent").await.unwrap();
+
+    kitchen.call("turn_on_lightbulb", json!({})).await.unwrap();
+    let message = tokio::time::timeout(Duration::from_secs(5), desk.next_log_message()).await.unwrap().unwrap();
+    assert_eq!(message.data["message"], "The lightbulb changed from OFF to ON by kitchen-agent (version 2)");
+    assert!(tokio::time::timeout(Duration::from_millis(100), kitchen.next_log_message()).await.is_err());
+
+    let status = desk.call("get_lightbulb_status", json!({})).await.unwrap();
+    assert!(status.contains("(version 2, last change: turn_on by kitchen-agent "));
+}
+
+#[tokio::test]
+async fn test_stale_version_is_rejected_over_the_protocol() {
+    let client = TestClient::connect(service()).await.unwrap();
+    client.call("turn_on_lightbulb", json!({})).await.unwrap();
+
+    let error = client.call("turn_off_lightbulb", json!({ "expected_version": 0 })).await.unwrap_err();
+    let data = error.data.unwrap();
+    assert_eq!((data["code"].as_str(), data["actual_version"].as_u64()), (Some("VERSION_CONFLICT"), Some(2)));
+    client.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_guest_session_only_sees_guest_tools() {
+    let client = TestClient::connect(service().guest()).await.unwrap();
+    let mut tools = client.tools().await.unwrap();
+    tools.sort();
+    assert_eq!(tools, ["get_lightbulb_state", "get_lightbulb_status", "turn_off_lightbulb", "turn_on_lightbulb"]);
+    assert_eq!(client.resources().await.unwrap(), ["lightbulb://colors"]);
+    assert!(client.call("lock_lightbulb", json!({})).await.is_err());
+    assert!(client.read("lightbulb://log").await.is_err());
+}
+
+#[tokio::test]
+async fn test_subscribers_hear_of_resource_updates() {
+    let sessions = SessionFactory::Shared(service());
+    let kitchen = TestClient::connect_as(sessions.create(), "kitchen-agent").await.unwrap();
+    let desk = TestClient::connect_as(sessions.create(), "desk-agent").await.unwrap();
+    desk.subscribe("lightbulb://log/tail").await.unwrap();
+    desk.subscribe("lightbulb://schedules").await.unwrap();
+    assert_eq!(desk.subscribe("lightbulb://nowhere").await.unwrap_err().data.unwrap()["code"], "UNKNOWN_RESOURCE");
+
+    kitchen.call("turn_on_lightbulb", json!({})).await.unwrap();
+    let updated = tokio::time::timeout(Duration::from_secs(5), desk.next_resource_update()).await.unwrap();
+    assert_eq!(updated.as_deref(), Some("lightbulb://log/tail"));
+    kitchen.call("set_schedule", json!({ "name": "evening", "at": "20:00" })).await.unwrap();
+    let updated = tokio::time::timeout(Duration::from_secs(5), desk.next_resource_update()).await.unwrap();
+    assert_eq!(updated.as_deref(), Some("lightbulb://schedules"));
+    // Only what was subscribed to
+    assert!(tokio::time::timeout(Duration::from_millis(100), kitchen.next_resource_update()).await.is_err());
+}
+
+#[cfg(feature = "bulbs")]
+#[tokio::test]
+async fn test_subscribers_hear_of_changes_to_the_bulb_they_follow() {
+    let sessions = SessionFactory::Shared(service());
+    let kitchen = TestClient::connect_as(sessions.create(), "kitchen-agent").await.unwrap();
+    let desk = TestClient::connect_as(sessions.create(), "desk-agent").await.unwrap();
+    kitchen.call("add_lightbulb", json!({ "id": "desk", "name": "Desk Lamp" })).await.unwrap();
+    desk.subscribe("lightbulb://Desk%20Lamp/state").await.unwrap();
+    desk.subscribe("lightbulb://main/log").await.unwrap();
+    assert_eq!(desk.subscribe("lightbulb://attic/state").await.unwrap_err().data.unwrap()["code"], "UNKNOWN_RESOURCE");
+
+    kitchen.call("turn_on_lightbulb", json!({ "bulb_id": "desk" })).await.unwrap();
+    let updated = tokio::time::timeout(Duration::from_secs(5), desk.next_resource_update()).await.unwrap();
+    assert_eq!(updated.as_deref(), Some("lightbulb://Desk%20Lamp/state"));
+    let state: serde_json::Value = serde_json::from_str(&desk.read("lightbulb://desk/state").await.unwrap()).unwrap();
+    assert_eq!((state["state"].as_str(), state["changed_by"].as_str()), (Some("ON"), Some("kitchen-agent")));
+    // The desk lamp's change is not the server's own bulb's
+    assert!(tokio::time::timeout(Duration::from_millis(100), desk.next_resource_update()).await.is_err());
+
+    kitchen.call("turn_on_lightbulb", json!({})).await.unwrap();
+    let updated = tokio::time::timeout(Duration::from_secs(5), desk.next_resource_update()).await.unwrap();
+    assert_eq!(updated.as_deref(), Some("lightbulb://main/log"));
+}
@@ -0,0 +1,137 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use lightbulb_mcp::LightService;
+use lightbulb_mcp::clock::{Clock, ManualClock};
+use lightbulb_mcp::config::WebhookConfig;
+use lightbulb_mcp::logger::{InMemoryLogger, Logger, format_log_line};
+use lightbulb_mcp::reply;
+use lightbulb_mcp::testing::TestClient;
+use lightbulb_mcp::webhooks::WebhookTransport;
+use serde_json::{Value, json};
+
+// Every snapshot is taken at this moment, so timestamps and durations never drift
+fn frozen_clock() -> ManualClock {
+    ManualClock::new(DateTime::parse_from_rfc3339("2025-08-02T14:00:00Z").unwrap().with_timezone(&Utc))
+}
+
+// Two days of made-up usage ending an hour before the frozen clock, after a line the parser skips
+async fn synthetic_logger(clock: &ManualClock) -> InMemoryLogger {
+    let start = clock.now() - TimeDelta::hours(38);
+    let entries = [
+        (0, "ON [morning] by kitchen-agent"),
+        (45, "OFF by kitchen-agent"),
+        (600, "ON [movie] by desk-agent (reason: film night)"),
+        (735, "OFF [movie] by desk-agent"),
+        (1_380, "ON by kitchen-agent"),
+        (1_395, "OFF by kitchen-agent"),
+        (2_220, "ON [movie,after-midnight] by desk-agent"),
+    ];
+    let mut logger = InMemoryLogger::new();
+    logger.append_line("this line predates the log format").await.unwrap();
+    for (minutes, action) in entries {
+        logger.append_line(&format_log_line(start + TimeDelta::minutes(minutes), action)).await.unwrap();
+    }
+    logger
+}
+
+async fn connect(clock: &ManualClock) -> TestClient {
+    let service = LightService::builder().logger(Box::new(synthetic_logger(clock).await)).clock(Arc::new(clock.clone())).build();
+    TestClient::connect_as(service, "snapshot-agent").await.unwrap()
+}
+
+fn pretty(json: &Value) -> String {
+    serde_json::to_string_pretty(json).unwrap()
+}
+
+// Accepts every delivery
+struct AcceptingTransport;
+
+#[async_trait::async_trait]
+impl WebhookTransport for AcceptingTransport {
+    async fn post(&self, _url: &str, _headers: &[(&'static str, String)], _body: &str) -> anyhow::Result<u16> {
+        Ok(204)
+    }
+}
+
+#[tokio::test]
+async fn test_log_resource() {
+    let clock = frozen_clock();
+    let client = connect(&clock).await;
+    client.call("turn_on_lightbulb", json!({ "tags": ["reading"] })).await.unwrap();
+    clock.advance(TimeDelta::minutes(20));
+    client.call("turn_off_lightbulb", json!({ "reason": "leaving" })).await.unwrap();
+    insta::assert_snapshot!(client.read("lightbulb://log.txt").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_empty_log_resource() {
+    let clock = frozen_clock();
+    let service = LightService::builder().logger(Box::new(InMemoryLogger::new())).clock(Arc::new(clock.clone())).build();
+    let client = TestClient::connect(service).await.unwrap();
+    insta::assert_snapshot!(client.read("lightbulb://log.txt").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_summary_resource() {
+    let clock = frozen_clock();
+    let client = connect(&clock).await;
+    insta::assert_snapshot!(client.read("lightbulb://summary").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_color_resource() {
+    let clock = frozen_clock();
+    let client = connect(&clock).await;
+    insta::assert_snapshot!(client.read("lightbulb://colors/sky%20blue").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_status_and_state() {
+    let clock = frozen_clock();
+    let client = connect(&clock).await;
+    client.call("turn_on_lightbulb", json!({ "tags": ["reading"] })).await.unwrap();
+    clock.advance(TimeDelta::minutes(5));
+    let status = client.call_result("get_lightbulb_status", json!({})).await.unwrap();
+    insta::assert_snapshot!("status", reply::summary_of(&status));
+    insta::assert_snapshot!("status_json", pretty(&reply::json_of(&status).unwrap()));
+    insta::assert_snapshot!("state", pretty(&client.call_json("get_lightbulb_state", json!({})).await.unwrap()));
+}
+
+#[tokio::test]
+async fn test_statistics() {
+    let clock = frozen_clock();
+    let client = connect(&clock).await;
+    let arguments = json!({ "metrics": ["counts", "on_time", "energy", "histogram"] });
+    insta::assert_snapshot!("statistics", pretty(&client.call_json("get_statistics", arguments).await.unwrap()));
+    let arguments = json!({ "metrics": ["counts", "on_time"], "tag": "movie" });
+    insta::assert_snapshot!("statistics_for_tag", pretty(&client.call_json("get_statistics", arguments).await.unwrap()));
+}
+
+#[tokio::test]
+async fn test_webhook_deliveries_resource() {
+    let clock = frozen_clock();
+    let webhook = WebhookConfig { url: "https://hooks.example.com/light".to_string(), secret: None, events: Vec::new(), max_retries: 0 };
+    let service = LightService::builder()
+        .logger(Box::new(InMemoryLogger::new()))
+        .clock(Arc::new(clock.clone()))
+        .webhooks(vec![webhook], Arc::new(AcceptingTransport))
+        .build();
+    let client = TestClient::connect(service).await.unwrap();
+    client.call("turn_on_lightbulb", json!({})).await.unwrap();
+
+    // Deliveries are made in the background
+    let deliveries = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let deliveries = client.read("lightbulb://webhooks").await.unwrap();
+            if deliveries != "[]" {
+                return deliveries;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    insta::assert_snapshot!(deliveries);
+}
@@ -0,0 +1,301 @@
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+const SERVER: &str = env!("CARGO_BIN_EXE_lightbulb-mcp");
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// The built server speaking newline-delimited JSON-RPC over its stdin and stdout, in a scratch directory so the
+// log starts empty
+struct Server {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    lines: Receiver<String>,
+    dir: PathBuf,
+}
+
+impl Server {
+    fn spawn(name: &str, args: &[&str]) -> Self {
+        let dir = std::env::temp_dir().join(format!("lightbulb-stdio-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut child = Command::new(SERVER)
+            .args(args)
+            .current_dir(&dir)
+            .env_remove("LIGHTBULB_CONFIG")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        // Read on a thread, so a server that never answers fails the test instead of hanging it
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let (sender, lines) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for line in stdout.lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { stdin: child.stdin.take(), child, lines, dir }
+    }
+
+    fn send_line(&mut self, line: &str) {
+        let stdin = self.stdin.as_mut().unwrap();
+        writeln!(stdin, "{}", line).unwrap();
+        stdin.flush().unwrap();
+    }
+
+    fn send(&mut self, message: Value) {
+        self.send_line(&message.to_string());
+    }
+
+    // The next line the server writes, which must be a JSON-RPC 2.0 message
+    fn receive(&mut self) -> Value {
+        let line = self.lines.recv_timeout(TIMEOUT).expect("the server did not answer");
+        let message: Value = serde_json::from_str(&line).unwrap_or_else(|e| panic!("not JSON ({}): {}", e, line));
+        assert_eq!(message["jsonrpc"], "2.0", "{}", line);
+        message
+    }
+
+    // Sends a request and returns its response, skipping the notifications sent meanwhile
+    fn request(&mut self, id: u64, method: &str, params: Value) -> Value {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message.get("id").is_some() {
+                assert_eq!(message["id"], id);
+                return message;
+            }
+        }
+    }
+
+    fn initialize(&mut self) -> Value {
+        let params = json!({ "protocolVersion": "2025-03-26", "capabilities": {}, "clientInfo": { "name": "wire-test", "version": "1" } });
+        let response = self.request(0, "initialize", params);
+        self.send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }));
+        response
+    }
+
+    fn call(&mut self, id: u64, tool: &str, arguments: Value) -> Value {
+        self.request(id, "tools/call", json!({ "name": tool, "arguments": arguments }))
+    }
+
+    // Closes stdin and waits for the server to exit
+    fn finish(&mut self) -> ExitStatus {
+        drop(self.stdin.take());
+        self.wait().expect("the server did not exit at the end of its input")
+    }
+
+    fn wait(&mut self) -> Option<ExitStatus> {
+        for _ in 0..100 {
+            if let Some(status) = self.child.try_wait().unwrap() {
+                return Some(status);
+            }
+            std::thread::sleep(TIMEOUT / 100);
+        }
+        None
+    }
+
+    // Every line still to come, up to the end of the server's output
+    fn remaining(&mut self) -> Vec<Value> {
+        let mut messages = Vec::new();
+        loop {
+            match self.lines.recv_timeout(TIMEOUT) {
+                Ok(line) => messages.push(serde_json::from_str(&line).unwrap()),
+                Err(RecvTimeoutError::Disconnected) => return messages,
+                Err(RecvTimeoutError::Timeout) => panic!("the server kept its output open"),
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn text(response: &Value) -> &str {
+    response["result"]["content"][0]["text"].as_str().unwrap_or_default()
+}
+
+#[test]
+fn test_initialize_handshake() {
+    let mut server = Server::spawn("handshake", &[]);
+    let response = server.initialize();
+    let result = &response["result"];
+    assert_eq!(result["protocolVersion"], "2025-03-26");
+    for capability in ["tools", "resources", "logging", "completions"] {
+        assert!(result["capabilities"][capability].is_object(), "missing {}: {}", capability, response);
+    }
+    assert_eq!(server.request(1, "ping", json!({})), json!({ "jsonrpc": "2.0", "id": 1, "result": {} }));
+    assert!(server.finish().success());
+}
+
+#[test]
+fn test_tools_and_resources() {
+    let mut server = Server::spawn("tools", &[]);
+    server.initialize();
+
+    let tools = server.request(1, "tools/list", json!({}));
+    let turn_on = tools["result"]["tools"].as_array().unwrap().iter().find(|tool| tool["name"] == "turn_on_lightbulb").unwrap();
+    assert_eq!(turn_on["inputSchema"]["type"], "object");
+    assert!(turn_on["inputSchema"]["properties"]["reason"].is_object());
+
+    let response = server.call(2, "turn_on_lightbulb", json!({ "reason": "wire test" }));
+    assert_eq!(response["result"]["isError"], false);
+    assert_eq!(response["result"]["content"][0]["type"], "text");
+    assert_eq!(text(&response), "Lightbulb turned on successfully (sequence 1)");
+
+    let resources = server.request(3, "resources/list", json!({}));
+    let uris: Vec<&str> = resources["result"]["resources"].as_array().unwrap().iter().filter_map(|resource| resource["uri"].as_str()).collect();
+    assert!(uris.contains(&"lightbulb://log") && uris.contains(&"lightbulb://summary"), "{:?}", uris);
+
+    let log = server.request(4, "resources/read", json!({ "uri": "lightbulb://log" }));
+    let contents = &log["result"]["contents"][0];
+    assert_eq!((&contents["uri"], &contents["mimeType"]), (&json!("lightbulb://log"), &json!("application/json")));
+    let page: Value = serde_json::from_str(contents["text"].as_str().unwrap()).unwrap();
+    assert_eq!(page["entries"][0]["message"], "Lightbulb turned ON by wire-test (reason: wire test)");
+    assert!(std::fs::read_to_string(server.dir.join("lightbulb.log")).unwrap().contains("turned ON by wire-test"));
+    let record: Value = serde_json::from_str(std::fs::read_to_string(server.dir.join("lightbulb.log.jsonl")).unwrap().lines().next().unwrap()).unwrap();
+    assert_eq!((&record["action"], &record["by"], &record["previous"]), (&json!("on"), &json!("wire-test"), &Value::Null));
+    assert!(server.finish().success());
+}
+
+#[test]
+fn test_errors_on_the_wire() {
+    let mut server = Server::spawn("errors", &[]);
+    server.initialize();
+
+    // A tool that ran and failed answers with an isError result, its data as the second content
+    let conflict = server.call(1, "turn_off_lightbulb", json!({ "expected_version": 9 }));
+    assert_eq!(conflict["result"]["isError"], true);
+    assert_eq!(conflict["result"]["content"][0]["text"], "The lightbulb state has changed: expected version 9, found 0");
+    let data: Value = serde_json::from_str(conflict["result"]["content"][1]["text"].as_str().unwrap()).unwrap();
+    assert_eq!(data, json!({ "code": "VERSION_CONFLICT", "expected_version": 9, "actual_version": 0 }));
+
+    let unknown = server.request(2, "resources/read", json!({ "uri": "lightbulb://nope" }));
+    assert_eq!(unknown["error"]["data"]["code"], "UNKNOWN_RESOURCE");
+
+    let bad_arguments = server.call(3, "turn_on_lightbulb", json!({ "tags": "movie" }));
+    assert_eq!(bad_arguments["error"]["code"], -32602);
+    assert_eq!(bad_arguments["error"]["data"]["code"], "INVALID_PARAMETER");
+
+    let no_tool = server.call(4, "dim_lightbulb", json!({}));
+    assert_eq!(no_tool["error"]["code"], -32602);
+    assert_eq!(no_tool["error"]["data"], json!({ "code": "UNKNOWN_TOOL", "tool": "dim_lightbulb" }));
+    assert!(server.finish().success());
+}
+
+#[test]
+fn test_bad_lines_do_not_end_the_session() {
+    let mut server = Server::spawn("bad-lines", &[]);
+    server.initialize();
+
+    let unknown = server.request(1, "lights/dim", json!({}));
+    assert_eq!((unknown["error"]["code"].as_i64(), unknown["error"]["message"].as_str()), (Some(-32601), Some("Method not found: lights/dim")));
+    server.send_line("{\"jsonrpc\": \"2.0\", \"id\": 2, \"method\"");
+    let malformed = server.receive();
+    assert_eq!((malformed["id"].is_null(), malformed["error"]["code"].as_i64()), (true, Some(-32700)));
+    server.send(json!({ "jsonrpc": "2.0", "method": "notifications/lights_dimmed" }));
+    server.send_line("");
+
+    let response = server.call(3, "turn_on_lightbulb", json!({}));
+    assert_eq!(text(&response), "Lightbulb turned on successfully (sequence 1)");
+    assert!(server.finish().success());
+}
+
+// Requests piped in ahead of the end of input are all answered before the server exits
+#[test]
+fn test_end_of_input_answers_pending_requests() {
+    let mut server = Server::spawn("eof", &[]);
+    server.initialize();
+    server.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": { "name": "turn_on_lightbulb", "arguments": {} } }));
+    server.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": { "name": "get_lightbulb_status", "arguments": {} } }));
+    assert!(server.finish().success());
+
+    let mut ids: Vec<u64> = server.remaining().iter().filter_map(|message| message["id"].as_u64()).collect();
+    ids.sort();
+    assert_eq!(ids, [1, 2]);
+}
+
+#[test]
+fn test_guest_flag_limits_the_tools() {
+    let mut server = Server::spawn("guest", &["--guest"]);
+    server.initialize();
+    let tools = server.request(1, "tools/list", json!({}));
+    let mut names: Vec<&str> = tools["result"]["tools"].as_array().unwrap().iter().filter_map(|tool| tool["name"].as_str()).collect();
+    names.sort();
+    assert_eq!(names, ["get_lightbulb_state", "get_lightbulb_status", "turn_off_lightbulb", "turn_on_lightbulb"]);
+
+    let hidden = server.request(2, "resources/read", json!({ "uri": "lightbulb://log" }));
+    assert_eq!(hidden["error"]["data"]["code"], "UNKNOWN_RESOURCE");
+    assert!(server.finish().success());
+}
+
+#[test]
+fn test_second_server_on_the_same_log_is_refused() {
+    let mut server = Server::spawn("single-instance", &[]);
+    server.initialize();
+    let second = Command::new(SERVER).current_dir(&server.dir).env_remove("LIGHTBULB_CONFIG").stdin(Stdio::null()).output().unwrap();
+    assert!(!second.status.success());
+    let stderr = String::from_utf8_lossy(&second.stderr);
+    assert!(stderr.contains("is already using lightbulb.log"), "{}", stderr);
+    assert!(server.finish().success());
+}
+
+// Diagnostics never reach stdout, where they would corrupt the session
+#[test]
+fn test_verbose_diagnostics_go_to_stderr() {
+    let dir = std::env::temp_dir().join(format!("lightbulb-stdio-verbose-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(SERVER)
+        .arg("-vv")
+        .current_dir(&dir)
+        .env_remove("LIGHTBULB_CONFIG")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let initialize = json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": { "protocolVersion": "2025-03-26", "capabilities": {}, "clientInfo": { "name": "wire-test", "version": "1" } } });
+    let call = json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/call", "params": { "name": "turn_on_lightbulb", "arguments": {} } });
+    let mut stdin = child.stdin.take().unwrap();
+    writeln!(stdin, "{}\n{}\n{}", initialize, json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }), call).unwrap();
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for expected in ["client wire-test 1 connected", "backend simulated set_power On: ok", "tool turn_on_lightbulb called by wire-test: ok"] {
+        assert!(stderr.contains(expected), "missing {:?}: {}", expected, stderr);
+    }
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        assert!(serde_json::from_str::<Value>(line).is_ok(), "{}", line);
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sigterm_shuts_down_gracefully() {
+    let mut server = Server::spawn("sigterm", &[]);
+    server.initialize();
+    server.call(1, "turn_on_lightbulb", json!({}));
+    // Still connected: the signal, not the end of input, stops it
+    let killed = Command::new("kill").args(["-TERM", &server.child.id().to_string()]).status().unwrap();
+    assert!(killed.success());
+    assert!(server.wait().expect("the server did not exit on SIGTERM").success());
+    let log = std::fs::read_to_string(server.dir.join("lightbulb.log")).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert!(lines[0].contains("] Lightbulb turned ON by wire-test"), "{}", log);
+    assert!(lines[1].ends_with("] Lightbulb server SHUTDOWN (SIGTERM) seq=2"), "{}", log);
+}